@@ -1,36 +1,79 @@
+extern crate crypto;
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
 
-fn main() {
-    // rerun build script if bridge contract has changed.
-    // without this cargo doesn't since the bridge contract
-    // is outside the crate directories
-    println!("cargo:rerun-if-changed=../contracts/bridge.sol");
+/// contract source that the bridge contracts are compiled from
+const SOURCE: &str = "../contracts/bridge.sol";
+/// checked in compilation artifacts of `SOURCE` together with `source_hash`
+/// (`sha256sum` of `SOURCE`) and `solc_version`. refreshed by `tools/compile_contracts.sh`
+const VENDORED_DIR: &str = "../contracts/compiled";
+const ARTIFACTS: [&str; 8] = [
+    "HomeBridge.abi",
+    "HomeBridge.bin",
+    "ForeignBridge.abi",
+    "ForeignBridge.bin",
+    "HomeTokenBridge.abi",
+    "HomeTokenBridge.bin",
+    "ForeignTokenBridge.abi",
+    "ForeignTokenBridge.bin",
+];
+/// modules of `contracts::v1` and the contracts they bind
+const BINDINGS_V1: [(&str, &str); 2] = [("home", "HomeBridge"), ("foreign", "ForeignBridge")];
+/// modules of `contracts::v2` and the contracts they bind
+const BINDINGS_V2: [(&str, &str); 2] = [
+    ("home_token", "HomeTokenBridge"),
+    ("foreign_token", "ForeignTokenBridge"),
+];
 
-    // make last git commit hash (`git rev-parse HEAD`)
-    // available via `env!("GIT_HASH")` in sources
-    let output = Command::new("git")
-        .args(&["rev-parse", "HEAD"])
-        .output()
-        .unwrap();
-    let git_hash = String::from_utf8(output.stdout).unwrap();
-    println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+/// sha256 of `bytes` in hex like `sha256sum` prints it
+fn source_hash(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    hasher.result_str()
+}
 
-    // make solc version used to compile contracts (`solc --version`)
-    // available via `env!("SOLC_VERSION")` in sources
-    let output = Command::new("solc").args(&["--version"]).output().unwrap();
+fn read(path: &Path) -> Option<String> {
+    let mut content = String::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .ok()
+        .map(|_| content.trim().to_owned())
+}
+
+fn write(path: &Path, content: &str) {
+    fs::write(path, content)
+        .unwrap_or_else(|err| panic!("cannot write `{}`: {}", path.display(), err));
+}
+
+/// compiles `SOURCE` into `output_dir` with `solc` and returns the version of `solc`
+fn compile(output_dir: &Path) -> String {
+    let output = match Command::new("solc").args(&["--version"]).output() {
+        Ok(output) => output,
+        Err(err) => {
+            if let std::io::ErrorKind::NotFound = err.kind() {
+                panic!("`solc` executable not found in `$PATH`. `solc` is required to compile the bridge contracts. please install it: https://solidity.readthedocs.io/en/develop/installing-solidity.html");
+            } else {
+                panic!("an error occurred when trying to spawn `solc`: {}", err);
+            }
+        }
+    };
     let output_string = String::from_utf8(output.stdout).unwrap();
-    let solc_version = output_string.lines().last().unwrap();
-    println!("cargo:rustc-env=SOLC_VERSION={}", solc_version);
+    let solc_version = output_string.lines().last().unwrap().to_owned();
 
-    // compile contracts for inclusion with ethabis `use_contract!`
     match Command::new("solc")
         .arg("--abi")
         .arg("--bin")
         .arg("--optimize")
         .arg("--output-dir")
-        .arg("../compiled_contracts")
+        .arg(output_dir)
         .arg("--overwrite")
-        .arg("../contracts/bridge.sol")
+        .arg(SOURCE)
         .status()
     {
         Ok(exit_status) => {
@@ -42,12 +85,89 @@ fn main() {
                 }
             }
         }
-        Err(err) => {
-            if let std::io::ErrorKind::NotFound = err.kind() {
-                panic!("`solc` executable not found in `$PATH`. `solc` is required to compile the bridge contracts. please install it: https://solidity.readthedocs.io/en/develop/installing-solidity.html");
-            } else {
-                panic!("an error occurred when trying to spawn `solc`: {}", err);
-            }
-        }
+        Err(err) => panic!("an error occurred when trying to spawn `solc`: {}", err),
+    }
+    solc_version
+}
+
+/// copies the artifacts from `from` to `to`
+fn copy_artifacts(from: &Path, to: &Path) {
+    for artifact in &ARTIFACTS {
+        let from = from.join(artifact);
+        let to = to.join(artifact);
+        fs::copy(&from, &to).unwrap_or_else(|err| {
+            panic!("cannot copy `{}` to `{}`: {}", from.display(), to.display(), err)
+        });
     }
 }
+
+/// `use_contract!` of the artifacts in `out_dir` for each of `modules`
+fn bindings(out_dir: &Path, modules: &[(&str, &str)]) -> String {
+    modules
+        .iter()
+        .map(|&(module, contract)| {
+            let abi = out_dir.join(format!("{}.abi", contract));
+            format!(
+                "use_contract!({}, {:?}, {:?});\n",
+                module,
+                contract,
+                abi.display().to_string()
+            )
+        })
+        .collect()
+}
+
+fn main() {
+    // rerun build script if bridge contract or its artifacts have changed.
+    // without this cargo doesn't since they are outside the crate directories
+    println!("cargo:rerun-if-changed={}", SOURCE);
+    println!("cargo:rerun-if-changed={}", VENDORED_DIR);
+    for artifact in &ARTIFACTS {
+        println!("cargo:rerun-if-changed={}/{}", VENDORED_DIR, artifact);
+    }
+
+    // make last git commit hash (`git rev-parse HEAD`)
+    // available via `env!("GIT_HASH")` in sources
+    let output = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .unwrap();
+    let git_hash = String::from_utf8(output.stdout).unwrap();
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+
+    // the artifacts compiled into the bridge. nothing is written outside of `OUT_DIR`
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("cargo sets `OUT_DIR`"));
+    let vendored_dir = Path::new(VENDORED_DIR);
+    let source = fs::read(SOURCE).unwrap_or_else(|err| panic!("cannot read `{}`: {}", SOURCE, err));
+    let hash = source_hash(&source);
+    let vendored_hash = read(&vendored_dir.join("source_hash"));
+    let force = env::var_os("CARGO_FEATURE_COMPILE_CONTRACTS").is_some();
+
+    // use the checked in artifacts. `solc` only runs with the `compile-contracts` feature
+    let solc_version = if force {
+        compile(&out_dir)
+    } else {
+        match vendored_hash {
+            Some(ref vendored_hash) if *vendored_hash != hash => println!(
+                "cargo:warning=`{}` changed since the artifacts in `{}` were compiled. run `tools/compile_contracts.sh` to refresh them",
+                SOURCE, VENDORED_DIR
+            ),
+            Some(_) => {}
+            None => panic!(
+                "`{}` has no artifacts. run `tools/compile_contracts.sh` and commit them, or build with `--features compile-contracts` to compile `{}` with `solc`",
+                VENDORED_DIR, SOURCE
+            ),
+        }
+        copy_artifacts(vendored_dir, &out_dir);
+        read(&vendored_dir.join("solc_version")).unwrap_or_else(|| "unknown".into())
+    };
+
+    // `use_contract!` of the artifacts in `OUT_DIR` for `contracts::v1` and `contracts::v2`.
+    // `use_contract!` only takes literal paths
+    write(&out_dir.join("contracts_v1.rs"), &bindings(&out_dir, &BINDINGS_V1));
+    write(&out_dir.join("contracts_v2.rs"), &bindings(&out_dir, &BINDINGS_V2));
+
+    // make solc version used to compile contracts (`solc --version`)
+    // available via `env!("SOLC_VERSION")` in sources
+    println!("cargo:rustc-env=SOLC_VERSION={}", solc_version);
+}
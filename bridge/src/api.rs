@@ -1,14 +1,21 @@
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
-use futures::{Future, Poll, Stream};
-use tokio_timer::{Interval, Timeout, Timer};
+use futures::{Async, Future, Poll, Stream};
+use futures::future::{join_all, JoinAll};
+use tokio_timer::{Interval, Sleep, Timeout, Timer};
 use web3::{self, api, Transport};
 use web3::api::Namespace;
-use web3::types::{Address, Bytes, CallRequest, Filter, FilterBuilder, H256, H520, Log,
-                  TransactionRequest, U256};
-use web3::helpers::CallResult;
+use web3::types::{Address, BlockNumber, Bytes, CallRequest, Filter, FilterBuilder, H256, H520,
+                  Log, Transaction, TransactionId, TransactionReceipt, TransactionRequest, U256};
+use web3::helpers::{self, CallResult};
 use error::{Error, ErrorKind};
+use checkpoints::Checkpoints;
+use heads::{ChainHead, HeadSubscription};
+use config::RetryConfig;
+use retry::{is_retryable, Backoff};
+use shard::{Leases, ShardedStream};
 
 /// Imperative alias for web3 function.
 pub use web3::confirm::send_transaction_with_confirmation;
@@ -54,6 +61,64 @@ pub fn block_number<T: Transport>(transport: T) -> ApiCall<U256, T::Out> {
     }
 }
 
+/// Imperative wrapper for web3 function.
+pub fn gas_price<T: Transport>(transport: T) -> ApiCall<U256, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).gas_price(),
+        message: "eth_gasPrice",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+pub fn code<T: Transport>(transport: T, address: Address) -> ApiCall<Bytes, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).code(address, None),
+        message: "eth_getCode",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+pub fn accounts<T: Transport>(transport: T) -> ApiCall<Vec<Address>, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).accounts(),
+        message: "eth_accounts",
+    }
+}
+
+/// Imperative wrapper for `eth_chainId` which is not supported by web3.
+pub fn chain_id<T: Transport>(transport: T) -> ApiCall<U256, T::Out> {
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_chainId", vec![])),
+        message: "eth_chainId",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+pub fn balance<T: Transport>(transport: T, address: Address) -> ApiCall<U256, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).balance(address, None),
+        message: "eth_getBalance",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+/// resolves to the nonce of the next transaction of `address` including pending ones.
+pub fn pending_nonce<T: Transport>(transport: T, address: Address) -> ApiCall<U256, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).transaction_count(address, Some(BlockNumber::Pending)),
+        message: "eth_getTransactionCount",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+/// resolves to the nonce of the next transaction of `address` in the latest block.
+pub fn confirmed_nonce<T: Transport>(transport: T, address: Address) -> ApiCall<U256, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).transaction_count(address, Some(BlockNumber::Latest)),
+        message: "eth_getTransactionCount",
+    }
+}
+
 /// Imperative wrapper for web3 function.
 pub fn send_transaction<T: Transport>(
     transport: T,
@@ -65,6 +130,41 @@ pub fn send_transaction<T: Transport>(
     }
 }
 
+/// Imperative wrapper for `eth_sendRawTransaction` with a transaction signed by this process.
+pub fn send_raw_transaction<T: Transport>(transport: T, raw: Bytes) -> ApiCall<H256, T::Out> {
+    ApiCall {
+        future: CallResult::new(transport.execute(
+            "eth_sendRawTransaction",
+            vec![helpers::serialize(&raw)],
+        )),
+        message: "eth_sendRawTransaction",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+/// resolves to `None` while the transaction is not mined.
+pub fn transaction_receipt<T: Transport>(
+    transport: T,
+    hash: H256,
+) -> ApiCall<Option<TransactionReceipt>, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).transaction_receipt(hash),
+        message: "eth_getTransactionReceipt",
+    }
+}
+
+/// Imperative wrapper for web3 function.
+/// resolves to `None` if the node doesn't know the transaction.
+pub fn transaction<T: Transport>(
+    transport: T,
+    hash: H256,
+) -> ApiCall<Option<Transaction>, T::Out> {
+    ApiCall {
+        future: api::Eth::new(transport).transaction(TransactionId::Hash(hash)),
+        message: "eth_getTransactionByHash",
+    }
+}
+
 /// Imperative wrapper for web3 function.
 pub fn call<T: Transport>(
     transport: T,
@@ -89,6 +189,249 @@ pub fn call<T: Transport>(
     }
 }
 
+fn call_request(tx: &TransactionRequest) -> CallRequest {
+    CallRequest {
+        from: Some(tx.from),
+        to: tx.to.expect("only transactions to contracts are simulated; qed"),
+        gas: tx.gas,
+        gas_price: tx.gas_price,
+        value: tx.value,
+        data: tx.data.clone(),
+    }
+}
+
+/// Imperative wrapper for web3 function.
+/// executes `tx` as a call against the pending block without sending it.
+pub fn call_transaction<T: Transport>(
+    transport: T,
+    tx: &TransactionRequest,
+) -> ApiCall<Bytes, T::Out> {
+    let future = api::Eth::new(transport).call(call_request(tx), Some(BlockNumber::Pending));
+
+    ApiCall {
+        future,
+        message: "eth_call",
+    }
+}
+
+/// entry of an EIP-2930 access list
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+/// result of `eth_createAccessList`
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListWithGasUsed {
+    pub access_list: Vec<AccessListItem>,
+    pub gas_used: U256,
+}
+
+/// `TransactionRequest` extended by the fields of typed transactions
+/// which are not supported by `web3::types::TransactionRequest`.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedTransactionRequest {
+    #[serde(flatten)]
+    pub request: TransactionRequest,
+    /// EIP-2718 transaction type
+    #[serde(rename = "type")]
+    pub transaction_type: U256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_list: Option<Vec<AccessListItem>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl TypedTransactionRequest {
+    /// `request` as legacy transaction of type `0`
+    pub fn legacy(request: TransactionRequest) -> Self {
+        TypedTransactionRequest {
+            request,
+            transaction_type: 0.into(),
+            access_list: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }
+    }
+}
+
+/// Imperative wrapper for `eth_createAccessList` which is not supported by web3.
+/// computes the access list of `tx` against the pending block.
+pub fn create_access_list<T: Transport>(
+    transport: T,
+    tx: &TransactionRequest,
+) -> ApiCall<AccessListWithGasUsed, T::Out> {
+    let params = vec![
+        helpers::serialize(&call_request(tx)),
+        helpers::serialize(&BlockNumber::Pending),
+    ];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_createAccessList", params)),
+        message: "eth_createAccessList",
+    }
+}
+
+/// Imperative wrapper for `eth_estimateGas`.
+/// estimates the gas `tx` would use against the pending block.
+pub fn estimate_gas<T: Transport>(transport: T, tx: &TransactionRequest) -> ApiCall<U256, T::Out> {
+    let params = vec![
+        helpers::serialize(&call_request(tx)),
+        helpers::serialize(&BlockNumber::Pending),
+    ];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_estimateGas", params)),
+        message: "eth_estimateGas",
+    }
+}
+
+/// Imperative wrapper for `eth_feeHistory` which is not supported by web3.
+/// returns the fee history of the latest `block_count` blocks.
+pub fn fee_history<T: Transport>(transport: T, block_count: u64) -> ApiCall<Value, T::Out> {
+    let params = vec![
+        helpers::serialize(&U256::from(block_count)),
+        helpers::serialize(&BlockNumber::Latest),
+        Value::Array(vec![]),
+    ];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_feeHistory", params)),
+        message: "eth_feeHistory",
+    }
+}
+
+/// fields of a block header returned by `eth_getBlockByNumber`
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct BlockHeader {
+    /// `None` for pending blocks
+    pub hash: Option<H256>,
+    /// `None` for pending blocks
+    pub number: Option<U256>,
+    /// hash of the parent block
+    #[serde(rename = "parentHash", default)]
+    pub parent_hash: H256,
+    /// seconds since the unix epoch
+    pub timestamp: U256,
+}
+
+/// Imperative wrapper for `eth_getBlockByNumber` without the transactions of the block.
+pub fn block_header<T: Transport>(
+    transport: T,
+    block: BlockNumber,
+) -> ApiCall<BlockHeader, T::Out> {
+    let params = vec![
+        helpers::serialize(&block),
+        Value::Bool(false),
+    ];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_getBlockByNumber", params)),
+        message: "eth_getBlockByNumber",
+    }
+}
+
+/// Imperative wrapper for `eth_getBlockByNumber` with a block tag which is not supported by web3
+/// (`"safe"` or `"finalized"`). fails on nodes that don't know the tag
+pub fn tagged_block_header<T: Transport>(
+    transport: T,
+    tag: &'static str,
+) -> ApiCall<BlockHeader, T::Out> {
+    let params = vec![Value::String(tag.into()), Value::Bool(false)];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_getBlockByNumber", params)),
+        message: "eth_getBlockByNumber",
+    }
+}
+
+/// Imperative wrapper for `eth_sendTransaction` with a typed transaction.
+pub fn send_typed_transaction<T: Transport>(
+    transport: T,
+    tx: &TypedTransactionRequest,
+) -> ApiCall<H256, T::Out> {
+    let params = vec![helpers::serialize(tx)];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("eth_sendTransaction", params)),
+        message: "eth_sendTransaction",
+    }
+}
+
+/// filter of parity's `trace_filter`
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFilter {
+    from_block: BlockNumber,
+    to_block: BlockNumber,
+    to_address: Vec<Address>,
+}
+
+/// action of a trace returned by parity's `trace_filter`.
+/// the fields that are set depend on the type of the trace.
+#[derive(Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceAction {
+    /// sender of a `call`
+    pub from: Option<Address>,
+    /// receiver of a `call`
+    pub to: Option<Address>,
+    /// value of a `call` or `reward`
+    pub value: Option<U256>,
+    /// contract that self-destructed in a `suicide`
+    pub address: Option<Address>,
+    /// receiver of the balance in a `suicide`
+    pub refund_address: Option<Address>,
+    /// balance transferred in a `suicide`
+    pub balance: Option<U256>,
+    /// receiver of a `reward`
+    pub author: Option<Address>,
+}
+
+/// trace returned by parity's `trace_filter`
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    pub action: TraceAction,
+    /// one of `call`, `create`, `suicide` or `reward`
+    #[serde(rename = "type")]
+    pub trace_type: String,
+    pub block_number: u64,
+    /// `None` for block rewards
+    pub transaction_hash: Option<H256>,
+    /// set if the traced call failed
+    pub error: Option<String>,
+}
+
+/// Imperative wrapper for parity's `trace_filter` which is not supported by web3.
+/// returns the traces in blocks `[from, to]` that transfer to `to_address`.
+/// requires the node to run with tracing enabled.
+pub fn trace_filter<T: Transport>(
+    transport: T,
+    from: u64,
+    to: u64,
+    to_address: Address,
+) -> ApiCall<Vec<Trace>, T::Out> {
+    let params = vec![
+        helpers::serialize(&TraceFilter {
+            from_block: from.into(),
+            to_block: to.into(),
+            to_address: vec![to_address],
+        }),
+    ];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("trace_filter", params)),
+        message: "trace_filter",
+    }
+}
+
 pub fn sign<T: Transport>(transport: T, address: Address, data: Bytes) -> ApiCall<H520, T::Out> {
     ApiCall {
         future: api::Eth::new(transport).sign(address, data),
@@ -96,6 +439,55 @@ pub fn sign<T: Transport>(transport: T, address: Address, data: Bytes) -> ApiCal
     }
 }
 
+/// Imperative wrapper for `personal_ecRecover` which is not supported by web3.
+/// returns the account that produced `signature` of `data` via `eth_sign`.
+pub fn ec_recover<T: Transport>(
+    transport: T,
+    data: Bytes,
+    signature: Bytes,
+) -> ApiCall<Address, T::Out> {
+    let params = vec![helpers::serialize(&data), helpers::serialize(&signature)];
+
+    ApiCall {
+        future: CallResult::new(transport.execute("personal_ecRecover", params)),
+        message: "personal_ecRecover",
+    }
+}
+
+/// when the logs of a block are confirmed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConfirmationStrategy {
+    /// once the chain is `confirmations` blocks ahead of the block
+    Depth,
+    /// like `Depth` but the hash of the last block of a range is pinned before its logs are fetched.
+    /// the logs are only yielded if that block is still on the chain afterwards
+    /// and the parent hashes from it down to the oldest block with logs lead through
+    /// the block hash of every log. a node that served logs of a replaced fork
+    /// while its head moved back to the pinned block is caught that way.
+    /// protects against blocks replaced at the same height on unstable chains
+    PinnedHash,
+    /// up to the block the node reports for the `safe` tag. `confirmations` are ignored.
+    /// requires a proof of stake chain after the merge
+    Safe,
+    /// up to the block the node reports for the `finalized` tag. `confirmations` are ignored.
+    /// requires a proof of stake chain after the merge
+    Finalized,
+    /// once the given time passed since the block was first seen as the latest block.
+    /// `confirmations` are ignored
+    Delay(Duration),
+}
+
+impl ConfirmationStrategy {
+    /// tag of the block up to which logs are confirmed if the node tracks it
+    pub fn block_tag(&self) -> Option<&'static str> {
+        match *self {
+            ConfirmationStrategy::Safe => Some("safe"),
+            ConfirmationStrategy::Finalized => Some("finalized"),
+            _ => None,
+        }
+    }
+}
+
 /// Used for `LogStream` initialization.
 pub struct LogStreamInit {
     pub after: u64,
@@ -103,6 +495,7 @@ pub struct LogStreamInit {
     pub request_timeout: Duration,
     pub poll_interval: Duration,
     pub confirmations: usize,
+    pub strategy: ConfirmationStrategy,
 }
 
 /// Contains all logs matching `LogStream` filter in inclusive range `[from, to]`.
@@ -113,20 +506,88 @@ pub struct LogStreamItem {
     pub logs: Vec<Log>,
 }
 
+/// number of headers fetched at once to verify the logs of a pinned range
+const VERIFY_WINDOW: u64 = 50;
+
+/// returns the oldest block with logs of `item` or `item.to` if it has none.
+/// `None` if a log has no block
+fn lowest_block(item: &LogStreamItem) -> Option<u64> {
+    item.logs.iter().fold(Some(item.to), |lowest, log| {
+        match (lowest, log.block_number) {
+            (Some(lowest), Some(block)) => Some(lowest.min(block.low_u64())),
+            _ => None,
+        }
+    })
+}
+
+/// verifies that `headers` of consecutive blocks, oldest first, are the ancestors
+/// of the block with hash `expected` and that the `logs` of their blocks have their hashes.
+/// returns the parent hash of the oldest header or `None` if the verification failed
+fn verify_headers(headers: &[BlockHeader], expected: H256, logs: &[Log]) -> Option<H256> {
+    let mut expected = expected;
+    for header in headers.iter().rev() {
+        let (hash, number) = match (header.hash, header.number) {
+            (Some(hash), Some(number)) if hash == expected => (hash, number),
+            _ => return None,
+        };
+        let replaced = logs.iter().any(|log| {
+            log.block_number == Some(number) && log.block_hash != Some(hash)
+        });
+        if replaced {
+            return None;
+        }
+        expected = header.parent_hash;
+    }
+    Some(expected)
+}
+
 /// Log Stream state.
 enum LogStreamState<T: Transport> {
     /// Log Stream is waiting for timer to poll.
     Wait,
     /// Fetching best block number.
     FetchBlockNumber(Timeout<ApiCall<U256, T::Out>>),
+    /// Fetching the block of the tag of the confirmation strategy.
+    FetchTaggedBlock(Timeout<ApiCall<BlockHeader, T::Out>>),
+    /// Pinning the hash of the last block of the range before fetching its logs.
+    PinBlock {
+        from: u64,
+        to: u64,
+        future: Timeout<ApiCall<BlockHeader, T::Out>>,
+    },
     /// Fetching logs for new best block.
     FetchLogs {
         from: u64,
         to: u64,
         future: Timeout<ApiCall<Vec<Log>, T::Out>>,
+        /// hash of block `to` if pinned
+        pinned: Option<H256>,
+    },
+    /// Checking that the block of the latest checkpoint is still on the chain
+    /// before fetching the logs up to `to` after it.
+    VerifyCheckpoint {
+        block: u64,
+        hash: H256,
+        to: u64,
+        future: Timeout<ApiCall<BlockHeader, T::Out>>,
+    },
+    /// Checking that the pinned block is still on the chain and that the logs are from
+    /// its ancestors. the headers are fetched in windows walking down from the pinned block.
+    VerifyPin {
+        item: Option<LogStreamItem>,
+        pinned: H256,
+        /// hash the newest header of the window must have
+        expected: H256,
+        /// oldest block with logs
+        lowest: u64,
+        /// oldest block of the window
+        start: u64,
+        future: JoinAll<Vec<Timeout<ApiCall<BlockHeader, T::Out>>>>,
     },
     /// All logs has been fetched.
     NextItem(Option<LogStreamItem>),
+    /// Waiting to fetch the best block number again after a call failed.
+    Retry(Sleep),
 }
 
 /// Creates new `LogStream`.
@@ -139,7 +600,13 @@ pub fn log_stream<T: Transport>(transport: T, timer: Timer, init: LogStreamInit)
         after: init.after,
         filter: init.filter,
         confirmations: init.confirmations,
+        strategy: init.strategy,
         request_timeout: init.request_timeout,
+        head: None,
+        checkpoints: None,
+        backoff: None,
+        seen: VecDeque::new(),
+        shard: None,
     }
 }
 
@@ -152,61 +619,378 @@ pub struct LogStream<T: Transport> {
     after: u64,
     filter: FilterBuilder,
     confirmations: usize,
+    strategy: ConfirmationStrategy,
     request_timeout: Duration,
+    /// blocks announced by the watcher of the chain
+    head: Option<HeadSubscription>,
+    /// checkpoints to rewind to after a reorg
+    checkpoints: Option<Checkpoints>,
+    /// delays before fetching again after a failed call. failures are returned if `None`
+    backoff: Option<Backoff>,
+    /// latest blocks and when they were first seen, oldest first.
+    /// only kept for `ConfirmationStrategy::Delay`
+    seen: VecDeque<(u64, Instant)>,
+    /// leases whose taken over partitions the stream rewinds for
+    shard: Option<(Leases, ShardedStream)>,
 }
 
-impl<T: Transport> Stream for LogStream<T> {
-    type Item = LogStreamItem;
-    type Error = Error;
+impl<T: Transport> LogStream<T> {
+    /// waits for the blocks announced to `head` instead of polling the latest block.
+    /// polls on its own interval while nothing watches the chain
+    pub fn following(mut self, head: &ChainHead) -> Self {
+        self.head = Some(head.follow());
+        self
+    }
 
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+    /// records the hashes of the last blocks of the yielded ranges in `checkpoints`.
+    /// checks that the latest checkpoint is still on the chain before continuing after it
+    /// and rewinds to the latest checkpoint still on the chain after a reorg.
+    /// does nothing if `checkpoints` are disabled
+    pub fn rewinding(mut self, checkpoints: &Checkpoints) -> Self {
+        if checkpoints.is_enabled() {
+            self.checkpoints = Some(checkpoints.clone());
+        }
+        self
+    }
+
+    /// rewinds to the blocks up to which the events of the partitions taken over by `leases`
+    /// were handled by their previous owner, so that `stream` handles them from there
+    pub fn sharded(mut self, leases: &Leases, stream: ShardedStream) -> Self {
+        self.shard = Some((leases.clone(), stream));
+        self
+    }
+
+    /// continues after `block` if it is before the blocks the stream is at.
+    /// the checkpoints after `block` are dropped
+    fn rewind(&mut self, block: u64) {
+        if block >= self.after {
+            return;
+        }
+        info!(
+            "rewinding from block {} to {} for the events of taken over partitions",
+            self.after, block
+        );
+        self.after = block;
+        if let Some(ref checkpoints) = self.checkpoints {
+            while checkpoints
+                .latest()
+                .map_or(false, |(checkpoint, _)| checkpoint > block)
+            {
+                checkpoints.discard_latest();
+            }
+        }
+    }
+
+    /// fetches again after timeouts and transient errors of the node instead of failing.
+    /// gives up once `retry.max_retries` calls in a row failed
+    pub fn retrying(mut self, retry: &RetryConfig) -> Self {
+        self.backoff = Some(Backoff::new(retry.clone()));
+        self
+    }
+
+    /// fetches the latest block or the block of the tag of the strategy
+    fn fetch_block_number(&self) -> LogStreamState<T> {
+        match self.strategy.block_tag() {
+            Some(tag) => LogStreamState::FetchTaggedBlock(self.timer.timeout(
+                tagged_block_header(&self.transport, tag),
+                self.request_timeout,
+            )),
+            None => LogStreamState::FetchBlockNumber(
+                self.timer
+                    .timeout(block_number(&self.transport), self.request_timeout),
+            ),
+        }
+    }
+
+    /// returns the last block that was first seen as latest block at least `delay` ago
+    fn delayed_block(&mut self, last_block: u64, delay: Duration) -> u64 {
+        let now = Instant::now();
+        if self.seen.back().map_or(true, |&(block, _)| block < last_block) {
+            self.seen.push_back((last_block, now));
+        }
+        while self.seen.len() > 1 && now.duration_since(self.seen[1].1) >= delay {
+            self.seen.pop_front();
+        }
+        match self.seen.front() {
+            Some(&(block, seen)) if now.duration_since(seen) >= delay => block,
+            _ => 0,
+        }
+    }
+
+    /// fetches the logs of the blocks confirmed by `last_block`.
+    /// `last_block` is confirmed itself for strategies with a block tag
+    fn confirm(&mut self, last_block: u64) -> LogStreamState<T> {
+        let last_confirmed_block = match self.strategy {
+            ConfirmationStrategy::Depth | ConfirmationStrategy::PinnedHash => {
+                last_block.saturating_sub(self.confirmations as u64)
+            }
+            ConfirmationStrategy::Safe | ConfirmationStrategy::Finalized => last_block,
+            ConfirmationStrategy::Delay(delay) => self.delayed_block(last_block, delay),
+        };
+        if last_confirmed_block <= self.after {
+            return LogStreamState::Wait;
+        }
+        match self.checkpoints.as_ref().and_then(Checkpoints::latest) {
+            Some((block, hash)) => LogStreamState::VerifyCheckpoint {
+                block,
+                hash,
+                to: last_confirmed_block,
+                future: self.fetch_header(block),
+            },
+            None => self.fetch_range(self.after + 1, last_confirmed_block),
+        }
+    }
+
+    /// fetches the logs of the blocks `from` to `to`.
+    /// pins the hash of `to` first if the strategy or the checkpoints need it
+    fn fetch_range(&self, from: u64, to: u64) -> LogStreamState<T> {
+        if self.strategy == ConfirmationStrategy::PinnedHash || self.checkpoints.is_some() {
+            LogStreamState::PinBlock {
+                from,
+                to,
+                future: self.fetch_header(to),
+            }
+        } else {
+            self.fetch_logs(from, to, None)
+        }
+    }
+
+    /// yields `item`. `hash` is the hash of block `item.to` if it was pinned
+    fn advance(&mut self, item: LogStreamItem, hash: Option<H256>) -> LogStreamState<T> {
+        self.after = item.to;
+        if let (Some(checkpoints), Some(hash)) = (self.checkpoints.as_ref(), hash) {
+            checkpoints.push(item.to, hash);
+        }
+        LogStreamState::NextItem(Some(item))
+    }
+
+    fn fetch_logs(&self, from: u64, to: u64, pinned: Option<H256>) -> LogStreamState<T> {
+        let filter = self.filter
+            .clone()
+            .from_block(from.into())
+            .to_block(to.into())
+            .build();
+        LogStreamState::FetchLogs {
+            from,
+            to,
+            future: self.timer
+                .timeout(logs(&self.transport, &filter), self.request_timeout),
+            pinned,
+        }
+    }
+
+    fn fetch_header(&self, block: u64) -> Timeout<ApiCall<BlockHeader, T::Out>> {
+        self.timer.timeout(
+            block_header(&self.transport, block.into()),
+            self.request_timeout,
+        )
+    }
+
+    /// fetches the headers of the window of blocks down from `newest` but not below `lowest`.
+    /// returns the oldest block of the window
+    fn fetch_headers(
+        &self,
+        lowest: u64,
+        newest: u64,
+    ) -> (u64, JoinAll<Vec<Timeout<ApiCall<BlockHeader, T::Out>>>>) {
+        let start = lowest.max(newest.saturating_sub(VERIFY_WINDOW - 1));
+        let headers = (start..newest + 1)
+            .map(|block| self.fetch_header(block))
+            .collect();
+        (start, join_all(headers))
+    }
+
+    fn poll_state(&mut self) -> Poll<Option<LogStreamItem>, Error> {
         loop {
             let next_state = match self.state {
                 LogStreamState::Wait => {
-                    let _ = try_stream!(self.interval.poll());
-                    LogStreamState::FetchBlockNumber(
-                        self.timer
-                            .timeout(block_number(&self.transport), self.request_timeout),
-                    )
+                    let rewind = self.shard
+                        .as_ref()
+                        .and_then(|&(ref leases, stream)| leases.take_rewind(stream));
+                    if let Some(block) = rewind {
+                        self.rewind(block);
+                    }
+                    let announced = match self.head {
+                        Some(ref mut head) => head.poll(),
+                        None => Async::Ready(None),
+                    };
+                    match announced {
+                        Async::NotReady => return Ok(Async::NotReady),
+                        // announced blocks aren't confirmed by a tag yet
+                        Async::Ready(Some(_)) if self.strategy.block_tag().is_some() => {
+                            self.fetch_block_number()
+                        }
+                        Async::Ready(Some(last_block)) => self.confirm(last_block),
+                        Async::Ready(None) => {
+                            let _ = try_stream!(self.interval.poll());
+                            self.fetch_block_number()
+                        }
+                    }
                 }
                 LogStreamState::FetchBlockNumber(ref mut future) => {
                     let last_block = try_ready!(future.poll()).low_u64();
-                    let last_confirmed_block = last_block.saturating_sub(self.confirmations as u64);
-                    if last_confirmed_block > self.after {
-                        let from = self.after + 1;
-                        let filter = self.filter
-                            .clone()
-                            .from_block(from.into())
-                            .to_block(last_confirmed_block.into())
-                            .build();
-                        LogStreamState::FetchLogs {
-                            from: from,
-                            to: last_confirmed_block,
-                            future: self.timer
-                                .timeout(logs(&self.transport, &filter), self.request_timeout),
-                        }
+                    self.confirm(last_block)
+                }
+                LogStreamState::FetchTaggedBlock(ref mut future) => {
+                    match try_ready!(future.poll()).number {
+                        Some(last_block) => self.confirm(last_block.low_u64()),
+                        None => LogStreamState::Wait,
+                    }
+                }
+                LogStreamState::VerifyCheckpoint {
+                    block,
+                    hash,
+                    to,
+                    ref mut future,
+                } => {
+                    let header = try_ready!(future.poll());
+                    if header.hash == Some(hash) {
+                        self.fetch_range(block + 1, to)
                     } else {
-                        LogStreamState::Wait
+                        let checkpoints = self.checkpoints
+                            .as_ref()
+                            .expect("checkpoints are verified only if they are kept; qed");
+                        warn!(
+                            "block {} of a checkpoint was replaced by a reorg. rewinding to the checkpoint before it",
+                            block
+                        );
+                        match checkpoints.discard_latest() {
+                            Some((block, hash)) => {
+                                self.after = block;
+                                LogStreamState::VerifyCheckpoint {
+                                    block,
+                                    hash,
+                                    to,
+                                    future: self.fetch_header(block),
+                                }
+                            }
+                            None => bail!(
+                                "a reorg replaced the blocks of all {} checkpoints kept. rebuild the database with `rebuild-state`",
+                                checkpoints.capacity()
+                            ),
+                        }
                     }
                 }
+                LogStreamState::PinBlock {
+                    ref mut future,
+                    from,
+                    to,
+                } => match try_ready!(future.poll()).hash {
+                    Some(hash) => self.fetch_logs(from, to, Some(hash)),
+                    None => LogStreamState::Wait,
+                },
                 LogStreamState::FetchLogs {
                     ref mut future,
                     from,
                     to,
+                    pinned,
                 } => {
                     let logs = try_ready!(future.poll());
                     let item = LogStreamItem { from, to, logs };
 
-                    self.after = to;
-                    LogStreamState::NextItem(Some(item))
+                    match pinned {
+                        Some(pinned) if self.strategy == ConfirmationStrategy::PinnedHash => {
+                            match lowest_block(&item) {
+                                Some(lowest) => {
+                                    let (start, future) = self.fetch_headers(lowest, to);
+                                    LogStreamState::VerifyPin {
+                                        item: Some(item),
+                                        pinned,
+                                        expected: pinned,
+                                        lowest,
+                                        start,
+                                        future,
+                                    }
+                                }
+                                None => {
+                                    warn!(
+                                        "logs up to block {} without a block were fetched. fetching them again",
+                                        to
+                                    );
+                                    LogStreamState::Wait
+                                }
+                            }
+                        }
+                        // a replaced block is detected by the check of the checkpoint
+                        // before the next range
+                        pinned => self.advance(item, pinned),
+                    }
+                }
+                LogStreamState::VerifyPin {
+                    ref mut item,
+                    pinned,
+                    expected,
+                    lowest,
+                    start,
+                    ref mut future,
+                } => {
+                    let headers = try_ready!(future.poll());
+                    let item = item.take().expect("item is only taken once; qed");
+                    match verify_headers(&headers, expected, &item.logs) {
+                        Some(parent) if start > lowest => {
+                            let (start, future) = self.fetch_headers(lowest, start - 1);
+                            LogStreamState::VerifyPin {
+                                item: Some(item),
+                                pinned,
+                                expected: parent,
+                                lowest,
+                                start,
+                                future,
+                            }
+                        }
+                        Some(_) => self.advance(item, Some(pinned)),
+                        None => {
+                            warn!(
+                                "blocks up to {} were replaced while their logs were fetched. fetching them again",
+                                item.to
+                            );
+                            LogStreamState::Wait
+                        }
+                    }
                 }
                 LogStreamState::NextItem(ref mut item) => match item.take() {
                     None => LogStreamState::Wait,
                     some => return Ok(some.into()),
                 },
+                LogStreamState::Retry(ref mut sleep) => {
+                    try_ready!(sleep.poll());
+                    self.fetch_block_number()
+                }
             };
 
             self.state = next_state;
         }
     }
 }
+
+impl<T: Transport> Stream for LogStream<T> {
+    type Item = LogStreamItem;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let err = match self.poll_state() {
+                Ok(Async::Ready(Some(item))) => {
+                    if let Some(ref mut backoff) = self.backoff {
+                        backoff.reset();
+                    }
+                    return Ok(Async::Ready(Some(item)));
+                }
+                Err(err) => err,
+                other => return other,
+            };
+            let delay = if is_retryable(&err) {
+                self.backoff.as_mut().and_then(Backoff::next_delay)
+            } else {
+                None
+            };
+            match delay {
+                Some(delay) => {
+                    warn!("failed to fetch logs: {}. retrying in {:?}", err, delay);
+                    self.state = LogStreamState::Retry(self.timer.sleep(delay));
+                }
+                None => return Err(err),
+            }
+        }
+    }
+}
@@ -1,11 +1,33 @@
+use std::collections::HashMap;
+use std::mem;
 use std::path::{Path, PathBuf};
-use tokio_core::reactor::Handle;
+use futures::sync::mpsc::UnboundedSender;
+use tokio_core::reactor::{Core, Handle};
 use tokio_timer::Timer;
 use web3::Transport;
-use web3::transports::ipc::Ipc;
-use error::{Error, ErrorKind, ResultExt};
-use config::Config;
+use api;
+use error::{Error, ResultExt};
+use checkpoints::{Checkpoints, StreamCheckpoints};
+use config::{Config, Node};
 use contracts::{foreign, home};
+use database::Database;
+use ens::resolve_name;
+use event_queue::TransferEvent;
+use gas_price::GasPriceOracles;
+use heads::Heads;
+use journal::Journal;
+use manifest::{self, Manifest};
+use metrics::Metered;
+use pacing::Paced;
+use parked::Parked;
+use retry::Retrying;
+use shard::Leases;
+use registry::resolve_address;
+use signer::LocalSigner;
+use sqlite::SqliteDatabase;
+use transaction::{detect_transaction_type, NonceManager, NonceManagers};
+use transport::NodeTransport;
+use value_limits::{ValueLimiter, ValueLimiters};
 
 pub struct App<T>
 where
@@ -17,6 +39,28 @@ where
     pub home_bridge: home::HomeBridge,
     pub foreign_bridge: foreign::ForeignBridge,
     pub timer: Timer,
+    /// latest blocks of both chains followed by the log streams
+    pub heads: Heads,
+    /// recent checkpoints of the log streams whose progress is saved in the database
+    pub checkpoints: StreamCheckpoints,
+    /// latest quotes of the gas price oracles of both chains
+    pub gas_price_oracles: GasPriceOracles,
+    /// key of `[keystore]` that signs instead of the nodes. the nodes sign if `None`
+    pub keystore: Option<LocalSigner>,
+    /// database of `[sqlite]` that holds the checkpoints instead of the database file
+    pub sqlite: Option<SqliteDatabase>,
+    /// nonces of the relay transactions to the chains with `stuck_transactions`
+    pub nonces: NonceManagers,
+    /// value relayed per direction under `value_limits`
+    pub value_limits: ValueLimiters,
+    /// relay transactions sent for events after the checkpoints
+    pub journal: Journal,
+    /// relays retried apart from their relay streams
+    pub parked: Parked,
+    /// partitions of the events this process handles under `[shard]`
+    pub leases: Leases,
+    /// the application that embeds the bridge is told about every transfer here
+    pub events: Option<UnboundedSender<TransferEvent>>,
 }
 
 pub struct Connections<T>
@@ -27,16 +71,36 @@ where
     pub foreign: T,
 }
 
-impl Connections<Ipc> {
-    pub fn new_ipc<P: AsRef<Path>>(handle: &Handle, home: P, foreign: P) -> Result<Self, Error> {
-        let home = Ipc::with_event_loop(home, handle)
-            .map_err(ErrorKind::Web3)
-            .map_err(Error::from)
-            .chain_err(|| "Cannot connect to home node ipc")?;
-        let foreign = Ipc::with_event_loop(foreign, handle)
-            .map_err(ErrorKind::Web3)
-            .map_err(Error::from)
-            .chain_err(|| "Cannot connect to foreign node ipc")?;
+impl Connections<Paced<Retrying<Metered<NodeTransport>>>> {
+    /// connects to the nodes of `home` and `foreign` via `ipc`, `http` or `ws`.
+    /// metrics of the calls are recorded with the paths or urls as endpoints.
+    /// calls are paced to the rate limits of the nodes and retried while they fail
+    /// for transient reasons
+    pub fn new(handle: &Handle, home: &Node, foreign: &Node) -> Result<Self, Error> {
+        let home_endpoint = home.endpoint.to_string();
+        let home_transport = NodeTransport::connect(home, handle)
+            .chain_err(|| format!("Cannot connect to home node {}", home_endpoint))?;
+        let home = Paced::new(
+            Retrying::new(
+                Metered::new(home_transport, home_endpoint.clone()),
+                home_endpoint.clone(),
+                home.retry.clone(),
+            ),
+            home_endpoint,
+            home.max_requests_per_second,
+        );
+        let foreign_endpoint = foreign.endpoint.to_string();
+        let foreign_transport = NodeTransport::connect(foreign, handle)
+            .chain_err(|| format!("Cannot connect to foreign node {}", foreign_endpoint))?;
+        let foreign = Paced::new(
+            Retrying::new(
+                Metered::new(foreign_transport, foreign_endpoint.clone()),
+                foreign_endpoint.clone(),
+                foreign.retry.clone(),
+            ),
+            foreign_endpoint,
+            foreign.max_requests_per_second,
+        );
 
         let result = Connections { home, foreign };
         Ok(result)
@@ -52,13 +116,55 @@ impl<T: Transport> Connections<T> {
     }
 }
 
-impl App<Ipc> {
-    pub fn new_ipc<P: AsRef<Path>>(
+impl App<Paced<Retrying<Metered<NodeTransport>>>> {
+    pub fn new<P: AsRef<Path>>(
         config: Config,
         database_path: P,
         handle: &Handle,
     ) -> Result<Self, Error> {
-        let connections = Connections::new_ipc(handle, &config.home.ipc, &config.foreign.ipc)?;
+        let connections = Connections::new(handle, &config.home, &config.foreign)?;
+        App::with_connections(config, database_path, connections)
+    }
+}
+
+impl<T: Transport> App<T> {
+    /// creates the app that talks to the nodes via `connections` of any transport.
+    /// used to embed the bridge in another application (see `builder`)
+    pub fn with_connections<P: AsRef<Path>>(
+        config: Config,
+        database_path: P,
+        connections: Connections<T>,
+    ) -> Result<Self, Error> {
+        let keystore = match config.keystore {
+            Some(ref keystore) => Some(LocalSigner::load(keystore)?),
+            None => None,
+        };
+        let sqlite = match config.sqlite {
+            Some(ref sqlite) => Some(SqliteDatabase::open(&sqlite.path)?),
+            None => None,
+        };
+        let nonces = NonceManagers {
+            home: config
+                .home
+                .stuck_transactions
+                .as_ref()
+                .map(|_| NonceManager::new(config.home.account)),
+            foreign: config
+                .foreign
+                .stuck_transactions
+                .as_ref()
+                .map(|_| NonceManager::new(config.foreign.account)),
+        };
+        let checkpoints = StreamCheckpoints {
+            deposit_relay: Checkpoints::new(config.home.reorg_checkpoints),
+            withdraw_confirm: Checkpoints::new(config.foreign.reorg_checkpoints),
+            withdraw_relay: Checkpoints::new(config.foreign.reorg_checkpoints),
+        };
+        let value_limits = ValueLimiters {
+            deposits: ValueLimiter::new(config.value_limits.deposits.clone()),
+            withdraws: ValueLimiter::new(config.value_limits.withdraws.clone()),
+        };
+        let leases = Leases::new(&config.shard);
         let result = App {
             config,
             database_path: database_path.as_ref().to_path_buf(),
@@ -66,12 +172,206 @@ impl App<Ipc> {
             home_bridge: home::HomeBridge::default(),
             foreign_bridge: foreign::ForeignBridge::default(),
             timer: Timer::default(),
+            heads: Heads::default(),
+            checkpoints,
+            gas_price_oracles: GasPriceOracles::default(),
+            keystore,
+            sqlite,
+            nonces,
+            value_limits,
+            journal: Journal::default(),
+            parked: Parked::default(),
+            leases,
+            events: None,
         };
         Ok(result)
     }
 }
 
+fn prepare_keystore_chain<T: Transport>(
+    event_loop: &mut Core,
+    timer: &Timer,
+    connection: &T,
+    chain: &str,
+    node: &mut Node,
+    keystore: &LocalSigner,
+) -> Result<(), Error> {
+    if !keystore.holds(node.account) {
+        warn!(
+            "keystore doesn't hold {}.account {}. its node signs for it",
+            chain, node.account
+        );
+    }
+    if node.chain_id.is_none() {
+        let chain_id = event_loop
+            .run(timer.timeout(api::chain_id(connection), node.request_timeout))
+            .chain_err(|| format!("Cannot get the chain id of {}", chain))?;
+        info!("chain id of {} is {}", chain, chain_id);
+        node.chain_id = Some(chain_id.low_u64());
+    }
+    Ok(())
+}
+
 impl<T: Transport> App<T> {
+    /// resolves the ENS names configured in place of addresses via the ENS registry on home.
+    /// every distinct name is resolved once.
+    pub fn resolve_ens_names(&mut self, event_loop: &mut Core) -> Result<(), Error> {
+        let mut resolved = HashMap::new();
+        let ens_names = mem::replace(&mut self.config.ens_names, Vec::new());
+        for ens_name in ens_names {
+            let address = match resolved.get(&ens_name.name).cloned() {
+                Some(address) => address,
+                None => {
+                    let address = event_loop
+                        .run(resolve_name(
+                            &self.connections.home,
+                            self.timer.clone(),
+                            self.config.home.request_timeout,
+                            self.config.ens_registry,
+                            ens_name.name.clone(),
+                        ))
+                        .chain_err(|| {
+                            format!(
+                                "Cannot resolve ENS name `{}` of `{}`",
+                                ens_name.name, ens_name.field
+                            )
+                        })?;
+                    info!("resolved ENS name `{}` to {}", ens_name.name, address);
+                    resolved.insert(ens_name.name.clone(), address);
+                    address
+                }
+            };
+            self.config.set_address(ens_name.field, address);
+        }
+
+        Ok(())
+    }
+
+    /// detects the transaction types of the chains
+    /// whose transaction type is not configured.
+    pub fn detect_transaction_types(&mut self, event_loop: &mut Core) -> Result<(), Error> {
+        if self.config.home.transaction_type.is_none() {
+            let detected = event_loop.run(detect_transaction_type(
+                &self.connections.home,
+                &self.timer,
+                self.config.home.request_timeout,
+            ))?;
+            info!("detected transaction type {:?} on home", detected);
+            self.config.home.transaction_type = Some(detected);
+        }
+
+        if self.config.foreign.transaction_type.is_none() {
+            let detected = event_loop.run(detect_transaction_type(
+                &self.connections.foreign,
+                &self.timer,
+                self.config.foreign.request_timeout,
+            ))?;
+            info!("detected transaction type {:?} on foreign", detected);
+            self.config.foreign.transaction_type = Some(detected);
+        }
+
+        Ok(())
+    }
+
+    /// asks the nodes for the chain ids that signing transactions with the keystore requires
+    /// unless they are configured. warns about accounts the keystore doesn't hold.
+    /// does nothing without keystore
+    pub fn prepare_keystore(&mut self, event_loop: &mut Core) -> Result<(), Error> {
+        let keystore = match self.keystore {
+            Some(ref keystore) => keystore.clone(),
+            None => return Ok(()),
+        };
+        info!("signing with keystore account {}", keystore.address());
+        prepare_keystore_chain(
+            event_loop,
+            &self.timer,
+            &self.connections.home,
+            "home",
+            &mut self.config.home,
+            &keystore,
+        )?;
+        prepare_keystore_chain(
+            event_loop,
+            &self.timer,
+            &self.connections.foreign,
+            "foreign",
+            &mut self.config.foreign,
+            &keystore,
+        )
+    }
+
+    /// loads the checkpoints from `[sqlite]` if it is configured, otherwise from the database file.
+    /// sqlite imports them from the database file on the first start
+    /// loads the checkpoints. with `deployment_manifest` configured
+    /// a missing database is created from the manifest first
+    pub fn load_database(&self) -> Result<Database, Error> {
+        if let Some(ref path) = self.config.deployment_manifest {
+            if !self.database_path.exists() {
+                Manifest::load(path)?.database().store(&self.database_path)?;
+                info!("created {:?} from {:?}", self.database_path, path);
+            }
+        }
+        match self.sqlite {
+            Some(ref sqlite) => sqlite.load_or_import(&self.database_path),
+            None => Database::load(&self.database_path),
+        }
+    }
+
+    /// resolves the addresses of the bridge contracts whose chain has a registry configured
+    /// and writes them into `database`.
+    pub fn resolve_contract_addresses(
+        &self,
+        event_loop: &mut Core,
+        database: &mut Database,
+    ) -> Result<(), Error> {
+        if let Some(ref registry) = self.config.home.registry {
+            let address = event_loop
+                .run(resolve_address(
+                    &self.connections.home,
+                    &self.timer,
+                    self.config.home.request_timeout,
+                    registry.address,
+                    registry.name.clone(),
+                ))
+                .chain_err(|| "Cannot resolve home contract address")?;
+            info!("resolved home contract address {} via registry", address);
+            database.home_contract_address = address;
+        }
+
+        if let Some(ref registry) = self.config.foreign.registry {
+            let address = event_loop
+                .run(resolve_address(
+                    &self.connections.foreign,
+                    &self.timer,
+                    self.config.foreign.request_timeout,
+                    registry.address,
+                    registry.name.clone(),
+                ))
+                .chain_err(|| "Cannot resolve foreign contract address")?;
+            info!("resolved foreign contract address {} via registry", address);
+            database.foreign_contract_address = address;
+        }
+
+        Ok(())
+    }
+
+    /// verifies the deployment of the contracts of `database` against `deployment_manifest`.
+    /// does nothing if there is no manifest configured
+    pub fn verify_deployment(
+        &self,
+        event_loop: &mut Core,
+        database: &Database,
+    ) -> Result<(), Error> {
+        let path = match self.config.deployment_manifest {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let manifest = Manifest::load(path)?;
+        manifest.check_database(database)?;
+        manifest::verify(event_loop, self, &manifest)
+            .chain_err(|| format!("Cannot verify the deployment of {:?}", path))
+    }
+
     pub fn as_ref(&self) -> App<&T> {
         App {
             config: self.config.clone(),
@@ -80,6 +380,17 @@ impl<T: Transport> App<T> {
             home_bridge: home::HomeBridge::default(),
             foreign_bridge: foreign::ForeignBridge::default(),
             timer: self.timer.clone(),
+            heads: self.heads.clone(),
+            checkpoints: self.checkpoints.clone(),
+            gas_price_oracles: self.gas_price_oracles.clone(),
+            keystore: self.keystore.clone(),
+            sqlite: self.sqlite.clone(),
+            nonces: self.nonces.clone(),
+            value_limits: self.value_limits.clone(),
+            journal: self.journal.clone(),
+            parked: self.parked.clone(),
+            leases: self.leases.clone(),
+            events: self.events.clone(),
         }
     }
 }
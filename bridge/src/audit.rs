@@ -0,0 +1,97 @@
+/// append-only audit logs kept separate from the database.
+/// every entry is a line of json.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use serde_json;
+use tiny_keccak::keccak256;
+use web3::types::{Address, H256, H520, U256};
+use error::{Error, ResultExt};
+use message_to_mainnet::MessageToMainnet;
+
+/// appends `entry` as a line of json to the file at `path`
+pub fn append<P: AsRef<Path>, S: Serialize>(path: P, entry: &S) -> Result<(), Error> {
+    let path = path.as_ref();
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .chain_err(|| format!("Cannot open audit log {:?}", path))?;
+    let line = serde_json::to_string(entry).expect("serialization can't fail; qed");
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// signature of a withdraw message produced by this authority
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SignatureRecord {
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    /// keccak256 of the signed message
+    pub message_hash: H256,
+    pub recipient: Address,
+    pub value: U256,
+    /// transaction the message was created for
+    pub message_transaction_hash: H256,
+    pub home_gas_price: U256,
+    pub home_bridge: Address,
+    pub signer: Address,
+    /// how the signature was produced (example: `eth_sign via /foreign.ipc`)
+    pub backend: String,
+    pub signature: H520,
+    /// transaction that submitted the signature. `None` if it wasn't submitted
+    pub transaction_hash: Option<H256>,
+}
+
+impl SignatureRecord {
+    pub fn new(
+        message: &MessageToMainnet,
+        signer: Address,
+        backend: String,
+        signature: H520,
+        transaction_hash: Option<H256>,
+    ) -> Self {
+        SignatureRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system time is after the unix epoch; qed")
+                .as_secs(),
+            message_hash: keccak256(&message.to_bytes()).into(),
+            recipient: message.recipient,
+            value: message.value,
+            message_transaction_hash: message.sidenet_transaction_hash,
+            home_gas_price: message.mainnet_gas_price,
+            home_bridge: message.mainnet_bridge_address,
+            signer,
+            backend,
+            signature,
+            transaction_hash,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+    use std::fs;
+    use std::io::Read;
+    use self::tempdir::TempDir;
+    use super::append;
+
+    #[test]
+    fn test_append() {
+        let tempdir = TempDir::new("test_append").unwrap();
+        let path = tempdir.path().join("audit.log");
+        append(&path, &vec![1]).unwrap();
+        append(&path, &vec![2, 3]).unwrap();
+        let mut content = String::new();
+        fs::File::open(&path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!("[1]\n[2,3]\n", content);
+    }
+}
@@ -0,0 +1,247 @@
+/// checks of the balance of the account that pays for the relay transactions to a chain.
+/// once the account runs out of ether every relay fails with an error of the node,
+/// so the balance is checked every `balance_watchdog.check_interval` instead:
+/// below `warn_balance` a warning is logged. below `min_balance` an error is logged,
+/// relays to the chain are paused (see `control`) and `alert_url` is posted an alert.
+/// relays continue once the account is topped up above `min_balance` again.
+/// the alert is posted by a thread of its own so a slow receiver doesn't hold up the bridge.
+/// the receiver is reached over http or https through `http_client::request`.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use futures::{Async, Future, Poll, Stream};
+use serde_json;
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::{Address, U256};
+use api::{self, ApiCall};
+use app::App;
+use config::BalanceWatchdogConfig;
+use control::set_underfunded;
+use error::{Error, ResultExt};
+use http_client;
+use signer::Chain;
+
+/// seconds to wait for the receiver of an alert to accept a connection and respond
+const ALERT_TIMEOUT: u64 = 5;
+
+/// how the balance of the account compares to the thresholds
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Level {
+    Sufficient,
+    /// below `warn_balance`
+    Low,
+    /// below `min_balance`
+    Critical,
+}
+
+fn level(config: &BalanceWatchdogConfig, balance: U256) -> Level {
+    if balance < config.min_balance {
+        Level::Critical
+    } else if balance < config.warn_balance {
+        Level::Low
+    } else {
+        Level::Sufficient
+    }
+}
+
+/// body of the alert posted to `alert_url`
+#[derive(Debug, Serialize)]
+struct Alert {
+    chain: Chain,
+    account: Address,
+    /// wei
+    balance: U256,
+    /// wei
+    min_balance: U256,
+}
+
+/// posts `alert` as json to `url`
+fn post_alert(url: &str, alert: &Alert) -> Result<(), Error> {
+    let body = serde_json::to_vec(alert).expect("serialization can't fail; qed");
+    let timeout = Duration::from_secs(ALERT_TIMEOUT);
+    http_client::request("POST", url, Some(&body), timeout)
+        .chain_err(|| format!("Cannot post alert to {}", url))?;
+    Ok(())
+}
+
+/// state of the balance watchdog
+enum BalanceWatchdogState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the balance of the account
+    FetchBalance(Timeout<ApiCall<U256, T::Out>>),
+    /// the balance has been checked
+    Yield,
+}
+
+/// creates the checks of the balance of the account on `chain`.
+/// `None` if `balance_watchdog` isn't configured for `chain`
+pub fn create_balance_watchdog<T: Transport>(
+    app: Arc<App<T>>,
+    chain: Chain,
+) -> Option<BalanceWatchdog<T>> {
+    let config = match chain {
+        Chain::Home => app.config.home.balance_watchdog.clone()?,
+        Chain::Foreign => app.config.foreign.balance_watchdog.clone()?,
+    };
+    Some(BalanceWatchdog {
+        interval: app.timer.interval(config.check_interval),
+        app,
+        chain,
+        config,
+        level: Level::Sufficient,
+        state: BalanceWatchdogState::Wait,
+    })
+}
+
+/// stream that checks the balance of the account on a chain on every tick of `interval`
+/// and pauses relays to the chain while it is below `min_balance`. yields after every check
+pub struct BalanceWatchdog<T: Transport> {
+    app: Arc<App<T>>,
+    chain: Chain,
+    config: BalanceWatchdogConfig,
+    interval: Interval,
+    /// level of the balance when last checked
+    level: Level,
+    state: BalanceWatchdogState<T>,
+}
+
+impl<T: Transport> BalanceWatchdog<T> {
+    fn name(&self) -> &'static str {
+        match self.chain {
+            Chain::Home => "home",
+            Chain::Foreign => "foreign",
+        }
+    }
+
+    fn account(&self) -> Address {
+        match self.chain {
+            Chain::Home => self.app.config.home.account,
+            Chain::Foreign => self.app.config.foreign.account,
+        }
+    }
+
+    /// posts the alert about `balance` to `alert_url` if it is configured
+    fn alert(&self, balance: U256) {
+        let url = match self.config.alert_url {
+            Some(ref url) => url.clone(),
+            None => return,
+        };
+        let alert = Alert {
+            chain: self.chain,
+            account: self.account(),
+            balance,
+            min_balance: self.config.min_balance,
+        };
+        thread::spawn(move || {
+            if let Err(err) = post_alert(&url, &alert) {
+                warn!("failed to post balance alert to {}: {}", url, err);
+            }
+        });
+    }
+}
+
+impl<T: Transport> Stream for BalanceWatchdog<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                BalanceWatchdogState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    let (connection, request_timeout) = match self.chain {
+                        Chain::Home => (
+                            &self.app.connections.home,
+                            self.app.config.home.request_timeout,
+                        ),
+                        Chain::Foreign => (
+                            &self.app.connections.foreign,
+                            self.app.config.foreign.request_timeout,
+                        ),
+                    };
+                    BalanceWatchdogState::FetchBalance(self.app.timer.timeout(
+                        api::balance(connection, self.account()),
+                        request_timeout,
+                    ))
+                }
+                BalanceWatchdogState::FetchBalance(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(balance)) => {
+                        let level = level(&self.config, balance);
+                        match level {
+                            Level::Critical if self.level != Level::Critical => {
+                                error!(
+                                    "balance of {} account {} is {} wei, below the minimum of {} wei. pausing relays to {} until it is topped up",
+                                    self.name(),
+                                    self.account(),
+                                    balance,
+                                    self.config.min_balance,
+                                    self.name()
+                                );
+                                self.alert(balance);
+                            }
+                            Level::Low if self.level != Level::Low => warn!(
+                                "balance of {} account {} is {} wei. relays to {} pause below {} wei",
+                                self.name(),
+                                self.account(),
+                                balance,
+                                self.name(),
+                                self.config.min_balance
+                            ),
+                            Level::Sufficient if self.level != Level::Sufficient => info!(
+                                "balance of {} account {} is {} wei again",
+                                self.name(),
+                                self.account(),
+                                balance
+                            ),
+                            _ => {}
+                        }
+                        if self.level == Level::Critical && level != Level::Critical {
+                            info!("resuming relays to {}", self.name());
+                        }
+                        self.level = level;
+                        set_underfunded(self.chain, level == Level::Critical);
+                        BalanceWatchdogState::Yield
+                    }
+                    Err(err) => {
+                        warn!(
+                            "failed to check the balance of the {} account: {}",
+                            self.name(),
+                            err
+                        );
+                        BalanceWatchdogState::Yield
+                    }
+                },
+                BalanceWatchdogState::Yield => {
+                    self.state = BalanceWatchdogState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use config::BalanceWatchdogConfig;
+    use super::{level, Level};
+
+    #[test]
+    fn test_level() {
+        let config = BalanceWatchdogConfig {
+            warn_balance: 100.into(),
+            min_balance: 10.into(),
+            check_interval: Duration::from_secs(60),
+            alert_url: None,
+        };
+        assert_eq!(Level::Sufficient, level(&config, 100.into()));
+        assert_eq!(Level::Low, level(&config, 99.into()));
+        assert_eq!(Level::Low, level(&config, 10.into()));
+        assert_eq!(Level::Critical, level(&config, 9.into()));
+    }
+}
@@ -0,0 +1,283 @@
+use std::cmp;
+use std::ops;
+use std::sync::Arc;
+use futures::{Future, Poll, Stream};
+use futures::future::{join_all, JoinAll};
+use web3::Transport;
+use web3::types::{Address, Bytes, FilterBuilder, H256, H520, TransactionRequest};
+use api::{self, LogStream};
+use app::App;
+use config::MessageCallsConfig;
+use contracts::foreign;
+use control::{pausable, Direction, Pausable, Pause};
+use database::Database;
+use dry_run;
+use error::Error;
+use maintenance::{deferred, Deferred};
+use message_call::MessageToMainnetCall;
+use shard::ShardedStream;
+use signer::{self, Chain, FailoverSignature, SendTransaction};
+use status;
+use util::web3_filter;
+
+/// returns a filter for `ForeignBridge.UserRequestForCall` events
+fn call_requests_filter(foreign: &foreign::ForeignBridge, address: Address) -> FilterBuilder {
+    let filter = foreign.events().user_request_for_call().create_filter();
+    web3_filter(filter, address)
+}
+
+fn submit_call_signature_payload(
+    foreign: &foreign::ForeignBridge,
+    call_message: Vec<u8>,
+    signature: H520,
+) -> Bytes {
+    foreign
+        .functions()
+        .submit_call_signature()
+        .input(signature.0.to_vec(), call_message)
+        .into()
+}
+
+/// State of call confirmation.
+enum CallConfirmState<T: Transport> {
+    /// Call confirm is waiting for logs.
+    Wait,
+    /// Signing calls.
+    SignCalls {
+        messages: Vec<MessageToMainnetCall>,
+        future: JoinAll<Vec<FailoverSignature<T>>>,
+        block: u64,
+    },
+    /// Confirming calls.
+    ConfirmCalls {
+        future: JoinAll<Vec<SendTransaction<T>>>,
+        /// transactions of the call requests in the order of the confirmations
+        origins: Vec<H256>,
+        block: u64,
+    },
+    /// All calls till given block have been confirmed.
+    Yield(Option<u64>),
+}
+
+pub fn create_call_confirm<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+    config: MessageCallsConfig,
+) -> CallConfirm<T> {
+    let logs_init = api::LogStreamInit {
+        // databases that predate calls start at the deployment
+        after: cmp::max(init.checked_call_confirm, init.foreign_deploy),
+        request_timeout: app.config.foreign.request_timeout,
+        poll_interval: app.config.foreign.poll_interval,
+        confirmations: app.config.foreign.required_confirmations,
+        strategy: app.config.foreign.confirmation_strategy,
+        filter: call_requests_filter(&app.foreign_bridge, init.foreign_contract_address),
+    };
+
+    CallConfirm {
+        logs: deferred(
+            pausable(
+                api::log_stream(
+                    app.connections.foreign.clone(),
+                    app.timer.clone(),
+                    logs_init,
+                ).following(&app.heads.foreign)
+                    .sharded(&app.leases, ShardedStream::CallConfirm)
+                    .retrying(&app.config.foreign.retry),
+                Pause {
+                    halted: true,
+                    ..app.config.pause(Direction::Withdraws)
+                },
+                &app.timer,
+                app.config.foreign.poll_interval,
+            ),
+            "foreign",
+            app.config.foreign.maintenance_windows.clone(),
+            app.config.clock.skew_tolerance,
+            &app.timer,
+            app.config.foreign.poll_interval,
+        ),
+        home_contract: init.home_contract_address,
+        foreign_contract: init.foreign_contract_address,
+        state: CallConfirmState::Wait,
+        config,
+        app,
+    }
+}
+
+/// stream that signs the calls requested on `foreign` and submits the signatures
+/// to `ForeignBridge.submitCallSignature`. calls over the limits of `message_calls` are skipped.
+/// calls move in the direction of withdraws and are paused with them
+pub struct CallConfirm<T: Transport> {
+    app: Arc<App<T>>,
+    config: MessageCallsConfig,
+    logs: Deferred<Pausable<LogStream<T>>>,
+    state: CallConfirmState<T>,
+    home_contract: Address,
+    foreign_contract: Address,
+}
+
+impl<T: Transport> CallConfirm<T> {
+    /// whether no signatures of calls are in progress. checked before the bridge shuts down
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            CallConfirmState::Wait | CallConfirmState::Yield(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for CallConfirm<T> {
+    type Item = u64;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                CallConfirmState::Wait => {
+                    let mut item = try_stream!(self.logs.poll());
+                    item.logs
+                        .retain(|log| self.app.leases.handles(ShardedStream::CallConfirm, log));
+                    info!("got {} new calls to sign", item.logs.len());
+                    let mut messages = Vec::new();
+                    for log in item.logs {
+                        let message = MessageToMainnetCall::from_log(log, self.home_contract)?;
+                        match message.check_limits(&self.config) {
+                            Ok(()) => {
+                                info!(
+                                    "call is ready for signature submission. tx hash {:?}",
+                                    message.sidenet_transaction_hash
+                                );
+                                messages.push(message);
+                            }
+                            Err(err) => warn!(
+                                "skipping call {:?}: {}",
+                                message.sidenet_transaction_hash, err
+                            ),
+                        }
+                    }
+
+                    let requests = messages
+                        .iter()
+                        .map(|message| {
+                            signer::sign_with_failover(self.app.clone(), Bytes(message.to_bytes()))
+                        })
+                        .collect::<Vec<_>>();
+
+                    status::set_queue("call_confirm", messages.len());
+                    info!("signing calls");
+                    CallConfirmState::SignCalls {
+                        future: join_all(requests),
+                        messages,
+                        block: item.to,
+                    }
+                }
+                CallConfirmState::SignCalls {
+                    ref mut future,
+                    ref mut messages,
+                    block,
+                } => {
+                    let signatures = try_ready!(future.poll());
+                    info!("signing calls complete");
+                    let app = &self.app;
+                    let foreign_contract = self.foreign_contract;
+                    let confirm_gas = self.config.confirm_gas;
+                    let origins = messages
+                        .iter()
+                        .map(|message| message.sidenet_transaction_hash)
+                        .collect();
+                    let confirmations = messages
+                        .drain(ops::RangeFull)
+                        .zip(signatures.into_iter())
+                        .map(|(message, (account, signature))| {
+                            let payload = submit_call_signature_payload(
+                                &app.foreign_bridge,
+                                message.to_bytes(),
+                                signature,
+                            );
+                            let request = TransactionRequest {
+                                // `submitCallSignature` requires the sender to be the signer
+                                from: account,
+                                to: Some(foreign_contract),
+                                gas: Some(confirm_gas.into()),
+                                gas_price: Some(app.config.txs.withdraw_confirm.gas_price.into()),
+                                value: None,
+                                data: Some(payload),
+                                nonce: None,
+                                condition: None,
+                            };
+                            signer::send_transaction(
+                                app,
+                                Chain::Foreign,
+                                request,
+                                app.config.txs.withdraw_confirm.dynamic_fees,
+                            )
+                        })
+                        .collect::<Result<Vec<_>, Error>>()?;
+
+                    info!("submitting {} call signatures", confirmations.len());
+                    CallConfirmState::ConfirmCalls {
+                        future: join_all(confirmations),
+                        origins,
+                        block,
+                    }
+                }
+                CallConfirmState::ConfirmCalls {
+                    ref mut future,
+                    ref origins,
+                    block,
+                } => {
+                    let hashes = try_ready!(future.poll());
+                    // nothing was submitted in a dry run
+                    if !dry_run::is_enabled() {
+                        for (origin, hash) in origins.iter().zip(&hashes) {
+                            info!(
+                                "submitted signature of call {:?} by transaction {:?}",
+                                origin, hash
+                            );
+                        }
+                    }
+                    status::set_queue("call_confirm", 0);
+                    info!("submitting call signatures complete");
+                    CallConfirmState::Yield(Some(block))
+                }
+                CallConfirmState::Yield(ref mut block) => match block.take() {
+                    None => {
+                        info!("waiting for new calls that should get signed");
+                        CallConfirmState::Wait
+                    }
+                    some => return Ok(some.into()),
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethabi;
+    use web3::types::H520;
+    use contracts::foreign;
+    use super::submit_call_signature_payload;
+
+    #[test]
+    fn test_submit_call_signature_payload() {
+        let foreign = foreign::ForeignBridge::default();
+        let mut message = vec![0x12; 161];
+        message[0] = 0x80;
+        let signature = H520::from(0x34);
+        let payload = submit_call_signature_payload(&foreign, message.clone(), signature);
+        let tokens = ethabi::decode(
+            &[ethabi::ParamType::Bytes, ethabi::ParamType::Bytes],
+            &payload.0[4..],
+        ).unwrap();
+        assert_eq!(
+            vec![
+                ethabi::Token::Bytes(signature.0.to_vec()),
+                ethabi::Token::Bytes(message),
+            ],
+            tokens
+        );
+    }
+}
@@ -0,0 +1,488 @@
+use std::cmp;
+use std::sync::Arc;
+use futures::{Future, Poll, Stream};
+use futures::future::{join_all, Join3, JoinAll};
+use tokio_timer::Timeout;
+use web3::Transport;
+use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest, U256};
+use ethabi::RawLog;
+use app::App;
+use api::{self, ApiCall, LogStream};
+use config::MessageCallsConfig;
+use contracts::foreign;
+use control::{pausable, Direction, Pausable, Pause};
+use database::Database;
+use error::{self, Error};
+use maintenance::{deferred, Deferred};
+use message_call::MessageToMainnetCall;
+use shard::ShardedStream;
+use signature::{verify_signatures, Signature};
+use signer::{remote_signer, Chain};
+use status;
+use transaction::{relay_transaction, RelayTransaction};
+use util::web3_filter;
+use super::withdraw_relay::FetchAuthorities;
+
+/// returns a filter for `ForeignBridge.CollectedCallSignatures` events
+fn collected_call_signatures_filter(
+    foreign: &foreign::ForeignBridge,
+    address: Address,
+) -> FilterBuilder {
+    let filter = foreign.events().collected_call_signatures().create_filter();
+    web3_filter(filter, address)
+}
+
+/// payloads for calls to `ForeignBridge.callSignature` and `ForeignBridge.callMessage`
+/// to retrieve the signatures (v, r, s) and message of a call
+/// which the call relay process should later relay to `HomeBridge`
+/// by calling `HomeBridge.executeCall(v, r, s, message)`
+#[derive(Debug, PartialEq)]
+struct CallRelayAssignment {
+    signature_payloads: Vec<Bytes>,
+    message_payload: Bytes,
+}
+
+fn call_signatures_payload(
+    foreign: &foreign::ForeignBridge,
+    required_signatures: u32,
+    my_address: Address,
+    log: Log,
+) -> error::Result<Option<CallRelayAssignment>> {
+    let hash = log.transaction_hash
+        .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
+    let raw_log = RawLog {
+        topics: log.topics.into_iter().map(|t| t.0.into()).collect(),
+        data: log.data.0,
+    };
+    let collected = foreign
+        .events()
+        .collected_call_signatures()
+        .parse_log(raw_log)?;
+    if collected.authority_responsible_for_relay != my_address.0.into() {
+        info!(
+            "bridge not responsible for relaying call to home. tx hash: {}",
+            hash
+        );
+        return Ok(None);
+    }
+    let signature_payloads = (0..required_signatures)
+        .into_iter()
+        .map(|index| {
+            foreign
+                .functions()
+                .call_signature()
+                .input(collected.message_hash, index)
+        })
+        .map(Into::into)
+        .collect();
+    let message_payload = foreign
+        .functions()
+        .call_message()
+        .input(collected.message_hash)
+        .into();
+
+    Ok(Some(CallRelayAssignment {
+        signature_payloads,
+        message_payload,
+    }))
+}
+
+/// decodes the outputs of `ForeignBridge.callMessage` and `ForeignBridge.callSignature`
+/// of a call into the message and the signatures to relay.
+/// fails if any of them is malformed
+fn decode_call(
+    foreign: &foreign::ForeignBridge,
+    message_output: &Bytes,
+    signature_outputs: &[Bytes],
+) -> error::Result<(Bytes, MessageToMainnetCall, Vec<Signature>)> {
+    let message = foreign
+        .functions()
+        .call_message()
+        .output(message_output.0.as_slice())?;
+    let call = MessageToMainnetCall::try_from_bytes(&message)?;
+    let signatures = signature_outputs
+        .iter()
+        .map(|output| {
+            let signature = foreign
+                .functions()
+                .call_signature()
+                .output(output.0.as_slice())?;
+            Signature::try_from_bytes(&signature)
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+    Ok((Bytes(message), call, signatures))
+}
+
+/// state of the call relay state machine
+enum CallRelayState<T: Transport> {
+    Wait,
+    FetchMessagesSignatures {
+        /// messages, signatures and the authorities of `HomeBridge`
+        future: Join3<
+            JoinAll<Vec<Timeout<ApiCall<Bytes, T::Out>>>>,
+            JoinAll<Vec<JoinAll<Vec<Timeout<ApiCall<Bytes, T::Out>>>>>>,
+            FetchAuthorities<T>,
+        >,
+        block: u64,
+    },
+    RelayCalls {
+        future: JoinAll<Vec<RelayTransaction<T>>>,
+        /// transactions of the call requests in the order of the relays
+        origins: Vec<H256>,
+        block: u64,
+    },
+    Yield(Option<u64>),
+}
+
+pub fn create_call_relay<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+    config: MessageCallsConfig,
+) -> CallRelay<T> {
+    let logs_init = api::LogStreamInit {
+        // databases that predate calls start at the deployment
+        after: cmp::max(init.checked_call_relay, init.foreign_deploy),
+        request_timeout: app.config.foreign.request_timeout,
+        poll_interval: app.config.foreign.poll_interval,
+        confirmations: app.config.foreign.required_confirmations,
+        strategy: app.config.foreign.confirmation_strategy,
+        filter: collected_call_signatures_filter(
+            &app.foreign_bridge,
+            init.foreign_contract_address,
+        ),
+    };
+
+    CallRelay {
+        logs: deferred(
+            pausable(
+                api::log_stream(
+                    app.connections.foreign.clone(),
+                    app.timer.clone(),
+                    logs_init,
+                ).following(&app.heads.foreign)
+                    .sharded(&app.leases, ShardedStream::CallRelay)
+                    .retrying(&app.config.foreign.retry),
+                Pause {
+                    chain: Chain::Home,
+                    ..app.config.pause(Direction::Withdraws)
+                },
+                &app.timer,
+                app.config.foreign.poll_interval,
+            ),
+            "home",
+            app.config.home.maintenance_windows.clone(),
+            app.config.clock.skew_tolerance,
+            &app.timer,
+            app.config.home.poll_interval,
+        ),
+        home_contract: init.home_contract_address,
+        foreign_contract: init.foreign_contract_address,
+        state: CallRelayState::Wait,
+        config,
+        app,
+    }
+}
+
+/// stream that relays calls whose signatures `ForeignBridge` collected
+/// to `HomeBridge.executeCall` if this authority is responsible for them.
+/// the gas of the relay is the gas limit of the call plus `message_calls.relay_gas`
+pub struct CallRelay<T: Transport> {
+    app: Arc<App<T>>,
+    config: MessageCallsConfig,
+    logs: Deferred<Pausable<LogStream<T>>>,
+    state: CallRelayState<T>,
+    foreign_contract: Address,
+    home_contract: Address,
+}
+
+impl<T: Transport> CallRelay<T> {
+    /// whether no relays of calls are in progress. checked before the bridge shuts down
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            CallRelayState::Wait | CallRelayState::Yield(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for CallRelay<T> {
+    type Item = u64;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                CallRelayState::Wait => {
+                    let mut item = try_stream!(self.logs.poll());
+                    item.logs
+                        .retain(|log| self.app.leases.handles(ShardedStream::CallRelay, log));
+                    info!("got {} new signed calls to relay", item.logs.len());
+                    let assignments = item.logs
+                        .into_iter()
+                        .map(|log| {
+                            call_signatures_payload(
+                                &self.app.foreign_bridge,
+                                self.app.config.authorities.required_signatures,
+                                self.app.config.foreign.account,
+                                log,
+                            )
+                        })
+                        .collect::<error::Result<Vec<_>>>()?
+                        .into_iter()
+                        .filter_map(|a| a)
+                        .collect::<Vec<_>>();
+
+                    status::set_queue("call_relay", assignments.len());
+                    let app = &self.app;
+                    let foreign_contract = self.foreign_contract;
+                    let call = |payload| {
+                        app.timer.timeout(
+                            api::call(&app.connections.foreign, foreign_contract, payload),
+                            app.config.foreign.request_timeout,
+                        )
+                    };
+                    let mut message_calls = Vec::new();
+                    let mut signature_calls = Vec::new();
+                    for assignment in assignments {
+                        message_calls.push(call(assignment.message_payload));
+                        signature_calls.push(join_all(
+                            assignment
+                                .signature_payloads
+                                .into_iter()
+                                .map(&call)
+                                .collect::<Vec<_>>(),
+                        ));
+                    }
+                    let authorities = if message_calls.is_empty() {
+                        FetchAuthorities::skip(app.clone(), self.home_contract)
+                    } else {
+                        FetchAuthorities::new(app.clone(), self.home_contract)
+                    };
+
+                    info!("fetching call messages and signatures");
+                    CallRelayState::FetchMessagesSignatures {
+                        future: join_all(message_calls)
+                            .join3(join_all(signature_calls), authorities),
+                        block: item.to,
+                    }
+                }
+                CallRelayState::FetchMessagesSignatures {
+                    ref mut future,
+                    block,
+                } => {
+                    let (messages_raw, signatures_raw, authorities) = try_ready!(future.poll());
+                    info!("fetching call messages and signatures complete");
+
+                    let app = &self.app;
+                    let home_contract = self.home_contract;
+                    let config = &self.config;
+                    let mut origins = Vec::new();
+                    let mut relays = Vec::new();
+                    for (message, signatures) in messages_raw.iter().zip(&signatures_raw) {
+                        // a malformed payload skips its call instead of stopping the relay
+                        let (message, call, signatures) =
+                            match decode_call(&app.foreign_bridge, message, signatures) {
+                                Ok(decoded) => decoded,
+                                Err(err) => {
+                                    warn!("skipping call that can't be decoded: {}", err);
+                                    continue;
+                                }
+                            };
+                        if call.mainnet_bridge_address != home_contract {
+                            warn!(
+                                "skipping call message meant for HomeBridge at {:?}: {:?}",
+                                call.mainnet_bridge_address, call
+                            );
+                            continue;
+                        }
+                        if let Err(err) = call.check_limits(config) {
+                            warn!("skipping call {:?}: {}", call.sidenet_transaction_hash, err);
+                            continue;
+                        }
+                        // signatures `HomeBridge.executeCall` rejects would only waste gas
+                        if let Err(err) = verify_signatures(
+                            &message.0,
+                            &signatures,
+                            &authorities,
+                            app.config.authorities.required_signatures,
+                        ) {
+                            warn!(
+                                "skipping call {:?} whose signatures don't verify: {}",
+                                call.sidenet_transaction_hash, err
+                            );
+                            status::record_error(format!(
+                                "skipped call {:?} whose signatures don't verify: {}",
+                                call.sidenet_transaction_hash, err
+                            ));
+                            continue;
+                        }
+
+                        let payload: Bytes = app.home_bridge
+                            .functions()
+                            .execute_call()
+                            .input(
+                                signatures.iter().map(|x| x.v),
+                                signatures.iter().map(|x| x.r),
+                                signatures.iter().map(|x| x.s),
+                                message.0,
+                            )
+                            .into();
+                        let request = TransactionRequest {
+                            from: app.config.home.account,
+                            to: Some(home_contract),
+                            gas: Some(call.gas_limit + U256::from(config.relay_gas)),
+                            // `HomeBridge.executeCall` requires the gas price of the request
+                            gas_price: Some(call.mainnet_gas_price),
+                            value: None,
+                            data: Some(payload),
+                            nonce: None,
+                            condition: None,
+                        };
+                        origins.push(call.sidenet_transaction_hash);
+                        relays.push(
+                            relay_transaction(
+                                app.connections.home.clone(),
+                                app.timer.clone(),
+                                &app.config.home,
+                                request,
+                                app.config
+                                    .home
+                                    .access_list_mode(&app.config.txs.withdraw_relay),
+                            ).via_signer(remote_signer(&app.config), Chain::Home)
+                                .via_keystore(app.keystore.clone(), Chain::Home)
+                                .with_nonces(app.nonces.get(Chain::Home))
+                                .with_fixed_gas_price(),
+                        );
+                    }
+
+                    info!("relaying {} calls", relays.len());
+                    CallRelayState::RelayCalls {
+                        future: join_all(relays),
+                        origins,
+                        block,
+                    }
+                }
+                CallRelayState::RelayCalls {
+                    ref mut future,
+                    ref origins,
+                    block,
+                } => {
+                    let relayed = try_ready!(future.poll());
+                    for (origin, hash) in origins.iter().zip(&relayed) {
+                        match *hash {
+                            Some(hash) => {
+                                info!("relayed call {:?} by transaction {:?}", origin, hash)
+                            }
+                            None => warn!(
+                                "call {:?} was not relayed because its simulation reverted",
+                                origin
+                            ),
+                        }
+                    }
+                    status::set_queue("call_relay", 0);
+                    info!("relaying calls complete");
+                    CallRelayState::Yield(Some(block))
+                }
+                CallRelayState::Yield(ref mut block) => match block.take() {
+                    None => {
+                        info!("waiting for signed calls to relay");
+                        CallRelayState::Wait
+                    }
+                    some => return Ok(some.into()),
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethabi;
+    use rustc_hex::FromHex;
+    use tiny_keccak::keccak256;
+    use web3::types::{Bytes, Log};
+    use contracts::foreign;
+    use message_call::MessageToMainnetCall;
+    use signature::Signature;
+    use super::{call_signatures_payload, decode_call};
+
+    #[test]
+    fn test_decode_call() {
+        let foreign = foreign::ForeignBridge::default();
+        let message = MessageToMainnetCall {
+            recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+            sender: "0000000000000000000000000000000000000011".into(),
+            sidenet_transaction_hash:
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+            gas_limit: 100_000.into(),
+            mainnet_gas_price: 1000.into(),
+            mainnet_bridge_address: "49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            data: vec![0xa9, 0x05, 0x9c, 0xbb],
+        };
+        let signature = Signature {
+            v: 28,
+            r: "75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            s: "6c640dda39845de99d9570ba76798e275ebc3036b5a5a758be9a8c0e6f6ed8d4".into(),
+        };
+        let message_output: Bytes = message.to_payload().into();
+        let signature_output: Bytes = signature.to_payload().into();
+
+        let (bytes, decoded, signatures) =
+            decode_call(&foreign, &message_output, &[signature_output.clone()]).unwrap();
+        assert_eq!(message.to_bytes(), bytes.0);
+        assert_eq!(message, decoded);
+        assert_eq!(vec![signature], signatures);
+
+        // calls without a function selector are rejected
+        let truncated = message.to_bytes()[..158].to_vec();
+        let truncated_output: Bytes = ethabi::encode(&[ethabi::Token::Bytes(truncated)]).into();
+        assert!(decode_call(&foreign, &truncated_output, &[signature_output]).is_err());
+        assert!(decode_call(&foreign, &Bytes(vec![1, 2, 3]), &[]).is_err());
+    }
+
+    #[test]
+    fn test_call_signatures_payload() {
+        let foreign = foreign::ForeignBridge::default();
+        let data = "000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0".from_hex().unwrap();
+        let log = Log {
+            data: data.into(),
+            topics: vec![
+                keccak256("CollectedCallSignatures(address,bytes32)".as_bytes()).into(),
+            ],
+            transaction_hash: Some(
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+            ),
+            ..Default::default()
+        };
+
+        let assignment = call_signatures_payload(
+            &foreign,
+            2,
+            "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+            log.clone(),
+        ).unwrap()
+            .unwrap();
+        let hash = "00000000000000000000000000000000000000000000000000000000000000f0";
+        let expected_message: Bytes = [
+            &keccak256("callMessage(bytes32)".as_bytes())[..4],
+            &hash.from_hex().unwrap()[..],
+        ].concat()
+            .into();
+        assert_eq!(expected_message, assignment.message_payload);
+        assert_eq!(2, assignment.signature_payloads.len());
+        assert_eq!(
+            &keccak256("callSignature(bytes32,uint256)".as_bytes())[..4],
+            &assignment.signature_payloads[1].0[..4]
+        );
+        assert_eq!(1, assignment.signature_payloads[1].0[67]);
+
+        let not_ours = call_signatures_payload(
+            &foreign,
+            2,
+            "aff3454fce5edbc8cca8697c15331677e6ebcccd".into(),
+            log,
+        ).unwrap();
+        assert_eq!(None, not_ours);
+    }
+}
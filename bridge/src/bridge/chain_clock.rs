@@ -0,0 +1,95 @@
+/// periodic observation of the time of both chains.
+/// records the timestamps of the latest blocks for `clock::now`
+/// and warns when the clock of this host diverges from the time of a chain.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use futures::{Async, Future, Poll, Stream};
+use futures::future::Join;
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::BlockNumber;
+use api::{self, ApiCall, BlockHeader};
+use app::App;
+use clock;
+use error::Error;
+
+type Fetch<T> = Timeout<ApiCall<BlockHeader, <T as Transport>::Out>>;
+
+/// state of the chain clock
+enum ChainClockState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the latest blocks of both chains
+    Fetch(Join<Fetch<T>, Fetch<T>>),
+}
+
+pub fn create_chain_clock<T: Transport + Clone>(app: Arc<App<T>>) -> ChainClock<T> {
+    ChainClock {
+        interval: app.timer.interval(app.config.clock.check_interval),
+        app,
+        state: ChainClockState::Wait,
+    }
+}
+
+/// stream that observes the time of both chains on every tick of `interval`
+pub struct ChainClock<T: Transport> {
+    app: Arc<App<T>>,
+    interval: Interval,
+    state: ChainClockState<T>,
+}
+
+impl<T: Transport> ChainClock<T> {
+    /// records the time of `chain` and warns if this host diverges from it
+    fn observe(&self, chain: &'static str, header: &BlockHeader) {
+        let timestamp = header.timestamp.low_u64();
+        clock::observe(chain, timestamp);
+        let divergence = clock::divergence(
+            SystemTime::now(),
+            UNIX_EPOCH + Duration::from_secs(timestamp),
+        );
+        if divergence.abs() as u64 > self.app.config.clock.max_divergence.as_secs() {
+            warn!(
+                "clock of this host is {}s {} the latest block of {}. check the time sync of this host and the node",
+                divergence.abs(),
+                if divergence > 0 { "ahead of" } else { "behind" },
+                chain
+            );
+        }
+    }
+}
+
+impl<T: Transport> Stream for ChainClock<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                ChainClockState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    let app = &self.app;
+                    ChainClockState::Fetch(
+                        app.timer
+                            .timeout(
+                                api::block_header(&app.connections.home, BlockNumber::Latest),
+                                app.config.home.request_timeout,
+                            )
+                            .join(app.timer.timeout(
+                                api::block_header(&app.connections.foreign, BlockNumber::Latest),
+                                app.config.foreign.request_timeout,
+                            )),
+                    )
+                }
+                ChainClockState::Fetch(ref mut future) => {
+                    let (home, foreign) = try_ready!(future.poll());
+                    self.observe("home", &home);
+                    self.observe("foreign", &foreign);
+                    self.state = ChainClockState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
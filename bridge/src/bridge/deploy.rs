@@ -4,6 +4,10 @@ use web3::Transport;
 use web3::confirm::SendTransactionWithConfirmation;
 use web3::types::{TransactionReceipt, TransactionRequest};
 use app::App;
+#[cfg(feature = "contracts-v2")]
+use config::Tokens;
+#[cfg(feature = "contracts-v2")]
+use contracts::{foreign_token, home_token};
 use std::path::Path;
 use std::fs;
 use std::fs::File;
@@ -23,6 +27,97 @@ pub enum DeployState<T: Transport + Clone> {
     },
 }
 
+/// name of the contract deployed on `home`: `HomeTokenBridge` if tokens are bridged
+pub fn home_contract_name<T: Transport>(app: &App<T>) -> &'static str {
+    match app.config.tokens() {
+        Some(_) => "HomeTokenBridge",
+        None => "HomeBridge",
+    }
+}
+
+/// name of the contract deployed on `foreign`: `ForeignTokenBridge` if tokens are bridged
+pub fn foreign_contract_name<T: Transport>(app: &App<T>) -> &'static str {
+    match app.config.tokens() {
+        Some(_) => "ForeignTokenBridge",
+        None => "ForeignBridge",
+    }
+}
+
+/// chain ids the contracts require version 2 messages to name.
+/// `0` for an unknown chain id which makes the contracts refuse all version 2 messages
+fn chain_ids<T: Transport>(app: &App<T>) -> (u64, u64) {
+    (
+        app.config.home.chain_id.unwrap_or(0),
+        app.config.foreign.chain_id.unwrap_or(0),
+    )
+}
+
+/// creation code of `HomeTokenBridge` for `tokens`
+#[cfg(feature = "contracts-v2")]
+fn home_token_creation_code<T: Transport>(app: &App<T>, tokens: Tokens) -> Vec<u8> {
+    let (home_chain_id, foreign_chain_id) = chain_ids(app);
+    home_token::HomeTokenBridge::default().constructor(
+        app.config.home.contract.bin.clone().0,
+        app.config.authorities.required_signatures,
+        app.config.authorities.accounts.clone(),
+        tokens.home,
+        home_chain_id,
+        foreign_chain_id,
+    )
+}
+
+/// creation code of `ForeignTokenBridge` for `tokens`
+#[cfg(feature = "contracts-v2")]
+fn foreign_token_creation_code<T: Transport>(app: &App<T>, tokens: Tokens) -> Vec<u8> {
+    let (home_chain_id, foreign_chain_id) = chain_ids(app);
+    foreign_token::ForeignTokenBridge::default().constructor(
+        app.config.foreign.contract.bin.clone().0,
+        app.config.authorities.required_signatures,
+        app.config.authorities.accounts.clone(),
+        tokens.foreign,
+        home_chain_id,
+        foreign_chain_id,
+    )
+}
+
+/// data of the transaction that deploys the contract on `home`:
+/// the bytecode of `home.contract` followed by the constructor arguments of the config
+pub fn home_creation_code<T: Transport>(app: &App<T>) -> Vec<u8> {
+    let (home_chain_id, foreign_chain_id) = chain_ids(app);
+    match app.config.tokens() {
+        #[cfg(feature = "contracts-v2")]
+        Some(tokens) => home_token_creation_code(app, tokens),
+        _ => app.home_bridge.constructor(
+            app.config.home.contract.bin.clone().0,
+            app.config.authorities.required_signatures,
+            app.config.authorities.accounts.clone(),
+            app.config.estimated_gas_cost_of_withdraw,
+            app.config.max_total_home_contract_balance,
+            app.config.max_single_deposit_value,
+            home_chain_id,
+            foreign_chain_id,
+        ),
+    }
+}
+
+/// data of the transaction that deploys the contract on `foreign`:
+/// the bytecode of `foreign.contract` followed by the constructor arguments of the config
+pub fn foreign_creation_code<T: Transport>(app: &App<T>) -> Vec<u8> {
+    let (home_chain_id, foreign_chain_id) = chain_ids(app);
+    match app.config.tokens() {
+        #[cfg(feature = "contracts-v2")]
+        Some(tokens) => foreign_token_creation_code(app, tokens),
+        _ => app.foreign_bridge.constructor(
+            app.config.foreign.contract.bin.clone().0,
+            app.config.authorities.required_signatures,
+            app.config.authorities.accounts.clone(),
+            app.config.estimated_gas_cost_of_withdraw,
+            home_chain_id,
+            foreign_chain_id,
+        ),
+    }
+}
+
 pub struct DeployHome<T: Transport + Clone> {
     app: Arc<App<T>>,
     state: DeployState<T>,
@@ -46,14 +141,7 @@ impl<T: Transport + Clone> Future for DeployHome<T> {
             let next_state = match self.state {
                 DeployState::Deployed { ref contract } => return Ok(contract.clone().into()),
                 DeployState::NotDeployed => {
-                    let data = self.app.home_bridge.constructor(
-                        self.app.config.home.contract.bin.clone().0,
-                        self.app.config.authorities.required_signatures,
-                        self.app.config.authorities.accounts.clone(),
-                        self.app.config.estimated_gas_cost_of_withdraw,
-                        self.app.config.max_total_home_contract_balance,
-                        self.app.config.max_single_deposit_value,
-                    );
+                    let data = home_creation_code(&self.app);
 
                     let tx_request = TransactionRequest {
                         from: self.app.config.home.account,
@@ -73,7 +161,7 @@ impl<T: Transport + Clone> Future for DeployHome<T> {
                         self.app.config.home.required_confirmations,
                     );
 
-                    info!("sending HomeBridge contract deployment transaction and waiting for {} confirmations...", self.app.config.home.required_confirmations);
+                    info!("sending {} contract deployment transaction and waiting for {} confirmations...", home_contract_name(&self.app), self.app.config.home.required_confirmations);
 
                     DeployState::Deploying {
                         data: data,
@@ -88,14 +176,28 @@ impl<T: Transport + Clone> Future for DeployHome<T> {
                     let address = receipt
                         .contract_address
                         .expect("contract creation receipt must have an address; qed");
-                    info!("HomeBridge deployment completed to {:?}", address);
+                    info!(
+                        "{} deployment completed to {:?}",
+                        home_contract_name(&self.app),
+                        address
+                    );
 
+                    let (abi, bin) = match self.app.config.tokens() {
+                        Some(_) => (
+                            include_str!(concat!(env!("OUT_DIR"), "/HomeTokenBridge.abi")),
+                            include_str!(concat!(env!("OUT_DIR"), "/HomeTokenBridge.bin")),
+                        ),
+                        None => (
+                            include_str!(concat!(env!("OUT_DIR"), "/HomeBridge.abi")),
+                            include_str!(concat!(env!("OUT_DIR"), "/HomeBridge.bin")),
+                        ),
+                    };
                     DeployState::Deployed {
                         contract: DeployedContract::new(
-                            "HomeBridge".into(),
+                            home_contract_name(&self.app).into(),
                             include_str!("../../../contracts/bridge.sol").into(),
-                            include_str!("../../../compiled_contracts/HomeBridge.abi").into(),
-                            include_str!("../../../compiled_contracts/HomeBridge.bin").into(),
+                            abi.into(),
+                            bin.into(),
                             data.to_hex(),
                             receipt,
                         ),
@@ -131,12 +233,7 @@ impl<T: Transport + Clone> Future for DeployForeign<T> {
             let next_state = match self.state {
                 DeployState::Deployed { ref contract } => return Ok(contract.clone().into()),
                 DeployState::NotDeployed => {
-                    let data = self.app.foreign_bridge.constructor(
-                        self.app.config.foreign.contract.bin.clone().0,
-                        self.app.config.authorities.required_signatures,
-                        self.app.config.authorities.accounts.clone(),
-                        self.app.config.estimated_gas_cost_of_withdraw,
-                    );
+                    let data = foreign_creation_code(&self.app);
 
                     let tx_request = TransactionRequest {
                         from: self.app.config.foreign.account,
@@ -156,7 +253,7 @@ impl<T: Transport + Clone> Future for DeployForeign<T> {
                         self.app.config.foreign.required_confirmations,
                     );
 
-                    info!("sending ForeignBridge contract deployment transaction and waiting for {} confirmations...", self.app.config.foreign.required_confirmations);
+                    info!("sending {} contract deployment transaction and waiting for {} confirmations...", foreign_contract_name(&self.app), self.app.config.foreign.required_confirmations);
 
                     DeployState::Deploying {
                         data: data,
@@ -171,14 +268,28 @@ impl<T: Transport + Clone> Future for DeployForeign<T> {
                     let address = receipt
                         .contract_address
                         .expect("contract creation receipt must have an address; qed");
-                    info!("ForeignBridge deployment completed to {:?}", address);
+                    info!(
+                        "{} deployment completed to {:?}",
+                        foreign_contract_name(&self.app),
+                        address
+                    );
 
+                    let (abi, bin) = match self.app.config.tokens() {
+                        Some(_) => (
+                            include_str!(concat!(env!("OUT_DIR"), "/ForeignTokenBridge.abi")),
+                            include_str!(concat!(env!("OUT_DIR"), "/ForeignTokenBridge.bin")),
+                        ),
+                        None => (
+                            include_str!(concat!(env!("OUT_DIR"), "/ForeignBridge.abi")),
+                            include_str!(concat!(env!("OUT_DIR"), "/ForeignBridge.bin")),
+                        ),
+                    };
                     DeployState::Deployed {
                         contract: DeployedContract::new(
-                            "ForeignBridge".into(),
+                            foreign_contract_name(&self.app).into(),
                             include_str!("../../../contracts/bridge.sol").into(),
-                            include_str!("../../../compiled_contracts/ForeignBridge.abi").into(),
-                            include_str!("../../../compiled_contracts/ForeignBridge.bin").into(),
+                            abi.into(),
+                            bin.into(),
                             data.to_hex(),
                             receipt,
                         ),
@@ -1,26 +1,154 @@
+use std::iter;
+use std::mem;
 use std::sync::Arc;
 use futures::{Future, Poll, Stream};
-use futures::future::{join_all, JoinAll};
-use tokio_timer::Timeout;
+use futures::future::{join_all, Join, JoinAll};
 use web3::Transport;
-use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest};
+use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest, U256};
 use ethabi::RawLog;
-use api::{self, ApiCall, LogStream};
+use api::{self, LogStream, LogStreamItem};
+use clock;
 use error::{Error, Result};
 use database::Database;
+#[cfg(feature = "contracts-v2")]
+use config::Tokens;
 use contracts::{foreign, home};
+#[cfg(feature = "contracts-v2")]
+use contracts::{foreign_token, home_token};
+use event_queue::TransferEvent;
+use gas_price::{gas_price, GasPrice};
+use history::{self, BlockTimestamps, Transfer, TransferDirection};
+use logging::{log_relay, RelayEvent, RelayStage};
+use metrics;
+use parked::ParkedRelay;
+use sinks;
+use status;
 use util::web3_filter;
 use app::App;
+use control::{pausable, Direction, Pausable};
+use maintenance::{deferred, Deferred};
+use shard::ShardedStream;
+use signer::{remote_signer, Chain};
+use transaction::{relay_transaction, RelayTransaction};
+use value_limits::{limited, Limited};
 
 fn deposits_filter(home: &home::HomeBridge, address: Address) -> FilterBuilder {
     let filter = home.events().deposit().create_filter();
     web3_filter(filter, address)
 }
 
+#[cfg(feature = "contracts-v2")]
+fn token_deposits_filter(address: Address) -> FilterBuilder {
+    let home = home_token::HomeTokenBridge::default();
+    let filter = home.events().transfer_to_foreign().create_filter();
+    web3_filter(filter, address)
+}
+
+/// returns the payload of the confirmation of a deposit on `foreign`.
+/// the confirmation charges `deposit_fee` tokens via `ForeignBridge.depositWithFee` if set
+pub fn deposit_payload(
+    foreign: &foreign::ForeignBridge,
+    recipient: Address,
+    value: U256,
+    transaction_hash: H256,
+    deposit_fee: Option<U256>,
+) -> Bytes {
+    match deposit_fee {
+        Some(fee) => foreign.functions().deposit_with_fee().input(
+            recipient,
+            value,
+            transaction_hash.0,
+            fee,
+        ),
+        None => foreign
+            .functions()
+            .deposit()
+            .input(recipient, value, transaction_hash.0),
+    }.into()
+}
+
+/// deposit of ether on `home` to be confirmed on `foreign`
+#[derive(Debug, PartialEq, Clone)]
+struct Deposit {
+    recipient: Address,
+    value: U256,
+    transaction_hash: H256,
+}
+
+/// returns the deposit in the `HomeBridge.Deposit` event in `log`
+fn parse_deposit(home: &home::HomeBridge, log: &Log) -> Result<Deposit> {
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let deposit_log = home.events().deposit().parse_log(raw_log)?;
+    Ok(Deposit {
+        recipient: deposit_log.recipient,
+        value: deposit_log.value,
+        transaction_hash: log.transaction_hash
+            .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?,
+    })
+}
+
+/// returns the payloads that confirm the deposits of the batch `deposits` one by one
+/// once the batch reverted. empty for a single deposit
+fn deposit_single_payloads(
+    foreign: &foreign::ForeignBridge,
+    deposits: &[Deposit],
+    deposit_fee: Option<U256>,
+) -> Vec<Bytes> {
+    if deposits.len() == 1 {
+        return Vec::new();
+    }
+    deposits
+        .iter()
+        .map(|deposit| {
+            deposit_payload(
+                foreign,
+                deposit.recipient,
+                deposit.value,
+                deposit.transaction_hash,
+                deposit_fee,
+            )
+        })
+        .collect()
+}
+
+/// returns the payload of the confirmation of `deposits` on `foreign` in one transaction
+/// via `ForeignBridge.depositBatch` which charges `deposit_fee` tokens for each if set.
+/// a single deposit is confirmed via `ForeignBridge.deposit` or `ForeignBridge.depositWithFee`
+fn deposit_batch_payload(
+    foreign: &foreign::ForeignBridge,
+    deposits: &[Deposit],
+    deposit_fee: Option<U256>,
+) -> Bytes {
+    if deposits.len() == 1 {
+        let deposit = &deposits[0];
+        return deposit_payload(
+            foreign,
+            deposit.recipient,
+            deposit.value,
+            deposit.transaction_hash,
+            deposit_fee,
+        );
+    }
+    foreign
+        .functions()
+        .deposit_batch()
+        .input(
+            deposits.iter().map(|deposit| deposit.recipient),
+            deposits.iter().map(|deposit| deposit.value),
+            deposits.iter().map(|deposit| deposit.transaction_hash),
+            deposit_fee.unwrap_or_else(U256::zero),
+        )
+        .into()
+}
+
 fn deposit_relay_payload(
     home: &home::HomeBridge,
     foreign: &foreign::ForeignBridge,
     log: Log,
+    deposit_fee: Option<U256>,
 ) -> Result<Bytes> {
     let raw_log = RawLog {
         topics: log.topics,
@@ -28,22 +156,247 @@ fn deposit_relay_payload(
     };
     let deposit_log = home.events().deposit().parse_log(raw_log)?;
     let hash = log.transaction_hash
-        .expect("log to be mined and contain `transaction_hash`");
-    let payload = foreign.functions().deposit().input(
+        .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
+    Ok(deposit_payload(
+        foreign,
         deposit_log.recipient,
         deposit_log.value,
-        hash.0,
-    );
+        hash,
+        deposit_fee,
+    ))
+}
+
+/// returns the value of the deposit in `log` for the value limits
+fn deposit_value(log: &Log) -> Result<U256> {
+    Ok(parse_deposit(&home::HomeBridge::default(), log)?.value)
+}
+
+/// returns the value of the transfer of tokens in `log` for the value limits
+#[cfg(feature = "contracts-v2")]
+fn token_deposit_value(log: &Log) -> Result<U256> {
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let home = home_token::HomeTokenBridge::default();
+    Ok(home.events().transfer_to_foreign().parse_log(raw_log)?.value)
+}
+
+/// returns the entry of the relay history for the deposit in `log`
+/// of which `deposit_fee` tokens are charged
+fn deposit_transfer(
+    home: &home::HomeBridge,
+    log: &Log,
+    deposit_fee: Option<U256>,
+) -> Result<Transfer> {
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let deposit_log = home.events().deposit().parse_log(raw_log)?;
+    Ok(Transfer {
+        direction: TransferDirection::Deposit,
+        block_number: log.block_number
+            .ok_or_else(|| "`log` must be mined and contain `block_number`")?
+            .low_u64(),
+        transaction_hash: log.transaction_hash
+            .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?,
+        block_timestamp: None,
+        recipient: deposit_log.recipient,
+        value: deposit_log.value,
+        relay_transaction_hash: None,
+        relayed_at: None,
+        fee: Some(deposit_fee.unwrap_or_else(U256::zero)),
+    })
+}
+
+/// returns the payload of the confirmation on `foreign` of a transfer of `tokens.home`
+/// to `HomeTokenBridge` in `log`. fails if `log` transfers another token.
+/// the confirmation charges `deposit_fee` tokens via `ForeignTokenBridge.depositWithFee` if set
+#[cfg(feature = "contracts-v2")]
+fn token_deposit_relay_payload(
+    tokens: &Tokens,
+    log: Log,
+    deposit_fee: Option<U256>,
+) -> Result<Bytes> {
+    let home = home_token::HomeTokenBridge::default();
+    let foreign = foreign_token::ForeignTokenBridge::default();
+    let hash = log.transaction_hash
+        .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
+    let raw_log = RawLog {
+        topics: log.topics,
+        data: log.data.0,
+    };
+    let transfer_log = home.events().transfer_to_foreign().parse_log(raw_log)?;
+    if transfer_log.token != tokens.home {
+        bail!(
+            "transfer to foreign of token {:?} but `home.token_address` is {:?}",
+            transfer_log.token,
+            tokens.home
+        );
+    }
+    let payload = match deposit_fee {
+        Some(fee) => foreign.functions().deposit_with_fee().input(
+            tokens.foreign,
+            transfer_log.recipient,
+            transfer_log.value,
+            hash.0,
+            fee,
+        ),
+        None => foreign.functions().deposit().input(
+            tokens.foreign,
+            transfer_log.recipient,
+            transfer_log.value,
+            hash.0,
+        ),
+    };
     Ok(payload.into())
 }
 
+/// returns the entry of the relay history for the transfer of tokens in `log`
+/// of which `deposit_fee` tokens are charged
+#[cfg(feature = "contracts-v2")]
+fn token_deposit_transfer(log: &Log, deposit_fee: Option<U256>) -> Result<Transfer> {
+    let home = home_token::HomeTokenBridge::default();
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let transfer_log = home.events().transfer_to_foreign().parse_log(raw_log)?;
+    Ok(Transfer {
+        direction: TransferDirection::Deposit,
+        block_number: log.block_number
+            .ok_or_else(|| "`log` must be mined and contain `block_number`")?
+            .low_u64(),
+        transaction_hash: log.transaction_hash
+            .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?,
+        block_timestamp: None,
+        recipient: transfer_log.recipient,
+        value: transfer_log.value,
+        relay_transaction_hash: None,
+        relayed_at: None,
+        fee: Some(deposit_fee.unwrap_or_else(U256::zero)),
+    })
+}
+
+/// returns the relay of the deposits `request` to `ForeignBridge`.
+/// journaled as relay of the events up to `block` if it is set
+pub fn deposit_transaction<T: Transport + Clone>(
+    app: &App<T>,
+    request: TransactionRequest,
+    block: Option<u64>,
+) -> RelayTransaction<T> {
+    let relay = relay_transaction(
+        app.connections.foreign.clone(),
+        app.timer.clone(),
+        &app.config.foreign,
+        request,
+        app.config
+            .foreign
+            .access_list_mode(&app.config.txs.deposit_relay),
+    ).via_signer(remote_signer(&app.config), Chain::Foreign)
+        .via_keystore(app.keystore.clone(), Chain::Foreign)
+        .with_dynamic_fees(app.config.txs.deposit_relay.dynamic_fees)
+        .with_nonces(app.nonces.get(Chain::Foreign));
+    match block {
+        Some(block) => relay.with_journal(app.journal.clone(), Chain::Foreign, block),
+        None => relay,
+    }
+}
+
+/// relay of deposits that is parked for retries if its simulation reverts
+#[derive(Clone)]
+struct DepositRelayRequest {
+    /// transaction of the first deposit the relay confirms
+    origin: Option<H256>,
+    /// value of the deposits the relay confirms
+    value: U256,
+    request: TransactionRequest,
+    /// relays of the deposits of a batch one by one. empty unless the relay is a batch.
+    /// a batch that reverts is relayed deposit by deposit instead
+    /// so that a single deposit that reverts doesn't fail the others
+    singles: Vec<DepositRelayRequest>,
+}
+
+/// parks `relay` whose simulation reverted for retries
+fn park_reverted<T: Transport>(app: &App<T>, relay: &DepositRelayRequest) {
+    if let Some(origin) = relay.origin {
+        app.parked.park(ParkedRelay::reverted(
+            Chain::Foreign,
+            origin,
+            relay.value,
+            &relay.request,
+            status::now(),
+            app.config.reverted_relays.retry_interval.as_secs(),
+        ));
+    }
+}
+
+/// logs, counts and records the relays `relayed` of the deposits `origins`
+/// with their entries of the relay history
+fn complete_relays<T: Transport>(
+    app: &App<T>,
+    origins: &[Option<H256>],
+    relayed: &[Option<H256>],
+    mut transfers: Vec<Transfer>,
+) -> Result<()> {
+    for (origin, hash) in origins.iter().zip(relayed) {
+        if let Some(origin) = *origin {
+            log_relay(TransferDirection::Deposit, origin, *hash);
+        }
+    }
+    let skipped = relayed.iter().filter(|hash| hash.is_none()).count();
+    if skipped > 0 {
+        warn!(
+            "{} deposits were not relayed because their simulation reverted. parked them for retries",
+            skipped
+        );
+    }
+    metrics::record_relays(|relays| relays.deposits += (relayed.len() - skipped) as u64);
+    let relayed_at = clock::timestamp("foreign");
+    for (transfer, hash) in transfers.iter_mut().zip(relayed) {
+        transfer.relay_transaction_hash = *hash;
+        transfer.relayed_at = Some(relayed_at);
+    }
+    let events = transfers.iter().map(TransferEvent::relayed).collect::<Vec<_>>();
+    sinks::record(app, &transfers, &events)?;
+    status::set_queue("deposit_relay", 0);
+    info!("deposit relay completed");
+    Ok(())
+}
+
 /// State of deposits relay.
 enum DepositRelayState<T: Transport> {
     /// Deposit relay is waiting for logs.
     Wait,
+    /// Fetching the gas price of the relays of the deposits in `item`.
+    FetchGasPrice {
+        future: GasPrice<T>,
+        item: Option<LogStreamItem>,
+    },
     /// Relaying deposits in progress.
     RelayDeposits {
-        future: JoinAll<Vec<Timeout<ApiCall<H256, T::Out>>>>,
+        /// relays and entries of the relay history for the deposits with the timestamps
+        /// of their blocks. no entries if the history is disabled
+        future: Join<JoinAll<Vec<RelayTransaction<T>>>, BlockTimestamps<T>>,
+        /// number of deposits each relay confirms
+        batch_sizes: Vec<usize>,
+        /// relays in the order of `future`
+        requests: Vec<DepositRelayRequest>,
+        /// transactions of the deposits in the order of the relays
+        origins: Vec<Option<H256>>,
+        block: u64,
+    },
+    /// Relaying the deposits of the batches that reverted one by one.
+    RelaySingles {
+        future: JoinAll<Vec<RelayTransaction<T>>>,
+        /// relays in the order of `future` with the index of their deposit
+        singles: Vec<(usize, DepositRelayRequest)>,
+        /// relays of the deposits so far
+        relayed: Vec<Option<H256>>,
+        /// entries of the relay history with the timestamps of their blocks
+        transfers: Vec<Transfer>,
+        origins: Vec<Option<H256>>,
         block: u64,
     },
     /// All deposits till given block has been relayed.
@@ -59,10 +412,48 @@ pub fn create_deposit_relay<T: Transport + Clone>(
         request_timeout: app.config.home.request_timeout,
         poll_interval: app.config.home.poll_interval,
         confirmations: app.config.home.required_confirmations,
-        filter: deposits_filter(&app.home_bridge, init.home_contract_address),
+        strategy: app.config.home.confirmation_strategy,
+        filter: match app.config.tokens() {
+            #[cfg(feature = "contracts-v2")]
+            Some(_) => token_deposits_filter(init.home_contract_address),
+            _ => deposits_filter(&app.home_bridge, init.home_contract_address),
+        },
     };
+    app.checkpoints
+        .deposit_relay
+        .seed(init.checked_deposit_relay, init.checked_deposit_relay_hash);
+    app.value_limits.deposits.seed(init.deposit_value_window);
     DepositRelay {
-        logs: api::log_stream(app.connections.home.clone(), app.timer.clone(), logs_init),
+        logs: limited(
+            deferred(
+                pausable(
+                    api::log_stream(app.connections.home.clone(), app.timer.clone(), logs_init)
+                        .following(&app.heads.home)
+                        .rewinding(&app.checkpoints.deposit_relay)
+                        .sharded(&app.leases, ShardedStream::DepositRelay)
+                        .retrying(&app.config.home.retry),
+                    app.config.pause(Direction::Deposits),
+                    &app.timer,
+                    app.config.home.poll_interval,
+                ),
+                "foreign",
+                app.config.foreign.maintenance_windows.clone(),
+                app.config.clock.skew_tolerance,
+                &app.timer,
+                app.config.foreign.poll_interval,
+            ),
+            Direction::Deposits,
+            app.value_limits.deposits.clone(),
+            match app.config.tokens() {
+                #[cfg(feature = "contracts-v2")]
+                Some(_) => token_deposit_value,
+                _ => deposit_value,
+            },
+            app.leases.clone(),
+            ShardedStream::DepositRelay,
+            &app.timer,
+            app.config.foreign.poll_interval,
+        ),
         foreign_contract: init.foreign_contract_address,
         state: DepositRelayState::Wait,
         app,
@@ -71,12 +462,22 @@ pub fn create_deposit_relay<T: Transport + Clone>(
 
 pub struct DepositRelay<T: Transport> {
     app: Arc<App<T>>,
-    logs: LogStream<T>,
+    logs: Limited<Deferred<Pausable<LogStream<T>>>>,
     state: DepositRelayState<T>,
     foreign_contract: Address,
 }
 
-impl<T: Transport> Stream for DepositRelay<T> {
+impl<T: Transport> DepositRelay<T> {
+    /// whether no relays of deposits are in progress. checked before the bridge shuts down
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            DepositRelayState::Wait | DepositRelayState::Yield(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for DepositRelay<T> {
     type Item = u64;
     type Error = Error;
 
@@ -84,49 +485,238 @@ impl<T: Transport> Stream for DepositRelay<T> {
         loop {
             let next_state = match self.state {
                 DepositRelayState::Wait => {
-                    let item = try_stream!(self.logs.poll());
+                    let mut item = try_stream!(self.logs.poll());
+                    item.logs
+                        .retain(|log| self.app.leases.handles(ShardedStream::DepositRelay, log));
                     info!("got {} new deposits to relay", item.logs.len());
-                    let deposits = item.logs
-                        .into_iter()
-                        .map(|log| {
-                            deposit_relay_payload(
-                                &self.app.home_bridge,
-                                &self.app.foreign_bridge,
-                                log,
-                            )
+                    for hash in item.logs.iter().filter_map(|log| log.transaction_hash) {
+                        RelayEvent::new(TransferDirection::Deposit, RelayStage::Detected, hash)
+                            .log();
+                    }
+                    let future = if item.logs.is_empty() {
+                        GasPrice::fixed(None)
+                    } else {
+                        gas_price(
+                            &self.app.connections.foreign,
+                            &self.app.timer,
+                            &self.app.config.foreign,
+                            &self.app.gas_price_oracles.foreign,
+                        )
+                    };
+                    DepositRelayState::FetchGasPrice {
+                        future,
+                        item: Some(item),
+                    }
+                }
+                DepositRelayState::FetchGasPrice {
+                    ref mut future,
+                    ref mut item,
+                } => {
+                    let gas_price = try_ready!(future.poll())
+                        .unwrap_or_else(|| self.app.config.txs.deposit_relay.gas_price.into());
+                    let item = item.take().expect("item is only taken once; qed");
+                    let block = item.to;
+                    let deposit_fee = self.app
+                        .config
+                        .token_fee
+                        .as_ref()
+                        .map(|token_fee| token_fee.deposit_fee);
+                    let tokens = self.app.config.tokens();
+                    let transfers = if self.app.config.relay_history.is_some() {
+                        item.logs
+                            .iter()
+                            .map(|log| match tokens {
+                                #[cfg(feature = "contracts-v2")]
+                                Some(_) => token_deposit_transfer(log, deposit_fee),
+                                _ => deposit_transfer(&self.app.home_bridge, log, deposit_fee),
+                            })
+                            .collect::<Result<Vec<_>>>()?
+                    } else {
+                        Vec::new()
+                    };
+                    let count = item.logs.len();
+                    let origins = item.logs
+                        .iter()
+                        .map(|log| log.transaction_hash)
+                        .collect::<Vec<_>>();
+                    let values = item.logs
+                        .iter()
+                        .map(|log| match tokens {
+                            #[cfg(feature = "contracts-v2")]
+                            Some(_) => token_deposit_value(log),
+                            _ => deposit_value(log),
                         })
-                        .collect::<Result<Vec<_>>>()?
+                        .collect::<Result<Vec<_>>>()?;
+                    let payloads = match (tokens, self.app.config.txs.max_batch_size) {
+                        (None, Some(max_batch_size)) => item.logs
+                            .iter()
+                            .map(|log| parse_deposit(&self.app.home_bridge, log))
+                            .collect::<Result<Vec<_>>>()?
+                            .chunks(max_batch_size)
+                            .map(|batch| {
+                                let foreign = &self.app.foreign_bridge;
+                                let payload = deposit_batch_payload(foreign, batch, deposit_fee);
+                                let singles = deposit_single_payloads(foreign, batch, deposit_fee);
+                                (payload, batch.len(), singles)
+                            })
+                            .collect::<Vec<_>>(),
+                        (tokens, _) => item.logs
+                            .into_iter()
+                            .map(|log| match tokens {
+                                #[cfg(feature = "contracts-v2")]
+                                Some(ref tokens) => {
+                                    token_deposit_relay_payload(tokens, log, deposit_fee)
+                                }
+                                _ => deposit_relay_payload(
+                                    &self.app.home_bridge,
+                                    &self.app.foreign_bridge,
+                                    log,
+                                    deposit_fee,
+                                ),
+                            })
+                            .map(|payload| payload.map(|payload| (payload, 1, Vec::new())))
+                            .collect::<Result<Vec<_>>>()?,
+                    };
+                    let batch_sizes = payloads
+                        .iter()
+                        .map(|&(_, size, _)| size)
+                        .collect::<Vec<_>>();
+                    let request = |payload: Bytes, size: usize| TransactionRequest {
+                        from: self.app.config.foreign.account,
+                        to: Some(self.foreign_contract.clone()),
+                        gas: Some(
+                            U256::from(self.app.config.txs.deposit_relay.gas)
+                                * U256::from(size as u64),
+                        ),
+                        gas_price: Some(gas_price),
+                        value: None,
+                        data: Some(payload),
+                        nonce: None,
+                        condition: None,
+                    };
+                    let mut offset = 0;
+                    let requests = payloads
                         .into_iter()
-                        .map(|payload| TransactionRequest {
-                            from: self.app.config.foreign.account,
-                            to: Some(self.foreign_contract.clone()),
-                            gas: Some(self.app.config.txs.deposit_relay.gas.into()),
-                            gas_price: Some(self.app.config.txs.deposit_relay.gas_price.into()),
-                            value: None,
-                            data: Some(payload),
-                            nonce: None,
-                            condition: None,
+                        .map(|(payload, size, singles)| {
+                            let first = offset;
+                            offset += size;
+                            DepositRelayRequest {
+                                origin: origins[first],
+                                value: values[first..first + size]
+                                    .iter()
+                                    .fold(U256::zero(), |sum, value| sum + *value),
+                                request: request(payload, size),
+                                singles: singles
+                                    .into_iter()
+                                    .enumerate()
+                                    .map(|(index, payload)| DepositRelayRequest {
+                                        origin: origins[first + index],
+                                        value: values[first + index],
+                                        request: request(payload, 1),
+                                        singles: Vec::new(),
+                                    })
+                                    .collect(),
+                            }
                         })
-                        .map(|request| {
-                            self.app.timer.timeout(
-                                api::send_transaction(&self.app.connections.foreign, request),
-                                self.app.config.foreign.request_timeout,
-                            )
+                        .collect::<Vec<_>>();
+                    let deposits = requests
+                        .iter()
+                        .map(|relay| {
+                            deposit_transaction(&self.app, relay.request.clone(), Some(block))
                         })
                         .collect::<Vec<_>>();
 
-                    info!("relaying {} deposits", deposits.len());
+                    info!(
+                        "relaying {} deposits in {} transactions",
+                        count,
+                        deposits.len()
+                    );
+                    status::set_queue("deposit_relay", count);
+                    let transfers = history::with_block_timestamps(
+                        &self.app.connections.home,
+                        &self.app.timer,
+                        self.app.config.home.request_timeout,
+                        transfers,
+                    );
                     DepositRelayState::RelayDeposits {
-                        future: join_all(deposits),
-                        block: item.to,
+                        future: join_all(deposits).join(transfers),
+                        batch_sizes,
+                        requests,
+                        origins,
+                        block,
                     }
                 }
                 DepositRelayState::RelayDeposits {
                     ref mut future,
+                    ref batch_sizes,
+                    ref requests,
+                    ref mut origins,
+                    block,
+                } => {
+                    let (relayed, transfers) = try_ready!(future.poll());
+                    let mut singles = Vec::new();
+                    let mut offset = 0;
+                    for ((relay, hash), size) in requests.iter().zip(&relayed).zip(batch_sizes) {
+                        if hash.is_none() {
+                            if relay.singles.is_empty() {
+                                park_reverted(&self.app, relay);
+                            } else {
+                                let deposits = relay.singles.iter().cloned().enumerate();
+                                singles.extend(
+                                    deposits.map(|(index, single)| (offset + index, single)),
+                                );
+                            }
+                        }
+                        offset += size;
+                    }
+                    // the hash of a batch is the relay of each of its deposits
+                    let relayed = relayed
+                        .into_iter()
+                        .zip(batch_sizes)
+                        .flat_map(|(hash, &size)| iter::repeat(hash).take(size))
+                        .collect::<Vec<_>>();
+                    if singles.is_empty() {
+                        complete_relays(&self.app, origins, &relayed, transfers)?;
+                        DepositRelayState::Yield(Some(block))
+                    } else {
+                        warn!(
+                            "relaying {} deposits of reverted batches one by one",
+                            singles.len()
+                        );
+                        let app = &self.app;
+                        let future = singles
+                            .iter()
+                            .map(|&(_, ref single)| {
+                                deposit_transaction(app, single.request.clone(), Some(block))
+                            })
+                            .collect::<Vec<_>>();
+                        DepositRelayState::RelaySingles {
+                            future: join_all(future),
+                            singles,
+                            relayed,
+                            transfers,
+                            origins: mem::replace(origins, Vec::new()),
+                            block,
+                        }
+                    }
+                }
+                DepositRelayState::RelaySingles {
+                    ref mut future,
+                    ref singles,
+                    ref mut relayed,
+                    ref mut transfers,
+                    ref origins,
                     block,
                 } => {
-                    let _ = try_ready!(future.poll());
-                    info!("deposit relay completed");
+                    let hashes = try_ready!(future.poll());
+                    for (&(index, ref single), hash) in singles.iter().zip(hashes) {
+                        relayed[index] = hash;
+                        if hash.is_none() {
+                            park_reverted(&self.app, single);
+                        }
+                    }
+                    let transfers = mem::replace(transfers, Vec::new());
+                    complete_relays(&self.app, origins, relayed, transfers)?;
                     DepositRelayState::Yield(Some(block))
                 }
                 DepositRelayState::Yield(ref mut block) => match block.take() {
@@ -142,9 +732,11 @@ impl<T: Transport> Stream for DepositRelay<T> {
 #[cfg(test)]
 mod tests {
     use rustc_hex::FromHex;
-    use web3::types::{Bytes, Log};
+    use web3::types::{Address, Bytes, Log, U256};
     use contracts::{foreign, home};
-    use super::deposit_relay_payload;
+    use history::{Transfer, TransferDirection};
+    use super::{deposit_batch_payload, deposit_payload, deposit_relay_payload,
+                deposit_single_payloads, deposit_transfer, parse_deposit, Deposit};
 
     #[test]
     fn test_deposit_relay_payload() {
@@ -163,8 +755,164 @@ mod tests {
             ..Default::default()
         };
 
-        let payload = deposit_relay_payload(&home, &foreign, log).unwrap();
+        let payload = deposit_relay_payload(&home, &foreign, log.clone(), None).unwrap();
         let expected: Bytes = "26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".from_hex().unwrap().into();
         assert_eq!(expected, payload);
+
+        let payload =
+            deposit_relay_payload(&home, &foreign, log.clone(), Some(0x10.into())).unwrap();
+        assert!(payload.0 != expected.0);
+        assert_eq!(4 + 4 * 32, payload.0.len());
+
+        // a pending log fails the relay instead of panicking
+        let pending = Log {
+            transaction_hash: None,
+            ..log
+        };
+        assert!(deposit_relay_payload(&home, &foreign, pending.clone(), None).is_err());
+        assert!(parse_deposit(&home, &pending).is_err());
+        assert!(deposit_transfer(&home, &pending, None).is_err());
+    }
+
+    #[test]
+    fn test_deposit_batch_payload() {
+        let home = home::HomeBridge::default();
+        let foreign = foreign::ForeignBridge::default();
+
+        let data = "000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0".from_hex().unwrap();
+        let log = Log {
+            data: data.into(),
+            topics: vec![
+                "e1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c".into(),
+            ],
+            transaction_hash: Some(
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+            ),
+            ..Default::default()
+        };
+        let deposit = parse_deposit(&home, &log).unwrap();
+        assert_eq!(
+            Deposit {
+                recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+                value: 0xf0.into(),
+                transaction_hash: log.transaction_hash.unwrap(),
+            },
+            deposit
+        );
+
+        // a single deposit is confirmed on its own
+        assert_eq!(
+            deposit_payload(
+                &foreign,
+                deposit.recipient,
+                deposit.value,
+                deposit.transaction_hash,
+                None
+            ),
+            deposit_batch_payload(&foreign, &[deposit.clone()], None)
+        );
+
+        assert!(deposit_single_payloads(&foreign, &[deposit.clone()], None).is_empty());
+        let singles = deposit_single_payloads(&foreign, &[deposit.clone(), deposit.clone()], None);
+        assert_eq!(vec![deposit_batch_payload(&foreign, &[deposit.clone()], None); 2], singles);
+
+        let batch = deposit_batch_payload(&foreign, &[deposit.clone(), deposit], None);
+        // `depositBatch(address[],uint256[],bytes32[],uint256)`
+        assert_eq!(vec![0xf0, 0x9f, 0xaf, 0xc0], batch.0[..4].to_vec());
+        // offsets of the arrays, the fee and the arrays of 2 deposits
+        assert_eq!(4 + 4 * 32 + 3 * 3 * 32, batch.0.len());
+    }
+
+    #[test]
+    fn test_deposit_transfer() {
+        let home = home::HomeBridge::default();
+
+        let data = "000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0".from_hex().unwrap();
+        let log = Log {
+            data: data.into(),
+            topics: vec![
+                "e1fffcc4923d04b559f4d29a8bfc6cda04eb5b0d3c460751c2402c5c5cc9109c".into(),
+            ],
+            transaction_hash: Some(
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+            ),
+            block_number: Some(0x1011.into()),
+            ..Default::default()
+        };
+
+        let expected = Transfer {
+            direction: TransferDirection::Deposit,
+            block_number: 0x1011,
+            block_timestamp: None,
+            transaction_hash: "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+                .into(),
+            recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+            value: 0xf0.into(),
+            relay_transaction_hash: None,
+            relayed_at: None,
+            fee: Some(0.into()),
+        };
+        assert_eq!(expected, deposit_transfer(&home, &log, None).unwrap());
+
+        let expected = Transfer {
+            fee: Some(0x10.into()),
+            ..expected
+        };
+        assert_eq!(
+            expected,
+            deposit_transfer(&home, &log, Some(0x10.into())).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "contracts-v2")]
+    fn test_token_deposit_relay_payload() {
+        use config::Tokens;
+        use super::{token_deposit_relay_payload, token_deposit_transfer};
+
+        let tokens = Tokens {
+            home: "0x0000000000000000000000000000000000000010".into(),
+            foreign: "0x0000000000000000000000000000000000000020".into(),
+        };
+
+        let data = "0000000000000000000000000000000000000000000000000000000000000010000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0".from_hex().unwrap();
+        let log = Log {
+            data: data.into(),
+            topics: vec![
+                "608806b04c9ac7ff8717caa6a20d4e31ccdf4818ec9479194c9cfda824cf8183".into(),
+            ],
+            transaction_hash: Some(
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+            ),
+            block_number: Some(0x1011.into()),
+            ..Default::default()
+        };
+
+        let payload = token_deposit_relay_payload(&tokens, log.clone(), None).unwrap();
+        let expected: Bytes = "c98444f70000000000000000000000000000000000000000000000000000000000000020000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".from_hex().unwrap().into();
+        assert_eq!(expected, payload);
+
+        // `depositWithFee(address,address,uint256,bytes32,uint256)`
+        let payload =
+            token_deposit_relay_payload(&tokens, log.clone(), Some(0x10.into())).unwrap();
+        assert!(payload.0[..4] != expected.0[..4]);
+        assert_eq!(4 + 5 * 32, payload.0.len());
+
+        let transfer = token_deposit_transfer(&log, None).unwrap();
+        assert_eq!(TransferDirection::Deposit, transfer.direction);
+        assert_eq!(
+            "aff3454fce5edbc8cca8697c15331677e6ebcccc".parse::<Address>().unwrap(),
+            transfer.recipient
+        );
+        assert_eq!(U256::from(0xf0), transfer.value);
+        assert_eq!(Some(U256::zero()), transfer.fee);
+        let transfer = token_deposit_transfer(&log, Some(0x10.into())).unwrap();
+        assert_eq!(Some(U256::from(0x10)), transfer.fee);
+
+        let other_token = Tokens {
+            home: "0x0000000000000000000000000000000000000030".into(),
+            ..tokens
+        };
+        assert!(token_deposit_relay_payload(&other_token, log, None).is_err());
     }
 }
@@ -0,0 +1,237 @@
+/// claiming of the relay fees this authority earns in tokens (see `token_fee`).
+/// `ForeignBridge.depositWithFee` credits every authority that confirms a deposit
+/// with a share of its fee. the unclaimed fees of `foreign.account` are checked every
+/// `token_fee.claim_interval` and claimed via `ForeignBridge.claimRelayFees` once they
+/// reach `token_fee.min_claim`. with `token_fee.transfer_home` the claimed tokens are then
+/// transferred to `home.account` via `ForeignBridge.transferHomeViaRelay` which swaps them
+/// for ether on `home` to reimburse the gas this authority spends on relaying.
+/// token bridges work the same via `ForeignTokenBridge`, except that with
+/// `token_fee.transfer_home` the fees are transferred to `home.account` in `tokens.home`
+/// via `ForeignTokenBridge.transferRelayFeesHome` instead of being claimed on `foreign`.
+/// `ForeignTokenBridge.relayFees` and `ForeignTokenBridge.claimRelayFees` share the ABI
+/// of `ForeignBridge`.
+
+use std::sync::Arc;
+use futures::{Async, Future, Poll, Stream};
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::{Address, Bytes, TransactionRequest, U256};
+use api::{self, ApiCall};
+use app::App;
+use config::TokenFeeConfig;
+#[cfg(feature = "contracts-v2")]
+use contracts::foreign_token::ForeignTokenBridge;
+use database::Database;
+use error::Error;
+use signer::{remote_signer, Chain};
+use status;
+use transaction::{relay_transaction, RelayTransaction};
+
+/// returns whether `unclaimed` relay fees are worth a claim
+fn should_claim(unclaimed: U256, min_claim: U256) -> bool {
+    !unclaimed.is_zero() && unclaimed >= min_claim
+}
+
+/// returns the payload of `ForeignTokenBridge.transferRelayFeesHome`
+/// which transfers the relay fees to `recipient` on `home` at `home_gas_price`
+#[cfg(feature = "contracts-v2")]
+fn transfer_relay_fees_home_payload(recipient: Address, home_gas_price: u64) -> Vec<u8> {
+    ForeignTokenBridge::default()
+        .functions()
+        .transfer_relay_fees_home()
+        .input(recipient, home_gas_price)
+}
+
+/// state of the fee claim
+enum FeeClaimState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the unclaimed relay fees of `foreign.account`
+    FetchFees(Timeout<ApiCall<Bytes, T::Out>>),
+    /// claiming `tokens`
+    Claim {
+        future: RelayTransaction<T>,
+        tokens: U256,
+    },
+    /// transferring the claimed `tokens` to `home.account`
+    TransferHome {
+        future: RelayTransaction<T>,
+        tokens: U256,
+    },
+}
+
+pub fn create_fee_claim<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+    token_fee: TokenFeeConfig,
+) -> FeeClaim<T> {
+    FeeClaim {
+        interval: app.timer.interval(token_fee.claim_interval),
+        foreign_contract: init.foreign_contract_address,
+        token_fee,
+        state: FeeClaimState::Wait,
+        app,
+    }
+}
+
+/// stream that checks and claims the relay fees of `foreign.account` on every tick of `interval`
+pub struct FeeClaim<T: Transport> {
+    app: Arc<App<T>>,
+    token_fee: TokenFeeConfig,
+    interval: Interval,
+    foreign_contract: Address,
+    state: FeeClaimState<T>,
+}
+
+/// returns the relay of a transaction from `foreign.account` to `ForeignBridge` with `payload`
+fn relay<T: Transport + Clone>(
+    app: &App<T>,
+    foreign_contract: Address,
+    payload: Vec<u8>,
+) -> RelayTransaction<T> {
+    let request = TransactionRequest {
+        from: app.config.foreign.account,
+        to: Some(foreign_contract),
+        gas: Some(app.config.txs.fee_claim.gas.into()),
+        gas_price: Some(app.config.txs.fee_claim.gas_price.into()),
+        value: None,
+        data: Some(payload.into()),
+        nonce: None,
+        condition: None,
+    };
+    relay_transaction(
+        app.connections.foreign.clone(),
+        app.timer.clone(),
+        &app.config.foreign,
+        request,
+        app.config.foreign.access_list_mode(&app.config.txs.fee_claim),
+    ).via_signer(remote_signer(&app.config), Chain::Foreign)
+        .via_keystore(app.keystore.clone(), Chain::Foreign)
+        .with_dynamic_fees(app.config.txs.fee_claim.dynamic_fees)
+        .with_nonces(app.nonces.get(Chain::Foreign))
+}
+
+impl<T: Transport + Clone> Stream for FeeClaim<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                FeeClaimState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    let account = self.app.config.foreign.account;
+                    let payload = self.app.foreign_bridge.functions().relay_fees().input(account);
+                    FeeClaimState::FetchFees(self.app.timer.timeout(
+                        api::call(
+                            &self.app.connections.foreign,
+                            self.foreign_contract,
+                            payload.into(),
+                        ),
+                        self.app.config.foreign.request_timeout,
+                    ))
+                }
+                FeeClaimState::FetchFees(ref mut future) => {
+                    let output = try_ready!(future.poll());
+                    let tokens: U256 = self.app
+                        .foreign_bridge
+                        .functions()
+                        .relay_fees()
+                        .output(output.0.as_slice())?;
+                    status::record(|status| status.unclaimed_relay_fees = Some(tokens));
+                    if !should_claim(tokens, self.token_fee.min_claim) {
+                        self.state = FeeClaimState::Wait;
+                        return Ok(Async::Ready(Some(())));
+                    }
+                    match self.app.config.tokens() {
+                        #[cfg(feature = "contracts-v2")]
+                        Some(_) if self.token_fee.transfer_home => {
+                            info!("transferring {} tokens of relay fees to home", tokens);
+                            let payload = transfer_relay_fees_home_payload(
+                                self.app.config.home.account,
+                                self.app.config.txs.withdraw_relay.gas_price,
+                            );
+                            FeeClaimState::TransferHome {
+                                future: relay(&self.app, self.foreign_contract, payload),
+                                tokens,
+                            }
+                        }
+                        _ => {
+                            info!("claiming {} tokens of relay fees", tokens);
+                            let payload =
+                                self.app.foreign_bridge.functions().claim_relay_fees().input();
+                            FeeClaimState::Claim {
+                                future: relay(&self.app, self.foreign_contract, payload),
+                                tokens,
+                            }
+                        }
+                    }
+                }
+                FeeClaimState::Claim {
+                    ref mut future,
+                    tokens,
+                } => {
+                    let hash = match try_ready!(future.poll()) {
+                        Some(hash) => hash,
+                        None => {
+                            warn!("claim of relay fees was not sent because its simulation reverted");
+                            self.state = FeeClaimState::Wait;
+                            return Ok(Async::Ready(Some(())));
+                        }
+                    };
+                    info!("claimed {} tokens of relay fees in {:?}", tokens, hash);
+                    status::record(|status| status.unclaimed_relay_fees = Some(U256::zero()));
+                    if !self.token_fee.transfer_home {
+                        self.state = FeeClaimState::Wait;
+                        return Ok(Async::Ready(Some(())));
+                    }
+                    // simulated against the pending block which already contains the claim
+                    let payload = self.app
+                        .foreign_bridge
+                        .functions()
+                        .transfer_home_via_relay()
+                        .input(
+                            self.app.config.home.account,
+                            tokens,
+                            self.app.config.txs.withdraw_relay.gas_price,
+                        );
+                    FeeClaimState::TransferHome {
+                        future: relay(&self.app, self.foreign_contract, payload),
+                        tokens,
+                    }
+                }
+                FeeClaimState::TransferHome {
+                    ref mut future,
+                    tokens,
+                } => {
+                    match try_ready!(future.poll()) {
+                        Some(hash) => info!(
+                            "transferring {} tokens of relay fees to home in {:?}",
+                            tokens, hash
+                        ),
+                        None => warn!(
+                            "transfer of {} tokens of relay fees to home was not sent because its simulation reverted. the tokens stay on foreign",
+                            tokens
+                        ),
+                    }
+                    self.state = FeeClaimState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::should_claim;
+
+    #[test]
+    fn test_should_claim() {
+        assert!(should_claim(10.into(), 10.into()));
+        assert!(should_claim(10.into(), 0.into()));
+        assert!(!should_claim(9.into(), 10.into()));
+        assert!(!should_claim(0.into(), 0.into()));
+    }
+}
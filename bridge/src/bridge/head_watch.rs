@@ -0,0 +1,97 @@
+/// watching the latest block of a chain for the log streams following it.
+/// polls the latest block when the next one is due according to the block time
+/// observed so far and announces new blocks to the `ChainHead` of the chain.
+/// polls back off to `max_poll_interval` while no log stream waits for a new block
+/// or a subscription (see `subscribe_new_heads`) announces them.
+
+use std::time::Duration;
+use futures::{Async, Future, Poll, Stream};
+use tokio_timer::{Sleep, Timeout, Timer};
+use web3::Transport;
+use web3::types::U256;
+use api::{self, ApiCall};
+use config::Node;
+use error::Error;
+use heads::{self, ChainHead};
+
+/// state of the head watch
+enum HeadWatchState<T: Transport> {
+    /// waiting until the next poll is due
+    Wait(Sleep),
+    /// fetching the latest block
+    FetchBlockNumber(Timeout<ApiCall<U256, T::Out>>),
+}
+
+pub fn create_head_watch<T: Transport>(
+    chain: &'static str,
+    transport: T,
+    timer: Timer,
+    node: &Node,
+    head: ChainHead,
+) -> HeadWatch<T> {
+    head.watch();
+    if node.subscribe_new_heads {
+        heads::subscribe(chain, node, head.clone());
+    }
+    HeadWatch {
+        state: HeadWatchState::FetchBlockNumber(
+            timer.timeout(api::block_number(&transport), node.request_timeout),
+        ),
+        transport,
+        timer,
+        head,
+        min_poll_interval: node.poll_interval,
+        max_poll_interval: node.max_poll_interval,
+        request_timeout: node.request_timeout,
+    }
+}
+
+/// stream that announces the latest block of a chain to `head`
+pub struct HeadWatch<T: Transport> {
+    transport: T,
+    timer: Timer,
+    head: ChainHead,
+    min_poll_interval: Duration,
+    max_poll_interval: Duration,
+    request_timeout: Duration,
+    state: HeadWatchState<T>,
+}
+
+impl<T: Transport> HeadWatch<T> {
+    fn schedule(&self) -> HeadWatchState<T> {
+        HeadWatchState::Wait(self.timer.sleep(
+            self.head
+                .next_poll(self.min_poll_interval, self.max_poll_interval),
+        ))
+    }
+}
+
+impl<T: Transport> Stream for HeadWatch<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                HeadWatchState::Wait(ref mut sleep) => {
+                    if self.head.needs_reschedule() {
+                        self.schedule()
+                    } else {
+                        try_ready!(sleep.poll());
+                        HeadWatchState::FetchBlockNumber(self.timer.timeout(
+                            api::block_number(&self.transport),
+                            self.request_timeout,
+                        ))
+                    }
+                }
+                HeadWatchState::FetchBlockNumber(ref mut future) => {
+                    let block = try_ready!(future.poll()).low_u64();
+                    self.head.announce(block);
+                    self.state = self.schedule();
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
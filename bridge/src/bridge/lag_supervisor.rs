@@ -0,0 +1,177 @@
+/// supervision of the progress of the bridge components.
+/// compares the blocks up to which the components have processed events with the
+/// latest blocks of both chains and fails with `ErrorKind::MaxLagExceeded` once a
+/// component lags behind by more than `max_lag.blocks` for longer than `max_lag.duration`.
+/// the components retry failed requests on their own so a lag that persists that long
+/// points to a wedged process that is better restarted.
+
+use std::sync::Arc;
+use std::time::Instant;
+use futures::{Async, Future, Poll, Stream};
+use futures::future::Join;
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::U256;
+use api::{self, ApiCall};
+use app::App;
+use config::MaxLagConfig;
+use database::Database;
+use error::{Error, ErrorKind};
+use status::{self, Status};
+
+type Fetch<T> = Timeout<ApiCall<U256, <T as Transport>::Out>>;
+
+/// returns the component that lags behind by the most blocks if that is more than `max_blocks`.
+/// the withdraw relay is only considered if it runs
+fn lagging_component(
+    status: &Status,
+    executes_withdraws: bool,
+    max_blocks: u64,
+) -> Option<(&'static str, u64)> {
+    let withdraw_relay_lag = if executes_withdraws {
+        status.withdraw_relay_lag()
+    } else {
+        None
+    };
+    [
+        ("deposit relay", status.deposit_relay_lag()),
+        ("withdraw confirm", status.withdraw_confirm_lag()),
+        ("withdraw relay", withdraw_relay_lag),
+    ].iter()
+        .filter_map(|&(component, lag)| lag.map(|lag| (component, lag)))
+        .filter(|&(_, lag)| lag > max_blocks)
+        .max_by_key(|&(_, lag)| lag)
+}
+
+/// state of the lag supervisor
+enum LagSupervisorState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the latest blocks of both chains
+    Fetch(Join<Fetch<T>, Fetch<T>>),
+}
+
+pub fn create_lag_supervisor<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+    max_lag: MaxLagConfig,
+) -> LagSupervisor<T> {
+    status::record(|status| {
+        status.checked_deposit_relay = Some(init.checked_deposit_relay);
+        status.checked_withdraw_confirm = Some(init.checked_withdraw_confirm);
+        status.checked_withdraw_relay = Some(init.checked_withdraw_relay);
+    });
+    LagSupervisor {
+        interval: app.timer.interval(max_lag.check_interval),
+        app,
+        max_lag,
+        lagging_since: None,
+        state: LagSupervisorState::Wait,
+    }
+}
+
+/// stream that checks the lag of the components on every tick of `interval`
+pub struct LagSupervisor<T: Transport> {
+    app: Arc<App<T>>,
+    max_lag: MaxLagConfig,
+    interval: Interval,
+    /// when a component was first seen lagging behind by more than `max_lag.blocks`.
+    /// `None` while no component does
+    lagging_since: Option<Instant>,
+    state: LagSupervisorState<T>,
+}
+
+impl<T: Transport> Stream for LagSupervisor<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                LagSupervisorState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    let app = &self.app;
+                    LagSupervisorState::Fetch(
+                        app.timer
+                            .timeout(
+                                api::block_number(&app.connections.home),
+                                app.config.home.request_timeout,
+                            )
+                            .join(app.timer.timeout(
+                                api::block_number(&app.connections.foreign),
+                                app.config.foreign.request_timeout,
+                            )),
+                    )
+                }
+                LagSupervisorState::Fetch(ref mut future) => {
+                    let (home_block, foreign_block) = try_ready!(future.poll());
+                    let mut status = status::snapshot();
+                    status.home_block = Some(home_block.low_u64());
+                    status.foreign_block = Some(foreign_block.low_u64());
+                    let lagging = lagging_component(
+                        &status,
+                        self.app.config.role.executes_withdraws(),
+                        self.max_lag.blocks,
+                    );
+                    match lagging {
+                        Some((component, lag)) => {
+                            let since = *self.lagging_since.get_or_insert_with(Instant::now);
+                            let elapsed = since.elapsed();
+                            if elapsed >= self.max_lag.duration {
+                                error!(
+                                    "{} lags {} blocks behind for {}s. exiting so the process can be restarted",
+                                    component,
+                                    lag,
+                                    elapsed.as_secs()
+                                );
+                                return Err(ErrorKind::MaxLagExceeded(
+                                    component,
+                                    lag,
+                                    elapsed.as_secs(),
+                                ).into());
+                            }
+                            warn!(
+                                "{} lags {} blocks behind for {}s",
+                                component,
+                                lag,
+                                elapsed.as_secs()
+                            );
+                        }
+                        None => self.lagging_since = None,
+                    }
+                    self.state = LagSupervisorState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use status::Status;
+    use super::lagging_component;
+
+    #[test]
+    fn test_lagging_component() {
+        let status = Status {
+            home_block: Some(100),
+            foreign_block: Some(200),
+            checked_deposit_relay: Some(90),
+            checked_withdraw_confirm: Some(180),
+            checked_withdraw_relay: Some(150),
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(("withdraw relay", 50)),
+            lagging_component(&status, true, 10)
+        );
+        assert_eq!(
+            Some(("withdraw confirm", 20)),
+            lagging_component(&status, false, 10)
+        );
+        assert_eq!(None, lagging_component(&status, true, 50));
+        assert_eq!(None, lagging_component(&Status::default(), true, 0));
+    }
+}
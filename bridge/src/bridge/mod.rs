@@ -1,19 +1,55 @@
+mod balance_watchdog;
+mod call_confirm;
+mod call_relay;
+mod chain_clock;
 mod deploy;
 mod deposit_relay;
+mod fee_claim;
+mod head_watch;
+mod lag_supervisor;
+mod parked_relays;
+mod signature_verification;
+mod solvency_check;
+mod status_update;
+mod stuck_transactions;
+mod unaccounted_transfers;
 mod withdraw_confirm;
 mod withdraw_relay;
 
-use std::fs;
 use std::sync::Arc;
 use std::path::PathBuf;
 use futures::{Async, Poll, Stream};
 use web3::Transport;
 use app::App;
+use checkpoints::StreamCheckpoints;
+use control::shutdown_requested;
 use database::Database;
 use error::{Error, Result};
+use journal::Journal;
+use metrics::{metrics_export, MetricsExport};
+use parked::Parked;
+use shard::{Leases, ShardedStream};
+use signer::Chain;
+use sqlite::SqliteDatabase;
+use status;
+use value_limits::ValueLimiters;
 
-pub use self::deploy::{DeployForeign, DeployHome};
-pub use self::deposit_relay::{create_deposit_relay, DepositRelay};
+pub use self::balance_watchdog::{create_balance_watchdog, BalanceWatchdog};
+pub use self::call_confirm::{create_call_confirm, CallConfirm};
+pub use self::call_relay::{create_call_relay, CallRelay};
+pub use self::chain_clock::{create_chain_clock, ChainClock};
+pub use self::deploy::{foreign_contract_name, foreign_creation_code, home_contract_name,
+                       home_creation_code, DeployForeign, DeployHome, DeployedContract};
+pub use self::deposit_relay::{create_deposit_relay, deposit_payload, DepositRelay};
+pub use self::fee_claim::{create_fee_claim, FeeClaim};
+pub use self::head_watch::{create_head_watch, HeadWatch};
+pub use self::lag_supervisor::{create_lag_supervisor, LagSupervisor};
+pub use self::parked_relays::{create_parked_relays, ParkedRelays};
+pub use self::solvency_check::{create_solvency_check, SolvencyCheck};
+pub use self::status_update::{create_status_update, StatusUpdate};
+pub use self::stuck_transactions::{create_stuck_transactions, StuckTransactions};
+pub use self::unaccounted_transfers::{create_unaccounted_transfers, unaccounted_transfer,
+                                      UnaccountedTransfer, UnaccountedTransfers};
 pub use self::withdraw_relay::{create_withdraw_relay, WithdrawRelay};
 pub use self::withdraw_confirm::{create_withdraw_confirm, WithdrawConfirm};
 
@@ -23,6 +59,23 @@ pub enum BridgeChecked {
     DepositRelay(u64),
     WithdrawRelay(u64),
     WithdrawConfirm(u64),
+    UnaccountedTransfers(u64),
+    CallConfirm(u64),
+    CallRelay(u64),
+}
+
+impl BridgeChecked {
+    /// returns the log stream whose events are partitioned under `[shard]` and its block
+    fn sharded(&self) -> Option<(ShardedStream, u64)> {
+        match *self {
+            BridgeChecked::DepositRelay(n) => Some((ShardedStream::DepositRelay, n)),
+            BridgeChecked::WithdrawRelay(n) => Some((ShardedStream::WithdrawRelay, n)),
+            BridgeChecked::WithdrawConfirm(n) => Some((ShardedStream::WithdrawConfirm, n)),
+            BridgeChecked::CallConfirm(n) => Some((ShardedStream::CallConfirm, n)),
+            BridgeChecked::CallRelay(n) => Some((ShardedStream::CallRelay, n)),
+            BridgeChecked::UnaccountedTransfers(_) => None,
+        }
+    }
 }
 
 pub trait BridgeBackend {
@@ -32,6 +85,16 @@ pub trait BridgeBackend {
 pub struct FileBackend {
     path: PathBuf,
     database: Database,
+    /// hashes of the checked blocks are looked up here
+    checkpoints: StreamCheckpoints,
+    /// the checkpoints are saved here instead of the file at `path` if it is set
+    sqlite: Option<SqliteDatabase>,
+    /// windows of the value limits are saved with the checkpoints
+    value_limits: ValueLimiters,
+    /// relay transactions in flight are saved with the checkpoints
+    journal: Journal,
+    /// parked relays are saved with the checkpoints
+    parked: Parked,
 }
 
 impl BridgeBackend for FileBackend {
@@ -40,22 +103,75 @@ impl BridgeBackend for FileBackend {
             match check {
                 BridgeChecked::DepositRelay(n) => {
                     self.database.checked_deposit_relay = n;
+                    self.database.checked_deposit_relay_hash =
+                        self.checkpoints.deposit_relay.hash(n);
+                    self.journal.clear(Chain::Foreign, n);
                 }
                 BridgeChecked::WithdrawRelay(n) => {
                     self.database.checked_withdraw_relay = n;
+                    self.database.checked_withdraw_relay_hash =
+                        self.checkpoints.withdraw_relay.hash(n);
+                    self.journal.clear(Chain::Home, n);
                 }
                 BridgeChecked::WithdrawConfirm(n) => {
                     self.database.checked_withdraw_confirm = n;
+                    self.database.checked_withdraw_confirm_hash =
+                        self.checkpoints.withdraw_confirm.hash(n);
+                }
+                BridgeChecked::UnaccountedTransfers(n) => {
+                    self.database.checked_unaccounted_transfers = n;
+                }
+                BridgeChecked::CallConfirm(n) => {
+                    self.database.checked_call_confirm = n;
+                }
+                BridgeChecked::CallRelay(n) => {
+                    self.database.checked_call_relay = n;
                 }
             }
         }
+        self.database.deposit_value_window = self.value_limits.deposits.window();
+        self.database.withdraw_value_window = self.value_limits.withdraws.window();
+        self.database.in_flight = self.journal.entries();
+        self.database.parked = self.parked.relays();
+        self.database.dead_letters = self.parked.dead_letters();
+
+        if let Some(ref sqlite) = self.sqlite {
+            return sqlite.save(&self.database);
+        }
 
-        let file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&self.path)?;
+        self.database.store(&self.path)
+    }
+}
+
+/// backend of dry runs. the checkpoints only advance in memory
+/// so that a real run processes the same blocks again
+pub struct DryRunBackend;
 
-        self.database.save(file)
+impl BridgeBackend for DryRunBackend {
+    fn save(&mut self, checks: Vec<BridgeChecked>) -> Result<()> {
+        for check in checks {
+            match check {
+                BridgeChecked::DepositRelay(n) => {
+                    info!("dry run: not saving checked deposit relay {}", n)
+                }
+                BridgeChecked::WithdrawRelay(n) => {
+                    info!("dry run: not saving checked withdraw relay {}", n)
+                }
+                BridgeChecked::WithdrawConfirm(n) => {
+                    info!("dry run: not saving checked withdraw confirm {}", n)
+                }
+                BridgeChecked::UnaccountedTransfers(n) => {
+                    info!("dry run: not saving checked unaccounted transfers {}", n)
+                }
+                BridgeChecked::CallConfirm(n) => {
+                    info!("dry run: not saving checked call confirm {}", n)
+                }
+                BridgeChecked::CallRelay(n) => {
+                    info!("dry run: not saving checked call relay {}", n)
+                }
+            }
+        }
+        Ok(())
     }
 }
 
@@ -72,6 +188,11 @@ pub fn create_bridge<T: Transport + Clone>(
     let backend = FileBackend {
         path: app.database_path.clone(),
         database: init.clone(),
+        checkpoints: app.checkpoints.clone(),
+        sqlite: app.sqlite.clone(),
+        value_limits: app.value_limits.clone(),
+        journal: app.journal.clone(),
+        parked: app.parked.clone(),
     };
 
     create_bridge_backed_by(app, init, backend)
@@ -83,24 +204,148 @@ pub fn create_bridge_backed_by<T: Transport + Clone, F: BridgeBackend>(
     init: &Database,
     backend: F,
 ) -> Bridge<T, F> {
+    app.journal.seed(init.in_flight.clone());
+    app.parked
+        .seed(init.parked.clone(), init.dead_letters.clone());
     Bridge {
+        home_head: create_head_watch(
+            "home",
+            app.connections.home.clone(),
+            app.timer.clone(),
+            &app.config.home,
+            app.heads.home.clone(),
+        ),
+        foreign_head: create_head_watch(
+            "foreign",
+            app.connections.foreign.clone(),
+            app.timer.clone(),
+            &app.config.foreign,
+            app.heads.foreign.clone(),
+        ),
         deposit_relay: create_deposit_relay(app.clone(), init),
-        withdraw_relay: create_withdraw_relay(app.clone(), init),
+        withdraw_relay: if app.config.role.executes_withdraws() {
+            Some(create_withdraw_relay(app.clone(), init))
+        } else {
+            None
+        },
+        parked_relays: create_parked_relays(app.clone(), init),
         withdraw_confirm: create_withdraw_confirm(app.clone(), init),
+        unaccounted_transfers: if app.config.unaccounted_transfers.is_some() {
+            Some(create_unaccounted_transfers(app.clone(), init))
+        } else {
+            None
+        },
+        call_confirm: app.config
+            .message_calls
+            .clone()
+            .map(|message_calls| create_call_confirm(app.clone(), init, message_calls)),
+        call_relay: match app.config.message_calls {
+            Some(ref message_calls) if app.config.role.executes_withdraws() => Some(
+                create_call_relay(app.clone(), init, message_calls.clone()),
+            ),
+            _ => None,
+        },
+        metrics_export: app.config.metrics.as_ref().map(|metrics| {
+            metrics_export(
+                metrics.file.clone(),
+                app.timer.interval(metrics.export_interval),
+            )
+        }),
+        solvency_check: if app.config.solvency_check {
+            Some(create_solvency_check(app.clone(), init))
+        } else {
+            None
+        },
+        chain_clock: create_chain_clock(app.clone()),
+        status_update: app.config.http_api.as_ref().map(|http_api| {
+            create_status_update(
+                app.clone(),
+                init,
+                app.timer.interval(http_api.status_interval),
+            )
+        }),
+        lag_supervisor: app.config
+            .max_lag
+            .clone()
+            .map(|max_lag| create_lag_supervisor(app.clone(), init, max_lag)),
+        fee_claim: app.config
+            .token_fee
+            .clone()
+            .map(|token_fee| create_fee_claim(app.clone(), init, token_fee)),
+        home_stuck_transactions: create_stuck_transactions(app.clone(), Chain::Home),
+        foreign_stuck_transactions: create_stuck_transactions(app.clone(), Chain::Foreign),
+        home_balance_watchdog: create_balance_watchdog(app.clone(), Chain::Home),
+        foreign_balance_watchdog: create_balance_watchdog(app.clone(), Chain::Foreign),
+        journal: app.journal.clone(),
+        parked: app.parked.clone(),
+        leases: app.leases.clone(),
         state: BridgeStatus::Wait,
         backend,
     }
 }
 
 pub struct Bridge<T: Transport, F> {
+    /// announce the latest blocks to the log streams of the components
+    home_head: HeadWatch<T>,
+    foreign_head: HeadWatch<T>,
     deposit_relay: DepositRelay<T>,
-    withdraw_relay: WithdrawRelay<T>,
+    /// `None` if this instance never sends transactions to `home`
+    withdraw_relay: Option<WithdrawRelay<T>>,
+    parked_relays: ParkedRelays<T>,
     withdraw_confirm: WithdrawConfirm<T>,
+    /// `None` if detection of unaccounted transfers is disabled
+    unaccounted_transfers: Option<UnaccountedTransfers<T>>,
+    /// `None` if relay of calls is disabled
+    call_confirm: Option<CallConfirm<T>>,
+    /// `None` if relay of calls is disabled or this instance never sends transactions to `home`
+    call_relay: Option<CallRelay<T>>,
+    /// `None` if the solvency check is disabled
+    solvency_check: Option<SolvencyCheck<T>>,
+    /// `None` if the export of metrics is disabled
+    metrics_export: Option<MetricsExport>,
+    /// observes the time of both chains for maintenance windows
+    chain_clock: ChainClock<T>,
+    /// `None` if the http api is disabled
+    status_update: Option<StatusUpdate<T>>,
+    /// `None` if the process shouldn't exit when a component lags behind
+    lag_supervisor: Option<LagSupervisor<T>>,
+    /// `None` if deposits are relayed without fee
+    fee_claim: Option<FeeClaim<T>>,
+    /// `None` if stuck transactions to the chain aren't replaced
+    home_stuck_transactions: Option<StuckTransactions<T>>,
+    foreign_stuck_transactions: Option<StuckTransactions<T>>,
+    /// `None` if the balance of the account on the chain isn't checked
+    home_balance_watchdog: Option<BalanceWatchdog<T>>,
+    foreign_balance_watchdog: Option<BalanceWatchdog<T>>,
+    /// saved as soon as a relay transaction is journaled
+    journal: Journal,
+    /// saved as soon as a relay is parked or done
+    parked: Parked,
+    /// the events of the held partitions are handled up to the checked blocks
+    leases: Leases,
     state: BridgeStatus,
     backend: F,
 }
 
-impl<T: Transport, F: BridgeBackend> Stream for Bridge<T, F> {
+impl<T: Transport, F> Bridge<T, F> {
+    /// whether no relays or signatures are in progress
+    fn is_idle(&self) -> bool {
+        self.deposit_relay.is_idle()
+            && self.withdraw_relay
+                .as_ref()
+                .map_or(true, |stream| stream.is_idle())
+            && self.parked_relays.is_idle()
+            && self.withdraw_confirm.is_idle()
+            && self.call_confirm
+                .as_ref()
+                .map_or(true, |stream| stream.is_idle())
+            && self.call_relay
+                .as_ref()
+                .map_or(true, |stream| stream.is_idle())
+    }
+}
+
+impl<T: Transport + Clone, F: BridgeBackend> Stream for Bridge<T, F> {
     type Item = ();
     type Error = Error;
 
@@ -108,21 +353,144 @@ impl<T: Transport, F: BridgeBackend> Stream for Bridge<T, F> {
         loop {
             let next_state = match self.state {
                 BridgeStatus::Wait => {
+                    // the heads are watched first so the log streams see the latest blocks
+                    let h_head = try_bridge!(self.home_head.poll());
+                    let f_head = try_bridge!(self.foreign_head.poll());
                     let d_relay =
                         try_bridge!(self.deposit_relay.poll()).map(BridgeChecked::DepositRelay);
-                    let w_relay =
-                        try_bridge!(self.withdraw_relay.poll()).map(BridgeChecked::WithdrawRelay);
+                    let w_relay = match self.withdraw_relay {
+                        Some(ref mut stream) => {
+                            try_bridge!(stream.poll()).map(BridgeChecked::WithdrawRelay)
+                        }
+                        None => None,
+                    };
+                    let p_relays = try_bridge!(self.parked_relays.poll());
                     let w_confirm = try_bridge!(self.withdraw_confirm.poll())
                         .map(BridgeChecked::WithdrawConfirm);
+                    let u_transfers = match self.unaccounted_transfers {
+                        Some(ref mut stream) => {
+                            try_bridge!(stream.poll()).map(BridgeChecked::UnaccountedTransfers)
+                        }
+                        None => None,
+                    };
+                    let c_confirm = match self.call_confirm {
+                        Some(ref mut stream) => {
+                            try_bridge!(stream.poll()).map(BridgeChecked::CallConfirm)
+                        }
+                        None => None,
+                    };
+                    let c_relay = match self.call_relay {
+                        Some(ref mut stream) => {
+                            try_bridge!(stream.poll()).map(BridgeChecked::CallRelay)
+                        }
+                        None => None,
+                    };
+                    // the head watches, the retries of parked relays, the solvency check,
+                    // the metrics export, the chain clock, the status update, the lag supervisor,
+                    // the fee claim, the replacement of stuck transactions
+                    // and the balance watchdogs have nothing to save
+                    let s_check = match self.solvency_check {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let m_export = match self.metrics_export {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let c_clock = try_bridge!(self.chain_clock.poll());
+                    let s_update = match self.status_update {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let l_supervisor = match self.lag_supervisor {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let f_claim = match self.fee_claim {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let h_stuck = match self.home_stuck_transactions {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let f_stuck = match self.foreign_stuck_transactions {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let h_balance = match self.home_balance_watchdog {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
+                    let f_balance = match self.foreign_balance_watchdog {
+                        Some(ref mut stream) => try_bridge!(stream.poll()),
+                        None => None,
+                    };
 
-                    let result: Vec<_> = [d_relay, w_relay, w_confirm]
-                        .into_iter()
+                    let result: Vec<_> = [
+                        d_relay,
+                        w_relay,
+                        w_confirm,
+                        u_transfers,
+                        c_confirm,
+                        c_relay,
+                    ].into_iter()
                         .filter_map(|c| *c)
                         .collect();
 
+                    // `|` so that both flags are reset
+                    let journaled = self.journal.take_dirty() | self.parked.take_dirty();
+
                     if result.is_empty() {
+                        if journaled {
+                            self.backend.save(Vec::new())?;
+                        }
+                        // relays are paused once shutdown is requested.
+                        // the bridge ends once those in progress are done
+                        if shutdown_requested() && self.is_idle() {
+                            info!("relays in progress are done. shutting down");
+                            self.backend.save(Vec::new())?;
+                            return Ok(Async::Ready(None));
+                        }
+                        if h_head.is_some() || f_head.is_some() || p_relays.is_some()
+                            || s_check.is_some()
+                            || m_export.is_some() || c_clock.is_some() || s_update.is_some()
+                            || l_supervisor.is_some() || f_claim.is_some() || h_stuck.is_some()
+                            || f_stuck.is_some() || h_balance.is_some() || f_balance.is_some()
+                        {
+                            continue;
+                        }
                         return Ok(Async::NotReady);
                     } else {
+                        status::record(|status| {
+                            for check in &result {
+                                match *check {
+                                    BridgeChecked::DepositRelay(n) => {
+                                        status.checked_deposit_relay = Some(n)
+                                    }
+                                    BridgeChecked::WithdrawRelay(n) => {
+                                        status.checked_withdraw_relay = Some(n)
+                                    }
+                                    BridgeChecked::WithdrawConfirm(n) => {
+                                        status.checked_withdraw_confirm = Some(n)
+                                    }
+                                    BridgeChecked::UnaccountedTransfers(n) => {
+                                        status.checked_unaccounted_transfers = Some(n)
+                                    }
+                                    BridgeChecked::CallConfirm(n) => {
+                                        status.checked_call_confirm = Some(n)
+                                    }
+                                    BridgeChecked::CallRelay(n) => {
+                                        status.checked_call_relay = Some(n)
+                                    }
+                                }
+                            }
+                        });
+                        for check in &result {
+                            if let Some((stream, n)) = check.sharded() {
+                                self.leases.checked(stream, n);
+                            }
+                        }
                         self.backend.save(result)?;
                         BridgeStatus::NextItem(Some(()))
                     }
@@ -142,7 +510,12 @@ impl<T: Transport, F: BridgeBackend> Stream for Bridge<T, F> {
 mod tests {
     extern crate tempdir;
     use self::tempdir::TempDir;
+    use checkpoints::{Checkpoints, StreamCheckpoints};
     use database::Database;
+    use journal::{Journal, JournalEntry};
+    use parked::{DeadLetter, ParkReason, Parked, ParkedRelay};
+    use signer::Chain;
+    use web3::types::TransactionRequest;
     use super::{BridgeBackend, BridgeChecked, FileBackend};
 
     #[test]
@@ -150,15 +523,37 @@ mod tests {
         let tempdir = TempDir::new("test_file_backend").unwrap();
         let mut path = tempdir.path().to_owned();
         path.push("db");
+        let checkpoints = StreamCheckpoints {
+            deposit_relay: Checkpoints::new(2),
+            ..Default::default()
+        };
+        let journal = Journal::default();
+        let parked = Parked::default();
         let mut backend = FileBackend {
             path: path.clone(),
             database: Database::default(),
+            checkpoints: checkpoints.clone(),
+            sqlite: None,
+            value_limits: Default::default(),
+            journal: journal.clone(),
+            parked: parked.clone(),
         };
 
+        let entry = JournalEntry {
+            chain: Chain::Foreign,
+            block: 2,
+            transaction_hash: 0x33.into(),
+            to: 1.into(),
+            data: vec![0x44].into(),
+        };
+        journal.record(entry.clone());
         backend.save(vec![BridgeChecked::DepositRelay(1)]).unwrap();
         assert_eq!(1, backend.database.checked_deposit_relay);
+        assert_eq!(vec![entry], Database::load(&path).unwrap().in_flight);
         assert_eq!(0, backend.database.checked_withdraw_confirm);
         assert_eq!(0, backend.database.checked_withdraw_relay);
+        assert_eq!(None, backend.database.checked_deposit_relay_hash);
+        checkpoints.deposit_relay.push(2, 0x22.into());
         backend
             .save(vec![
                 BridgeChecked::DepositRelay(2),
@@ -169,6 +564,46 @@ mod tests {
         assert_eq!(2, backend.database.checked_deposit_relay);
         assert_eq!(3, backend.database.checked_withdraw_confirm);
         assert_eq!(2, backend.database.checked_withdraw_relay);
+        assert_eq!(Some(0x22.into()), backend.database.checked_deposit_relay_hash);
+        assert_eq!(None, backend.database.checked_withdraw_relay_hash);
+        // the relay of block 2 is checkpointed
+        assert!(backend.database.in_flight.is_empty());
+
+        let request = TransactionRequest {
+            from: 1.into(),
+            to: Some(2.into()),
+            gas: Some(100_000.into()),
+            gas_price: Some(10.into()),
+            value: None,
+            data: Some(vec![0x55].into()),
+            nonce: None,
+            condition: None,
+        };
+        let relay = ParkedRelay::new(
+            Chain::Home,
+            0x66.into(),
+            5.into(),
+            ParkReason::InsufficientBalance,
+            &request,
+        );
+        parked.park(relay.clone());
+        backend.save(vec![BridgeChecked::WithdrawRelay(3)]).unwrap();
+        // parked relays outlive the checkpoint
+        assert_eq!(vec![relay.clone()], backend.database.parked);
+
+        let dead_letter = DeadLetter {
+            revert_reason: "already processed".into(),
+            given_up_at: 1_500_000_000,
+            relay: ParkedRelay {
+                reason: ParkReason::Reverted,
+                attempts: 5,
+                ..relay
+            },
+        };
+        parked.give_up(dead_letter.clone());
+        backend.save(vec![BridgeChecked::WithdrawRelay(4)]).unwrap();
+        assert!(backend.database.parked.is_empty());
+        assert_eq!(vec![dead_letter], backend.database.dead_letters);
 
         let loaded = Database::load(path).unwrap();
         assert_eq!(backend.database, loaded);
@@ -0,0 +1,360 @@
+/// retries of the relays parked apart from their relay streams (see `parked`).
+/// withdraws parked while the balance of `HomeBridge` didn't cover them are relayed again
+/// on a tick of `home.poll_interval` once the balance covers them, in the order they were
+/// parked. a withdraw whose relay still reverts once the balance covers it is dropped
+/// (example: another authority already relayed it).
+/// deposits and withdraws whose simulation reverted are relayed again on the first tick
+/// after their `retry_at`. they are parked again until `reverted_relays.max_attempts`
+/// attempts reverted and then given up with an error in the status of the http api.
+/// relays given up are kept as dead letters with the revert reason of their last attempt
+/// (see `parked::DeadLetter`).
+/// no relays are started in a direction while it is paused or the bridge shuts down.
+
+use std::mem;
+use std::sync::Arc;
+use futures::{Async, Future, Poll, Stream};
+use futures::future::{join_all, JoinAll};
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::{Address, H256, U256};
+use api::{self, ApiCall};
+use app::App;
+use control::{Direction, Pause};
+use database::Database;
+use error::Error;
+use history::TransferDirection;
+use logging::log_relay;
+use metrics;
+use parked::{DeadLetter, ParkReason, ParkedRelay};
+use signer::Chain;
+use status;
+use transaction::RelayTransaction;
+use super::deposit_relay::deposit_transaction;
+use super::withdraw_relay::withdraw_transaction;
+
+/// returns the leading withdraws of `parked` whose values the `balance` covers together
+fn covered(parked: Vec<ParkedRelay>, balance: U256) -> Vec<ParkedRelay> {
+    let mut remaining = balance;
+    parked
+        .into_iter()
+        .take_while(|relay| {
+            if relay.value > remaining {
+                return false;
+            }
+            remaining = remaining - relay.value;
+            true
+        })
+        .collect()
+}
+
+/// returns the direction of the transfers relayed to `chain`
+fn direction(chain: Chain) -> TransferDirection {
+    match chain {
+        Chain::Home => TransferDirection::Withdraw,
+        Chain::Foreign => TransferDirection::Deposit,
+    }
+}
+
+/// attempt of a parked relay. resolves to the hash of the relay transaction
+/// and the revert reason of its simulation if it reverted
+struct Attempt<T: Transport>(RelayTransaction<T>);
+
+impl<T: Transport + Clone> Future for Attempt<T> {
+    type Item = (Option<H256>, Option<String>);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let hash = try_ready!(self.0.poll());
+        let reason = self.0.revert_reason().map(str::to_owned);
+        Ok(Async::Ready((hash, reason)))
+    }
+}
+
+/// returns the state that relays `relays` again
+fn relay<T: Transport + Clone>(app: &App<T>, relays: Vec<ParkedRelay>) -> ParkedRelaysState<T> {
+    if relays.is_empty() {
+        return ParkedRelaysState::Yield;
+    }
+    let future = relays
+        .iter()
+        .map(|relay| {
+            Attempt(match relay.chain {
+                Chain::Home => {
+                    withdraw_transaction(app, relay.request(app.config.home.account), None)
+                }
+                Chain::Foreign => {
+                    deposit_transaction(app, relay.request(app.config.foreign.account), None)
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    ParkedRelaysState::Relay {
+        future: join_all(future),
+        relays,
+    }
+}
+
+/// parks `relay` whose attempt reverted again with `revert_reason`
+/// or gives it up as dead letter after its last attempt
+fn retry<T: Transport>(app: &App<T>, relay: &ParkedRelay, revert_reason: Option<String>) {
+    let config = &app.config.reverted_relays;
+    let now = status::now();
+    match relay
+        .clone()
+        .retry(now, config.retry_interval.as_secs(), config.max_attempts)
+    {
+        Some(relay) => app.parked.park(relay),
+        None => {
+            let revert_reason = revert_reason.unwrap_or_else(|| "unknown".into());
+            let message = format!(
+                "giving up the relay of {} {:?} after {} attempts reverted: {}",
+                match relay.chain {
+                    Chain::Home => "withdraw",
+                    Chain::Foreign => "deposit",
+                },
+                relay.origin,
+                config.max_attempts,
+                revert_reason
+            );
+            error!("{}", message);
+            status::record_error(message);
+            app.parked.give_up(DeadLetter {
+                revert_reason,
+                given_up_at: now,
+                relay: relay.clone(),
+            });
+        }
+    }
+}
+
+/// state of the retries of parked relays
+enum ParkedRelaysState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the balance of `HomeBridge` to cover the parked withdraws
+    FetchBalance {
+        future: Timeout<ApiCall<U256, T::Out>>,
+        parked: Vec<ParkedRelay>,
+        /// reverted relays due for another attempt
+        retries: Vec<ParkedRelay>,
+    },
+    /// relaying the due relays and the parked withdraws the balance covers
+    Relay {
+        future: JoinAll<Vec<Attempt<T>>>,
+        relays: Vec<ParkedRelay>,
+    },
+    /// the parked relays have been checked
+    Yield,
+}
+
+pub fn create_parked_relays<T: Transport>(
+    app: Arc<App<T>>,
+    init: &Database,
+) -> ParkedRelays<T> {
+    ParkedRelays {
+        interval: app.timer.interval(app.config.home.poll_interval),
+        deposits_pause: app.config.pause(Direction::Deposits),
+        withdraws_pause: Pause {
+            chain: Chain::Home,
+            ..app.config.pause(Direction::Withdraws)
+        },
+        home_contract: init.home_contract_address,
+        state: ParkedRelaysState::Wait,
+        app,
+    }
+}
+
+/// stream that retries the parked relays on every tick of `interval`.
+/// yields after every check
+pub struct ParkedRelays<T: Transport> {
+    app: Arc<App<T>>,
+    home_contract: Address,
+    interval: Interval,
+    /// deposits are relayed again only while this doesn't pause them
+    deposits_pause: Pause,
+    /// withdraws are relayed again only while this doesn't pause them
+    withdraws_pause: Pause,
+    state: ParkedRelaysState<T>,
+}
+
+impl<T: Transport> ParkedRelays<T> {
+    /// whether no relays are in progress. checked before the bridge shuts down
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            ParkedRelaysState::Wait | ParkedRelaysState::Yield => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for ParkedRelays<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                ParkedRelaysState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    let now = status::now();
+                    let mut retries = Vec::new();
+                    let mut parked = Vec::new();
+                    // `is_paused` also holds once the bridge shuts down
+                    if !self.deposits_pause.is_paused() {
+                        retries.extend(self.app.parked.due(Chain::Foreign, now));
+                    }
+                    if !self.withdraws_pause.is_paused() {
+                        retries.extend(self.app.parked.due(Chain::Home, now));
+                        parked = self.app
+                            .parked
+                            .find(Chain::Home, ParkReason::InsufficientBalance);
+                    }
+                    if !retries.is_empty() {
+                        info!("retrying {} reverted relays", retries.len());
+                    }
+                    if parked.is_empty() {
+                        relay(&self.app, retries)
+                    } else {
+                        ParkedRelaysState::FetchBalance {
+                            future: self.app.timer.timeout(
+                                api::balance(&self.app.connections.home, self.home_contract),
+                                self.app.config.home.request_timeout,
+                            ),
+                            parked,
+                            retries,
+                        }
+                    }
+                }
+                ParkedRelaysState::FetchBalance {
+                    ref mut future,
+                    ref mut parked,
+                    ref mut retries,
+                } => {
+                    let withdraws = match future.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(balance)) => {
+                            let withdraws = covered(mem::replace(parked, Vec::new()), balance);
+                            if !withdraws.is_empty() {
+                                info!(
+                                    "balance of HomeBridge ({} wei) covers {} parked withdraws. relaying them again",
+                                    balance,
+                                    withdraws.len()
+                                );
+                            }
+                            withdraws
+                        }
+                        Err(err) => {
+                            warn!(
+                                "failed to check the balance of HomeBridge for parked withdraws: {}",
+                                err
+                            );
+                            Vec::new()
+                        }
+                    };
+                    let mut relays = mem::replace(retries, Vec::new());
+                    relays.extend(withdraws);
+                    relay(&self.app, relays)
+                }
+                ParkedRelaysState::Relay {
+                    ref mut future,
+                    ref relays,
+                } => {
+                    let relayed = try_ready!(future.poll());
+                    let (mut deposits, mut withdraws) = (0, 0);
+                    for (relay, (hash, revert_reason)) in relays.iter().zip(relayed) {
+                        log_relay(direction(relay.chain), relay.origin, hash);
+                        match (hash, relay.reason) {
+                            (Some(_), _) => {
+                                match relay.chain {
+                                    Chain::Home => withdraws += 1,
+                                    Chain::Foreign => deposits += 1,
+                                }
+                                self.app.parked.remove(relay.chain, relay.origin);
+                            }
+                            (None, ParkReason::InsufficientBalance) => {
+                                warn!(
+                                    "dropping parked withdraw {:?} whose relay reverted although the balance covers it",
+                                    relay.origin
+                                );
+                                self.app.parked.remove(relay.chain, relay.origin);
+                            }
+                            (None, ParkReason::Reverted) => {
+                                retry(&self.app, relay, revert_reason)
+                            }
+                        }
+                    }
+                    metrics::record_relays(|relays| {
+                        relays.deposits += deposits;
+                        relays.withdraws += withdraws;
+                    });
+                    ParkedRelaysState::Yield
+                }
+                ParkedRelaysState::Yield => {
+                    self.state = ParkedRelaysState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::TransactionRequest;
+    use parked::{ParkReason, ParkedRelay};
+    use signer::Chain;
+    use super::covered;
+
+    fn relay(origin: u64, value: u64) -> ParkedRelay {
+        let request = TransactionRequest {
+            from: 1.into(),
+            to: Some(2.into()),
+            gas: None,
+            gas_price: None,
+            value: None,
+            data: None,
+            nonce: None,
+            condition: None,
+        };
+        ParkedRelay::new(
+            Chain::Home,
+            origin.into(),
+            value.into(),
+            ParkReason::InsufficientBalance,
+            &request,
+        )
+    }
+
+    #[test]
+    fn test_covered() {
+        let parked = vec![relay(1, 10), relay(2, 20), relay(3, 5)];
+        assert_eq!(0, covered(parked.clone(), 9.into()).len());
+        assert_eq!(vec![relay(1, 10)], covered(parked.clone(), 29.into()));
+        // a later withdraw isn't relayed ahead of an earlier one the balance doesn't cover
+        assert_eq!(2, covered(parked.clone(), 34.into()).len());
+        assert_eq!(parked.clone(), covered(parked, 35.into()));
+    }
+
+    #[test]
+    fn test_covered_exact() {
+        let parked = vec![relay(1, 10), relay(2, 20), relay(3, 5)];
+        assert_eq!(vec![relay(1, 10), relay(2, 20)], covered(parked.clone(), 30.into()));
+        assert_eq!(parked.clone(), covered(parked, 35.into()));
+    }
+
+    #[test]
+    fn test_covered_partial() {
+        let parked = vec![relay(1, 10), relay(2, 20), relay(3, 5)];
+        assert_eq!(vec![relay(1, 10)], covered(parked.clone(), 10.into()));
+        assert_eq!(vec![relay(1, 10), relay(2, 20)], covered(parked, 31.into()));
+    }
+
+    #[test]
+    fn test_covered_empty() {
+        assert!(covered(Vec::new(), 100.into()).is_empty());
+        assert!(covered(vec![relay(1, 10)], 0.into()).is_empty());
+        // withdraws of no value are covered by any balance
+        assert_eq!(vec![relay(1, 0)], covered(vec![relay(1, 0)], 0.into()));
+    }
+}
@@ -0,0 +1,273 @@
+/// verification of the signatures this authority submitted to `ForeignBridge`.
+/// once a `submitSignature` transaction is mined the message and the signatures
+/// stored for the hash of the message are read back from `ForeignBridge`.
+/// the stored message has to equal the signed one and one of the stored signatures
+/// has to equal the submitted one and recover to the account that submitted it.
+/// a mismatch points to a bug in the contract or a drift of the message encoding.
+/// submissions that aren't mined within the relay deadline of `foreign` can't be verified.
+
+use std::sync::Arc;
+use futures::{Async, Future, Poll};
+use tokio_timer::{Sleep, Timeout};
+use tiny_keccak::keccak256;
+use web3::Transport;
+use web3::types::{Address, Bytes, H256, H520, TransactionReceipt};
+use api::{self, ApiCall};
+use app::App;
+use error::Error;
+use history::TransferDirection;
+use logging::{RelayEvent, RelayStage};
+use message_to_mainnet::MessageToMainnet;
+use transaction::is_rpc_error;
+
+/// outcome of the verification of a submitted signature
+#[derive(Debug, PartialEq, Clone)]
+pub enum Verification {
+    /// the stored message and signature match
+    Verified,
+    /// the submission wasn't mined within the deadline
+    Unmined,
+    /// the stored message or signature doesn't match. describes the mismatch
+    Mismatch(String),
+}
+
+/// compares the message `stored` for the hash of `message` with `message`
+fn check_message(message: &[u8], stored: &[u8]) -> Option<String> {
+    if message == stored {
+        None
+    } else {
+        Some(format!(
+            "ForeignBridge stores message 0x{} for the hash of the signed message 0x{}",
+            to_hex(stored),
+            to_hex(message)
+        ))
+    }
+}
+
+/// compares the account `recovered` from the stored signature with the `account` that signed it
+fn check_signer(account: Address, recovered: Address) -> Option<String> {
+    if account == recovered {
+        None
+    } else {
+        Some(format!("signature stored for {} recovers to {}", account, recovered))
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn call<T: Transport>(
+    app: &App<T>,
+    contract: Address,
+    payload: Bytes,
+) -> Timeout<ApiCall<Bytes, T::Out>> {
+    app.timer.timeout(
+        api::call(&app.connections.foreign, contract, payload),
+        app.config.foreign.request_timeout,
+    )
+}
+
+fn read_signature<T: Transport>(
+    app: &App<T>,
+    contract: Address,
+    hash: [u8; 32],
+    index: usize,
+) -> VerifySignatureState<T> {
+    let payload = app.foreign_bridge
+        .functions()
+        .signature()
+        .input(hash, index as u64)
+        .into();
+    VerifySignatureState::Signature {
+        index,
+        future: call(app, contract, payload),
+    }
+}
+
+enum VerifySignatureState<T: Transport> {
+    /// waiting for the receipt of the submission
+    Receipt(Timeout<ApiCall<Option<TransactionReceipt>, T::Out>>),
+    /// waiting before the receipt is requested again
+    Wait(Sleep),
+    /// reading the stored message
+    Message(Timeout<ApiCall<Bytes, T::Out>>),
+    /// reading the stored signature at `index`
+    Signature {
+        index: usize,
+        future: Timeout<ApiCall<Bytes, T::Out>>,
+    },
+    /// recovering the signer of the stored signature
+    Recover(Timeout<ApiCall<Address, T::Out>>),
+}
+
+/// creates a new `VerifySignature` for the `signature` of `message` by `account`
+/// that was submitted to `contract` by the transaction `transaction`
+pub fn verify_signature<T: Transport>(
+    app: Arc<App<T>>,
+    contract: Address,
+    message: Vec<u8>,
+    account: Address,
+    signature: H520,
+    transaction: H256,
+) -> VerifySignature<T> {
+    let state = VerifySignatureState::Receipt(app.timer.timeout(
+        api::transaction_receipt(&app.connections.foreign, transaction),
+        app.config.foreign.request_timeout,
+    ));
+    VerifySignature {
+        deadline: app.timer.sleep(app.config.foreign.relay_deadline),
+        hash: keccak256(&message),
+        app,
+        contract,
+        message,
+        account,
+        signature,
+        transaction,
+        state,
+    }
+}
+
+/// future that reads a submitted signature back from `ForeignBridge`
+/// and resolves to the outcome of the verification
+pub struct VerifySignature<T: Transport> {
+    app: Arc<App<T>>,
+    contract: Address,
+    message: Vec<u8>,
+    /// keccak256 of `message` under which `ForeignBridge` stores the signatures
+    hash: [u8; 32],
+    account: Address,
+    signature: H520,
+    /// the submission
+    transaction: H256,
+    /// fires when the submission wasn't mined in time
+    deadline: Sleep,
+    state: VerifySignatureState<T>,
+}
+
+impl<T: Transport> Future for VerifySignature<T> {
+    type Item = Verification;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                VerifySignatureState::Receipt(ref mut future) => match try_ready!(future.poll()) {
+                    Some(receipt) => {
+                        RelayEvent::new(
+                            TransferDirection::Withdraw,
+                            RelayStage::Mined,
+                            MessageToMainnet::from_bytes(&self.message).sidenet_transaction_hash,
+                        ).receipt(&receipt)
+                            .log();
+                        let payload = self.app
+                            .foreign_bridge
+                            .functions()
+                            .message()
+                            .input(self.hash)
+                            .into();
+                        VerifySignatureState::Message(call(&self.app, self.contract, payload))
+                    }
+                    None => {
+                        if let Async::Ready(()) = self.deadline.poll()? {
+                            return Ok(Async::Ready(Verification::Unmined));
+                        }
+                        VerifySignatureState::Wait(
+                            self.app.timer.sleep(self.app.config.foreign.poll_interval),
+                        )
+                    }
+                },
+                VerifySignatureState::Wait(ref mut future) => {
+                    try_ready!(future.poll());
+                    VerifySignatureState::Receipt(self.app.timer.timeout(
+                        api::transaction_receipt(&self.app.connections.foreign, self.transaction),
+                        self.app.config.foreign.request_timeout,
+                    ))
+                }
+                VerifySignatureState::Message(ref mut future) => {
+                    let output = try_ready!(future.poll());
+                    let stored = self.app
+                        .foreign_bridge
+                        .functions()
+                        .message()
+                        .output(output.0.as_slice())?;
+                    if let Some(mismatch) = check_message(&self.message, &stored) {
+                        return Ok(Async::Ready(Verification::Mismatch(mismatch)));
+                    }
+                    read_signature(&self.app, self.contract, self.hash, 0)
+                }
+                VerifySignatureState::Signature {
+                    index,
+                    ref mut future,
+                } => {
+                    // reading past the stored signatures reverts
+                    let stored = match future.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(output)) => self.app
+                            .foreign_bridge
+                            .functions()
+                            .signature()
+                            .output(output.0.as_slice())
+                            .ok(),
+                        Err(ref err) if is_rpc_error(err) => None,
+                        Err(err) => return Err(err),
+                    };
+                    match stored {
+                        Some(ref stored) if stored.as_slice() == &self.signature.0[..] => {
+                            VerifySignatureState::Recover(self.app.timer.timeout(
+                                api::ec_recover(
+                                    &self.app.connections.foreign,
+                                    Bytes(self.message.clone()),
+                                    Bytes(stored.clone()),
+                                ),
+                                self.app.config.foreign.request_timeout,
+                            ))
+                        }
+                        Some(_) if index + 1 < self.app.config.authorities.accounts.len() => {
+                            read_signature(&self.app, self.contract, self.hash, index + 1)
+                        }
+                        _ => {
+                            return Ok(Async::Ready(Verification::Mismatch(format!(
+                                "ForeignBridge doesn't store the signature of {} for message 0x{}",
+                                self.account,
+                                to_hex(&self.message)
+                            ))))
+                        }
+                    }
+                }
+                VerifySignatureState::Recover(ref mut future) => {
+                    let recovered = try_ready!(future.poll());
+                    let verification = match check_signer(self.account, recovered) {
+                        Some(mismatch) => Verification::Mismatch(mismatch),
+                        None => Verification::Verified,
+                    };
+                    return Ok(Async::Ready(verification));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_message, check_signer};
+
+    #[test]
+    fn test_check_message() {
+        assert_eq!(None, check_message(&[1, 2, 3], &[1, 2, 3]));
+        assert_eq!(
+            Some(
+                "ForeignBridge stores message 0x0102 for the hash of the signed message 0x010203"
+                    .into()
+            ),
+            check_message(&[1, 2, 3], &[1, 2])
+        );
+    }
+
+    #[test]
+    fn test_check_signer() {
+        assert_eq!(None, check_signer(1.into(), 1.into()));
+        assert!(check_signer(1.into(), 2.into()).is_some());
+    }
+}
@@ -0,0 +1,290 @@
+/// detection of an insolvent `HomeBridge`.
+/// withdraws whose signatures were collected on `foreign` (`CollectedSignatures`)
+/// but that weren't executed via `HomeBridge.withdraw` yet are pending.
+/// if the balance of `HomeBridge` doesn't cover the total value of pending withdraws
+/// not all of them can ever be paid out. that's not a transient error:
+/// the bridge alerts and halts signing of new withdraws until the balance covers them again.
+/// relays of pending withdraws continue.
+/// pending withdraws are kept in memory. after a restart all `CollectedSignatures`
+/// since the deployment of `ForeignBridge` are checked again.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use futures::{Future, Poll, Stream};
+use futures::future::{join_all, JoinAll};
+use tokio_timer::Timeout;
+use web3::Transport;
+use web3::types::{Address, Bytes, FilterBuilder, H256, Log, U256};
+use ethabi::RawLog;
+use api::{self, ApiCall, LogStream};
+use app::App;
+use contracts::foreign;
+use control::set_insolvent;
+use database::Database;
+use error::Error;
+use message_to_mainnet::MessageToMainnet;
+use status::{self, PendingWithdraws};
+use util::web3_filter;
+
+/// returns a filter for `ForeignBridge.CollectedSignatures` events
+fn collected_signatures_filter(
+    foreign: &foreign::ForeignBridge,
+    address: Address,
+) -> FilterBuilder {
+    let filter = foreign.events().collected_signatures().create_filter();
+    web3_filter(filter, address)
+}
+
+/// returns the payload of the call to `ForeignBridge.message`
+/// for the message whose signatures were collected in `log`
+fn message_payload(foreign: &foreign::ForeignBridge, log: Log) -> Result<Bytes, Error> {
+    let raw_log = RawLog {
+        topics: log.topics.into_iter().map(|t| t.0.into()).collect(),
+        data: log.data.0,
+    };
+    let collected_signatures = foreign.events().collected_signatures().parse_log(raw_log)?;
+    Ok(foreign
+        .functions()
+        .message()
+        .input(collected_signatures.message_hash)
+        .into())
+}
+
+/// returns the total value of `pending` withdraws or `None` on overflow
+fn total_value(pending: &HashMap<H256, U256>) -> Option<U256> {
+    pending
+        .values()
+        .fold(Some(U256::zero()), |total, value| {
+            total.and_then(|total| {
+                let (sum, overflow) = total.overflowing_add(*value);
+                if overflow {
+                    None
+                } else {
+                    Some(sum)
+                }
+            })
+        })
+}
+
+/// state of the solvency check
+enum SolvencyCheckState<T: Transport> {
+    /// waiting for new `CollectedSignatures` logs
+    Wait,
+    /// fetching the messages whose signatures were collected
+    FetchMessages {
+        future: JoinAll<Vec<Timeout<ApiCall<Bytes, T::Out>>>>,
+        block: u64,
+    },
+    /// checking which pending withdraws `HomeBridge` executed
+    FetchExecuted {
+        future: JoinAll<Vec<Timeout<ApiCall<Bytes, T::Out>>>>,
+        hashes: Vec<H256>,
+        block: u64,
+    },
+    /// fetching the balance of `HomeBridge`
+    FetchBalance {
+        future: Timeout<ApiCall<U256, T::Out>>,
+        block: u64,
+    },
+    /// solvency has been checked with all logs till given block
+    Yield(Option<u64>),
+}
+
+pub fn create_solvency_check<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+) -> SolvencyCheck<T> {
+    let logs_init = api::LogStreamInit {
+        after: init.foreign_deploy,
+        request_timeout: app.config.foreign.request_timeout,
+        poll_interval: app.config.foreign.poll_interval,
+        confirmations: app.config.foreign.required_confirmations,
+        strategy: app.config.foreign.confirmation_strategy,
+        filter: collected_signatures_filter(&app.foreign_bridge, init.foreign_contract_address),
+    };
+
+    SolvencyCheck {
+        logs: api::log_stream(
+            app.connections.foreign.clone(),
+            app.timer.clone(),
+            logs_init,
+        ).following(&app.heads.foreign)
+            .retrying(&app.config.foreign.retry),
+        home_contract: init.home_contract_address,
+        foreign_contract: init.foreign_contract_address,
+        pending: HashMap::new(),
+        insolvent: false,
+        state: SolvencyCheckState::Wait,
+        app,
+    }
+}
+
+/// stream of the numbers of blocks on `foreign` up to which
+/// the collected signatures have been checked for solvency
+pub struct SolvencyCheck<T: Transport> {
+    app: Arc<App<T>>,
+    logs: LogStream<T>,
+    home_contract: Address,
+    foreign_contract: Address,
+    /// values of pending withdraws by transaction hash
+    pending: HashMap<H256, U256>,
+    /// whether `HomeBridge` was insolvent when last checked
+    insolvent: bool,
+    state: SolvencyCheckState<T>,
+}
+
+impl<T: Transport> Stream for SolvencyCheck<T> {
+    type Item = u64;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                SolvencyCheckState::Wait => {
+                    let item = try_stream!(self.logs.poll());
+                    let requests = item.logs
+                        .into_iter()
+                        .map(|log| {
+                            let payload = message_payload(&self.app.foreign_bridge, log)?;
+                            Ok(self.app.timer.timeout(
+                                api::call(
+                                    &self.app.connections.foreign,
+                                    self.foreign_contract,
+                                    payload,
+                                ),
+                                self.app.config.foreign.request_timeout,
+                            ))
+                        })
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    SolvencyCheckState::FetchMessages {
+                        future: join_all(requests),
+                        block: item.to,
+                    }
+                }
+                SolvencyCheckState::FetchMessages {
+                    ref mut future,
+                    block,
+                } => {
+                    let outputs = try_ready!(future.poll());
+                    for output in outputs {
+                        let message = self.app
+                            .foreign_bridge
+                            .functions()
+                            .message()
+                            .output(output.0.as_slice())?;
+                        let message = match MessageToMainnet::try_from_bytes(&message) {
+                            Ok(message) => message,
+                            Err(err) => {
+                                warn!("skipping withdraw message that can't be decoded: {}", err);
+                                continue;
+                            }
+                        };
+                        // messages meant for another `HomeBridge` can't be executed here
+                        if message.mainnet_bridge_address == self.home_contract {
+                            self.pending
+                                .insert(message.sidenet_transaction_hash, message.value);
+                        }
+                    }
+
+                    let app = &self.app;
+                    let home_contract = self.home_contract;
+                    let hashes = self.pending.keys().cloned().collect::<Vec<_>>();
+                    let requests = hashes
+                        .iter()
+                        .map(|hash| {
+                            let payload =
+                                app.home_bridge.functions().withdraws().input(*hash).into();
+                            app.timer.timeout(
+                                api::call(&app.connections.home, home_contract, payload),
+                                app.config.home.request_timeout,
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    SolvencyCheckState::FetchExecuted {
+                        future: join_all(requests),
+                        hashes,
+                        block,
+                    }
+                }
+                SolvencyCheckState::FetchExecuted {
+                    ref mut future,
+                    ref hashes,
+                    block,
+                } => {
+                    let outputs = try_ready!(future.poll());
+                    for (hash, output) in hashes.iter().zip(outputs) {
+                        let executed = self.app
+                            .home_bridge
+                            .functions()
+                            .withdraws()
+                            .output(output.0.as_slice())?;
+                        if executed {
+                            self.pending.remove(hash);
+                        }
+                    }
+                    SolvencyCheckState::FetchBalance {
+                        future: self.app.timer.timeout(
+                            api::balance(&self.app.connections.home, self.home_contract),
+                            self.app.config.home.request_timeout,
+                        ),
+                        block,
+                    }
+                }
+                SolvencyCheckState::FetchBalance {
+                    ref mut future,
+                    block,
+                } => {
+                    let balance = try_ready!(future.poll());
+                    let total = total_value(&self.pending);
+                    let insolvent = total.map_or(true, |total| total > balance);
+                    if insolvent && !self.insolvent {
+                        error!(
+                            "HomeBridge is insolvent: {} pending withdraws total {:?} wei but its balance is {} wei. halting signing of withdraws",
+                            self.pending.len(),
+                            total,
+                            balance
+                        );
+                    } else if !insolvent && self.insolvent {
+                        info!(
+                            "balance of HomeBridge ({} wei) covers pending withdraws again. resuming signing of withdraws",
+                            balance
+                        );
+                    }
+                    self.insolvent = insolvent;
+                    set_insolvent(insolvent);
+                    let count = self.pending.len();
+                    status::record(|status| {
+                        status.pending_withdraws = Some(PendingWithdraws {
+                            count,
+                            total_value: total,
+                        })
+                    });
+                    SolvencyCheckState::Yield(Some(block))
+                }
+                SolvencyCheckState::Yield(ref mut block) => match block.take() {
+                    None => SolvencyCheckState::Wait,
+                    some => return Ok(some.into()),
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use web3::types::U256;
+    use super::total_value;
+
+    #[test]
+    fn test_total_value() {
+        let mut pending = HashMap::new();
+        assert_eq!(Some(0.into()), total_value(&pending));
+        pending.insert(1.into(), 100.into());
+        pending.insert(2.into(), 50.into());
+        assert_eq!(Some(150.into()), total_value(&pending));
+        pending.insert(3.into(), U256::max_value());
+        assert_eq!(None, total_value(&pending));
+    }
+}
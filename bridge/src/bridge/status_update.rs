@@ -0,0 +1,125 @@
+/// periodic update of the chain status shown by the dashboard:
+/// the latest blocks of both chains, the balances of `HomeBridge`
+/// and of the accounts of this authority and the pending nonces of the accounts.
+
+use std::sync::Arc;
+use futures::{Async, Future, Poll, Stream};
+use futures::future::{Join, Join5};
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::{Address, U256};
+use api::{self, ApiCall};
+use app::App;
+use database::Database;
+use error::Error;
+use status;
+
+type Fetch<T> = Timeout<ApiCall<U256, <T as Transport>::Out>>;
+
+/// state of the status update
+enum StatusUpdateState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the latest blocks and the balances and the pending nonces
+    Fetch(
+        Join<
+            Join5<Fetch<T>, Fetch<T>, Fetch<T>, Fetch<T>, Fetch<T>>,
+            Join<Fetch<T>, Fetch<T>>,
+        >,
+    ),
+}
+
+pub fn create_status_update<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+    interval: Interval,
+) -> StatusUpdate<T> {
+    status::record(|status| {
+        status.checked_deposit_relay = Some(init.checked_deposit_relay);
+        status.checked_withdraw_confirm = Some(init.checked_withdraw_confirm);
+        status.checked_withdraw_relay = Some(init.checked_withdraw_relay);
+        if app.config.unaccounted_transfers.is_some() {
+            status.checked_unaccounted_transfers = Some(init.checked_unaccounted_transfers);
+        }
+        if app.config.message_calls.is_some() {
+            status.checked_call_confirm = Some(init.checked_call_confirm);
+            if app.config.role.executes_withdraws() {
+                status.checked_call_relay = Some(init.checked_call_relay);
+            }
+        }
+    });
+    StatusUpdate {
+        app,
+        home_contract: init.home_contract_address,
+        interval,
+        state: StatusUpdateState::Wait,
+    }
+}
+
+/// stream that updates the chain status on every tick of `interval`
+pub struct StatusUpdate<T: Transport> {
+    app: Arc<App<T>>,
+    home_contract: Address,
+    interval: Interval,
+    state: StatusUpdateState<T>,
+}
+
+impl<T: Transport> Stream for StatusUpdate<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                StatusUpdateState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    let app = &self.app;
+                    let home = |call| app.timer.timeout(call, app.config.home.request_timeout);
+                    let foreign =
+                        |call| app.timer.timeout(call, app.config.foreign.request_timeout);
+                    StatusUpdateState::Fetch(
+                        home(api::block_number(&app.connections.home)).join5(
+                            foreign(api::block_number(&app.connections.foreign)),
+                            home(api::balance(&app.connections.home, self.home_contract)),
+                            home(api::balance(
+                                &app.connections.home,
+                                app.config.home.account,
+                            )),
+                            foreign(api::balance(
+                                &app.connections.foreign,
+                                app.config.foreign.account,
+                            )),
+                        ).join(
+                            home(api::pending_nonce(
+                                &app.connections.home,
+                                app.config.home.account,
+                            )).join(foreign(api::pending_nonce(
+                                &app.connections.foreign,
+                                app.config.foreign.account,
+                            ))),
+                        ),
+                    )
+                }
+                StatusUpdateState::Fetch(ref mut future) => {
+                    let (
+                        (home_block, foreign_block, contract, home_account, foreign_account),
+                        (home_nonce, foreign_nonce),
+                    ) = try_ready!(future.poll());
+                    status::record(|status| {
+                        status.updated_at = Some(status::now());
+                        status.home_block = Some(home_block.low_u64());
+                        status.foreign_block = Some(foreign_block.low_u64());
+                        status.home_contract_balance = Some(contract);
+                        status.home_account_balance = Some(home_account);
+                        status.foreign_account_balance = Some(foreign_account);
+                        status.home_pending_nonce = Some(home_nonce);
+                        status.foreign_pending_nonce = Some(foreign_nonce);
+                    });
+                    self.state = StatusUpdateState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
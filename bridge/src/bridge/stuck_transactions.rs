@@ -0,0 +1,355 @@
+/// replacement of stuck relay transactions.
+/// relay transactions to a chain with `stuck_transactions` get their nonces from the
+/// `NonceManager` of the chain which tracks them until the nonce of the sender
+/// in the latest block passes them.
+/// a transaction that isn't mined within `stuck_transactions.timeout` is replaced
+/// by the same transaction with the same nonce and a gas price raised by
+/// `stuck_transactions.gas_price_bump` percent, at most `stuck_transactions.max_gas_price`.
+/// relays of withdraws must pay the gas price of the message
+/// so they are rebroadcast unchanged instead.
+/// if the node lost the transaction with the lowest unconfirmed nonce
+/// the gap is filled with a transfer of 0 wei to the sender
+/// so that the transactions after it can be mined.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll, Stream};
+use futures::future::Join;
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::U256;
+use api::{self, ApiCall, TypedTransactionRequest};
+use app::App;
+use config::StuckTransactionsConfig;
+use error::Error;
+use signer::Chain;
+use transaction::{bump_fees, gap_filler, send_replacement, NonceManager, PendingTransaction,
+                  Replacement};
+
+type Fetch<T> = Timeout<ApiCall<U256, <T as Transport>::Out>>;
+
+/// returns the transactions to send for the `stuck` transactions, lowest nonce first.
+/// `gap` is the nonce the chain waits for if none of the tracked transactions holds it
+fn replacements(
+    chain: &str,
+    config: &StuckTransactionsConfig,
+    stuck: Vec<PendingTransaction>,
+    gap: Option<U256>,
+) -> VecDeque<TypedTransactionRequest> {
+    let mut replacements = VecDeque::new();
+    match (gap, stuck.first()) {
+        (Some(gap), Some(lowest)) if gap < lowest.nonce() => {
+            let fees = bump_fees(&lowest.transaction, config.gas_price_bump, config.max_gas_price)
+                .unwrap_or_else(|| lowest.transaction.clone());
+            warn!(
+                "{} waits for nonce {} of {} which no sent transaction holds. filling the gap",
+                chain, gap, lowest.transaction.request.from
+            );
+            replacements.push_back(gap_filler(&fees, gap));
+        }
+        _ => {}
+    }
+    for pending in stuck {
+        let transaction = if pending.fixed_gas_price {
+            warn!(
+                "transaction {} with nonce {} to {} is stuck. its gas price is fixed. rebroadcasting it",
+                pending.hash,
+                pending.nonce(),
+                chain
+            );
+            pending.transaction
+        } else {
+            match bump_fees(&pending.transaction, config.gas_price_bump, config.max_gas_price) {
+                Some(bumped) => {
+                    warn!(
+                        "transaction {} with nonce {} to {} is stuck. replacing it with {}% higher fees",
+                        pending.hash,
+                        pending.nonce(),
+                        chain,
+                        config.gas_price_bump
+                    );
+                    bumped
+                }
+                None => {
+                    warn!(
+                        "transaction {} with nonce {} to {} is stuck at the maximum gas price. rebroadcasting it",
+                        pending.hash,
+                        pending.nonce(),
+                        chain
+                    );
+                    pending.transaction
+                }
+            }
+        };
+        replacements.push_back(transaction);
+    }
+    replacements
+}
+
+/// state of the replacement of stuck transactions
+enum StuckTransactionsState<T: Transport> {
+    /// waiting for the next tick of the interval
+    Wait,
+    /// fetching the nonces of the sender in the latest block and including pending transactions
+    FetchNonces(Join<Fetch<T>, Fetch<T>>),
+    /// sending a replacement
+    Replace {
+        future: Replacement<T>,
+        transaction: TypedTransactionRequest,
+        remaining: VecDeque<TypedTransactionRequest>,
+    },
+    /// replacements of this round are sent
+    Yield,
+}
+
+/// creates the replacement of stuck transactions to `chain`.
+/// `None` if `stuck_transactions` isn't configured for `chain`
+pub fn create_stuck_transactions<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    chain: Chain,
+) -> Option<StuckTransactions<T>> {
+    let (nonces, node) = match chain {
+        Chain::Home => (app.nonces.home.clone()?, &app.config.home),
+        Chain::Foreign => (app.nonces.foreign.clone()?, &app.config.foreign),
+    };
+    let config = node.stuck_transactions.clone()?;
+    Some(StuckTransactions {
+        interval: app.timer.interval(node.poll_interval),
+        app: app.clone(),
+        chain,
+        nonces,
+        config,
+        state: StuckTransactionsState::Wait,
+    })
+}
+
+/// stream that checks for stuck transactions to a chain on every tick of `interval`
+/// and replaces them. yields after every check
+pub struct StuckTransactions<T: Transport> {
+    app: Arc<App<T>>,
+    chain: Chain,
+    nonces: NonceManager,
+    config: StuckTransactionsConfig,
+    interval: Interval,
+    state: StuckTransactionsState<T>,
+}
+
+impl<T: Transport + Clone> StuckTransactions<T> {
+    fn name(&self) -> &'static str {
+        match self.chain {
+            Chain::Home => "home",
+            Chain::Foreign => "foreign",
+        }
+    }
+
+    fn connection(&self) -> &T {
+        match self.chain {
+            Chain::Home => &self.app.connections.home,
+            Chain::Foreign => &self.app.connections.foreign,
+        }
+    }
+
+    fn request_timeout(&self) -> Duration {
+        match self.chain {
+            Chain::Home => self.app.config.home.request_timeout,
+            Chain::Foreign => self.app.config.foreign.request_timeout,
+        }
+    }
+
+    /// sends the next of `remaining`. yields once all are sent
+    fn next_replacement(
+        &self,
+        mut remaining: VecDeque<TypedTransactionRequest>,
+    ) -> StuckTransactionsState<T> {
+        while let Some(transaction) = remaining.pop_front() {
+            let chain_id = match self.chain {
+                Chain::Home => self.app.config.home.chain_id,
+                Chain::Foreign => self.app.config.foreign.chain_id,
+            };
+            let future = send_replacement(
+                self.connection().clone(),
+                &self.app.timer,
+                self.request_timeout(),
+                self.app
+                    .keystore
+                    .as_ref()
+                    .map(|keystore| (keystore, self.chain)),
+                chain_id,
+                transaction.clone(),
+            );
+            match future {
+                Ok(future) => {
+                    return StuckTransactionsState::Replace {
+                        future,
+                        transaction,
+                        remaining,
+                    }
+                }
+                Err(err) => {
+                    warn!("failed to replace stuck transaction to {}: {}", self.name(), err);
+                    self.postpone(&transaction);
+                }
+            }
+        }
+        StuckTransactionsState::Yield
+    }
+
+    /// waits another timeout before replacing the transaction with the nonce of `transaction`
+    fn postpone(&self, transaction: &TypedTransactionRequest) {
+        if let Some(nonce) = transaction.request.nonce {
+            self.nonces.postpone(nonce, Instant::now());
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for StuckTransactions<T> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                StuckTransactionsState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    if self.nonces.is_empty() {
+                        continue;
+                    }
+                    let account = self.nonces.account();
+                    let timeout = self.request_timeout();
+                    let timer = &self.app.timer;
+                    StuckTransactionsState::FetchNonces(
+                        timer
+                            .timeout(api::confirmed_nonce(self.connection(), account), timeout)
+                            .join(
+                                timer.timeout(api::pending_nonce(self.connection(), account), timeout),
+                            ),
+                    )
+                }
+                StuckTransactionsState::FetchNonces(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready((confirmed, pending))) => {
+                        let mined = self.nonces.confirm(confirmed);
+                        if mined > 0 {
+                            debug!("{} relay transactions to {} mined", mined, self.name());
+                        }
+                        let stuck = self.nonces.stuck(self.config.timeout, Instant::now());
+                        let gap = self.nonces.gap(confirmed, pending);
+                        let remaining = replacements(self.name(), &self.config, stuck, gap);
+                        self.next_replacement(remaining)
+                    }
+                    Err(err) => {
+                        warn!(
+                            "failed to check for stuck transactions to {}: {}",
+                            self.name(),
+                            err
+                        );
+                        StuckTransactionsState::Yield
+                    }
+                },
+                StuckTransactionsState::Replace {
+                    ref mut future,
+                    ref transaction,
+                    ref mut remaining,
+                } => {
+                    let result = match future.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(hash)) => Ok(hash),
+                        Err(err) => Err(err),
+                    };
+                    let transaction = transaction.clone();
+                    let remaining = mem::replace(remaining, VecDeque::new());
+                    match result {
+                        Ok(hash) => {
+                            info!(
+                                "sent replacement {} with nonce {:?} to {}",
+                                hash,
+                                transaction.request.nonce,
+                                self.name()
+                            );
+                            self.nonces.replace(hash, transaction, Instant::now());
+                        }
+                        Err(err) => {
+                            warn!(
+                                "failed to replace stuck transaction to {}: {}",
+                                self.name(),
+                                err
+                            );
+                            self.postpone(&transaction);
+                        }
+                    }
+                    self.next_replacement(remaining)
+                }
+                StuckTransactionsState::Yield => {
+                    self.state = StuckTransactionsState::Wait;
+                    return Ok(Async::Ready(Some(())));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use web3::types::TransactionRequest;
+    use api::TypedTransactionRequest;
+    use config::StuckTransactionsConfig;
+    use transaction::PendingTransaction;
+    use super::replacements;
+
+    fn pending(nonce: u64, fixed_gas_price: bool) -> PendingTransaction {
+        PendingTransaction {
+            hash: nonce.into(),
+            transaction: TypedTransactionRequest::legacy(TransactionRequest {
+                from: 1.into(),
+                to: Some(2.into()),
+                gas: Some(100_000.into()),
+                gas_price: Some(100.into()),
+                value: None,
+                data: None,
+                nonce: Some(nonce.into()),
+                condition: None,
+            }),
+            fixed_gas_price,
+            sent_at: Instant::now(),
+            replacements: 0,
+        }
+    }
+
+    #[test]
+    fn test_replacements() {
+        let config = StuckTransactionsConfig {
+            timeout: Duration::from_secs(300),
+            gas_price_bump: 12,
+            max_gas_price: Some(200.into()),
+        };
+        let stuck = vec![pending(5, false), pending(6, true)];
+        let replacements = replacements("home", &config, stuck, Some(4.into()));
+        assert_eq!(3, replacements.len());
+
+        assert_eq!(Some(4.into()), replacements[0].request.nonce);
+        assert_eq!(Some(1.into()), replacements[0].request.to);
+        assert_eq!(Some(0.into()), replacements[0].request.value);
+        assert_eq!(Some(112.into()), replacements[0].request.gas_price);
+
+        assert_eq!(Some(5.into()), replacements[1].request.nonce);
+        assert_eq!(Some(112.into()), replacements[1].request.gas_price);
+
+        assert_eq!(pending(6, true).transaction, replacements[2]);
+    }
+
+    #[test]
+    fn test_replacements_without_gap() {
+        let config = StuckTransactionsConfig {
+            timeout: Duration::from_secs(300),
+            gas_price_bump: 12,
+            max_gas_price: None,
+        };
+        let replacements = replacements("foreign", &config, vec![pending(5, false)], None);
+        assert_eq!(1, replacements.len());
+        assert_eq!(Some(5.into()), replacements[0].request.nonce);
+    }
+}
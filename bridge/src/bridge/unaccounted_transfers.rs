@@ -0,0 +1,258 @@
+/// detection of ether that reaches `HomeBridge` without going through `deposit`.
+/// plain transfers call the fallback function of `HomeBridge` which emits `Deposit`.
+/// ether sent via `selfdestruct` of another contract or as block reward
+/// bypasses it. no authority ever relays it so it vanishes from the accounting of the bridge.
+/// such transfers are found via parity's `trace_filter` (requires `--tracing on`),
+/// logged and appended to the audit log from which they can be refunded.
+
+use std::cmp;
+use std::path::PathBuf;
+use std::sync::Arc;
+use futures::{Async, Future, Poll, Stream};
+use tokio_timer::{Interval, Timeout};
+use web3::Transport;
+use web3::types::{Address, H256, U256};
+use api::{self, ApiCall, Trace};
+use app::App;
+use audit;
+use database::Database;
+use error::Error;
+
+/// ether that reached `HomeBridge` without a `Deposit`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UnaccountedTransfer {
+    /// `suicide` or `reward`
+    pub kind: String,
+    /// contract that self-destructed. `None` for block rewards
+    pub sender: Option<Address>,
+    pub value: U256,
+    pub block_number: u64,
+    /// `None` for block rewards
+    pub transaction_hash: Option<H256>,
+}
+
+/// returns the transfer of `trace` to `contract` if it bypassed `deposit`
+pub fn unaccounted_transfer(trace: Trace, contract: Address) -> Option<UnaccountedTransfer> {
+    if trace.error.is_some() {
+        return None;
+    }
+    let (sender, value) = match trace.trace_type.as_str() {
+        "suicide" if trace.action.refund_address == Some(contract) => {
+            (trace.action.address, trace.action.balance)
+        }
+        "reward" if trace.action.author == Some(contract) => (None, trace.action.value),
+        // calls with value go through the fallback function which emits `Deposit`
+        _ => return None,
+    };
+    match value {
+        Some(value) if !value.is_zero() => Some(UnaccountedTransfer {
+            kind: trace.trace_type,
+            sender,
+            value,
+            block_number: trace.block_number,
+            transaction_hash: trace.transaction_hash,
+        }),
+        _ => None,
+    }
+}
+
+/// State of unaccounted transfers detection.
+enum UnaccountedTransfersState<T: Transport> {
+    /// Waiting for timer to poll.
+    Wait,
+    /// Fetching best block number.
+    FetchBlockNumber(Timeout<ApiCall<U256, T::Out>>),
+    /// Fetching traces of transfers to `HomeBridge` up to block `to`.
+    FetchTraces {
+        future: Timeout<ApiCall<Vec<Trace>, T::Out>>,
+        to: u64,
+    },
+    /// All blocks till given block have been checked.
+    Yield(Option<u64>),
+}
+
+pub fn create_unaccounted_transfers<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    init: &Database,
+) -> UnaccountedTransfers<T> {
+    UnaccountedTransfers {
+        interval: app.timer.interval(app.config.home.poll_interval),
+        after: cmp::max(init.checked_unaccounted_transfers, init.home_deploy),
+        home_contract: init.home_contract_address,
+        audit_log: app.config
+            .unaccounted_transfers
+            .as_ref()
+            .and_then(|config| config.audit_log.clone()),
+        state: UnaccountedTransfersState::Wait,
+        app,
+    }
+}
+
+/// stream of the numbers of blocks on `home` that have been checked for unaccounted transfers
+pub struct UnaccountedTransfers<T: Transport> {
+    app: Arc<App<T>>,
+    interval: Interval,
+    after: u64,
+    home_contract: Address,
+    audit_log: Option<PathBuf>,
+    state: UnaccountedTransfersState<T>,
+}
+
+impl<T: Transport> Stream for UnaccountedTransfers<T> {
+    type Item = u64;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                UnaccountedTransfersState::Wait => {
+                    let _ = try_stream!(self.interval.poll());
+                    UnaccountedTransfersState::FetchBlockNumber(self.app.timer.timeout(
+                        api::block_number(&self.app.connections.home),
+                        self.app.config.home.request_timeout,
+                    ))
+                }
+                UnaccountedTransfersState::FetchBlockNumber(ref mut future) => {
+                    let last_block = try_ready!(future.poll()).low_u64();
+                    let last_confirmed_block = last_block
+                        .saturating_sub(self.app.config.home.required_confirmations as u64);
+                    if last_confirmed_block > self.after {
+                        UnaccountedTransfersState::FetchTraces {
+                            future: self.app.timer.timeout(
+                                api::trace_filter(
+                                    &self.app.connections.home,
+                                    self.after + 1,
+                                    last_confirmed_block,
+                                    self.home_contract,
+                                ),
+                                self.app.config.home.request_timeout,
+                            ),
+                            to: last_confirmed_block,
+                        }
+                    } else {
+                        UnaccountedTransfersState::Wait
+                    }
+                }
+                UnaccountedTransfersState::FetchTraces { ref mut future, to } => {
+                    let traces = try_ready!(future.poll());
+                    for transfer in traces
+                        .into_iter()
+                        .filter_map(|trace| unaccounted_transfer(trace, self.home_contract))
+                    {
+                        warn!(
+                            "{} wei reached HomeBridge without a deposit ({} from {:?} in block {}, transaction {:?}). it won't be relayed",
+                            transfer.value,
+                            transfer.kind,
+                            transfer.sender,
+                            transfer.block_number,
+                            transfer.transaction_hash
+                        );
+                        if let Some(ref path) = self.audit_log {
+                            audit::append(path, &transfer)?;
+                        }
+                    }
+                    self.after = to;
+                    UnaccountedTransfersState::Yield(Some(to))
+                }
+                UnaccountedTransfersState::Yield(ref mut block) => match block.take() {
+                    None => UnaccountedTransfersState::Wait,
+                    some => return Ok(some.into()),
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::{Trace, TraceAction};
+    use super::{unaccounted_transfer, UnaccountedTransfer};
+
+    fn trace(trace_type: &str, action: TraceAction) -> Trace {
+        Trace {
+            action,
+            trace_type: trace_type.into(),
+            block_number: 10,
+            transaction_hash: Some(5.into()),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_unaccounted_transfer_suicide() {
+        let suicide = trace(
+            "suicide",
+            TraceAction {
+                address: Some(2.into()),
+                refund_address: Some(1.into()),
+                balance: Some(100.into()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            Some(UnaccountedTransfer {
+                kind: "suicide".into(),
+                sender: Some(2.into()),
+                value: 100.into(),
+                block_number: 10,
+                transaction_hash: Some(5.into()),
+            }),
+            unaccounted_transfer(suicide, 1.into())
+        );
+    }
+
+    #[test]
+    fn test_unaccounted_transfer_reward() {
+        let reward = trace(
+            "reward",
+            TraceAction {
+                author: Some(1.into()),
+                value: Some(100.into()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            Some(100.into()),
+            unaccounted_transfer(reward, 1.into()).map(|transfer| transfer.value)
+        );
+    }
+
+    #[test]
+    fn test_unaccounted_transfer_ignores_deposits() {
+        let deposit = trace(
+            "call",
+            TraceAction {
+                from: Some(2.into()),
+                to: Some(1.into()),
+                value: Some(100.into()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(None, unaccounted_transfer(deposit, 1.into()));
+    }
+
+    #[test]
+    fn test_unaccounted_transfer_ignores_failed_and_empty() {
+        let mut failed = trace(
+            "suicide",
+            TraceAction {
+                refund_address: Some(1.into()),
+                balance: Some(100.into()),
+                ..Default::default()
+            },
+        );
+        failed.error = Some("Reverted".into());
+        assert_eq!(None, unaccounted_transfer(failed, 1.into()));
+
+        let empty = trace(
+            "suicide",
+            TraceAction {
+                refund_address: Some(1.into()),
+                balance: Some(0.into()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(None, unaccounted_transfer(empty, 1.into()));
+    }
+}
@@ -1,33 +1,82 @@
 use std::sync::Arc;
 use std::ops;
-use futures::{Future, Poll, Stream};
+use std::time::SystemTime;
+use futures::{Async, Future, Poll, Stream};
 use futures::future::{join_all, JoinAll};
-use tokio_timer::Timeout;
 use web3::Transport;
-use web3::types::{Address, Bytes, FilterBuilder, H256, H520, TransactionRequest};
-use api::{self, ApiCall, LogStream};
+use web3::types::{Address, Bytes, FilterBuilder, H256, H520, Log, TransactionRequest, U256};
+use ethabi::RawLog;
+use api::{self, LogStream};
 use app::App;
+use audit::{self, SignatureRecord};
+use control::{self, pausable, Direction, Pausable, Pause};
+use maintenance::{deferred, Deferred};
 use contracts::foreign;
+#[cfg(feature = "contracts-v2")]
+use contracts::foreign_token;
 use util::web3_filter;
 use database::Database;
+use dry_run;
 use error::Error;
-use message_to_mainnet::{MessageToMainnet, MESSAGE_LENGTH};
+use event_queue::TransferEvent;
+use history::TransferDirection;
+use logging::{RelayEvent, RelayStage};
+use message_to_mainnet::{MessageToMainnet, TokenMessageToMainnet, MESSAGE_LENGTH,
+                         TOKEN_MESSAGE_LENGTH};
+use metrics;
+use otlp;
+use shard::ShardedStream;
+use signer::{self, Chain, FailoverSignature, SendTransaction};
+use sinks;
+use status;
+use value_limits::{limited, Limited};
+use super::signature_verification::{verify_signature, Verification, VerifySignature};
 
 fn withdraws_filter(foreign: &foreign::ForeignBridge, address: Address) -> FilterBuilder {
     let filter = foreign.events().withdraw().create_filter();
     web3_filter(filter, address)
 }
 
+#[cfg(feature = "contracts-v2")]
+fn token_withdraws_filter(address: Address) -> FilterBuilder {
+    let foreign = foreign_token::ForeignTokenBridge::default();
+    let filter = foreign.events().transfer_to_home().create_filter();
+    web3_filter(filter, address)
+}
+
+/// returns the value of the withdraw in `log` for the value limits
+fn withdraw_value(log: &Log) -> Result<U256, Error> {
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let foreign = foreign::ForeignBridge::default();
+    Ok(foreign.events().withdraw().parse_log(raw_log)?.value)
+}
+
+/// returns the value of the transfer of tokens in `log` for the value limits
+#[cfg(feature = "contracts-v2")]
+fn token_withdraw_value(log: &Log) -> Result<U256, Error> {
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let foreign = foreign_token::ForeignTokenBridge::default();
+    Ok(foreign.events().transfer_to_home().parse_log(raw_log)?.value)
+}
+
+/// `ForeignTokenBridge.submitSignature` shares the ABI of `ForeignBridge.submitSignature`
+/// but takes token messages of `TOKEN_MESSAGE_LENGTH` bytes
 fn withdraw_submit_signature_payload(
     foreign: &foreign::ForeignBridge,
     withdraw_message: Vec<u8>,
     signature: H520,
 ) -> Bytes {
-    assert_eq!(
-        withdraw_message.len(),
+    assert!(
+        withdraw_message.len() == MESSAGE_LENGTH || withdraw_message.len() == TOKEN_MESSAGE_LENGTH,
+        "ForeignBridge never accepts messages with len != {} bytes and ForeignTokenBridge never accepts messages with len != {} bytes; qed",
         MESSAGE_LENGTH,
-        "ForeignBridge never accepts messages with len != {} bytes; qed",
-        MESSAGE_LENGTH
+        TOKEN_MESSAGE_LENGTH
     );
     foreign
         .functions()
@@ -36,6 +85,31 @@ fn withdraw_submit_signature_payload(
         .into()
 }
 
+/// appends the signatures in `signed` to the signature audit log if one is configured.
+/// `hashes` are the hashes of the transactions that submitted them
+fn audit_signatures<T: Transport>(
+    app: &App<T>,
+    signed: &[(Vec<u8>, Address, H520)],
+    hashes: &[Option<H256>],
+) -> Result<(), Error> {
+    let path = match app.config.signature_audit_log {
+        Some(ref path) => path,
+        None => return Ok(()),
+    };
+    let backend = signer::signing_backend(&app.config);
+    for (&(ref message, account, signature), hash) in signed.iter().zip(hashes) {
+        let record = SignatureRecord::new(
+            &MessageToMainnet::from_bytes(message),
+            account,
+            backend.clone(),
+            signature,
+            *hash,
+        );
+        audit::append(path, &record)?;
+    }
+    Ok(())
+}
+
 /// State of withdraw confirmation.
 enum WithdrawConfirmState<T: Transport> {
     /// Withdraw confirm is waiting for logs.
@@ -43,12 +117,22 @@ enum WithdrawConfirmState<T: Transport> {
     /// Signing withdraws.
     SignWithdraws {
         messages: Vec<Vec<u8>>,
-        future: JoinAll<Vec<Timeout<ApiCall<H520, T::Out>>>>,
+        future: JoinAll<Vec<FailoverSignature<T>>>,
         block: u64,
+        /// when signing started
+        started: SystemTime,
     },
     /// Confirming withdraws.
     ConfirmWithdraws {
-        future: JoinAll<Vec<Timeout<ApiCall<H256, T::Out>>>>,
+        future: JoinAll<Vec<SendTransaction<T>>>,
+        /// messages, the accounts that signed them and their signatures
+        signed: Vec<(Vec<u8>, Address, H520)>,
+        block: u64,
+        started: SystemTime,
+    },
+    /// Reading the submitted signatures back from `ForeignBridge`.
+    VerifySignatures {
+        future: JoinAll<Vec<VerifySignature<T>>>,
         block: u64,
     },
     /// All withdraws till given block has been confirmed.
@@ -64,15 +148,57 @@ pub fn create_withdraw_confirm<T: Transport + Clone>(
         request_timeout: app.config.foreign.request_timeout,
         poll_interval: app.config.foreign.poll_interval,
         confirmations: app.config.foreign.required_confirmations,
-        filter: withdraws_filter(&app.foreign_bridge, init.foreign_contract_address.clone()),
+        strategy: app.config.foreign.confirmation_strategy,
+        filter: match app.config.tokens() {
+            #[cfg(feature = "contracts-v2")]
+            Some(_) => token_withdraws_filter(init.foreign_contract_address.clone()),
+            _ => withdraws_filter(&app.foreign_bridge, init.foreign_contract_address.clone()),
+        },
     };
+    app.checkpoints
+        .withdraw_confirm
+        .seed(init.checked_withdraw_confirm, init.checked_withdraw_confirm_hash);
+    app.value_limits.withdraws.seed(init.withdraw_value_window);
 
     WithdrawConfirm {
-        logs: api::log_stream(
-            app.connections.foreign.clone(),
-            app.timer.clone(),
-            logs_init,
+        logs: limited(
+            deferred(
+                pausable(
+                    api::log_stream(
+                        app.connections.foreign.clone(),
+                        app.timer.clone(),
+                        logs_init,
+                    ).following(&app.heads.foreign)
+                        .rewinding(&app.checkpoints.withdraw_confirm)
+                        .sharded(&app.leases, ShardedStream::WithdrawConfirm)
+                        .retrying(&app.config.foreign.retry),
+                    Pause {
+                        insolvency: app.config.solvency_check,
+                        halted: true,
+                        ..app.config.pause(Direction::Withdraws)
+                    },
+                    &app.timer,
+                    app.config.foreign.poll_interval,
+                ),
+                "foreign",
+                app.config.foreign.maintenance_windows.clone(),
+                app.config.clock.skew_tolerance,
+                &app.timer,
+                app.config.foreign.poll_interval,
+            ),
+            Direction::Withdraws,
+            app.value_limits.withdraws.clone(),
+            match app.config.tokens() {
+                #[cfg(feature = "contracts-v2")]
+                Some(_) => token_withdraw_value,
+                _ => withdraw_value,
+            },
+            app.leases.clone(),
+            ShardedStream::WithdrawConfirm,
+            &app.timer,
+            app.config.foreign.poll_interval,
         ),
+        home_contract: init.home_contract_address,
         foreign_contract: init.foreign_contract_address,
         state: WithdrawConfirmState::Wait,
         app,
@@ -81,12 +207,23 @@ pub fn create_withdraw_confirm<T: Transport + Clone>(
 
 pub struct WithdrawConfirm<T: Transport> {
     app: Arc<App<T>>,
-    logs: LogStream<T>,
+    logs: Limited<Deferred<Pausable<LogStream<T>>>>,
     state: WithdrawConfirmState<T>,
+    home_contract: Address,
     foreign_contract: Address,
 }
 
-impl<T: Transport> Stream for WithdrawConfirm<T> {
+impl<T: Transport> WithdrawConfirm<T> {
+    /// whether no signatures of withdraws are in progress. checked before the bridge shuts down
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            WithdrawConfirmState::Wait | WithdrawConfirmState::Yield(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for WithdrawConfirm<T> {
     type Item = u64;
     type Error = Error;
 
@@ -94,63 +231,81 @@ impl<T: Transport> Stream for WithdrawConfirm<T> {
         loop {
             let next_state = match self.state {
                 WithdrawConfirmState::Wait => {
-                    let item = try_stream!(self.logs.poll());
+                    let mut item = try_stream!(self.logs.poll());
+                    item.logs
+                        .retain(|log| self.app.leases.handles(ShardedStream::WithdrawConfirm, log));
                     info!("got {} new withdraws to sign", item.logs.len());
+                    let tokens = self.app.config.tokens();
+                    let chain_ids = self.app.config.message_chain_ids();
                     let withdraw_messages = item.logs
                         .into_iter()
                         .map(|log| {
-                            info!(
-                                "withdraw is ready for signature submission. tx hash {}",
-                                log.transaction_hash.unwrap()
-                            );
-                            Ok(MessageToMainnet::from_log(log)?.to_bytes())
+                            let hash = log.transaction_hash.ok_or_else(|| {
+                                "`log` must be mined and contain `transaction_hash`"
+                            })?;
+                            info!("withdraw is ready for signature submission. tx hash {}", hash);
+                            RelayEvent::new(TransferDirection::Withdraw, RelayStage::Detected, hash)
+                                .log();
+                            Ok(match tokens {
+                                #[cfg(feature = "contracts-v2")]
+                                Some(ref tokens) => TokenMessageToMainnet::from_log(
+                                    log,
+                                    tokens,
+                                    self.home_contract,
+                                    chain_ids,
+                                )?.to_bytes(),
+                                _ => {
+                                    MessageToMainnet::from_log(log, self.home_contract, chain_ids)?
+                                        .to_bytes()
+                                }
+                            })
                         })
                         .collect::<Result<Vec<_>, Error>>()?;
 
                     let requests = withdraw_messages
                         .clone()
                         .into_iter()
-                        .map(|message| {
-                            self.app.timer.timeout(
-                                api::sign(
-                                    &self.app.connections.foreign,
-                                    self.app.config.foreign.account,
-                                    Bytes(message),
-                                ),
-                                self.app.config.foreign.request_timeout,
-                            )
-                        })
+                        .map(|message| signer::sign_with_failover(self.app.clone(), Bytes(message)))
                         .collect::<Vec<_>>();
 
+                    status::set_queue("withdraw_confirm", withdraw_messages.len());
                     info!("signing");
                     WithdrawConfirmState::SignWithdraws {
                         future: join_all(requests),
                         messages: withdraw_messages,
                         block: item.to,
+                        started: SystemTime::now(),
                     }
                 }
                 WithdrawConfirmState::SignWithdraws {
                     ref mut future,
                     ref mut messages,
                     block,
+                    started,
                 } => {
                     let signatures = try_ready!(future.poll());
                     info!("signing complete");
                     // borrow checker...
                     let app = &self.app;
                     let foreign_contract = &self.foreign_contract;
-                    let confirmations = messages
+                    let signed = messages
                         .drain(ops::RangeFull)
                         .zip(signatures.into_iter())
-                        .map(|(withdraw_message, signature)| {
-                            withdraw_submit_signature_payload(
+                        .map(|(message, (account, signature))| (message, account, signature))
+                        .collect::<Vec<_>>();
+                    let confirmations = signed
+                        .iter()
+                        .map(|&(ref withdraw_message, account, signature)| {
+                            let payload = withdraw_submit_signature_payload(
                                 &app.foreign_bridge,
-                                withdraw_message,
+                                withdraw_message.clone(),
                                 signature,
-                            )
+                            );
+                            (account, payload)
                         })
-                        .map(|payload| TransactionRequest {
-                            from: app.config.foreign.account,
+                        .map(|(account, payload)| TransactionRequest {
+                            // `submitSignature` requires the sender to be the signer
+                            from: account,
                             to: Some(foreign_contract.clone()),
                             gas: Some(app.config.txs.withdraw_confirm.gas.into()),
                             gas_price: Some(app.config.txs.withdraw_confirm.gas_price.into()),
@@ -161,25 +316,117 @@ impl<T: Transport> Stream for WithdrawConfirm<T> {
                         })
                         .map(|request| {
                             info!("submitting signature");
-                            app.timer.timeout(
-                                api::send_transaction(&app.connections.foreign, request),
-                                app.config.foreign.request_timeout,
+                            signer::send_transaction(
+                                app,
+                                Chain::Foreign,
+                                request,
+                                app.config.txs.withdraw_confirm.dynamic_fees,
                             )
                         })
-                        .collect::<Vec<_>>();
+                        .collect::<Result<Vec<_>, Error>>()?;
 
                     info!("submitting {} signatures", confirmations.len());
                     WithdrawConfirmState::ConfirmWithdraws {
                         future: join_all(confirmations),
+                        signed,
                         block,
+                        started,
                     }
                 }
                 WithdrawConfirmState::ConfirmWithdraws {
                     ref mut future,
+                    ref signed,
                     block,
+                    started,
                 } => {
-                    let _ = try_ready!(future.poll());
+                    let hashes = match future.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        // nothing was submitted in a dry run
+                        Ok(Async::Ready(_)) if dry_run::is_enabled() => vec![None; signed.len()],
+                        Ok(Async::Ready(hashes)) => {
+                            hashes.into_iter().map(Some).collect::<Vec<_>>()
+                        }
+                        Err(err) => {
+                            // the signatures were produced even though submitting them failed
+                            audit_signatures(&self.app, signed, &vec![None; signed.len()])?;
+                            return Err(err.into());
+                        }
+                    };
+                    audit_signatures(&self.app, signed, &hashes)?;
+                    for (&(ref message, _, _), hash) in signed.iter().zip(&hashes) {
+                        if let Some(hash) = *hash {
+                            RelayEvent::new(
+                                TransferDirection::Withdraw,
+                                RelayStage::Signed,
+                                MessageToMainnet::from_bytes(message).sidenet_transaction_hash,
+                            ).transaction(hash)
+                                .log();
+                        }
+                    }
+                    let submitted = hashes.iter().filter(|hash| hash.is_some()).count();
+                    metrics::record_relays(|relays| relays.signatures += submitted as u64);
+                    let events = signed
+                        .iter()
+                        .zip(&hashes)
+                        .filter_map(|(&(ref message, _, _), hash)| {
+                            hash.map(|hash| {
+                                TransferEvent::signed(&MessageToMainnet::from_bytes(message), hash)
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    sinks::record(&self.app, &[], &events)?;
+                    otlp::signatures(&events, started);
+                    status::set_queue("withdraw_confirm", 0);
                     info!("submitting signatures complete");
+                    if !self.app.config.verify_signatures || dry_run::is_enabled() {
+                        WithdrawConfirmState::Yield(Some(block))
+                    } else {
+                        let app = &self.app;
+                        let foreign_contract = self.foreign_contract;
+                        let verifications = signed
+                            .iter()
+                            .zip(hashes)
+                            .map(|(&(ref message, account, signature), hash)| {
+                                verify_signature(
+                                    app.clone(),
+                                    foreign_contract,
+                                    message.clone(),
+                                    account,
+                                    signature,
+                                    hash.expect("all signatures were submitted; qed"),
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        info!("verifying {} signatures", verifications.len());
+                        WithdrawConfirmState::VerifySignatures {
+                            future: join_all(verifications),
+                            block,
+                        }
+                    }
+                }
+                WithdrawConfirmState::VerifySignatures {
+                    ref mut future,
+                    block,
+                } => {
+                    let verifications = try_ready!(future.poll());
+                    for verification in verifications {
+                        match verification {
+                            Verification::Verified => {}
+                            Verification::Unmined => {
+                                warn!("signature submission wasn't mined in time. it can't be verified")
+                            }
+                            Verification::Mismatch(mismatch) => {
+                                let message = format!(
+                                    "verification of a submitted signature failed: {}. halting signing of withdraws until restart",
+                                    mismatch
+                                );
+                                error!("{}", message);
+                                status::record_error(message);
+                                control::halt_signing();
+                            }
+                        }
+                    }
+                    info!("verifying signatures complete");
                     WithdrawConfirmState::Yield(Some(block))
                 }
                 WithdrawConfirmState::Yield(ref mut block) => match block.take() {
@@ -1,18 +1,34 @@
+use std::mem;
 use std::sync::Arc;
-use futures::{Future, Poll, Stream};
-use futures::future::{join_all, Join, JoinAll};
+use futures::{Async, Future, Poll, Stream};
+use futures::future::{join_all, Join, Join4, JoinAll};
 use tokio_timer::Timeout;
 use web3::Transport;
-use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest};
-use ethabi::{self, RawLog};
+use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest, U256};
+use ethabi::RawLog;
 use app::App;
+use control::{pausable, Direction, Pausable, Pause};
+use maintenance::{deferred, Deferred};
 use api::{self, ApiCall, LogStream};
+use clock;
+use config::Tokens;
 use contracts::foreign;
 use util::web3_filter;
 use database::Database;
 use error::{self, Error};
-use message_to_mainnet::MessageToMainnet;
-use signature::Signature;
+use event_queue::TransferEvent;
+use gas_price::{gas_price, GasPrice};
+use history::{self, BlockTimestamps, Transfer, TransferDirection};
+use logging::{log_relay, RelayEvent, RelayStage};
+use metrics;
+use parked::{ParkReason, ParkedRelay};
+use sinks;
+use status;
+use message_to_mainnet::{MessageToMainnet, TokenMessageToMainnet};
+use shard::ShardedStream;
+use signature::{verify_signatures, Signature};
+use signer::{remote_signer, Chain};
+use transaction::{is_rpc_error, relay_transaction, RelayTransaction};
 
 /// returns a filter for `ForeignBridge.CollectedSignatures` events
 fn collected_signatures_filter(
@@ -39,6 +55,8 @@ fn signatures_payload(
     my_address: Address,
     log: Log,
 ) -> error::Result<Option<RelayAssignment>> {
+    let hash = log.transaction_hash
+        .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
     // convert web3::Log to ethabi::RawLog since ethabi events can
     // only be parsed from the latter
     let raw_log = RawLog {
@@ -49,7 +67,7 @@ fn signatures_payload(
     if collected_signatures.authority_responsible_for_relay != my_address.0.into() {
         info!(
             "bridge not responsible for relaying transaction to home. tx hash: {}",
-            log.transaction_hash.unwrap()
+            hash
         );
         // this authority is not responsible for relaying this transaction.
         // someone else will relay this transaction to home.
@@ -77,18 +95,316 @@ fn signatures_payload(
     }))
 }
 
+/// relay cost `HomeBridge.withdraw` pays out of the value of a withdraw to the relaying authority.
+/// `None` on overflow
+fn withdraw_fee(estimated_gas_cost_of_withdraw: U256, gas_price: U256) -> Option<U256> {
+    let (fee, overflow) = estimated_gas_cost_of_withdraw.overflowing_mul(gas_price);
+    if overflow {
+        None
+    } else {
+        Some(fee)
+    }
+}
+
+/// decodes the outputs of `ForeignBridge.message` and `ForeignBridge.signature`
+/// of a withdraw into the message and the signatures to relay.
+/// with `tokens` the message must be a `TokenMessageToMainnet` of `tokens.home`.
+/// fails if any of them is malformed
+fn decode_withdraw(
+    foreign: &foreign::ForeignBridge,
+    tokens: Option<&Tokens>,
+    message_output: &Bytes,
+    signature_outputs: &[Bytes],
+) -> error::Result<(Bytes, MessageToMainnet, Vec<Signature>)> {
+    let message = foreign
+        .functions()
+        .message()
+        .output(message_output.0.as_slice())?;
+    let message_to_mainnet = match tokens {
+        Some(tokens) => {
+            let token_message = TokenMessageToMainnet::try_from_bytes(&message)?;
+            if token_message.token != tokens.home {
+                bail!(
+                    "message withdraws token {:?} but `home.token_address` is {:?}",
+                    token_message.token,
+                    tokens.home
+                );
+            }
+            token_message.message
+        }
+        None => MessageToMainnet::try_from_bytes(&message)?,
+    };
+    let signatures = signature_outputs
+        .iter()
+        .map(|output| {
+            let signature = foreign.functions().signature().output(output.0.as_slice())?;
+            Signature::try_from_bytes(&signature)
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+    Ok((Bytes(message), message_to_mainnet, signatures))
+}
+
+/// future that reads `HomeBridge.authorities` index by index until a call reverts.
+/// resolves to the authorities whose signatures `HomeBridge.withdraw` accepts
+pub struct FetchAuthorities<T: Transport> {
+    app: Arc<App<T>>,
+    home_contract: Address,
+    authorities: Vec<Address>,
+    /// call for the next authority. `None` if the authorities aren't needed
+    future: Option<Timeout<ApiCall<Bytes, T::Out>>>,
+}
+
+impl<T: Transport> FetchAuthorities<T> {
+    pub fn new(app: Arc<App<T>>, home_contract: Address) -> Self {
+        let mut fetch = FetchAuthorities {
+            app,
+            home_contract,
+            authorities: Vec::new(),
+            future: None,
+        };
+        fetch.future = Some(fetch.call(0));
+        fetch
+    }
+
+    /// resolves to no authorities right away
+    pub fn skip(app: Arc<App<T>>, home_contract: Address) -> Self {
+        FetchAuthorities {
+            app,
+            home_contract,
+            authorities: Vec::new(),
+            future: None,
+        }
+    }
+
+    fn call(&self, index: usize) -> Timeout<ApiCall<Bytes, T::Out>> {
+        let payload = self.app
+            .home_bridge
+            .functions()
+            .authorities()
+            .input(index as u64);
+        self.app.timer.timeout(
+            api::call(&self.app.connections.home, self.home_contract, payload.into()),
+            self.app.config.home.request_timeout,
+        )
+    }
+}
+
+impl<T: Transport> Future for FetchAuthorities<T> {
+    type Item = Vec<Address>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let output = match self.future {
+                None => return Ok(Async::Ready(mem::replace(&mut self.authorities, Vec::new()))),
+                Some(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(output)) => Some(output),
+                    // reading past the last authority reverts
+                    Err(ref err) if is_rpc_error(err) => None,
+                    Err(err) => return Err(err),
+                },
+            };
+            let authority = match output {
+                Some(ref output) if !output.0.is_empty() => self.app
+                    .home_bridge
+                    .functions()
+                    .authorities()
+                    .output(output.0.as_slice())
+                    .ok(),
+                _ => None,
+            };
+            match authority {
+                Some(authority) => {
+                    self.authorities.push(Address::from(authority.0));
+                    self.future = Some(self.call(self.authorities.len()));
+                }
+                None if self.authorities.is_empty() => bail!(
+                    "Cannot read the authorities of HomeBridge at {:?}",
+                    self.home_contract
+                ),
+                None => self.future = None,
+            }
+        }
+    }
+}
+
+/// returns the relay of the withdraw `request` to `HomeBridge`.
+/// journaled as relay of the events up to `block` if it is set
+pub fn withdraw_transaction<T: Transport + Clone>(
+    app: &App<T>,
+    request: TransactionRequest,
+    block: Option<u64>,
+) -> RelayTransaction<T> {
+    let relay = relay_transaction(
+        app.connections.home.clone(),
+        app.timer.clone(),
+        &app.config.home,
+        request,
+        app.config
+            .home
+            .access_list_mode(&app.config.txs.withdraw_relay),
+    ).via_signer(remote_signer(&app.config), Chain::Home)
+        .via_keystore(app.keystore.clone(), Chain::Home)
+        .with_nonces(app.nonces.get(Chain::Home))
+        .with_fixed_gas_price();
+    match block {
+        Some(block) => relay.with_journal(app.journal.clone(), Chain::Home, block),
+        None => relay,
+    }
+}
+
+/// outcome of the relay of a withdraw
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WithdrawOutcome {
+    /// relayed by the transaction
+    Relayed(H256),
+    /// parked until the balance of `HomeBridge` covers it (see `parked`)
+    Parked,
+    /// not relayed because the simulation of the relay reverted.
+    /// parked for retries (see `parked`)
+    Reverted,
+}
+
+impl WithdrawOutcome {
+    /// transaction that relayed the withdraw
+    fn hash(&self) -> Option<H256> {
+        match *self {
+            WithdrawOutcome::Relayed(hash) => Some(hash),
+            WithdrawOutcome::Parked | WithdrawOutcome::Reverted => None,
+        }
+    }
+}
+
+/// state of the relay of a single withdraw
+enum RelayWithdrawState<T: Transport> {
+    /// relaying the withdraw
+    Relay(RelayTransaction<T>),
+    /// fetching the balance of `HomeBridge`
+    FetchBalance(Timeout<ApiCall<U256, T::Out>>),
+}
+
+fn relay_withdraw<T: Transport + Clone>(
+    app: Arc<App<T>>,
+    home_contract: Address,
+    request: TransactionRequest,
+    origin: H256,
+    value: U256,
+    block: u64,
+) -> RelayWithdraw<T> {
+    RelayWithdraw {
+        state: RelayWithdrawState::Relay(withdraw_transaction(
+            &app,
+            request.clone(),
+            Some(block),
+        )),
+        app,
+        home_contract,
+        request,
+        origin,
+        value,
+    }
+}
+
+/// future that relays a withdraw of `value` to `HomeBridge`.
+/// `HomeBridge.withdraw` reverts while the balance of `HomeBridge` doesn't cover `value`.
+/// such withdraws are not dropped but parked (see `parked`) so that the batch completes
+/// and relayed again by `bridge::ParkedRelays` once the balance covers them.
+/// resolves to `Reverted` if the withdraw reverted for any other reason
+/// (example: another authority already relayed it) or if it was a withdraw of tokens.
+/// such withdraws are parked too and retried every `reverted_relays.retry_interval`.
+pub struct RelayWithdraw<T: Transport> {
+    app: Arc<App<T>>,
+    home_contract: Address,
+    request: TransactionRequest,
+    /// transaction of the withdraw on foreign
+    origin: H256,
+    value: U256,
+    state: RelayWithdrawState<T>,
+}
+
+impl<T: Transport> RelayWithdraw<T> {
+    /// parks the withdraw whose relay reverted for retries
+    fn park_reverted(&self) -> WithdrawOutcome {
+        self.app.parked.park(ParkedRelay::reverted(
+            Chain::Home,
+            self.origin,
+            self.value,
+            &self.request,
+            status::now(),
+            self.app.config.reverted_relays.retry_interval.as_secs(),
+        ));
+        WithdrawOutcome::Reverted
+    }
+}
+
+impl<T: Transport + Clone> Future for RelayWithdraw<T> {
+    type Item = WithdrawOutcome;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                RelayWithdrawState::Relay(ref mut future) => match try_ready!(future.poll()) {
+                    Some(hash) => return Ok(Async::Ready(WithdrawOutcome::Relayed(hash))),
+                    // the ether balance of `HomeTokenBridge` doesn't cover token withdraws
+                    None if self.app.config.tokens().is_some() => {
+                        return Ok(Async::Ready(self.park_reverted()))
+                    }
+                    None => RelayWithdrawState::FetchBalance(self.app.timer.timeout(
+                        api::balance(&self.app.connections.home, self.home_contract),
+                        self.app.config.home.request_timeout,
+                    )),
+                },
+                RelayWithdrawState::FetchBalance(ref mut future) => {
+                    let balance = try_ready!(future.poll());
+                    if balance >= self.value {
+                        return Ok(Async::Ready(self.park_reverted()));
+                    }
+                    warn!(
+                        "balance of HomeBridge ({} wei) is insufficient to relay withdraw {:?} of {} wei. parking it until the balance covers it",
+                        balance, self.origin, self.value
+                    );
+                    self.app.parked.park(ParkedRelay::new(
+                        Chain::Home,
+                        self.origin,
+                        self.value,
+                        ParkReason::InsufficientBalance,
+                        &self.request,
+                    ));
+                    RelayEvent::new(TransferDirection::Withdraw, RelayStage::Skipped, self.origin)
+                        .reason("parked until the balance of HomeBridge covers it")
+                        .log();
+                    return Ok(Async::Ready(WithdrawOutcome::Parked));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
 /// state of the withdraw relay state machine
 pub enum WithdrawRelayState<T: Transport> {
     Wait,
     FetchMessagesSignatures {
-        future: Join<
+        /// messages, signatures, the current gas price on `home`
+        /// and the authorities of `HomeBridge`
+        future: Join4<
             JoinAll<Vec<Timeout<ApiCall<Bytes, T::Out>>>>,
             JoinAll<Vec<JoinAll<Vec<Timeout<ApiCall<Bytes, T::Out>>>>>>,
+            GasPrice<T>,
+            FetchAuthorities<T>,
         >,
+        /// blocks of the `CollectedSignatures` logs of the messages
+        log_blocks: Vec<U256>,
         block: u64,
     },
     RelayWithdraws {
-        future: JoinAll<Vec<Timeout<ApiCall<H256, T::Out>>>>,
+        /// relays and entries of the relay history for the withdraws with the timestamps
+        /// of their blocks. no entries if the history is disabled
+        future: Join<JoinAll<Vec<RelayWithdraw<T>>>, BlockTimestamps<T>>,
+        /// transactions of the withdraws in the order of the relays
+        origins: Vec<H256>,
         block: u64,
     },
     Yield(Option<u64>),
@@ -103,14 +419,36 @@ pub fn create_withdraw_relay<T: Transport + Clone>(
         request_timeout: app.config.foreign.request_timeout,
         poll_interval: app.config.foreign.poll_interval,
         confirmations: app.config.foreign.required_confirmations,
+        strategy: app.config.foreign.confirmation_strategy,
         filter: collected_signatures_filter(&app.foreign_bridge, init.foreign_contract_address),
     };
+    app.checkpoints
+        .withdraw_relay
+        .seed(init.checked_withdraw_relay, init.checked_withdraw_relay_hash);
 
     WithdrawRelay {
-        logs: api::log_stream(
-            app.connections.foreign.clone(),
-            app.timer.clone(),
-            logs_init,
+        logs: deferred(
+            pausable(
+                api::log_stream(
+                    app.connections.foreign.clone(),
+                    app.timer.clone(),
+                    logs_init,
+                ).following(&app.heads.foreign)
+                    .rewinding(&app.checkpoints.withdraw_relay)
+                    .sharded(&app.leases, ShardedStream::WithdrawRelay)
+                    .retrying(&app.config.foreign.retry),
+                Pause {
+                    chain: Chain::Home,
+                    ..app.config.pause(Direction::Withdraws)
+                },
+                &app.timer,
+                app.config.foreign.poll_interval,
+            ),
+            "home",
+            app.config.home.maintenance_windows.clone(),
+            app.config.clock.skew_tolerance,
+            &app.timer,
+            app.config.home.poll_interval,
         ),
         home_contract: init.home_contract_address,
         foreign_contract: init.foreign_contract_address,
@@ -121,13 +459,23 @@ pub fn create_withdraw_relay<T: Transport + Clone>(
 
 pub struct WithdrawRelay<T: Transport> {
     app: Arc<App<T>>,
-    logs: LogStream<T>,
+    logs: Deferred<Pausable<LogStream<T>>>,
     state: WithdrawRelayState<T>,
     foreign_contract: Address,
     home_contract: Address,
 }
 
-impl<T: Transport> Stream for WithdrawRelay<T> {
+impl<T: Transport> WithdrawRelay<T> {
+    /// whether no relays of withdraws are in progress. checked before the bridge shuts down
+    pub fn is_idle(&self) -> bool {
+        match self.state {
+            WithdrawRelayState::Wait | WithdrawRelayState::Yield(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Transport + Clone> Stream for WithdrawRelay<T> {
     type Item = u64;
     type Error = Error;
 
@@ -135,28 +483,40 @@ impl<T: Transport> Stream for WithdrawRelay<T> {
         loop {
             let next_state = match self.state {
                 WithdrawRelayState::Wait => {
-                    let item = try_stream!(self.logs.poll());
+                    let mut item = try_stream!(self.logs.poll());
+                    item.logs
+                        .retain(|log| self.app.leases.handles(ShardedStream::WithdrawRelay, log));
                     info!("got {} new signed withdraws to relay", item.logs.len());
                     let assignments = item.logs
                         .into_iter()
                         .map(|log| {
-                            info!(
-                                "collected signature is ready for relay: tx hash: {}",
-                                log.transaction_hash.unwrap()
-                            );
+                            let hash = log.transaction_hash.ok_or_else(|| {
+                                "`log` must be mined and contain `transaction_hash`"
+                            })?;
+                            info!("collected signature is ready for relay: tx hash: {}", hash);
+                            let log_block = log.block_number.ok_or_else(|| {
+                                "`log` must be mined and contain `block_number`"
+                            })?;
                             signatures_payload(
                                 &self.app.foreign_bridge,
                                 self.app.config.authorities.required_signatures,
                                 self.app.config.foreign.account,
                                 log,
-                            )
+                            ).map(|assignment| assignment.map(|a| (log_block, a)))
                         })
-                        .collect::<error::Result<Vec<_>>>()?;
+                        .collect::<error::Result<Vec<_>>>()?
+                        .into_iter()
+                        .filter_map(|a| a)
+                        .collect::<Vec<_>>();
 
+                    status::set_queue("withdraw_relay", assignments.len());
+                    let log_blocks = assignments
+                        .iter()
+                        .map(|&(log_block, _)| log_block)
+                        .collect();
                     let (signatures, messages): (Vec<_>, Vec<_>) = assignments
                         .into_iter()
-                        .filter_map(|a| a)
-                        .map(|assignment| {
+                        .map(|(_, assignment)| {
                             (assignment.signature_payloads, assignment.message_payload)
                         })
                         .unzip();
@@ -195,60 +555,188 @@ impl<T: Transport> Stream for WithdrawRelay<T> {
                         .map(|calls| join_all(calls))
                         .collect::<Vec<_>>();
 
+                    // the gas price of a withdraw is set by its message.
+                    // the current one only tells whether the relay is likely to get stuck
+                    let (current_gas_price, authorities) = if message_calls.is_empty() {
+                        (
+                            GasPrice::fixed(None),
+                            FetchAuthorities::skip(self.app.clone(), self.home_contract),
+                        )
+                    } else {
+                        (
+                            gas_price(
+                                &self.app.connections.home,
+                                &self.app.timer,
+                                &self.app.config.home,
+                                &self.app.gas_price_oracles.home,
+                            ),
+                            FetchAuthorities::new(self.app.clone(), self.home_contract),
+                        )
+                    };
+
                     info!("fetching messages and signatures");
                     WithdrawRelayState::FetchMessagesSignatures {
-                        future: join_all(message_calls).join(join_all(signature_calls)),
+                        future: join_all(message_calls).join4(
+                            join_all(signature_calls),
+                            current_gas_price,
+                            authorities,
+                        ),
+                        log_blocks,
                         block: item.to,
                     }
                 }
                 WithdrawRelayState::FetchMessagesSignatures {
                     ref mut future,
+                    ref log_blocks,
                     block,
                 } => {
-                    let (messages_raw, signatures_raw) = try_ready!(future.poll());
+                    let (messages_raw, signatures_raw, current_gas_price, authorities) =
+                        try_ready!(future.poll());
                     info!("fetching messages and signatures complete");
                     assert_eq!(messages_raw.len(), signatures_raw.len());
 
                     let app = &self.app;
                     let home_contract = &self.home_contract;
+                    let tokens = app.config.tokens();
 
-                    let messages = messages_raw
-                        .iter()
-                        .map(|message| {
-                            app.foreign_bridge
-                                .functions()
-                                .message()
-                                .output(message.0.as_slice())
-                                .map(Bytes)
-                        })
-                        .collect::<ethabi::Result<Vec<_>>>()
-                        .map_err(error::Error::from)?;
-                    info!("messages decoded");
-
-                    let signatures = signatures_raw
+                    // a malformed payload skips its withdraw instead of stopping the relay
+                    let withdraws = messages_raw
                         .iter()
-                        .map(|signatures| {
-                            signatures
-                                .iter()
-                                .map(|signature| {
-                                    Signature::from_bytes(
-                                        app.foreign_bridge
-                                            .functions()
-                                            .signature()
-                                            .output(signature.0.as_slice())?
-                                            .as_slice(),
-                                    )
-                                })
-                                .collect::<Result<Vec<_>, Error>>()
-                                .map_err(error::Error::from)
+                        .zip(signatures_raw.iter())
+                        .zip(log_blocks.iter())
+                        .filter_map(|((message, signatures), &log_block)| {
+                            match decode_withdraw(
+                                &app.foreign_bridge,
+                                tokens.as_ref(),
+                                message,
+                                signatures,
+                            ) {
+                                Ok(withdraw) => {
+                                    RelayEvent::new(
+                                        TransferDirection::Withdraw,
+                                        RelayStage::Collected,
+                                        (withdraw.1).sidenet_transaction_hash,
+                                    ).log();
+                                    Some((withdraw, log_block))
+                                }
+                                Err(err) => {
+                                    warn!(
+                                        "skipping withdraw collected in block {} that can't be decoded: {}",
+                                        log_block, err
+                                    );
+                                    None
+                                }
+                            }
                         })
-                        .collect::<error::Result<Vec<_>>>()?;
-                    info!("signatures decoded");
+                        .collect::<Vec<_>>();
+                    info!("messages and signatures decoded");
 
-                    let relays = messages
+                    let mut transfers = Vec::new();
+                    let mut origins = Vec::new();
+                    let relays = withdraws
                         .into_iter()
-                        .zip(signatures.into_iter())
-                        .map(|(message, signatures)| {
+                        .filter(|&((_, ref message_to_mainnet, _), _)| {
+                            let bound_to = message_to_mainnet.mainnet_bridge_address;
+                            if bound_to != *home_contract {
+                                warn!(
+                                    "skipping withdraw message meant for HomeBridge at {:?}: {:?}",
+                                    bound_to, message_to_mainnet
+                                );
+                                RelayEvent::new(
+                                    TransferDirection::Withdraw,
+                                    RelayStage::Skipped,
+                                    message_to_mainnet.sidenet_transaction_hash,
+                                ).reason(format!("meant for HomeBridge at {:?}", bound_to))
+                                    .log();
+                            }
+                            bound_to == *home_contract
+                        })
+                        .filter(|&((_, ref message_to_mainnet, _), _)| {
+                            // legacy messages have no chain ids and are accepted
+                            // until every authority signs version 2 messages
+                            let chain_ids = match message_to_mainnet.chain_ids {
+                                Some(chain_ids) => chain_ids,
+                                None => return true,
+                            };
+                            let matches = chain_ids
+                                .matches(app.config.home.chain_id, app.config.foreign.chain_id);
+                            if !matches {
+                                warn!(
+                                    "skipping withdraw message meant for chains {:?}: {:?}",
+                                    chain_ids, message_to_mainnet
+                                );
+                                RelayEvent::new(
+                                    TransferDirection::Withdraw,
+                                    RelayStage::Skipped,
+                                    message_to_mainnet.sidenet_transaction_hash,
+                                ).reason(format!("meant for chains {:?}", chain_ids))
+                                    .log();
+                            }
+                            matches
+                        })
+                        // signatures `HomeBridge.withdraw` rejects would only waste gas on a revert
+                        .filter(|&((ref message, ref message_to_mainnet, ref signatures), _)| {
+                            match verify_signatures(
+                                &message.0,
+                                signatures,
+                                &authorities,
+                                app.config.authorities.required_signatures,
+                            ) {
+                                Ok(()) => true,
+                                Err(err) => {
+                                    warn!(
+                                        "skipping withdraw {:?} whose signatures don't verify: {}",
+                                        message_to_mainnet.sidenet_transaction_hash, err
+                                    );
+                                    status::record_error(format!(
+                                        "skipped withdraw {:?} whose signatures don't verify: {}",
+                                        message_to_mainnet.sidenet_transaction_hash, err
+                                    ));
+                                    RelayEvent::new(
+                                        TransferDirection::Withdraw,
+                                        RelayStage::Skipped,
+                                        message_to_mainnet.sidenet_transaction_hash,
+                                    ).reason(format!("signatures don't verify: {}", err))
+                                        .log();
+                                    false
+                                }
+                            }
+                        })
+                        .inspect(|&((_, ref message_to_mainnet, _), _)| {
+                            let requested = message_to_mainnet.mainnet_gas_price;
+                            if let Some(current) = current_gas_price {
+                                if requested < current {
+                                    warn!(
+                                        "withdraw {:?} requests gas price {} below the current {} on home. its relay may take long",
+                                        message_to_mainnet.sidenet_transaction_hash,
+                                        requested,
+                                        current
+                                    );
+                                }
+                            }
+                        })
+                        .map(|((message, message_to_mainnet, signatures), log_block)| {
+                            origins.push(message_to_mainnet.sidenet_transaction_hash);
+                            if app.config.relay_history.is_some() {
+                                transfers.push(Transfer {
+                                    direction: TransferDirection::Withdraw,
+                                    block_number: log_block.low_u64(),
+                                    block_timestamp: None,
+                                    transaction_hash: message_to_mainnet.sidenet_transaction_hash,
+                                    recipient: message_to_mainnet.recipient,
+                                    value: message_to_mainnet.value,
+                                    relay_transaction_hash: None,
+                                    relayed_at: None,
+                                    // `HomeTokenBridge.withdraw` pays no relay cost
+                                    fee: match tokens {
+                                        Some(_) => Some(U256::zero()),
+                                        None => withdraw_fee(
+                                            app.config.estimated_gas_cost_of_withdraw,
+                                            message_to_mainnet.mainnet_gas_price,
+                                        ),
+                                    },
+                                });
+                            }
                             let payload: Bytes = app.home_bridge
                                 .functions()
                                 .withdraw()
@@ -256,40 +744,85 @@ impl<T: Transport> Stream for WithdrawRelay<T> {
                                     signatures.iter().map(|x| x.v),
                                     signatures.iter().map(|x| x.r),
                                     signatures.iter().map(|x| x.s),
-                                    message.clone().0,
+                                    message.0,
                                 )
                                 .into();
                             let request = TransactionRequest {
                                 from: app.config.home.account,
                                 to: Some(home_contract.clone()),
                                 gas: Some(app.config.txs.withdraw_relay.gas.into()),
-                                gas_price: Some(
-                                    MessageToMainnet::from_bytes(message.0.as_slice())
-                                        .mainnet_gas_price,
-                                ),
+                                gas_price: Some(message_to_mainnet.mainnet_gas_price),
                                 value: None,
                                 data: Some(payload),
                                 nonce: None,
                                 condition: None,
                             };
-                            app.timer.timeout(
-                                api::send_transaction(&app.connections.home, request),
-                                app.config.home.request_timeout,
+                            relay_withdraw(
+                                app.clone(),
+                                *home_contract,
+                                request,
+                                message_to_mainnet.sidenet_transaction_hash,
+                                message_to_mainnet.value,
+                                block,
                             )
                         })
                         .collect::<Vec<_>>();
 
                     info!("relaying {} withdraws", relays.len());
+                    let transfers = history::with_block_timestamps(
+                        &app.connections.foreign,
+                        &app.timer,
+                        app.config.foreign.request_timeout,
+                        transfers,
+                    );
                     WithdrawRelayState::RelayWithdraws {
-                        future: join_all(relays),
+                        future: join_all(relays).join(transfers),
+                        origins,
                         block,
                     }
                 }
                 WithdrawRelayState::RelayWithdraws {
                     ref mut future,
+                    ref origins,
                     block,
                 } => {
-                    let _ = try_ready!(future.poll());
+                    let (outcomes, mut transfers) = try_ready!(future.poll());
+                    let count = |outcome: WithdrawOutcome| {
+                        outcomes.iter().filter(|o| **o == outcome).count()
+                    };
+                    let (skipped, parked) = (
+                        count(WithdrawOutcome::Reverted),
+                        count(WithdrawOutcome::Parked),
+                    );
+                    for (origin, outcome) in origins.iter().zip(&outcomes) {
+                        // parked withdraws are logged once they are relayed
+                        if *outcome != WithdrawOutcome::Parked {
+                            log_relay(TransferDirection::Withdraw, *origin, outcome.hash());
+                        }
+                    }
+                    if skipped > 0 {
+                        warn!(
+                            "{} withdraws were not relayed because their simulation reverted. parked them for retries",
+                            skipped
+                        );
+                    }
+                    if parked > 0 {
+                        warn!(
+                            "{} withdraws were parked until the balance of HomeBridge covers them",
+                            parked
+                        );
+                    }
+                    metrics::record_relays(|relays| {
+                        relays.withdraws += (outcomes.len() - skipped - parked) as u64
+                    });
+                    let relayed_at = clock::timestamp("home");
+                    for (transfer, outcome) in transfers.iter_mut().zip(&outcomes) {
+                        transfer.relay_transaction_hash = outcome.hash();
+                        transfer.relayed_at = Some(relayed_at);
+                    }
+                    let events = transfers.iter().map(TransferEvent::relayed).collect::<Vec<_>>();
+                    sinks::record(&self.app, &transfers, &events)?;
+                    status::set_queue("withdraw_relay", 0);
                     info!("relaying withdraws complete");
                     WithdrawRelayState::Yield(Some(block))
                 }
@@ -308,10 +841,128 @@ impl<T: Transport> Stream for WithdrawRelay<T> {
 
 #[cfg(test)]
 mod tests {
+    use ethabi;
     use rustc_hex::FromHex;
-    use web3::types::{Bytes, Log};
+    use web3::types::{Bytes, Log, U256};
+    use config::Tokens;
     use contracts::foreign;
-    use super::signatures_payload;
+    use message_to_mainnet::{ChainIds, MessageToMainnet, TokenMessageToMainnet};
+    use signature::Signature;
+    use super::{decode_withdraw, signatures_payload, withdraw_fee};
+
+    #[test]
+    fn test_withdraw_fee() {
+        assert_eq!(Some(2_000_000.into()), withdraw_fee(200_000.into(), 10.into()));
+        assert_eq!(None, withdraw_fee(U256::max_value(), 2.into()));
+    }
+
+    #[test]
+    fn test_decode_withdraw() {
+        let foreign = foreign::ForeignBridge::default();
+        let message = MessageToMainnet {
+            recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+            value: 1000.into(),
+            sidenet_transaction_hash:
+                "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+            mainnet_gas_price: 1000.into(),
+            mainnet_bridge_address: "49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            chain_ids: None,
+        };
+        let signature = Signature {
+            v: 28,
+            r: "75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            s: "6c640dda39845de99d9570ba76798e275ebc3036b5a5a758be9a8c0e6f6ed8d4".into(),
+        };
+        let message_output: Bytes = message.to_payload().into();
+        let signature_output: Bytes = signature.to_payload().into();
+
+        let (bytes, decoded, signatures) =
+            decode_withdraw(&foreign, None, &message_output, &[signature_output.clone()]).unwrap();
+        assert_eq!(message.to_bytes(), bytes.0);
+        assert_eq!(message, decoded);
+        assert_eq!(vec![signature], signatures);
+
+        let truncated = message.to_bytes()[1..].to_vec();
+        let truncated_output: Bytes = ethabi::encode(&[ethabi::Token::Bytes(truncated)]).into();
+        assert!(
+            decode_withdraw(&foreign, None, &truncated_output, &[signature_output.clone()]).is_err()
+        );
+
+        let bad_signature_output: Bytes = Signature {
+            v: 0,
+            r: "75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            s: "6c640dda39845de99d9570ba76798e275ebc3036b5a5a758be9a8c0e6f6ed8d4".into(),
+        }.to_payload()
+            .into();
+        assert!(decode_withdraw(&foreign, None, &message_output, &[bad_signature_output]).is_err());
+        assert!(decode_withdraw(&foreign, None, &Bytes(vec![1, 2, 3]), &[]).is_err());
+
+        // version 2 messages are accepted next to legacy ones
+        let message_v2 = MessageToMainnet {
+            chain_ids: Some(ChainIds {
+                home: 1,
+                foreign: 77,
+            }),
+            ..message
+        };
+        let message_v2_output: Bytes = message_v2.to_payload().into();
+        let (bytes, decoded, _) =
+            decode_withdraw(&foreign, None, &message_v2_output, &[signature_output]).unwrap();
+        assert_eq!(message_v2.to_bytes(), bytes.0);
+        assert_eq!(message_v2, decoded);
+    }
+
+    #[test]
+    fn test_decode_token_withdraw() {
+        let foreign = foreign::ForeignBridge::default();
+        let tokens = Tokens {
+            home: "0000000000000000000000000000000000000010".into(),
+            foreign: "0000000000000000000000000000000000000020".into(),
+        };
+        let message = TokenMessageToMainnet {
+            message: MessageToMainnet {
+                recipient: "aff3454fce5edbc8cca8697c15331677e6ebcccc".into(),
+                value: 1000.into(),
+                sidenet_transaction_hash:
+                    "884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+                mainnet_gas_price: 1000.into(),
+                mainnet_bridge_address: "49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                chain_ids: None,
+            },
+            token: tokens.home,
+        };
+        let signature_output: Bytes = Signature {
+            v: 28,
+            r: "75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            s: "6c640dda39845de99d9570ba76798e275ebc3036b5a5a758be9a8c0e6f6ed8d4".into(),
+        }.to_payload()
+            .into();
+        let message_output: Bytes =
+            ethabi::encode(&[ethabi::Token::Bytes(message.to_bytes())]).into();
+
+        let (bytes, decoded, _) = decode_withdraw(
+            &foreign,
+            Some(&tokens),
+            &message_output,
+            &[signature_output.clone()],
+        ).unwrap();
+        assert_eq!(message.to_bytes(), bytes.0);
+        assert_eq!(message.message, decoded);
+
+        // ether messages and messages of other tokens are rejected
+        let signature_outputs = [signature_output];
+        assert!(decode_withdraw(&foreign, None, &message_output, &signature_outputs).is_err());
+        let other_token = Tokens {
+            home: tokens.foreign,
+            ..tokens
+        };
+        assert!(
+            decode_withdraw(&foreign, Some(&other_token), &message_output, &signature_outputs)
+                .is_err()
+        );
+        let ether_output: Bytes = message.message.to_payload().into();
+        assert!(decode_withdraw(&foreign, Some(&tokens), &ether_output, &signature_outputs).is_err());
+    }
 
     #[test]
     fn test_signatures_payload() {
@@ -365,7 +1016,14 @@ mod tests {
             ..Default::default()
         };
 
-        let assignment = signatures_payload(&foreign, 2, my_address, log).unwrap();
+        let assignment = signatures_payload(&foreign, 2, my_address, log.clone()).unwrap();
         assert_eq!(None, assignment);
+
+        // logs of pending transactions have no transaction hash
+        let pending = Log {
+            transaction_hash: None,
+            ..log
+        };
+        assert!(signatures_payload(&foreign, 2, my_address, pending).is_err());
     }
 }
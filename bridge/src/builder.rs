@@ -0,0 +1,109 @@
+/// embedding of the bridge in another application.
+/// `BridgeBuilder` sets up what the binary sets up before it starts listening to events
+/// and returns the `Bridge` stream for the application to run on its own event loop:
+///
+/// ```ignore
+/// let (sender, receiver) = futures::sync::mpsc::unbounded();
+/// let bridge = BridgeBuilder::new(config, "bridge.db", &event_loop.handle())?
+///     .with_events(sender)
+///     .build(&mut event_loop)?;
+/// event_loop.handle().spawn(receiver.for_each(|event| ...));
+/// event_loop.run(bridge.collect())?;
+/// ```
+///
+/// the nodes are reached over any web3 transport with `BridgeBuilder::with_transports`.
+/// the relay streams can also be created on their own from the built app
+/// (see `bridge::create_deposit_relay`, `bridge::create_withdraw_confirm`
+/// and `bridge::create_withdraw_relay`).
+
+use std::path::Path;
+use std::sync::Arc;
+use futures::sync::mpsc::UnboundedSender;
+use tokio_core::reactor::{Core, Handle};
+use web3::Transport;
+use app::{App, Connections};
+use bridge::{create_bridge, Bridge, FileBackend};
+use config::Config;
+use database::Database;
+use error::Error;
+use event_queue::{self, TransferEvent};
+use metrics::Metered;
+use pacing::Paced;
+use retry::Retrying;
+use shard;
+use transport::NodeTransport;
+
+/// builds a `Bridge` from a `Config` without the binary
+pub struct BridgeBuilder<T: Transport> {
+    app: App<T>,
+    /// loaded from the database file or sqlite if `None`
+    database: Option<Database>,
+}
+
+impl BridgeBuilder<Paced<Retrying<Metered<NodeTransport>>>> {
+    /// connects to the nodes of `config` like the binary does.
+    /// the checkpoints are saved to the database file at `database_path`
+    pub fn new<P: AsRef<Path>>(
+        config: Config,
+        database_path: P,
+        handle: &Handle,
+    ) -> Result<Self, Error> {
+        Ok(BridgeBuilder {
+            app: App::new(config, database_path, handle)?,
+            database: None,
+        })
+    }
+}
+
+impl<T: Transport + Clone> BridgeBuilder<T> {
+    /// talks to the nodes via `home` and `foreign` instead of the endpoints of `config`.
+    /// the checkpoints are saved to the database file at `database_path`
+    pub fn with_transports<P: AsRef<Path>>(
+        config: Config,
+        database_path: P,
+        home: T,
+        foreign: T,
+    ) -> Result<Self, Error> {
+        Ok(BridgeBuilder {
+            app: App::with_connections(config, database_path, Connections { home, foreign })?,
+            database: None,
+        })
+    }
+
+    /// starts from `database` instead of the database file or sqlite
+    pub fn with_database(mut self, database: Database) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// sends an event to `sender` for every transfer that was signed, relayed or skipped
+    pub fn with_events(mut self, sender: UnboundedSender<TransferEvent>) -> Self {
+        self.app.events = Some(sender);
+        self
+    }
+
+    /// resolves ENS names, detects the transaction types, prepares the keystore,
+    /// loads the database and resolves the contract addresses on `event_loop`.
+    /// starts publishing to `event_queue` and takes the leases of `shard` if they are configured.
+    /// returns the app the relay streams are created from and the database they start from
+    pub fn build_app(mut self, event_loop: &mut Core) -> Result<(Arc<App<T>>, Database), Error> {
+        self.app.resolve_ens_names(event_loop)?;
+        self.app.detect_transaction_types(event_loop)?;
+        self.app.prepare_keystore(event_loop)?;
+        let mut database = match self.database {
+            Some(database) => database,
+            None => self.app.load_database()?,
+        };
+        self.app.resolve_contract_addresses(event_loop, &mut database)?;
+        event_queue::start(&self.app.config.event_queue)?;
+        shard::start(&self.app.config, &database, self.app.leases.clone())?;
+        Ok((Arc::new(self.app), database))
+    }
+
+    /// sets up the app like `build_app` and creates the bridge
+    /// that saves its checkpoints like the binary
+    pub fn build(self, event_loop: &mut Core) -> Result<Bridge<T, FileBackend>, Error> {
+        let (app, database) = self.build_app(event_loop)?;
+        Ok(create_bridge(app, &database))
+    }
+}
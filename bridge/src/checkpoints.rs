@@ -0,0 +1,130 @@
+/// recent checkpoints of a log stream: the hashes of the last blocks of the ranges it yielded.
+/// before a stream continues after its latest checkpoint it checks that the block is still
+/// on the chain. after a reorg replaced it the stream rewinds to the latest checkpoint
+/// that is still on the chain so the events of the replaced blocks are processed again
+/// instead of being skipped. relays of events that were processed before revert in their simulation.
+/// the hash of the latest checkpoint is saved in the database next to its block number
+/// so reorgs while the bridge was stopped are detected as well.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use web3::types::H256;
+
+#[derive(Default)]
+struct CheckpointsState {
+    /// number of checkpoints kept. `0` disables the checks
+    capacity: usize,
+    /// oldest first
+    recent: VecDeque<(u64, H256)>,
+}
+
+/// recent checkpoints shared by a log stream and the backend that saves its progress
+#[derive(Clone, Default)]
+pub struct Checkpoints(Arc<Mutex<CheckpointsState>>);
+
+impl Checkpoints {
+    /// keeps up to `capacity` checkpoints. `0` disables the checks
+    pub fn new(capacity: usize) -> Self {
+        Checkpoints(Arc::new(Mutex::new(CheckpointsState {
+            capacity,
+            recent: VecDeque::new(),
+        })))
+    }
+
+    fn state(&self) -> MutexGuard<CheckpointsState> {
+        self.0.lock().expect("no code panics while holding the lock; qed")
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state().capacity > 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.state().capacity
+    }
+
+    /// starts over from the checkpoint at `block` loaded from the database.
+    /// databases saved without hashes have no checkpoint until the stream yields
+    pub fn seed(&self, block: u64, hash: Option<H256>) {
+        let mut state = self.state();
+        state.recent.clear();
+        if let Some(hash) = hash {
+            if state.capacity > 0 {
+                state.recent.push_back((block, hash));
+            }
+        }
+    }
+
+    /// records that the stream yielded the range up to `block` with `hash`
+    pub fn push(&self, block: u64, hash: H256) {
+        let mut state = self.state();
+        if state.capacity == 0 {
+            return;
+        }
+        state.recent.push_back((block, hash));
+        while state.recent.len() > state.capacity {
+            state.recent.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> Option<(u64, H256)> {
+        self.state().recent.back().cloned()
+    }
+
+    /// drops the latest checkpoint whose block was replaced.
+    /// returns the checkpoint before it or `None` if none is left
+    pub fn discard_latest(&self) -> Option<(u64, H256)> {
+        let mut state = self.state();
+        state.recent.pop_back();
+        state.recent.back().cloned()
+    }
+
+    /// returns the hash of the checkpoint at `block` if it is kept
+    pub fn hash(&self, block: u64) -> Option<H256> {
+        self.state()
+            .recent
+            .iter()
+            .rev()
+            .find(|&&(checkpoint, _)| checkpoint == block)
+            .map(|&(_, hash)| hash)
+    }
+}
+
+/// checkpoints of the streams of the components whose progress is saved in the database
+#[derive(Clone, Default)]
+pub struct StreamCheckpoints {
+    pub deposit_relay: Checkpoints,
+    pub withdraw_confirm: Checkpoints,
+    pub withdraw_relay: Checkpoints,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checkpoints;
+
+    #[test]
+    fn test_checkpoints() {
+        let checkpoints = Checkpoints::new(2);
+        checkpoints.seed(10, Some(1.into()));
+        assert_eq!(Some((10, 1.into())), checkpoints.latest());
+        checkpoints.push(20, 2.into());
+        checkpoints.push(30, 3.into());
+        assert_eq!(None, checkpoints.hash(10));
+        assert_eq!(Some(2.into()), checkpoints.hash(20));
+        assert_eq!(Some((20, 2.into())), checkpoints.discard_latest());
+        assert_eq!(None, checkpoints.discard_latest());
+        assert_eq!(None, checkpoints.latest());
+
+        checkpoints.seed(10, None);
+        assert_eq!(None, checkpoints.latest());
+    }
+
+    #[test]
+    fn test_disabled_checkpoints() {
+        let checkpoints = Checkpoints::default();
+        assert!(!checkpoints.is_enabled());
+        checkpoints.seed(10, Some(1.into()));
+        checkpoints.push(20, 2.into());
+        assert_eq!(None, checkpoints.latest());
+    }
+}
@@ -0,0 +1,96 @@
+/// time of the chains.
+/// recurring windows are evaluated against the timestamps of the latest blocks of a chain
+/// rather than against the clock of this host, which may be skewed.
+/// between two observations the time of a chain is extrapolated with the monotonic clock
+/// of this host. the time of this host is only used until a chain was first observed.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// latest block timestamp of a chain and when it was observed
+#[derive(Debug, Clone, Copy)]
+struct Observation {
+    timestamp: u64,
+    at: Instant,
+}
+
+fn registry() -> &'static Mutex<HashMap<&'static str, Observation>> {
+    static INIT: Once = ONCE_INIT;
+    static mut REGISTRY: *const Mutex<HashMap<&'static str, Observation>> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            REGISTRY = Box::into_raw(Box::new(Mutex::new(HashMap::new())));
+        });
+        &*REGISTRY
+    }
+}
+
+/// records `timestamp` of the latest block of `chain`
+pub fn observe(chain: &'static str, timestamp: u64) {
+    let observation = Observation {
+        timestamp,
+        at: Instant::now(),
+    };
+    registry()
+        .lock()
+        .expect("clock is never poisoned; qed")
+        .insert(chain, observation);
+}
+
+/// returns the current time of `chain`.
+/// the time of this host if `chain` wasn't observed yet
+pub fn now(chain: &'static str) -> SystemTime {
+    let observation = registry()
+        .lock()
+        .expect("clock is never poisoned; qed")
+        .get(chain)
+        .cloned();
+    match observation {
+        Some(observation) => {
+            UNIX_EPOCH + Duration::from_secs(observation.timestamp) + observation.at.elapsed()
+        }
+        None => SystemTime::now(),
+    }
+}
+
+/// returns the current time of `chain` in seconds since the unix epoch
+pub fn timestamp(chain: &'static str) -> u64 {
+    now(chain)
+        .duration_since(UNIX_EPOCH)
+        .expect("time of the chains is after the unix epoch; qed")
+        .as_secs()
+}
+
+/// seconds the time of this host `host` is ahead of the chain time `chain`.
+/// negative if it is behind
+pub fn divergence(host: SystemTime, chain: SystemTime) -> i64 {
+    match host.duration_since(chain) {
+        Ok(ahead) => ahead.as_secs() as i64,
+        Err(behind) => -(behind.duration().as_secs() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::{divergence, now, observe};
+
+    #[test]
+    fn test_now() {
+        observe("test_now", 1_500_000_000);
+        let time = now("test_now")
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert!(time >= 1_500_000_000 && time < 1_500_000_010);
+    }
+
+    #[test]
+    fn test_divergence() {
+        let chain = UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(30, divergence(chain + Duration::from_secs(30), chain));
+        assert_eq!(-30, divergence(chain - Duration::from_secs(30), chain));
+        assert_eq!(0, divergence(chain, chain));
+    }
+}
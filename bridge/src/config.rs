@@ -1,16 +1,59 @@
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
-use std::fs;
+use std::{cmp, fmt, fs};
 use std::io::Read;
 use std::time::Duration;
 use rustc_hex::FromHex;
 use web3::types::{Address, Bytes};
 use ethereum_types::U256;
-use error::{Error, ResultExt};
+use error::{Error, ResultExt, MAX_LAG_EXIT_CODE};
+use api::{AccessListItem, ConfirmationStrategy};
+use contracts::ContractVersion;
+use control::{Direction, Pause};
+use ens::MAINNET_REGISTRY;
+use message_to_mainnet::ChainIds;
+use maintenance::{MaintenanceWindow, Weekday};
+use network::{self, ChainPreset, GasPreset, NetworkPreset};
+use signer::Chain;
+use transaction::{AccessListMode, DynamicFees, TransactionType};
 use toml;
+use tls;
 
 const DEFAULT_POLL_INTERVAL: u64 = 1;
+const DEFAULT_MAX_POLL_INTERVAL: u64 = 30;
 const DEFAULT_CONFIRMATIONS: usize = 12;
+const DEFAULT_REORG_CHECKPOINTS: usize = 32;
 const DEFAULT_TIMEOUT: u64 = 5;
+const DEFAULT_RELAY_DEADLINE: u64 = 60;
+const DEFAULT_MAX_RELAY_ATTEMPTS: u32 = 20;
+const DEFAULT_METRICS_EXPORT_INTERVAL: u64 = 10;
+const DEFAULT_STATUS_INTERVAL: u64 = 10;
+const DEFAULT_SKEW_TOLERANCE: u64 = 30;
+const DEFAULT_MAX_CLOCK_DIVERGENCE: u64 = 120;
+const DEFAULT_CLOCK_CHECK_INTERVAL: u64 = 30;
+const DEFAULT_LAG_CHECK_INTERVAL: u64 = 30;
+const DEFAULT_FEE_CLAIM_INTERVAL: u64 = 3600;
+const DEFAULT_REVERTED_RELAY_ATTEMPTS: u32 = 5;
+const DEFAULT_REVERTED_RELAY_RETRY_INTERVAL: u64 = 600;
+const DEFAULT_SHARD_PARTITIONS: u64 = 16;
+const DEFAULT_SHARD_LEASE_DURATION: u64 = 30;
+const DEFAULT_OTLP_EXPORT_INTERVAL: u64 = 10;
+const DEFAULT_OTLP_SERVICE_NAME: &str = "parity-bridge";
+const DEFAULT_GAS_PRICE_REFRESH_INTERVAL: u64 = 60;
+const DEFAULT_GAS_PRICE_POINTER: &str = "/fast";
+const DEFAULT_GAS_PRICE_WEI_PER_UNIT: u64 = 1_000_000_000;
+const DEFAULT_STUCK_TRANSACTION_TIMEOUT: u64 = 300;
+/// nodes only accept replacements that raise the gas price by at least 10 percent
+const MIN_GAS_PRICE_BUMP: u64 = 10;
+const DEFAULT_GAS_PRICE_BUMP: u64 = 12;
+const DEFAULT_BALANCE_CHECK_INTERVAL: u64 = 60;
+const DEFAULT_MAX_RETRIES: u32 = 10;
+const DEFAULT_INITIAL_BACKOFF: u64 = 500;
+const DEFAULT_MAX_BACKOFF: u64 = 30_000;
+const DEFAULT_CALL_CONFIRM_GAS: u64 = 300_000;
+const DEFAULT_CALL_RELAY_GAS: u64 = 200_000;
+const DEFAULT_MAX_CALL_GAS_LIMIT: u64 = 1_000_000;
+const DEFAULT_MAX_CALL_DATA_LENGTH: usize = 1024;
 
 /// Application config.
 #[derive(Debug, PartialEq, Clone)]
@@ -22,6 +65,70 @@ pub struct Config {
     pub estimated_gas_cost_of_withdraw: U256,
     pub max_total_home_contract_balance: U256,
     pub max_single_deposit_value: U256,
+    /// generation of the bridge contracts
+    pub contract_version: ContractVersion,
+    /// format of the withdraw messages the authorities sign (see `message_to_mainnet`)
+    pub message_version: MessageVersion,
+    /// manifest written by `parity-bridge-deploy` that the deployment is verified against
+    /// before the bridge starts (see `manifest`). a missing database is created from it.
+    /// not verified if `None`
+    pub deployment_manifest: Option<PathBuf>,
+    /// address of the ENS registry on `home`
+    pub ens_registry: Address,
+    /// ENS names in place of addresses that have to be resolved before the config is used
+    pub ens_names: Vec<EnsName>,
+    pub pause_files: PauseFiles,
+    /// off-chain limits on the value relayed per direction. unlimited by default
+    pub value_limits: ValueLimits,
+    /// detection of ether that reaches `HomeBridge` without a `Deposit`.
+    /// disabled if `None`
+    pub unaccounted_transfers: Option<UnaccountedTransfersConfig>,
+    /// relay of calls requested via `ForeignBridge.requestCall` (see `message_call`).
+    /// disabled if `None`
+    pub message_calls: Option<MessageCallsConfig>,
+    /// whether to halt signing of withdraws while `HomeBridge` can't cover the pending withdraws
+    pub solvency_check: bool,
+    /// export of rpc metrics. disabled if `None`
+    pub metrics: Option<MetricsConfig>,
+    /// file that every signature this authority produces is appended to as a line of json
+    pub signature_audit_log: Option<PathBuf>,
+    /// whether to read submitted signatures back from `ForeignBridge` and
+    /// halt signing of withdraws if one doesn't match
+    pub verify_signatures: bool,
+    /// file that every relayed deposit and withdraw is appended to as a line of json
+    pub relay_history: Option<PathBuf>,
+    /// message queue the lifecycle of transfers is published to. disabled if `None`
+    pub event_queue: Option<EventQueueConfig>,
+    /// postgres database the relay history and the lifecycle of transfers are mirrored into.
+    /// disabled if `None`
+    pub postgres: Option<PostgresConfig>,
+    /// sqlite database that holds the checkpoints instead of the database file
+    /// and records every processed transfer. disabled if `None`
+    pub sqlite: Option<SqliteConfig>,
+    /// export of traces and metrics to an opentelemetry collector. disabled if `None`
+    pub otlp: Option<OtlpConfig>,
+    /// http api for explorers and operators. disabled if `None`
+    pub http_api: Option<HttpApiConfig>,
+    pub role: Role,
+    /// format of the lines of the log. overridden by `--log-format`
+    pub log_format: LogFormat,
+    /// signer daemon that holds the keys of this authority.
+    /// the nodes sign and send transactions themselves if `None`
+    pub signer: Option<SignerConfig>,
+    /// encrypted key of the authority accounts that signs instead of the nodes.
+    /// the nodes sign if `None`
+    pub keystore: Option<KeystoreConfig>,
+    /// leases of the partitions of the events of this authority among its processes.
+    /// handles all events if `None`
+    pub shard: Option<ShardConfig>,
+    /// comparison of the clock of this host with the time of the chains
+    pub clock: ClockConfig,
+    /// exit of the process when a component lags behind for too long. disabled if `None`
+    pub max_lag: Option<MaxLagConfig>,
+    /// relay fees of deposits that are charged in tokens. deposits are relayed without fee if `None`
+    pub token_fee: Option<TokenFeeConfig>,
+    /// retries of relays whose simulation reverted (see `parked`)
+    pub reverted_relays: RevertedRelaysConfig,
 }
 
 impl Config {
@@ -32,369 +139,4325 @@ impl Config {
         Self::load_from_str(&buffer)
     }
 
-    fn load_from_str(s: &str) -> Result<Config, Error> {
+    /// loads the config from the toml in `s`.
+    /// lets an application that embeds the bridge keep the config where it likes
+    pub fn load_from_str(s: &str) -> Result<Config, Error> {
         let config: load::Config = toml::from_str(s).chain_err(|| "Cannot parse config")?;
         Config::from_load_struct(config)
     }
 
     fn from_load_struct(config: load::Config) -> Result<Config, Error> {
+        let token_bridge =
+            config.home.token_address.is_some() || config.foreign.token_address.is_some();
+        let contract_version = match (config.contract_version, token_bridge) {
+            (None, false) | (Some(load::ContractVersion::V1), false) => ContractVersion::V1,
+            (None, true) | (Some(load::ContractVersion::V2), true) => ContractVersion::V2,
+            (Some(load::ContractVersion::V1), true) => bail!(
+                "`home.token_address` and `foreign.token_address` require `contract_version = \"v2\"`"
+            ),
+            (Some(load::ContractVersion::V2), false) => bail!(
+                "`contract_version = \"v2\"` requires `home.token_address` and `foreign.token_address`"
+            ),
+        };
+        if !contract_version.is_enabled() {
+            bail!(
+                "contract version {:?} is not supported by this binary. rebuild it with feature `bridge/contracts-v2`",
+                contract_version
+            );
+        }
+
+        let has_fallback_accounts = |node: &load::Node| {
+            node.fallback_accounts
+                .as_ref()
+                .map_or(false, |accounts| !accounts.is_empty())
+        };
+        if has_fallback_accounts(&config.home) {
+            bail!(
+                "`home.fallback_accounts` is not supported. only signatures on `foreign` fail over"
+            );
+        }
+        if has_fallback_accounts(&config.foreign) && config.signer.is_some() {
+            bail!(
+                "`foreign.fallback_accounts` sign via `foreign.ipc` and can't be combined with `signer`"
+            );
+        }
+
+        if config.signer.is_some()
+            && (config.home.stuck_transactions.is_some()
+                || config.foreign.stuck_transactions.is_some())
+        {
+            bail!(
+                "`stuck_transactions` can't be combined with `signer`. the node of the signer assigns the nonces"
+            );
+        }
+
+        if config.keystore.is_some() && config.signer.is_some() {
+            if let None | Some(load::Role::Full) = config.role {
+                bail!(
+                    "`keystore` can't be combined with `signer`. configure the keystore of the signer daemon"
+                );
+            }
+        }
+
+        let event_queue = match config.event_queue {
+            Some(event_queue) => Some(EventQueueConfig::from_load_struct(event_queue)?),
+            None => None,
+        };
+
+        if config.postgres.is_some() && !cfg!(feature = "postgres-sink") {
+            bail!(
+                "`postgres` is not supported by this binary. rebuild it with feature `bridge/postgres-sink`"
+            );
+        }
+
+        if config.sqlite.is_some() && !cfg!(feature = "sqlite") {
+            bail!(
+                "`sqlite` is not supported by this binary. rebuild it with feature `bridge/sqlite`"
+            );
+        }
+
+        if let Some(ref max_lag) = config.max_lag {
+            if max_lag.blocks == 0 {
+                bail!("`max_lag.blocks` must be greater than 0");
+            }
+        }
+
+        if let Some(ref reverted_relays) = config.reverted_relays {
+            if reverted_relays.max_attempts == Some(0) {
+                bail!("`reverted_relays.max_attempts` must be greater than 0");
+            }
+        }
+
+        if let Some(ref token_fee) = config.token_fee {
+            if token_fee.deposit_fee.0.is_zero() {
+                bail!(
+                    "`token_fee.deposit_fee` must be greater than 0. remove `[token_fee]` to relay deposits without fee"
+                );
+            }
+            if token_fee.transfer_home == Some(true) && config.signer.is_some() {
+                bail!(
+                    "`token_fee.transfer_home` can't be combined with `signer`. the signer only claims relay fees"
+                );
+            }
+        }
+
+        if let Some(ref value_limits) = config.value_limits {
+            for &(direction, limit) in &[
+                ("deposits", &value_limits.deposits),
+                ("withdraws", &value_limits.withdraws),
+            ] {
+                let limit = match *limit {
+                    Some(ref limit) => limit,
+                    None => continue,
+                };
+                let single = limit.max_single_value.as_ref().map(|value| value.0);
+                let daily = limit.max_daily_value.as_ref().map(|value| value.0);
+                if single.map_or(false, |value| value.is_zero())
+                    || daily.map_or(false, |value| value.is_zero())
+                {
+                    bail!(
+                        "`value_limits.{}` must be greater than 0. leave a limit out to not limit the value",
+                        direction
+                    );
+                }
+                if let (Some(single), Some(daily)) = (single, daily) {
+                    if single > daily {
+                        bail!(
+                            "`value_limits.{0}.max_single_value` must not exceed `value_limits.{0}.max_daily_value`",
+                            direction
+                        );
+                    }
+                }
+            }
+        }
+
+        let max_batch_size = config
+            .transactions
+            .as_ref()
+            .and_then(|transactions| transactions.max_batch_size);
+        if max_batch_size == Some(0) {
+            bail!("`transactions.max_batch_size` must be greater than 0");
+        }
+
+        match (&config.home.token_address, &config.foreign.token_address) {
+            (&Some(_), &Some(_)) => {
+                if max_batch_size.is_some() {
+                    bail!("`transactions.max_batch_size` is only supported by the ether bridge");
+                }
+                if config.signer.is_some() {
+                    bail!(
+                        "token bridges can't be combined with `signer`. the signer only signs for the ether bridge"
+                    );
+                }
+                if config.solvency_check == Some(true) || config.unaccounted_transfers.is_some()
+                {
+                    bail!(
+                        "`solvency_check` and `unaccounted_transfers` watch ether and are only supported by the ether bridge"
+                    );
+                }
+                if config.message_calls.is_some() {
+                    bail!("`message_calls` is only supported by the ether bridge");
+                }
+            }
+            (&None, &None) => {}
+            _ => bail!(
+                "`home.token_address` and `foreign.token_address` must be set together to run a token bridge"
+            ),
+        }
+
+        if let Some(ref shard) = config.shard {
+            if !cfg!(feature = "shard-leases") {
+                bail!(
+                    "`shard` is not supported by this binary. rebuild it with feature `bridge/shard-leases`"
+                );
+            }
+            if shard.partitions == Some(0) {
+                bail!("`shard.partitions` must be greater than 0");
+            }
+            if shard.lease_duration == Some(0) {
+                bail!("`shard.lease_duration` must be greater than 0");
+            }
+        }
+
+        if let Some(ref transactions) = config.transactions {
+            let legacy_foreign = match config.foreign.transaction_type {
+                Some(load::TransactionType::Legacy) | Some(load::TransactionType::Eip2930) => true,
+                _ => false,
+            };
+            let relays_to_foreign = [
+                ("deposit_relay", &transactions.deposit_relay),
+                ("withdraw_confirm", &transactions.withdraw_confirm),
+                ("fee_claim", &transactions.fee_claim),
+            ];
+            for &(name, tx) in &relays_to_foreign {
+                if let Some(ref tx) = *tx {
+                    check_dynamic_fees(name, tx)?;
+                    if legacy_foreign && tx.max_priority_fee_per_gas.is_some() {
+                        bail!(
+                            "`transactions.{}` has EIP-1559 fees which can't be combined with `foreign.transaction_type` legacy or eip2930",
+                            name
+                        );
+                    }
+                }
+            }
+            let fixed_gas_price = [
+                ("home_deploy", &transactions.home_deploy),
+                ("foreign_deploy", &transactions.foreign_deploy),
+                ("withdraw_relay", &transactions.withdraw_relay),
+            ];
+            for &(name, tx) in &fixed_gas_price {
+                if let Some(ref tx) = *tx {
+                    if tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some() {
+                        bail!(
+                            "`transactions.{}` doesn't support EIP-1559 fees. withdraws are relayed at the gas price of their message and deploys use `gas_price`",
+                            name
+                        );
+                    }
+                }
+            }
+        }
+
+        let preset = match config.network {
+            Some(ref name) => Some(network::preset(name).ok_or_else(|| {
+                format!(
+                    "unknown network `{}`. known networks: {}",
+                    name,
+                    network::names().join(", ")
+                )
+            })?),
+            None => None,
+        };
+
+        let mut ens_names = Vec::new();
         let result = Config {
-            home: Node::from_load_struct(config.home)?,
-            foreign: Node::from_load_struct(config.foreign)?,
+            home: Node::from_load_struct(
+                "home",
+                config.home,
+                AddressField::HomeAccount,
+                AddressField::HomeRegistry,
+                &mut ens_names,
+                preset.map(|preset| &preset.home),
+            )?,
+            foreign: Node::from_load_struct(
+                "foreign",
+                config.foreign,
+                AddressField::ForeignAccount,
+                AddressField::ForeignRegistry,
+                &mut ens_names,
+                preset.map(|preset| &preset.foreign),
+            )?,
             authorities: Authorities {
-                accounts: config.authorities.accounts,
+                accounts: config
+                    .authorities
+                    .accounts
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, account)| {
+                        account.into_address(AddressField::Authority(index), &mut ens_names)
+                    })
+                    .collect(),
                 required_signatures: config.authorities.required_signatures,
             },
-            txs: config
-                .transactions
-                .map(Transactions::from_load_struct)
+            txs: Transactions::from_load_struct(config.transactions.unwrap_or_default(), preset),
+            estimated_gas_cost_of_withdraw: config.estimated_gas_cost_of_withdraw.0,
+            max_total_home_contract_balance: config.max_total_home_contract_balance.0,
+            max_single_deposit_value: config.max_single_deposit_value.0,
+            contract_version,
+            message_version: match config.message_version {
+                None | Some(load::MessageVersion::V1) => MessageVersion::V1,
+                Some(load::MessageVersion::V2) => MessageVersion::V2,
+            },
+            deployment_manifest: config.deployment_manifest,
+            ens_registry: config
+                .ens_registry
+                .map(|address| address.0)
+                .or_else(|| preset.and_then(|preset| preset.ens_registry).map(Into::into))
+                .unwrap_or_else(|| MAINNET_REGISTRY.into()),
+            ens_names,
+            pause_files: config
+                .pause_files
+                .map(|files| PauseFiles {
+                    deposits: files.deposits,
+                    withdraws: files.withdraws,
+                })
+                .unwrap_or_default(),
+            value_limits: config
+                .value_limits
+                .map(|value_limits| {
+                    let limit = |limit: Option<load::ValueLimit>| {
+                        limit.map_or_else(ValueLimit::default, |limit| ValueLimit {
+                            max_single_value: limit.max_single_value.map(|value| value.0),
+                            max_daily_value: limit.max_daily_value.map(|value| value.0),
+                        })
+                    };
+                    ValueLimits {
+                        deposits: limit(value_limits.deposits),
+                        withdraws: limit(value_limits.withdraws),
+                    }
+                })
+                .unwrap_or_default(),
+            unaccounted_transfers: config.unaccounted_transfers.map(|unaccounted_transfers| {
+                UnaccountedTransfersConfig {
+                    audit_log: unaccounted_transfers.audit_log,
+                }
+            }),
+            message_calls: config.message_calls.map(|message_calls| MessageCallsConfig {
+                confirm_gas: message_calls
+                    .confirm_gas
+                    .unwrap_or(DEFAULT_CALL_CONFIRM_GAS),
+                relay_gas: message_calls.relay_gas.unwrap_or(DEFAULT_CALL_RELAY_GAS),
+                max_gas_limit: message_calls
+                    .max_gas_limit
+                    .unwrap_or(DEFAULT_MAX_CALL_GAS_LIMIT),
+                max_data_length: message_calls
+                    .max_data_length
+                    .unwrap_or(DEFAULT_MAX_CALL_DATA_LENGTH),
+            }),
+            solvency_check: config.solvency_check.unwrap_or(false),
+            metrics: config.metrics.map(|metrics| MetricsConfig {
+                file: metrics.file,
+                export_interval: Duration::from_secs(
+                    metrics
+                        .export_interval
+                        .unwrap_or(DEFAULT_METRICS_EXPORT_INTERVAL),
+                ),
+            }),
+            signature_audit_log: config.signature_audit_log,
+            verify_signatures: config.verify_signatures.unwrap_or(true),
+            relay_history: config.relay_history,
+            event_queue,
+            postgres: config.postgres.map(|postgres| PostgresConfig { url: postgres.url }),
+            sqlite: config.sqlite.map(|sqlite| SqliteConfig { path: sqlite.path }),
+            otlp: config.otlp.map(|otlp| OtlpConfig {
+                endpoint: otlp.endpoint,
+                service_name: otlp.service_name
+                    .unwrap_or_else(|| DEFAULT_OTLP_SERVICE_NAME.into()),
+                export_interval: Duration::from_secs(
+                    otlp.export_interval.unwrap_or(DEFAULT_OTLP_EXPORT_INTERVAL),
+                ),
+            }),
+            http_api: config.http_api.map(|http_api| HttpApiConfig {
+                address: http_api.address,
+                status_interval: Duration::from_secs(
+                    http_api.status_interval.unwrap_or(DEFAULT_STATUS_INTERVAL),
+                ),
+                admin_token_file: http_api.admin_token_file,
+            }),
+            role: match config.role {
+                None | Some(load::Role::Full) => Role::Full,
+                Some(load::Role::Signer) => Role::Signer,
+            },
+            log_format: match config.log_format {
+                None | Some(load::LogFormat::Text) => LogFormat::Text,
+                Some(load::LogFormat::Json) => LogFormat::Json,
+            },
+            signer: config.signer.map(|signer| SignerConfig {
+                socket: signer.socket,
+                token_file: signer.token_file,
+            }),
+            keystore: config.keystore.map(|keystore| KeystoreConfig {
+                path: keystore.path,
+                password_file: keystore.password_file,
+            }),
+            shard: config.shard.map(ShardConfig::from_load_struct),
+            clock: config
+                .clock
+                .map(ClockConfig::from_load_struct)
+                .unwrap_or_default(),
+            max_lag: config.max_lag.map(|max_lag| MaxLagConfig {
+                blocks: max_lag.blocks,
+                duration: Duration::from_secs(max_lag.duration),
+                check_interval: Duration::from_secs(
+                    max_lag.check_interval.unwrap_or(DEFAULT_LAG_CHECK_INTERVAL),
+                ),
+            }),
+            token_fee: config.token_fee.map(|token_fee| TokenFeeConfig {
+                deposit_fee: token_fee.deposit_fee.0,
+                claim_interval: Duration::from_secs(
+                    token_fee.claim_interval.unwrap_or(DEFAULT_FEE_CLAIM_INTERVAL),
+                ),
+                min_claim: token_fee.min_claim.map_or_else(U256::zero, |min_claim| min_claim.0),
+                transfer_home: token_fee.transfer_home.unwrap_or(false),
+            }),
+            reverted_relays: config
+                .reverted_relays
+                .map(RevertedRelaysConfig::from_load_struct)
                 .unwrap_or_default(),
-            estimated_gas_cost_of_withdraw: config.estimated_gas_cost_of_withdraw,
-            max_total_home_contract_balance: config.max_total_home_contract_balance,
-            max_single_deposit_value: config.max_single_deposit_value,
         };
 
+        if result.message_version == MessageVersion::V2
+            && (result.home.chain_id.is_none() || result.foreign.chain_id.is_none())
+        {
+            bail!("`message_version = \"v2\"` requires `home.chain_id` and `foreign.chain_id`");
+        }
+
         Ok(result)
     }
+
+    /// chain ids the withdraw messages of this bridge name.
+    /// `None` if the authorities sign messages without chain ids
+    pub fn message_chain_ids(&self) -> Option<ChainIds> {
+        match (self.message_version, self.home.chain_id, self.foreign.chain_id) {
+            (MessageVersion::V2, Some(home), Some(foreign)) => Some(ChainIds { home, foreign }),
+            _ => None,
+        }
+    }
+
+    /// returns the pause switch of `direction`
+    pub fn pause(&self, direction: Direction) -> Pause {
+        let file = match direction {
+            Direction::Deposits => self.pause_files.deposits.clone(),
+            Direction::Withdraws => self.pause_files.withdraws.clone(),
+        };
+        Pause {
+            direction,
+            file,
+            insolvency: false,
+            halted: false,
+            // deposits are relayed and withdraws are confirmed on foreign
+            chain: Chain::Foreign,
+        }
+    }
+
+    /// tokens bridged by a token bridge. `None` for the ether bridge
+    pub fn tokens(&self) -> Option<Tokens> {
+        match (self.home.token_address, self.foreign.token_address) {
+            (Some(home), Some(foreign)) => Some(Tokens { home, foreign }),
+            _ => None,
+        }
+    }
+
+    /// sets the address of `field` to `address`
+    pub fn set_address(&mut self, field: AddressField, address: Address) {
+        match field {
+            AddressField::HomeAccount => self.home.account = address,
+            AddressField::ForeignAccount => self.foreign.account = address,
+            AddressField::Authority(index) => self.authorities.accounts[index] = address,
+            AddressField::FallbackAccount(index) => self.foreign.fallback_accounts[index] = address,
+            AddressField::HomeRegistry => {
+                self.home
+                    .registry
+                    .as_mut()
+                    .expect("field is only recorded for configured registries; qed")
+                    .address = address
+            }
+            AddressField::ForeignRegistry => {
+                self.foreign
+                    .registry
+                    .as_mut()
+                    .expect("field is only recorded for configured registries; qed")
+                    .address = address
+            }
+        }
+    }
 }
 
+/// bridges run by one process (see `supervisor`)
 #[derive(Debug, PartialEq, Clone)]
-pub struct Node {
-    pub account: Address,
-    pub contract: ContractConfig,
-    pub ipc: PathBuf,
-    pub request_timeout: Duration,
-    pub poll_interval: Duration,
-    pub required_confirmations: usize,
+pub struct BridgesConfig {
+    /// format of the lines of the log. overridden by `--log-format`
+    pub log_format: LogFormat,
+    pub bridges: Vec<BridgeDefinition>,
 }
 
-impl Node {
-    fn from_load_struct(node: load::Node) -> Result<Node, Error> {
-        let result = Node {
-            account: node.account,
-            contract: ContractConfig {
-                bin: {
-                    let mut read = String::new();
-                    let mut file = fs::File::open(&node.contract.bin).chain_err(|| {
-                        format!(
-                            "Cannot open compiled contract file at {}",
-                            node.contract.bin.to_string_lossy()
-                        )
-                    })?;
-                    file.read_to_string(&mut read)?;
-                    Bytes(read.from_hex()?)
-                },
+/// a bridge of the process with its own contracts, authority and database
+#[derive(Debug, PartialEq, Clone)]
+pub struct BridgeDefinition {
+    /// prefix of the log lines, metrics and queues of the bridge
+    pub name: String,
+    pub config: Config,
+    pub database: PathBuf,
+}
+
+impl BridgesConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<BridgesConfig, Error> {
+        let mut file = fs::File::open(path).chain_err(|| "Cannot open bridges")?;
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)?;
+        Self::load_from_str(&buffer)
+    }
+
+    /// loads the bridges from the toml in `s`. the configs of the bridges are read from their files
+    pub fn load_from_str(s: &str) -> Result<BridgesConfig, Error> {
+        let bridges: load::Bridges = toml::from_str(s).chain_err(|| "Cannot parse bridges")?;
+        if bridges.bridges.is_empty() {
+            bail!("`bridges` must define at least one bridge");
+        }
+        let mut definitions: Vec<BridgeDefinition> = Vec::new();
+        for bridge in bridges.bridges {
+            let valid_name = !bridge.name.is_empty()
+                && bridge
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                bail!(
+                    "bridge name `{}` must consist of letters, digits, `-` and `_`",
+                    bridge.name
+                );
+            }
+            if definitions.iter().any(|other| other.name == bridge.name) {
+                bail!("bridge name `{}` is used twice", bridge.name);
+            }
+            if definitions
+                .iter()
+                .any(|other| other.database == bridge.database)
+            {
+                bail!(
+                    "bridge `{}` shares the database {:?} with another bridge",
+                    bridge.name,
+                    bridge.database
+                );
+            }
+            let config = Config::load(&bridge.config).chain_err(|| {
+                format!("Cannot load config {:?} of bridge `{}`", bridge.config, bridge.name)
+            })?;
+            definitions.push(BridgeDefinition {
+                name: bridge.name,
+                config,
+                database: bridge.database,
+            });
+        }
+        // the http api, the postgres sink and the opentelemetry export serve the whole process
+        if count_configured(&definitions, |config| config.http_api.is_some()) > 1 {
+            bail!("only one bridge of the process can configure `http_api`");
+        }
+        if count_configured(&definitions, |config| config.postgres.is_some()) > 1 {
+            bail!("only one bridge of the process can configure `postgres`");
+        }
+        if count_configured(&definitions, |config| config.otlp.is_some()) > 1 {
+            bail!("only one bridge of the process can configure `otlp`");
+        }
+        Ok(BridgesConfig {
+            log_format: match bridges.log_format {
+                None | Some(load::LogFormat::Text) => LogFormat::Text,
+                Some(load::LogFormat::Json) => LogFormat::Json,
             },
-            ipc: node.ipc,
-            request_timeout: Duration::from_secs(node.request_timeout.unwrap_or(DEFAULT_TIMEOUT)),
-            poll_interval: Duration::from_secs(node.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL)),
-            required_confirmations: node.required_confirmations.unwrap_or(DEFAULT_CONFIRMATIONS),
+            bridges: definitions,
+        })
+    }
+}
+
+/// returns the number of `definitions` whose config satisfies `configured`
+fn count_configured<F: Fn(&Config) -> bool>(
+    definitions: &[BridgeDefinition],
+    configured: F,
+) -> usize {
+    definitions
+        .iter()
+        .filter(|definition| configured(&definition.config))
+        .count()
+}
+
+/// files whose existence pauses a relay direction
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct PauseFiles {
+    pub deposits: Option<PathBuf>,
+    pub withdraws: Option<PathBuf>,
+}
+
+/// limits on the value this authority relays in a direction, independent of the contracts.
+/// see `value_limits`
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct ValueLimit {
+    /// transfers of more value are skipped. unlimited if `None`
+    pub max_single_value: Option<U256>,
+    /// relaying pauses once the value relayed within 24 hours would exceed it.
+    /// unlimited if `None`
+    pub max_daily_value: Option<U256>,
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct ValueLimits {
+    /// limits of deposit relays
+    pub deposits: ValueLimit,
+    /// limits of withdraw confirmations
+    pub withdraws: ValueLimit,
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct UnaccountedTransfersConfig {
+    /// file that detected transfers are appended to as lines of json
+    pub audit_log: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MessageCallsConfig {
+    /// gas of the transactions that submit signatures of calls to `ForeignBridge`
+    pub confirm_gas: u64,
+    /// gas of `HomeBridge.executeCall` on top of the gas limit of the call
+    pub relay_gas: u64,
+    /// calls that request a higher gas limit are neither signed nor relayed
+    pub max_gas_limit: u64,
+    /// calls with more bytes of data are neither signed nor relayed
+    pub max_data_length: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MetricsConfig {
+    /// file that the metrics are written to as json
+    pub file: PathBuf,
+    pub export_interval: Duration,
+}
+
+/// duties this instance performs for its authority
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Role {
+    /// relays deposits, signs withdraws and relays withdraws
+    Full,
+    /// relays deposits and signs withdraws but never sends transactions to `home`.
+    /// withdraws are executed by their recipients or a designated executor
+    Signer,
+}
+
+impl Role {
+    /// whether this instance sends transactions that move funds on `home`
+    pub fn executes_withdraws(&self) -> bool {
+        *self == Role::Full
+    }
+}
+
+/// format of the withdraw messages the authorities sign (see `message_to_mainnet`)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MessageVersion {
+    /// the fields without chain ids. accepted by every deployment
+    V1,
+    /// the fields prefixed with the chain ids of `home` and `foreign`.
+    /// only accepted by contracts deployed with both chain ids
+    V2,
+}
+
+/// format of the lines of the log (see `logging`)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LogFormat {
+    /// human readable text
+    Text,
+    /// one json object per line
+    Json,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct HttpApiConfig {
+    /// address the http api listens on
+    pub address: SocketAddr,
+    /// interval between two updates of the latest blocks and balances shown by the dashboard
+    pub status_interval: Duration,
+    /// file holding the token that authenticates requests to pause and resume relays
+    /// and to replay dead letters.
+    /// `None` disables these requests
+    pub admin_token_file: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SignerConfig {
+    /// unix socket of the signer daemon
+    pub socket: PathBuf,
+    /// file holding the token that authenticates requests to the signer daemon
+    pub token_file: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeystoreConfig {
+    /// encrypted json keystore (version 3) of the authority accounts
+    pub path: PathBuf,
+    /// file holding the password of the keystore
+    pub password_file: PathBuf,
+}
+
+/// message queue the lifecycle of transfers is published to
+#[derive(Debug, PartialEq, Clone)]
+pub enum EventQueueConfig {
+    Kafka {
+        /// `host:port` of the bootstrap brokers
+        brokers: Vec<String>,
+        topic: String,
+    },
+    Nats {
+        /// `host:port` of the nats server
+        address: String,
+        subject: String,
+    },
+}
+
+impl EventQueueConfig {
+    fn from_load_struct(event_queue: load::EventQueue) -> Result<Self, Error> {
+        let config = match (event_queue.kafka, event_queue.nats) {
+            (Some(kafka), None) => {
+                if kafka.brokers.is_empty() {
+                    bail!("`event_queue.kafka.brokers` must not be empty");
+                }
+                EventQueueConfig::Kafka {
+                    brokers: kafka.brokers,
+                    topic: kafka.topic,
+                }
+            }
+            (None, Some(nats)) => EventQueueConfig::Nats {
+                address: nats.address,
+                subject: nats.subject,
+            },
+            _ => bail!("`event_queue` requires exactly one of `kafka` and `nats`"),
+        };
+        if !config.is_enabled() {
+            bail!(
+                "`event_queue` is not supported by this binary. rebuild it with feature `bridge/{}`",
+                config.feature()
+            );
+        }
+        Ok(config)
+    }
+
+    /// whether the sink of this queue is compiled in
+    pub fn is_enabled(&self) -> bool {
+        match *self {
+            EventQueueConfig::Kafka { .. } => cfg!(feature = "event-queue-kafka"),
+            EventQueueConfig::Nats { .. } => cfg!(feature = "event-queue-nats"),
+        }
+    }
+
+    /// name of the cargo feature that compiles in the sink of this queue
+    pub fn feature(&self) -> &'static str {
+        match *self {
+            EventQueueConfig::Kafka { .. } => "event-queue-kafka",
+            EventQueueConfig::Nats { .. } => "event-queue-nats",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PostgresConfig {
+    /// connection url (example: `postgres://bridge@localhost/bridge`)
+    pub url: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SqliteConfig {
+    /// file of the database. created if it doesn't exist
+    pub path: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OtlpConfig {
+    /// `host:port`, reached over http, or `http://` or `https://` url
+    /// of the OTLP/HTTP receiver of the collector
+    pub endpoint: String,
+    /// `service.name` of the exported resource
+    pub service_name: String,
+    /// interval between two exports
+    pub export_interval: Duration,
+}
+
+/// partitions of the events of an authority leased by the processes relaying them
+/// from a table in postgres they share (see `shard`). every process has its own database
+#[derive(Debug, PartialEq, Clone)]
+pub struct ShardConfig {
+    /// url of the postgres database with the lease table
+    pub url: String,
+    /// name of this process in the lease table. a random name per start if `None`
+    pub owner: Option<String>,
+    pub partition_by: PartitionBy,
+    /// number of partitions of the events of a bridge partitioned by `Hash`
+    pub partitions: u64,
+    /// time a lease is held without being renewed.
+    /// the leases are renewed three times within it
+    pub lease_duration: Duration,
+}
+
+impl ShardConfig {
+    fn from_load_struct(shard: load::Shard) -> Self {
+        ShardConfig {
+            url: shard.url,
+            owner: shard.owner,
+            partition_by: match shard.partition_by {
+                None | Some(load::PartitionBy::Hash) => PartitionBy::Hash,
+                Some(load::PartitionBy::Token) => PartitionBy::Token,
+            },
+            partitions: shard.partitions.unwrap_or(DEFAULT_SHARD_PARTITIONS),
+            lease_duration: Duration::from_secs(
+                shard
+                    .lease_duration
+                    .unwrap_or(DEFAULT_SHARD_LEASE_DURATION),
+            ),
+        }
+    }
+}
+
+/// how the events of an authority are partitioned among its processes
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PartitionBy {
+    /// by the transaction hash of the event modulo `partitions`
+    Hash,
+    /// by the token of the bridge. all events of a bridge are one partition
+    /// so the bridges of a bridges file are spread among the processes
+    Token,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClockConfig {
+    /// maintenance windows are widened by this on both ends
+    pub skew_tolerance: Duration,
+    /// divergence between the clock of this host and the time of a chain that is warned about
+    pub max_divergence: Duration,
+    /// interval between two observations of the time of the chains
+    pub check_interval: Duration,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig {
+            skew_tolerance: Duration::from_secs(DEFAULT_SKEW_TOLERANCE),
+            max_divergence: Duration::from_secs(DEFAULT_MAX_CLOCK_DIVERGENCE),
+            check_interval: Duration::from_secs(DEFAULT_CLOCK_CHECK_INTERVAL),
+        }
+    }
+}
+
+impl ClockConfig {
+    fn from_load_struct(clock: load::Clock) -> Self {
+        ClockConfig {
+            skew_tolerance: Duration::from_secs(
+                clock.skew_tolerance.unwrap_or(DEFAULT_SKEW_TOLERANCE),
+            ),
+            max_divergence: Duration::from_secs(
+                clock.max_divergence.unwrap_or(DEFAULT_MAX_CLOCK_DIVERGENCE),
+            ),
+            check_interval: Duration::from_secs(
+                clock.check_interval.unwrap_or(DEFAULT_CLOCK_CHECK_INTERVAL),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RevertedRelaysConfig {
+    /// attempts of a relay whose simulation reverted before it is given up.
+    /// the first attempt is the one of the relay stream
+    pub max_attempts: u32,
+    /// interval between two attempts
+    pub retry_interval: Duration,
+}
+
+impl Default for RevertedRelaysConfig {
+    fn default() -> Self {
+        RevertedRelaysConfig {
+            max_attempts: DEFAULT_REVERTED_RELAY_ATTEMPTS,
+            retry_interval: Duration::from_secs(DEFAULT_REVERTED_RELAY_RETRY_INTERVAL),
+        }
+    }
+}
+
+impl RevertedRelaysConfig {
+    fn from_load_struct(reverted_relays: load::RevertedRelays) -> Self {
+        RevertedRelaysConfig {
+            max_attempts: reverted_relays
+                .max_attempts
+                .unwrap_or(DEFAULT_REVERTED_RELAY_ATTEMPTS),
+            retry_interval: Duration::from_secs(
+                reverted_relays
+                    .retry_interval
+                    .unwrap_or(DEFAULT_REVERTED_RELAY_RETRY_INTERVAL),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MaxLagConfig {
+    /// blocks a component may lag behind the latest block
+    pub blocks: u64,
+    /// time a component may lag behind by more than `blocks` before the process exits
+    pub duration: Duration,
+    /// interval between two checks of the lag
+    pub check_interval: Duration,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TokenFeeConfig {
+    /// tokens of every deposit that are kept by `ForeignBridge.depositWithFee`
+    /// and split among the confirming authorities. all authorities have to charge the same fee
+    pub deposit_fee: U256,
+    /// interval between two checks of the unclaimed relay fees of `foreign.account`
+    pub claim_interval: Duration,
+    /// relay fees are only claimed once they reach this many tokens
+    pub min_claim: U256,
+    /// whether claimed relay fees are transferred to `home.account`
+    /// via `ForeignBridge.transferHomeViaRelay`
+    pub transfer_home: bool,
+}
+
+/// config option that holds an address
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AddressField {
+    HomeAccount,
+    ForeignAccount,
+    HomeRegistry,
+    ForeignRegistry,
+    Authority(usize),
+    FallbackAccount(usize),
+}
+
+impl fmt::Display for AddressField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddressField::HomeAccount => write!(f, "home.account"),
+            AddressField::ForeignAccount => write!(f, "foreign.account"),
+            AddressField::HomeRegistry => write!(f, "home.registry.address"),
+            AddressField::ForeignRegistry => write!(f, "foreign.registry.address"),
+            AddressField::Authority(index) => write!(f, "authorities.accounts[{}]", index),
+            AddressField::FallbackAccount(index) => {
+                write!(f, "foreign.fallback_accounts[{}]", index)
+            }
+        }
+    }
+}
+
+/// ENS name that was configured in place of the address of `field`
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnsName {
+    pub field: AddressField,
+    pub name: String,
+}
+
+/// returns a config file with every option, comments and defaults.
+/// `home_bin` and `foreign_bin` are the paths to the compiled contracts.
+pub fn template(home_bin: &Path, foreign_bin: &Path) -> String {
+    format!(
+        r#"# READ THE CONFIG DOCUMENTATION AT:
+# https://github.com/paritytech/parity-bridge/#configuration
+
+# uncomment to take the chain ids, confirmation depths, poll intervals, gas and
+# ENS registry of a known pair of chains from a preset. options set here take precedence.
+# one of {networks}
+# network = "kovan-sokol"
+
+# upper bound on the gas a transaction to `HomeBridge.withdraw` consumes.
+# run `tools/estimate_gas_costs.sh` to compute an estimate.
+estimated_gas_cost_of_withdraw = "200_000"
+
+# limits total balance on `home` and therefore total ether that could get lost
+# if the bridge is faulty or compromised in any way!
+# set to `"0"` to disable limit (not recommended at this point)
+# currently set to 10 ether.
+max_total_home_contract_balance = "10_000_000_000_000_000_000"
+
+# limit `msg.value` for a single deposit into the home contract.
+# set to `"0"` to disable limit (not recommended at this point)
+# currently set to 1 ether.
+max_single_deposit_value = "1_000_000_000_000_000_000"
+
+# generation of the bridge contracts. `"v1"` for the ether bridge and `"v2"` for a token bridge
+contract_version = "v1"
+
+# format of the withdraw messages the authorities sign. `"v2"` binds the signatures to
+# `home.chain_id` and `foreign.chain_id` and requires contracts deployed with both chain ids
+message_version = "v1"
+
+# uncomment to verify the deployment against the manifest written by `parity-bridge-deploy`
+# before starting. a missing database is created from it
+# deployment_manifest = "deployment.toml"
+
+# `"full"` or `"signer"`. a signer never sends transactions to `home`
+role = "full"
+
+# `"text"` or `"json"`. json lines carry the hash of the originating transaction of each relay
+log_format = "text"
+
+# ENS registry on `home` to resolve ENS names used in place of addresses
+ens_registry = "0x{ens_registry}"
+
+# uncomment to pause a relay direction while the file exists
+# [pause_files]
+# deposits = "pause-deposits"
+# withdraws = "pause-withdraws"
+
+# uncomment to limit the value this authority relays per direction independent of the contracts.
+# transfers above `max_single_value` are skipped. relaying pauses once the value relayed
+# within 24 hours would exceed `max_daily_value` until the 24 hours are over
+# [value_limits.deposits]
+# max_single_value = "1_000_000_000_000_000_000"
+# max_daily_value = "10_000_000_000_000_000_000"
+# [value_limits.withdraws]
+# max_single_value = "1_000_000_000_000_000_000"
+# max_daily_value = "10_000_000_000_000_000_000"
+
+# uncomment to detect ether that reaches `HomeBridge` without a deposit.
+# requires the node of `home.ipc` to run with `--tracing on`
+# [unaccounted_transfers]
+# audit_log = "unaccounted_transfers.log"
+
+# uncomment to relay calls requested via `ForeignBridge.requestCall` to `HomeBridge.executeCall`
+# [message_calls]
+# confirm_gas = 300000
+# relay_gas = 200000
+# max_gas_limit = 1000000
+# max_data_length = 1024
+
+# halt signing of withdraws while `HomeBridge` can't cover the pending withdraws
+solvency_check = false
+
+# uncomment to record every signature this authority produces
+# signature_audit_log = "signatures.log"
+
+# read submitted signatures back from `ForeignBridge` and halt signing of withdraws on a mismatch
+verify_signatures = true
+
+# uncomment to record every deposit and withdraw this authority relays
+# relay_history = "relays.log"
+
+# uncomment to publish the lifecycle of transfers to kafka or nats.
+# requires the binary to be built with feature `bridge/event-queue-kafka` or `bridge/event-queue-nats`
+# [event_queue]
+# kafka = {{ brokers = ["127.0.0.1:9092"], topic = "bridge-transfers" }}
+# nats = {{ address = "127.0.0.1:4222", subject = "bridge.transfers" }}
+
+# uncomment to mirror the relay history and the lifecycle of transfers into postgres.
+# requires the binary to be built with feature `bridge/postgres-sink`
+# [postgres]
+# url = "postgres://bridge@localhost/bridge"
+
+# uncomment to keep the checkpoints and a record of every processed transfer in sqlite.
+# the checkpoints are imported from the database file on the first start.
+# requires the binary to be built with feature `bridge/sqlite`
+# [sqlite]
+# path = "bridge.sqlite"
+
+# uncomment to export traces of the transfers and metrics to an opentelemetry collector
+# [otlp]
+# host:port of the OTLP/HTTP receiver, or its http:// or https:// url
+# endpoint = "127.0.0.1:4318"
+# service_name = "{otlp_service_name}"
+# seconds between two exports
+# export_interval = {otlp_export_interval}
+
+# uncomment to export metrics of the rpc calls to the nodes
+# [metrics]
+# file = "metrics.json"
+# export_interval = 10
+
+# uncomment to serve the http api and the dashboard at `http://<address>/`
+# [http_api]
+# address = "127.0.0.1:8080"
+# status_interval = 10
+# uncomment to allow pausing and resuming relays via `POST /pause/<direction>`
+# and replaying dead letters via `POST /replay`
+# admin_token_file = "http-admin.token"
+
+# uncomment to have a separate `parity-bridge signer` hold the keys of this authority
+# [signer]
+# socket = "signer.sock"
+# token_file = "signer.token"
+
+# uncomment to sign with an encrypted json keystore instead of unlocked accounts on the nodes.
+# the keystore holds the key of `home.account` and `foreign.account`
+# [keystore]
+# path = "keys/authority.json"
+# password_file = "keys/authority.pwd"
+
+# uncomment to split the relay work of this authority among several processes.
+# they lease the partitions of the events from a table in a postgres database they share.
+# give every process its own database
+# [shard]
+# url = "postgres://bridge@localhost/leases"
+# "hash" partitions the events by transaction hash, "token" by the token of the bridge
+# partition_by = "hash"
+# partitions = {shard_partitions}
+# seconds a lease is held without being renewed by its process
+# lease_duration = {shard_lease_duration}
+
+# uncomment to change how the clock of this host is compared with the time of the chains.
+# maintenance windows follow the timestamps of the latest blocks
+# [clock]
+# seconds maintenance windows are widened by on both ends
+# skew_tolerance = {skew_tolerance}
+# seconds the clock of this host may diverge from the time of a chain before it is warned about
+# max_divergence = {max_divergence}
+# seconds between two observations of the time of the chains
+# check_interval = {check_interval}
+
+# uncomment to exit with code {max_lag_exit_code} when a component lags more than `blocks` behind
+# the latest block for longer than `duration` seconds, so a supervisor can restart the process
+# [max_lag]
+# blocks = 100
+# duration = 600
+# seconds between two checks of the lag
+# check_interval = {lag_check_interval}
+
+# uncomment to change how relays whose simulation reverted are retried.
+# they are listed at `GET /parked` of the http api while they are retried
+# and at `GET /dead_letters` once they are given up
+# [reverted_relays]
+# attempts before a relay is given up, including the first one
+# max_attempts = {reverted_relay_attempts}
+# seconds between two attempts
+# retry_interval = {reverted_relay_retry_interval}
+
+# uncomment to charge a relay fee in tokens on every deposit. all authorities have to set the same
+# `deposit_fee`. the fees this authority earns are claimed periodically and, with `transfer_home`,
+# transferred to `home.account` to reimburse the gas spent on relaying
+# [token_fee]
+# deposit_fee = "1_000_000_000_000_000"
+# seconds between two checks of the unclaimed fees
+# claim_interval = {fee_claim_interval}
+# min_claim = "10_000_000_000_000_000"
+# transfer_home = false
+
+[home]
+# ACTION REQUIRED: set to your authority address
+account = "0x0000000000000000000000000000000000000000"
+# ACTION REQUIRED: set to the ipc socket of the parity node that has `home.account` unlocked
+ipc = "home.ipc"
+# or reach the node via json-rpc over http or a websocket instead of `ipc`
+# http = "http://127.0.0.1:8545"
+# ws = "ws://127.0.0.1:8546"
+# https:// and wss:// urls are reached over tls. the certificate of the node is checked against
+# the system roots and `ca_bundle`. `client_identity` is a pkcs#12 file for mutual tls
+# tls = { ca_bundle = "node-ca.pem", client_identity = "bridge.p12", client_identity_password_file = "bridge.pass" }
+# gzip responses over `http` if the node supports it
+compression = false
+# gzip the bodies of requests over `http`. only for nodes or proxies that accept them gzipped
+compress_requests = false
+required_confirmations = {confirmations}
+# "depth" or "pinned_hash" which also checks that the confirmed blocks weren't replaced.
+# "safe" or "finalized" follow the block tags of proof of stake chains instead.
+# "delay" confirms blocks `confirmation_delay` seconds after they were first seen
+confirmation_strategy = "depth"
+# number of recent checkpoints kept to rewind to after a reorg. 0 disables reorg detection
+reorg_checkpoints = {reorg_checkpoints}
+# seconds between polls of the home node for changes
+poll_interval = {poll_interval}
+# upper bound in seconds of the interval between polls while no new block is due
+max_poll_interval = {max_poll_interval}
+# learn about new blocks from an `eth_subscribe` subscription via `ipc` or `ws` instead of polls
+subscribe_new_heads = false
+# seconds to wait for the response to a single request
+request_timeout = {request_timeout}
+# seconds after which a relay attempt is abandoned and rescheduled
+relay_deadline = {relay_deadline}
+# attempts after which the relay of a transaction fails
+max_relay_attempts = {max_relay_attempts}
+# upper bound in milliseconds of a random delay before relaying a transaction
+max_submission_delay = 0
+# attach EIP-2930 access lists to relay transactions
+access_lists = false
+# one of "legacy", "eip2930", "eip1559" or "auto"
+transaction_type = "auto"
+# uncomment to refuse nodes of another chain
+# chain_id = 1
+# uncomment to bound the rate of calls to the home node
+# max_requests_per_second = 10
+# uncomment on both chains with `contract_version = "v2"` to run a token bridge of this ERC20 token on `home`
+# token_address = "0x0000000000000000000000000000000000000000"
+
+[home.contract]
+bin = "{home_bin}"
+
+# uncomment to resolve the address of `HomeBridge` from a registry
+# [home.registry]
+# address = "0x0000000000000000000000000000000000000000"
+# name = "HomeBridge"
+
+# uncomment to price relay transactions to `home` with the quotes of a gas price oracle
+# or `eth_gasPrice` instead of the `gas_price` of `[transactions]`
+# [home.gas_price_oracle]
+# url = "http://127.0.0.1:8080/gasprice"
+# pointer = "{gas_price_pointer}"
+# wei_per_unit = {gas_price_wei_per_unit}
+# refresh_interval = {gas_price_refresh_interval}
+# max_gas_price = 200_000_000_000
+
+# uncomment to replace relay transactions to `home` that aren't mined within `timeout` seconds
+# with transactions of the same nonce and a gas price raised by `gas_price_bump` percent
+# [home.stuck_transactions]
+# timeout = {stuck_transaction_timeout}
+# gas_price_bump = {gas_price_bump}
+# max_gas_price = 200_000_000_000
+
+# uncomment to check the balance of `home.account` every `check_interval` seconds.
+# below `warn_balance` wei a warning is logged. below `min_balance` wei relay transactions
+# to `home` are paused and `alert_url` is posted an alert until the account is topped up
+# [home.balance_watchdog]
+# warn_balance = "1_000_000_000_000_000_000"
+# min_balance = "100_000_000_000_000_000"
+# check_interval = {balance_check_interval}
+# alert_url = "http://127.0.0.1:8080/alerts"
+
+# calls to the home node that fail for transient reasons (connection errors, timeouts,
+# `header not found` of a node that is catching up) are retried after an exponential backoff
+# in milliseconds. uncomment to tune it. `max_retries = 0` disables retries
+# [home.retry]
+# max_retries = {max_retries}
+# initial_backoff = {initial_backoff}
+# max_backoff = {max_backoff}
+# jitter = true
+
+# uncomment to defer transactions to `home` during a recurring window (UTC)
+# [[home.maintenance_windows]]
+# days = ["saturday", "sunday"]
+# start = "02:00"
+# duration = 7200
+
+[foreign]
+# ACTION REQUIRED: set to your authority address
+account = "0x0000000000000000000000000000000000000000"
+# ACTION REQUIRED: set to the ipc socket of the parity node that has `foreign.account` unlocked
+ipc = "foreign.ipc"
+# or reach the node via json-rpc over http or a websocket instead of `ipc`
+# http = "http://127.0.0.1:8545"
+# ws = "ws://127.0.0.1:8546"
+# https:// and wss:// urls are reached over tls. the certificate of the node is checked against
+# the system roots and `ca_bundle`. `client_identity` is a pkcs#12 file for mutual tls
+# tls = { ca_bundle = "node-ca.pem", client_identity = "bridge.p12", client_identity_password_file = "bridge.pass" }
+# gzip responses over `http` if the node supports it
+compression = false
+# gzip the bodies of requests over `http`. only for nodes or proxies that accept them gzipped
+compress_requests = false
+required_confirmations = {confirmations}
+# "depth" or "pinned_hash" which also checks that the confirmed blocks weren't replaced.
+# "safe" or "finalized" follow the block tags of proof of stake chains instead.
+# "delay" confirms blocks `confirmation_delay` seconds after they were first seen
+confirmation_strategy = "depth"
+# number of recent checkpoints kept to rewind to after a reorg. 0 disables reorg detection
+reorg_checkpoints = {reorg_checkpoints}
+# seconds between polls of the foreign node for changes
+poll_interval = {poll_interval}
+# upper bound in seconds of the interval between polls while no new block is due
+max_poll_interval = {max_poll_interval}
+# learn about new blocks from an `eth_subscribe` subscription via `ipc` or `ws` instead of polls
+subscribe_new_heads = false
+# seconds to wait for the response to a single request
+request_timeout = {request_timeout}
+# seconds after which a relay attempt is abandoned and rescheduled
+relay_deadline = {relay_deadline}
+# attempts after which the relay of a transaction fails
+max_relay_attempts = {max_relay_attempts}
+# upper bound in milliseconds of a random delay before relaying a transaction
+max_submission_delay = 0
+# attach EIP-2930 access lists to relay transactions
+access_lists = false
+# one of "legacy", "eip2930", "eip1559" or "auto"
+transaction_type = "auto"
+# uncomment to refuse nodes of another chain
+# chain_id = 1
+# uncomment to bound the rate of calls to the foreign node
+# max_requests_per_second = 10
+# uncomment on both chains with `contract_version = "v2"` to run a token bridge of this ERC20 token on `foreign`
+# token_address = "0x0000000000000000000000000000000000000000"
+# uncomment to sign with further accounts of this authority in order
+# while the signer of `foreign.account` is unavailable
+# fallback_accounts = ["0x0000000000000000000000000000000000000000"]
+
+[foreign.contract]
+bin = "{foreign_bin}"
+
+# uncomment to resolve the address of `ForeignBridge` from a registry
+# [foreign.registry]
+# address = "0x0000000000000000000000000000000000000000"
+# name = "ForeignBridge"
+
+# uncomment to price relay transactions to `foreign` with the quotes of a gas price oracle
+# or `eth_gasPrice` instead of the `gas_price` of `[transactions]`
+# [foreign.gas_price_oracle]
+# url = "http://127.0.0.1:8080/gasprice"
+# pointer = "{gas_price_pointer}"
+# wei_per_unit = {gas_price_wei_per_unit}
+# refresh_interval = {gas_price_refresh_interval}
+# max_gas_price = 200_000_000_000
+
+# uncomment to replace relay transactions to `foreign` that aren't mined within `timeout` seconds
+# with transactions of the same nonce and a gas price raised by `gas_price_bump` percent
+# [foreign.stuck_transactions]
+# timeout = {stuck_transaction_timeout}
+# gas_price_bump = {gas_price_bump}
+# max_gas_price = 200_000_000_000
+
+# uncomment to check the balance of `foreign.account` every `check_interval` seconds.
+# below `warn_balance` wei a warning is logged. below `min_balance` wei relay transactions
+# to `foreign` are paused and `alert_url` is posted an alert until the account is topped up
+# [foreign.balance_watchdog]
+# warn_balance = "1_000_000_000_000_000_000"
+# min_balance = "100_000_000_000_000_000"
+# check_interval = {balance_check_interval}
+# alert_url = "http://127.0.0.1:8080/alerts"
+
+# calls to the foreign node that fail for transient reasons (connection errors, timeouts,
+# `header not found` of a node that is catching up) are retried after an exponential backoff
+# in milliseconds. uncomment to tune it. `max_retries = 0` disables retries
+# [foreign.retry]
+# max_retries = {max_retries}
+# initial_backoff = {initial_backoff}
+# max_backoff = {max_backoff}
+# jitter = true
+
+# uncomment to defer transactions to `foreign` during a recurring window (UTC)
+# [[foreign.maintenance_windows]]
+# days = ["saturday", "sunday"]
+# start = "02:00"
+# duration = 7200
+
+[authorities]
+# ACTION REQUIRED: set this to the addresses of the authority list
+accounts = [
+	"0x0000000000000000000000000000000000000000",
+]
+# ACTION REQUIRED: set this to a (super-)majority of `authorities.accounts`
+# example: set to 3 for 5 authorities. set to 7 for 10 authorities
+required_signatures = 1
+
+[transactions]
+# ACTION REQUIRED: you have to set `gas_price` for each transaction
+# if your authority can't do free transactions on the chain.
+
+# these happen on `home`:
+home_deploy = {{ gas = 1_000_000, gas_price = 0 }}
+withdraw_relay = {{ gas = 200_000, gas_price = 0 }}
+
+# these happen on `foreign`:
+foreign_deploy = {{ gas = 3_000_000, gas_price = 0 }}
+deposit_relay = {{ gas = 150_000, gas_price = 0 }}
+withdraw_confirm = {{ gas = 300_000, gas_price = 0 }}
+# only used with `[token_fee]`
+fee_claim = {{ gas = 100_000, gas_price = 0 }}
+# uncomment to confirm up to `max_batch_size` deposits per transaction via `ForeignBridge.depositBatch`.
+# a batch gets `deposit_relay.gas` per deposit
+# max_batch_size = 10
+# relays to `foreign` are sent as EIP-1559 transactions if `max_priority_fee_per_gas` is set.
+# `max_fee_per_gas` defaults to the gas price. example:
+# deposit_relay = {{ gas = 150_000, max_fee_per_gas = 40_000_000_000, max_priority_fee_per_gas = 2_000_000_000 }}
+"#,
+        ens_registry = MAINNET_REGISTRY,
+        confirmations = DEFAULT_CONFIRMATIONS,
+        reorg_checkpoints = DEFAULT_REORG_CHECKPOINTS,
+        poll_interval = DEFAULT_POLL_INTERVAL,
+        max_poll_interval = DEFAULT_MAX_POLL_INTERVAL,
+        request_timeout = DEFAULT_TIMEOUT,
+        relay_deadline = DEFAULT_RELAY_DEADLINE,
+        max_relay_attempts = DEFAULT_MAX_RELAY_ATTEMPTS,
+        skew_tolerance = DEFAULT_SKEW_TOLERANCE,
+        max_divergence = DEFAULT_MAX_CLOCK_DIVERGENCE,
+        check_interval = DEFAULT_CLOCK_CHECK_INTERVAL,
+        max_lag_exit_code = MAX_LAG_EXIT_CODE,
+        lag_check_interval = DEFAULT_LAG_CHECK_INTERVAL,
+        fee_claim_interval = DEFAULT_FEE_CLAIM_INTERVAL,
+        reverted_relay_attempts = DEFAULT_REVERTED_RELAY_ATTEMPTS,
+        reverted_relay_retry_interval = DEFAULT_REVERTED_RELAY_RETRY_INTERVAL,
+        shard_partitions = DEFAULT_SHARD_PARTITIONS,
+        shard_lease_duration = DEFAULT_SHARD_LEASE_DURATION,
+        otlp_service_name = DEFAULT_OTLP_SERVICE_NAME,
+        otlp_export_interval = DEFAULT_OTLP_EXPORT_INTERVAL,
+        gas_price_pointer = DEFAULT_GAS_PRICE_POINTER,
+        gas_price_wei_per_unit = DEFAULT_GAS_PRICE_WEI_PER_UNIT,
+        gas_price_refresh_interval = DEFAULT_GAS_PRICE_REFRESH_INTERVAL,
+        stuck_transaction_timeout = DEFAULT_STUCK_TRANSACTION_TIMEOUT,
+        gas_price_bump = DEFAULT_GAS_PRICE_BUMP,
+        balance_check_interval = DEFAULT_BALANCE_CHECK_INTERVAL,
+        max_retries = DEFAULT_MAX_RETRIES,
+        initial_backoff = DEFAULT_INITIAL_BACKOFF,
+        max_backoff = DEFAULT_MAX_BACKOFF,
+        networks = network::names().join(", "),
+        home_bin = home_bin.display(),
+        foreign_bin = foreign_bin.display(),
+    )
+}
+
+/// ERC20 tokens of a token bridge
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Tokens {
+    /// token held by `HomeTokenBridge`
+    pub home: Address,
+    /// token held by `ForeignTokenBridge`
+    pub foreign: Address,
+}
+
+/// how the node of a chain is reached
+#[derive(Debug, PartialEq, Clone)]
+pub enum NodeEndpoint {
+    /// path of its ipc socket
+    Ipc(PathBuf),
+    /// `http://` or `https://` url of its json-rpc endpoint
+    Http(String),
+    /// `ws://` or `wss://` url of its websocket endpoint
+    Ws(String),
+}
+
+impl NodeEndpoint {
+    /// whether the endpoint is reached over tls
+    pub fn is_tls(&self) -> bool {
+        match *self {
+            NodeEndpoint::Ipc(_) => false,
+            NodeEndpoint::Http(ref url) => url.starts_with("https://"),
+            NodeEndpoint::Ws(ref url) => url.starts_with("wss://"),
+        }
+    }
+}
+
+impl fmt::Display for NodeEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NodeEndpoint::Ipc(ref path) => write!(f, "{}", path.display()),
+            NodeEndpoint::Http(ref url) | NodeEndpoint::Ws(ref url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// tls towards a node reached over `https://` or `wss://` (see `tls`)
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TlsConfig {
+    /// pem file of certificates the certificate of the node is checked against
+    /// in addition to the system roots
+    pub ca_bundle: Option<PathBuf>,
+    /// pkcs#12 file of the certificate and key the bridge authenticates with
+    pub client_identity: Option<PathBuf>,
+    /// file that holds the password of `client_identity`
+    pub client_identity_password_file: Option<PathBuf>,
+    /// name sent in SNI and checked against the certificate of the node
+    /// instead of the host of the url
+    pub server_name: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Node {
+    pub account: Address,
+    pub contract: ContractConfig,
+    pub endpoint: NodeEndpoint,
+    pub request_timeout: Duration,
+    /// tls towards an `https://` or `wss://` endpoint
+    pub tls: TlsConfig,
+    /// whether responses over `http` are gzipped if the node supports it
+    pub compression: bool,
+    /// whether the bodies of requests over `http` are gzipped
+    pub compress_requests: bool,
+    /// time after which an attempt to relay a transaction
+    /// to this chain is abandoned and rescheduled
+    pub relay_deadline: Duration,
+    /// number of attempts after which relaying a transaction to this chain fails
+    pub max_relay_attempts: u32,
+    /// upper bound of the random delay before relaying a transaction to this chain
+    pub max_submission_delay: Duration,
+    /// lower bound of the interval between two polls of the latest block
+    pub poll_interval: Duration,
+    /// upper bound of the interval between two polls of the latest block.
+    /// polls back off towards it while no new block is due or no stream waits for one
+    pub max_poll_interval: Duration,
+    /// whether new blocks are announced by an `eth_subscribe("newHeads")` subscription
+    /// via `ipc` or `ws`. polls only serve as fallback then
+    pub subscribe_new_heads: bool,
+    pub required_confirmations: usize,
+    /// how logs on this chain are confirmed
+    pub confirmation_strategy: ConfirmationStrategy,
+    /// number of recent checkpoints of each log stream on this chain kept
+    /// to rewind to after a reorg. `0` disables reorg detection
+    pub reorg_checkpoints: usize,
+    /// chain id the node has to report. not checked if `None`
+    pub chain_id: Option<u64>,
+    /// whether relay transactions on this chain carry EIP-2930 access lists
+    pub access_lists: bool,
+    /// transaction envelope of relay transactions on this chain.
+    /// `None` if it should be detected at startup.
+    pub transaction_type: Option<TransactionType>,
+    /// registry to resolve the address of the bridge contract on this chain from
+    pub registry: Option<RegistryConfig>,
+    /// windows during which transactions to this chain are deferred
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+    /// upper bound of the rate of calls to the node. unbounded if `None`.
+    /// calls are paced further while the node limits them
+    pub max_requests_per_second: Option<u32>,
+    /// accounts of the same authority that sign in order when the signer of `account`
+    /// is unavailable. only used on `foreign`
+    pub fallback_accounts: Vec<Address>,
+    /// ERC20 token bridged on this chain. set on both chains for a token bridge
+    pub token_address: Option<Address>,
+    /// prices relay transactions to this chain instead of the `gas_price` of `txs`
+    pub gas_price_oracle: Option<GasPriceOracleConfig>,
+    /// replacement of relay transactions to this chain that don't get mined. disabled if `None`
+    pub stuck_transactions: Option<StuckTransactionsConfig>,
+    /// checks of the balance of `account`. disabled if `None`
+    pub balance_watchdog: Option<BalanceWatchdogConfig>,
+    /// backoff between the retries of calls to the node that failed for transient reasons
+    pub retry: RetryConfig,
+}
+
+impl Node {
+    /// returns how the access list of a relay transaction
+    /// with config `tx` on this chain is obtained
+    pub fn access_list_mode(&self, tx: &TransactionConfig) -> AccessListMode {
+        match (self.access_lists, tx.access_list.as_ref()) {
+            (false, _) => AccessListMode::Disabled,
+            (true, Some(access_list)) => AccessListMode::Static(access_list.clone()),
+            (true, None) => AccessListMode::Generate,
+        }
+    }
+
+    fn from_load_struct(
+        chain: &str,
+        node: load::Node,
+        account_field: AddressField,
+        registry_field: AddressField,
+        ens_names: &mut Vec<EnsName>,
+        preset: Option<&ChainPreset>,
+    ) -> Result<Node, Error> {
+        let endpoint = match (node.ipc, node.http, node.ws) {
+            (Some(ipc), None, None) => NodeEndpoint::Ipc(ipc),
+            (None, Some(http), None) => {
+                tls::parse_url(&http, "http")
+                    .chain_err(|| format!("`{}.http` is invalid", chain))?;
+                NodeEndpoint::Http(http.trim().to_owned())
+            }
+            (None, None, Some(ws)) => {
+                tls::parse_url(&ws, "ws")
+                    .chain_err(|| format!("`{}.ws` is invalid", chain))?;
+                NodeEndpoint::Ws(ws.trim().to_owned())
+            }
+            _ => bail!("`{}` requires exactly one of `ipc`, `http` and `ws`", chain),
+        };
+        let tls = match node.tls {
+            Some(tls) => {
+                if !endpoint.is_tls() {
+                    bail!("`{}.tls` requires an https:// or wss:// url", chain);
+                }
+                if tls.client_identity_password_file.is_some() && tls.client_identity.is_none() {
+                    bail!(
+                        "`{}.tls.client_identity_password_file` requires `{}.tls.client_identity`",
+                        chain,
+                        chain
+                    );
+                }
+                TlsConfig {
+                    ca_bundle: tls.ca_bundle,
+                    client_identity: tls.client_identity,
+                    client_identity_password_file: tls.client_identity_password_file,
+                    server_name: tls.server_name,
+                }
+            }
+            None => TlsConfig::default(),
+        };
+        if node.compression == Some(true) {
+            match endpoint {
+                NodeEndpoint::Http(_) => {}
+                _ => bail!("`{}.compression` requires `http`", chain),
+            }
+        }
+        if node.compress_requests == Some(true) {
+            match endpoint {
+                NodeEndpoint::Http(_) => {}
+                _ => bail!("`{}.compress_requests` requires `http`", chain),
+            }
+        }
+        if node.subscribe_new_heads == Some(true) {
+            if let NodeEndpoint::Http(_) = endpoint {
+                bail!("`{}.subscribe_new_heads` requires `ipc` or `ws`", chain);
+            }
+        }
+        let poll_interval = node.poll_interval
+            .or_else(|| preset.map(|preset| preset.poll_interval))
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        let confirmation_strategy = match (node.confirmation_strategy, node.confirmation_delay) {
+            (None, None) | (Some(load::ConfirmationStrategy::Depth), None) => {
+                ConfirmationStrategy::Depth
+            }
+            (Some(load::ConfirmationStrategy::PinnedHash), None) => {
+                ConfirmationStrategy::PinnedHash
+            }
+            (Some(load::ConfirmationStrategy::Safe), None) => ConfirmationStrategy::Safe,
+            (Some(load::ConfirmationStrategy::Finalized), None) => ConfirmationStrategy::Finalized,
+            (Some(load::ConfirmationStrategy::Delay), Some(delay)) => {
+                ConfirmationStrategy::Delay(Duration::from_secs(delay))
+            }
+            (Some(load::ConfirmationStrategy::Delay), None) => bail!(
+                "`{}.confirmation_strategy = \"delay\"` requires `{}.confirmation_delay`",
+                chain,
+                chain
+            ),
+            (_, Some(_)) => bail!(
+                "`{}.confirmation_delay` requires `{}.confirmation_strategy = \"delay\"`",
+                chain,
+                chain
+            ),
+        };
+        if node.max_relay_attempts == Some(0) {
+            bail!("`{}.max_relay_attempts` must be greater than 0", chain);
+        }
+        let result = Node {
+            account: node.account.into_address(account_field, ens_names),
+            contract: ContractConfig {
+                bin: {
+                    let mut read = String::new();
+                    let mut file = fs::File::open(&node.contract.bin).chain_err(|| {
+                        format!(
+                            "Cannot open compiled contract file at {}",
+                            node.contract.bin.to_string_lossy()
+                        )
+                    })?;
+                    file.read_to_string(&mut read)?;
+                    Bytes(read.from_hex()?)
+                },
+            },
+            endpoint,
+            request_timeout: Duration::from_secs(node.request_timeout.unwrap_or(DEFAULT_TIMEOUT)),
+            tls,
+            compression: node.compression.unwrap_or(false),
+            compress_requests: node.compress_requests.unwrap_or(false),
+            relay_deadline: Duration::from_secs(
+                node.relay_deadline.unwrap_or(DEFAULT_RELAY_DEADLINE),
+            ),
+            max_relay_attempts: node.max_relay_attempts.unwrap_or(DEFAULT_MAX_RELAY_ATTEMPTS),
+            max_submission_delay: Duration::from_millis(
+                node.max_submission_delay.unwrap_or_default(),
+            ),
+            poll_interval: Duration::from_secs(poll_interval),
+            max_poll_interval: Duration::from_secs(cmp::max(
+                poll_interval,
+                node.max_poll_interval.unwrap_or(DEFAULT_MAX_POLL_INTERVAL),
+            )),
+            subscribe_new_heads: node.subscribe_new_heads.unwrap_or(false),
+            required_confirmations: node.required_confirmations
+                .or_else(|| preset.map(|preset| preset.required_confirmations))
+                .unwrap_or(DEFAULT_CONFIRMATIONS),
+            chain_id: node.chain_id
+                .or_else(|| preset.and_then(|preset| preset.chain_id)),
+            confirmation_strategy,
+            reorg_checkpoints: node.reorg_checkpoints.unwrap_or(DEFAULT_REORG_CHECKPOINTS),
+            access_lists: node.access_lists.unwrap_or(false),
+            transaction_type: match node.transaction_type {
+                None | Some(load::TransactionType::Auto) => None,
+                Some(load::TransactionType::Legacy) => Some(TransactionType::Legacy),
+                Some(load::TransactionType::Eip2930) => Some(TransactionType::AccessList),
+                Some(load::TransactionType::Eip1559) => Some(TransactionType::DynamicFee),
+            },
+            registry: node.registry.map(|registry| RegistryConfig {
+                address: registry.address.into_address(registry_field, ens_names),
+                name: registry.name,
+            }),
+            maintenance_windows: node.maintenance_windows
+                .unwrap_or_default()
+                .into_iter()
+                .map(maintenance_window_from_load_struct)
+                .collect::<Result<_, _>>()?,
+            max_requests_per_second: node.max_requests_per_second,
+            fallback_accounts: node.fallback_accounts
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .map(|(index, account)| {
+                    account.into_address(AddressField::FallbackAccount(index), ens_names)
+                })
+                .collect(),
+            token_address: node.token_address.map(|address| address.0),
+            gas_price_oracle: match node.gas_price_oracle {
+                Some(oracle) => Some(gas_price_oracle_from_load_struct(oracle)?),
+                None => None,
+            },
+            stuck_transactions: match node.stuck_transactions {
+                Some(stuck) => Some(stuck_transactions_from_load_struct(chain, stuck)?),
+                None => None,
+            },
+            balance_watchdog: match node.balance_watchdog {
+                Some(watchdog) => Some(balance_watchdog_from_load_struct(chain, watchdog)?),
+                None => None,
+            },
+            retry: match node.retry {
+                Some(retry) => retry_from_load_struct(chain, retry)?,
+                None => RetryConfig::default(),
+            },
+        };
+
+        Ok(result)
+    }
+}
+
+fn gas_price_oracle_from_load_struct(
+    oracle: load::GasPriceOracle,
+) -> Result<GasPriceOracleConfig, Error> {
+    if let Some(ref url) = oracle.url {
+        tls::parse_url(url, "http").chain_err(|| "Invalid gas price oracle url")?;
+    }
+    let pointer = oracle
+        .pointer
+        .unwrap_or_else(|| DEFAULT_GAS_PRICE_POINTER.into());
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        bail!("pointer of gas price oracle must be empty or start with `/`");
+    }
+    Ok(GasPriceOracleConfig {
+        url: oracle.url,
+        pointer,
+        wei_per_unit: oracle.wei_per_unit.unwrap_or(DEFAULT_GAS_PRICE_WEI_PER_UNIT),
+        refresh_interval: Duration::from_secs(
+            oracle
+                .refresh_interval
+                .unwrap_or(DEFAULT_GAS_PRICE_REFRESH_INTERVAL),
+        ),
+        max_gas_price: oracle.max_gas_price,
+    })
+}
+
+fn stuck_transactions_from_load_struct(
+    chain: &str,
+    stuck: load::StuckTransactions,
+) -> Result<StuckTransactionsConfig, Error> {
+    let timeout = stuck.timeout.unwrap_or(DEFAULT_STUCK_TRANSACTION_TIMEOUT);
+    if timeout == 0 {
+        bail!("`{}.stuck_transactions.timeout` must be greater than 0", chain);
+    }
+    let gas_price_bump = stuck.gas_price_bump.unwrap_or(DEFAULT_GAS_PRICE_BUMP);
+    if gas_price_bump < MIN_GAS_PRICE_BUMP {
+        bail!(
+            "`{}.stuck_transactions.gas_price_bump` must be at least {} percent. nodes reject smaller bumps",
+            chain,
+            MIN_GAS_PRICE_BUMP
+        );
+    }
+    Ok(StuckTransactionsConfig {
+        timeout: Duration::from_secs(timeout),
+        gas_price_bump,
+        max_gas_price: stuck.max_gas_price.map(U256::from),
+    })
+}
+
+fn balance_watchdog_from_load_struct(
+    chain: &str,
+    watchdog: load::BalanceWatchdog,
+) -> Result<BalanceWatchdogConfig, Error> {
+    let min_balance = watchdog.min_balance.0;
+    let warn_balance = watchdog
+        .warn_balance
+        .map_or(min_balance, |warn_balance| warn_balance.0);
+    if warn_balance < min_balance {
+        bail!(
+            "`{0}.balance_watchdog.warn_balance` must not be less than `{0}.balance_watchdog.min_balance`",
+            chain
+        );
+    }
+    let check_interval = watchdog
+        .check_interval
+        .unwrap_or(DEFAULT_BALANCE_CHECK_INTERVAL);
+    if check_interval == 0 {
+        bail!("`{}.balance_watchdog.check_interval` must be greater than 0", chain);
+    }
+    if let Some(ref url) = watchdog.alert_url {
+        tls::parse_url(url, "http")
+            .chain_err(|| format!("Invalid `{}.balance_watchdog.alert_url`", chain))?;
+    }
+    Ok(BalanceWatchdogConfig {
+        warn_balance,
+        min_balance,
+        check_interval: Duration::from_secs(check_interval),
+        alert_url: watchdog.alert_url,
+    })
+}
+
+fn retry_from_load_struct(chain: &str, retry: load::Retry) -> Result<RetryConfig, Error> {
+    let initial_backoff = retry.initial_backoff.unwrap_or(DEFAULT_INITIAL_BACKOFF);
+    if initial_backoff == 0 {
+        bail!("`{}.retry.initial_backoff` must be greater than 0", chain);
+    }
+    let max_backoff = retry.max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF);
+    if max_backoff < initial_backoff {
+        bail!(
+            "`{0}.retry.max_backoff` must not be less than `{0}.retry.initial_backoff`",
+            chain
+        );
+    }
+    Ok(RetryConfig {
+        max_retries: retry.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        initial_backoff: Duration::from_millis(initial_backoff),
+        max_backoff: Duration::from_millis(max_backoff),
+        jitter: retry.jitter.unwrap_or(true),
+    })
+}
+
+fn maintenance_window_from_load_struct(
+    window: load::MaintenanceWindow,
+) -> Result<MaintenanceWindow, Error> {
+    if window.duration == 0 {
+        bail!("duration of maintenance window must be greater than 0");
+    }
+    Ok(MaintenanceWindow {
+        days: window
+            .days
+            .into_iter()
+            .map(|day| match day {
+                load::Weekday::Monday => Weekday::Monday,
+                load::Weekday::Tuesday => Weekday::Tuesday,
+                load::Weekday::Wednesday => Weekday::Wednesday,
+                load::Weekday::Thursday => Weekday::Thursday,
+                load::Weekday::Friday => Weekday::Friday,
+                load::Weekday::Saturday => Weekday::Saturday,
+                load::Weekday::Sunday => Weekday::Sunday,
+            })
+            .collect(),
+        start: window.start.0,
+        duration: Duration::from_secs(window.duration),
+    })
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct Transactions {
+    pub home_deploy: TransactionConfig,
+    pub foreign_deploy: TransactionConfig,
+    pub deposit_relay: TransactionConfig,
+    pub withdraw_confirm: TransactionConfig,
+    pub withdraw_relay: TransactionConfig,
+    /// claims of relay fees and their transfers to `home`
+    pub fee_claim: TransactionConfig,
+    /// upper bound of the number of deposits confirmed by one relay transaction.
+    /// every deposit is confirmed by its own transaction if `None`
+    pub max_batch_size: Option<usize>,
+}
+
+impl Transactions {
+    fn from_load_struct(cfg: load::Transactions, preset: Option<&NetworkPreset>) -> Self {
+        Transactions {
+            home_deploy: TransactionConfig::from_load_struct(
+                cfg.home_deploy,
+                preset.map(|preset| &preset.home_deploy),
+            ),
+            foreign_deploy: TransactionConfig::from_load_struct(
+                cfg.foreign_deploy,
+                preset.map(|preset| &preset.foreign_deploy),
+            ),
+            deposit_relay: TransactionConfig::from_load_struct(
+                cfg.deposit_relay,
+                preset.map(|preset| &preset.deposit_relay),
+            ),
+            withdraw_confirm: TransactionConfig::from_load_struct(
+                cfg.withdraw_confirm,
+                preset.map(|preset| &preset.withdraw_confirm),
+            ),
+            withdraw_relay: TransactionConfig::from_load_struct(
+                cfg.withdraw_relay,
+                preset.map(|preset| &preset.withdraw_relay),
+            ),
+            fee_claim: TransactionConfig::from_load_struct(cfg.fee_claim, None),
+            max_batch_size: cfg.max_batch_size,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct TransactionConfig {
+    pub gas: u64,
+    pub gas_price: u64,
+    /// access list to use instead of generating one
+    /// if access lists are enabled for the chain
+    pub access_list: Option<Vec<AccessListItem>>,
+    /// EIP-1559 fees. relays are sent as EIP-1559 transactions if they are set
+    pub dynamic_fees: Option<DynamicFees>,
+}
+
+impl TransactionConfig {
+    /// options missing in `cfg` are taken from `preset`
+    fn from_load_struct(cfg: Option<load::TransactionConfig>, preset: Option<&GasPreset>) -> Self {
+        let cfg = cfg.unwrap_or_default();
+        TransactionConfig {
+            gas: cfg.gas
+                .map(|gas| gas.0)
+                .or_else(|| preset.map(|preset| preset.gas))
+                .unwrap_or_default(),
+            gas_price: cfg.gas_price
+                .map(|gas_price| gas_price.0)
+                .or_else(|| preset.map(|preset| preset.gas_price))
+                .unwrap_or_default(),
+            access_list: cfg.access_list.map(|access_list| {
+                access_list
+                    .into_iter()
+                    .map(|item| AccessListItem {
+                        address: item.address.0,
+                        storage_keys: item.storage_keys,
+                    })
+                    .collect()
+            }),
+            dynamic_fees: cfg.max_priority_fee_per_gas
+                .map(|max_priority_fee_per_gas| DynamicFees {
+                    max_fee_per_gas: cfg.max_fee_per_gas
+                        .map(|max_fee_per_gas| max_fee_per_gas.0.into()),
+                    max_priority_fee_per_gas: max_priority_fee_per_gas.0.into(),
+                }),
+        }
+    }
+}
+
+/// checks that the EIP-1559 fees of the transaction `name` are consistent
+fn check_dynamic_fees(name: &str, tx: &load::TransactionConfig) -> Result<(), Error> {
+    match (&tx.max_fee_per_gas, &tx.max_priority_fee_per_gas) {
+        (&Some(_), &None) => bail!(
+            "`transactions.{}.max_fee_per_gas` requires `max_priority_fee_per_gas`",
+            name
+        ),
+        (&Some(ref max_fee), &Some(ref priority_fee)) if priority_fee.0 > max_fee.0 => bail!(
+            "`transactions.{}.max_priority_fee_per_gas` ({}) exceeds `max_fee_per_gas` ({})",
+            name,
+            priority_fee.0,
+            max_fee.0
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ContractConfig {
+    pub bin: Bytes,
+}
+
+/// source of the gas prices of relay transactions to a chain (see `gas_price`)
+#[derive(Debug, PartialEq, Clone)]
+pub struct GasPriceOracleConfig {
+    /// `http://` or `https://` url of the oracle. the node is asked via `eth_gasPrice` if `None`
+    pub url: Option<String>,
+    /// json pointer to the gas price in the response of the oracle
+    pub pointer: String,
+    /// wei per unit of the gas price in the response of the oracle
+    pub wei_per_unit: u64,
+    /// interval between two requests to the oracle
+    pub refresh_interval: Duration,
+    /// upper bound of the gas price of a relay transaction in wei
+    pub max_gas_price: u64,
+}
+
+/// replacement of relay transactions that aren't mined (see `bridge::stuck_transactions`)
+#[derive(Debug, PartialEq, Clone)]
+pub struct StuckTransactionsConfig {
+    /// time after which a relay transaction whose nonce isn't confirmed is replaced
+    pub timeout: Duration,
+    /// percentage the gas price of a replacement is raised by
+    pub gas_price_bump: u64,
+    /// upper bound of the gas price of replacements in wei. unbounded if `None`
+    pub max_gas_price: Option<U256>,
+}
+
+/// checks of the balance of the account of a chain (see `bridge::balance_watchdog`)
+#[derive(Debug, PartialEq, Clone)]
+pub struct BalanceWatchdogConfig {
+    /// a warning is logged while the balance in wei is below it
+    pub warn_balance: U256,
+    /// relay transactions to the chain are paused while the balance in wei is below it
+    pub min_balance: U256,
+    /// interval between two checks
+    pub check_interval: Duration,
+    /// `http://` or `https://` url that is posted an alert
+    /// when the balance falls below `min_balance`
+    pub alert_url: Option<String>,
+}
+
+/// retries of calls to a node that failed for transient reasons (see `retry`)
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetryConfig {
+    /// number of retries in a row before the error is returned. `0` disables retries
+    pub max_retries: u32,
+    /// delay before the first retry. doubles with every further retry
+    pub initial_backoff: Duration,
+    /// upper bound of the delay between two retries
+    pub max_backoff: Duration,
+    /// whether a random part of up to half the delay is skipped
+    pub jitter: bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: Duration::from_millis(DEFAULT_INITIAL_BACKOFF),
+            max_backoff: Duration::from_millis(DEFAULT_MAX_BACKOFF),
+            jitter: true,
+        }
+    }
+}
+
+/// entry of an on-chain registry that holds the address of a bridge contract
+#[derive(Debug, PartialEq, Clone)]
+pub struct RegistryConfig {
+    /// address of the registry contract
+    pub address: Address,
+    /// name the bridge contract is registered under
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Authorities {
+    pub accounts: Vec<Address>,
+    pub required_signatures: u32,
+}
+
+pub use self::load::parse_uint;
+
+/// Some config values may not be defined in `toml` file, but they should be specified at runtime.
+/// `load` module separates `Config` representation in file with optional from the one used
+/// in application.
+mod load {
+    use std::fmt;
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+    use web3::types::{Address, H256};
+    use ethereum_types::U256;
+    use serde::{Deserialize, Deserializer};
+    use serde::de::{Error, Visitor};
+    use ens::is_name;
+    use util::to_checksum;
+    use super::{AddressField, EnsName};
+
+    /// parses an unsigned integer from a decimal or `0x`-prefixed hex string.
+    /// digits may be separated by `_` (example: `"1_000_000_000_000_000_000"`).
+    pub fn parse_uint(s: &str) -> Result<U256, String> {
+        if s.starts_with('_') || s.ends_with('_') || s.contains("__") {
+            return Err(format!("`{}` has a misplaced `_`", s));
+        }
+        let digits: String = s.chars().filter(|c| *c != '_').collect();
+
+        if digits.starts_with("0x") {
+            let hex = &digits[2..];
+            if hex.is_empty() || !hex.chars().all(|c| c.is_digit(16)) {
+                return Err(format!("`{}` is not a valid hex number", s));
+            }
+            if hex.trim_left_matches('0').len() > 64 {
+                return Err(format!("`{}` exceeds the maximum of 2^256 - 1", s));
+            }
+            let padded = format!("{:0>64}", hex.trim_left_matches('0'));
+            return padded
+                .parse()
+                .map_err(|_| format!("`{}` is not a valid hex number", s));
+        }
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(10)) {
+            return Err(format!(
+                "`{}` is neither a decimal nor a `0x`-prefixed hex number",
+                s
+            ));
+        }
+        U256::from_dec_str(&digits)
+            .map_err(|_| format!("`{}` exceeds the maximum of 2^256 - 1", s))
+    }
+
+    struct UintVisitor;
+
+    impl<'de> Visitor<'de> for UintVisitor {
+        type Value = U256;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a non-negative integer or a string with a decimal or hex number")
+        }
+
+        fn visit_i64<E: Error>(self, value: i64) -> Result<U256, E> {
+            if value < 0 {
+                return Err(E::custom(format!("`{}` is negative", value)));
+            }
+            Ok((value as u64).into())
+        }
+
+        fn visit_u64<E: Error>(self, value: u64) -> Result<U256, E> {
+            Ok(value.into())
+        }
+
+        fn visit_str<E: Error>(self, value: &str) -> Result<U256, E> {
+            parse_uint(value).map_err(E::custom)
+        }
+    }
+
+    /// the toml crate parses integer literals as `i64`.
+    /// certain config options (example: `max_total_home_contract_balance`)
+    /// frequently don't fit into `i64`.
+    /// they can be put in string literals which are parsed by `parse_uint`.
+    pub struct Uint256(pub U256);
+
+    impl<'de> Deserialize<'de> for Uint256 {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_any(UintVisitor).map(Uint256)
+        }
+    }
+
+    /// like `Uint256` but rejects values that don't fit into `u64`
+    pub struct Uint64(pub u64);
+
+    impl<'de> Deserialize<'de> for Uint64 {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let value = deserializer.deserialize_any(UintVisitor)?;
+            if value > u64::max_value().into() {
+                return Err(D::Error::custom(format!(
+                    "`{}` exceeds the maximum of {}",
+                    value,
+                    u64::max_value()
+                )));
+            }
+            Ok(Uint64(value.low_u64()))
+        }
+    }
+
+    /// an address or an ENS name that resolves to an address
+    pub enum AddressOrName {
+        Address(Address),
+        Name(String),
+    }
+
+    impl AddressOrName {
+        /// returns the address or records the name as `field` in `ens_names`
+        /// and returns a placeholder until it is resolved
+        pub fn into_address(self, field: AddressField, ens_names: &mut Vec<EnsName>) -> Address {
+            match self {
+                AddressOrName::Address(address) => address,
+                AddressOrName::Name(name) => {
+                    ens_names.push(EnsName { field, name });
+                    Address::default()
+                }
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AddressOrName {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let s: String = Deserialize::deserialize(deserializer)?;
+            if is_name(&s) {
+                return Ok(AddressOrName::Name(s));
+            }
+            parse_address(&s)
+                .map(AddressOrName::Address)
+                .map_err(D::Error::custom)
+        }
+    }
+
+    /// an address whose EIP-55 checksum was validated
+    pub struct ChecksumAddress(pub Address);
+
+    impl<'de> Deserialize<'de> for ChecksumAddress {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let s: String = Deserialize::deserialize(deserializer)?;
+            parse_address(&s)
+                .map(ChecksumAddress)
+                .map_err(D::Error::custom)
+        }
+    }
+
+    /// parses an address from hex with an optional `0x` prefix.
+    /// mixed-case hex must carry a valid EIP-55 checksum.
+    /// single-case hex carries no checksum and is accepted with a warning
+    /// since a typo in it can't be detected.
+    fn parse_address(s: &str) -> Result<Address, String> {
+        let hex = if s.starts_with("0x") { &s[2..] } else { s };
+        if hex.len() != 40 {
+            return Err(format!("`{}` is neither an address nor an ENS name", s));
+        }
+        let address: Address = hex.parse()
+            .map_err(|_| format!("`{}` is neither an address nor an ENS name", s))?;
+
+        let has_lowercase = hex.chars().any(|c| c.is_ascii_lowercase());
+        let has_uppercase = hex.chars().any(|c| c.is_ascii_uppercase());
+        if has_lowercase && has_uppercase {
+            let checksum = to_checksum(&address);
+            if hex != checksum {
+                return Err(format!(
+                    "address `{}` has an invalid EIP-55 checksum. did you mean `0x{}`?",
+                    s, checksum
+                ));
+            }
+        } else if has_lowercase || has_uppercase {
+            warn!(
+                "address `{}` in config has no EIP-55 checksum. consider using `0x{}`",
+                s,
+                to_checksum(&address)
+            );
+        }
+
+        Ok(address)
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Config {
+        pub network: Option<String>,
+        pub home: Node,
+        pub foreign: Node,
+        pub authorities: Authorities,
+        pub transactions: Option<Transactions>,
+        pub estimated_gas_cost_of_withdraw: Uint256,
+        pub max_total_home_contract_balance: Uint256,
+        pub max_single_deposit_value: Uint256,
+        pub contract_version: Option<ContractVersion>,
+        pub message_version: Option<MessageVersion>,
+        pub deployment_manifest: Option<PathBuf>,
+        pub ens_registry: Option<ChecksumAddress>,
+        pub pause_files: Option<PauseFiles>,
+        pub value_limits: Option<ValueLimits>,
+        pub unaccounted_transfers: Option<UnaccountedTransfers>,
+        pub message_calls: Option<MessageCalls>,
+        pub solvency_check: Option<bool>,
+        pub metrics: Option<Metrics>,
+        pub signature_audit_log: Option<PathBuf>,
+        pub verify_signatures: Option<bool>,
+        pub relay_history: Option<PathBuf>,
+        pub event_queue: Option<EventQueue>,
+        pub postgres: Option<Postgres>,
+        pub sqlite: Option<Sqlite>,
+        pub otlp: Option<Otlp>,
+        pub http_api: Option<HttpApi>,
+        pub role: Option<Role>,
+        pub log_format: Option<LogFormat>,
+        pub signer: Option<Signer>,
+        pub keystore: Option<Keystore>,
+        pub shard: Option<Shard>,
+        pub clock: Option<Clock>,
+        pub max_lag: Option<MaxLag>,
+        pub token_fee: Option<TokenFee>,
+        pub reverted_relays: Option<RevertedRelays>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct RevertedRelays {
+        pub max_attempts: Option<u32>,
+        /// seconds
+        pub retry_interval: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct TokenFee {
+        pub deposit_fee: Uint256,
+        /// seconds
+        pub claim_interval: Option<u64>,
+        pub min_claim: Option<Uint256>,
+        pub transfer_home: Option<bool>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct MaxLag {
+        pub blocks: u64,
+        /// seconds
+        pub duration: u64,
+        pub check_interval: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Clock {
+        pub skew_tolerance: Option<u64>,
+        pub max_divergence: Option<u64>,
+        pub check_interval: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Shard {
+        pub url: String,
+        pub owner: Option<String>,
+        pub partition_by: Option<PartitionBy>,
+        pub partitions: Option<u64>,
+        pub lease_duration: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum PartitionBy {
+        Hash,
+        Token,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Signer {
+        pub socket: PathBuf,
+        pub token_file: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Keystore {
+        pub path: PathBuf,
+        pub password_file: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Role {
+        Full,
+        Signer,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum MessageVersion {
+        V1,
+        V2,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Bridges {
+        pub log_format: Option<LogFormat>,
+        pub bridges: Vec<Bridge>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Bridge {
+        pub name: String,
+        pub config: PathBuf,
+        pub database: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum LogFormat {
+        Text,
+        Json,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct EventQueue {
+        pub kafka: Option<Kafka>,
+        pub nats: Option<Nats>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Kafka {
+        pub brokers: Vec<String>,
+        pub topic: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Nats {
+        pub address: String,
+        pub subject: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Postgres {
+        pub url: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Sqlite {
+        pub path: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Otlp {
+        pub endpoint: String,
+        pub service_name: Option<String>,
+        pub export_interval: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct HttpApi {
+        pub address: SocketAddr,
+        pub status_interval: Option<u64>,
+        pub admin_token_file: Option<PathBuf>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Metrics {
+        pub file: PathBuf,
+        pub export_interval: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct UnaccountedTransfers {
+        pub audit_log: Option<PathBuf>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct MessageCalls {
+        pub confirm_gas: Option<u64>,
+        pub relay_gas: Option<u64>,
+        pub max_gas_limit: Option<u64>,
+        pub max_data_length: Option<usize>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct PauseFiles {
+        pub deposits: Option<PathBuf>,
+        pub withdraws: Option<PathBuf>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct ValueLimits {
+        pub deposits: Option<ValueLimit>,
+        pub withdraws: Option<ValueLimit>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct ValueLimit {
+        pub max_single_value: Option<Uint256>,
+        pub max_daily_value: Option<Uint256>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ContractVersion {
+        V1,
+        V2,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Node {
+        pub account: AddressOrName,
+        pub contract: ContractConfig,
+        pub ipc: Option<PathBuf>,
+        pub http: Option<String>,
+        pub ws: Option<String>,
+        pub request_timeout: Option<u64>,
+        pub tls: Option<Tls>,
+        pub compression: Option<bool>,
+        pub compress_requests: Option<bool>,
+        pub relay_deadline: Option<u64>,
+        pub max_relay_attempts: Option<u32>,
+        pub max_submission_delay: Option<u64>,
+        pub poll_interval: Option<u64>,
+        pub max_poll_interval: Option<u64>,
+        pub subscribe_new_heads: Option<bool>,
+        pub required_confirmations: Option<usize>,
+        pub confirmation_strategy: Option<ConfirmationStrategy>,
+        pub confirmation_delay: Option<u64>,
+        pub reorg_checkpoints: Option<usize>,
+        pub chain_id: Option<u64>,
+        pub access_lists: Option<bool>,
+        pub transaction_type: Option<TransactionType>,
+        pub registry: Option<Registry>,
+        pub maintenance_windows: Option<Vec<MaintenanceWindow>>,
+        pub max_requests_per_second: Option<u32>,
+        pub fallback_accounts: Option<Vec<AddressOrName>>,
+        pub token_address: Option<ChecksumAddress>,
+        pub gas_price_oracle: Option<GasPriceOracle>,
+        pub stuck_transactions: Option<StuckTransactions>,
+        pub balance_watchdog: Option<BalanceWatchdog>,
+        pub retry: Option<Retry>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Tls {
+        pub ca_bundle: Option<PathBuf>,
+        pub client_identity: Option<PathBuf>,
+        pub client_identity_password_file: Option<PathBuf>,
+        pub server_name: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Retry {
+        pub max_retries: Option<u32>,
+        /// milliseconds
+        pub initial_backoff: Option<u64>,
+        /// milliseconds
+        pub max_backoff: Option<u64>,
+        pub jitter: Option<bool>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct BalanceWatchdog {
+        pub warn_balance: Option<Uint256>,
+        pub min_balance: Uint256,
+        /// seconds
+        pub check_interval: Option<u64>,
+        pub alert_url: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct StuckTransactions {
+        /// seconds
+        pub timeout: Option<u64>,
+        /// percent
+        pub gas_price_bump: Option<u64>,
+        pub max_gas_price: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct GasPriceOracle {
+        pub url: Option<String>,
+        pub pointer: Option<String>,
+        pub wei_per_unit: Option<u64>,
+        /// seconds
+        pub refresh_interval: Option<u64>,
+        pub max_gas_price: u64,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct MaintenanceWindow {
+        #[serde(default)]
+        pub days: Vec<Weekday>,
+        pub start: TimeOfDay,
+        /// seconds
+        pub duration: u64,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Weekday {
+        Monday,
+        Tuesday,
+        Wednesday,
+        Thursday,
+        Friday,
+        Saturday,
+        Sunday,
+    }
+
+    /// parses `"HH:MM"` into seconds after midnight
+    pub fn parse_time_of_day(s: &str) -> Result<u64, String> {
+        let invalid = || format!("`{}` is not a time of day in the format `HH:MM`", s);
+        let mut parts = s.splitn(2, ':');
+        let hours: u64 = parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+        let minutes: u64 = parts.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(hours * 3600 + minutes * 60)
+    }
+
+    /// time of day in UTC as seconds after midnight
+    pub struct TimeOfDay(pub u64);
+
+    impl<'de> Deserialize<'de> for TimeOfDay {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let s: String = Deserialize::deserialize(deserializer)?;
+            parse_time_of_day(&s)
+                .map(TimeOfDay)
+                .map_err(D::Error::custom)
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Registry {
+        pub address: AddressOrName,
+        pub name: String,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ConfirmationStrategy {
+        Depth,
+        PinnedHash,
+        Safe,
+        Finalized,
+        Delay,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TransactionType {
+        Auto,
+        Legacy,
+        Eip2930,
+        Eip1559,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct Transactions {
+        pub home_deploy: Option<TransactionConfig>,
+        pub foreign_deploy: Option<TransactionConfig>,
+        pub deposit_relay: Option<TransactionConfig>,
+        pub withdraw_confirm: Option<TransactionConfig>,
+        pub withdraw_relay: Option<TransactionConfig>,
+        pub fee_claim: Option<TransactionConfig>,
+        pub max_batch_size: Option<usize>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(deny_unknown_fields)]
+    pub struct TransactionConfig {
+        pub gas: Option<Uint64>,
+        pub gas_price: Option<Uint64>,
+        pub access_list: Option<Vec<AccessListItem>>,
+        pub max_fee_per_gas: Option<Uint64>,
+        pub max_priority_fee_per_gas: Option<Uint64>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct AccessListItem {
+        pub address: ChecksumAddress,
+        #[serde(default)]
+        pub storage_keys: Vec<H256>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct ContractConfig {
+        pub bin: PathBuf,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Authorities {
+        pub accounts: Vec<AddressOrName>,
+        pub required_signatures: u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+    use rustc_hex::FromHex;
+    use web3::types::Address;
+    use super::load::{parse_time_of_day, parse_uint};
+    use super::{template, AddressField, Authorities, BalanceWatchdogConfig, BridgesConfig,
+                ClockConfig, Config,
+                ContractConfig,
+                EnsName, EventQueueConfig, GasPriceOracleConfig, HttpApiConfig, KeystoreConfig,
+                LogFormat, MaxLagConfig, MessageCallsConfig, MessageVersion, MetricsConfig, Node,
+                NodeEndpoint,
+                OtlpConfig, PartitionBy, PauseFiles,
+                RegistryConfig, RetryConfig, RevertedRelaysConfig, Role,
+                ShardConfig, SignerConfig, StuckTransactionsConfig, TlsConfig, TokenFeeConfig,
+                Tokens, TransactionConfig,
+                Transactions, UnaccountedTransfersConfig, ValueLimit, ValueLimits,
+                DEFAULT_CONFIRMATIONS};
+    use ethereum_types::U256;
+    use api::{AccessListItem, ConfirmationStrategy};
+    use contracts::ContractVersion;
+    use ens::MAINNET_REGISTRY;
+    use message_to_mainnet::ChainIds;
+    use maintenance::{MaintenanceWindow, Weekday};
+    use transaction::{AccessListMode, DynamicFees, TransactionType};
+
+    #[test]
+    fn load_full_setup_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = "100000"
+max_total_home_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+contract_version = "v1"
+deployment_manifest = "/deployment.toml"
+solvency_check = true
+signature_audit_log = "/signatures.log"
+verify_signatures = false
+relay_history = "/relays.log"
+role = "signer"
+log_format = "json"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = "/home.ipc"
+poll_interval = 2
+max_poll_interval = 12
+subscribe_new_heads = true
+relay_deadline = 30
+max_relay_attempts = 5
+max_submission_delay = 2000
+required_confirmations = 100
+access_lists = true
+transaction_type = "eip1559"
+max_requests_per_second = 10
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[[home.maintenance_windows]]
+days = ["saturday"]
+start = "02:30"
+duration = 3600
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = "/foreign.ipc"
+transaction_type = "legacy"
+confirmation_strategy = "pinned_hash"
+reorg_checkpoints = 8
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[foreign.registry]
+address = "0x0000000000000000000000000000000000000006"
+name = "ForeignBridge"
+
+[foreign.gas_price_oracle]
+url = "http://127.0.0.1:8081/gas"
+max_gas_price = 100_000_000_000
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+	"0x0000000000000000000000000000000000000002",
+	"0x0000000000000000000000000000000000000003"
+]
+required_signatures = 2
+
+[pause_files]
+deposits = "/pause-deposits"
+
+[unaccounted_transfers]
+audit_log = "/unaccounted_transfers.log"
+
+[message_calls]
+max_gas_limit = 500000
+
+[metrics]
+file = "/metrics.json"
+
+[otlp]
+endpoint = "127.0.0.1:4318"
+
+[http_api]
+address = "127.0.0.1:8080"
+admin_token_file = "/http-admin.token"
+
+[signer]
+socket = "/signer.sock"
+token_file = "/signer.token"
+
+[keystore]
+path = "/keys/authority.json"
+password_file = "/keys/authority.pwd"
+
+[clock]
+skew_tolerance = 60
+max_divergence = 300
+
+[max_lag]
+blocks = 50
+duration = 900
+
+[token_fee]
+deposit_fee = "1_000"
+min_claim = "5_000"
+
+[reverted_relays]
+max_attempts = 3
+
+[transactions]
+home_deploy = { gas = 20 }
+withdraw_relay = { gas = 30, access_list = [{ address = "0x0000000000000000000000000000000000000004", storage_keys = ["0x0000000000000000000000000000000000000000000000000000000000000005"] }] }
+"#;
+
+        let mut expected = Config {
+            txs: Transactions::default(),
+            home: Node {
+                account: "1B68Cb0B50181FC4006Ce572cF346e596E51818b".into(),
+                endpoint: NodeEndpoint::Ipc("/home.ipc".into()),
+                contract: ContractConfig {
+                    bin: include_str!("../../contracts/compiled/HomeBridge.bin")
+                        .from_hex()
+                        .unwrap()
+                        .into(),
+                },
+                poll_interval: Duration::from_secs(2),
+                max_poll_interval: Duration::from_secs(12),
+                subscribe_new_heads: true,
+                request_timeout: Duration::from_secs(5),
+                tls: TlsConfig::default(),
+                compression: false,
+                compress_requests: false,
+                relay_deadline: Duration::from_secs(30),
+                max_relay_attempts: 5,
+                max_submission_delay: Duration::from_millis(2000),
+                required_confirmations: 100,
+                confirmation_strategy: ConfirmationStrategy::Depth,
+                reorg_checkpoints: 32,
+                chain_id: None,
+                access_lists: true,
+                transaction_type: Some(TransactionType::DynamicFee),
+                registry: None,
+                maintenance_windows: vec![
+                    MaintenanceWindow {
+                        days: vec![Weekday::Saturday],
+                        start: 9000,
+                        duration: Duration::from_secs(3600),
+                    },
+                ],
+                max_requests_per_second: Some(10),
+                fallback_accounts: vec![],
+                token_address: None,
+                gas_price_oracle: None,
+                stuck_transactions: None,
+                balance_watchdog: None,
+                retry: RetryConfig::default(),
+            },
+            foreign: Node {
+                account: "0000000000000000000000000000000000000001".into(),
+                contract: ContractConfig {
+                    bin: include_str!("../../contracts/compiled/ForeignBridge.bin")
+                        .from_hex()
+                        .unwrap()
+                        .into(),
+                },
+                endpoint: NodeEndpoint::Ipc("/foreign.ipc".into()),
+                poll_interval: Duration::from_secs(1),
+                max_poll_interval: Duration::from_secs(30),
+                subscribe_new_heads: false,
+                request_timeout: Duration::from_secs(5),
+                tls: TlsConfig::default(),
+                compression: false,
+                compress_requests: false,
+                relay_deadline: Duration::from_secs(60),
+                max_relay_attempts: 20,
+                max_submission_delay: Duration::from_millis(0),
+                required_confirmations: 12,
+                confirmation_strategy: ConfirmationStrategy::PinnedHash,
+                reorg_checkpoints: 8,
+                chain_id: None,
+                access_lists: false,
+                transaction_type: Some(TransactionType::Legacy),
+                registry: Some(RegistryConfig {
+                    address: "0000000000000000000000000000000000000006".into(),
+                    name: "ForeignBridge".into(),
+                }),
+                maintenance_windows: vec![],
+                max_requests_per_second: None,
+                fallback_accounts: vec![],
+                token_address: None,
+                gas_price_oracle: Some(GasPriceOracleConfig {
+                    url: Some("http://127.0.0.1:8081/gas".into()),
+                    pointer: "/fast".into(),
+                    wei_per_unit: 1_000_000_000,
+                    refresh_interval: Duration::from_secs(60),
+                    max_gas_price: 100_000_000_000,
+                }),
+                stuck_transactions: None,
+                balance_watchdog: None,
+                retry: RetryConfig::default(),
+            },
+            authorities: Authorities {
+                accounts: vec![
+                    "0000000000000000000000000000000000000001".into(),
+                    "0000000000000000000000000000000000000002".into(),
+                    "0000000000000000000000000000000000000003".into(),
+                ],
+                required_signatures: 2,
+            },
+            estimated_gas_cost_of_withdraw: U256::from_dec_str("100000").unwrap(),
+            max_total_home_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
+            max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
+            contract_version: ContractVersion::V1,
+            message_version: MessageVersion::V1,
+            deployment_manifest: Some("/deployment.toml".into()),
+            ens_registry: MAINNET_REGISTRY.into(),
+            ens_names: vec![],
+            pause_files: PauseFiles::default(),
+            value_limits: ValueLimits::default(),
+            unaccounted_transfers: Some(UnaccountedTransfersConfig {
+                audit_log: Some("/unaccounted_transfers.log".into()),
+            }),
+            message_calls: Some(MessageCallsConfig {
+                confirm_gas: 300_000,
+                relay_gas: 200_000,
+                max_gas_limit: 500_000,
+                max_data_length: 1024,
+            }),
+            solvency_check: true,
+            metrics: Some(MetricsConfig {
+                file: "/metrics.json".into(),
+                export_interval: Duration::from_secs(10),
+            }),
+            signature_audit_log: Some("/signatures.log".into()),
+            verify_signatures: false,
+            relay_history: Some("/relays.log".into()),
+            event_queue: None,
+            postgres: None,
+            sqlite: None,
+            otlp: Some(OtlpConfig {
+                endpoint: "127.0.0.1:4318".into(),
+                service_name: "parity-bridge".into(),
+                export_interval: Duration::from_secs(10),
+            }),
+            http_api: Some(HttpApiConfig {
+                address: "127.0.0.1:8080".parse().unwrap(),
+                status_interval: Duration::from_secs(10),
+                admin_token_file: Some("/http-admin.token".into()),
+            }),
+            role: Role::Signer,
+            log_format: LogFormat::Json,
+            signer: Some(SignerConfig {
+                socket: "/signer.sock".into(),
+                token_file: "/signer.token".into(),
+            }),
+            keystore: Some(KeystoreConfig {
+                path: "/keys/authority.json".into(),
+                password_file: "/keys/authority.pwd".into(),
+            }),
+            shard: None,
+            clock: ClockConfig {
+                skew_tolerance: Duration::from_secs(60),
+                max_divergence: Duration::from_secs(300),
+                check_interval: Duration::from_secs(30),
+            },
+            max_lag: Some(MaxLagConfig {
+                blocks: 50,
+                duration: Duration::from_secs(900),
+                check_interval: Duration::from_secs(30),
+            }),
+            token_fee: Some(TokenFeeConfig {
+                deposit_fee: 1000.into(),
+                claim_interval: Duration::from_secs(3600),
+                min_claim: 5000.into(),
+                transfer_home: false,
+            }),
+            reverted_relays: RevertedRelaysConfig {
+                max_attempts: 3,
+                retry_interval: Duration::from_secs(600),
+            },
+        };
+
+        expected.pause_files.deposits = Some("/pause-deposits".into());
+        expected.txs.home_deploy = TransactionConfig {
+            gas: 20,
+            gas_price: 0,
+            access_list: None,
+            dynamic_fees: None,
+        };
+        expected.txs.withdraw_relay = TransactionConfig {
+            gas: 30,
+            gas_price: 0,
+            access_list: Some(vec![
+                AccessListItem {
+                    address: "0000000000000000000000000000000000000004".into(),
+                    storage_keys: vec![
+                        "0000000000000000000000000000000000000000000000000000000000000005".into(),
+                    ],
+                },
+            ]),
+            dynamic_fees: None,
+        };
+
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(expected, config);
+    }
+
+    #[test]
+    fn load_minimal_setup_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = "200000000"
+max_total_home_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+	"0x0000000000000000000000000000000000000002",
+	"0x0000000000000000000000000000000000000003"
+]
+required_signatures = 2
+"#;
+        let expected = Config {
+            txs: Transactions::default(),
+            home: Node {
+                account: "1B68Cb0B50181FC4006Ce572cF346e596E51818b".into(),
+                endpoint: NodeEndpoint::Ipc("".into()),
+                contract: ContractConfig {
+                    bin: include_str!("../../contracts/compiled/HomeBridge.bin")
+                        .from_hex()
+                        .unwrap()
+                        .into(),
+                },
+                poll_interval: Duration::from_secs(1),
+                max_poll_interval: Duration::from_secs(30),
+                subscribe_new_heads: false,
+                request_timeout: Duration::from_secs(5),
+                tls: TlsConfig::default(),
+                compression: false,
+                compress_requests: false,
+                relay_deadline: Duration::from_secs(60),
+                max_relay_attempts: 20,
+                max_submission_delay: Duration::from_millis(0),
+                required_confirmations: 12,
+                confirmation_strategy: ConfirmationStrategy::Depth,
+                reorg_checkpoints: 32,
+                chain_id: None,
+                access_lists: false,
+                transaction_type: None,
+                registry: None,
+                maintenance_windows: vec![],
+                max_requests_per_second: None,
+                fallback_accounts: vec![],
+                token_address: None,
+                gas_price_oracle: None,
+                stuck_transactions: None,
+                balance_watchdog: None,
+                retry: RetryConfig::default(),
+            },
+            foreign: Node {
+                account: "0000000000000000000000000000000000000001".into(),
+                endpoint: NodeEndpoint::Ipc("".into()),
+                contract: ContractConfig {
+                    bin: include_str!("../../contracts/compiled/ForeignBridge.bin")
+                        .from_hex()
+                        .unwrap()
+                        .into(),
+                },
+                poll_interval: Duration::from_secs(1),
+                max_poll_interval: Duration::from_secs(30),
+                subscribe_new_heads: false,
+                request_timeout: Duration::from_secs(5),
+                tls: TlsConfig::default(),
+                compression: false,
+                compress_requests: false,
+                relay_deadline: Duration::from_secs(60),
+                max_relay_attempts: 20,
+                max_submission_delay: Duration::from_millis(0),
+                required_confirmations: 12,
+                confirmation_strategy: ConfirmationStrategy::Depth,
+                reorg_checkpoints: 32,
+                chain_id: None,
+                access_lists: false,
+                transaction_type: None,
+                registry: None,
+                maintenance_windows: vec![],
+                max_requests_per_second: None,
+                fallback_accounts: vec![],
+                token_address: None,
+                gas_price_oracle: None,
+                stuck_transactions: None,
+                balance_watchdog: None,
+                retry: RetryConfig::default(),
+            },
+            authorities: Authorities {
+                accounts: vec![
+                    "0000000000000000000000000000000000000001".into(),
+                    "0000000000000000000000000000000000000002".into(),
+                    "0000000000000000000000000000000000000003".into(),
+                ],
+                required_signatures: 2,
+            },
+            estimated_gas_cost_of_withdraw: U256::from_dec_str("200000000").unwrap(),
+            max_total_home_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
+            max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
+            contract_version: ContractVersion::V1,
+            message_version: MessageVersion::V1,
+            deployment_manifest: None,
+            ens_registry: MAINNET_REGISTRY.into(),
+            ens_names: vec![],
+            pause_files: PauseFiles::default(),
+            value_limits: ValueLimits::default(),
+            unaccounted_transfers: None,
+            message_calls: None,
+            solvency_check: false,
+            metrics: None,
+            signature_audit_log: None,
+            verify_signatures: true,
+            relay_history: None,
+            event_queue: None,
+            postgres: None,
+            sqlite: None,
+            otlp: None,
+            http_api: None,
+            role: Role::Full,
+            log_format: LogFormat::Text,
+            signer: None,
+            keystore: None,
+            shard: None,
+            clock: ClockConfig::default(),
+            max_lag: None,
+            token_fee: None,
+            reverted_relays: RevertedRelaysConfig::default(),
+        };
+
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(expected, config);
+    }
+
+    #[test]
+    fn load_ens_names_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = "200000000"
+max_total_home_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+ens_registry = "0x0000000000000000000000000000000000000007"
+
+[home]
+account = "authority.bridge.eth"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+fallback_accounts = ["0x0000000000000000000000000000000000000002", "backup.bridge.eth"]
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[foreign.registry]
+address = "registry.bridge.eth"
+name = "ForeignBridge"
+
+[authorities]
+accounts = [
+	"0x0000000000000000000000000000000000000001",
+	"authority.bridge.eth",
+]
+required_signatures = 1
+"#;
+
+        let mut config = Config::load_from_str(toml).unwrap();
+        assert_eq!(
+            "0000000000000000000000000000000000000007".parse::<Address>().unwrap(),
+            config.ens_registry
+        );
+        assert_eq!(
+            vec![
+                EnsName {
+                    field: AddressField::HomeAccount,
+                    name: "authority.bridge.eth".into(),
+                },
+                EnsName {
+                    field: AddressField::ForeignRegistry,
+                    name: "registry.bridge.eth".into(),
+                },
+                EnsName {
+                    field: AddressField::FallbackAccount(1),
+                    name: "backup.bridge.eth".into(),
+                },
+                EnsName {
+                    field: AddressField::Authority(1),
+                    name: "authority.bridge.eth".into(),
+                },
+            ],
+            config.ens_names
+        );
+
+        let address: Address = "0000000000000000000000000000000000000008".parse().unwrap();
+        config.set_address(AddressField::HomeAccount, address);
+        config.set_address(AddressField::ForeignRegistry, address);
+        config.set_address(AddressField::Authority(1), address);
+        config.set_address(AddressField::FallbackAccount(1), address);
+        assert_eq!(address, config.home.account);
+        assert_eq!(address, config.foreign.registry.unwrap().address);
+        assert_eq!(address, config.authorities.accounts[1]);
+        assert_eq!(
+            vec![
+                "0000000000000000000000000000000000000002".parse().unwrap(),
+                address,
+            ],
+            config.foreign.fallback_accounts
+        );
+    }
+
+    #[test]
+    fn load_invalid_address_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = "200000000"
+max_total_home_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[home]
+account = "0x12"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#;
+
+        assert!(Config::load_from_str(toml).is_err());
+    }
+
+    #[test]
+    fn load_bad_checksum_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = "200000000"
+max_total_home_contract_balance = "10000000000000000000"
+max_single_deposit_value = "1000000000000000000"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818B"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#;
+
+        assert!(Config::load_from_str(toml).is_err());
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(Ok(0), parse_time_of_day("00:00"));
+        assert_eq!(Ok(9000), parse_time_of_day("02:30"));
+        assert_eq!(Ok(86340), parse_time_of_day("23:59"));
+        assert!(parse_time_of_day("24:00").is_err());
+        assert!(parse_time_of_day("12:60").is_err());
+        assert!(parse_time_of_day("12").is_err());
+        assert!(parse_time_of_day("").is_err());
+    }
+
+    #[test]
+    fn test_parse_uint() {
+        assert_eq!(Ok(U256::from(0)), parse_uint("0"));
+        assert_eq!(
+            Ok(U256::from_dec_str("1000000000000000000").unwrap()),
+            parse_uint("1_000_000_000_000_000_000")
+        );
+        assert_eq!(Ok(U256::from(0xdeadbeefu64)), parse_uint("0xdead_beef"));
+        assert_eq!(Ok(U256::max_value()), parse_uint(&format!("0x{}", "f".repeat(64))));
+        assert_eq!(
+            Ok(U256::max_value()),
+            parse_uint(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+            )
+        );
+        assert!(parse_uint("").is_err());
+        assert!(parse_uint("0x").is_err());
+        assert!(parse_uint("_1").is_err());
+        assert!(parse_uint("1__0").is_err());
+        assert!(parse_uint("1e18").is_err());
+        assert!(parse_uint("-1").is_err());
+        assert!(parse_uint(&format!("0x1{}", "0".repeat(64))).is_err());
+        assert!(
+            parse_uint(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+            ).is_err()
+        );
+    }
+
+    #[test]
+    fn load_numbers_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "10_000_000_000_000_000_000"
+max_single_deposit_value = "0xde0b6b3a7640000"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+
+[transactions]
+deposit_relay = { gas = "150_000", gas_price = "0x3b9aca00" }
+"#;
+
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(U256::from(100000), config.estimated_gas_cost_of_withdraw);
+        assert_eq!(
+            U256::from_dec_str("10000000000000000000").unwrap(),
+            config.max_total_home_contract_balance
+        );
+        assert_eq!(
+            U256::from_dec_str("1000000000000000000").unwrap(),
+            config.max_single_deposit_value
+        );
+        assert_eq!(150000, config.txs.deposit_relay.gas);
+        assert_eq!(1000000000, config.txs.deposit_relay.gas_price);
+
+        let toml = toml.replace(r#"gas = "150_000""#, r#"gas = "0x10000000000000000""#);
+        assert!(Config::load_from_str(&toml).is_err());
+    }
+
+    #[test]
+    fn load_event_queue_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+
+[event_queue]
+nats = { address = "127.0.0.1:4222", subject = "bridge.transfers" }
+"#;
+
+        let expected = EventQueueConfig::Nats {
+            address: "127.0.0.1:4222".into(),
+            subject: "bridge.transfers".into(),
+        };
+        match Config::load_from_str(toml) {
+            Ok(config) => {
+                assert!(cfg!(feature = "event-queue-nats"));
+                assert_eq!(Some(expected), config.event_queue);
+            }
+            Err(_) => assert!(!cfg!(feature = "event-queue-nats")),
+        }
+
+        let toml = toml.replace(
+            "[event_queue]\n",
+            "[event_queue]\nkafka = { brokers = [], topic = \"bridge-transfers\" }\n",
+        );
+        assert!(Config::load_from_str(&toml).is_err());
+    }
+
+    #[test]
+    fn load_shard_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+
+[shard]
+url = "postgres://bridge@localhost/leases"
+partition_by = "token"
+lease_duration = 60
+"#;
+
+        let expected = ShardConfig {
+            url: "postgres://bridge@localhost/leases".into(),
+            owner: None,
+            partition_by: PartitionBy::Token,
+            partitions: 16,
+            lease_duration: Duration::from_secs(60),
+        };
+        match Config::load_from_str(toml) {
+            Ok(config) => {
+                assert!(cfg!(feature = "shard-leases"));
+                assert_eq!(Some(expected), config.shard);
+            }
+            Err(_) => assert!(!cfg!(feature = "shard-leases")),
+        }
+
+        let toml = toml.replace("lease_duration = 60", "partitions = 0");
+        assert!(Config::load_from_str(&toml).is_err());
+    }
+
+    #[test]
+    fn load_network_preset_from_str() {
+        let toml = r#"
+network = "mainnet-poa"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+required_confirmations = 3
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+
+[transactions]
+withdraw_confirm = { gas = "400_000" }
+"#;
+
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(Some(1), config.home.chain_id);
+        assert_eq!(12, config.home.required_confirmations);
+        assert_eq!(Duration::from_secs(15), config.home.poll_interval);
+        assert_eq!(Some(99), config.foreign.chain_id);
+        assert_eq!(3, config.foreign.required_confirmations);
+        assert_eq!(150_000, config.txs.deposit_relay.gas);
+        assert_eq!(1_000_000_000, config.txs.deposit_relay.gas_price);
+        assert_eq!(400_000, config.txs.withdraw_confirm.gas);
+        assert_eq!(1_000_000_000, config.txs.withdraw_confirm.gas_price);
+        assert_eq!(Address::from(MAINNET_REGISTRY), config.ens_registry);
+
+        let toml = toml.replace("mainnet-poa", "mainnet");
+        assert!(Config::load_from_str(&toml).is_err());
+    }
+
+    #[test]
+    fn load_message_version_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+message_version = "v2"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+chain_id = 1
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+chain_id = 99
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#;
+
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(MessageVersion::V2, config.message_version);
+        assert_eq!(
+            Some(ChainIds {
+                home: 1,
+                foreign: 99,
+            }),
+            config.message_chain_ids()
+        );
+
+        let v1 = Config::load_from_str(&toml.replace("\"v2\"", "\"v1\"")).unwrap();
+        assert_eq!(None, v1.message_chain_ids());
+
+        // version 2 messages need the chain ids of both chains
+        let toml = toml.replace("chain_id = 99\n", "");
+        assert!(Config::load_from_str(&toml).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "contracts-v2")]
+    fn load_token_addresses_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+token_address = "0x0000000000000000000000000000000000000010"
+
+[home.contract]
+bin = "../contracts/compiled/HomeTokenBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+token_address = "0x0000000000000000000000000000000000000020"
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignTokenBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#;
+
+        let config = Config::load_from_str(toml).unwrap();
+        let expected = Tokens {
+            home: "0000000000000000000000000000000000000010".into(),
+            foreign: "0000000000000000000000000000000000000020".into(),
+        };
+        assert_eq!(Some(expected), config.tokens());
+
+        let home_only = toml.replace(
+            "token_address = \"0x0000000000000000000000000000000000000020\"\n",
+            "",
+        );
+        assert!(Config::load_from_str(&home_only).is_err());
+
+        let with_fee = format!("{}\n[token_fee]\ndeposit_fee = \"1\"\n", toml);
+        let config = Config::load_from_str(&with_fee).unwrap();
+        assert_eq!(Some(1.into()), config.token_fee.map(|token_fee| token_fee.deposit_fee));
+
+        let with_batches = format!("{}\n[transactions]\nmax_batch_size = 5\n", toml);
+        assert!(Config::load_from_str(&with_batches).is_err());
+    }
+
+    #[test]
+    fn load_contract_version_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+token_address = "0x0000000000000000000000000000000000000010"
+
+[home.contract]
+bin = "../contracts/compiled/HomeTokenBridge.bin"
+
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
+token_address = "0x0000000000000000000000000000000000000020"
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignTokenBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#;
+
+        // token bridges default to version 2
+        match Config::load_from_str(toml) {
+            Ok(config) => {
+                assert!(cfg!(feature = "contracts-v2"));
+                assert_eq!(ContractVersion::V2, config.contract_version);
+            }
+            Err(_) => assert!(!cfg!(feature = "contracts-v2")),
+        }
+
+        let v1 = format!("contract_version = \"v1\"\n{}", toml);
+        assert!(Config::load_from_str(&v1).is_err());
+
+        let ether = toml.replace(
+            "token_address = \"0x0000000000000000000000000000000000000010\"\n",
+            "",
+        ).replace(
+            "token_address = \"0x0000000000000000000000000000000000000020\"\n",
+            "",
+        );
+        let config = Config::load_from_str(&ether).unwrap();
+        assert_eq!(ContractVersion::V1, config.contract_version);
+        let v2 = format!("contract_version = \"v2\"\n{}", ether);
+        assert!(Config::load_from_str(&v2).is_err());
+    }
+
+    #[test]
+    fn load_keystore_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+
+[keystore]
+path = "authority.json"
+password_file = "authority.pwd"
+"#;
+
+        let config = Config::load_from_str(toml).unwrap();
+        let expected = KeystoreConfig {
+            path: "authority.json".into(),
+            password_file: "authority.pwd".into(),
+        };
+        assert_eq!(Some(expected), config.keystore);
+
+        let relayer = format!(
+            "{}\n[signer]\nsocket = \"signer.sock\"\ntoken_file = \"signer.token\"\n",
+            toml
+        );
+        assert!(Config::load_from_str(&relayer).is_err());
+        let signer = format!("role = \"signer\"\n{}", relayer);
+        assert!(Config::load_from_str(&signer).is_ok());
+    }
+
+    #[test]
+    fn load_endpoints_from_str() {
+        let toml = |home: &str, foreign: &str| {
+            format!(
+                r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+{}
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+{}
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#,
+                home, foreign
+            )
+        };
+
+        let config = Config::load_from_str(&toml(
+            "http = \"http://127.0.0.1:8545\"",
+            "ws = \"ws://127.0.0.1:8546\"\nsubscribe_new_heads = true",
+        )).unwrap();
+        assert_eq!(
+            NodeEndpoint::Http("http://127.0.0.1:8545".into()),
+            config.home.endpoint
+        );
+        assert_eq!(
+            NodeEndpoint::Ws("ws://127.0.0.1:8546".into()),
+            config.foreign.endpoint
+        );
+        assert!(config.foreign.subscribe_new_heads);
+
+        assert!(Config::load_from_str(&toml("", "ipc = \"\"")).is_err());
+        let both = toml("ipc = \"\"\nhttp = \"http://a\"", "ipc = \"\"");
+        assert!(Config::load_from_str(&both).is_err());
+        assert!(Config::load_from_str(&toml("http = \"ftp://a\"", "ipc = \"\"")).is_err());
+        assert!(Config::load_from_str(&toml("ws = \"wsx://a\"", "ipc = \"\"")).is_err());
+        let compressed_ipc = toml("ipc = \"\"\ncompression = true", "ipc = \"\"");
+        assert!(Config::load_from_str(&compressed_ipc).is_err());
+        let deflated_ws = toml("ws = \"ws://a\"\ncompression = true", "ipc = \"\"");
+        assert!(Config::load_from_str(&deflated_ws).is_err());
+        let compressed_ws = toml("ws = \"ws://a\"\ncompress_requests = true", "ipc = \"\"");
+        assert!(Config::load_from_str(&compressed_ws).is_err());
+        assert!(
+            Config::load_from_str(&toml(
+                "http = \"http://a\"\ntls = { server_name = \"a\" }",
+                "ipc = \"\""
+            )).is_err()
+        );
+        assert!(
+            Config::load_from_str(&toml(
+                "ws = \"wss://a\"\ntls = { client_identity_password_file = \"a\" }",
+                "ipc = \"\""
+            )).is_err()
+        );
+
+        let config = Config::load_from_str(&toml(
+            "http = \"https://node\"\ncompression = true\ncompress_requests = true\n\
+             tls = { ca_bundle = \"ca.pem\" }",
+            "ws = \"wss://node/v3\"\ntls = { server_name = \"rpc.example.com\" }",
+        )).unwrap();
+        assert!(config.home.endpoint.is_tls());
+        assert!(config.home.compression);
+        assert!(config.home.compress_requests);
+        assert_eq!(Some("ca.pem".into()), config.home.tls.ca_bundle);
+        assert!(config.foreign.endpoint.is_tls());
+        assert!(!config.foreign.compression);
+        assert_eq!(Some("rpc.example.com".into()), config.foreign.tls.server_name);
+        assert!(
+            Config::load_from_str(&toml(
+                "http = \"http://a\"\nsubscribe_new_heads = true",
+                "ipc = \"\""
+            )).is_err()
+        );
+    }
+
+    #[test]
+    fn load_confirmation_strategy_from_str() {
+        let toml = |home: &str, foreign: &str| {
+            format!(
+                r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+{}
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+{}
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#,
+                home, foreign
+            )
+        };
+
+        let config = Config::load_from_str(&toml(
+            "confirmation_strategy = \"finalized\"",
+            "confirmation_strategy = \"delay\"\nconfirmation_delay = 30",
+        )).unwrap();
+        assert_eq!(ConfirmationStrategy::Finalized, config.home.confirmation_strategy);
+        assert_eq!(
+            ConfirmationStrategy::Delay(Duration::from_secs(30)),
+            config.foreign.confirmation_strategy
+        );
+
+        let config = Config::load_from_str(&toml("confirmation_strategy = \"safe\"", "")).unwrap();
+        assert_eq!(ConfirmationStrategy::Safe, config.home.confirmation_strategy);
+        assert_eq!(ConfirmationStrategy::Depth, config.foreign.confirmation_strategy);
+
+        assert!(Config::load_from_str(&toml("confirmation_strategy = \"delay\"", "")).is_err());
+        assert!(Config::load_from_str(&toml("confirmation_delay = 30", "")).is_err());
+        assert!(
+            Config::load_from_str(&toml(
+                "confirmation_strategy = \"finalized\"\nconfirmation_delay = 30",
+                ""
+            )).is_err()
+        );
+        assert!(Config::load_from_str(&toml("confirmation_strategy = \"latest\"", "")).is_err());
+    }
+
+    #[test]
+    fn load_balance_watchdog_from_str() {
+        let toml = |balance_watchdog: &str| {
+            format!(
+                r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[foreign.balance_watchdog]
+{}
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#,
+                balance_watchdog
+            )
+        };
+
+        let config = Config::load_from_str(&toml("min_balance = \"100\"")).unwrap();
+        assert_eq!(None, config.home.balance_watchdog);
+        assert_eq!(
+            Some(BalanceWatchdogConfig {
+                warn_balance: 100.into(),
+                min_balance: 100.into(),
+                check_interval: Duration::from_secs(60),
+                alert_url: None,
+            }),
+            config.foreign.balance_watchdog
+        );
+
+        let config = Config::load_from_str(&toml(
+            "warn_balance = \"1_000\"\nmin_balance = \"100\"\ncheck_interval = 10\nalert_url = \"http://alerts:8080/bridge\"",
+        )).unwrap();
+        assert_eq!(
+            Some(BalanceWatchdogConfig {
+                warn_balance: 1_000.into(),
+                min_balance: 100.into(),
+                check_interval: Duration::from_secs(10),
+                alert_url: Some("http://alerts:8080/bridge".into()),
+            }),
+            config.foreign.balance_watchdog
+        );
+
+        assert!(Config::load_from_str(&toml("")).is_err());
+        assert!(
+            Config::load_from_str(&toml("warn_balance = \"10\"\nmin_balance = \"100\"")).is_err()
+        );
+        assert!(
+            Config::load_from_str(&toml("min_balance = \"100\"\nalert_url = \"ftp://alerts\""))
+                .is_err()
+        );
+        assert!(
+            Config::load_from_str(&toml("min_balance = \"100\"\nalert_url = \"https://alerts\""))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn load_retry_from_str() {
+        let toml = |retry: &str| {
+            format!(
+                r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[home.retry]
+{}
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#,
+                retry
+            )
+        };
+
+        let config = Config::load_from_str(&toml("")).unwrap();
+        assert_eq!(RetryConfig::default(), config.home.retry);
+        assert_eq!(RetryConfig::default(), config.foreign.retry);
+
+        let config = Config::load_from_str(&toml(
+            "max_retries = 3\ninitial_backoff = 100\nmax_backoff = 1_000\njitter = false",
+        )).unwrap();
+        assert_eq!(
+            RetryConfig {
+                max_retries: 3,
+                initial_backoff: Duration::from_millis(100),
+                max_backoff: Duration::from_millis(1_000),
+                jitter: false,
+            },
+            config.home.retry
+        );
+
+        assert!(Config::load_from_str(&toml("initial_backoff = 0")).is_err());
+        assert!(
+            Config::load_from_str(&toml("initial_backoff = 1_000\nmax_backoff = 100")).is_err()
+        );
+        assert!(Config::load_from_str(&toml("retries = 3")).is_err());
+    }
+
+    #[test]
+    fn load_stuck_transactions_from_str() {
+        let toml = |stuck_transactions: &str| {
+            format!(
+                r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[foreign.stuck_transactions]
+{}
+
+[authorities]
+accounts = []
+required_signatures = 1
+"#,
+                stuck_transactions
+            )
         };
 
-        Ok(result)
-    }
-}
+        let config = Config::load_from_str(&toml("")).unwrap();
+        assert_eq!(None, config.home.stuck_transactions);
+        assert_eq!(
+            Some(StuckTransactionsConfig {
+                timeout: Duration::from_secs(300),
+                gas_price_bump: 12,
+                max_gas_price: None,
+            }),
+            config.foreign.stuck_transactions
+        );
 
-#[derive(Debug, PartialEq, Default, Clone)]
-pub struct Transactions {
-    pub home_deploy: TransactionConfig,
-    pub foreign_deploy: TransactionConfig,
-    pub deposit_relay: TransactionConfig,
-    pub withdraw_confirm: TransactionConfig,
-    pub withdraw_relay: TransactionConfig,
-}
+        let config = Config::load_from_str(&toml(
+            "timeout = 60\ngas_price_bump = 25\nmax_gas_price = 100_000_000_000",
+        )).unwrap();
+        assert_eq!(
+            Some(StuckTransactionsConfig {
+                timeout: Duration::from_secs(60),
+                gas_price_bump: 25,
+                max_gas_price: Some(100_000_000_000u64.into()),
+            }),
+            config.foreign.stuck_transactions
+        );
 
-impl Transactions {
-    fn from_load_struct(cfg: load::Transactions) -> Self {
-        Transactions {
-            home_deploy: cfg.home_deploy
-                .map(TransactionConfig::from_load_struct)
-                .unwrap_or_default(),
-            foreign_deploy: cfg.foreign_deploy
-                .map(TransactionConfig::from_load_struct)
-                .unwrap_or_default(),
-            deposit_relay: cfg.deposit_relay
-                .map(TransactionConfig::from_load_struct)
-                .unwrap_or_default(),
-            withdraw_confirm: cfg.withdraw_confirm
-                .map(TransactionConfig::from_load_struct)
-                .unwrap_or_default(),
-            withdraw_relay: cfg.withdraw_relay
-                .map(TransactionConfig::from_load_struct)
-                .unwrap_or_default(),
-        }
+        assert!(Config::load_from_str(&toml("timeout = 0")).is_err());
+        assert!(Config::load_from_str(&toml("gas_price_bump = 5")).is_err());
+        let with_signer = format!(
+            "{}\n[signer]\nsocket = \"/signer.sock\"\ntoken_file = \"/signer.token\"\n",
+            toml("")
+        );
+        assert!(Config::load_from_str(&with_signer).is_err());
     }
-}
 
-#[derive(Debug, PartialEq, Default, Clone)]
-pub struct TransactionConfig {
-    pub gas: u64,
-    pub gas_price: u64,
-}
+    #[test]
+    fn load_dynamic_fees_from_str() {
+        let toml = r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
 
-impl TransactionConfig {
-    fn from_load_struct(cfg: load::TransactionConfig) -> Self {
-        TransactionConfig {
-            gas: cfg.gas.unwrap_or_default(),
-            gas_price: cfg.gas_price.unwrap_or_default(),
-        }
-    }
-}
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct ContractConfig {
-    pub bin: Bytes,
-}
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Authorities {
-    pub accounts: Vec<Address>,
-    pub required_signatures: u32,
-}
+[foreign]
+account = "0x0000000000000000000000000000000000000001"
+ipc = ""
 
-/// Some config values may not be defined in `toml` file, but they should be specified at runtime.
-/// `load` module separates `Config` representation in file with optional from the one used
-/// in application.
-mod load {
-    use std::path::PathBuf;
-    use web3::types::Address;
-    use ethereum_types::U256;
-    use serde::{Deserialize, Deserializer};
-    use serde::de::Error;
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
 
-    /// the toml crate parses integer literals as `i64`.
-    /// certain config options (example: `max_total_home_contract_balance`)
-    /// frequently don't fit into `i64`.
-    /// workaround: put them in string literals, use this custom
-    /// deserializer and parse them as U256.
-    fn deserialize_u256<'de, D>(deserializer: D) -> Result<U256, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s: &str = Deserialize::deserialize(deserializer)?;
-        U256::from_dec_str(s).map_err(|_| D::Error::custom("failed to parse U256 from dec str"))
-    }
+[authorities]
+accounts = []
+required_signatures = 1
 
-    #[derive(Deserialize)]
-    #[serde(deny_unknown_fields)]
-    pub struct Config {
-        pub home: Node,
-        pub foreign: Node,
-        pub authorities: Authorities,
-        pub transactions: Option<Transactions>,
-        #[serde(deserialize_with = "deserialize_u256")]
-        pub estimated_gas_cost_of_withdraw: U256,
-        #[serde(deserialize_with = "deserialize_u256")]
-        pub max_total_home_contract_balance: U256,
-        #[serde(deserialize_with = "deserialize_u256")]
-        pub max_single_deposit_value: U256,
-    }
+[transactions]
+deposit_relay = { gas = 10, max_fee_per_gas = 30, max_priority_fee_per_gas = 2 }
+fee_claim = { gas = 10, gas_price = 20, max_priority_fee_per_gas = 3 }
+"#;
 
-    #[derive(Deserialize)]
-    #[serde(deny_unknown_fields)]
-    pub struct Node {
-        pub account: Address,
-        pub contract: ContractConfig,
-        pub ipc: PathBuf,
-        pub request_timeout: Option<u64>,
-        pub poll_interval: Option<u64>,
-        pub required_confirmations: Option<usize>,
-    }
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(
+            Some(DynamicFees {
+                max_fee_per_gas: Some(30.into()),
+                max_priority_fee_per_gas: 2.into(),
+            }),
+            config.txs.deposit_relay.dynamic_fees
+        );
+        assert_eq!(
+            Some(DynamicFees {
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: 3.into(),
+            }),
+            config.txs.fee_claim.dynamic_fees
+        );
+        assert_eq!(None, config.txs.withdraw_confirm.dynamic_fees);
 
-    #[derive(Deserialize)]
-    #[serde(deny_unknown_fields)]
-    pub struct Transactions {
-        pub home_deploy: Option<TransactionConfig>,
-        pub foreign_deploy: Option<TransactionConfig>,
-        pub deposit_relay: Option<TransactionConfig>,
-        pub withdraw_confirm: Option<TransactionConfig>,
-        pub withdraw_relay: Option<TransactionConfig>,
-    }
+        let priority_above_max =
+            toml.replace("max_priority_fee_per_gas = 2", "max_priority_fee_per_gas = 31");
+        assert!(Config::load_from_str(&priority_above_max).is_err());
 
-    #[derive(Deserialize)]
-    #[serde(deny_unknown_fields)]
-    pub struct TransactionConfig {
-        pub gas: Option<u64>,
-        pub gas_price: Option<u64>,
-    }
+        let max_fee_only = toml.replace(", max_priority_fee_per_gas = 2", "");
+        assert!(Config::load_from_str(&max_fee_only).is_err());
 
-    #[derive(Deserialize)]
-    #[serde(deny_unknown_fields)]
-    pub struct ContractConfig {
-        pub bin: PathBuf,
-    }
+        let withdraw_relay = format!(
+            "{}withdraw_relay = {{ gas = 10, max_priority_fee_per_gas = 1 }}\n",
+            toml
+        );
+        assert!(Config::load_from_str(&withdraw_relay).is_err());
 
-    #[derive(Deserialize)]
-    #[serde(deny_unknown_fields)]
-    pub struct Authorities {
-        pub accounts: Vec<Address>,
-        pub required_signatures: u32,
+        let legacy = toml.replace(
+            "[foreign]\n",
+            "[foreign]\ntransaction_type = \"legacy\"\n",
+        );
+        assert!(Config::load_from_str(&legacy).is_err());
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
-    use rustc_hex::FromHex;
-    use super::{Authorities, Config, ContractConfig, Node, TransactionConfig, Transactions};
-    use ethereum_types::U256;
 
     #[test]
-    fn load_full_setup_from_str() {
+    fn load_max_batch_size_from_str() {
         let toml = r#"
-estimated_gas_cost_of_withdraw = "100000"
-max_total_home_contract_balance = "10000000000000000000"
-max_single_deposit_value = "1000000000000000000"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
 
 [home]
 account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
-ipc = "/home.ipc"
-poll_interval = 2
-required_confirmations = 100
+ipc = ""
 
 [home.contract]
-bin = "../compiled_contracts/HomeBridge.bin"
+bin = "../contracts/compiled/HomeBridge.bin"
 
 [foreign]
 account = "0x0000000000000000000000000000000000000001"
-ipc = "/foreign.ipc"
+ipc = ""
 
 [foreign.contract]
-bin = "../compiled_contracts/ForeignBridge.bin"
+bin = "../contracts/compiled/ForeignBridge.bin"
 
 [authorities]
-accounts = [
-	"0x0000000000000000000000000000000000000001",
-	"0x0000000000000000000000000000000000000002",
-	"0x0000000000000000000000000000000000000003"
-]
-required_signatures = 2
+accounts = []
+required_signatures = 1
 
 [transactions]
-home_deploy = { gas = 20 }
+max_batch_size = 20
+deposit_relay = { gas = 10 }
 "#;
 
-        let mut expected = Config {
-            txs: Transactions::default(),
-            home: Node {
-                account: "1B68Cb0B50181FC4006Ce572cF346e596E51818b".into(),
-                ipc: "/home.ipc".into(),
-                contract: ContractConfig {
-                    bin: include_str!("../../compiled_contracts/HomeBridge.bin")
-                        .from_hex()
-                        .unwrap()
-                        .into(),
-                },
-                poll_interval: Duration::from_secs(2),
-                request_timeout: Duration::from_secs(5),
-                required_confirmations: 100,
-            },
-            foreign: Node {
-                account: "0000000000000000000000000000000000000001".into(),
-                contract: ContractConfig {
-                    bin: include_str!("../../compiled_contracts/ForeignBridge.bin")
-                        .from_hex()
-                        .unwrap()
-                        .into(),
-                },
-                ipc: "/foreign.ipc".into(),
-                poll_interval: Duration::from_secs(1),
-                request_timeout: Duration::from_secs(5),
-                required_confirmations: 12,
-            },
-            authorities: Authorities {
-                accounts: vec![
-                    "0000000000000000000000000000000000000001".into(),
-                    "0000000000000000000000000000000000000002".into(),
-                    "0000000000000000000000000000000000000003".into(),
-                ],
-                required_signatures: 2,
-            },
-            estimated_gas_cost_of_withdraw: U256::from_dec_str("100000").unwrap(),
-            max_total_home_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
-            max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
-        };
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(Some(20), config.txs.max_batch_size);
+        assert_eq!(10, config.txs.deposit_relay.gas);
 
-        expected.txs.home_deploy = TransactionConfig {
-            gas: 20,
-            gas_price: 0,
-        };
+        let unbatched = toml.replace("max_batch_size = 20\n", "");
+        let config = Config::load_from_str(&unbatched).unwrap();
+        assert_eq!(None, config.txs.max_batch_size);
 
-        let config = Config::load_from_str(toml).unwrap();
-        assert_eq!(expected, config);
+        let zero = toml.replace("max_batch_size = 20", "max_batch_size = 0");
+        assert!(Config::load_from_str(&zero).is_err());
     }
 
     #[test]
-    fn load_minimal_setup_from_str() {
+    fn load_value_limits_from_str() {
         let toml = r#"
-estimated_gas_cost_of_withdraw = "200000000"
-max_total_home_contract_balance = "10000000000000000000"
-max_single_deposit_value = "1000000000000000000"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
 
 [home]
 account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
 ipc = ""
 
 [home.contract]
-bin = "../compiled_contracts/HomeBridge.bin"
+bin = "../contracts/compiled/HomeBridge.bin"
 
 [foreign]
 account = "0x0000000000000000000000000000000000000001"
 ipc = ""
 
 [foreign.contract]
-bin = "../compiled_contracts/ForeignBridge.bin"
+bin = "../contracts/compiled/ForeignBridge.bin"
 
 [authorities]
-accounts = [
-	"0x0000000000000000000000000000000000000001",
-	"0x0000000000000000000000000000000000000002",
-	"0x0000000000000000000000000000000000000003"
-]
-required_signatures = 2
+accounts = []
+required_signatures = 1
+
+[value_limits.deposits]
+max_single_value = "1_000"
+max_daily_value = "10_000"
+
+[value_limits.withdraws]
+max_daily_value = "500"
 "#;
-        let expected = Config {
-            txs: Transactions::default(),
-            home: Node {
-                account: "1B68Cb0B50181FC4006Ce572cF346e596E51818b".into(),
-                ipc: "".into(),
-                contract: ContractConfig {
-                    bin: include_str!("../../compiled_contracts/HomeBridge.bin")
-                        .from_hex()
-                        .unwrap()
-                        .into(),
+
+        let config = Config::load_from_str(toml).unwrap();
+        assert_eq!(
+            ValueLimits {
+                deposits: ValueLimit {
+                    max_single_value: Some(1_000.into()),
+                    max_daily_value: Some(10_000.into()),
                 },
-                poll_interval: Duration::from_secs(1),
-                request_timeout: Duration::from_secs(5),
-                required_confirmations: 12,
-            },
-            foreign: Node {
-                account: "0000000000000000000000000000000000000001".into(),
-                ipc: "".into(),
-                contract: ContractConfig {
-                    bin: include_str!("../../compiled_contracts/ForeignBridge.bin")
-                        .from_hex()
-                        .unwrap()
-                        .into(),
+                withdraws: ValueLimit {
+                    max_single_value: None,
+                    max_daily_value: Some(500.into()),
                 },
-                poll_interval: Duration::from_secs(1),
-                request_timeout: Duration::from_secs(5),
-                required_confirmations: 12,
             },
-            authorities: Authorities {
-                accounts: vec![
-                    "0000000000000000000000000000000000000001".into(),
-                    "0000000000000000000000000000000000000002".into(),
-                    "0000000000000000000000000000000000000003".into(),
-                ],
-                required_signatures: 2,
+            config.value_limits
+        );
+
+        let above_daily =
+            toml.replace("max_single_value = \"1_000\"", "max_single_value = \"20_000\"");
+        assert!(Config::load_from_str(&above_daily).is_err());
+        let zero = toml.replace("max_daily_value = \"500\"", "max_daily_value = \"0\"");
+        assert!(Config::load_from_str(&zero).is_err());
+    }
+
+    #[test]
+    fn load_template() {
+        let toml = template(
+            Path::new("../contracts/compiled/HomeBridge.bin"),
+            Path::new("../contracts/compiled/ForeignBridge.bin"),
+        );
+        let config = Config::load_from_str(&toml).unwrap();
+        assert_eq!(DEFAULT_CONFIRMATIONS, config.home.required_confirmations);
+        assert_eq!(None, config.foreign.transaction_type);
+        assert_eq!(200000, config.txs.withdraw_relay.gas);
+    }
+
+    #[test]
+    fn test_access_list_mode() {
+        let mut node = Node {
+            account: "0000000000000000000000000000000000000001".into(),
+            contract: ContractConfig {
+                bin: Default::default(),
             },
-            estimated_gas_cost_of_withdraw: U256::from_dec_str("200000000").unwrap(),
-            max_total_home_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
-            max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
+            endpoint: NodeEndpoint::Ipc("".into()),
+            request_timeout: Duration::from_secs(5),
+            tls: TlsConfig::default(),
+            compression: false,
+            compress_requests: false,
+            relay_deadline: Duration::from_secs(60),
+            max_relay_attempts: 20,
+            max_submission_delay: Duration::from_millis(0),
+            poll_interval: Duration::from_secs(1),
+            max_poll_interval: Duration::from_secs(30),
+            subscribe_new_heads: false,
+            required_confirmations: 12,
+            confirmation_strategy: ConfirmationStrategy::Depth,
+            reorg_checkpoints: 32,
+            chain_id: None,
+            access_lists: false,
+            transaction_type: None,
+            registry: None,
+            maintenance_windows: vec![],
+            max_requests_per_second: None,
+            fallback_accounts: vec![],
+            token_address: None,
+            gas_price_oracle: None,
+            stuck_transactions: None,
+            balance_watchdog: None,
+            retry: RetryConfig::default(),
+        };
+        let access_list = vec![
+            AccessListItem {
+                address: "0000000000000000000000000000000000000004".into(),
+                storage_keys: vec![],
+            },
+        ];
+        let tx = TransactionConfig::default();
+        let tx_with_access_list = TransactionConfig {
+            access_list: Some(access_list.clone()),
+            ..Default::default()
         };
 
-        let config = Config::load_from_str(toml).unwrap();
-        assert_eq!(expected, config);
+        assert_eq!(AccessListMode::Disabled, node.access_list_mode(&tx));
+        assert_eq!(AccessListMode::Disabled, node.access_list_mode(&tx_with_access_list));
+        node.access_lists = true;
+        assert_eq!(AccessListMode::Generate, node.access_list_mode(&tx));
+        assert_eq!(
+            AccessListMode::Static(access_list),
+            node.access_list_mode(&tx_with_access_list)
+        );
+    }
+
+    #[test]
+    fn load_bridges_from_str() {
+        extern crate tempdir;
+        use std::fs;
+        use std::io::Write;
+        use self::tempdir::TempDir;
+
+        let config = |http_api: &str| {
+            format!(
+                r#"
+estimated_gas_cost_of_withdraw = 100_000
+max_total_home_contract_balance = "0"
+max_single_deposit_value = "0"
+
+[home]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[home.contract]
+bin = "../contracts/compiled/HomeBridge.bin"
+
+[foreign]
+account = "0x1B68Cb0B50181FC4006Ce572cF346e596E51818b"
+ipc = ""
+
+[foreign.contract]
+bin = "../contracts/compiled/ForeignBridge.bin"
+
+[authorities]
+accounts = []
+required_signatures = 1
+{}
+"#,
+                http_api
+            )
+        };
+        let tempdir = TempDir::new("load_bridges_from_str").unwrap();
+        let plain = tempdir.path().join("plain.toml");
+        let with_http_api = tempdir.path().join("http-api.toml");
+        fs::File::create(&plain)
+            .unwrap()
+            .write_all(config("").as_bytes())
+            .unwrap();
+        fs::File::create(&with_http_api)
+            .unwrap()
+            .write_all(config("[http_api]\naddress = \"127.0.0.1:8080\"").as_bytes())
+            .unwrap();
+        let (plain, with_http_api) = (plain.as_path(), with_http_api.as_path());
+        let bridges = |definitions: &[(&str, &Path, &str)]| {
+            let mut toml = "log_format = \"json\"\n".to_owned();
+            for &(name, config, database) in definitions {
+                toml.push_str(&format!(
+                    "[[bridges]]\nname = \"{}\"\nconfig = {:?}\ndatabase = \"{}\"\n",
+                    name, config, database
+                ));
+            }
+            BridgesConfig::load_from_str(&toml)
+        };
+
+        let loaded = bridges(&[
+            ("eth-poa", with_http_api, "eth-poa.toml"),
+            ("eth_xdai", plain, "eth-xdai.toml"),
+        ]).unwrap();
+        assert_eq!(LogFormat::Json, loaded.log_format);
+        assert_eq!(2, loaded.bridges.len());
+        assert_eq!("eth-poa", loaded.bridges[0].name);
+        assert_eq!(Path::new("eth-poa.toml"), loaded.bridges[0].database.as_path());
+        assert_eq!(Config::load(plain).unwrap(), loaded.bridges[1].config);
+
+        assert!(bridges(&[]).is_err());
+        assert!(bridges(&[("eth poa", plain, "eth-poa.toml")]).is_err());
+        assert!(
+            bridges(&[
+                ("eth-poa", plain, "eth-poa.toml"),
+                ("eth-poa", plain, "eth-xdai.toml"),
+            ]).is_err()
+        );
+        assert!(
+            bridges(&[
+                ("eth-poa", plain, "db.toml"),
+                ("eth-xdai", plain, "db.toml"),
+            ]).is_err()
+        );
+        assert!(
+            bridges(&[
+                ("eth-poa", with_http_api, "eth-poa.toml"),
+                ("eth-xdai", with_http_api, "eth-xdai.toml"),
+            ]).is_err()
+        );
     }
 }
@@ -1,6 +1,49 @@
-use_contract!(home, "HomeBridge", "../compiled_contracts/HomeBridge.abi");
-use_contract!(
-    foreign,
-    "ForeignBridge",
-    "../compiled_contracts/ForeignBridge.abi"
-);
+/// bindings for the ether bridge contracts of `contracts/bridge.sol`
+pub mod v1 {
+    // `use_contract!` of `home` and `foreign`
+    // for the artifacts that `build.rs` places in `OUT_DIR`
+    include!(concat!(env!("OUT_DIR"), "/contracts_v1.rs"));
+}
+
+/// bindings for the ERC20 token bridge contracts of `contracts/bridge.sol`
+#[cfg(feature = "contracts-v2")]
+pub mod v2 {
+    // `use_contract!` of `home_token` and `foreign_token`
+    // for the artifacts that `build.rs` places in `OUT_DIR`
+    include!(concat!(env!("OUT_DIR"), "/contracts_v2.rs"));
+}
+
+pub use self::v1::{foreign, home};
+#[cfg(feature = "contracts-v2")]
+pub use self::v2::{foreign_token, home_token};
+
+/// bindings for registries that implement `getAddress(bytes32,string)` of parity's `SimpleRegistry`
+use_contract!(registry, "Registry", "../contracts/Registry.abi");
+
+/// generation of the bridge contracts a deployment uses
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ContractVersion {
+    /// ether bridge of `contracts/bridge.sol`
+    V1,
+    /// ERC20 token bridge of `contracts/bridge.sol`. requires feature `contracts-v2`
+    V2,
+}
+
+impl ContractVersion {
+    /// returns whether the bindings for this version are compiled in
+    pub fn is_enabled(&self) -> bool {
+        match *self {
+            ContractVersion::V1 => true,
+            ContractVersion::V2 => cfg!(feature = "contracts-v2"),
+        }
+    }
+}
+
+/// bindings for the ENS registry and resolvers
+pub mod ens {
+    use_contract!(ens, "ENS", "../contracts/ENS.abi");
+    use_contract!(resolver, "ENSResolver", "../contracts/ENSResolver.abi");
+
+    pub use self::ens::ENS;
+    pub use self::resolver::ENSResolver;
+}
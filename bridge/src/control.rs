@@ -0,0 +1,367 @@
+/// local control of a running bridge.
+/// each relay direction can be paused without a restart by either
+/// creating its pause file, sending a signal to the process
+/// or a request to the http api (see `http`):
+/// `SIGUSR1` toggles the pause of deposit relays,
+/// `SIGUSR2` toggles the pause of withdraw confirmations and relays.
+/// paused streams keep their position and continue where they left off once resumed.
+/// in a process that runs several bridges (see `supervisor`) the pauses by http api,
+/// insolvency, halted signing and low balance apply to the bridge they were caused by
+/// while signals apply to every bridge of the process.
+/// signing of withdraws is also halted while `HomeBridge` is insolvent
+/// (see `bridge::solvency_check`) and, until a restart, once a submitted signature
+/// failed verification (see `bridge::signature_verification`).
+/// relays to a chain are paused while the balance of the account on it is too low
+/// to pay for them (see `bridge::balance_watchdog`).
+/// `SIGINT` and `SIGTERM` pause every direction for good and shut the bridge down
+/// once the relays in progress are done (see `bridge::Bridge`).
+/// a second `SIGINT` or `SIGTERM` exits right away.
+/// on windows `ctrl-c`, `ctrl-break`, closing the console and the shutdown of windows
+/// do the same, which is how service wrappers stop the bridge.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::time::Duration;
+use futures::{Async, Poll, Stream};
+use tokio_timer::{Interval, Timer};
+use error::Error;
+use signer::Chain;
+use supervisor;
+
+/// toggled by `SIGUSR1`. pauses the deposits of every bridge of the process
+static DEPOSITS_TOGGLED: AtomicBool = ATOMIC_BOOL_INIT;
+/// toggled by `SIGUSR2`. pauses the withdraws of every bridge of the process
+static WITHDRAWS_TOGGLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// set by the first `SIGINT` or `SIGTERM`
+static SHUTDOWN: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// pauses of a bridge of the process
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Flags {
+    /// set by the http api
+    deposits_paused: bool,
+    /// set by the http api
+    withdraws_paused: bool,
+    /// set while `HomeBridge` can't cover the pending withdraws
+    insolvent: bool,
+    /// set once a signature that was read back from `ForeignBridge` didn't match
+    signing_halted: bool,
+    /// set while the balance of `home.account` is below `home.balance_watchdog.min_balance`
+    home_underfunded: bool,
+    /// set while the balance of `foreign.account` is below
+    /// `foreign.balance_watchdog.min_balance`
+    foreign_underfunded: bool,
+}
+
+/// pauses by bridge. the key is `None` in a process that runs a single bridge
+type Registry = BTreeMap<Option<String>, Flags>;
+
+fn registry() -> &'static Mutex<Registry> {
+    static INIT: Once = ONCE_INIT;
+    static mut REGISTRY: *const Mutex<Registry> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            REGISTRY = Box::into_raw(Box::new(Mutex::new(Registry::new())));
+        });
+        &*REGISTRY
+    }
+}
+
+/// calls `f` with the pauses of `bridge`
+fn with_flags<R, F: FnOnce(&mut Flags) -> R>(bridge: Option<String>, f: F) -> R {
+    let mut registry = registry().lock().expect("pauses are never poisoned; qed");
+    f(registry.entry(bridge).or_insert_with(Default::default))
+}
+
+/// returns whether `bridge` runs in this process, so far as it checked its pauses
+pub fn is_known(bridge: &Option<String>) -> bool {
+    registry()
+        .lock()
+        .expect("pauses are never poisoned; qed")
+        .contains_key(bridge)
+}
+
+/// sets whether `HomeBridge` of the current bridge can't cover the pending withdraws
+pub fn set_insolvent(insolvent: bool) {
+    with_flags(supervisor::current(), |flags| flags.insolvent = insolvent);
+}
+
+/// halts signing of withdraws of the current bridge until the process is restarted
+pub fn halt_signing() {
+    with_flags(supervisor::current(), |flags| flags.signing_halted = true);
+}
+
+/// sets whether the account of the current bridge on `chain` can't pay for relays
+pub fn set_underfunded(chain: Chain, underfunded: bool) {
+    with_flags(supervisor::current(), |flags| match chain {
+        Chain::Home => flags.home_underfunded = underfunded,
+        Chain::Foreign => flags.foreign_underfunded = underfunded,
+    });
+}
+
+/// returns true once the bridge was asked to shut down
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// relay direction that can be paused
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction {
+    /// deposits from home to foreign
+    Deposits,
+    /// withdraws from foreign to home
+    Withdraws,
+}
+
+fn toggled(direction: Direction) -> &'static AtomicBool {
+    match direction {
+        Direction::Deposits => &DEPOSITS_TOGGLED,
+        Direction::Withdraws => &WITHDRAWS_TOGGLED,
+    }
+}
+
+/// pauses or resumes `direction` of `bridge`. has no effect on pauses by signal,
+/// pause file, insolvency, halted signing or low balance
+pub fn set_paused(bridge: Option<String>, direction: Direction, paused: bool) {
+    with_flags(bridge, |flags| match direction {
+        Direction::Deposits => flags.deposits_paused = paused,
+        Direction::Withdraws => flags.withdraws_paused = paused,
+    });
+}
+
+/// pause switch of a relay direction
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pause {
+    pub direction: Direction,
+    /// the direction is paused while this file exists
+    pub file: Option<PathBuf>,
+    /// whether the direction is also paused while `HomeBridge` is insolvent
+    pub insolvency: bool,
+    /// whether the direction is also paused once signing was halted
+    pub halted: bool,
+    /// chain the direction sends its transactions to.
+    /// the direction is also paused while the account on it is underfunded
+    pub chain: Chain,
+}
+
+impl Pause {
+    /// returns true if the direction of the current bridge is paused
+    /// by signal, http api or pause file or the bridge shuts down
+    pub fn is_paused(&self) -> bool {
+        self.is_paused_for(supervisor::current())
+    }
+
+    /// returns true if the direction of `bridge` is paused
+    /// by signal, http api or pause file or the bridge shuts down
+    pub fn is_paused_for(&self, bridge: Option<String>) -> bool {
+        let signaled = toggled(self.direction).load(Ordering::SeqCst) || shutdown_requested();
+        let flags = with_flags(bridge, |flags| flags.clone());
+        let paused = match self.direction {
+            Direction::Deposits => flags.deposits_paused,
+            Direction::Withdraws => flags.withdraws_paused,
+        };
+        let underfunded = match self.chain {
+            Chain::Home => flags.home_underfunded,
+            Chain::Foreign => flags.foreign_underfunded,
+        };
+        signaled || paused || self.file.as_ref().map_or(false, |file| file.exists())
+            || (self.insolvency && flags.insolvent)
+            || (self.halted && flags.signing_halted) || underfunded
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn toggle_deposits(_: ::libc::c_int) {
+    DEPOSITS_TOGGLED.fetch_xor(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn toggle_withdraws(_: ::libc::c_int) {
+    WITHDRAWS_TOGGLED.fetch_xor(true, Ordering::SeqCst);
+}
+
+/// installs the handlers for `SIGUSR1` and `SIGUSR2` that toggle the pauses
+#[cfg(unix)]
+pub fn install_signal_handlers() {
+    unsafe {
+        ::libc::signal(::libc::SIGUSR1, toggle_deposits as ::libc::sighandler_t);
+        ::libc::signal(::libc::SIGUSR2, toggle_withdraws as ::libc::sighandler_t);
+    }
+}
+
+/// signals are not supported. pauses can only be controlled via pause files
+#[cfg(not(unix))]
+pub fn install_signal_handlers() {}
+
+#[cfg(unix)]
+extern "C" fn request_shutdown(_: ::libc::c_int) {
+    if SHUTDOWN.swap(true, Ordering::SeqCst) {
+        unsafe { ::libc::_exit(1) }
+    }
+}
+
+/// installs the handlers for `SIGINT` and `SIGTERM` that shut the bridge down.
+/// installed once the bridge starts so that other commands are interrupted as usual
+#[cfg(unix)]
+pub fn install_shutdown_handlers() {
+    unsafe {
+        ::libc::signal(::libc::SIGINT, request_shutdown as ::libc::sighandler_t);
+        ::libc::signal(::libc::SIGTERM, request_shutdown as ::libc::sighandler_t);
+    }
+}
+
+#[cfg(windows)]
+const CTRL_C_EVENT: u32 = 0;
+#[cfg(windows)]
+const CTRL_BREAK_EVENT: u32 = 1;
+#[cfg(windows)]
+const CTRL_CLOSE_EVENT: u32 = 2;
+#[cfg(windows)]
+const CTRL_SHUTDOWN_EVENT: u32 = 6;
+
+#[cfg(windows)]
+extern "system" {
+    fn SetConsoleCtrlHandler(
+        handler: Option<extern "system" fn(u32) -> i32>,
+        add: i32,
+    ) -> i32;
+}
+
+#[cfg(windows)]
+extern "system" fn handle_ctrl_event(event: u32) -> i32 {
+    match event {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT => {
+            if SHUTDOWN.swap(true, Ordering::SeqCst) {
+                ::std::process::exit(1);
+            }
+            1
+        }
+        CTRL_CLOSE_EVENT | CTRL_SHUTDOWN_EVENT => {
+            SHUTDOWN.store(true, Ordering::SeqCst);
+            // windows terminates the process once the handler returns.
+            // it keeps running until the relays in progress are done or windows gives up
+            loop {
+                ::std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+        // `CTRL_LOGOFF_EVENT` is sent to services whenever any user logs off
+        _ => 0,
+    }
+}
+
+/// installs the handler for console control events that shuts the bridge down.
+/// installed once the bridge starts so that other commands are interrupted as usual
+#[cfg(windows)]
+pub fn install_shutdown_handlers() {
+    if unsafe { SetConsoleCtrlHandler(Some(handle_ctrl_event), 1) } == 0 {
+        warn!("cannot install the handler of ctrl-c. ctrl-c exits right away");
+    }
+}
+
+/// signals are not supported. the bridge exits on `ctrl-c` right away
+#[cfg(not(any(unix, windows)))]
+pub fn install_shutdown_handlers() {}
+
+/// creates a new `Pausable` which checks whether `pause` is lifted every `poll_interval`
+pub fn pausable<S: Stream>(
+    stream: S,
+    pause: Pause,
+    timer: &Timer,
+    poll_interval: Duration,
+) -> Pausable<S> {
+    Pausable {
+        stream,
+        pause,
+        interval: timer.interval(poll_interval),
+        item: None,
+        paused: false,
+    }
+}
+
+/// stream that holds back the items of `stream` while `pause` is in effect.
+/// `stream` is not polled until the held back item is released.
+pub struct Pausable<S: Stream> {
+    stream: S,
+    pause: Pause,
+    interval: Interval,
+    item: Option<S::Item>,
+    /// whether the pause was in effect when last checked
+    paused: bool,
+}
+
+impl<S: Stream> Stream for Pausable<S>
+where
+    Error: From<S::Error>,
+{
+    type Item = S::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.item.is_none() {
+                self.item = Some(try_stream!(self.stream.poll()));
+            }
+
+            let paused = self.pause.is_paused();
+            if paused != self.paused {
+                if paused {
+                    warn!("{:?} paused", self.pause.direction);
+                } else {
+                    info!("{:?} resumed", self.pause.direction);
+                }
+                self.paused = paused;
+            }
+
+            if !paused {
+                return Ok(Async::Ready(self.item.take()));
+            }
+
+            let _ = try_stream!(self.interval.poll());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use signer::Chain;
+    use supervisor;
+    use super::{halt_signing, is_known, set_paused, set_underfunded, Direction, Pause};
+
+    #[test]
+    fn test_pauses_by_bridge() {
+        let pause = Pause {
+            direction: Direction::Withdraws,
+            file: None,
+            insolvency: false,
+            halted: true,
+            chain: Chain::Home,
+        };
+        let kovan = Some("test_pauses_kovan".to_owned());
+        let sokol = Some("test_pauses_sokol".to_owned());
+        assert!(!is_known(&kovan));
+        {
+            let _entered = supervisor::enter("test_pauses_kovan");
+            assert!(!pause.is_paused());
+            halt_signing();
+            assert!(pause.is_paused());
+        }
+        assert!(is_known(&kovan));
+        assert!(!pause.is_paused_for(sokol.clone()));
+
+        set_paused(sokol.clone(), Direction::Withdraws, true);
+        assert!(pause.is_paused_for(sokol.clone()));
+        set_paused(sokol.clone(), Direction::Withdraws, false);
+        assert!(!pause.is_paused_for(sokol.clone()));
+
+        {
+            let _entered = supervisor::enter("test_pauses_sokol");
+            set_underfunded(Chain::Foreign, true);
+            assert!(!pause.is_paused());
+            set_underfunded(Chain::Home, true);
+            assert!(pause.is_paused());
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use std::cmp;
 use std::path::Path;
 use std::{fmt, fs, io, str};
 use std::io::{Read, Write};
-use web3::types::{Address, TransactionReceipt};
+use web3::types::{Address, H256, TransactionReceipt};
 use toml;
 use error::{Error, ErrorKind, ResultExt};
+use journal::JournalEntry;
+use parked::{DeadLetter, ParkedRelay};
+use value_limits::ValueWindow;
 
 /// Application "database".
 #[derive(Debug, PartialEq, Deserialize, Serialize, Default, Clone)]
@@ -22,6 +26,40 @@ pub struct Database {
     pub checked_withdraw_relay: u64,
     /// Number of last block which has been checked for withdraw confirms.
     pub checked_withdraw_confirm: u64,
+    /// Number of last block on home which has been checked for unaccounted transfers.
+    #[serde(default)]
+    pub checked_unaccounted_transfers: u64,
+    /// Number of last block on foreign which has been checked for call confirms.
+    #[serde(default)]
+    pub checked_call_confirm: u64,
+    /// Number of last block on foreign which has been checked for call relays.
+    #[serde(default)]
+    pub checked_call_relay: u64,
+    /// Hash of block `checked_deposit_relay` if it is known.
+    /// Used to detect reorgs that replaced it (see `checkpoints`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked_deposit_relay_hash: Option<H256>,
+    /// Hash of block `checked_withdraw_relay` if it is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked_withdraw_relay_hash: Option<H256>,
+    /// Hash of block `checked_withdraw_confirm` if it is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checked_withdraw_confirm_hash: Option<H256>,
+    /// Value of deposits relayed in the current window of `value_limits.deposits`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deposit_value_window: Option<ValueWindow>,
+    /// Value of withdraws confirmed in the current window of `value_limits.withdraws`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdraw_value_window: Option<ValueWindow>,
+    /// Relay transactions sent for events after the checkpoints (see `journal`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub in_flight: Vec<JournalEntry>,
+    /// Relays retried apart from their relay streams (see `parked`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parked: Vec<ParkedRelay>,
+    /// Relays given up after their last attempt reverted (see `parked`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dead_letters: Vec<DeadLetter>,
 }
 
 impl str::FromStr for Database {
@@ -58,6 +96,64 @@ impl Database {
         Ok(())
     }
 
+    /// writes the database to the file at `path`.
+    /// it is written to a temporary file next to it first which replaces the file
+    /// once it is synced to disk, so a crash never leaves a partially written database behind
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp).chain_err(|| format!("Cannot create {:?}", tmp))?;
+        self.save(&mut file)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path).chain_err(|| format!("Cannot write database to {:?}", path))?;
+        Ok(())
+    }
+
+    /// returns this database with the checkpoints of `snapshot` imported.
+    /// every checkpoint advances to the later of both.
+    /// `snapshot` must be of the same deployment.
+    pub fn import_snapshot(&self, snapshot: &Database) -> Result<Self, Error> {
+        if self.home_contract_address != snapshot.home_contract_address
+            || self.foreign_contract_address != snapshot.foreign_contract_address
+        {
+            bail!(
+                "snapshot is of another deployment (home: {:?}, foreign: {:?})",
+                snapshot.home_contract_address,
+                snapshot.foreign_contract_address
+            );
+        }
+        let (checked_deposit_relay, checked_deposit_relay_hash) = later_checkpoint(
+            (self.checked_deposit_relay, self.checked_deposit_relay_hash),
+            (snapshot.checked_deposit_relay, snapshot.checked_deposit_relay_hash),
+        );
+        let (checked_withdraw_relay, checked_withdraw_relay_hash) = later_checkpoint(
+            (self.checked_withdraw_relay, self.checked_withdraw_relay_hash),
+            (snapshot.checked_withdraw_relay, snapshot.checked_withdraw_relay_hash),
+        );
+        let (checked_withdraw_confirm, checked_withdraw_confirm_hash) = later_checkpoint(
+            (self.checked_withdraw_confirm, self.checked_withdraw_confirm_hash),
+            (snapshot.checked_withdraw_confirm, snapshot.checked_withdraw_confirm_hash),
+        );
+        Ok(Self {
+            checked_deposit_relay,
+            checked_deposit_relay_hash,
+            checked_withdraw_relay,
+            checked_withdraw_relay_hash,
+            checked_withdraw_confirm,
+            checked_withdraw_confirm_hash,
+            checked_unaccounted_transfers: cmp::max(
+                self.checked_unaccounted_transfers,
+                snapshot.checked_unaccounted_transfers,
+            ),
+            checked_call_confirm: cmp::max(
+                self.checked_call_confirm,
+                snapshot.checked_call_confirm,
+            ),
+            checked_call_relay: cmp::max(self.checked_call_relay, snapshot.checked_call_relay),
+            ..self.clone()
+        })
+    }
+
     pub fn from_receipts(
         home_receipt: &TransactionReceipt,
         foreign_receipt: &TransactionReceipt,
@@ -74,12 +170,43 @@ impl Database {
             checked_deposit_relay: home_receipt.block_number.low_u64(),
             checked_withdraw_relay: foreign_receipt.block_number.low_u64(),
             checked_withdraw_confirm: foreign_receipt.block_number.low_u64(),
+            checked_unaccounted_transfers: home_receipt.block_number.low_u64(),
+            checked_call_confirm: foreign_receipt.block_number.low_u64(),
+            checked_call_relay: foreign_receipt.block_number.low_u64(),
+            checked_deposit_relay_hash: None,
+            checked_withdraw_relay_hash: None,
+            checked_withdraw_confirm_hash: None,
+            deposit_value_window: None,
+            withdraw_value_window: None,
+            in_flight: Vec::new(),
+            parked: Vec::new(),
+            dead_letters: Vec::new(),
         }
     }
 }
 
+/// returns the later of two checkpoints with the hash of its block.
+/// the hash is dropped if both are at the same block with different hashes
+fn later_checkpoint(
+    a: (u64, Option<H256>),
+    b: (u64, Option<H256>),
+) -> (u64, Option<H256>) {
+    match a.0.cmp(&b.0) {
+        cmp::Ordering::Greater => a,
+        cmp::Ordering::Less => b,
+        cmp::Ordering::Equal if a.1 == b.1 || b.1.is_none() => a,
+        cmp::Ordering::Equal if a.1.is_none() => b,
+        cmp::Ordering::Equal => (a.0, None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    extern crate tempdir;
+    use self::tempdir::TempDir;
+    use journal::JournalEntry;
+    use signer::Chain;
+    use value_limits::ValueWindow;
     use super::Database;
 
     #[test]
@@ -91,6 +218,10 @@ foreign_deploy = 101
 checked_deposit_relay = 120
 checked_withdraw_relay = 121
 checked_withdraw_confirm = 121
+checked_unaccounted_transfers = 120
+checked_call_confirm = 121
+checked_call_relay = 0
+checked_deposit_relay_hash = "0x0000000000000000000000000000000000000000000000000000000000000012"
 "#;
 
         let expected = Database {
@@ -101,6 +232,17 @@ checked_withdraw_confirm = 121
             checked_deposit_relay: 120,
             checked_withdraw_relay: 121,
             checked_withdraw_confirm: 121,
+            checked_unaccounted_transfers: 120,
+            checked_call_confirm: 121,
+            checked_call_relay: 0,
+            checked_deposit_relay_hash: Some(0x12.into()),
+            checked_withdraw_relay_hash: None,
+            checked_withdraw_confirm_hash: None,
+            deposit_value_window: None,
+            withdraw_value_window: None,
+            in_flight: Vec::new(),
+            parked: Vec::new(),
+            dead_letters: Vec::new(),
         };
 
         let database = toml.parse().unwrap();
@@ -108,4 +250,89 @@ checked_withdraw_confirm = 121
         let s = database.to_string();
         assert_eq!(s, toml);
     }
+
+    #[test]
+    fn database_value_windows_to_and_from_str() {
+        let database = Database {
+            deposit_value_window: Some(ValueWindow {
+                started_at: 1_500_000_000,
+                total: 0x100.into(),
+            }),
+            ..Default::default()
+        };
+        let s = database.to_string();
+        assert!(s.contains("[deposit_value_window]"));
+        assert!(!s.contains("withdraw_value_window"));
+        assert_eq!(database, s.parse().unwrap());
+    }
+
+    #[test]
+    fn database_store() {
+        let tempdir = TempDir::new("database_store").unwrap();
+        let path = tempdir.path().join("db.toml");
+        let database = Database {
+            checked_deposit_relay: 120,
+            in_flight: vec![
+                JournalEntry {
+                    chain: Chain::Foreign,
+                    block: 121,
+                    transaction_hash: 0x21.into(),
+                    to: 1.into(),
+                    data: vec![0x12, 0x34].into(),
+                },
+            ],
+            ..Default::default()
+        };
+        database.store(&path).unwrap();
+        assert_eq!(database, Database::load(&path).unwrap());
+
+        // a shorter database replaces the file entirely
+        let database = Database {
+            checked_deposit_relay: 121,
+            ..Default::default()
+        };
+        database.store(&path).unwrap();
+        assert_eq!(database, Database::load(&path).unwrap());
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn test_import_snapshot() {
+        let database = Database {
+            home_contract_address: 1.into(),
+            foreign_contract_address: 2.into(),
+            home_deploy: 100,
+            foreign_deploy: 101,
+            checked_deposit_relay: 100,
+            checked_withdraw_relay: 200,
+            checked_withdraw_confirm: 101,
+            checked_unaccounted_transfers: 100,
+            checked_withdraw_relay_hash: Some(0x20.into()),
+            ..Default::default()
+        };
+        let snapshot = Database {
+            checked_deposit_relay: 150,
+            checked_deposit_relay_hash: Some(0x15.into()),
+            checked_withdraw_relay_hash: Some(0x15.into()),
+            checked_withdraw_relay: 150,
+            checked_withdraw_confirm: 160,
+            ..database.clone()
+        };
+
+        let expected = Database {
+            checked_deposit_relay: 150,
+            checked_deposit_relay_hash: Some(0x15.into()),
+            checked_withdraw_relay: 200,
+            checked_withdraw_relay_hash: Some(0x20.into()),
+            checked_withdraw_confirm: 160,
+            ..database.clone()
+        };
+        assert_eq!(expected, database.import_snapshot(&snapshot).unwrap());
+
+        let other_deployment = Database {
+            home_contract_address: 3.into(),
+            ..snapshot
+        };
+        assert!(database.import_snapshot(&other_deployment).is_err());
+    }
 }
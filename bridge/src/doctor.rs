@@ -0,0 +1,591 @@
+/// diagnostics of the setup of a bridge (`parity-bridge doctor`).
+/// runs the checks behind the most common support questions one after another
+/// and reports each as passed or failed with a hint how to fix it.
+/// checks that depend on a failed one are left out.
+
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::time::Instant;
+use tokio_core::reactor::Core;
+use web3::Transport;
+use web3::types::{Address, U256};
+use api;
+use app::App;
+use config::Config;
+use database::Database;
+use error::{Error, ErrorKind};
+use signer::remote_signer;
+
+/// outcome of a check
+#[derive(Debug, PartialEq, Clone)]
+pub struct Check {
+    pub name: String,
+    /// what was found
+    pub detail: String,
+    /// how to fix a failed check. `None` if it passed
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass<N: Into<String>, D: Into<String>>(name: N, detail: D) -> Self {
+        Check {
+            name: name.into(),
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail<N, D, H>(name: N, detail: D, hint: H) -> Self
+    where
+        N: Into<String>,
+        D: Into<String>,
+        H: Into<String>,
+    {
+        Check {
+            name: name.into(),
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.hint.is_none()
+    }
+}
+
+/// renders `checks` one per line followed by a summary
+pub fn render(checks: &[Check]) -> String {
+    let mut out = String::new();
+    for check in checks {
+        out.push_str(&format!(
+            "[{}] {}: {}\n",
+            if check.passed() { "pass" } else { "FAIL" },
+            check.name,
+            check.detail
+        ));
+        if let Some(ref hint) = check.hint {
+            out.push_str(&format!("       hint: {}\n", hint));
+        }
+    }
+    let failed = checks.iter().filter(|check| !check.passed()).count();
+    out.push_str(&format!("{} checks, {} failed", checks.len(), failed));
+    out
+}
+
+/// returns the balance `account` needs for a transaction with `gas` and `gas_price`.
+/// `None` on overflow
+fn required_balance(gas: u64, gas_price: u64) -> Option<U256> {
+    let (required, overflow) = U256::from(gas).overflowing_mul(gas_price.into());
+    if overflow {
+        None
+    } else {
+        Some(required)
+    }
+}
+
+fn check_balance(chain: &str, account: Address, balance: U256, gas: u64, gas_price: u64) -> Check {
+    let name = format!("{} balance", chain);
+    match required_balance(gas, gas_price) {
+        Some(required) if balance >= required => Check::pass(
+            name,
+            format!("{} has {} wei", account, balance),
+        ),
+        required => Check::fail(
+            name,
+            format!(
+                "{} has {} wei but a transaction costs up to {} wei",
+                account,
+                balance,
+                required.map_or_else(|| "more than 2^256".into(), |required| required.to_string())
+            ),
+            format!(
+                "fund {} on {} or lower the gas price of the transactions in `transactions`",
+                account, chain
+            ),
+        ),
+    }
+}
+
+/// checks that the database at `path` exists, parses and can be written by this user
+fn check_database(path: &Path) -> (Check, Option<Database>) {
+    let name = "database";
+    let database = match Database::load(path) {
+        Ok(database) => database,
+        Err(Error(ErrorKind::MissingFile(_), _)) => {
+            return (
+                Check::fail(
+                    name,
+                    format!("{:?} doesn't exist", path),
+                    "deploy the contracts, import a snapshot (`database import`) \
+                     or rebuild the database from the chains (`rebuild-state`)",
+                ),
+                None,
+            )
+        }
+        Err(err) => {
+            return (
+                Check::fail(
+                    name,
+                    format!("{:?} can't be loaded: {}", path, err),
+                    "restore the database from a snapshot or rebuild it (`rebuild-state`)",
+                ),
+                None,
+            )
+        }
+    };
+    if let Err(err) = OpenOptions::new().append(true).open(path) {
+        return (
+            Check::fail(
+                name,
+                format!("{:?} isn't writable: {}", path, err),
+                "the bridge saves its checkpoints to the database. \
+                 run it as the owner of the file or fix the permissions",
+            ),
+            Some(database),
+        );
+    }
+    if let Some(owner) = foreign_owner(path) {
+        return (
+            Check::fail(
+                name,
+                format!("{:?} is owned by user {}, not by this user", path, owner),
+                "run the bridge as the user that owns the database \
+                 so two installations don't share it by accident",
+            ),
+            Some(database),
+        );
+    }
+    (
+        Check::pass(name, format!("{:?} is writable", path)),
+        Some(database),
+    )
+}
+
+/// returns the owner of `path` if it isn't the user running this process
+#[cfg(unix)]
+fn foreign_owner(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    let owner = path.metadata().ok()?.uid();
+    let user = unsafe { ::libc::geteuid() };
+    if owner == user {
+        None
+    } else {
+        Some(owner)
+    }
+}
+
+#[cfg(not(unix))]
+fn foreign_owner(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// checks the chain id `actual` the node of `chain` reports against the `expected` one.
+/// `None` if no chain id is expected
+fn check_chain_id(chain: &str, expected: Option<u64>, actual: U256) -> Option<Check> {
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return None,
+    };
+    let name = format!("{} chain id", chain);
+    Some(if actual == expected.into() {
+        Check::pass(name, format!("chain {} as expected", expected))
+    } else {
+        Check::fail(
+            name,
+            format!("node is on chain {} but chain {} is expected", actual, expected),
+            format!(
+                "point `{}.ipc`, `http` or `ws` to a node of chain {} or fix `network` and `{}.chain_id`",
+                chain, expected, chain
+            ),
+        )
+    })
+}
+
+/// checks that the node of `chain` answers and returns its chain id
+fn check_rpc<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    chain: &str,
+    transport: &T,
+    checks: &mut Vec<Check>,
+) -> Option<U256> {
+    let node = if chain == "home" {
+        &app.config.home
+    } else {
+        &app.config.foreign
+    };
+    let start = Instant::now();
+    let result = event_loop.run(
+        app.timer
+            .timeout(api::block_number(transport), node.request_timeout),
+    );
+    let elapsed = start.elapsed();
+    let millis = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos() / 1_000_000);
+    match result {
+        Ok(block) => checks.push(Check::pass(
+            format!("{} rpc", chain),
+            format!("latest block {} in {} ms", block, millis),
+        )),
+        Err(err) => {
+            checks.push(Check::fail(
+                format!("{} rpc", chain),
+                format!("{} didn't answer: {}", node.endpoint, err),
+                format!(
+                    "check that the node of {} runs, is synced and `{}.ipc`, `http` or `ws` points to it",
+                    chain, chain
+                ),
+            ));
+            return None;
+        }
+    }
+    if let Some(tag) = node.confirmation_strategy.block_tag() {
+        match event_loop.run(
+            app.timer
+                .timeout(api::tagged_block_header(transport, tag), node.request_timeout),
+        ) {
+            Ok(header) => checks.push(Check::pass(
+                format!("{} {} block", chain, tag),
+                format!("{} block {}", tag, header.number.unwrap_or_default()),
+            )),
+            Err(err) => checks.push(Check::fail(
+                format!("{} {} block", chain, tag),
+                format!("the node doesn't report the {} block: {}", tag, err),
+                format!(
+                    "run a node of a proof of stake chain that supports the `{}` tag or change `{}.confirmation_strategy`",
+                    tag, chain
+                ),
+            )),
+        }
+    }
+    match event_loop.run(
+        app.timer
+            .timeout(api::chain_id(transport), node.request_timeout),
+    ) {
+        Ok(chain_id) => {
+            checks.extend(check_chain_id(chain, node.chain_id, chain_id));
+            Some(chain_id)
+        }
+        Err(err) => {
+            checks.push(Check::fail(
+                format!("{} chain id", chain),
+                format!("`eth_chainId` failed: {}", err),
+                "upgrade the node to a version that supports `eth_chainId`",
+            ));
+            None
+        }
+    }
+}
+
+/// checks that `HomeBridge` or `ForeignBridge` at `address` has code and lists `account` as an authority
+fn check_contract<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    chain: &str,
+    transport: &T,
+    address: Address,
+    account: Address,
+    checks: &mut Vec<Check>,
+) {
+    let node = if chain == "home" {
+        &app.config.home
+    } else {
+        &app.config.foreign
+    };
+    let name = format!("{} contract", chain);
+    match event_loop.run(
+        app.timer
+            .timeout(api::code(transport, address), node.request_timeout),
+    ) {
+        Ok(ref code) if !code.0.is_empty() => checks.push(Check::pass(
+            name,
+            format!("{} has {} bytes of code", address, code.0.len()),
+        )),
+        Ok(_) => {
+            checks.push(Check::fail(
+                name,
+                format!("there is no contract at {}", address),
+                "check that the database belongs to this deployment and the node is synced",
+            ));
+            return;
+        }
+        Err(err) => {
+            checks.push(Check::fail(
+                name,
+                format!("`eth_getCode` failed: {}", err),
+                "check the node",
+            ));
+            return;
+        }
+    }
+
+    let mut authorities = Vec::new();
+    loop {
+        let payload = if chain == "home" {
+            app.home_bridge
+                .functions()
+                .authorities()
+                .input(authorities.len() as u64)
+        } else {
+            app.foreign_bridge
+                .functions()
+                .authorities()
+                .input(authorities.len() as u64)
+        };
+        // reading past the last authority reverts
+        let output = match event_loop.run(app.timer.timeout(
+            api::call(transport, address, payload.into()),
+            node.request_timeout,
+        )) {
+            Ok(ref output) if output.0.is_empty() => break,
+            Ok(output) => output,
+            Err(_) => break,
+        };
+        let authority = if chain == "home" {
+            app.home_bridge
+                .functions()
+                .authorities()
+                .output(output.0.as_slice())
+        } else {
+            app.foreign_bridge
+                .functions()
+                .authorities()
+                .output(output.0.as_slice())
+        };
+        match authority {
+            Ok(authority) => authorities.push(Address::from(authority.0)),
+            Err(_) => break,
+        }
+    }
+    let name = format!("{} authority", chain);
+    if authorities.contains(&account) {
+        checks.push(Check::pass(
+            name,
+            format!("{} is one of {} authorities", account, authorities.len()),
+        ));
+    } else {
+        checks.push(Check::fail(
+            name,
+            format!(
+                "{} is not among the {} authorities of the contract",
+                account,
+                authorities.len()
+            ),
+            "use the account this authority was deployed with or have the contracts redeployed",
+        ));
+    }
+    let mut configured = app.config.authorities.accounts.clone();
+    let mut deployed = authorities;
+    configured.sort();
+    deployed.sort();
+    if configured != deployed {
+        checks.push(Check::fail(
+            format!("{} authorities", chain),
+            "`authorities.accounts` differs from the authorities of the contract",
+            "update `authorities.accounts` to the authorities the contracts were deployed with",
+        ));
+    }
+}
+
+/// checks that this authority can sign withdraws
+fn check_signer<T: Transport>(event_loop: &mut Core, app: &App<T>, checks: &mut Vec<Check>) {
+    if let Some(signer) = remote_signer(&app.config) {
+        checks.push(match signer.check_available() {
+            Ok(()) => Check::pass("signer", "signer daemon accepts connections"),
+            Err(err) => Check::fail(
+                "signer",
+                format!("signer daemon is unavailable: {}", err),
+                "start `parity-bridge signer` and check `signer.socket` and `signer.token_file`",
+            ),
+        });
+        return;
+    }
+    let accounts = event_loop.run(app.timer.timeout(
+        api::accounts(&app.connections.foreign),
+        app.config.foreign.request_timeout,
+    ));
+    checks.push(match accounts {
+        Ok(ref accounts) if accounts.contains(&app.config.foreign.account) => Check::pass(
+            "signer",
+            format!("node of foreign holds the key of {}", app.config.foreign.account),
+        ),
+        Ok(_) => Check::fail(
+            "signer",
+            format!(
+                "node of foreign doesn't hold the key of {}",
+                app.config.foreign.account
+            ),
+            "import the key of `foreign.account` into the node and unlock it or configure a `signer`",
+        ),
+        Err(err) => Check::fail(
+            "signer",
+            format!("`eth_accounts` failed: {}", err),
+            "check the node of foreign",
+        ),
+    });
+}
+
+/// runs all checks of the bridge configured by `config` with the database at `database_path`
+pub fn diagnose<P: AsRef<Path>>(
+    event_loop: &mut Core,
+    config: Config,
+    database_path: P,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let mut app = match App::new(config, &database_path, &event_loop.handle()) {
+        Ok(app) => app,
+        Err(err) => {
+            checks.push(Check::fail(
+                "connection",
+                format!("cannot connect to the nodes: {}", err),
+                "check that both nodes run and `ipc`, `http` or `ws` of `home` and `foreign` point to them",
+            ));
+            return checks;
+        }
+    };
+    if let Err(err) = app.resolve_ens_names(event_loop) {
+        checks.push(Check::fail(
+            "ens",
+            err.to_string(),
+            "check the ENS names in the config and `ens_registry`",
+        ));
+        return checks;
+    }
+    let app = app.as_ref();
+
+    let home_chain = check_rpc(event_loop, &app, "home", &app.connections.home, &mut checks);
+    let foreign_chain = check_rpc(
+        event_loop,
+        &app,
+        "foreign",
+        &app.connections.foreign,
+        &mut checks,
+    );
+    if let (Some(home_chain), Some(foreign_chain)) = (home_chain, foreign_chain) {
+        checks.push(if home_chain == foreign_chain {
+            Check::fail(
+                "chain ids",
+                format!("home and foreign are both chain {}", home_chain),
+                "`home` and `foreign` must connect to nodes of different chains",
+            )
+        } else {
+            Check::pass(
+                "chain ids",
+                format!("home is chain {}, foreign is chain {}", home_chain, foreign_chain),
+            )
+        });
+    }
+
+    let (check, database) = check_database(database_path.as_ref());
+    checks.push(check);
+
+    if home_chain.is_some() {
+        if let Some(ref database) = database {
+            check_contract(
+                event_loop,
+                &app,
+                "home",
+                &app.connections.home,
+                database.home_contract_address,
+                // `HomeBridge` checks the signatures of `foreign.account`
+                app.config.foreign.account,
+                &mut checks,
+            );
+        }
+        if app.config.role.executes_withdraws() {
+            let account = app.config.home.account;
+            let tx = &app.config.txs.withdraw_relay;
+            match event_loop.run(app.timer.timeout(
+                api::balance(&app.connections.home, account),
+                app.config.home.request_timeout,
+            )) {
+                Ok(balance) => checks.push(check_balance(
+                    "home",
+                    account,
+                    balance,
+                    tx.gas,
+                    tx.gas_price,
+                )),
+                Err(err) => checks.push(Check::fail(
+                    "home balance",
+                    format!("`eth_getBalance` failed: {}", err),
+                    "check the node of home",
+                )),
+            }
+        }
+    }
+
+    if foreign_chain.is_some() {
+        if let Some(ref database) = database {
+            check_contract(
+                event_loop,
+                &app,
+                "foreign",
+                &app.connections.foreign,
+                database.foreign_contract_address,
+                app.config.foreign.account,
+                &mut checks,
+            );
+        }
+        let account = app.config.foreign.account;
+        // the most expensive transaction this authority sends to foreign
+        let tx = [&app.config.txs.deposit_relay, &app.config.txs.withdraw_confirm]
+            .iter()
+            .cloned()
+            .max_by_key(|tx| required_balance(tx.gas, tx.gas_price).unwrap_or_else(U256::max_value))
+            .expect("array is not empty; qed");
+        let (gas, gas_price) = (tx.gas, tx.gas_price);
+        match event_loop.run(app.timer.timeout(
+            api::balance(&app.connections.foreign, account),
+            app.config.foreign.request_timeout,
+        )) {
+            Ok(balance) => checks.push(check_balance("foreign", account, balance, gas, gas_price)),
+            Err(err) => checks.push(Check::fail(
+                "foreign balance",
+                format!("`eth_getBalance` failed: {}", err),
+                "check the node of foreign",
+            )),
+        }
+        check_signer(event_loop, &app, &mut checks);
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_balance, check_chain_id, render, required_balance, Check};
+
+    #[test]
+    fn test_required_balance() {
+        assert_eq!(Some(300_000.into()), required_balance(300_000, 1));
+        assert_eq!(Some(0.into()), required_balance(300_000, 0));
+    }
+
+    #[test]
+    fn test_check_balance() {
+        assert!(check_balance("home", 1.into(), 300_000.into(), 300_000, 1).passed());
+        assert!(!check_balance("home", 1.into(), 299_999.into(), 300_000, 1).passed());
+        assert!(check_balance("home", 1.into(), 0.into(), 300_000, 0).passed());
+    }
+
+    #[test]
+    fn test_check_chain_id() {
+        assert_eq!(None, check_chain_id("home", None, 42.into()));
+        assert!(check_chain_id("home", Some(42), 42.into()).unwrap().passed());
+        assert!(!check_chain_id("home", Some(1), 42.into()).unwrap().passed());
+    }
+
+    #[test]
+    fn test_render() {
+        let checks = vec![
+            Check::pass("home rpc", "latest block 10 in 3 ms"),
+            Check::fail("foreign rpc", "no answer", "start the node"),
+        ];
+        assert_eq!(
+            "[pass] home rpc: latest block 10 in 3 ms\n\
+             [FAIL] foreign rpc: no answer\n       hint: start the node\n\
+             2 checks, 1 failed",
+            render(&checks)
+        );
+    }
+}
@@ -0,0 +1,164 @@
+/// dry runs (`--dry-run`) that validate a new config or a contract upgrade without spending gas.
+/// the bridge follows both chains and processes their events as usual,
+/// but every transaction it would send is simulated via `eth_call` against the pending block
+/// and its gas estimated via `eth_estimateGas` instead.
+/// sender, recipient, value, gas, gas price and calldata of the transaction are logged
+/// together with whether it would revert.
+/// withdraws are signed as usual but the signatures are never submitted.
+/// the checkpoints only advance in memory (see `bridge::DryRunBackend`) and nothing is written
+/// to the relay history, sqlite, the event queue or postgres,
+/// so a run without `--dry-run` processes the same events for real.
+
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use rustc_hex::ToHex;
+use tokio_timer::{Timeout, Timer};
+use web3::Transport;
+use web3::types::{Bytes, TransactionRequest, U256};
+use api::{self, ApiCall};
+use app::App;
+use error::Error;
+use transaction::revert_reason;
+
+/// set once by `enable`
+static DRY_RUN: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// turns the run of `app` into a dry run. must be called after the database was loaded.
+/// stops recording relays in the relay history, sqlite, the event queue and postgres
+pub fn enable<T: Transport>(app: &mut App<T>) {
+    DRY_RUN.store(true, Ordering::SeqCst);
+    app.config.relay_history = None;
+    app.config.event_queue = None;
+    app.config.postgres = None;
+    app.sqlite = None;
+}
+
+/// returns true if transactions are simulated instead of sent
+pub fn is_enabled() -> bool {
+    DRY_RUN.load(Ordering::SeqCst)
+}
+
+/// describes what `request` would send
+fn describe(request: &TransactionRequest) -> String {
+    let optional = |value: Option<U256>| value.map_or("none".into(), |value| value.to_string());
+    format!(
+        "from {:?} to {:?} with value {} wei, gas {}, gas price {} wei and data 0x{}",
+        request.from,
+        request.to,
+        optional(request.value),
+        optional(request.gas),
+        optional(request.gas_price),
+        request
+            .data
+            .as_ref()
+            .map_or(String::new(), |data| data.0.to_hex())
+    )
+}
+
+/// state of a simulated transaction
+enum DryRunState<T: Transport> {
+    /// simulating the transaction via `eth_call` against the pending block
+    Simulate(Timeout<ApiCall<Bytes, T::Out>>),
+    /// estimating the gas of the transaction via `eth_estimateGas`
+    Estimate(Timeout<ApiCall<U256, T::Out>>),
+}
+
+/// creates a future that simulates `request` instead of sending it
+pub fn simulate<T: Transport>(
+    transport: T,
+    timer: &Timer,
+    request_timeout: Duration,
+    request: TransactionRequest,
+) -> DryRun<T> {
+    DryRun {
+        state: DryRunState::Simulate(timer.timeout(
+            api::call_transaction(&transport, &request),
+            request_timeout,
+        )),
+        transport,
+        timer: timer.clone(),
+        request_timeout,
+        request,
+    }
+}
+
+/// future that simulates a transaction and logs what would have been sent.
+/// resolves to false if the transaction would revert
+pub struct DryRun<T: Transport> {
+    transport: T,
+    timer: Timer,
+    request_timeout: Duration,
+    request: TransactionRequest,
+    state: DryRunState<T>,
+}
+
+impl<T: Transport> Future for DryRun<T> {
+    type Item = bool;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                DryRunState::Simulate(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(_)) => DryRunState::Estimate(self.timer.timeout(
+                        api::estimate_gas(&self.transport, &self.request),
+                        self.request_timeout,
+                    )),
+                    Err(err) => match revert_reason(&err) {
+                        Some(reason) => {
+                            warn!(
+                                "dry run: transaction {} would revert: {}",
+                                describe(&self.request),
+                                reason
+                            );
+                            return Ok(Async::Ready(false));
+                        }
+                        None => return Err(err),
+                    },
+                },
+                DryRunState::Estimate(ref mut future) => {
+                    match future.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(estimate)) => info!(
+                            "dry run: would send transaction {}. estimated gas: {}",
+                            describe(&self.request),
+                            estimate
+                        ),
+                        Err(err) => warn!(
+                            "dry run: would send transaction {}. estimating its gas failed: {}",
+                            describe(&self.request),
+                            err
+                        ),
+                    }
+                    return Ok(Async::Ready(true));
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::TransactionRequest;
+    use super::describe;
+
+    #[test]
+    fn test_describe() {
+        let request = TransactionRequest {
+            from: 1.into(),
+            to: Some(2.into()),
+            gas: Some(100_000.into()),
+            gas_price: Some(1_000.into()),
+            value: None,
+            data: Some(vec![0xab, 0xcd].into()),
+            nonce: None,
+            condition: None,
+        };
+        let description = describe(&request);
+        assert!(description.contains("with value none wei, gas 100000, gas price 1000 wei"));
+        assert!(description.ends_with("and data 0xabcd"));
+    }
+}
@@ -0,0 +1,166 @@
+/// resolution of ENS names (https://eips.ethereum.org/EIPS/eip-137).
+/// a name is resolved by asking the ENS registry for the resolver of the name
+/// and then asking the resolver for the address of the name.
+
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use tiny_keccak::keccak256;
+use tokio_timer::{Timeout, Timer};
+use web3::Transport;
+use web3::types::{Address, Bytes};
+use api::{self, ApiCall};
+use contracts::ens::{ENSResolver, ENS};
+use error::Error;
+
+/// address of the ENS registry on the ethereum mainnet
+pub const MAINNET_REGISTRY: &str = "00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+/// computes the `namehash` of `name` which identifies it in the ENS registry and resolvers
+pub fn namehash(name: &str) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    if name.is_empty() {
+        return node;
+    }
+    for label in name.rsplit('.') {
+        let mut buffer = [0u8; 64];
+        buffer[..32].copy_from_slice(&node);
+        buffer[32..].copy_from_slice(&keccak256(label.as_bytes()));
+        node = keccak256(&buffer);
+    }
+    node
+}
+
+/// returns true if `s` looks like an ENS name rather than an address
+pub fn is_name(s: &str) -> bool {
+    !s.starts_with("0x") && s.contains('.')
+}
+
+/// ENS returns the zero address for names and resolvers that don't exist
+fn non_zero(address: Address) -> Option<Address> {
+    if address == Address::default() {
+        None
+    } else {
+        Some(address)
+    }
+}
+
+/// state of the resolution of an ENS name
+enum ResolveNameState<T: Transport> {
+    /// fetching the address of the resolver of the name from the registry
+    FetchResolver(Timeout<ApiCall<Bytes, T::Out>>),
+    /// fetching the address of the name from its resolver
+    FetchAddress(Timeout<ApiCall<Bytes, T::Out>>),
+}
+
+/// creates a future that resolves `name` via the ENS registry at `registry`
+pub fn resolve_name<T: Transport>(
+    transport: T,
+    timer: Timer,
+    request_timeout: Duration,
+    registry: Address,
+    name: String,
+) -> ResolveName<T> {
+    let node = namehash(&name);
+    let ens = ENS::default();
+    let payload = ens.functions().resolver().input(node).into();
+    let state = ResolveNameState::FetchResolver(timer.timeout(
+        api::call(&transport, registry, payload),
+        request_timeout,
+    ));
+    ResolveName {
+        transport,
+        timer,
+        request_timeout,
+        name,
+        node,
+        ens,
+        resolver: ENSResolver::default(),
+        state,
+    }
+}
+
+/// future that resolves to the address an ENS name points to
+pub struct ResolveName<T: Transport> {
+    transport: T,
+    timer: Timer,
+    request_timeout: Duration,
+    name: String,
+    node: [u8; 32],
+    ens: ENS,
+    resolver: ENSResolver,
+    state: ResolveNameState<T>,
+}
+
+impl<T: Transport> Future for ResolveName<T> {
+    type Item = Address;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                ResolveNameState::FetchResolver(ref mut future) => {
+                    let output = try_ready!(future.poll());
+                    let resolver: Address = self.ens
+                        .functions()
+                        .resolver()
+                        .output(output.0.as_slice())?
+                        .0
+                        .into();
+                    let resolver = match non_zero(resolver) {
+                        Some(resolver) => resolver,
+                        None => bail!("ENS name `{}` has no resolver", self.name),
+                    };
+                    let payload = self.resolver.functions().addr().input(self.node).into();
+                    ResolveNameState::FetchAddress(self.timer.timeout(
+                        api::call(&self.transport, resolver, payload),
+                        self.request_timeout,
+                    ))
+                }
+                ResolveNameState::FetchAddress(ref mut future) => {
+                    let output = try_ready!(future.poll());
+                    let address: Address = self.resolver
+                        .functions()
+                        .addr()
+                        .output(output.0.as_slice())?
+                        .0
+                        .into();
+                    return match non_zero(address) {
+                        Some(address) => Ok(Async::Ready(address)),
+                        None => bail!("ENS name `{}` does not resolve to an address", self.name),
+                    };
+                }
+            };
+
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hex::ToHex;
+    use super::{is_name, namehash};
+
+    #[test]
+    fn test_namehash() {
+        assert_eq!(
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            namehash("").to_hex()
+        );
+        assert_eq!(
+            "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae",
+            namehash("eth").to_hex()
+        );
+        assert_eq!(
+            "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f",
+            namehash("foo.eth").to_hex()
+        );
+    }
+
+    #[test]
+    fn test_is_name() {
+        assert!(is_name("bridge.parity.eth"));
+        assert!(!is_name("0x0000000000000000000000000000000000000001"));
+        assert!(!is_name("0000000000000000000000000000000000000001"));
+    }
+}
@@ -2,9 +2,13 @@
 
 use std::io;
 use api::ApiCall;
+use signer::SignerCall;
 use tokio_timer::{TimeoutError, TimerError};
 use {ethabi, rustc_hex, toml, web3};
 
+/// exit code of the process after `ErrorKind::MaxLagExceeded`
+pub const MAX_LAG_EXIT_CODE: i32 = 3;
+
 error_chain! {
     types {
         Error, ErrorKind, ResultExt, Result;
@@ -30,6 +34,11 @@ error_chain! {
             description("File not found"),
             display("File {} not found", filename),
         }
+        // a component lagged behind for longer than `max_lag.duration`
+        MaxLagExceeded(component: &'static str, blocks: u64, seconds: u64) {
+            description("Component lags behind"),
+            display("{} lags {} blocks behind for {}s", component, blocks, seconds),
+        }
         // workaround for lack of web3:Error Display and Error implementations
         Web3(err: web3::Error) {
             description("web3 error"),
@@ -47,3 +56,9 @@ impl<T, F> From<TimeoutError<ApiCall<T, F>>> for Error {
         }
     }
 }
+
+impl From<TimeoutError<SignerCall>> for Error {
+    fn from(_: TimeoutError<SignerCall>) -> Self {
+        ErrorKind::Timeout("signer request").into()
+    }
+}
@@ -0,0 +1,487 @@
+/// publication of the lifecycle of the transfers this authority handles to a message queue.
+/// every event is a message of json published to a kafka topic or a nats subject
+/// so consumers don't have to poll the http api or the relay history.
+/// the sinks are compiled in via the cargo features `event-queue-kafka` and `event-queue-nats`.
+/// events are published after the fact by a thread of each bridge that keeps one connection
+/// to the queue, so the relays never wait for the queue. events the thread can't keep up with
+/// or that can't be published are logged and dropped.
+/// an application that embeds the bridge receives the same events over a channel
+/// (see `builder::BridgeBuilder::with_events`).
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use futures::sync::mpsc::UnboundedSender;
+use serde_json;
+use web3::types::{Address, H256, U256};
+use config::EventQueueConfig;
+use error::Error;
+use history::{Transfer, TransferDirection};
+use message_to_mainnet::MessageToMainnet;
+use status;
+use supervisor;
+
+/// seconds to wait for the queue to accept a connection or acknowledge the events
+#[cfg(any(feature = "event-queue-kafka", feature = "event-queue-nats"))]
+const PUBLISH_TIMEOUT: u64 = 5;
+/// number of events that may wait for the publisher before further events are dropped
+const QUEUE_CAPACITY: usize = 10_000;
+/// most events published at once
+const MAX_BATCH_SIZE: usize = 100;
+
+/// stage of the lifecycle of a transfer
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStage {
+    /// this authority submitted its signature of a withdraw to `ForeignBridge`
+    Signed,
+    /// this authority relayed the transfer to the destination chain
+    Relayed,
+    /// the simulation of the relay reverted (example: it was relayed already)
+    Skipped,
+}
+
+/// event published to the queue
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TransferEvent {
+    pub stage: TransferStage,
+    pub direction: TransferDirection,
+    /// transaction of the deposit on home or of the withdraw on foreign
+    pub transaction_hash: H256,
+    pub recipient: Address,
+    pub value: U256,
+    /// transaction of this authority that signed or relayed the transfer
+    pub authority_transaction_hash: Option<H256>,
+    /// seconds since the unix epoch when the event was published
+    pub timestamp: u64,
+}
+
+impl TransferEvent {
+    /// event of the relay of `transfer`
+    pub fn relayed(transfer: &Transfer) -> Self {
+        TransferEvent {
+            stage: if transfer.relay_transaction_hash.is_some() {
+                TransferStage::Relayed
+            } else {
+                TransferStage::Skipped
+            },
+            direction: transfer.direction,
+            transaction_hash: transfer.transaction_hash,
+            recipient: transfer.recipient,
+            value: transfer.value,
+            authority_transaction_hash: transfer.relay_transaction_hash,
+            timestamp: status::now(),
+        }
+    }
+
+    /// event of the signature of the withdraw `message` submitted by the transaction `hash`
+    pub fn signed(message: &MessageToMainnet, hash: H256) -> Self {
+        TransferEvent {
+            stage: TransferStage::Signed,
+            direction: TransferDirection::Withdraw,
+            transaction_hash: message.sidenet_transaction_hash,
+            recipient: message.recipient,
+            value: message.value,
+            authority_transaction_hash: Some(hash),
+            timestamp: status::now(),
+        }
+    }
+}
+
+/// publishers of the bridges of the process by name. `None` in a process that runs
+/// a single bridge (see `supervisor`)
+type Publishers = BTreeMap<Option<String>, SyncSender<TransferEvent>>;
+
+fn publishers() -> &'static Mutex<Publishers> {
+    static INIT: Once = ONCE_INIT;
+    static mut PUBLISHERS: *const Mutex<Publishers> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            PUBLISHERS = Box::into_raw(Box::new(Mutex::new(BTreeMap::new())));
+        });
+        &*PUBLISHERS
+    }
+}
+
+/// connects to the queue of `config` and starts the thread that publishes
+/// the events of the current bridge. fails if the queue can't be reached
+pub fn start(config: &Option<EventQueueConfig>) -> Result<(), Error> {
+    let config = match *config {
+        Some(ref config) => config.clone(),
+        None => return Ok(()),
+    };
+    let connection = connect(&config)?;
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+    thread::Builder::new()
+        .name(supervisor::prefixed("event-queue"))
+        .spawn(move || publish_events(config, connection, receiver))?;
+    publishers()
+        .lock()
+        .expect("publishers are never poisoned; qed")
+        .insert(supervisor::current(), sender);
+    info!("publishing transfer events to the event queue");
+    Ok(())
+}
+
+/// publishes `events` to the queue of the current bridge if it was started.
+/// events the publisher can't keep up with are dropped
+pub fn publish(events: &[TransferEvent]) {
+    let publishers = publishers()
+        .lock()
+        .expect("publishers are never poisoned; qed");
+    let sender = match publishers.get(&supervisor::current()) {
+        Some(sender) => sender,
+        None => return,
+    };
+    let mut dropped = 0;
+    for event in events {
+        match sender.try_send(event.clone()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => dropped += 1,
+        }
+    }
+    if dropped > 0 {
+        let message = format!(
+            "dropped {} transfer events the event queue couldn't keep up with",
+            dropped
+        );
+        error!("{}", message);
+        status::record_error(message);
+    }
+}
+
+/// publishes the events of `receiver` in batches until the bridge exits.
+/// a batch that fails is sent again once over a new connection.
+/// failures are logged and recorded in the status of the bridge
+fn publish_events(
+    config: EventQueueConfig,
+    connection: Connection,
+    receiver: Receiver<TransferEvent>,
+) {
+    let mut connection = Some(connection);
+    while let Ok(event) = receiver.recv() {
+        let mut events = vec![event];
+        events.extend(receiver.try_iter().take(MAX_BATCH_SIZE - 1));
+        for attempt in 0..2 {
+            if connection.is_none() {
+                connection = connect(&config)
+                    .map_err(|err| warn!("reconnecting to the event queue failed: {}", err))
+                    .ok();
+            }
+            let result = match connection {
+                Some(ref mut connection) => send(connection, &config, &events),
+                None => Err("not connected to the event queue".into()),
+            };
+            match result {
+                Ok(()) => break,
+                Err(err) => {
+                    connection = None;
+                    if attempt == 0 {
+                        warn!("publishing transfer events failed, reconnecting: {}", err);
+                        continue;
+                    }
+                    let message =
+                        format!("failed to publish {} transfer events: {}", events.len(), err);
+                    error!("{}", message);
+                    status::record_error(message);
+                }
+            }
+        }
+    }
+}
+
+/// sends `events` to the application that embeds the bridge if it asked for them.
+/// events are dropped once the application stopped receiving them
+pub fn notify(sender: &Option<UnboundedSender<TransferEvent>>, events: &[TransferEvent]) {
+    if let Some(ref sender) = *sender {
+        for event in events {
+            if sender.unbounded_send(event.clone()).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// persistent connection to the queue
+enum Connection {
+    Kafka(kafka_sink::Connection),
+    Nats(nats_sink::Connection),
+}
+
+fn connect(config: &EventQueueConfig) -> Result<Connection, Error> {
+    match *config {
+        EventQueueConfig::Kafka { ref brokers, .. } => {
+            kafka_sink::connect(brokers).map(Connection::Kafka)
+        }
+        EventQueueConfig::Nats { ref address, .. } => {
+            nats_sink::connect(address).map(Connection::Nats)
+        }
+    }
+}
+
+fn send(
+    connection: &mut Connection,
+    config: &EventQueueConfig,
+    events: &[TransferEvent],
+) -> Result<(), Error> {
+    let payloads = events
+        .iter()
+        .map(|event| serde_json::to_vec(event).expect("serialization can't fail; qed"))
+        .collect::<Vec<_>>();
+    let keys = events
+        .iter()
+        .map(|event| format!("{:?}", event.transaction_hash))
+        .collect::<Vec<_>>();
+    match (connection, config) {
+        (
+            &mut Connection::Kafka(ref mut connection),
+            &EventQueueConfig::Kafka { ref topic, .. },
+        ) => kafka_sink::send(connection, topic, &keys, &payloads),
+        (
+            &mut Connection::Nats(ref mut connection),
+            &EventQueueConfig::Nats { ref subject, .. },
+        ) => nats_sink::send(connection, subject, &payloads),
+        _ => unreachable!("the connection is to the queue of the config; qed"),
+    }
+}
+
+#[cfg(feature = "event-queue-kafka")]
+mod kafka_sink {
+    use std::time::Duration;
+    use kafka::producer::{Producer, Record, RequiredAcks};
+    use error::{Error, ResultExt};
+    use super::PUBLISH_TIMEOUT;
+
+    /// producer connected to the kafka brokers
+    pub struct Connection(Producer);
+
+    pub fn connect(brokers: &[String]) -> Result<Connection, Error> {
+        Producer::from_hosts(brokers.to_vec())
+            .with_ack_timeout(Duration::from_secs(PUBLISH_TIMEOUT))
+            .with_required_acks(RequiredAcks::All)
+            .create()
+            .map(Connection)
+            .chain_err(|| format!("Cannot connect to kafka brokers {:?}", brokers))
+    }
+
+    /// sends `payloads` to `topic` keyed by the transaction of their transfer
+    /// so that the events of a transfer end up in the same partition
+    pub fn send(
+        connection: &mut Connection,
+        topic: &str,
+        keys: &[String],
+        payloads: &[Vec<u8>],
+    ) -> Result<(), Error> {
+        let records = keys.iter()
+            .zip(payloads)
+            .map(|(key, payload)| {
+                Record::from_key_value(topic, key.as_bytes(), payload.as_slice())
+            })
+            .collect::<Vec<_>>();
+        let confirms = connection
+            .0
+            .send_all(&records)
+            .chain_err(|| format!("Cannot publish to kafka topic {}", topic))?;
+        for confirm in confirms {
+            for partition in confirm.partition_confirms {
+                if let Err(code) = partition.offset {
+                    bail!(
+                        "kafka rejected events for partition {} of topic {}: {:?}",
+                        partition.partition,
+                        topic,
+                        code
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "event-queue-kafka"))]
+mod kafka_sink {
+    use error::Error;
+
+    pub struct Connection;
+
+    pub fn connect(_: &[String]) -> Result<Connection, Error> {
+        unreachable!("kafka is only configured if feature `event-queue-kafka` is enabled; qed")
+    }
+
+    pub fn send(_: &mut Connection, _: &str, _: &[String], _: &[Vec<u8>]) -> Result<(), Error> {
+        unreachable!("kafka is only configured if feature `event-queue-kafka` is enabled; qed")
+    }
+}
+
+/// frame of the nats protocol that publishes `payload` to `subject`
+#[cfg_attr(not(feature = "event-queue-nats"), allow(dead_code))]
+fn nats_publish_frame(subject: &str, payload: &[u8]) -> Vec<u8> {
+    let mut frame = format!("PUB {} {}\r\n", subject, payload.len()).into_bytes();
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(b"\r\n");
+    frame
+}
+
+#[cfg(feature = "event-queue-nats")]
+mod nats_sink {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::time::Duration;
+    use error::{Error, ResultExt};
+    use super::{nats_publish_frame, PUBLISH_TIMEOUT};
+
+    /// connection to a nats server over its text protocol
+    pub struct Connection {
+        address: String,
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    pub fn connect(address: &str) -> Result<Connection, Error> {
+        let socket = address
+            .to_socket_addrs()
+            .chain_err(|| format!("Cannot resolve nats server {}", address))?
+            .next()
+            .ok_or_else(|| Error::from(format!("Cannot resolve nats server {}", address)))?;
+        let timeout = Duration::from_secs(PUBLISH_TIMEOUT);
+        let stream = TcpStream::connect_timeout(&socket, timeout)
+            .chain_err(|| format!("Cannot connect to nats server {}", address))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        // the server greets with `INFO {...}`
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("INFO") {
+            bail!("unexpected greeting of nats server {}: {}", address, line.trim());
+        }
+        writer.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")?;
+        Ok(Connection {
+            address: address.to_owned(),
+            reader,
+            writer,
+        })
+    }
+
+    /// sends `payloads` to `subject`.
+    /// a `PING` after the payloads makes sure the server processed them.
+    /// answers the `PING`s the server sent since the last events on the way
+    pub fn send(
+        connection: &mut Connection,
+        subject: &str,
+        payloads: &[Vec<u8>],
+    ) -> Result<(), Error> {
+        let mut frames = Vec::new();
+        for payload in payloads {
+            frames.extend(nats_publish_frame(subject, payload));
+        }
+        frames.extend_from_slice(b"PING\r\n");
+        connection.writer.write_all(&frames)?;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if connection.reader.read_line(&mut line)? == 0 {
+                bail!("nats server {} closed the connection", connection.address);
+            }
+            match line.trim() {
+                "PONG" => return Ok(()),
+                "PING" => connection.writer.write_all(b"PONG\r\n")?,
+                other if other.starts_with("-ERR") => bail!(
+                    "nats server {} rejected the events: {}",
+                    connection.address,
+                    other
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "event-queue-nats"))]
+mod nats_sink {
+    use error::Error;
+
+    pub struct Connection;
+
+    pub fn connect(_: &str) -> Result<Connection, Error> {
+        unreachable!("nats is only configured if feature `event-queue-nats` is enabled; qed")
+    }
+
+    pub fn send(_: &mut Connection, _: &str, _: &[Vec<u8>]) -> Result<(), Error> {
+        unreachable!("nats is only configured if feature `event-queue-nats` is enabled; qed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use futures::sync::mpsc;
+    use serde_json;
+    use history::{Transfer, TransferDirection};
+    use super::{nats_publish_frame, notify, TransferEvent, TransferStage};
+
+    #[test]
+    fn test_relayed() {
+        let mut transfer = Transfer {
+            direction: TransferDirection::Deposit,
+            block_number: 1,
+            block_timestamp: Some(2),
+            transaction_hash: 3.into(),
+            recipient: 4.into(),
+            value: 5.into(),
+            relay_transaction_hash: Some(6.into()),
+            relayed_at: Some(7),
+            fee: None,
+        };
+        let event = TransferEvent::relayed(&transfer);
+        assert_eq!(TransferStage::Relayed, event.stage);
+        assert_eq!(Some(6.into()), event.authority_transaction_hash);
+
+        transfer.relay_transaction_hash = None;
+        let event = TransferEvent::relayed(&transfer);
+        assert_eq!(TransferStage::Skipped, event.stage);
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.starts_with(r#"{"stage":"skipped","direction":"deposit","#));
+    }
+
+    #[test]
+    fn test_notify() {
+        let transfer = Transfer {
+            direction: TransferDirection::Withdraw,
+            block_number: 1,
+            block_timestamp: None,
+            transaction_hash: 3.into(),
+            recipient: 4.into(),
+            value: 5.into(),
+            relay_transaction_hash: Some(6.into()),
+            relayed_at: Some(7),
+            fee: None,
+        };
+        let event = TransferEvent::relayed(&transfer);
+        // nobody asked for the events
+        notify(&None, &[event.clone()]);
+
+        let (sender, receiver) = mpsc::unbounded();
+        let sender = Some(sender);
+        notify(&sender, &[event.clone(), event.clone()]);
+        let mut received = receiver.wait();
+        assert_eq!(Some(Ok(event.clone())), received.next());
+        assert_eq!(Some(Ok(event.clone())), received.next());
+
+        // the application stopped receiving
+        drop(received);
+        notify(&sender, &[event]);
+    }
+
+    #[test]
+    fn test_nats_publish_frame() {
+        assert_eq!(
+            b"PUB bridge.transfers 2\r\n{}\r\n".to_vec(),
+            nats_publish_frame("bridge.transfers", b"{}")
+        );
+    }
+}
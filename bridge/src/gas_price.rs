@@ -0,0 +1,225 @@
+/// gas prices of relay transactions that follow the market instead of the fixed
+/// `gas_price` of `[transactions]`. a chain with a `gas_price_oracle` prices its relays
+/// with the latest quote of an http oracle which a thread of its own fetches every
+/// `refresh_interval`. without an oracle url or while the quote is outdated because the
+/// oracle is unreachable the node is asked via `eth_gasPrice`. prices are capped at
+/// `max_gas_price`.
+/// the oracle is reached over http or https through `http_client::request`.
+
+use std::cmp;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::u64;
+use futures::{Async, Future, Poll};
+use serde_json::{self, Value};
+use tokio_timer::{Timeout, Timer};
+use web3::Transport;
+use web3::types::U256;
+use api::{self, ApiCall};
+use config::{GasPriceOracleConfig, Node};
+use error::{Error, ResultExt};
+use http_client;
+
+/// seconds to wait for the oracle to accept a connection and respond
+const FETCH_TIMEOUT: u64 = 5;
+/// quotes older than this many refresh intervals are outdated
+const OUTDATED_AFTER_INTERVALS: u32 = 3;
+
+/// returns the gas price in wei of the oracle quote `value` in units of `wei_per_unit`.
+/// quotes are numbers or strings of numbers
+fn parse_quote(value: &Value, wei_per_unit: u64) -> Option<u64> {
+    if let Some(units) = value.as_u64() {
+        return units.checked_mul(wei_per_unit);
+    }
+    let units = match *value {
+        Value::Number(ref number) => number.as_f64()?,
+        Value::String(ref number) => number.trim().parse::<f64>().ok()?,
+        _ => return None,
+    };
+    let wei = units * wei_per_unit as f64;
+    if wei.is_finite() && wei >= 0.0 && wei < u64::MAX as f64 {
+        Some(wei as u64)
+    } else {
+        None
+    }
+}
+
+/// fetches the current quote of the oracle in wei
+fn fetch_quote(config: &GasPriceOracleConfig, url: &str) -> Result<u64, Error> {
+    let timeout = Duration::from_secs(FETCH_TIMEOUT);
+    let body = http_client::request("GET", url, None, timeout)
+        .chain_err(|| format!("Cannot fetch gas price oracle {}", url))?;
+    let json: Value = serde_json::from_slice(&body)
+        .chain_err(|| format!("Cannot parse response of gas price oracle {}", url))?;
+    json.pointer(&config.pointer)
+        .and_then(|value| parse_quote(value, config.wei_per_unit))
+        .ok_or_else(|| {
+            format!(
+                "response of gas price oracle {} has no gas price at {}",
+                url, config.pointer
+            ).into()
+        })
+}
+
+#[derive(Default)]
+struct QuoteState {
+    /// gas price in wei and when it was fetched
+    latest: Option<(u64, Instant)>,
+}
+
+/// latest quote of the gas price oracle of a chain
+#[derive(Clone, Default)]
+pub struct GasPriceOracle(Arc<Mutex<QuoteState>>);
+
+impl GasPriceOracle {
+    fn state(&self) -> MutexGuard<QuoteState> {
+        self.0.lock().expect("no code panics while holding the lock; qed")
+    }
+
+    pub fn set_quote(&self, gas_price: u64) {
+        self.state().latest = Some((gas_price, Instant::now()));
+    }
+
+    /// returns the latest quote unless it is older than `max_age`
+    pub fn quote(&self, max_age: Duration) -> Option<u64> {
+        self.state()
+            .latest
+            .and_then(|(gas_price, fetched_at)| {
+                if fetched_at.elapsed() <= max_age {
+                    Some(gas_price)
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+/// gas price oracles of both chains
+#[derive(Clone, Default)]
+pub struct GasPriceOracles {
+    pub home: GasPriceOracle,
+    pub foreign: GasPriceOracle,
+}
+
+/// starts the thread that fetches the quotes of the oracle of `node` into `oracle`
+/// every `refresh_interval`. does nothing if `node` has no oracle url
+pub fn start(chain: &'static str, node: &Node, oracle: GasPriceOracle) {
+    let config = match node.gas_price_oracle {
+        Some(ref config) => config.clone(),
+        None => return,
+    };
+    let url = match config.url {
+        Some(ref url) => url.clone(),
+        None => return,
+    };
+    thread::spawn(move || loop {
+        match fetch_quote(&config, &url) {
+            Ok(gas_price) => oracle.set_quote(gas_price),
+            Err(err) => warn!(
+                "cannot fetch the gas price of {} from its oracle: {}. asking the node if this persists",
+                chain, err
+            ),
+        }
+        thread::sleep(config.refresh_interval);
+    });
+}
+
+enum GasPriceState<T: Transport> {
+    Ready(Option<U256>),
+    FetchNode(Timeout<ApiCall<U256, T::Out>>),
+}
+
+/// future of the gas price of the next relay transaction to a chain.
+/// resolves to `None` if the chain has no `gas_price_oracle`
+pub struct GasPrice<T: Transport> {
+    state: GasPriceState<T>,
+    max_gas_price: U256,
+}
+
+impl<T: Transport> GasPrice<T> {
+    /// resolves to `gas_price` right away
+    pub fn fixed(gas_price: Option<U256>) -> Self {
+        GasPrice {
+            state: GasPriceState::Ready(gas_price),
+            max_gas_price: U256::max_value(),
+        }
+    }
+}
+
+/// returns the gas price of the next relay transaction to the chain of `node`:
+/// the latest quote of `oracle` or `eth_gasPrice` if it has none or it's outdated
+pub fn gas_price<T: Transport>(
+    transport: &T,
+    timer: &Timer,
+    node: &Node,
+    oracle: &GasPriceOracle,
+) -> GasPrice<T> {
+    let config = match node.gas_price_oracle {
+        Some(ref config) => config,
+        None => return GasPrice::fixed(None),
+    };
+    let quote = config
+        .url
+        .as_ref()
+        .and_then(|_| oracle.quote(config.refresh_interval * OUTDATED_AFTER_INTERVALS));
+    let state = match quote {
+        Some(gas_price) => GasPriceState::Ready(Some(gas_price.into())),
+        None => {
+            GasPriceState::FetchNode(timer.timeout(api::gas_price(transport), node.request_timeout))
+        }
+    };
+    GasPrice {
+        state,
+        max_gas_price: config.max_gas_price.into(),
+    }
+}
+
+impl<T: Transport> Future for GasPrice<T> {
+    type Item = Option<U256>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let gas_price = match self.state {
+            GasPriceState::Ready(gas_price) => gas_price,
+            GasPriceState::FetchNode(ref mut future) => Some(try_ready!(future.poll())),
+        };
+        Ok(Async::Ready(gas_price.map(|gas_price| {
+            if gas_price > self.max_gas_price {
+                warn!(
+                    "capping gas price of {} wei at max_gas_price of {} wei",
+                    gas_price, self.max_gas_price
+                );
+            }
+            cmp::min(gas_price, self.max_gas_price)
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json::{self, Value};
+    use super::{parse_quote, GasPriceOracle};
+
+    #[test]
+    fn test_parse_quote() {
+        let gwei = 1_000_000_000;
+        assert_eq!(Some(25_000_000_000), parse_quote(&Value::from(25), gwei));
+        assert_eq!(Some(25_500_000_000), parse_quote(&Value::from(25.5), gwei));
+        assert_eq!(Some(25_500_000_000), parse_quote(&Value::from("25.5"), gwei));
+        assert_eq!(Some(3), parse_quote(&Value::from("3"), 1));
+        assert_eq!(None, parse_quote(&Value::from(-1.0), 1));
+        assert_eq!(None, parse_quote(&Value::from(u64::max_value()), 2));
+        let object = serde_json::from_str(r#"{"fast": 1}"#).unwrap();
+        assert_eq!(None, parse_quote(&object, 1));
+    }
+
+    #[test]
+    fn test_quote() {
+        let oracle = GasPriceOracle::default();
+        assert_eq!(None, oracle.quote(Duration::from_secs(60)));
+        oracle.set_quote(10);
+        assert_eq!(Some(10), oracle.quote(Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,329 @@
+/// latest blocks of both chains shared by all log streams.
+/// instead of every log stream polling `eth_blockNumber` on its own interval, one watcher
+/// per chain learns about new blocks and wakes the streams waiting for them.
+/// the watcher schedules its polls for when the next block is due according to the
+/// observed block time and backs off to `max_poll_interval` while no stream waits.
+/// with `subscribe_new_heads` the node announces new blocks via `eth_subscribe("newHeads")`
+/// over its ipc socket (a named pipe on windows) or websocket on a thread of its own
+/// and the polls only serve as fallback.
+/// the subscription is opened again on a new connection after the connection dropped.
+
+use std::cmp;
+#[cfg(not(unix))]
+use std::io::BufReader;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::u32;
+use futures::Async;
+use futures::task::{self, Task};
+use serde_json::{self, Value};
+use config::{Node, NodeEndpoint, TlsConfig};
+use error::{Error, ResultExt};
+#[cfg(not(unix))]
+use named_pipe;
+use tls::Connector;
+use websocket;
+
+/// seconds before a failed subscription is opened again
+const RESUBSCRIBE_DELAY: u64 = 5;
+
+const SUBSCRIBE_REQUEST: &str =
+    r#"{"jsonrpc":"2.0","id":1,"method":"eth_subscribe","params":["newHeads"]}"#;
+
+#[derive(Default)]
+struct HeadState {
+    latest: Option<u64>,
+    announced_at: Option<Instant>,
+    /// moving average of the time between two blocks
+    cadence: Option<Duration>,
+    /// whether a watcher announces the blocks of this chain
+    watched: bool,
+    /// whether a subscription currently announces the blocks of this chain
+    subscribed: bool,
+    /// whether a stream started waiting since the watcher scheduled its next poll
+    reschedule: bool,
+    /// streams waiting for a block after `latest`
+    waiting: Vec<Task>,
+    /// the watcher, woken when a stream starts waiting
+    watcher: Option<Task>,
+}
+
+/// latest block of a chain
+#[derive(Clone, Default)]
+pub struct ChainHead(Arc<Mutex<HeadState>>);
+
+impl ChainHead {
+    fn state(&self) -> MutexGuard<HeadState> {
+        self.0.lock().expect("no code panics while holding the lock; qed")
+    }
+
+    /// marks the chain as watched. streams following it stop polling on their own
+    pub fn watch(&self) {
+        self.state().watched = true;
+    }
+
+    pub fn latest(&self) -> Option<u64> {
+        self.state().latest
+    }
+
+    /// records whether a subscription announces the blocks of this chain
+    pub fn set_subscribed(&self, subscribed: bool) {
+        self.state().subscribed = subscribed;
+    }
+
+    /// announces `block` as latest block and wakes the waiting streams.
+    /// returns `false` if `block` isn't newer than the latest block
+    pub fn announce(&self, block: u64) -> bool {
+        let mut state = self.state();
+        let now = Instant::now();
+        match (state.latest, state.announced_at) {
+            (Some(latest), _) if block <= latest => return false,
+            (Some(latest), Some(announced_at)) => {
+                let blocks = cmp::min(block - latest, u32::MAX as u64) as u32;
+                let sample = now.duration_since(announced_at) / blocks;
+                state.cadence = Some(match state.cadence {
+                    Some(cadence) => (cadence * 3 + sample) / 4,
+                    None => sample,
+                });
+            }
+            _ => {}
+        }
+        state.latest = Some(block);
+        state.announced_at = Some(now);
+        for task in state.waiting.drain(..) {
+            task.notify();
+        }
+        true
+    }
+
+    /// returns the delay before the watcher polls the latest block again.
+    /// registers the current task as watcher to be woken once a stream starts waiting
+    pub fn next_poll(&self, min: Duration, max: Duration) -> Duration {
+        let mut state = self.state();
+        state.watcher = Some(task::current());
+        state.reschedule = false;
+        next_poll_delay(
+            state.cadence,
+            state.announced_at.map(|at| at.elapsed()),
+            !state.waiting.is_empty(),
+            state.subscribed,
+            min,
+            max,
+        )
+    }
+
+    /// returns whether the delay returned by `next_poll` is outdated
+    pub fn needs_reschedule(&self) -> bool {
+        self.state().reschedule
+    }
+
+    /// returns a subscription to the blocks announced after the latest one
+    pub fn follow(&self) -> HeadSubscription {
+        HeadSubscription {
+            seen: self.latest().unwrap_or(0),
+            head: self.clone(),
+        }
+    }
+}
+
+/// latest blocks of both chains
+#[derive(Clone, Default)]
+pub struct Heads {
+    pub home: ChainHead,
+    pub foreign: ChainHead,
+}
+
+/// blocks of a chain announced after the last one seen
+pub struct HeadSubscription {
+    head: ChainHead,
+    seen: u64,
+}
+
+impl HeadSubscription {
+    /// returns `Ready(Some(block))` once a block newer than the last one seen was announced.
+    /// `NotReady` wakes the current task with the next announcement.
+    /// returns `Ready(None)` if nothing watches the chain so the caller has to poll itself
+    pub fn poll(&mut self) -> Async<Option<u64>> {
+        let mut state = self.head.state();
+        if !state.watched {
+            return Async::Ready(None);
+        }
+        match state.latest {
+            Some(latest) if latest > self.seen => {
+                self.seen = latest;
+                return Async::Ready(Some(latest));
+            }
+            _ => {}
+        }
+        if state.waiting.is_empty() {
+            state.reschedule = true;
+            if let Some(ref watcher) = state.watcher {
+                watcher.notify();
+            }
+        }
+        if !state.waiting.iter().any(|task| task.will_notify_current()) {
+            state.waiting.push(task::current());
+        }
+        Async::NotReady
+    }
+}
+
+/// returns the delay before the next poll of the latest block.
+/// the poll is due with the next block expected `cadence` after the latest one.
+/// overdue blocks are polled for every quarter of `cadence`.
+/// polls back off to `max` while no stream is `waiting` or a subscription announces blocks
+pub fn next_poll_delay(
+    cadence: Option<Duration>,
+    since_latest: Option<Duration>,
+    waiting: bool,
+    subscribed: bool,
+    min: Duration,
+    max: Duration,
+) -> Duration {
+    if !waiting || subscribed {
+        return max;
+    }
+    let delay = match (cadence, since_latest) {
+        (Some(cadence), Some(since)) if since < cadence => cadence - since,
+        (Some(cadence), Some(_)) => cadence / 4,
+        _ => min,
+    };
+    cmp::min(cmp::max(delay, min), max)
+}
+
+/// returns the number of the block announced by a `newHeads` notification
+fn new_head_number(notification: &Value) -> Option<u64> {
+    if notification.get("method")?.as_str()? != "eth_subscription" {
+        return None;
+    }
+    let number = notification.get("params")?.get("result")?.get("number")?.as_str()?;
+    u64::from_str_radix(number.trim_left_matches("0x"), 16).ok()
+}
+
+/// announces the new blocks of `node` to `head` from a thread of its own.
+/// opens the subscription again after failures
+pub fn subscribe(chain: &'static str, node: &Node, head: ChainHead) {
+    let endpoint = node.endpoint.clone();
+    let tls = node.tls.clone();
+    thread::spawn(move || loop {
+        let result = match endpoint {
+            NodeEndpoint::Ipc(ref ipc) => follow_new_heads(ipc, &head),
+            NodeEndpoint::Ws(ref url) => follow_new_heads_ws(url, endpoint.is_tls(), &tls, &head),
+            NodeEndpoint::Http(_) => Err("subscriptions require `ipc` or `ws`".into()),
+        };
+        if let Err(err) = result {
+            warn!(
+                "subscription to new blocks of {} failed: {}. polling until it is restored",
+                chain, err
+            );
+        }
+        head.set_subscribed(false);
+        thread::sleep(Duration::from_secs(RESUBSCRIBE_DELAY));
+    });
+}
+
+/// handles the response to the subscription request or a notification of the subscription
+fn handle_message(message: &Value, head: &ChainHead) -> Result<(), Error> {
+    if let Some(error) = message.get("error") {
+        bail!("node refused the subscription: {}", error);
+    }
+    if message.get("id").is_some() {
+        head.set_subscribed(true);
+    } else if let Some(block) = new_head_number(message) {
+        head.announce(block);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn follow_new_heads(ipc: &Path, head: &ChainHead) -> Result<(), Error> {
+    let mut stream = UnixStream::connect(ipc)
+        .chain_err(|| format!("Cannot connect to node at {:?}", ipc))?;
+    writeln!(stream, "{}", SUBSCRIBE_REQUEST)?;
+    for message in serde_json::Deserializer::from_reader(stream).into_iter::<Value>() {
+        let message = message.chain_err(|| "Cannot parse message of node")?;
+        handle_message(&message, head)?;
+    }
+    bail!("node closed the connection")
+}
+
+#[cfg(not(unix))]
+fn follow_new_heads(ipc: &Path, head: &ChainHead) -> Result<(), Error> {
+    let pipe = named_pipe::open(ipc, Duration::from_secs(RESUBSCRIBE_DELAY))?;
+    let mut reader = BufReader::new(pipe);
+    writeln!(reader.get_mut(), "{}", SUBSCRIBE_REQUEST)?;
+    loop {
+        let message = named_pipe::read_message(&mut reader)?;
+        handle_message(&message, head)?;
+    }
+}
+
+fn follow_new_heads_ws(
+    url: &str,
+    is_tls: bool,
+    tls: &TlsConfig,
+    head: &ChainHead,
+) -> Result<(), Error> {
+    let connector = if is_tls {
+        Some(Connector::new(tls)?)
+    } else {
+        None
+    };
+    let connection = websocket::connect(url, connector.as_ref())?;
+    connection.write_text(SUBSCRIBE_REQUEST)?;
+    loop {
+        let message = connection.read_text()?;
+        let message: Value =
+            serde_json::from_str(&message).chain_err(|| "Cannot parse message of node")?;
+        handle_message(&message, head)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use serde_json;
+    use super::{new_head_number, next_poll_delay, ChainHead};
+
+    #[test]
+    fn test_next_poll_delay() {
+        let secs = Duration::from_secs;
+        let cadence = Some(secs(16));
+        let (min, max) = (secs(1), secs(30));
+        assert_eq!(secs(30), next_poll_delay(cadence, Some(secs(2)), false, false, min, max));
+        assert_eq!(secs(30), next_poll_delay(cadence, Some(secs(2)), true, true, min, max));
+        assert_eq!(secs(14), next_poll_delay(cadence, Some(secs(2)), true, false, min, max));
+        assert_eq!(secs(4), next_poll_delay(cadence, Some(secs(20)), true, false, min, max));
+        assert_eq!(secs(1), next_poll_delay(None, None, true, false, min, max));
+        assert_eq!(secs(4), next_poll_delay(cadence, Some(secs(16)), true, false, min, max));
+        assert_eq!(
+            secs(30),
+            next_poll_delay(Some(secs(60)), Some(secs(2)), true, false, min, max)
+        );
+    }
+
+    #[test]
+    fn test_new_head_number() {
+        let notification = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","method":"eth_subscription","params":{"subscription":"0x1","result":{"number":"0x1b4","hash":"0x00"}}}"#,
+        ).unwrap();
+        assert_eq!(Some(436), new_head_number(&notification));
+        let response = serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#).unwrap();
+        assert_eq!(None, new_head_number(&response));
+    }
+
+    #[test]
+    fn test_announce() {
+        let head = ChainHead::default();
+        assert!(head.announce(10));
+        assert!(!head.announce(10));
+        assert!(!head.announce(9));
+        assert!(head.announce(12));
+        assert_eq!(Some(12), head.latest());
+    }
+}
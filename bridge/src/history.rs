@@ -0,0 +1,363 @@
+/// history of the transfers this authority relayed.
+/// every relayed batch of deposits and withdraws is appended to the history file
+/// as lines of json. the position of a transfer in the file never changes
+/// which gives a stable order and stable cursors for pagination.
+/// transfers carry the timestamp of the block of their event
+/// so the history is based on chain time rather than the clock of the relaying host.
+
+use std::collections::{HashMap, VecDeque};
+use std::{fs, mem};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use futures::future::{join_all, JoinAll};
+use serde_json;
+use tokio_timer::{Timeout, Timer};
+use web3::Transport;
+use web3::types::{Address, BlockNumber, H256, U256};
+use api::{self, ApiCall, BlockHeader};
+use audit;
+use error::{Error, ResultExt};
+
+/// direction of a transfer
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    /// ether deposited on home and relayed to foreign
+    Deposit,
+    /// tokens withdrawn on foreign and relayed to home
+    Withdraw,
+}
+
+/// a transfer relayed by this authority
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub direction: TransferDirection,
+    /// block of the event the relay was triggered by:
+    /// `Deposit` on home or `CollectedSignatures` on foreign
+    pub block_number: u64,
+    /// seconds since the unix epoch of the block of the event.
+    /// `None` for transfers recorded before timestamps were tracked
+    #[serde(default)]
+    pub block_timestamp: Option<u64>,
+    /// transaction of the deposit on home or of the withdraw on foreign
+    pub transaction_hash: H256,
+    pub recipient: Address,
+    pub value: U256,
+    /// transaction of this authority that relayed the transfer.
+    /// `None` if the simulation of the relay reverted (example: it was relayed already)
+    pub relay_transaction_hash: Option<H256>,
+    /// seconds since the unix epoch on the destination chain when the relay completed.
+    /// `None` for transfers recorded before it was tracked
+    #[serde(default)]
+    pub relayed_at: Option<u64>,
+    /// relay cost the recipient paid to the relaying authority out of `value`.
+    /// zero for deposits. `None` for transfers recorded before it was tracked
+    #[serde(default)]
+    pub fee: Option<U256>,
+}
+
+/// appends `transfers` to the history file at `path`
+pub fn record<P: AsRef<Path>>(path: P, transfers: &[Transfer]) -> Result<(), Error> {
+    for transfer in transfers {
+        audit::append(path.as_ref(), transfer)?;
+    }
+    Ok(())
+}
+
+/// creates a future that resolves to `transfers` with the timestamps of the blocks
+/// of their events on the chain of `transport`. every block is fetched once
+pub fn with_block_timestamps<T: Transport>(
+    transport: &T,
+    timer: &Timer,
+    request_timeout: Duration,
+    transfers: Vec<Transfer>,
+) -> BlockTimestamps<T> {
+    let mut blocks = transfers
+        .iter()
+        .map(|transfer| transfer.block_number)
+        .collect::<Vec<_>>();
+    blocks.sort();
+    blocks.dedup();
+    let headers = blocks
+        .iter()
+        .map(|&block| {
+            timer.timeout(
+                api::block_header(transport, BlockNumber::Number(block)),
+                request_timeout,
+            )
+        })
+        .collect();
+    BlockTimestamps {
+        future: join_all(headers),
+        blocks,
+        transfers,
+    }
+}
+
+/// future that sets the block timestamps of transfers
+pub struct BlockTimestamps<T: Transport> {
+    future: JoinAll<Vec<Timeout<ApiCall<BlockHeader, T::Out>>>>,
+    blocks: Vec<u64>,
+    transfers: Vec<Transfer>,
+}
+
+impl<T: Transport> Future for BlockTimestamps<T> {
+    type Item = Vec<Transfer>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let headers = try_ready!(self.future.poll());
+        let timestamps = self.blocks
+            .iter()
+            .cloned()
+            .zip(headers.into_iter().map(|header| header.timestamp.low_u64()))
+            .collect::<HashMap<_, _>>();
+        let mut transfers = mem::replace(&mut self.transfers, Vec::new());
+        for transfer in &mut transfers {
+            transfer.block_timestamp = timestamps.get(&transfer.block_number).cloned();
+        }
+        Ok(Async::Ready(transfers))
+    }
+}
+
+/// page of transfers
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Page {
+    pub transfers: Vec<Transfer>,
+    /// cursor of the next page. `None` if this is the last page
+    pub next_page: Option<u64>,
+}
+
+/// filter of a query
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Query {
+    /// only transfers in this direction. all if `None`
+    pub direction: Option<TransferDirection>,
+    /// only transfers whose event is in this block or later
+    pub from_block: u64,
+    /// cursor of the page. `0` for the first page
+    pub page: u64,
+    /// maximum number of transfers per page
+    pub limit: usize,
+}
+
+/// opens the history file at `path`. `None` if there is no history yet
+fn open(path: &Path) -> Result<Option<fs::File>, Error> {
+    match fs::File::open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).chain_err(|| "Cannot open history"),
+    }
+}
+
+/// parses the transfer in `line` at `position` of the history file
+fn parse_line(line: io::Result<String>, position: usize) -> Result<Transfer, Error> {
+    serde_json::from_str(&line?)
+        .chain_err(|| format!("Cannot parse line {} of history", position + 1))
+}
+
+/// returns the `count` transfers in the history file at `path` that were relayed last.
+/// the most recent transfer comes first
+pub fn recent<P: AsRef<Path>>(path: P, count: usize) -> Result<Vec<Transfer>, Error> {
+    let file = match open(path.as_ref())? {
+        Some(file) => file,
+        None => return Ok(vec![]),
+    };
+    let mut transfers = VecDeque::with_capacity(count + 1);
+    for (position, line) in BufReader::new(file).lines().enumerate() {
+        transfers.push_front(parse_line(line, position)?);
+        transfers.truncate(count);
+    }
+    Ok(transfers.into_iter().collect())
+}
+
+/// returns the transfers in the history file at `path` whose block is not older than `timestamp`
+/// in the order they were relayed. transfers without block timestamp are skipped
+pub fn since<P: AsRef<Path>>(path: P, timestamp: u64) -> Result<Vec<Transfer>, Error> {
+    let file = match open(path.as_ref())? {
+        Some(file) => file,
+        None => return Ok(vec![]),
+    };
+    let mut transfers = Vec::new();
+    for (position, line) in BufReader::new(file).lines().enumerate() {
+        let transfer = parse_line(line, position)?;
+        if transfer.block_timestamp.map_or(false, |block| block >= timestamp) {
+            transfers.push(transfer);
+        }
+    }
+    Ok(transfers)
+}
+
+/// returns the page of transfers in the history file at `path` that match `query`.
+/// returns an empty page if there is no history yet
+pub fn query<P: AsRef<Path>>(path: P, query: &Query) -> Result<Page, Error> {
+    let file = match open(path.as_ref())? {
+        Some(file) => file,
+        None => {
+            return Ok(Page {
+                transfers: vec![],
+                next_page: None,
+            })
+        }
+    };
+
+    let mut transfers = Vec::new();
+    for (position, line) in BufReader::new(file)
+        .lines()
+        .enumerate()
+        .skip(query.page as usize)
+    {
+        let transfer = parse_line(line, position)?;
+        if query.direction.map_or(false, |d| d != transfer.direction)
+            || transfer.block_number < query.from_block
+        {
+            continue;
+        }
+        if transfers.len() == query.limit {
+            return Ok(Page {
+                transfers,
+                next_page: Some(position as u64),
+            });
+        }
+        transfers.push(transfer);
+    }
+
+    Ok(Page {
+        transfers,
+        next_page: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+    use self::tempdir::TempDir;
+    use super::{parse_line, query, recent, record, since, Query, Transfer, TransferDirection};
+
+    fn transfer(direction: TransferDirection, block_number: u64) -> Transfer {
+        Transfer {
+            direction,
+            block_number,
+            block_timestamp: Some(1_500_000_000 + block_number),
+            transaction_hash: block_number.into(),
+            recipient: 1.into(),
+            value: 100.into(),
+            relay_transaction_hash: Some(2.into()),
+            relayed_at: Some(1_500_000_060 + block_number),
+            fee: Some(0.into()),
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let tempdir = TempDir::new("test_query").unwrap();
+        let path = tempdir.path().join("history");
+        let mut all = Query {
+            limit: 2,
+            ..Query::default()
+        };
+        assert!(query(&path, &all).unwrap().transfers.is_empty());
+
+        record(
+            &path,
+            &[
+                transfer(TransferDirection::Deposit, 10),
+                transfer(TransferDirection::Withdraw, 11),
+                transfer(TransferDirection::Deposit, 12),
+                transfer(TransferDirection::Deposit, 13),
+            ],
+        ).unwrap();
+
+        let first = query(&path, &all).unwrap();
+        assert_eq!(
+            vec![
+                transfer(TransferDirection::Deposit, 10),
+                transfer(TransferDirection::Withdraw, 11),
+            ],
+            first.transfers
+        );
+        assert_eq!(Some(2), first.next_page);
+
+        all.page = 2;
+        let second = query(&path, &all).unwrap();
+        assert_eq!(
+            vec![
+                transfer(TransferDirection::Deposit, 12),
+                transfer(TransferDirection::Deposit, 13),
+            ],
+            second.transfers
+        );
+        assert_eq!(None, second.next_page);
+
+        let deposits = Query {
+            direction: Some(TransferDirection::Deposit),
+            from_block: 11,
+            page: 0,
+            limit: 1,
+        };
+        let page = query(&path, &deposits).unwrap();
+        assert_eq!(vec![transfer(TransferDirection::Deposit, 12)], page.transfers);
+        assert_eq!(Some(3), page.next_page);
+    }
+    #[test]
+    fn test_recent() {
+        let tempdir = TempDir::new("test_recent").unwrap();
+        let path = tempdir.path().join("history");
+        assert!(recent(&path, 2).unwrap().is_empty());
+
+        record(
+            &path,
+            &[
+                transfer(TransferDirection::Deposit, 10),
+                transfer(TransferDirection::Withdraw, 11),
+                transfer(TransferDirection::Deposit, 12),
+            ],
+        ).unwrap();
+
+        assert_eq!(
+            vec![
+                transfer(TransferDirection::Deposit, 12),
+                transfer(TransferDirection::Withdraw, 11),
+            ],
+            recent(&path, 2).unwrap()
+        );
+        assert_eq!(3, recent(&path, 5).unwrap().len());
+    }
+
+    #[test]
+    fn test_since() {
+        let tempdir = TempDir::new("test_since").unwrap();
+        let path = tempdir.path().join("history");
+        let mut untimed = transfer(TransferDirection::Withdraw, 13);
+        untimed.block_timestamp = None;
+        record(
+            &path,
+            &[
+                transfer(TransferDirection::Deposit, 10),
+                transfer(TransferDirection::Deposit, 12),
+                untimed,
+                transfer(TransferDirection::Withdraw, 11),
+            ],
+        ).unwrap();
+
+        assert_eq!(
+            vec![
+                transfer(TransferDirection::Deposit, 12),
+                transfer(TransferDirection::Withdraw, 11),
+            ],
+            since(&path, 1_500_000_011).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_line_without_timestamp() {
+        let line = r#"{"direction":"deposit","block_number":10,"transaction_hash":"0x000000000000000000000000000000000000000000000000000000000000000a","recipient":"0x0000000000000000000000000000000000000001","value":"0x64","relay_transaction_hash":"0x0000000000000000000000000000000000000000000000000000000000000002"}"#;
+        let mut expected = transfer(TransferDirection::Deposit, 10);
+        expected.block_timestamp = None;
+        expected.relayed_at = None;
+        expected.fee = None;
+        assert_eq!(expected, parse_line(Ok(line.into()), 0).unwrap());
+    }
+}
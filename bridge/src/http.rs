@@ -0,0 +1,655 @@
+/// minimal http api and dashboard for explorers and operators.
+/// requests are served one at a time on a thread of their own
+/// so slow clients can't stall the bridge. only `GET` and `POST` are supported.
+/// query parameters are not percent-decoded since none of them needs it.
+///
+/// endpoints:
+/// - `GET /` - html dashboard that renders `GET /status`
+/// - `GET /status` - chain lag, balances, pending withdraws, work queues and recent transfers.
+///   see `status::Status`
+/// - `GET /checkpoints` - blocks up to which each stream has processed events
+/// - `GET /parked` - relays of the bridge that configures `http_api` that are retried apart
+///   from their relay streams, with the reason and the attempts so far. see `parked`
+/// - `GET /dead_letters` - relays of the bridge that configures `http_api` given up after
+///   their last attempt reverted, with the revert reason. see `parked::DeadLetter`
+/// - `POST /replay?chain=&origin=` - parks the dead letter of the transfer `origin` to `chain`
+///   (`home` or `foreign`) again. it is retried with all of its attempts.
+///   requires `Authorization: Bearer <token>` like pause and resume requests
+/// - `GET /pauses?bridge=` - whether each relay direction is paused. see `control`
+/// - `POST /pause/deposits?bridge=`, `POST /pause/withdraws?bridge=`,
+///   `POST /resume/deposits?bridge=`, `POST /resume/withdraws?bridge=` - pauses or resumes
+///   a relay direction.
+///   requires `Authorization: Bearer <token>` with the token of `http_api.admin_token_file`
+///
+/// `bridge` names one of the bridges of the process (see `supervisor`)
+/// and defaults to the bridge that configures `http_api`.
+/// - `GET /transfers?direction=&from_block=&page=&limit=` - page of the relay history.
+///   see `history::query`
+/// - `GET /stats?days=` - daily aggregates of the relay history. see `stats::Statistics`
+/// - `GET /metrics` - metrics in the prometheus text format. see `prometheus`
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use web3::types::H256;
+use serde::Serialize;
+use serde_json;
+use clock;
+use config::Config;
+use control::{self, Direction, Pause};
+use error::{Error, ResultExt};
+use history::{self, Transfer, TransferDirection};
+use metrics;
+use parked::Parked;
+use prometheus;
+use signer::{constant_time_eq, read_token, Chain};
+use stats;
+use status::{self, Status};
+use supervisor;
+
+/// page served at `GET /`
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// number of transfers per page if `limit` isn't given
+const DEFAULT_PAGE_SIZE: usize = 100;
+/// upper bound on `limit`
+const MAX_PAGE_SIZE: usize = 1000;
+/// number of transfers in `GET /status`
+const RECENT_TRANSFERS: usize = 20;
+
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// bearer token of the `Authorization` header
+    pub token: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json<S: Serialize>(value: &S) -> Self {
+        Response {
+            status: 200,
+            content_type: "application/json",
+            body: serde_json::to_string(value).expect("serialization can't fail; qed"),
+        }
+    }
+
+    pub fn html(body: &str) -> Self {
+        Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: body.to_owned(),
+        }
+    }
+
+    pub fn text(content_type: &'static str, body: String) -> Self {
+        Response {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            error: &'a str,
+        }
+
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(&ErrorBody { error: message })
+                .expect("serialization can't fail; qed"),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// parses the request line of an http request (example: `GET /transfers?page=2 HTTP/1.1`).
+/// returns the method and the request or `None` if the line is malformed
+fn parse_request_line(line: &str) -> Option<(&str, Request)> {
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+
+    let mut target = target.splitn(2, '?');
+    let path = target.next()?.to_owned();
+    let query = target
+        .next()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            let key = pair.next().unwrap_or("").to_owned();
+            let value = pair.next().unwrap_or("").to_owned();
+            (key, value)
+        })
+        .collect();
+
+    Some((
+        method,
+        Request {
+            path,
+            query,
+            token: None,
+        },
+    ))
+}
+
+/// returns the token of an `Authorization: Bearer <token>` header line
+fn parse_bearer_token(line: &str) -> Option<String> {
+    let mut header = line.splitn(2, ':');
+    if !header.next()?.trim().eq_ignore_ascii_case("authorization") {
+        return None;
+    }
+    let mut value = header.next()?.split_whitespace();
+    if !value.next()?.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    value.next().map(str::to_owned)
+}
+
+/// returns the direction and whether to pause it for `POST /pause/..` and `POST /resume/..`
+fn parse_pause_path(path: &str) -> Option<(Direction, bool)> {
+    let mut parts = path.trim_left_matches('/').splitn(2, '/');
+    let paused = match parts.next()? {
+        "pause" => true,
+        "resume" => false,
+        _ => return None,
+    };
+    let direction = match parts.next()? {
+        "deposits" => Direction::Deposits,
+        "withdraws" => Direction::Withdraws,
+        _ => return None,
+    };
+    Some((direction, paused))
+}
+
+/// parses the query of `POST /replay` and returns the chain and the origin of the dead letter
+fn parse_replay_query(request: &Request) -> Result<(Chain, H256), String> {
+    let chain = match request.query.get("chain").map(|c| c.as_str()) {
+        Some("home") => Chain::Home,
+        Some("foreign") => Chain::Foreign,
+        _ => return Err("chain must be `home` or `foreign`".into()),
+    };
+    let origin = request.query.get("origin").map_or("", |o| o.as_str());
+    let origin = origin
+        .trim_left_matches("0x")
+        .parse()
+        .map_err(|_| format!("origin must be a transaction hash but is `{}`", origin))?;
+    Ok((chain, origin))
+}
+
+/// parses the query of `GET /transfers`
+fn parse_transfers_query(request: &Request) -> Result<history::Query, String> {
+    let direction = match request.query.get("direction").map(|d| d.as_str()) {
+        None | Some("") => None,
+        Some("deposit") => Some(TransferDirection::Deposit),
+        Some("withdraw") => Some(TransferDirection::Withdraw),
+        Some(other) => {
+            return Err(format!(
+                "direction must be `deposit` or `withdraw` but is `{}`",
+                other
+            ))
+        }
+    };
+
+    fn number<N: FromStr>(request: &Request, key: &str, default: N) -> Result<N, String> {
+        match request.query.get(key) {
+            None => Ok(default),
+            Some(value) => value.parse().map_err(|_| {
+                format!("{} must be a non-negative integer but is `{}`", key, value)
+            }),
+        }
+    }
+
+    let limit = number(request, "limit", DEFAULT_PAGE_SIZE)?;
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(format!("limit must be between 1 and {}", MAX_PAGE_SIZE));
+    }
+
+    Ok(history::Query {
+        direction,
+        from_block: number(request, "from_block", 0)?,
+        page: number(request, "page", 0)?,
+        limit,
+    })
+}
+
+/// parses the query of `GET /stats` and returns the number of days
+fn parse_stats_query(request: &Request) -> Result<u64, String> {
+    let days = match request.query.get("days") {
+        None => stats::DEFAULT_DAYS,
+        Some(days) => days
+            .parse()
+            .map_err(|_| format!("days must be a non-negative integer but is `{}`", days))?,
+    };
+    if days == 0 || days > stats::MAX_DAYS {
+        return Err(format!("days must be between 1 and {}", stats::MAX_DAYS));
+    }
+    Ok(days)
+}
+
+/// body of `GET /status`
+#[derive(Serialize)]
+struct StatusReport {
+    status: Status,
+    deposit_relay_lag: Option<u64>,
+    withdraw_confirm_lag: Option<u64>,
+    withdraw_relay_lag: Option<u64>,
+    /// most recent first. `None` if the relay history is disabled
+    recent_transfers: Option<Vec<Transfer>>,
+}
+
+/// body of `GET /checkpoints`. `None` until the stream was first observed
+#[derive(Serialize)]
+struct CheckpointsReport {
+    deposit_relay: Option<u64>,
+    withdraw_confirm: Option<u64>,
+    withdraw_relay: Option<u64>,
+    /// `None` if detection of unaccounted transfers is disabled
+    unaccounted_transfers: Option<u64>,
+    /// `None` if relay of calls is disabled
+    call_confirm: Option<u64>,
+    call_relay: Option<u64>,
+}
+
+/// body of `GET /pauses` and of the responses to pause and resume requests
+#[derive(Serialize)]
+struct PausesReport {
+    deposits: bool,
+    withdraws: bool,
+}
+
+/// state the endpoints are served from
+struct Api {
+    /// bridge that configures `http_api`. `None` in a process that runs a single bridge
+    bridge: Option<String>,
+    relay_history: Option<PathBuf>,
+    parked: Parked,
+    deposits_pause: Pause,
+    withdraws_pause: Pause,
+    /// token of `http_api.admin_token_file`. pause and resume requests are refused if `None`
+    admin_token: Option<String>,
+}
+
+impl Api {
+    fn handle(&self, method: &str, request: &Request) -> Response {
+        match (method, request.path.as_str()) {
+            ("GET", "/") => Response::html(DASHBOARD),
+            ("GET", "/status") => self.status(),
+            ("GET", "/checkpoints") => self.checkpoints(),
+            ("GET", "/parked") => Response::json(&self.parked.relays()),
+            ("GET", "/dead_letters") => Response::json(&self.parked.dead_letters()),
+            ("POST", "/replay") => self.replay(request),
+            ("GET", "/pauses") => match self.bridge_of(request) {
+                Ok(bridge) => self.pauses(bridge),
+                Err(response) => response,
+            },
+            ("GET", "/transfers") => self.transfers(request),
+            ("GET", "/stats") => self.stats(request),
+            ("GET", "/metrics") => Response::text(
+                prometheus::CONTENT_TYPE,
+                prometheus::render(&metrics::snapshot(), &metrics::relays(), &status::snapshot()),
+            ),
+            ("POST", path) => match parse_pause_path(path) {
+                Some((direction, paused)) => self.set_paused(request, direction, paused),
+                None => Response::error(404, "not found"),
+            },
+            ("GET", _) => Response::error(404, "not found"),
+            _ => Response::error(405, "only GET and POST are supported"),
+        }
+    }
+
+    fn checkpoints(&self) -> Response {
+        let status = status::snapshot();
+        Response::json(&CheckpointsReport {
+            deposit_relay: status.checked_deposit_relay,
+            withdraw_confirm: status.checked_withdraw_confirm,
+            withdraw_relay: status.checked_withdraw_relay,
+            unaccounted_transfers: status.checked_unaccounted_transfers,
+            call_confirm: status.checked_call_confirm,
+            call_relay: status.checked_call_relay,
+        })
+    }
+
+    /// bridge named by the `bridge` query parameter. the bridge of the api if there's none
+    fn bridge_of(&self, request: &Request) -> Result<Option<String>, Response> {
+        match request.query.get("bridge") {
+            Some(name) if control::is_known(&Some(name.clone())) => Ok(Some(name.clone())),
+            Some(_) => Err(Response::error(404, "unknown bridge")),
+            None => Ok(self.bridge.clone()),
+        }
+    }
+
+    fn pauses(&self, bridge: Option<String>) -> Response {
+        Response::json(&PausesReport {
+            deposits: self.deposits_pause.is_paused_for(bridge.clone()),
+            withdraws: self.withdraws_pause.is_paused_for(bridge),
+        })
+    }
+
+    /// fails with the response to send unless `request` holds the token of the api
+    fn authorize(&self, request: &Request) -> Result<(), Response> {
+        let admin_token = match self.admin_token {
+            Some(ref token) => token,
+            None => {
+                return Err(Response::error(
+                    403,
+                    "http_api.admin_token_file is not configured",
+                ))
+            }
+        };
+        let authorized = request.token.as_ref().map_or(false, |token| {
+            constant_time_eq(token.as_bytes(), admin_token.as_bytes())
+        });
+        if !authorized {
+            warn!("rejected http api request with invalid token");
+            return Err(Response::error(401, "invalid token"));
+        }
+        Ok(())
+    }
+
+    fn replay(&self, request: &Request) -> Response {
+        if let Err(response) = self.authorize(request) {
+            return response;
+        }
+        let (chain, origin) = match parse_replay_query(request) {
+            Ok(query) => query,
+            Err(message) => return Response::error(400, &message),
+        };
+        match self.parked.replay(chain, origin, status::now()) {
+            Some(relay) => {
+                warn!("replaying dead letter {:?} to {:?} via http api", origin, chain);
+                Response::json(&relay)
+            }
+            None => Response::error(404, "no such dead letter"),
+        }
+    }
+
+    fn set_paused(&self, request: &Request, direction: Direction, paused: bool) -> Response {
+        if let Err(response) = self.authorize(request) {
+            return response;
+        }
+        let bridge = match self.bridge_of(request) {
+            Ok(bridge) => bridge,
+            Err(response) => return response,
+        };
+        control::set_paused(bridge.clone(), direction, paused);
+        let name = bridge.as_ref().map_or("bridge", String::as_str);
+        if paused {
+            warn!("{:?} of {} paused via http api", direction, name);
+        } else {
+            info!("{:?} of {} resumed via http api", direction, name);
+        }
+        self.pauses(bridge)
+    }
+
+    fn status(&self) -> Response {
+        let recent_transfers = match self.relay_history {
+            Some(ref path) => match history::recent(path, RECENT_TRANSFERS) {
+                Ok(transfers) => Some(transfers),
+                Err(err) => {
+                    error!("cannot read relay history: {}", err);
+                    return Response::error(500, "cannot read relay history");
+                }
+            },
+            None => None,
+        };
+        let status = status::snapshot();
+        Response::json(&StatusReport {
+            deposit_relay_lag: status.deposit_relay_lag(),
+            withdraw_confirm_lag: status.withdraw_confirm_lag(),
+            withdraw_relay_lag: status.withdraw_relay_lag(),
+            status,
+            recent_transfers,
+        })
+    }
+
+    fn transfers(&self, request: &Request) -> Response {
+        let path = match self.relay_history {
+            Some(ref path) => path,
+            None => return Response::error(404, "relay history is disabled"),
+        };
+        let query = match parse_transfers_query(request) {
+            Ok(query) => query,
+            Err(message) => return Response::error(400, &message),
+        };
+        match history::query(path, &query) {
+            Ok(page) => Response::json(&page),
+            Err(err) => {
+                error!("cannot read relay history: {}", err);
+                Response::error(500, "cannot read relay history")
+            }
+        }
+    }
+
+    fn stats(&self, request: &Request) -> Response {
+        let path = match self.relay_history {
+            Some(ref path) => path,
+            None => return Response::error(404, "relay history is disabled"),
+        };
+        let days = match parse_stats_query(request) {
+            Ok(days) => days,
+            Err(message) => return Response::error(400, &message),
+        };
+        match stats::load(path, clock::timestamp("home"), days) {
+            Ok(statistics) => Response::json(&statistics),
+            Err(err) => {
+                error!("cannot read relay history: {}", err);
+                Response::error(500, "cannot read relay history")
+            }
+        }
+    }
+
+    fn serve(&self, stream: TcpStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        // the headers are skipped except for the token. requests have no body
+        let mut token = None;
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            token = parse_bearer_token(&header).or(token);
+            header.clear();
+        }
+
+        let response = match parse_request_line(&line) {
+            Some((method, mut request)) => {
+                request.token = token;
+                self.handle(method, &request)
+            }
+            None => Response::error(400, "malformed request"),
+        };
+
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            response.reason(),
+            response.content_type,
+            response.body.len(),
+            response.body
+        )?;
+        Ok(())
+    }
+}
+
+/// serves the http api on `http_api.address` if it is configured.
+/// `parked` are the parked relays of the bridge.
+/// fails if the address can't be bound or `http_api.admin_token_file` can't be read
+pub fn serve_api(config: &Config, parked: Parked) -> Result<(), Error> {
+    let http_api = match config.http_api {
+        Some(ref http_api) => http_api,
+        None => return Ok(()),
+    };
+    let admin_token = match http_api.admin_token_file {
+        Some(ref path) => Some(read_token(path)?),
+        None => None,
+    };
+    let address = http_api.address;
+    let listener =
+        TcpListener::bind(address).chain_err(|| format!("Cannot bind http api to {}", address))?;
+    let api = Api {
+        bridge: supervisor::current(),
+        relay_history: config.relay_history.clone(),
+        parked,
+        deposits_pause: config.pause(Direction::Deposits),
+        withdraws_pause: config.pause(Direction::Withdraws),
+        admin_token,
+    };
+    info!("serving http api on {}", address);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.map_err(Into::into).and_then(|stream| api.serve(stream));
+            if let Err(err) = result {
+                warn!("http api request failed: {}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use control::Direction;
+    use history::{Query, TransferDirection};
+    use signer::Chain;
+    use super::{parse_bearer_token, parse_pause_path, parse_replay_query, parse_request_line,
+                parse_stats_query, parse_transfers_query};
+
+    #[test]
+    fn test_parse_request_line() {
+        let (method, request) =
+            parse_request_line("GET /transfers?direction=deposit&page=2 HTTP/1.1\r\n").unwrap();
+        assert_eq!("GET", method);
+        assert_eq!("/transfers", request.path);
+        assert_eq!("deposit", request.query["direction"]);
+        assert_eq!("2", request.query["page"]);
+
+        let (_, request) = parse_request_line("GET /transfers HTTP/1.0").unwrap();
+        assert!(request.query.is_empty());
+
+        assert!(parse_request_line("GET /transfers").is_none());
+        assert!(parse_request_line("").is_none());
+    }
+
+    #[test]
+    fn test_parse_bearer_token() {
+        assert_eq!(
+            Some("secret".to_owned()),
+            parse_bearer_token("Authorization: Bearer secret\r\n")
+        );
+        assert_eq!(
+            Some("secret".to_owned()),
+            parse_bearer_token("authorization:bearer secret")
+        );
+        assert_eq!(None, parse_bearer_token("Authorization: Basic c2VjcmV0\r\n"));
+        assert_eq!(None, parse_bearer_token("Authorization: Bearer\r\n"));
+        assert_eq!(None, parse_bearer_token("Host: 127.0.0.1:8080\r\n"));
+    }
+
+    #[test]
+    fn test_parse_pause_path() {
+        assert_eq!(Some((Direction::Deposits, true)), parse_pause_path("/pause/deposits"));
+        assert_eq!(Some((Direction::Withdraws, false)), parse_pause_path("/resume/withdraws"));
+        assert_eq!(None, parse_pause_path("/pause"));
+        assert_eq!(None, parse_pause_path("/pause/refunds"));
+        assert_eq!(None, parse_pause_path("/status"));
+    }
+
+    #[test]
+    fn test_parse_replay_query() {
+        let (_, request) = parse_request_line(
+            "POST /replay?chain=home&origin=0x0000000000000000000000000000000000000000000000000000000000000012 HTTP/1.1",
+        ).unwrap();
+        assert_eq!(Ok((Chain::Home, 0x12.into())), parse_replay_query(&request));
+
+        let queries = [
+            "origin=0x12",
+            "chain=side&origin=0x12",
+            "chain=foreign",
+            "chain=home&origin=x",
+        ];
+        for query in &queries {
+            let line = format!("POST /replay?{} HTTP/1.1", query);
+            let (_, request) = parse_request_line(&line).unwrap();
+            assert!(parse_replay_query(&request).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_transfers_query() {
+        let (_, request) = parse_request_line(
+            "GET /transfers?direction=withdraw&from_block=10&page=3&limit=5 HTTP/1.1",
+        ).unwrap();
+        assert_eq!(
+            Query {
+                direction: Some(TransferDirection::Withdraw),
+                from_block: 10,
+                page: 3,
+                limit: 5,
+            },
+            parse_transfers_query(&request).unwrap()
+        );
+
+        let (_, request) = parse_request_line("GET /transfers HTTP/1.1").unwrap();
+        assert_eq!(
+            Query {
+                direction: None,
+                from_block: 0,
+                page: 0,
+                limit: 100,
+            },
+            parse_transfers_query(&request).unwrap()
+        );
+
+        for query in &["direction=up", "from_block=-1", "page=x", "limit=0", "limit=1001"] {
+            let line = format!("GET /transfers?{} HTTP/1.1", query);
+            let (_, request) = parse_request_line(&line).unwrap();
+            assert!(parse_transfers_query(&request).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_stats_query() {
+        let (_, request) = parse_request_line("GET /stats HTTP/1.1").unwrap();
+        assert_eq!(Ok(7), parse_stats_query(&request));
+        let (_, request) = parse_request_line("GET /stats?days=30 HTTP/1.1").unwrap();
+        assert_eq!(Ok(30), parse_stats_query(&request));
+        for query in &["days=0", "days=367", "days=x"] {
+            let line = format!("GET /stats?{} HTTP/1.1", query);
+            let (_, request) = parse_request_line(&line).unwrap();
+            assert!(parse_stats_query(&request).is_err());
+        }
+    }
+}
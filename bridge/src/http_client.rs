@@ -0,0 +1,787 @@
+/// json-rpc transport over http for nodes configured with an `https://` url, `compression`
+/// or `compress_requests`. plain `http` without compression keeps using the transport of `web3`.
+/// requests are sent by a pool of threads that each keep a connection to the node alive
+/// between requests. a request on a connection the node closed while it was idle is sent
+/// again on a new connection, unless it sends a transaction which the node might have
+/// received already. with `compression` responses are requested gzipped.
+/// with `compress_requests` the bodies of requests are sent gzipped.
+/// `request` sends single requests to other http servers the bridge talks to.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use futures::{Async, Future, Poll};
+use futures::sync::oneshot;
+use jsonrpc_core as rpc;
+use serde_json::{self, Value};
+use web3::{self, helpers, RequestId, Transport};
+use config::TlsConfig;
+use error::Error;
+use tls::{self, Connector, NodeStream, NodeUrl};
+use websocket::call_result;
+
+/// number of threads, and so connections, that send requests to the node
+const WORKERS: usize = 8;
+/// seconds after which an idle connection isn't used anymore.
+/// nodes and proxies close idle connections after a while
+const MAX_IDLE: u64 = 10;
+/// upper bound of the length of the status line and each header of a response
+const MAX_HEADER_LENGTH: u64 = 8 * 1024;
+/// upper bound of the number of headers of a response
+const MAX_HEADERS: usize = 100;
+/// upper bound of the length of the body of a response, after it's inflated
+const MAX_BODY_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// response of the node
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    reason: String,
+    body: Vec<u8>,
+    /// whether the connection can be used for the next request
+    keep_alive: bool,
+}
+
+/// reads a line of at most `MAX_HEADER_LENGTH` bytes
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, Error> {
+    let mut line = String::new();
+    (&mut *reader).take(MAX_HEADER_LENGTH).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        bail!("node sent a truncated or oversized line");
+    }
+    Ok(line.trim_right().to_owned())
+}
+
+/// reads a body of `Transfer-Encoding: chunked`
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| format!("node sent a malformed chunk size {}", line))?;
+        if size == 0 {
+            // trailers
+            while !read_line(reader)?.is_empty() {}
+            return Ok(body);
+        }
+        if body.len() as u64 + size > MAX_BODY_LENGTH {
+            bail!("node sent a body of more than {} bytes", MAX_BODY_LENGTH);
+        }
+        let start = body.len();
+        body.resize(start + size as usize, 0);
+        reader.read_exact(&mut body[start..])?;
+        if !read_line(reader)?.is_empty() {
+            bail!("node sent a chunk longer than its size");
+        }
+    }
+}
+
+/// gzips a body
+fn gzip(body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+/// writes a request of `method` for `url` to `stream`. `body` is sent as json,
+/// gzipped if `gzip_body`. a gzipped response is requested if `accept_gzip`
+fn write_request<W: Write>(
+    stream: &mut W,
+    method: &str,
+    url: &NodeUrl,
+    body: Option<&[u8]>,
+    gzip_body: bool,
+    accept_gzip: bool,
+) -> io::Result<()> {
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\n",
+        method, url.path, url.address
+    );
+    if accept_gzip {
+        head.push_str("Accept-Encoding: gzip\r\n");
+    }
+    let gzipped;
+    let body = match body {
+        Some(body) if gzip_body => {
+            gzipped = gzip(body)?;
+            head.push_str("Content-Encoding: gzip\r\n");
+            Some(&gzipped[..])
+        }
+        body => body,
+    };
+    if let Some(body) = body {
+        head.push_str(&format!(
+            "Content-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        ));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    if let Some(body) = body {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+/// inflates a gzipped body
+fn gunzip(body: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut inflated = Vec::new();
+    GzDecoder::new(body)
+        .take(MAX_BODY_LENGTH + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|err| format!("node sent a corrupt gzipped body: {}", err))?;
+    if inflated.len() as u64 > MAX_BODY_LENGTH {
+        bail!("node sent a body of more than {} bytes", MAX_BODY_LENGTH);
+    }
+    Ok(inflated)
+}
+
+/// reads a response to a request of `HTTP/1.1`
+fn read_response<R: BufRead>(reader: &mut R) -> Result<Response, Error> {
+    let status_line = read_line(reader)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or("");
+    let status = match (version.starts_with("HTTP/"), parts.next()) {
+        (true, Some(status)) => status
+            .parse::<u16>()
+            .map_err(|_| format!("node sent a malformed status line {}", status_line))?,
+        _ => bail!("node sent a malformed status line {}", status_line),
+    };
+    let reason = parts.next().unwrap_or("").to_owned();
+    let mut keep_alive = version == "HTTP/1.1";
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut gzip = false;
+    let mut headers = 0;
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            bail!("node sent more than {} headers", MAX_HEADERS);
+        }
+        let mut parts = line.splitn(2, ':');
+        let (name, value) = match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => (name.trim().to_lowercase(), value.trim().to_lowercase()),
+            _ => bail!("node sent a malformed header {}", line),
+        };
+        match name.as_str() {
+            "content-length" => {
+                content_length = Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| format!("node sent a malformed header {}", line))?,
+                )
+            }
+            "transfer-encoding" => chunked = value.ends_with("chunked"),
+            "content-encoding" => match value.as_str() {
+                "gzip" => gzip = true,
+                "identity" => {}
+                _ => bail!("node sent a body of unsupported encoding {}", value),
+            },
+            "connection" if value == "close" => keep_alive = false,
+            "connection" if value == "keep-alive" => keep_alive = true,
+            _ => {}
+        }
+    }
+    let body = match (chunked, content_length) {
+        (true, _) => read_chunked(reader)?,
+        (false, Some(length)) => {
+            if length > MAX_BODY_LENGTH {
+                bail!("node sent a body of {} bytes", length);
+            }
+            let mut body = vec![0u8; length as usize];
+            reader.read_exact(&mut body)?;
+            body
+        }
+        // the body ends with the connection
+        (false, None) => {
+            keep_alive = false;
+            let mut body = Vec::new();
+            (&mut *reader).take(MAX_BODY_LENGTH + 1).read_to_end(&mut body)?;
+            if body.len() as u64 > MAX_BODY_LENGTH {
+                bail!("node sent a body of more than {} bytes", MAX_BODY_LENGTH);
+            }
+            body
+        }
+    };
+    let body = if gzip { gunzip(&body)? } else { body };
+    Ok(Response {
+        status,
+        reason,
+        body,
+        keep_alive,
+    })
+}
+
+/// why an exchange with the node failed
+enum Failure {
+    /// the node closed the connection before it responded.
+    /// on a reused connection that usually means it closed it while it was idle
+    Closed(Error),
+    Failed(Error),
+}
+
+/// request that waits for its response
+struct Job {
+    request: String,
+    /// whether the request may be sent again after the node closed an idle connection
+    resendable: bool,
+    response: oneshot::Sender<Result<Value, web3::Error>>,
+}
+
+/// connection kept alive between requests
+struct Connection {
+    stream: NodeStream,
+    last_used: Instant,
+}
+
+/// how the workers reach the node
+struct Endpoint {
+    url: String,
+    node_url: NodeUrl,
+    tls: Option<Connector>,
+    compression: bool,
+    compress_requests: bool,
+    timeout: Duration,
+}
+
+impl Endpoint {
+    fn connect(&self) -> Result<NodeStream, Error> {
+        NodeStream::connect(&self.node_url, self.tls.as_ref(), self.timeout)
+    }
+
+    /// sends `request` over `stream` and reads the response
+    fn exchange(&self, stream: &mut NodeStream, request: &str) -> Result<Response, Failure> {
+        write_request(
+            stream,
+            "POST",
+            &self.node_url,
+            Some(request.as_bytes()),
+            self.compress_requests,
+            self.compression,
+        ).map_err(|err| Failure::Closed(err.into()))?;
+        let mut reader = BufReader::new(stream);
+        let closed = reader.fill_buf().map(|bytes| bytes.is_empty());
+        match closed {
+            Ok(false) => read_response(&mut reader).map_err(Failure::Failed),
+            Ok(true) => Err(Failure::Closed("node closed the connection".into())),
+            Err(ref err) if is_closed(err) => Err(Failure::Closed(
+                format!("node closed the connection: {}", err).into(),
+            )),
+            Err(err) => Err(Failure::Failed(err.into())),
+        }
+    }
+
+    /// sends `job` on the connection of the worker. opens a new connection if there's none.
+    /// leaves the connection for the next job if the node keeps it alive
+    fn send(&self, connection: &mut Option<Connection>, job: &Job) -> Result<Response, Error> {
+        let reused = match connection.take() {
+            Some(ref idle) if idle.last_used.elapsed().as_secs() >= MAX_IDLE => None,
+            reused => reused,
+        };
+        let (stream, result) = match reused {
+            Some(mut reused) => {
+                let result = self.exchange(&mut reused.stream, &job.request);
+                match result {
+                    Err(Failure::Closed(ref err)) if job.resendable => {
+                        debug!("resending request to {}: {}", self.url, err);
+                        let mut stream = self.connect()?;
+                        let result = self.exchange(&mut stream, &job.request);
+                        (stream, result)
+                    }
+                    result => (reused.stream, result),
+                }
+            }
+            None => {
+                let mut stream = self.connect()?;
+                let result = self.exchange(&mut stream, &job.request);
+                (stream, result)
+            }
+        };
+        match result {
+            Ok(response) => {
+                if response.keep_alive {
+                    *connection = Some(Connection {
+                        stream,
+                        last_used: Instant::now(),
+                    });
+                }
+                Ok(response)
+            }
+            Err(Failure::Closed(err)) | Err(Failure::Failed(err)) => Err(err),
+        }
+    }
+
+    /// returns the result of `job`
+    fn call(&self, connection: &mut Option<Connection>, job: &Job) -> Result<Value, web3::Error> {
+        let response = self.send(connection, job).map_err(|err| {
+            web3::Error::Transport(format!("request to {} failed: {}", self.url, err))
+        })?;
+        // the same message as the http transport of `web3` so rate limits are detected
+        if response.status < 200 || response.status >= 300 {
+            return Err(web3::Error::Transport(format!(
+                "Unexpected response status code: {} {}",
+                response.status, response.reason
+            )));
+        }
+        let response: Value = serde_json::from_slice(&response.body).map_err(|err| {
+            web3::Error::Transport(format!("node responded with malformed json: {}", err))
+        })?;
+        call_result(response)
+    }
+}
+
+/// whether `err` means the peer closed the connection
+fn is_closed(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::UnexpectedEof => true,
+        _ => false,
+    }
+}
+
+/// sends the jobs until all `HttpClient`s are dropped
+fn work(endpoint: Arc<Endpoint>, jobs: Arc<Mutex<Receiver<Job>>>) {
+    let mut connection = None;
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+        // the call timed out while it was queued
+        if job.response.is_canceled() {
+            continue;
+        }
+        let result = endpoint.call(&mut connection, &job);
+        let _ = job.response.send(result);
+    }
+}
+
+/// json-rpc transport over http with tls and compression
+#[derive(Clone)]
+pub struct HttpClient {
+    url: Arc<String>,
+    id: Arc<AtomicUsize>,
+    jobs: Arc<Mutex<Sender<Job>>>,
+}
+
+impl fmt::Debug for HttpClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpClient").field("url", &self.url).finish()
+    }
+}
+
+impl HttpClient {
+    /// sends requests to `url`. `https://` urls are wrapped in tls by `tls`.
+    /// `compression` requests gzipped responses, `compress_requests` gzips the requests.
+    /// reads time out after `timeout`
+    pub fn new(
+        url: &str,
+        tls: Option<Connector>,
+        compression: bool,
+        compress_requests: bool,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let endpoint = Arc::new(Endpoint {
+            url: url.to_owned(),
+            node_url: tls::parse_url(url, "http")?,
+            tls,
+            compression,
+            compress_requests,
+            timeout,
+        });
+        let (sender, receiver) = channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..WORKERS {
+            let endpoint = endpoint.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("http-client-{}", index))
+                .spawn(move || work(endpoint, receiver))?;
+        }
+        Ok(HttpClient {
+            url: Arc::new(url.to_owned()),
+            id: Arc::new(AtomicUsize::new(1)),
+            jobs: Arc::new(Mutex::new(sender)),
+        })
+    }
+}
+
+impl Transport for HttpClient {
+    type Out = HttpCall;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.id.fetch_add(1, Ordering::SeqCst);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, _id: RequestId, request: rpc::Call) -> Self::Out {
+        let resendable = match request {
+            rpc::Call::MethodCall(ref call) => !call.method.starts_with("eth_send"),
+            _ => false,
+        };
+        let request = match serde_json::to_string(&request) {
+            Ok(request) => request,
+            Err(err) => {
+                return HttpCall::Failed(Some(web3::Error::Transport(format!(
+                    "cannot serialize request: {}",
+                    err
+                ))))
+            }
+        };
+        let (sender, receiver) = oneshot::channel();
+        let job = Job {
+            request,
+            resendable,
+            response: sender,
+        };
+        let sent = self.jobs
+            .lock()
+            .map_err(|_| ())
+            .and_then(|jobs| jobs.send(job).map_err(|_| ()));
+        match sent {
+            Ok(()) => HttpCall::Pending(receiver),
+            Err(()) => HttpCall::Failed(Some(web3::Error::Transport(format!(
+                "http transport to {} stopped",
+                self.url
+            )))),
+        }
+    }
+}
+
+/// sends a single request of `method` to `url` on a connection of its own and returns
+/// the body of the response. `body` is sent as json. responses are requested gzipped.
+/// the certificates of `https://` urls are checked against the system roots.
+/// fails unless the server responds with a status of `2xx`.
+/// connecting, reads and writes time out after `timeout`
+pub fn request(
+    method: &str,
+    url: &str,
+    body: Option<&[u8]>,
+    timeout: Duration,
+) -> Result<Vec<u8>, Error> {
+    let node_url = tls::parse_url(url, "http")?;
+    let tls = if node_url.tls {
+        Some(Connector::new(&TlsConfig::default())?)
+    } else {
+        None
+    };
+    let mut stream = NodeStream::connect(&node_url, tls.as_ref(), timeout)?;
+    write_request(&mut stream, method, &node_url, body, false, true)?;
+    let response = read_response(&mut BufReader::new(stream))?;
+    if response.status < 200 || response.status >= 300 {
+        bail!(
+            "{} responded with {} {}",
+            url,
+            response.status,
+            response.reason
+        );
+    }
+    Ok(response.body)
+}
+
+/// response of a call through an `HttpClient`
+pub enum HttpCall {
+    Pending(oneshot::Receiver<Result<Value, web3::Error>>),
+    Failed(Option<web3::Error>),
+}
+
+impl Future for HttpCall {
+    type Item = Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            HttpCall::Pending(ref mut receiver) => match receiver.poll() {
+                Ok(Async::Ready(result)) => result.map(Async::Ready),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(oneshot::Canceled) => Err(web3::Error::Transport(
+                    "http transport dropped the request".into(),
+                )),
+            },
+            HttpCall::Failed(ref mut err) => {
+                Err(err.take().expect("a failed call isn't polled again; qed"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Cursor, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use flate2::Compression;
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use futures::sync::oneshot;
+    use serde_json::Value;
+    use tls;
+    use super::{gzip, read_response, request, write_request, Endpoint, Job, Response};
+
+    fn response(bytes: &[u8]) -> Response {
+        read_response(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    /// reads a request of `write_request`. returns its head and body
+    fn read_request<R: BufRead>(reader: &mut R) -> (String, Vec<u8>) {
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let length = head.lines()
+            .find(|line| line.starts_with("Content-Length: "))
+            .map_or(0, |line| line[16..].parse().unwrap());
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).unwrap();
+        (head, body)
+    }
+
+    /// accepts a connection for each element of `connections` on `127.0.0.1` in turn
+    /// and answers as many requests on it as it has responses before closing it.
+    /// returns the url of the server and the requests each connection received
+    fn serve(
+        connections: Vec<Vec<Vec<u8>>>,
+    ) -> (String, thread::JoinHandle<Vec<Vec<(String, Vec<u8>)>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            connections
+                .into_iter()
+                .map(|responses| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    responses
+                        .into_iter()
+                        .map(|response| {
+                            let request = read_request(&mut reader);
+                            stream.write_all(&response).unwrap();
+                            request
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+        (url, server)
+    }
+
+    fn ok(body: &[u8]) -> Vec<u8> {
+        let mut response =
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn endpoint(url: &str, compression: bool, compress_requests: bool) -> Endpoint {
+        Endpoint {
+            url: url.to_owned(),
+            node_url: tls::parse_url(url, "http").unwrap(),
+            tls: None,
+            compression,
+            compress_requests,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn job(request: &str, resendable: bool) -> Job {
+        Job {
+            request: request.to_owned(),
+            resendable,
+            response: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn test_write_request() {
+        let url = tls::parse_url("http://node:8545/rpc", "http").unwrap();
+        let mut plain = Vec::new();
+        write_request(&mut plain, "POST", &url, Some(&b"{}"[..]), false, false).unwrap();
+        assert_eq!(
+            &b"POST /rpc HTTP/1.1\r\nHost: node:8545\r\n\
+               Content-Type: application/json\r\nContent-Length: 2\r\n\r\n{}"[..],
+            &plain[..]
+        );
+
+        let mut get = Vec::new();
+        write_request(&mut get, "GET", &url, None, true, true).unwrap();
+        assert_eq!(
+            &b"GET /rpc HTTP/1.1\r\nHost: node:8545\r\nAccept-Encoding: gzip\r\n\r\n"[..],
+            &get[..]
+        );
+
+        let body = "{\"jsonrpc\":\"2.0\",\"method\":\"eth_getLogs\"}".repeat(100);
+        let mut gzipped = Vec::new();
+        write_request(&mut gzipped, "POST", &url, Some(body.as_bytes()), true, true).unwrap();
+        let (head, sent) = read_request(&mut Cursor::new(gzipped));
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        assert!(head.contains("Accept-Encoding: gzip\r\n"));
+        assert!(sent.len() < body.len());
+        let mut inflated = String::new();
+        GzDecoder::new(&sent[..]).read_to_string(&mut inflated).unwrap();
+        assert_eq!(body, inflated);
+    }
+
+    #[test]
+    fn test_keep_alive() {
+        let (url, server) = serve(vec![vec![
+            ok(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"0x1\"}"),
+            ok(b"{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":\"0x2\"}"),
+        ]]);
+        let endpoint = endpoint(&url, false, false);
+        let mut connection = None;
+        assert_eq!(
+            Value::String("0x1".into()),
+            endpoint.call(&mut connection, &job("first", true)).unwrap()
+        );
+        assert!(connection.is_some());
+        // a second connection would never be answered
+        assert_eq!(
+            Value::String("0x2".into()),
+            endpoint.call(&mut connection, &job("second", true)).unwrap()
+        );
+        let connections = server.join().unwrap();
+        assert_eq!(1, connections.len());
+        assert_eq!(b"first".to_vec(), connections[0][0].1);
+        assert_eq!(b"second".to_vec(), connections[0][1].1);
+    }
+
+    #[test]
+    fn test_resend_on_closed_connection() {
+        let result = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"0x1\"}";
+        // the server closes the connection after each response
+        let (url, server) = serve(vec![vec![ok(result)], vec![ok(result)], vec![ok(result)]]);
+        let endpoint = endpoint(&url, false, false);
+        let mut connection = None;
+        endpoint.call(&mut connection, &job("first", true)).unwrap();
+        // wait for the server to close the idle connection
+        thread::sleep(Duration::from_millis(100));
+        endpoint.call(&mut connection, &job("second", true)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        // transactions aren't sent again. the node might have received them already
+        assert!(
+            endpoint
+                .call(&mut connection, &job("eth_sendRawTransaction", false))
+                .is_err()
+        );
+        // the next request opens a new connection
+        assert!(connection.is_none());
+        endpoint.call(&mut connection, &job("third", true)).unwrap();
+        let connections = server.join().unwrap();
+        assert_eq!(3, connections.len());
+        assert_eq!(b"second".to_vec(), connections[1][0].1);
+        assert_eq!(b"third".to_vec(), connections[2][0].1);
+    }
+
+    #[test]
+    fn test_gzipped_exchange() {
+        let body = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"0x1\"}";
+        let gzipped = gzip(body.as_bytes()).unwrap();
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzipped.len()
+        ).into_bytes();
+        response.extend_from_slice(&gzipped);
+        let (url, server) = serve(vec![vec![response]]);
+        let endpoint = endpoint(&url, true, true);
+        let mut connection = None;
+        let request = "{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"eth_blockNumber\"}";
+        assert_eq!(
+            Value::String("0x1".into()),
+            endpoint.call(&mut connection, &job(request, true)).unwrap()
+        );
+        let connections = server.join().unwrap();
+        let (ref head, ref sent) = connections[0][0];
+        assert!(head.contains("Accept-Encoding: gzip\r\n"));
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        let mut inflated = String::new();
+        GzDecoder::new(&sent[..]).read_to_string(&mut inflated).unwrap();
+        assert_eq!(request, inflated);
+    }
+
+    #[test]
+    fn test_request() {
+        let (url, server) = serve(vec![
+            vec![ok(b"{\"fast\":20}")],
+            vec![b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_vec()],
+        ]);
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            b"{\"fast\":20}".to_vec(),
+            request("POST", &url, Some(&b"{}"[..]), timeout).unwrap()
+        );
+        assert!(request("GET", &url, None, timeout).is_err());
+        let connections = server.join().unwrap();
+        assert!(connections[0][0].0.starts_with("POST /rpc HTTP/1.1\r\n"));
+        assert_eq!(b"{}".to_vec(), connections[0][0].1);
+        assert!(connections[1][0].0.starts_with("GET /rpc HTTP/1.1\r\n"));
+        assert!(request("GET", "ftp://oracle", None, timeout).is_err());
+    }
+
+    #[test]
+    fn test_read_response() {
+        assert_eq!(
+            Response {
+                status: 200,
+                reason: "OK".into(),
+                body: b"{\"result\":\"0x1\"}".to_vec(),
+                keep_alive: true,
+            },
+            response(b"HTTP/1.1 200 OK\r\nContent-Length: 16\r\n\r\n{\"result\":\"0x1\"}")
+        );
+        let chunked = response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nTrailer: 1\r\n\r\n",
+        );
+        assert_eq!(b"hello, world".to_vec(), chunked.body);
+        assert!(chunked.keep_alive);
+        let closed = response(b"HTTP/1.1 429 Too Many Requests\r\nConnection: close\r\n\r\nslow");
+        assert_eq!(429, closed.status);
+        assert_eq!("Too Many Requests", closed.reason);
+        assert_eq!(b"slow".to_vec(), closed.body);
+        assert!(!closed.keep_alive);
+        assert!(!response(b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n").keep_alive);
+
+        assert!(read_response(&mut Cursor::new(&b""[..])).is_err());
+        assert!(read_response(&mut Cursor::new(&b"SSH-2.0\r\n\r\n"[..])).is_err());
+        let truncated = b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort";
+        assert!(read_response(&mut Cursor::new(&truncated[..])).is_err());
+        let brotli = b"HTTP/1.1 200 OK\r\nContent-Encoding: br\r\nContent-Length: 0\r\n\r\n";
+        assert!(read_response(&mut Cursor::new(&brotli[..])).is_err());
+    }
+
+    #[test]
+    fn test_read_gzipped_response() {
+        let body = "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":[]}".repeat(100);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        let mut bytes = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            gzipped.len()
+        ).into_bytes();
+        bytes.extend_from_slice(&gzipped);
+        assert_eq!(body.as_bytes(), &response(&bytes).body[..]);
+    }
+}
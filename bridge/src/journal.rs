@@ -0,0 +1,128 @@
+/// journal of the relay transactions that were sent for events after the checkpoints.
+/// a relay transaction is journaled as soon as the node returned its hash and the bridge
+/// writes the journal to the database right away (see `bridge::Bridge`).
+/// the entries of a relay stream are dropped once its checkpoint passed their block.
+/// after a crash or a kill the events after the checkpoints are relayed again.
+/// a relay whose transaction matches an entry first looks up the receipt of the journaled
+/// transaction instead of sending it again blindly (see `transaction::RelayTransaction`).
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use web3::types::{Address, Bytes, H256};
+use signer::Chain;
+
+/// relay transaction sent for events after the checkpoint of its relay stream
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// chain the transaction was sent to.
+    /// deposits are relayed to foreign and withdraws to home
+    pub chain: Chain,
+    /// last block of the events the transaction relays
+    pub block: u64,
+    pub transaction_hash: H256,
+    /// recipient and payload that identify the transaction after a restart
+    pub to: Address,
+    pub data: Bytes,
+}
+
+/// relay transactions in flight. clones share the entries
+#[derive(Debug, Default, Clone)]
+pub struct Journal {
+    entries: Arc<Mutex<Vec<JournalEntry>>>,
+    /// set when the entries changed since the journal was last written
+    dirty: Arc<AtomicBool>,
+}
+
+impl Journal {
+    fn lock(&self) -> MutexGuard<Vec<JournalEntry>> {
+        self.entries.lock().expect("journal is never poisoned; qed")
+    }
+
+    /// continues the journal loaded from the database
+    pub fn seed(&self, entries: Vec<JournalEntry>) {
+        *self.lock() = entries;
+    }
+
+    /// returns the entries to save in the database
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.lock().clone()
+    }
+
+    /// journals `entry`. replaces the entry of an earlier attempt of the same relay
+    pub fn record(&self, entry: JournalEntry) {
+        let mut entries = self.lock();
+        entries.retain(|e| e.chain != entry.chain || e.to != entry.to || e.data != entry.data);
+        entries.push(entry);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// returns the hash of the journaled transaction to `to` on `chain` with `data`
+    pub fn find(&self, chain: Chain, to: Address, data: &Bytes) -> Option<H256> {
+        self.lock()
+            .iter()
+            .find(|e| e.chain == chain && e.to == to && e.data == *data)
+            .map(|e| e.transaction_hash)
+    }
+
+    /// drops the entries of transactions to `chain` for events up to the checkpoint `block`
+    pub fn clear(&self, chain: Chain, block: u64) {
+        let mut entries = self.lock();
+        let len = entries.len();
+        entries.retain(|e| e.chain != chain || e.block > block);
+        if entries.len() != len {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// returns whether the entries changed since the last call
+    pub fn take_dirty(&self) -> bool {
+        self.dirty.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use signer::Chain;
+    use super::{Journal, JournalEntry};
+
+    fn entry(chain: Chain, block: u64, hash: u64, data: u8) -> JournalEntry {
+        JournalEntry {
+            chain,
+            block,
+            transaction_hash: hash.into(),
+            to: 1.into(),
+            data: vec![data].into(),
+        }
+    }
+
+    #[test]
+    fn test_journal() {
+        let journal = Journal::default();
+        assert!(!journal.take_dirty());
+        journal.record(entry(Chain::Foreign, 10, 0x10, 1));
+        journal.record(entry(Chain::Foreign, 12, 0x12, 2));
+        journal.record(entry(Chain::Home, 10, 0x20, 1));
+        assert!(journal.take_dirty());
+        assert!(!journal.take_dirty());
+
+        // a new attempt replaces the entry
+        journal.record(entry(Chain::Foreign, 10, 0x11, 1));
+        assert_eq!(
+            Some(0x11.into()),
+            journal.find(Chain::Foreign, 1.into(), &vec![1].into())
+        );
+        assert_eq!(None, journal.find(Chain::Foreign, 2.into(), &vec![1].into()));
+        assert_eq!(None, journal.find(Chain::Foreign, 1.into(), &vec![3].into()));
+        assert!(journal.take_dirty());
+
+        journal.clear(Chain::Foreign, 11);
+        assert!(journal.take_dirty());
+        assert_eq!(
+            vec![entry(Chain::Foreign, 12, 0x12, 2), entry(Chain::Home, 10, 0x20, 1)],
+            journal.entries()
+        );
+        // nothing to clear
+        journal.clear(Chain::Foreign, 11);
+        assert!(!journal.take_dirty());
+    }
+}
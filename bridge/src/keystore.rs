@@ -0,0 +1,282 @@
+/// keys of encrypted json keystores (version 3, as written by parity and geth).
+/// with `[keystore]` configured the bridge signs withdraw messages and relay transactions
+/// with the key of the keystore instead of an unlocked account on the nodes.
+/// the key is decrypted once at startup and only kept in memory.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use crypto::aes::{self, KeySize};
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::scrypt::{scrypt, ScryptParams};
+use crypto::sha2::Sha256;
+use crypto::symmetriccipher::SynchronousStreamCipher;
+use rustc_hex::FromHex;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use serde_json;
+use tiny_keccak::keccak256;
+use web3::types::{Address, H520};
+use error::{Error, ResultExt};
+use signature::{hash_message, public_key_address};
+
+#[derive(Deserialize)]
+struct KeyFile {
+    version: u64,
+    crypto: KeyCrypto,
+}
+
+#[derive(Deserialize)]
+struct KeyCrypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: String,
+}
+
+#[derive(Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+/// parameters of `scrypt` (`n`, `r`, `p`) or `pbkdf2` (`c`, `prf`)
+#[derive(Deserialize)]
+struct KdfParams {
+    dklen: usize,
+    salt: String,
+    n: Option<u32>,
+    r: Option<u32>,
+    p: Option<u32>,
+    c: Option<u32>,
+    prf: Option<String>,
+}
+
+fn from_hex(field: &str, hex: &str) -> Result<Vec<u8>, Error> {
+    hex.trim_left_matches("0x")
+        .from_hex()
+        .map_err(|err| format!("keystore has invalid `{}`: {}", field, err).into())
+}
+
+/// derives the key that encrypts the secret from `password`
+fn derive_key(kdf: &str, params: &KdfParams, password: &[u8]) -> Result<Vec<u8>, Error> {
+    if params.dklen < 32 {
+        bail!("keystore derives keys of {} bytes. at least 32 are required", params.dklen);
+    }
+    let salt = from_hex("salt", &params.salt)?;
+    let mut derived = vec![0u8; params.dklen];
+    match kdf {
+        "scrypt" => {
+            let (n, r, p) = match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) => (n, r, p),
+                _ => bail!("keystore uses scrypt without `n`, `r` and `p`"),
+            };
+            if !n.is_power_of_two() || n < 2 {
+                bail!("keystore uses scrypt with `n` {} which isn't a power of 2", n);
+            }
+            let params = ScryptParams::new(n.trailing_zeros() as u8, r, p);
+            scrypt(password, &salt, &params, &mut derived);
+        }
+        "pbkdf2" => {
+            if params.prf.as_ref().map_or(false, |prf| prf != "hmac-sha256") {
+                bail!("keystore uses pbkdf2 with an unsupported `prf`. only hmac-sha256 works");
+            }
+            let c = params
+                .c
+                .ok_or_else(|| Error::from("keystore uses pbkdf2 without `c`"))?;
+            let mut mac = Hmac::new(Sha256::new(), password);
+            pbkdf2(&mut mac, &salt, c, &mut derived);
+        }
+        kdf => bail!(
+            "keystore uses unsupported kdf {}. only scrypt and pbkdf2 are supported",
+            kdf
+        ),
+    }
+    Ok(derived)
+}
+
+/// decrypts the secret key of the keystore `json` with `password`
+fn decrypt(json: &str, password: &[u8]) -> Result<Vec<u8>, Error> {
+    let key_file: KeyFile = serde_json::from_str(json).chain_err(|| "Cannot parse keystore")?;
+    if key_file.version != 3 {
+        bail!("keystore has version {}. only version 3 is supported", key_file.version);
+    }
+    let crypto = key_file.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        bail!(
+            "keystore uses unsupported cipher {}. only aes-128-ctr is supported",
+            crypto.cipher
+        );
+    }
+    let derived = derive_key(&crypto.kdf, &crypto.kdfparams, password)?;
+    let ciphertext = from_hex("ciphertext", &crypto.ciphertext)?;
+    let mut mac_input = derived[16..32].to_vec();
+    mac_input.extend_from_slice(&ciphertext);
+    if keccak256(&mac_input)[..] != from_hex("mac", &crypto.mac)?[..] {
+        bail!("Cannot decrypt keystore. the password is wrong");
+    }
+    let iv = from_hex("iv", &crypto.cipherparams.iv)?;
+    if iv.len() != 16 {
+        bail!("keystore has an `iv` of {} bytes instead of 16", iv.len());
+    }
+    let mut secret = vec![0u8; ciphertext.len()];
+    aes::ctr(KeySize::KeySize128, &derived[..16], &iv).process(&ciphertext, &mut secret);
+    Ok(secret)
+}
+
+/// secp256k1 key of an authority account
+pub struct LocalKey {
+    context: Secp256k1,
+    secret: SecretKey,
+    address: Address,
+}
+
+impl LocalKey {
+    /// decrypts the keystore at `path` with the password in `password_file`.
+    /// surrounding whitespace of the password is ignored
+    pub fn load<P: AsRef<Path>>(path: P, password_file: P) -> Result<Self, Error> {
+        let mut json = String::new();
+        fs::File::open(path.as_ref())
+            .and_then(|mut file| file.read_to_string(&mut json))
+            .chain_err(|| format!("Cannot read keystore {:?}", path.as_ref()))?;
+        let mut password = String::new();
+        fs::File::open(password_file.as_ref())
+            .and_then(|mut file| file.read_to_string(&mut password))
+            .chain_err(|| {
+                format!("Cannot read keystore password file {:?}", password_file.as_ref())
+            })?;
+        Self::from_secret(&decrypt(&json, password.trim().as_bytes())?)
+    }
+
+    pub fn from_secret(secret: &[u8]) -> Result<Self, Error> {
+        let context = Secp256k1::new();
+        let secret = SecretKey::from_slice(&context, secret)
+            .map_err(|err| format!("keystore holds an invalid secret key: {:?}", err))?;
+        let public = PublicKey::from_secret_key(&context, &secret)
+            .map_err(|err| format!("keystore holds an invalid secret key: {:?}", err))?;
+        Ok(LocalKey {
+            address: public_key_address(&context, &public),
+            context,
+            secret,
+        })
+    }
+
+    /// account of the key
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// signs `hash`. returns the recovery id and `r` followed by `s`
+    pub fn sign_hash(&self, hash: &[u8; 32]) -> Result<(u8, [u8; 64]), Error> {
+        let message = Message::from_slice(hash).expect("hashes are 32 bytes long; qed");
+        let signature = self.context
+            .sign_recoverable(&message, &self.secret)
+            .map_err(|err| format!("Cannot sign with keystore: {:?}", err))?;
+        let (recovery_id, rs) = signature.serialize_compact(&self.context);
+        Ok((recovery_id.to_i32() as u8, rs))
+    }
+
+    /// signs `message` like `eth_sign` of the nodes: prefixed by
+    /// `\x19Ethereum Signed Message:\n` and its length as `MessageSigning`
+    /// of the contracts expects.
+    /// returns `r`, `s` and `v` of 27 or 28
+    pub fn sign_message(&self, message: &[u8]) -> Result<H520, Error> {
+        let (recovery_id, rs) = self.sign_hash(&hash_message(message))?;
+        let mut signature = [0u8; 65];
+        signature[..64].copy_from_slice(&rs);
+        signature[64] = 27 + recovery_id;
+        Ok(H520(signature))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hex::FromHex;
+    use web3::types::Address;
+    use super::{decrypt, LocalKey};
+
+    /// test vectors of the web3 secret storage definition.
+    /// the password is `testpassword`
+    const SECRET: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    const SCRYPT_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "p": 8,
+                "r": 1,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    fn secret() -> Vec<u8> {
+        SECRET.from_hex().unwrap()
+    }
+
+    #[test]
+    fn test_decrypt_pbkdf2() {
+        assert_eq!(secret(), decrypt(PBKDF2_KEYSTORE, b"testpassword").unwrap());
+        assert!(decrypt(PBKDF2_KEYSTORE, b"wrongpassword").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_scrypt() {
+        assert_eq!(secret(), decrypt(SCRYPT_KEYSTORE, b"testpassword").unwrap());
+    }
+
+    #[test]
+    fn test_decrypt_unsupported() {
+        let keystore = PBKDF2_KEYSTORE.replace("aes-128-ctr", "aes-128-cbc");
+        assert!(decrypt(&keystore, b"testpassword").is_err());
+        let keystore = PBKDF2_KEYSTORE.replace("\"version\": 3", "\"version\": 1");
+        assert!(decrypt(&keystore, b"testpassword").is_err());
+    }
+
+    #[test]
+    fn test_address() {
+        // the secret key 1 belongs to the account of the generator point
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        let key = LocalKey::from_secret(&secret).unwrap();
+        let expected: Address = "7e5f4552091a69125d5dfcb7b8c2659029395bdf".into();
+        assert_eq!(expected, key.address());
+        assert!(LocalKey::from_secret(&[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_sign_message() {
+        let key = LocalKey::from_secret(&secret()).unwrap();
+        let signature = key.sign_message(b"hello").unwrap();
+        assert!(signature.0[64] == 27 || signature.0[64] == 28);
+        assert_eq!(signature, key.sign_message(b"hello").unwrap());
+        assert!(signature != key.sign_message(b"hello!").unwrap());
+    }
+}
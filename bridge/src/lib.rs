@@ -5,24 +5,40 @@ extern crate ethabi;
 extern crate ethabi_contract;
 #[macro_use]
 extern crate ethabi_derive;
+extern crate crypto;
 extern crate ethereum_types;
+extern crate flate2;
 #[macro_use]
 extern crate futures;
+#[cfg(feature = "event-queue-kafka")]
+extern crate kafka;
+extern crate jsonrpc_core;
+extern crate libc;
+extern crate native_tls;
+#[cfg(any(feature = "postgres-sink", feature = "shard-leases"))]
+extern crate postgres;
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate pretty_assertions;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
+extern crate rand;
 extern crate rustc_hex;
+extern crate secp256k1;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate tiny_keccak;
 extern crate tokio_core;
 extern crate tokio_timer;
 extern crate toml;
+extern crate tungstenite;
+extern crate url;
 extern crate web3;
 
 #[macro_use]
@@ -30,11 +46,68 @@ mod macros;
 
 pub mod api;
 pub mod app;
+pub mod audit;
 pub mod config;
 pub mod bridge;
+pub mod builder;
+pub mod checkpoints;
+pub mod clock;
 pub mod contracts;
+pub mod control;
 pub mod database;
+pub mod doctor;
+pub mod dry_run;
+pub mod ens;
 pub mod error;
+pub mod event_queue;
+pub mod gas_price;
+pub mod heads;
+pub mod history;
+pub mod http;
+pub mod http_client;
+pub mod journal;
+pub mod keystore;
+pub mod logging;
+pub mod maintenance;
+pub mod manifest;
+pub mod message_call;
 pub mod util;
 pub mod message_to_mainnet;
+pub mod metrics;
+pub mod named_pipe;
+pub mod network;
+pub mod otlp;
+pub mod parked;
+pub mod pacing;
+pub mod postgres_sink;
+pub mod prometheus;
+pub mod raw_transaction;
+pub mod rebuild;
+pub mod refund;
+pub mod registry;
+pub mod replay;
+pub mod retry;
+pub mod shard;
 pub mod signature;
+pub mod signer;
+pub mod sinks;
+pub mod sqlite;
+pub mod stats;
+pub mod status;
+pub mod supervisor;
+pub mod top;
+pub mod tls;
+pub mod transaction;
+pub mod transport;
+pub mod value_limits;
+pub mod websocket;
+
+// what an application that embeds the bridge needs (see `builder`)
+pub use app::App;
+pub use bridge::{create_bridge, create_bridge_backed_by, create_deposit_relay,
+                 create_withdraw_confirm, create_withdraw_relay, Bridge, BridgeBackend,
+                 BridgeChecked, DepositRelay, FileBackend, WithdrawConfirm, WithdrawRelay};
+pub use builder::BridgeBuilder;
+pub use config::Config;
+pub use database::Database;
+pub use event_queue::{TransferEvent, TransferStage};
@@ -0,0 +1,315 @@
+/// structured logging.
+/// the log is plain text by default. with `log_format = "json"` or `--log-format json`
+/// every line is a json object with `timestamp` (milliseconds since the unix epoch),
+/// `level`, `target` and `message`.
+/// every stage a transfer passes is logged as a relay event with the target `bridge::relay`:
+/// detected, signed, collected, relayed, mined or skipped.
+/// a relay event carries the `direction` of the transfer and the hash of the transaction
+/// on the source chain that started it as `correlation_id`, so filtering the log by it
+/// traces a deposit through `deposit_relay` or a withdraw through `withdraw_confirm`
+/// and `withdraw_relay`. in json the fields of a relay event are fields of its line.
+/// in a process that runs several bridges every line is tagged with the name of the bridge
+/// that logged it (`bridge` in json, see `supervisor`).
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{LogLevel, LogRecord};
+use serde_json::{self, Map, Value};
+use web3::types::{H256, TransactionReceipt, U256};
+use config::LogFormat;
+use error::Error;
+use history::TransferDirection;
+use supervisor;
+
+/// target of relay events
+pub const RELAY_TARGET: &str = "bridge::relay";
+
+/// set if the log is written as json
+static JSON: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// sets the format of the lines of the log
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::SeqCst);
+}
+
+/// returns true if the log is written as json
+pub fn is_json() -> bool {
+    JSON.load(Ordering::SeqCst)
+}
+
+/// parses the value of `--log-format`
+pub fn parse_format(format: &str) -> Result<LogFormat, Error> {
+    match format {
+        "text" => Ok(LogFormat::Text),
+        "json" => Ok(LogFormat::Json),
+        _ => bail!("unknown log format `{}`. expected `text` or `json`", format),
+    }
+}
+
+/// formats `record` as a line of the log in the format set by `set_format`
+pub fn format(record: &LogRecord) -> String {
+    let bridge = supervisor::current();
+    if !is_json() {
+        let prefix = bridge.map_or(String::new(), |bridge| format!("[{}] ", bridge));
+        return format!(
+            "{}:{}: {}{}",
+            record.level(),
+            record.location().module_path(),
+            prefix,
+            record.args()
+        );
+    }
+    format_json(
+        timestamp(),
+        bridge,
+        record.level(),
+        record.target(),
+        &record.args().to_string(),
+    )
+}
+
+/// milliseconds since the unix epoch
+fn timestamp() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is after the unix epoch; qed");
+    now.as_secs() * 1000 + u64::from(now.subsec_nanos() / 1_000_000)
+}
+
+/// formats a line of the json log. the message of a relay event is the json of its fields
+fn format_json(
+    timestamp: u64,
+    bridge: Option<String>,
+    level: LogLevel,
+    target: &str,
+    message: &str,
+) -> String {
+    let mut line = Map::new();
+    line.insert("timestamp".into(), timestamp.into());
+    if let Some(bridge) = bridge {
+        line.insert("bridge".into(), bridge.into());
+    }
+    line.insert("level".into(), level.to_string().into());
+    line.insert("target".into(), target.into());
+    let fields = if target == RELAY_TARGET {
+        serde_json::from_str::<Value>(message).ok()
+    } else {
+        None
+    };
+    match fields {
+        Some(Value::Object(fields)) => line.extend(fields),
+        _ => {
+            line.insert("message".into(), message.into());
+        }
+    }
+    Value::Object(line).to_string()
+}
+
+/// stage of a transfer reported by a relay event
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayStage {
+    /// the transfer was seen on the source chain
+    Detected,
+    /// this authority submitted its signature of a withdraw to `ForeignBridge`
+    Signed,
+    /// the signatures of a withdraw were collected by `ForeignBridge`
+    Collected,
+    /// this authority sent the relay transaction to the destination chain
+    Relayed,
+    /// a transaction of this authority for the transfer was mined
+    Mined,
+    /// the transfer was not relayed. `reason` tells why
+    Skipped,
+}
+
+impl RelayStage {
+    fn name(&self) -> &'static str {
+        match *self {
+            RelayStage::Detected => "detected",
+            RelayStage::Signed => "signed",
+            RelayStage::Collected => "collected",
+            RelayStage::Relayed => "relayed",
+            RelayStage::Mined => "mined",
+            RelayStage::Skipped => "skipped",
+        }
+    }
+}
+
+/// stage a transfer reached, logged with the target `RELAY_TARGET`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RelayEvent {
+    /// transaction of the deposit on home or of the withdraw on foreign
+    pub correlation_id: H256,
+    pub direction: TransferDirection,
+    pub stage: RelayStage,
+    /// transaction of this authority that signed or relayed the transfer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<H256>,
+    /// block the transaction of this authority was mined in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<U256>,
+    /// why the transfer was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl RelayEvent {
+    pub fn new(direction: TransferDirection, stage: RelayStage, correlation_id: H256) -> Self {
+        RelayEvent {
+            correlation_id,
+            direction,
+            stage,
+            transaction_hash: None,
+            block_number: None,
+            gas_used: None,
+            reason: None,
+        }
+    }
+
+    /// sets the transaction of this authority
+    pub fn transaction(mut self, hash: H256) -> Self {
+        self.transaction_hash = Some(hash);
+        self
+    }
+
+    /// sets the transaction of this authority and where it was mined
+    pub fn receipt(mut self, receipt: &TransactionReceipt) -> Self {
+        self.transaction_hash = Some(receipt.transaction_hash);
+        self.block_number = Some(receipt.block_number.low_u64());
+        self.gas_used = Some(receipt.gas_used);
+        self
+    }
+
+    pub fn reason<S: Into<String>>(mut self, reason: S) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// logs the event as json or as text
+    pub fn log(&self) {
+        if is_json() {
+            let fields = serde_json::to_string(self).expect("relay events serialize; qed");
+            info!(target: RELAY_TARGET, "{}", fields);
+        } else {
+            info!(target: RELAY_TARGET, "{}", self);
+        }
+    }
+}
+
+/// logs the outcome of the relay of the transfer started by `origin`.
+/// `relay` is `None` if the simulation of the relay reverted
+pub fn log_relay(direction: TransferDirection, origin: H256, relay: Option<H256>) {
+    let event = match relay {
+        Some(hash) => RelayEvent::new(direction, RelayStage::Relayed, origin).transaction(hash),
+        None => RelayEvent::new(direction, RelayStage::Skipped, origin)
+            .reason("the simulation of the relay reverted"),
+    };
+    event.log();
+}
+
+impl fmt::Display for RelayEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            TransferDirection::Deposit => "deposit",
+            TransferDirection::Withdraw => "withdraw",
+        };
+        write!(
+            f,
+            "{} {:?} {}",
+            direction,
+            self.correlation_id,
+            self.stage.name()
+        )?;
+        if let Some(hash) = self.transaction_hash {
+            write!(f, " by transaction {:?}", hash)?;
+        }
+        if let Some(block_number) = self.block_number {
+            write!(f, " in block {}", block_number)?;
+        }
+        if let Some(gas_used) = self.gas_used {
+            write!(f, " using {} gas", gas_used)?;
+        }
+        if let Some(ref reason) = self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::LogLevel;
+    use serde_json::{self, Value};
+    use config::LogFormat;
+    use history::TransferDirection;
+    use super::{format_json, parse_format, RelayEvent, RelayStage, RELAY_TARGET};
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(LogFormat::Text, parse_format("text").unwrap());
+        assert_eq!(LogFormat::Json, parse_format("json").unwrap());
+        assert!(parse_format("yaml").is_err());
+    }
+
+    #[test]
+    fn test_format_json() {
+        let line = format_json(
+            1_000,
+            None,
+            LogLevel::Info,
+            "bridge::bridge",
+            "deposit relay completed",
+        );
+        let expected = r#"{
+            "timestamp": 1000,
+            "level": "INFO",
+            "target": "bridge::bridge",
+            "message": "deposit relay completed"
+        }"#;
+        assert_eq!(
+            serde_json::from_str::<Value>(expected).unwrap(),
+            serde_json::from_str::<Value>(&line).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_json_relay_event() {
+        let event = RelayEvent::new(TransferDirection::Withdraw, RelayStage::Skipped, 1.into())
+            .reason("signatures don't verify");
+        let message = serde_json::to_string(&event).unwrap();
+        let line = format_json(
+            1_000,
+            Some("eth-poa".into()),
+            LogLevel::Info,
+            RELAY_TARGET,
+            &message,
+        );
+        let expected = r#"{
+            "timestamp": 1000,
+            "bridge": "eth-poa",
+            "level": "INFO",
+            "target": "bridge::relay",
+            "correlation_id": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "direction": "withdraw",
+            "stage": "skipped",
+            "reason": "signatures don't verify"
+        }"#;
+        assert_eq!(
+            serde_json::from_str::<Value>(expected).unwrap(),
+            serde_json::from_str::<Value>(&line).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_relay_event_display() {
+        let event = RelayEvent::new(TransferDirection::Deposit, RelayStage::Relayed, 1.into())
+            .transaction(2.into());
+        let text = event.to_string();
+        assert!(text.starts_with("deposit 0x"));
+        assert!(text.contains(" relayed by transaction 0x"));
+    }
+}
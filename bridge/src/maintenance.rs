@@ -0,0 +1,274 @@
+/// recurring maintenance windows of a chain (example: every saturday from 02:00 to 04:00 UTC).
+/// during a maintenance window the bridge keeps following events
+/// but defers sending transactions to the chain until the window is over.
+/// transactions that were already in flight when a window starts are completed.
+/// windows follow the time of the chain (see `clock`). they are widened by the skew tolerance
+/// on both ends so that transactions are deferred while either clock could be in the window.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use futures::{Async, Poll, Stream};
+use tokio_timer::{Interval, Timer};
+use clock;
+use error::Error;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+const SECONDS_PER_WEEK: u64 = 7 * SECONDS_PER_DAY;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// returns the weekday of the `day`th day after the unix epoch (a thursday)
+    fn from_days_since_epoch(day: u64) -> Weekday {
+        match (day + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+/// window that recurs at the same time of day on certain days of the week
+#[derive(Debug, PartialEq, Clone)]
+pub struct MaintenanceWindow {
+    /// days on which the window starts. every day if empty
+    pub days: Vec<Weekday>,
+    /// seconds after midnight UTC at which the window starts
+    pub start: u64,
+    pub duration: Duration,
+}
+
+impl MaintenanceWindow {
+    /// returns the end (seconds since the unix epoch) of the occurrence
+    /// of this window widened by `tolerance` that contains `time` or `None` if there is none
+    fn end_of_occurrence(&self, time: u64, tolerance: u64) -> Option<u64> {
+        let duration = self.duration.as_secs();
+        let today = time / SECONDS_PER_DAY;
+        // occurrences that started on previous days may not be over yet
+        // and the occurrence of tomorrow may already have begun
+        let max_days_back = (self.start + duration + tolerance) / SECONDS_PER_DAY;
+        (0..max_days_back + 2)
+            .filter_map(|back| (today + 1).checked_sub(back))
+            .filter(|day| {
+                self.days.is_empty() || self.days.contains(&Weekday::from_days_since_epoch(*day))
+            })
+            .map(|day| day * SECONDS_PER_DAY + self.start)
+            .filter(|start| {
+                start.saturating_sub(tolerance) <= time && time < start + duration + tolerance
+            })
+            .map(|start| start + duration + tolerance)
+            .max()
+    }
+}
+
+/// returns how long a maintenance window in `windows` widened by `tolerance`
+/// is still active at `now`. overlapping and adjoining windows count as one.
+/// zero if no window is active.
+pub fn remaining(windows: &[MaintenanceWindow], now: SystemTime, tolerance: Duration) -> Duration {
+    let tolerance = tolerance.as_secs();
+    let now = now.duration_since(UNIX_EPOCH)
+        .expect("system time is after the unix epoch; qed")
+        .as_secs();
+    let mut end = now;
+    // windows might cover the entire week
+    while end - now < SECONDS_PER_WEEK {
+        match windows
+            .iter()
+            .filter_map(|window| window.end_of_occurrence(end, tolerance))
+            .max()
+        {
+            Some(next) => end = next,
+            None => break,
+        }
+    }
+    Duration::from_secs(end - now)
+}
+
+/// creates a new `Deferred` which checks whether a window in `windows` is still active
+/// at the time of `chain` every `poll_interval`.
+pub fn deferred<S: Stream>(
+    stream: S,
+    chain: &'static str,
+    windows: Vec<MaintenanceWindow>,
+    skew_tolerance: Duration,
+    timer: &Timer,
+    poll_interval: Duration,
+) -> Deferred<S> {
+    Deferred {
+        stream,
+        chain,
+        windows,
+        skew_tolerance,
+        interval: timer.interval(poll_interval),
+        item: None,
+        active: false,
+    }
+}
+
+/// stream that holds back the items of `stream` while a maintenance window is active.
+/// `stream` is not polled until the held back item is released.
+pub struct Deferred<S: Stream> {
+    stream: S,
+    chain: &'static str,
+    windows: Vec<MaintenanceWindow>,
+    /// widens the windows on both ends
+    skew_tolerance: Duration,
+    interval: Interval,
+    item: Option<S::Item>,
+    /// whether a window was active when last checked
+    active: bool,
+}
+
+impl<S: Stream> Stream for Deferred<S>
+where
+    Error: From<S::Error>,
+{
+    type Item = S::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.item.is_none() {
+                self.item = Some(try_stream!(self.stream.poll()));
+            }
+
+            let remaining = remaining(&self.windows, clock::now(self.chain), self.skew_tolerance);
+            let active = remaining > Duration::from_secs(0);
+            if active && !self.active {
+                warn!(
+                    "maintenance window on {} is active. deferring transactions for {}s",
+                    self.chain,
+                    remaining.as_secs()
+                );
+            } else if !active && self.active {
+                info!("maintenance window on {} is over", self.chain);
+            }
+            self.active = active;
+
+            if !active {
+                return Ok(Async::Ready(self.item.take()));
+            }
+
+            let _ = try_stream!(self.interval.poll());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::{remaining, MaintenanceWindow, Weekday};
+
+    // 2018-01-06 00:00:00 UTC, a saturday
+    const SATURDAY: u64 = 1_515_196_800;
+    const HOUR: u64 = 3600;
+
+    fn remaining_at(windows: &[MaintenanceWindow], time: u64) -> u64 {
+        remaining(
+            windows,
+            UNIX_EPOCH + Duration::from_secs(time),
+            Duration::from_secs(0),
+        ).as_secs()
+    }
+
+    #[test]
+    fn test_weekday() {
+        assert_eq!(Weekday::Thursday, Weekday::from_days_since_epoch(0));
+        assert_eq!(
+            Weekday::Saturday,
+            Weekday::from_days_since_epoch(SATURDAY / 86_400)
+        );
+    }
+
+    #[test]
+    fn test_remaining() {
+        let windows = vec![
+            MaintenanceWindow {
+                days: vec![Weekday::Saturday],
+                start: 2 * HOUR,
+                duration: Duration::from_secs(2 * HOUR),
+            },
+        ];
+        assert_eq!(0, remaining_at(&windows, SATURDAY + HOUR));
+        assert_eq!(2 * HOUR, remaining_at(&windows, SATURDAY + 2 * HOUR));
+        assert_eq!(1, remaining_at(&windows, SATURDAY + 4 * HOUR - 1));
+        assert_eq!(0, remaining_at(&windows, SATURDAY + 4 * HOUR));
+        // sunday
+        assert_eq!(0, remaining_at(&windows, SATURDAY + 26 * HOUR));
+    }
+
+    #[test]
+    fn test_remaining_past_midnight() {
+        let windows = vec![
+            MaintenanceWindow {
+                days: vec![],
+                start: 23 * HOUR,
+                duration: Duration::from_secs(2 * HOUR),
+            },
+        ];
+        assert_eq!(2 * HOUR, remaining_at(&windows, SATURDAY + 23 * HOUR));
+        assert_eq!(HOUR, remaining_at(&windows, SATURDAY + 24 * HOUR));
+        assert_eq!(0, remaining_at(&windows, SATURDAY + 25 * HOUR));
+    }
+
+    #[test]
+    fn test_remaining_adjoining_windows() {
+        let windows = vec![
+            MaintenanceWindow {
+                days: vec![Weekday::Saturday],
+                start: 0,
+                duration: Duration::from_secs(HOUR),
+            },
+            MaintenanceWindow {
+                days: vec![Weekday::Saturday],
+                start: HOUR,
+                duration: Duration::from_secs(HOUR),
+            },
+        ];
+        assert_eq!(2 * HOUR, remaining_at(&windows, SATURDAY));
+    }
+
+    #[test]
+    fn test_remaining_entire_week() {
+        let windows = vec![
+            MaintenanceWindow {
+                days: vec![],
+                start: 0,
+                duration: Duration::from_secs(24 * HOUR),
+            },
+        ];
+        assert!(remaining_at(&windows, SATURDAY) >= 7 * 24 * HOUR);
+    }
+
+    #[test]
+    fn test_remaining_with_tolerance() {
+        let windows = vec![
+            MaintenanceWindow {
+                days: vec![Weekday::Sunday],
+                start: 0,
+                duration: Duration::from_secs(HOUR),
+            },
+        ];
+        let tolerance = Duration::from_secs(60);
+        let remaining_at = |time| {
+            remaining(&windows, UNIX_EPOCH + Duration::from_secs(time), tolerance).as_secs()
+        };
+        // saturday shortly before midnight
+        assert_eq!(0, remaining_at(SATURDAY + 24 * HOUR - 61));
+        assert_eq!(HOUR + 120, remaining_at(SATURDAY + 24 * HOUR - 60));
+        assert_eq!(60, remaining_at(SATURDAY + 25 * HOUR));
+        assert_eq!(0, remaining_at(SATURDAY + 25 * HOUR + 60));
+    }
+}
@@ -0,0 +1,480 @@
+/// deployment manifests (`deployment.toml`) written by `parity-bridge-deploy`.
+/// a manifest records for the contract on each chain its name and address,
+/// the transaction and block it was deployed in, the keccak256 hash of the compiled bytecode
+/// it was created with, the keccak256 hash of its code on chain and its constructor arguments.
+/// with `deployment_manifest` configured a missing database is created from the manifest
+/// and the bridge only starts once `verify` confirmed the deployment against the chains,
+/// the compiled contracts of `home.contract` and `foreign.contract` and the config.
+/// `parity-bridge-deploy verify` runs the same verification on its own.
+
+use std::{fmt, fs, io, str};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+use rustc_hex::FromHex;
+use tiny_keccak::keccak256;
+use tokio_core::reactor::Core;
+use toml;
+use web3::Transport;
+use web3::types::{Address, Bytes, H256};
+use api;
+use app::App;
+use bridge::{foreign_contract_name, foreign_creation_code, home_contract_name,
+             home_creation_code, DeployedContract};
+use database::Database;
+use error::{Error, ErrorKind, ResultExt};
+
+/// deployment of the bridge contracts on both chains
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct Manifest {
+    /// version of `parity-bridge-deploy` that deployed the contracts
+    pub bridge_version: String,
+    /// commit of `parity-bridge-deploy` that deployed the contracts
+    pub commit_hash: String,
+    pub home: ContractDeployment,
+    pub foreign: ContractDeployment,
+}
+
+/// deployment of a bridge contract on one chain
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct ContractDeployment {
+    pub contract_name: String,
+    pub contract_address: Address,
+    /// transaction that created the contract
+    pub transaction_hash: H256,
+    /// block the contract was created in
+    pub block_number: u64,
+    /// keccak256 hash of the compiled bytecode the contract was created with
+    pub bytecode_hash: H256,
+    /// keccak256 hash of the code of the contract on chain
+    pub code_hash: H256,
+    /// abi encoded arguments of the constructor
+    pub constructor_arguments: Bytes,
+}
+
+impl ContractDeployment {
+    /// records the deployment of `contract` whose code on chain is `code`
+    pub fn new(contract: &DeployedContract, code: &Bytes) -> Result<Self, Error> {
+        let bytecode: Vec<u8> = contract.bytecode_hex.from_hex()?;
+        let creation_code: Vec<u8> = contract.contract_creation_code_hex.from_hex()?;
+        Ok(ContractDeployment {
+            contract_name: contract.contract_name.clone(),
+            contract_address: contract
+                .receipt
+                .contract_address
+                .expect("contract creation receipt must have an address; qed"),
+            transaction_hash: contract.receipt.transaction_hash,
+            block_number: contract.receipt.block_number.low_u64(),
+            bytecode_hash: keccak256(&bytecode).into(),
+            code_hash: keccak256(&code.0).into(),
+            constructor_arguments: creation_code[bytecode.len()..].to_vec().into(),
+        })
+    }
+}
+
+impl str::FromStr for Manifest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).chain_err(|| "Cannot parse deployment manifest")
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&toml::to_string(self).expect("serialization can't fail; qed"))
+    }
+}
+
+impl Manifest {
+    /// records the deployments of `home` and `foreign` together with their code on chain
+    pub fn from_deployments<T: Transport>(
+        event_loop: &mut Core,
+        app: &App<T>,
+        home: &DeployedContract,
+        foreign: &DeployedContract,
+    ) -> Result<Self, Error> {
+        let home = record(
+            event_loop,
+            app,
+            &app.connections.home,
+            app.config.home.request_timeout,
+            home,
+        )?;
+        let foreign = record(
+            event_loop,
+            app,
+            &app.connections.foreign,
+            app.config.foreign.request_timeout,
+            foreign,
+        )?;
+        Ok(Manifest {
+            bridge_version: env!("CARGO_PKG_VERSION").into(),
+            commit_hash: env!("GIT_HASH").into(),
+            home,
+            foreign,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(ErrorKind::MissingFile(format!("{:?}", path.as_ref())).into())
+            }
+            Err(err) => return Err(err).chain_err(|| "Cannot open deployment manifest"),
+        };
+
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)?;
+        buffer.parse()
+    }
+
+    pub fn save<W: Write>(&self, mut write: W) -> Result<(), Error> {
+        write.write_all(self.to_string().as_bytes())?;
+        Ok(())
+    }
+
+    /// returns the database of a bridge that starts at the deployment
+    pub fn database(&self) -> Database {
+        Database {
+            home_contract_address: self.home.contract_address,
+            foreign_contract_address: self.foreign.contract_address,
+            home_deploy: self.home.block_number,
+            foreign_deploy: self.foreign.block_number,
+            checked_deposit_relay: self.home.block_number,
+            checked_withdraw_relay: self.foreign.block_number,
+            checked_withdraw_confirm: self.foreign.block_number,
+            checked_unaccounted_transfers: self.home.block_number,
+            checked_call_confirm: self.foreign.block_number,
+            checked_call_relay: self.foreign.block_number,
+            ..Database::default()
+        }
+    }
+
+    /// fails if `database` belongs to another deployment
+    pub fn check_database(&self, database: &Database) -> Result<(), Error> {
+        if database.home_contract_address != self.home.contract_address
+            || database.foreign_contract_address != self.foreign.contract_address
+        {
+            bail!(
+                "database is of another deployment (home: {:?}, foreign: {:?}) than the manifest \
+                 (home: {:?}, foreign: {:?})",
+                database.home_contract_address,
+                database.foreign_contract_address,
+                self.home.contract_address,
+                self.foreign.contract_address
+            );
+        }
+        Ok(())
+    }
+}
+
+/// records the deployment of `contract` together with its code on the chain of `transport`
+fn record<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    transport: &T,
+    request_timeout: Duration,
+    contract: &DeployedContract,
+) -> Result<ContractDeployment, Error> {
+    let address = contract
+        .receipt
+        .contract_address
+        .expect("contract creation receipt must have an address; qed");
+    let code = event_loop.run(app.timer.timeout(api::code(transport, address), request_timeout))?;
+    ContractDeployment::new(contract, &code)
+}
+
+/// what a chain reveals about the deployment of a contract
+#[derive(Debug, Clone)]
+struct OnChain {
+    /// data of the transaction of the deployment. `None` if the node doesn't know it
+    input: Option<Bytes>,
+    /// contract created by the transaction. `None` if it isn't mined
+    contract_address: Option<Address>,
+    /// block the transaction was mined in. `None` if it isn't mined
+    block_number: Option<u64>,
+    /// code at the address of the deployment
+    code: Bytes,
+}
+
+/// fetches what the chain of `transport` reveals about `deployment`
+fn fetch<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    transport: &T,
+    request_timeout: Duration,
+    deployment: &ContractDeployment,
+) -> Result<OnChain, Error> {
+    let transaction = event_loop.run(app.timer.timeout(
+        api::transaction(transport, deployment.transaction_hash),
+        request_timeout,
+    ))?;
+    let receipt = event_loop.run(app.timer.timeout(
+        api::transaction_receipt(transport, deployment.transaction_hash),
+        request_timeout,
+    ))?;
+    let code = event_loop.run(app.timer.timeout(
+        api::code(transport, deployment.contract_address),
+        request_timeout,
+    ))?;
+    Ok(OnChain {
+        input: transaction.map(|transaction| transaction.input),
+        contract_address: receipt
+            .as_ref()
+            .and_then(|receipt| receipt.contract_address),
+        block_number: receipt.map(|receipt| receipt.block_number.low_u64()),
+        code,
+    })
+}
+
+/// compares `deployment` on `chain` with what the chain reveals about it
+/// and with the contract `contract_name` created with `creation_code`:
+/// `bytecode_len` bytes of compiled bytecode followed by the constructor arguments of the config
+fn compare(
+    chain: &str,
+    contract_name: &str,
+    creation_code: &[u8],
+    bytecode_len: usize,
+    deployment: &ContractDeployment,
+    on_chain: &OnChain,
+) -> Result<(), Error> {
+    if deployment.contract_name != contract_name {
+        bail!(
+            "{}: the manifest deployed {} but the config bridges with {}",
+            chain,
+            deployment.contract_name,
+            contract_name
+        );
+    }
+    let (bytecode, constructor_arguments) = creation_code.split_at(bytecode_len);
+    if H256::from(keccak256(bytecode)) != deployment.bytecode_hash {
+        bail!(
+            "{}: the bytecode of `{}.contract` differs from the bytecode {} was deployed with",
+            chain,
+            chain,
+            contract_name
+        );
+    }
+    if constructor_arguments != &deployment.constructor_arguments.0[..] {
+        bail!(
+            "{}: the constructor arguments of the config differ from those {} was deployed with. \
+             check `authorities`, `estimated_gas_cost_of_withdraw` and the limits of the config",
+            chain,
+            contract_name
+        );
+    }
+    match on_chain.input {
+        Some(ref input) if &input.0[..] == creation_code => {}
+        Some(_) => bail!(
+            "{}: transaction {:?} didn't deploy {} with the bytecode and constructor arguments \
+             of the manifest",
+            chain,
+            deployment.transaction_hash,
+            contract_name
+        ),
+        None => bail!(
+            "{}: the node doesn't know transaction {:?} that deployed {}",
+            chain,
+            deployment.transaction_hash,
+            contract_name
+        ),
+    }
+    if on_chain.contract_address != Some(deployment.contract_address) {
+        bail!(
+            "{}: transaction {:?} didn't create {} at {:?}",
+            chain,
+            deployment.transaction_hash,
+            contract_name,
+            deployment.contract_address
+        );
+    }
+    if on_chain.block_number != Some(deployment.block_number) {
+        bail!(
+            "{}: transaction {:?} wasn't mined in block {}",
+            chain,
+            deployment.transaction_hash,
+            deployment.block_number
+        );
+    }
+    if on_chain.code.0.is_empty() {
+        bail!(
+            "{}: there is no contract at {:?}",
+            chain,
+            deployment.contract_address
+        );
+    }
+    if H256::from(keccak256(&on_chain.code.0)) != deployment.code_hash {
+        bail!(
+            "{}: the code at {:?} differs from the code of the manifest",
+            chain,
+            deployment.contract_address
+        );
+    }
+    Ok(())
+}
+
+/// verifies the deployments of `manifest` against both chains,
+/// the compiled contracts of `home.contract` and `foreign.contract` and the config of `app`
+pub fn verify<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    manifest: &Manifest,
+) -> Result<(), Error> {
+    let home = fetch(
+        event_loop,
+        app,
+        &app.connections.home,
+        app.config.home.request_timeout,
+        &manifest.home,
+    ).chain_err(|| "Cannot fetch the deployment on home")?;
+    compare(
+        "home",
+        home_contract_name(app),
+        &home_creation_code(app),
+        app.config.home.contract.bin.0.len(),
+        &manifest.home,
+        &home,
+    )?;
+    info!(
+        "verified {} at {:?} on home",
+        manifest.home.contract_name, manifest.home.contract_address
+    );
+
+    let foreign = fetch(
+        event_loop,
+        app,
+        &app.connections.foreign,
+        app.config.foreign.request_timeout,
+        &manifest.foreign,
+    ).chain_err(|| "Cannot fetch the deployment on foreign")?;
+    compare(
+        "foreign",
+        foreign_contract_name(app),
+        &foreign_creation_code(app),
+        app.config.foreign.contract.bin.0.len(),
+        &manifest.foreign,
+        &foreign,
+    )?;
+    info!(
+        "verified {} at {:?} on foreign",
+        manifest.foreign.contract_name, manifest.foreign.contract_address
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tiny_keccak::keccak256;
+    use database::Database;
+    use super::{compare, ContractDeployment, Manifest, OnChain};
+
+    fn deployment() -> ContractDeployment {
+        ContractDeployment {
+            contract_name: "HomeBridge".into(),
+            contract_address: 0x10.into(),
+            transaction_hash: 0x20.into(),
+            block_number: 100,
+            bytecode_hash: keccak256(&[0x60, 0x80]).into(),
+            code_hash: keccak256(&[0x60, 0x01]).into(),
+            constructor_arguments: vec![0x01, 0x02].into(),
+        }
+    }
+
+    fn on_chain() -> OnChain {
+        OnChain {
+            input: Some(vec![0x60, 0x80, 0x01, 0x02].into()),
+            contract_address: Some(0x10.into()),
+            block_number: Some(100),
+            code: vec![0x60, 0x01].into(),
+        }
+    }
+
+    #[test]
+    fn manifest_to_and_from_str() {
+        let toml = r#"bridge_version = "0.4.0"
+commit_hash = "abcdef"
+
+[home]
+contract_name = "HomeBridge"
+contract_address = "0x0000000000000000000000000000000000000010"
+transaction_hash = "0x0000000000000000000000000000000000000000000000000000000000000020"
+block_number = 100
+bytecode_hash = "0x0000000000000000000000000000000000000000000000000000000000000001"
+code_hash = "0x0000000000000000000000000000000000000000000000000000000000000002"
+constructor_arguments = "0x0102"
+
+[foreign]
+contract_name = "ForeignBridge"
+contract_address = "0x0000000000000000000000000000000000000011"
+transaction_hash = "0x0000000000000000000000000000000000000000000000000000000000000021"
+block_number = 200
+bytecode_hash = "0x0000000000000000000000000000000000000000000000000000000000000003"
+code_hash = "0x0000000000000000000000000000000000000000000000000000000000000004"
+constructor_arguments = "0x03"
+"#;
+        let manifest: Manifest = toml.parse().unwrap();
+        assert_eq!(100, manifest.home.block_number);
+        assert_eq!(vec![0x03], manifest.foreign.constructor_arguments.0);
+        assert_eq!(manifest, manifest.to_string().parse().unwrap());
+
+        let database = manifest.database();
+        assert_eq!(
+            Database {
+                home_contract_address: 0x10.into(),
+                foreign_contract_address: 0x11.into(),
+                home_deploy: 100,
+                foreign_deploy: 200,
+                checked_deposit_relay: 100,
+                checked_withdraw_relay: 200,
+                checked_withdraw_confirm: 200,
+                checked_unaccounted_transfers: 100,
+                checked_call_confirm: 200,
+                checked_call_relay: 200,
+                ..Database::default()
+            },
+            database
+        );
+        assert!(manifest.check_database(&database).is_ok());
+        assert!(manifest.check_database(&Database::default()).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let creation_code = [0x60, 0x80, 0x01, 0x02];
+        let compare = |name: &str, creation_code: &[u8], on_chain: &OnChain| {
+            compare("home", name, creation_code, 2, &deployment(), on_chain)
+        };
+        assert!(compare("HomeBridge", &creation_code, &on_chain()).is_ok());
+        assert!(compare("HomeTokenBridge", &creation_code, &on_chain()).is_err());
+        // other bytecode
+        assert!(compare("HomeBridge", &[0x60, 0x81, 0x01, 0x02], &on_chain()).is_err());
+        // other constructor arguments
+        assert!(compare("HomeBridge", &[0x60, 0x80, 0x01, 0x03], &on_chain()).is_err());
+
+        let mut unknown = on_chain();
+        unknown.input = None;
+        assert!(compare("HomeBridge", &creation_code, &unknown).is_err());
+
+        let mut other_input = on_chain();
+        other_input.input = Some(vec![0x60, 0x80, 0x01].into());
+        assert!(compare("HomeBridge", &creation_code, &other_input).is_err());
+
+        let mut other_block = on_chain();
+        other_block.block_number = Some(101);
+        assert!(compare("HomeBridge", &creation_code, &other_block).is_err());
+
+        let mut no_code = on_chain();
+        no_code.code = vec![].into();
+        assert!(compare("HomeBridge", &creation_code, &no_code).is_err());
+
+        let mut other_code = on_chain();
+        other_code.code = vec![0x60, 0x02].into();
+        let err = compare("HomeBridge", &creation_code, &other_code).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("differs from the code of the manifest")
+        );
+    }
+}
@@ -0,0 +1,310 @@
+/// calls relayed from `foreign` to `home` ("generalized message bridge").
+/// `ForeignBridge.requestCall` burns the relay cost from the sender and logs `UserRequestForCall`.
+/// the authorities sign a `MessageToMainnetCall` for it and submit their signatures
+/// via `ForeignBridge.submitCallSignature` (see `bridge::call_confirm`).
+/// once `ForeignBridge` collected enough of them the responsible authority relays the call
+/// to `HomeBridge.executeCall` (see `bridge::call_relay`), which calls the recipient
+/// with the data and the gas limit of the request.
+/// the recipient can authenticate the sender on `foreign` via `HomeBridge.messageSender`.
+/// only the ether bridge supports calls.
+use ethereum_types::{Address, H256, U256};
+use contracts::foreign::events::UserRequestForCall;
+use config::MessageCallsConfig;
+use web3::types::Log;
+use ethabi;
+use error::Error;
+
+/// first byte of every `MessageToMainnetCall.to_bytes()`.
+/// `HomeBridge.executeCall` and `ForeignBridge.submitCallSignature` refuse messages
+/// that begin with another byte. withdraw messages of version 2 begin with their version `0x02`
+/// and legacy withdraw messages are too short to be taken for a call
+pub const CALL_MESSAGE_TAG: u8 = 0x80;
+
+/// length of the tag and the fields of a `MessageToMainnetCall.to_bytes()` that precede its data
+pub const CALL_MESSAGE_HEADER_LENGTH: usize = 157;
+
+/// calls must carry at least the 4 byte selector of the function they call
+pub const MIN_CALL_DATA_LENGTH: usize = 4;
+
+/// the message of a call that is relayed from side to main.
+/// validators sign off on this message.
+#[derive(PartialEq, Debug, Clone)]
+pub struct MessageToMainnetCall {
+    /// contract on `home` that is called
+    pub recipient: Address,
+    /// account on `foreign` that requested the call
+    pub sender: Address,
+    pub sidenet_transaction_hash: H256,
+    /// gas `HomeBridge.executeCall` passes to the call
+    pub gas_limit: U256,
+    pub mainnet_gas_price: U256,
+    /// address of the `HomeBridge` the message is meant for.
+    /// `HomeBridge.executeCall` rejects messages that are meant for another deployment
+    pub mainnet_bridge_address: Address,
+    /// data of the call. begins with the selector of the called function
+    pub data: Vec<u8>,
+}
+
+impl MessageToMainnetCall {
+    /// parses message from a byte slice.
+    /// panics if `bytes` is shorter than `CALL_MESSAGE_HEADER_LENGTH`. ignores the tag.
+    /// use `try_from_bytes` for messages that weren't created by this process
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            recipient: bytes[1..21].into(),
+            sender: bytes[21..41].into(),
+            sidenet_transaction_hash: bytes[41..73].into(),
+            gas_limit: U256::from_big_endian(&bytes[73..105]),
+            mainnet_gas_price: U256::from_big_endian(&bytes[105..137]),
+            mainnet_bridge_address: bytes[137..CALL_MESSAGE_HEADER_LENGTH].into(),
+            data: bytes[CALL_MESSAGE_HEADER_LENGTH..].to_vec(),
+        }
+    }
+
+    /// parses message from a byte slice.
+    /// fails if `bytes` doesn't begin with `CALL_MESSAGE_TAG`,
+    /// is too short to carry a function selector or a field can't be part of a call
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < CALL_MESSAGE_HEADER_LENGTH + MIN_CALL_DATA_LENGTH {
+            bail!(
+                "call message must be at least {} bytes long but is {}",
+                CALL_MESSAGE_HEADER_LENGTH + MIN_CALL_DATA_LENGTH,
+                bytes.len()
+            );
+        }
+        if bytes[0] != CALL_MESSAGE_TAG {
+            bail!(
+                "call message must begin with {:#04x} but begins with {:#04x}",
+                CALL_MESSAGE_TAG,
+                bytes[0]
+            );
+        }
+        let message = Self::from_bytes(bytes);
+        if message.recipient.is_zero() {
+            bail!("call message has no recipient");
+        }
+        if message.sidenet_transaction_hash.is_zero() {
+            bail!("call message has no sidenet transaction hash");
+        }
+        if message.mainnet_bridge_address.is_zero() {
+            bail!("call message isn't bound to a HomeBridge");
+        }
+        if message.gas_limit > u64::max_value().into() {
+            bail!("call message has a gas limit that doesn't fit into 64 bits");
+        }
+        Ok(message)
+    }
+
+    /// construct a message from a `UserRequestForCall` event that was logged on `foreign`
+    /// that is meant for the `HomeBridge` at `mainnet_bridge_address`
+    pub fn from_log(web3_log: Log, mainnet_bridge_address: Address) -> Result<Self, Error> {
+        let ethabi_raw_log = ethabi::RawLog {
+            topics: web3_log.topics,
+            data: web3_log.data.0,
+        };
+        let request_log = UserRequestForCall::default().parse_log(ethabi_raw_log)?;
+        let hash = web3_log
+            .transaction_hash
+            .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
+        Ok(Self {
+            recipient: request_log.recipient,
+            sender: request_log.sender,
+            sidenet_transaction_hash: hash,
+            gas_limit: request_log.gas_limit,
+            mainnet_gas_price: request_log.home_gas_price,
+            mainnet_bridge_address,
+            data: request_log.data,
+        })
+    }
+
+    /// fails if the call exceeds the gas limit or the length of data
+    /// this authority signs and relays calls up to
+    pub fn check_limits(&self, config: &MessageCallsConfig) -> Result<(), Error> {
+        if self.gas_limit > config.max_gas_limit.into() {
+            bail!(
+                "gas limit {} exceeds `message_calls.max_gas_limit` {}",
+                self.gas_limit,
+                config.max_gas_limit
+            );
+        }
+        if self.data.len() > config.max_data_length {
+            bail!(
+                "{} bytes of data exceed `message_calls.max_data_length` {}",
+                self.data.len(),
+                config.max_data_length
+            );
+        }
+        Ok(())
+    }
+
+    /// serializes message to a byte vector.
+    /// mainly used to construct the message byte vector that is then signed
+    /// and passed to `ForeignBridge.submitCallSignature`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![0u8; CALL_MESSAGE_HEADER_LENGTH];
+        result[0] = CALL_MESSAGE_TAG;
+        result[1..21].copy_from_slice(&self.recipient.0[..]);
+        result[21..41].copy_from_slice(&self.sender.0[..]);
+        result[41..73].copy_from_slice(&self.sidenet_transaction_hash.0[..]);
+        self.gas_limit.to_big_endian(&mut result[73..105]);
+        self.mainnet_gas_price.to_big_endian(&mut result[105..137]);
+        result[137..CALL_MESSAGE_HEADER_LENGTH]
+            .copy_from_slice(&self.mainnet_bridge_address.0[..]);
+        result.extend_from_slice(&self.data);
+        result
+    }
+
+    /// serializes message to an ethabi payload
+    pub fn to_payload(&self) -> Vec<u8> {
+        ethabi::encode(&[ethabi::Token::Bytes(self.to_bytes())])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethabi::{self, Token};
+    use quickcheck::TestResult;
+    use rustc_hex::FromHex;
+    use tiny_keccak::keccak256;
+    use web3::types::Log;
+    use config::MessageCallsConfig;
+    use super::*;
+
+    fn message() -> MessageToMainnetCall {
+        MessageToMainnetCall {
+            recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+            sender: "0x0000000000000000000000000000000000000011".into(),
+            sidenet_transaction_hash:
+                "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            gas_limit: 100_000.into(),
+            mainnet_gas_price: 8000000000u64.into(),
+            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            data: vec![0xa9, 0x05, 0x9c, 0xbb, 0x01],
+        }
+    }
+
+    #[test]
+    fn test_message_to_mainnet_call_to_bytes() {
+        assert_eq!(message().to_bytes(), "80eac4a655451e159313c3641e29824e77d6fcb0ce000000000000000000000000000000000000001175ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e200000000000000000000000000000000000000000000000000000000000186a000000000000000000000000000000000000000000000000000000001dcd6500049edf201c1e139282643d5e7c6fb0c7219ad1db7a9059cbb01".from_hex().unwrap())
+    }
+
+    #[test]
+    fn test_message_to_mainnet_call_try_from_bytes() {
+        let message = message();
+        let bytes = message.to_bytes();
+        assert_eq!(message, MessageToMainnetCall::from_bytes(&bytes));
+        assert_eq!(message, MessageToMainnetCall::try_from_bytes(&bytes).unwrap());
+        assert!(
+            MessageToMainnetCall::try_from_bytes(&bytes[..CALL_MESSAGE_HEADER_LENGTH]).is_err()
+        );
+        assert!(MessageToMainnetCall::try_from_bytes(&[]).is_err());
+
+        let mut untagged = bytes.clone();
+        untagged[0] = 0x02;
+        assert!(MessageToMainnetCall::try_from_bytes(&untagged).is_err());
+
+        let mut unbound = bytes.clone();
+        for byte in &mut unbound[137..CALL_MESSAGE_HEADER_LENGTH] {
+            *byte = 0;
+        }
+        assert!(MessageToMainnetCall::try_from_bytes(&unbound).is_err());
+
+        let mut no_recipient = bytes.clone();
+        for byte in &mut no_recipient[1..21] {
+            *byte = 0;
+        }
+        assert!(MessageToMainnetCall::try_from_bytes(&no_recipient).is_err());
+
+        let mut too_large_gas_limit = bytes;
+        too_large_gas_limit[73] = 1;
+        assert!(MessageToMainnetCall::try_from_bytes(&too_large_gas_limit).is_err());
+    }
+
+    #[test]
+    fn test_message_to_mainnet_call_from_log() {
+        let expected = message();
+        let signature = "UserRequestForCall(address,address,bytes,uint256,uint256)";
+        let data = ethabi::encode(&[
+            Token::Address(expected.sender),
+            Token::Address(expected.recipient),
+            Token::Bytes(expected.data.clone()),
+            Token::Uint(expected.gas_limit),
+            Token::Uint(expected.mainnet_gas_price),
+        ]);
+        let log = Log {
+            data: data.into(),
+            topics: vec![keccak256(signature.as_bytes()).into()],
+            transaction_hash: Some(expected.sidenet_transaction_hash),
+            ..Default::default()
+        };
+        let message =
+            MessageToMainnetCall::from_log(log.clone(), expected.mainnet_bridge_address).unwrap();
+        assert_eq!(expected, message);
+
+        let unmined = Log {
+            transaction_hash: None,
+            ..log
+        };
+        assert!(MessageToMainnetCall::from_log(unmined, expected.mainnet_bridge_address).is_err());
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let config = MessageCallsConfig {
+            confirm_gas: 300_000,
+            relay_gas: 200_000,
+            max_gas_limit: 100_000,
+            max_data_length: 5,
+        };
+        let message = message();
+        assert!(message.check_limits(&config).is_ok());
+        let expensive = MessageToMainnetCall {
+            gas_limit: 100_001.into(),
+            ..message.clone()
+        };
+        assert!(expensive.check_limits(&config).is_err());
+        let long = MessageToMainnetCall {
+            data: vec![0; 6],
+            ..message
+        };
+        assert!(long.check_limits(&config).is_err());
+    }
+
+    quickcheck! {
+        fn quickcheck_message_to_mainnet_call_roundtrips_to_bytes(
+            recipient_raw: Vec<u8>,
+            sender_raw: Vec<u8>,
+            transaction_hash_raw: Vec<u8>,
+            gas_limit: u64,
+            mainnet_gas_price_raw: u64,
+            data: Vec<u8>
+        ) -> TestResult {
+            if recipient_raw.len() != 20 || sender_raw.len() != 20
+                || transaction_hash_raw.len() != 32
+            {
+                return TestResult::discard();
+            }
+
+            let message = MessageToMainnetCall {
+                recipient: recipient_raw.as_slice().into(),
+                sender: sender_raw.as_slice().into(),
+                sidenet_transaction_hash: transaction_hash_raw.as_slice().into(),
+                gas_limit: gas_limit.into(),
+                mainnet_gas_price: mainnet_gas_price_raw.into(),
+                mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                data,
+            };
+
+            let bytes = message.to_bytes();
+            assert_eq!(message, MessageToMainnetCall::from_bytes(bytes.as_slice()));
+
+            let payload = message.to_payload();
+            let mut tokens = ethabi::decode(&[ethabi::ParamType::Bytes], payload.as_slice())
+                .unwrap();
+            let decoded = tokens.pop().unwrap().to_bytes().unwrap();
+            assert_eq!(message, MessageToMainnetCall::from_bytes(decoded.as_slice()));
+
+            TestResult::passed()
+        }
+    }
+}
@@ -1,5 +1,12 @@
+use std::{fmt, str};
 use ethereum_types::{Address, H256, U256};
 use contracts::foreign::events::Withdraw;
+#[cfg(feature = "contracts-v2")]
+use contracts::foreign_token::events::TransferToHome;
+#[cfg(feature = "contracts-v2")]
+use config::Tokens;
+use rustc_hex::{FromHex, ToHex};
+use serde::de::{self, Deserialize, Deserializer};
 use web3::types::Log;
 use ethabi;
 use error::Error;
@@ -7,32 +14,227 @@ use error::Error;
 /// the message that is relayed from side to main.
 /// contains all the information required for the relay.
 /// validators sign off on this message.
-#[derive(PartialEq, Debug)]
+///
+/// serializes to an object with `0x`-prefixed hex addresses and hashes
+/// and decimal strings for `value` and `mainnet_gas_price`.
+/// `Display` and `FromStr` use the `0x`-prefixed hex encoding of `to_bytes` instead
+#[derive(PartialEq, Debug, Serialize)]
 pub struct MessageToMainnet {
     pub recipient: Address,
+    #[serde(serialize_with = "decimal::serialize")]
     pub value: U256,
     pub sidenet_transaction_hash: H256,
+    #[serde(serialize_with = "decimal::serialize")]
     pub mainnet_gas_price: U256,
+    /// address of the `HomeBridge` the message is meant for.
+    /// `HomeBridge.withdraw` rejects messages that are meant for another deployment.
+    /// zero for messages of the baseline format, which aren't bound to a `HomeBridge`
+    pub mainnet_bridge_address: Address,
+    /// chain ids of home and foreign the message is meant for.
+    /// `None` for messages of the legacy format, which has no version byte
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_ids: Option<ChainIds>,
 }
 
-/// length of a `MessageToMainnet.to_bytes()` in bytes
-pub const MESSAGE_LENGTH: usize = 116;
+/// chain ids of the chains a version 2 message is meant for
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainIds {
+    pub home: u64,
+    pub foreign: u64,
+}
+
+impl ChainIds {
+    /// whether these are the chain ids `home` and `foreign`.
+    /// a chain id that isn't known matches any
+    pub fn matches(&self, home: Option<u64>, foreign: Option<u64>) -> bool {
+        home.map_or(true, |home| home == self.home)
+            && foreign.map_or(true, |foreign| foreign == self.foreign)
+    }
+}
+
+/// length of a message of the baseline format in bytes: the fields of the legacy format
+/// without the address of the `HomeBridge`. such messages were signed before messages were
+/// bound to a `HomeBridge` and are parsed to inspect them, but the contracts refuse them
+/// since their signatures would be valid for every deployment of the same authorities
+pub const BASELINE_MESSAGE_LENGTH: usize = 116;
+
+/// length of a `MessageToMainnet.to_bytes()` of the legacy format in bytes
+pub const MESSAGE_LENGTH: usize = BASELINE_MESSAGE_LENGTH + 20;
+
+/// length of a `TokenMessageToMainnet.to_bytes()` of the legacy format in bytes
+pub const TOKEN_MESSAGE_LENGTH: usize = MESSAGE_LENGTH + 20;
+
+/// first byte of messages of the version 2 format
+pub const MESSAGE_VERSION: u8 = 2;
+
+/// length of the version byte and the chain ids that precede the fields of version 2 messages
+const MESSAGE_V2_PREFIX_LENGTH: usize = 1 + 32 + 32;
+
+/// length of a version 2 `MessageToMainnet.to_bytes()` in bytes
+pub const MESSAGE_V2_LENGTH: usize = MESSAGE_V2_PREFIX_LENGTH + MESSAGE_LENGTH;
+
+/// length of a version 2 `TokenMessageToMainnet.to_bytes()` in bytes
+pub const TOKEN_MESSAGE_V2_LENGTH: usize = MESSAGE_V2_LENGTH + 20;
+
+/// `U256` as decimal string.
+/// the default serialization of `U256` is a hex string
+mod decimal {
+    use ethereum_types::U256;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if s.is_empty() || !s.chars().all(|c| c.is_digit(10)) {
+            return Err(D::Error::custom(format!("`{}` is not a decimal number", s)));
+        }
+        U256::from_dec_str(&s)
+            .map_err(|_| D::Error::custom(format!("`{}` exceeds the maximum of 2^256 - 1", s)))
+    }
+}
+
+/// decodes `s` from hex with optional `0x` prefix
+fn from_hex(s: &str) -> Result<Vec<u8>, Error> {
+    let hex = if s.starts_with("0x") { &s[2..] } else { s };
+    Ok(hex.from_hex()?)
+}
+
+/// returns the version of the message `bytes`: `None` for the baseline and the legacy format.
+/// they have no version byte, so they're told apart by their length
+fn message_version(bytes: &[u8]) -> Result<Option<u8>, Error> {
+    match bytes.len() {
+        BASELINE_MESSAGE_LENGTH | MESSAGE_LENGTH | TOKEN_MESSAGE_LENGTH => Ok(None),
+        MESSAGE_V2_LENGTH | TOKEN_MESSAGE_V2_LENGTH if bytes[0] == MESSAGE_VERSION => {
+            Ok(Some(MESSAGE_VERSION))
+        }
+        MESSAGE_V2_LENGTH | TOKEN_MESSAGE_V2_LENGTH => {
+            bail!("message has unsupported version {}", bytes[0])
+        }
+        length => bail!(
+            "message must be {} bytes long (baseline), {} bytes long (legacy) or {} bytes long (version {}) but is {}",
+            BASELINE_MESSAGE_LENGTH,
+            MESSAGE_LENGTH,
+            MESSAGE_V2_LENGTH,
+            MESSAGE_VERSION,
+            length
+        ),
+    }
+}
 
 impl MessageToMainnet {
-    /// parses message from a byte slice
+    /// parses message from a byte slice of the baseline, the legacy or the version 2 format.
+    /// parses the fields of a `TokenMessageToMainnet` except for its token.
+    /// panics if `bytes` isn't `BASELINE_MESSAGE_LENGTH`, `MESSAGE_LENGTH`,
+    /// `TOKEN_MESSAGE_LENGTH`, `MESSAGE_V2_LENGTH` or `TOKEN_MESSAGE_V2_LENGTH` long
+    /// or has another version than `MESSAGE_VERSION`.
+    /// use `try_from_bytes` for messages that weren't created by this process
     pub fn from_bytes(bytes: &[u8]) -> Self {
-        assert_eq!(bytes.len(), MESSAGE_LENGTH);
+        let version = message_version(bytes).expect("message has a supported format; qed");
+        let (chain_ids, bytes) = match version {
+            None => (None, bytes),
+            Some(_) => {
+                let chain_ids = ChainIds {
+                    home: U256::from_big_endian(&bytes[1..33]).low_u64(),
+                    foreign: U256::from_big_endian(&bytes[33..65]).low_u64(),
+                };
+                (Some(chain_ids), &bytes[MESSAGE_V2_PREFIX_LENGTH..])
+            }
+        };
 
         Self {
             recipient: bytes[0..20].into(),
             value: U256::from_big_endian(&bytes[20..52]),
             sidenet_transaction_hash: bytes[52..84].into(),
-            mainnet_gas_price: U256::from_big_endian(&bytes[84..MESSAGE_LENGTH]),
+            mainnet_gas_price: U256::from_big_endian(&bytes[84..BASELINE_MESSAGE_LENGTH]),
+            mainnet_bridge_address: match bytes.len() {
+                BASELINE_MESSAGE_LENGTH => Address::zero(),
+                _ => bytes[BASELINE_MESSAGE_LENGTH..MESSAGE_LENGTH].into(),
+            },
+            chain_ids,
+        }
+    }
+
+    /// parses message from a byte slice.
+    /// fails if `bytes` is neither a baseline message of `BASELINE_MESSAGE_LENGTH` bytes,
+    /// a legacy message of `MESSAGE_LENGTH` bytes nor a version 2 message
+    /// of `MESSAGE_V2_LENGTH` bytes or a field can't be part of a withdraw
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes.len() {
+            BASELINE_MESSAGE_LENGTH | MESSAGE_LENGTH | MESSAGE_V2_LENGTH => (),
+            length => bail!(
+                "message must be {} bytes long (baseline), {} bytes long (legacy) or {} bytes long (version {}) but is {}",
+                BASELINE_MESSAGE_LENGTH,
+                MESSAGE_LENGTH,
+                MESSAGE_V2_LENGTH,
+                MESSAGE_VERSION,
+                length
+            ),
+        }
+        if message_version(bytes)?.is_some() {
+            let home = U256::from_big_endian(&bytes[1..33]);
+            let foreign = U256::from_big_endian(&bytes[33..65]);
+            if home > u64::max_value().into() || foreign > u64::max_value().into() {
+                bail!("message has a chain id that doesn't fit into 64 bits");
+            }
+        }
+        let message = Self::from_bytes(bytes);
+        if message.is_baseline() {
+            message.check_transfer_fields()?;
+        } else {
+            message.check_fields()?;
         }
+        Ok(message)
+    }
+
+    /// whether the message is of the baseline format, which isn't bound to a `HomeBridge`
+    pub fn is_baseline(&self) -> bool {
+        self.chain_ids.is_none() && self.mainnet_bridge_address.is_zero()
+    }
+
+    /// returns a builder of a message of the legacy format
+    pub fn builder() -> MessageToMainnetBuilder {
+        MessageToMainnetBuilder::default()
+    }
+
+    /// fails if a field can't be part of a withdraw
+    fn check_fields(&self) -> Result<(), Error> {
+        self.check_transfer_fields()?;
+        if self.mainnet_bridge_address.is_zero() {
+            bail!("message isn't bound to a HomeBridge");
+        }
+        Ok(())
+    }
+
+    /// fails if a field of the transfer or the chain ids can't be part of a withdraw
+    fn check_transfer_fields(&self) -> Result<(), Error> {
+        if let Some(chain_ids) = self.chain_ids {
+            if chain_ids.home == 0 || chain_ids.foreign == 0 {
+                bail!("message has no chain id");
+            }
+        }
+        if self.recipient.is_zero() {
+            bail!("message has no recipient");
+        }
+        if self.sidenet_transaction_hash.is_zero() {
+            bail!("message has no sidenet transaction hash");
+        }
+        Ok(())
     }
 
     /// construct a message from a `Withdraw` event that was logged on `foreign`
-    pub fn from_log(web3_log: Log) -> Result<Self, Error> {
+    /// that is meant for the `HomeBridge` at `mainnet_bridge_address`.
+    /// a version 2 message for the chains `chain_ids` if they are `Some`
+    /// (see `Config::message_chain_ids`)
+    pub fn from_log(
+        web3_log: Log,
+        mainnet_bridge_address: Address,
+        chain_ids: Option<ChainIds>,
+    ) -> Result<Self, Error> {
         let ethabi_raw_log = ethabi::RawLog {
             topics: web3_log.topics,
             data: web3_log.data.0,
@@ -46,20 +248,41 @@ impl MessageToMainnet {
             value: withdraw_log.value,
             sidenet_transaction_hash: hash,
             mainnet_gas_price: withdraw_log.home_gas_price,
+            mainnet_bridge_address,
+            chain_ids,
         })
     }
 
     /// serializes message to a byte vector.
     /// mainly used to construct the message byte vector that is then signed
-    /// and passed to `ForeignBridge.submitSignature`
+    /// and passed to `ForeignBridge.submitSignature`.
+    /// messages with `chain_ids` are serialized in the version 2 format
+    /// and messages of the baseline format as they were parsed
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut result = vec![0u8; MESSAGE_LENGTH];
-        result[0..20].copy_from_slice(&self.recipient.0[..]);
-        self.value.to_big_endian(&mut result[20..52]);
-        result[52..84].copy_from_slice(&self.sidenet_transaction_hash.0[..]);
+        let mut result = match self.chain_ids {
+            None => Vec::with_capacity(MESSAGE_LENGTH),
+            Some(chain_ids) => {
+                let mut prefix = vec![0u8; MESSAGE_V2_PREFIX_LENGTH];
+                prefix[0] = MESSAGE_VERSION;
+                U256::from(chain_ids.home).to_big_endian(&mut prefix[1..33]);
+                U256::from(chain_ids.foreign).to_big_endian(&mut prefix[33..65]);
+                prefix
+            }
+        };
+        let mut fields = [0u8; MESSAGE_LENGTH];
+        fields[0..20].copy_from_slice(&self.recipient.0[..]);
+        self.value.to_big_endian(&mut fields[20..52]);
+        fields[52..84].copy_from_slice(&self.sidenet_transaction_hash.0[..]);
         self.mainnet_gas_price
-            .to_big_endian(&mut result[84..MESSAGE_LENGTH]);
-        return result;
+            .to_big_endian(&mut fields[84..BASELINE_MESSAGE_LENGTH]);
+        if self.is_baseline() {
+            result.extend_from_slice(&fields[..BASELINE_MESSAGE_LENGTH]);
+            return result;
+        }
+        fields[BASELINE_MESSAGE_LENGTH..MESSAGE_LENGTH]
+            .copy_from_slice(&self.mainnet_bridge_address.0[..]);
+        result.extend_from_slice(&fields);
+        result
     }
 
     /// serializes message to an ethabi payload
@@ -68,11 +291,260 @@ impl MessageToMainnet {
     }
 }
 
+impl str::FromStr for MessageToMainnet {
+    type Err = Error;
+
+    /// parses the hex encoding of a message of the baseline, the legacy or the version 2 format
+    /// with optional `0x` prefix. fails like `try_from_bytes`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_bytes(&from_hex(s)?)
+    }
+}
+
+impl fmt::Display for MessageToMainnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", self.to_bytes().to_hex())
+    }
+}
+
+/// fields of a serialized `MessageToMainnet`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MessageToMainnetFields {
+    recipient: Address,
+    #[serde(deserialize_with = "decimal::deserialize")]
+    value: U256,
+    sidenet_transaction_hash: H256,
+    #[serde(deserialize_with = "decimal::deserialize")]
+    mainnet_gas_price: U256,
+    mainnet_bridge_address: Address,
+    #[serde(default)]
+    chain_ids: Option<ChainIds>,
+}
+
+impl<'de> Deserialize<'de> for MessageToMainnet {
+    /// fails like `try_from_bytes` if a field can't be part of a withdraw
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = MessageToMainnetFields::deserialize(deserializer)?;
+        let message = MessageToMainnet {
+            recipient: fields.recipient,
+            value: fields.value,
+            sidenet_transaction_hash: fields.sidenet_transaction_hash,
+            mainnet_gas_price: fields.mainnet_gas_price,
+            mainnet_bridge_address: fields.mainnet_bridge_address,
+            chain_ids: fields.chain_ids,
+        };
+        message
+            .check_fields()
+            .map_err(|err| de::Error::custom(err.to_string()))?;
+        Ok(message)
+    }
+}
+
+/// builds a `MessageToMainnet` for tools that create messages outside of the bridge.
+/// `value` and `mainnet_gas_price` default to `0`.
+/// messages are of the legacy format unless `chain_ids` are set
+#[derive(Debug, Default, Clone)]
+pub struct MessageToMainnetBuilder {
+    recipient: Address,
+    value: U256,
+    sidenet_transaction_hash: H256,
+    mainnet_gas_price: U256,
+    mainnet_bridge_address: Address,
+    chain_ids: Option<ChainIds>,
+}
+
+impl MessageToMainnetBuilder {
+    pub fn recipient(mut self, recipient: Address) -> Self {
+        self.recipient = recipient;
+        self
+    }
+
+    pub fn value(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
+
+    /// transaction on `foreign` the message is created for
+    pub fn sidenet_transaction_hash(mut self, hash: H256) -> Self {
+        self.sidenet_transaction_hash = hash;
+        self
+    }
+
+    pub fn mainnet_gas_price(mut self, gas_price: U256) -> Self {
+        self.mainnet_gas_price = gas_price;
+        self
+    }
+
+    /// address of the `HomeBridge` the message is meant for
+    pub fn mainnet_bridge_address(mut self, address: Address) -> Self {
+        self.mainnet_bridge_address = address;
+        self
+    }
+
+    /// builds a message of the version 2 format for the chains `chain_ids`
+    pub fn chain_ids(mut self, chain_ids: ChainIds) -> Self {
+        self.chain_ids = Some(chain_ids);
+        self
+    }
+
+    /// fails like `MessageToMainnet::try_from_bytes` if a field can't be part of a withdraw.
+    /// `recipient`, `sidenet_transaction_hash` and `mainnet_bridge_address` are required
+    pub fn build(self) -> Result<MessageToMainnet, Error> {
+        let message = MessageToMainnet {
+            recipient: self.recipient,
+            value: self.value,
+            sidenet_transaction_hash: self.sidenet_transaction_hash,
+            mainnet_gas_price: self.mainnet_gas_price,
+            mainnet_bridge_address: self.mainnet_bridge_address,
+            chain_ids: self.chain_ids,
+        };
+        message.check_fields()?;
+        Ok(message)
+    }
+
+    /// builds the message of a token bridge that withdraws `token` on `home`
+    pub fn build_token(self, token: Address) -> Result<TokenMessageToMainnet, Error> {
+        TokenMessageToMainnet::new(self.build()?, token)
+    }
+}
+
+/// the message that is relayed from side to main by the token bridges.
+/// the fields of `MessageToMainnet` followed by the token on `home`
+/// which `HomeTokenBridge.withdraw` transfers to the recipient.
+/// serializes to an object of the serialized `message` and `token`
+#[derive(PartialEq, Debug, Serialize)]
+pub struct TokenMessageToMainnet {
+    pub message: MessageToMainnet,
+    pub token: Address,
+}
+
+impl TokenMessageToMainnet {
+    /// fails if `token` is missing
+    pub fn new(message: MessageToMainnet, token: Address) -> Result<Self, Error> {
+        if token.is_zero() {
+            bail!("token message has no token");
+        }
+        Ok(Self { message, token })
+    }
+
+    /// parses message from a byte slice.
+    /// fails if `bytes` is neither `TOKEN_MESSAGE_LENGTH` nor `TOKEN_MESSAGE_V2_LENGTH` long
+    /// or a field can't be part of a withdraw
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != TOKEN_MESSAGE_LENGTH && bytes.len() != TOKEN_MESSAGE_V2_LENGTH {
+            bail!(
+                "token message must be {} bytes long (legacy) or {} bytes long (version {}) but is {}",
+                TOKEN_MESSAGE_LENGTH,
+                TOKEN_MESSAGE_V2_LENGTH,
+                MESSAGE_VERSION,
+                bytes.len()
+            );
+        }
+        let (message, token) = bytes.split_at(bytes.len() - 20);
+        Self::new(MessageToMainnet::try_from_bytes(message)?, token.into())
+    }
+
+    /// construct a message from a `TransferToHome` event that was logged on `foreign`
+    /// that is meant for the `HomeTokenBridge` at `mainnet_bridge_address`
+    /// and the chains `chain_ids` like `MessageToMainnet::from_log`.
+    /// fails if the event doesn't transfer `tokens.foreign`
+    #[cfg(feature = "contracts-v2")]
+    pub fn from_log(
+        web3_log: Log,
+        tokens: &Tokens,
+        mainnet_bridge_address: Address,
+        chain_ids: Option<ChainIds>,
+    ) -> Result<Self, Error> {
+        let ethabi_raw_log = ethabi::RawLog {
+            topics: web3_log.topics,
+            data: web3_log.data.0,
+        };
+        let transfer_log = TransferToHome::default().parse_log(ethabi_raw_log)?;
+        if transfer_log.token != tokens.foreign {
+            bail!(
+                "transfer to home of token {:?} but `foreign.token_address` is {:?}",
+                transfer_log.token,
+                tokens.foreign
+            );
+        }
+        let hash = web3_log
+            .transaction_hash
+            .ok_or_else(|| "`log` must be mined and contain `transaction_hash`")?;
+        Ok(Self {
+            message: MessageToMainnet {
+                recipient: transfer_log.recipient,
+                value: transfer_log.value,
+                sidenet_transaction_hash: hash,
+                mainnet_gas_price: transfer_log.home_gas_price,
+                mainnet_bridge_address,
+                chain_ids,
+            },
+            token: tokens.home,
+        })
+    }
+
+    /// serializes message to a byte vector
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = self.message.to_bytes();
+        result.extend_from_slice(&self.token.0[..]);
+        result
+    }
+}
+
+impl str::FromStr for TokenMessageToMainnet {
+    type Err = Error;
+
+    /// parses the hex encoding of a token message with optional `0x` prefix.
+    /// fails like `try_from_bytes`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from_bytes(&from_hex(s)?)
+    }
+}
+
+impl fmt::Display for TokenMessageToMainnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", self.to_bytes().to_hex())
+    }
+}
+
+/// fields of a serialized `TokenMessageToMainnet`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TokenMessageToMainnetFields {
+    message: MessageToMainnet,
+    token: Address,
+}
+
+impl<'de> Deserialize<'de> for TokenMessageToMainnet {
+    /// fails like `try_from_bytes` if a field can't be part of a withdraw
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = TokenMessageToMainnetFields::deserialize(deserializer)?;
+        TokenMessageToMainnet::new(fields.message, fields.token)
+            .map_err(|err| de::Error::custom(err.to_string()))
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use ethabi::Token;
     use quickcheck::TestResult;
+    use serde_json;
     use super::*;
     use rustc_hex::FromHex;
+    use tiny_keccak::keccak256;
+
+    fn message() -> MessageToMainnet {
+        MessageToMainnet {
+            recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+            value: 3800000000000000u64.into(),
+            sidenet_transaction_hash:
+                "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            mainnet_gas_price: 8000000000u64.into(),
+            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            chain_ids: None,
+        }
+    }
 
     #[test]
     fn test_message_to_mainnet_to_bytes() {
@@ -81,15 +553,311 @@ mod test {
         let sidenet_transaction_hash: H256 =
             "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into();
         let mainnet_gas_price = U256::from_dec_str("8000000000").unwrap();
+        let mainnet_bridge_address: Address = "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into();
 
         let message = MessageToMainnet {
             recipient,
             value,
             sidenet_transaction_hash,
             mainnet_gas_price,
+            mainnet_bridge_address,
+            chain_ids: None,
         };
 
-        assert_eq!(message.to_bytes(), "eac4a655451e159313c3641e29824e77d6fcb0ce000000000000000000000000000000000000000000000000000d80147225800075ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e200000000000000000000000000000000000000000000000000000001dcd65000".from_hex().unwrap())
+        assert_eq!(message.to_bytes(), "eac4a655451e159313c3641e29824e77d6fcb0ce000000000000000000000000000000000000000000000000000d80147225800075ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e200000000000000000000000000000000000000000000000000000001dcd6500049edf201c1e139282643d5e7c6fb0c7219ad1db7".from_hex().unwrap())
+    }
+
+    #[test]
+    fn test_message_to_mainnet_from_log() {
+        let expected = message();
+        let signature = "Withdraw(address,uint256,uint256)";
+        let data = ethabi::encode(&[
+            Token::Address(expected.recipient),
+            Token::Uint(expected.value),
+            Token::Uint(expected.mainnet_gas_price),
+        ]);
+        let log = Log {
+            data: data.into(),
+            topics: vec![keccak256(signature.as_bytes()).into()],
+            transaction_hash: Some(expected.sidenet_transaction_hash),
+            ..Default::default()
+        };
+        let message =
+            MessageToMainnet::from_log(log.clone(), expected.mainnet_bridge_address, None).unwrap();
+        assert_eq!(expected, message);
+        assert_eq!(MESSAGE_LENGTH, message.to_bytes().len());
+
+        let chain_ids = ChainIds {
+            home: 1,
+            foreign: 42,
+        };
+        let message =
+            MessageToMainnet::from_log(log, expected.mainnet_bridge_address, Some(chain_ids))
+                .unwrap();
+        assert_eq!(Some(chain_ids), message.chain_ids);
+        assert_eq!(MESSAGE_V2_LENGTH, message.to_bytes().len());
+    }
+
+    #[test]
+    fn test_message_to_mainnet_try_from_bytes() {
+        let message = MessageToMainnet {
+            recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+            value: 3800000000000000u64.into(),
+            sidenet_transaction_hash:
+                "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            mainnet_gas_price: 8000000000u64.into(),
+            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            chain_ids: None,
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(message, MessageToMainnet::try_from_bytes(&bytes).unwrap());
+        assert!(MessageToMainnet::try_from_bytes(&bytes[1..]).is_err());
+        assert!(MessageToMainnet::try_from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(MessageToMainnet::try_from_bytes(&[]).is_err());
+
+        let mut unbound = bytes.clone();
+        for byte in &mut unbound[BASELINE_MESSAGE_LENGTH..MESSAGE_LENGTH] {
+            *byte = 0;
+        }
+        assert!(MessageToMainnet::try_from_bytes(&unbound).is_err());
+
+        let mut no_recipient = bytes;
+        for byte in &mut no_recipient[0..20] {
+            *byte = 0;
+        }
+        assert!(MessageToMainnet::try_from_bytes(&no_recipient).is_err());
+    }
+
+    #[test]
+    fn test_message_to_mainnet_try_from_baseline_bytes() {
+        // message signed before messages were bound to a `HomeBridge`
+        let bytes = "eac4a655451e159313c3641e29824e77d6fcb0ce000000000000000000000000000000000000000000000000000d80147225800075ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e200000000000000000000000000000000000000000000000000000001dcd65000".from_hex().unwrap();
+        assert_eq!(BASELINE_MESSAGE_LENGTH, bytes.len());
+        let message = MessageToMainnet::try_from_bytes(&bytes).unwrap();
+        assert_eq!(
+            MessageToMainnet {
+                recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+                value: 3800000000000000u64.into(),
+                sidenet_transaction_hash:
+                    "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+                mainnet_gas_price: 8000000000u64.into(),
+                mainnet_bridge_address: Address::zero(),
+                chain_ids: None,
+            },
+            message
+        );
+        assert!(message.is_baseline());
+        assert_eq!(bytes, message.to_bytes());
+        assert_eq!(message, format!("0x{}", bytes.to_hex()).parse().unwrap());
+
+        let mut no_hash = bytes;
+        for byte in &mut no_hash[52..84] {
+            *byte = 0;
+        }
+        assert!(MessageToMainnet::try_from_bytes(&no_hash).is_err());
+    }
+
+    #[test]
+    fn test_message_v2_roundtrips() {
+        let message = MessageToMainnet {
+            recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+            value: 3800000000000000u64.into(),
+            sidenet_transaction_hash:
+                "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            mainnet_gas_price: 8000000000u64.into(),
+            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            chain_ids: Some(ChainIds {
+                home: 1,
+                foreign: 77,
+            }),
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(MESSAGE_V2_LENGTH, bytes.len());
+        assert_eq!(MESSAGE_VERSION, bytes[0]);
+        assert_eq!(1, bytes[32]);
+        assert_eq!(77, bytes[64]);
+        assert_eq!(message, MessageToMainnet::from_bytes(&bytes));
+        assert_eq!(message, MessageToMainnet::try_from_bytes(&bytes).unwrap());
+
+        // the fields follow the prefix in the legacy layout
+        let legacy = MessageToMainnet {
+            chain_ids: None,
+            ..MessageToMainnet::from_bytes(&bytes)
+        };
+        assert_eq!(legacy.to_bytes(), &bytes[65..]);
+
+        let mut unsupported = bytes.clone();
+        unsupported[0] = 3;
+        assert!(MessageToMainnet::try_from_bytes(&unsupported).is_err());
+
+        let mut no_chain_id = bytes.clone();
+        no_chain_id[32] = 0;
+        assert!(MessageToMainnet::try_from_bytes(&no_chain_id).is_err());
+
+        let mut too_large_chain_id = bytes;
+        too_large_chain_id[1] = 1;
+        assert!(MessageToMainnet::try_from_bytes(&too_large_chain_id).is_err());
+    }
+
+    #[test]
+    fn test_message_to_mainnet_serde() {
+        let json = r#"{"recipient":"0xeac4a655451e159313c3641e29824e77d6fcb0ce","value":"3800000000000000","sidenet_transaction_hash":"0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2","mainnet_gas_price":"8000000000","mainnet_bridge_address":"0x49edf201c1e139282643d5e7c6fb0c7219ad1db7"}"#;
+        assert_eq!(json, serde_json::to_string(&message()).unwrap());
+        assert_eq!(message(), serde_json::from_str::<MessageToMainnet>(json).unwrap());
+
+        let v2 = MessageToMainnet {
+            chain_ids: Some(ChainIds {
+                home: 1,
+                foreign: 77,
+            }),
+            ..message()
+        };
+        let v2_json = serde_json::to_string(&v2).unwrap();
+        assert!(v2_json.ends_with(r#","chain_ids":{"home":1,"foreign":77}}"#));
+        assert_eq!(v2, serde_json::from_str::<MessageToMainnet>(&v2_json).unwrap());
+
+        // `U256` is decimal
+        let hex_value = json.replace(r#""3800000000000000""#, r#""0xd801472258000""#);
+        assert!(serde_json::from_str::<MessageToMainnet>(&hex_value).is_err());
+        let no_recipient = json.replace(
+            "eac4a655451e159313c3641e29824e77d6fcb0ce",
+            "0000000000000000000000000000000000000000",
+        );
+        assert!(serde_json::from_str::<MessageToMainnet>(&no_recipient).is_err());
+        let unknown_field = json.replace(r#"{"recipient""#, r#"{"token":null,"recipient""#);
+        assert!(serde_json::from_str::<MessageToMainnet>(&unknown_field).is_err());
+        let no_chain_id = v2_json.replace(r#""home":1"#, r#""home":0"#);
+        assert!(serde_json::from_str::<MessageToMainnet>(&no_chain_id).is_err());
+    }
+
+    #[test]
+    fn test_message_to_mainnet_from_str() {
+        let hex = "0xeac4a655451e159313c3641e29824e77d6fcb0ce000000000000000000000000000000000000000000000000000d80147225800075ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e200000000000000000000000000000000000000000000000000000001dcd6500049edf201c1e139282643d5e7c6fb0c7219ad1db7";
+        assert_eq!(hex, message().to_string());
+        assert_eq!(message(), hex.parse().unwrap());
+        assert_eq!(message(), hex[2..].parse().unwrap());
+        assert!(hex[..hex.len() - 2].parse::<MessageToMainnet>().is_err());
+        assert!("0xzz".parse::<MessageToMainnet>().is_err());
+    }
+
+    #[test]
+    fn test_message_to_mainnet_builder() {
+        let expected = message();
+        let builder = MessageToMainnet::builder()
+            .recipient(expected.recipient)
+            .value(expected.value)
+            .sidenet_transaction_hash(expected.sidenet_transaction_hash)
+            .mainnet_gas_price(expected.mainnet_gas_price)
+            .mainnet_bridge_address(expected.mainnet_bridge_address);
+        assert_eq!(expected, builder.clone().build().unwrap());
+
+        let chain_ids = ChainIds {
+            home: 1,
+            foreign: 77,
+        };
+        let v2 = builder.clone().chain_ids(chain_ids).build().unwrap();
+        assert_eq!(Some(chain_ids), v2.chain_ids);
+        assert_eq!(MESSAGE_V2_LENGTH, v2.to_bytes().len());
+
+        let token = builder.clone().build_token(0x10.into()).unwrap();
+        assert_eq!(expected, token.message);
+        assert!(builder.clone().build_token(Address::zero()).is_err());
+
+        assert!(MessageToMainnet::builder().build().is_err());
+        assert!(
+            builder
+                .clone()
+                .mainnet_bridge_address(Address::zero())
+                .build()
+                .is_err()
+        );
+        assert!(
+            builder
+                .chain_ids(ChainIds {
+                    home: 0,
+                    foreign: 77,
+                })
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_token_message_to_mainnet_serde() {
+        let message = TokenMessageToMainnet::new(message(), 0x10.into()).unwrap();
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.starts_with(r#"{"message":{"recipient":"#));
+        assert!(json.ends_with(r#"},"token":"0x0000000000000000000000000000000000000010"}"#));
+        assert_eq!(message, serde_json::from_str(&json).unwrap());
+        assert_eq!(message, message.to_string().parse().unwrap());
+
+        let no_token = json.replace("0000000000000000000000000000000000000010", &"0".repeat(40));
+        assert!(serde_json::from_str::<TokenMessageToMainnet>(&no_token).is_err());
+    }
+
+    #[test]
+    fn test_chain_ids_matches() {
+        let chain_ids = ChainIds {
+            home: 1,
+            foreign: 77,
+        };
+        assert!(chain_ids.matches(Some(1), Some(77)));
+        assert!(chain_ids.matches(None, Some(77)));
+        assert!(chain_ids.matches(None, None));
+        assert!(!chain_ids.matches(Some(3), Some(77)));
+        assert!(!chain_ids.matches(Some(1), Some(1)));
+    }
+
+    #[test]
+    fn test_token_message_to_mainnet_try_from_bytes() {
+        let message = TokenMessageToMainnet {
+            message: MessageToMainnet {
+                recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+                value: 3800000000000000u64.into(),
+                sidenet_transaction_hash:
+                    "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+                mainnet_gas_price: 8000000000u64.into(),
+                mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                chain_ids: None,
+            },
+            token: "0x0000000000000000000000000000000000000010".into(),
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(TOKEN_MESSAGE_LENGTH, bytes.len());
+        assert_eq!(message, TokenMessageToMainnet::try_from_bytes(&bytes).unwrap());
+        assert_eq!(message.message, MessageToMainnet::from_bytes(&bytes));
+        assert!(TokenMessageToMainnet::try_from_bytes(&bytes[..MESSAGE_LENGTH]).is_err());
+        assert!(MessageToMainnet::try_from_bytes(&bytes).is_err());
+
+        let mut no_token = bytes;
+        for byte in &mut no_token[MESSAGE_LENGTH..] {
+            *byte = 0;
+        }
+        assert!(TokenMessageToMainnet::try_from_bytes(&no_token).is_err());
+    }
+
+    #[test]
+    fn test_token_message_v2_try_from_bytes() {
+        let message = TokenMessageToMainnet {
+            message: MessageToMainnet {
+                recipient: "0xeac4a655451e159313c3641e29824e77d6fcb0ce".into(),
+                value: 3800000000000000u64.into(),
+                sidenet_transaction_hash:
+                    "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+                mainnet_gas_price: 8000000000u64.into(),
+                mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                chain_ids: Some(ChainIds {
+                    home: 1,
+                    foreign: 77,
+                }),
+            },
+            token: "0x0000000000000000000000000000000000000010".into(),
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(TOKEN_MESSAGE_V2_LENGTH, bytes.len());
+        assert_eq!(message, TokenMessageToMainnet::try_from_bytes(&bytes).unwrap());
+        assert_eq!(message.message, MessageToMainnet::from_bytes(&bytes));
+        assert!(MessageToMainnet::try_from_bytes(&bytes).is_err());
     }
 
     quickcheck! {
@@ -97,7 +865,8 @@ mod test {
             recipient_raw: Vec<u8>,
             value_raw: u64,
             sidenet_transaction_hash_raw: Vec<u8>,
-            mainnet_gas_price_raw: u64
+            mainnet_gas_price_raw: u64,
+            mainnet_bridge_address_raw: u64
         ) -> TestResult {
             if recipient_raw.len() != 20 || sidenet_transaction_hash_raw.len() != 32 {
                 return TestResult::discard();
@@ -107,12 +876,15 @@ mod test {
             let value: U256 = value_raw.into();
             let sidenet_transaction_hash: H256 = sidenet_transaction_hash_raw.as_slice().into();
             let mainnet_gas_price: U256 = mainnet_gas_price_raw.into();
+            let mainnet_bridge_address: Address = mainnet_bridge_address_raw.into();
 
             let message = MessageToMainnet {
                 recipient,
                 value,
                 sidenet_transaction_hash,
-                mainnet_gas_price
+                mainnet_gas_price,
+                mainnet_bridge_address,
+                chain_ids: None,
             };
 
             let bytes = message.to_bytes();
@@ -126,5 +898,39 @@ mod test {
 
             TestResult::passed()
         }
+
+        fn quickcheck_message_to_mainnet_roundtrips_to_json_and_hex(
+            recipient_raw: Vec<u8>,
+            value_raw: u64,
+            sidenet_transaction_hash_raw: Vec<u8>,
+            mainnet_gas_price_raw: u64,
+            mainnet_bridge_address_raw: u64,
+            chain_ids_raw: Option<(u64, u64)>
+        ) -> TestResult {
+            if recipient_raw.len() != 20 || sidenet_transaction_hash_raw.len() != 32 {
+                return TestResult::discard();
+            }
+
+            let mut builder = MessageToMainnet::builder()
+                .recipient(recipient_raw.as_slice().into())
+                .value(value_raw.into())
+                .sidenet_transaction_hash(sidenet_transaction_hash_raw.as_slice().into())
+                .mainnet_gas_price(mainnet_gas_price_raw.into())
+                .mainnet_bridge_address(mainnet_bridge_address_raw.into());
+            if let Some((home, foreign)) = chain_ids_raw {
+                builder = builder.chain_ids(ChainIds { home, foreign });
+            }
+            let message = match builder.build() {
+                Ok(message) => message,
+                // zero fields are rejected
+                Err(_) => return TestResult::discard(),
+            };
+
+            let json = serde_json::to_string(&message).unwrap();
+            assert_eq!(message, serde_json::from_str(&json).unwrap());
+            assert_eq!(message, message.to_string().parse().unwrap());
+
+            TestResult::passed()
+        }
     }
 }
@@ -0,0 +1,334 @@
+/// metrics of the rpc calls to the nodes of both chains.
+/// every call is tagged with the endpoint (the ipc path of the node) and the method.
+/// per endpoint and method the number of calls, errors and abandoned calls
+/// and a histogram of the latencies are recorded.
+/// per endpoint the number of rescheduled relay attempts
+/// and of calls rejected by the rate limit of the node are recorded.
+/// the relays of this authority are counted as `RelayMetrics` per bridge.
+/// in a process that runs several bridges the endpoints are prefixed with the name of the bridge
+/// (see `supervisor`).
+/// metrics are periodically exported as json to the file configured in `metrics.file`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll, Stream};
+use jsonrpc_core as rpc;
+use serde_json;
+use tokio_timer::Interval;
+use web3::{self, RequestId, Transport};
+use error::{Error, ResultExt};
+use supervisor::{self, prefixed};
+
+/// upper bounds of the latency histogram buckets in milliseconds
+pub const LATENCY_BUCKETS_MS: [u64; 9] = [10, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+/// metrics of the calls of a method to an endpoint
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct MethodMetrics {
+    pub calls: u64,
+    /// calls that completed with an error
+    pub errors: u64,
+    /// calls that were dropped before they completed (example: because they timed out)
+    pub abandoned: u64,
+    /// `latency_buckets[i]` is the number of completed calls
+    /// that took at most `LATENCY_BUCKETS_MS[i]` ms.
+    /// the last entry is the number of all completed calls
+    pub latency_buckets: Vec<u64>,
+    pub latency_sum_ms: u64,
+}
+
+impl Default for MethodMetrics {
+    fn default() -> Self {
+        MethodMetrics {
+            calls: 0,
+            errors: 0,
+            abandoned: 0,
+            latency_buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+            latency_sum_ms: 0,
+        }
+    }
+}
+
+impl MethodMetrics {
+    /// records a call that completed after `latency`
+    fn observe(&mut self, latency: Duration, error: bool) {
+        let millis = latency.as_secs() * 1000 + u64::from(latency.subsec_nanos() / 1_000_000);
+        if error {
+            self.errors += 1;
+        }
+        for (bucket, bound) in self.latency_buckets.iter_mut().zip(LATENCY_BUCKETS_MS.iter()) {
+            if millis <= *bound {
+                *bucket += 1;
+            }
+        }
+        *self.latency_buckets
+            .last_mut()
+            .expect("there is a bucket for all calls; qed") += 1;
+        self.latency_sum_ms += millis;
+    }
+}
+
+/// metrics of an endpoint
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct EndpointMetrics {
+    pub methods: BTreeMap<String, MethodMetrics>,
+    /// relay attempts that were abandoned and rescheduled
+    pub retries: u64,
+    /// calls the node rejected because of its rate limit
+    pub rate_limited: u64,
+}
+
+/// metrics by endpoint
+pub type Metrics = BTreeMap<String, EndpointMetrics>;
+
+fn registry() -> &'static Mutex<Metrics> {
+    static INIT: Once = ONCE_INIT;
+    static mut REGISTRY: *const Mutex<Metrics> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            REGISTRY = Box::into_raw(Box::new(Mutex::new(Metrics::new())));
+        });
+        &*REGISTRY
+    }
+}
+
+/// relays of this authority since the process started
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct RelayMetrics {
+    /// deposits relayed to `foreign`
+    pub deposits: u64,
+    /// signatures of withdraws submitted to `foreign`
+    pub signatures: u64,
+    /// withdraws relayed to `home`
+    pub withdraws: u64,
+}
+
+/// relays by bridge. the key is `None` in a process that runs a single bridge
+pub type Relays = BTreeMap<Option<String>, RelayMetrics>;
+
+fn relay_registry() -> &'static Mutex<Relays> {
+    static INIT: Once = ONCE_INIT;
+    static mut REGISTRY: *const Mutex<Relays> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            REGISTRY = Box::into_raw(Box::new(Mutex::new(Relays::new())));
+        });
+        &*REGISTRY
+    }
+}
+
+/// counts the relays of the current bridge recorded by `f`
+pub fn record_relays<F: FnOnce(&mut RelayMetrics)>(f: F) {
+    f(relay_registry()
+        .lock()
+        .expect("metrics are never poisoned; qed")
+        .entry(supervisor::current())
+        .or_insert_with(Default::default));
+}
+
+/// returns the relays counted so far by bridge
+pub fn relays() -> Relays {
+    relay_registry()
+        .lock()
+        .expect("metrics are never poisoned; qed")
+        .clone()
+}
+
+/// returns the relays counted so far by all bridges of the process
+pub fn total_relays() -> RelayMetrics {
+    relays()
+        .values()
+        .fold(RelayMetrics::default(), |total, relays| RelayMetrics {
+            deposits: total.deposits + relays.deposits,
+            signatures: total.signatures + relays.signatures,
+            withdraws: total.withdraws + relays.withdraws,
+        })
+}
+
+fn record<F: FnOnce(&mut EndpointMetrics)>(endpoint: &str, f: F) {
+    let mut metrics = registry().lock().expect("metrics are never poisoned; qed");
+    f(metrics.entry(prefixed(endpoint)).or_insert_with(Default::default));
+}
+
+fn record_method<F: FnOnce(&mut MethodMetrics)>(endpoint: &str, method: &str, f: F) {
+    record(endpoint, |metrics| {
+        f(metrics
+            .methods
+            .entry(method.to_owned())
+            .or_insert_with(Default::default))
+    });
+}
+
+/// records that a relay attempt on `endpoint` was abandoned and rescheduled
+pub fn record_retry(endpoint: &str) {
+    record(endpoint, |metrics| metrics.retries += 1);
+}
+
+/// records that `endpoint` rejected a call because of its rate limit
+pub fn record_rate_limited(endpoint: &str) {
+    record(endpoint, |metrics| metrics.rate_limited += 1);
+}
+
+/// returns the metrics recorded so far
+pub fn snapshot() -> Metrics {
+    registry()
+        .lock()
+        .expect("metrics are never poisoned; qed")
+        .clone()
+}
+
+/// transport that records metrics of the calls to `endpoint` made through it
+#[derive(Debug, Clone)]
+pub struct Metered<T> {
+    transport: T,
+    endpoint: String,
+}
+
+impl<T> Metered<T> {
+    pub fn new(transport: T, endpoint: String) -> Self {
+        Metered {
+            transport,
+            endpoint,
+        }
+    }
+}
+
+impl<T: Transport> Transport for Metered<T> {
+    type Out = MeteredCall<T::Out>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let method = match request {
+            rpc::Call::MethodCall(ref call) => call.method.clone(),
+            rpc::Call::Notification(ref notification) => notification.method.clone(),
+            rpc::Call::Invalid(_) => "invalid".into(),
+        };
+        record_method(&self.endpoint, &method, |metrics| metrics.calls += 1);
+        MeteredCall {
+            future: self.transport.send(id, request),
+            endpoint: self.endpoint.clone(),
+            method,
+            start: Instant::now(),
+            done: false,
+        }
+    }
+}
+
+/// rpc call whose outcome and latency are recorded
+pub struct MeteredCall<F> {
+    future: F,
+    endpoint: String,
+    method: String,
+    start: Instant,
+    done: bool,
+}
+
+impl<F: Future<Item = rpc::Value, Error = web3::Error>> Future for MeteredCall<F> {
+    type Item = rpc::Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.future.poll();
+        match result {
+            Ok(Async::NotReady) => {}
+            ref result => {
+                let latency = self.start.elapsed();
+                let error = result.is_err();
+                record_method(&self.endpoint, &self.method, |metrics| {
+                    metrics.observe(latency, error)
+                });
+                self.done = true;
+            }
+        }
+        result
+    }
+}
+
+impl<F> Drop for MeteredCall<F> {
+    fn drop(&mut self) {
+        if !self.done {
+            record_method(&self.endpoint, &self.method, |metrics| {
+                metrics.abandoned += 1
+            });
+        }
+    }
+}
+
+/// writes the metrics recorded so far to the file at `path`.
+/// the file is replaced atomically so readers never see a partial export
+fn export(path: &PathBuf) -> Result<(), Error> {
+    let json = serde_json::to_string_pretty(&snapshot()).expect("serialization can't fail; qed");
+    let tmp = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp).chain_err(|| format!("Cannot create {:?}", tmp))?;
+    file.write_all(json.as_bytes())?;
+    fs::rename(&tmp, path).chain_err(|| format!("Cannot write metrics to {:?}", path))?;
+    Ok(())
+}
+
+/// creates a new `MetricsExport` that writes the metrics to `path` every tick of `interval`
+pub fn metrics_export(path: PathBuf, interval: Interval) -> MetricsExport {
+    MetricsExport { path, interval }
+}
+
+/// stream that exports the metrics on every tick of `interval`
+pub struct MetricsExport {
+    path: PathBuf,
+    interval: Interval,
+}
+
+impl Stream for MetricsExport {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let _ = try_stream!(self.interval.poll());
+        export(&self.path)?;
+        Ok(Async::Ready(Some(())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use supervisor;
+    use super::{record_relays, record_retry, relays, snapshot, total_relays, MethodMetrics,
+                LATENCY_BUCKETS_MS};
+
+    #[test]
+    fn test_observe() {
+        let mut metrics = MethodMetrics::default();
+        metrics.observe(Duration::from_millis(40), false);
+        metrics.observe(Duration::from_millis(600), true);
+        metrics.observe(Duration::from_secs(20), false);
+
+        assert_eq!(1, metrics.errors);
+        assert_eq!(LATENCY_BUCKETS_MS.len() + 1, metrics.latency_buckets.len());
+        assert_eq!(vec![0, 1, 1, 1, 1, 2, 2, 2, 2, 3], metrics.latency_buckets);
+        assert_eq!(20_640, metrics.latency_sum_ms);
+    }
+
+    #[test]
+    fn test_record_retry() {
+        record_retry("test_record_retry");
+        record_retry("test_record_retry");
+        assert_eq!(2, snapshot()["test_record_retry"].retries);
+    }
+
+    #[test]
+    fn test_record_relays() {
+        let before = total_relays();
+        {
+            let _entered = supervisor::enter("test_record_relays");
+            record_relays(|relays| relays.signatures += 3);
+        }
+        assert_eq!(3, relays()[&Some("test_record_relays".into())].signatures);
+        assert!(total_relays().signatures >= before.signatures + 3);
+    }
+}
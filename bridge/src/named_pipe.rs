@@ -0,0 +1,402 @@
+/// json-rpc transport over the named pipe of a node (example: `\\.\pipe\jsonrpc.ipc`),
+/// which is what `ipc` refers to on windows. the ipc transport of `web3` only supports
+/// unix sockets.
+/// a pipe handle opened without overlapped io can't be read while it's written,
+/// so requests are sent by a pool of threads that each hold a connection of their own
+/// and wait for the response before they send the next request.
+/// a connection is opened again for the next request after it broke.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll};
+use futures::sync::oneshot;
+use jsonrpc_core as rpc;
+use serde_json::{self, Value};
+use web3::{self, helpers, RequestId, Transport};
+use error::{Error, ResultExt};
+use websocket::call_result;
+
+/// number of threads, and so connections, that send requests to the node
+const WORKERS: usize = 4;
+/// milliseconds between attempts to open the pipe while all its instances are busy
+const BUSY_RETRY_INTERVAL: u64 = 50;
+/// `ERROR_PIPE_BUSY`. returned while every instance of the pipe is connected
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// opens a connection to the pipe at `path`.
+/// waits up to `timeout` while all instances of the pipe are busy
+pub fn open(path: &Path, timeout: Duration) -> Result<File, Error> {
+    let started = Instant::now();
+    loop {
+        match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => return Ok(file),
+            Err(ref err)
+                if err.raw_os_error() == Some(ERROR_PIPE_BUSY) && started.elapsed() < timeout =>
+            {
+                thread::sleep(Duration::from_millis(BUSY_RETRY_INTERVAL));
+            }
+            Err(err) => {
+                return Err(err).chain_err(|| format!("Cannot connect to node at {:?}", path))
+            }
+        }
+    }
+}
+
+/// reads the next message of the node
+pub fn read_message<R: Read>(reader: R) -> Result<Value, Error> {
+    serde_json::Deserializer::from_reader(reader)
+        .into_iter::<Value>()
+        .next()
+        .ok_or_else(|| Error::from("node closed the connection"))?
+        .chain_err(|| "Cannot parse message of node")
+}
+
+/// sends `request` on `connection` and returns the response.
+/// opens the connection first if there's none
+fn exchange(
+    path: &Path,
+    timeout: Duration,
+    connection: &mut Option<BufReader<File>>,
+    request: &str,
+) -> Result<Value, Error> {
+    if connection.is_none() {
+        *connection = Some(BufReader::new(open(path, timeout)?));
+    }
+    let reader = connection.as_mut().expect("opened above; qed");
+    writeln!(reader.get_mut(), "{}", request)?;
+    loop {
+        let message = read_message(&mut *reader)?;
+        // notifications of subscriptions have no id
+        if message.get("id").is_some() {
+            return Ok(message);
+        }
+    }
+}
+
+/// request that waits for its response
+struct Job {
+    request: String,
+    response: oneshot::Sender<Result<Value, web3::Error>>,
+}
+
+/// sends the jobs until all `NamedPipe`s are dropped
+fn work(path: Arc<PathBuf>, timeout: Duration, jobs: Arc<Mutex<Receiver<Job>>>) {
+    let mut connection = None;
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+        // the call timed out while it was queued
+        if job.response.is_canceled() {
+            continue;
+        }
+        let result = match exchange(&path, timeout, &mut connection, &job.request) {
+            Ok(response) => call_result(response),
+            Err(err) => {
+                connection = None;
+                Err(web3::Error::Transport(format!(
+                    "request to {:?} failed: {}",
+                    path, err
+                )))
+            }
+        };
+        let _ = job.response.send(result);
+    }
+}
+
+/// json-rpc transport over a named pipe
+#[derive(Clone)]
+pub struct NamedPipe {
+    path: Arc<PathBuf>,
+    id: Arc<AtomicUsize>,
+    jobs: Arc<Mutex<Sender<Job>>>,
+}
+
+impl fmt::Debug for NamedPipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NamedPipe").field("path", &self.path).finish()
+    }
+}
+
+impl NamedPipe {
+    /// sends requests to the pipe at `path`. fails if the pipe can't be opened.
+    /// waits up to `timeout` while all instances of the pipe are busy
+    pub fn connect(path: &Path, timeout: Duration) -> Result<Self, Error> {
+        open(path, timeout)?;
+        let path = Arc::new(path.to_owned());
+        let (sender, receiver) = channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..WORKERS {
+            let path = path.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("named-pipe-{}", index))
+                .spawn(move || work(path, timeout, receiver))?;
+        }
+        Ok(NamedPipe {
+            path,
+            id: Arc::new(AtomicUsize::new(1)),
+            jobs: Arc::new(Mutex::new(sender)),
+        })
+    }
+}
+
+impl Transport for NamedPipe {
+    type Out = NamedPipeCall;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.id.fetch_add(1, Ordering::SeqCst);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, _id: RequestId, request: rpc::Call) -> Self::Out {
+        let request = match serde_json::to_string(&request) {
+            Ok(request) => request,
+            Err(err) => {
+                return NamedPipeCall::Failed(Some(web3::Error::Transport(format!(
+                    "cannot serialize request: {}",
+                    err
+                ))))
+            }
+        };
+        let (sender, receiver) = oneshot::channel();
+        let job = Job {
+            request,
+            response: sender,
+        };
+        let sent = self.jobs
+            .lock()
+            .map_err(|_| ())
+            .and_then(|jobs| jobs.send(job).map_err(|_| ()));
+        match sent {
+            Ok(()) => NamedPipeCall::Pending(receiver),
+            Err(()) => NamedPipeCall::Failed(Some(web3::Error::Transport(format!(
+                "named pipe transport to {:?} stopped",
+                self.path
+            )))),
+        }
+    }
+}
+
+/// response of a call through a `NamedPipe`
+pub enum NamedPipeCall {
+    Pending(oneshot::Receiver<Result<Value, web3::Error>>),
+    Failed(Option<web3::Error>),
+}
+
+impl Future for NamedPipeCall {
+    type Item = Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            NamedPipeCall::Pending(ref mut receiver) => match receiver.poll() {
+                Ok(Async::Ready(result)) => result.map(Async::Ready),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(oneshot::Canceled) => Err(web3::Error::Transport(
+                    "named pipe transport dropped the request".into(),
+                )),
+            },
+            NamedPipeCall::Failed(ref mut err) => {
+                Err(err.take().expect("a failed call isn't polled again; qed"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+    use std::path::Path;
+    use std::time::Duration;
+    use super::{open, read_message};
+
+    #[test]
+    fn test_read_message() {
+        let messages = b"{\"id\":1,\"result\":\"0x1\"}\n{\"method\":\"eth_subscription\"}{\"id\":2}";
+        let mut reader = BufReader::new(Cursor::new(&messages[..]));
+        assert_eq!(Some(1), read_message(&mut reader).unwrap()["id"].as_u64());
+        assert!(read_message(&mut reader).unwrap().get("id").is_none());
+        assert_eq!(Some(2), read_message(&mut reader).unwrap()["id"].as_u64());
+        assert!(read_message(&mut reader).is_err());
+        assert!(read_message(&b"{\"id\":"[..]).is_err());
+    }
+
+    #[test]
+    fn test_open_missing_pipe() {
+        assert!(open(Path::new("missing-pipe.ipc"), Duration::from_secs(1)).is_err());
+    }
+
+    /// pipes served by the tests. they need the pipe api of windows to create pipes
+    #[cfg(windows)]
+    mod pipes {
+        use std::ffi::OsStr;
+        use std::fs::File;
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::raw::c_void;
+        use std::os::windows::ffi::OsStrExt;
+        use std::os::windows::io::FromRawHandle;
+        use std::path::{Path, PathBuf};
+        use std::process;
+        use std::ptr;
+        use std::sync::mpsc::channel;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use futures::Future;
+        use serde_json::{self, Value};
+        use web3::Transport;
+        use super::super::{exchange, open, NamedPipe};
+
+        const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+        const PIPE_TYPE_BYTE: u32 = 0x0;
+        const INVALID_HANDLE_VALUE: isize = -1;
+        /// `ERROR_PIPE_CONNECTED`. the client connected before `ConnectNamedPipe` was called
+        const ERROR_PIPE_CONNECTED: u32 = 535;
+        /// `ERROR_NO_DATA`. the client connected and closed before `ConnectNamedPipe` was called
+        const ERROR_NO_DATA: u32 = 232;
+
+        extern "system" {
+            fn CreateNamedPipeW(
+                name: *const u16,
+                open_mode: u32,
+                pipe_mode: u32,
+                max_instances: u32,
+                out_buffer_size: u32,
+                in_buffer_size: u32,
+                default_timeout: u32,
+                security_attributes: *mut c_void,
+            ) -> *mut c_void;
+            fn ConnectNamedPipe(pipe: *mut c_void, overlapped: *mut c_void) -> i32;
+            fn GetLastError() -> u32;
+        }
+
+        fn pipe_path(name: &str) -> PathBuf {
+            format!(r"\\.\pipe\parity-bridge-test-{}-{}", process::id(), name).into()
+        }
+
+        /// creates an instance of the pipe at `path` that allows `max_instances` instances
+        fn create(path: &Path, max_instances: u32) -> *mut c_void {
+            let name: Vec<u16> = OsStr::new(path).encode_wide().chain(Some(0)).collect();
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    name.as_ptr(),
+                    PIPE_ACCESS_DUPLEX,
+                    PIPE_TYPE_BYTE,
+                    max_instances,
+                    4096,
+                    4096,
+                    0,
+                    ptr::null_mut(),
+                )
+            };
+            assert!(handle as isize != INVALID_HANDLE_VALUE);
+            handle
+        }
+
+        /// waits for a client to connect to the instance `handle`
+        fn accept(handle: *mut c_void) -> File {
+            let connected = unsafe { ConnectNamedPipe(handle, ptr::null_mut()) };
+            if connected == 0 {
+                let error = unsafe { GetLastError() };
+                assert!(error == ERROR_PIPE_CONNECTED || error == ERROR_NO_DATA);
+            }
+            unsafe { File::from_raw_handle(handle as _) }
+        }
+
+        /// answers every request on `pipe` with a notification followed by a response
+        /// with the result `0x10`, until the client closes the connection
+        fn serve(pipe: File) {
+            let mut reader = BufReader::new(pipe);
+            loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => {}
+                }
+                let request: Value = serde_json::from_str(&line).unwrap();
+                write!(
+                    reader.get_mut(),
+                    "{{\"jsonrpc\":\"2.0\",\"method\":\"eth_subscription\",\"params\":{{}}}}\
+                     {{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":\"0x10\"}}\n",
+                    request["id"]
+                ).unwrap();
+            }
+        }
+
+        /// serves `connections` connections to the pipe at `path` one after another.
+        /// the next instance is created before a connection is served, so clients never
+        /// find the pipe missing. returns once the pipe is ready and the thread of the server
+        fn server(path: &Path, connections: usize) -> thread::JoinHandle<()> {
+            let path = path.to_owned();
+            let (ready, wait) = channel();
+            let server = thread::spawn(move || {
+                let mut next = create(&path, 255);
+                ready.send(()).unwrap();
+                for connection in 0..connections {
+                    let pipe = accept(next);
+                    if connection + 1 < connections {
+                        next = create(&path, 255);
+                    }
+                    serve(pipe);
+                }
+            });
+            wait.recv().unwrap();
+            server
+        }
+
+        #[test]
+        fn test_exchange() {
+            let path = pipe_path("exchange");
+            let server = server(&path, 1);
+            let timeout = Duration::from_secs(5);
+            let mut connection = None;
+            for id in 1..3 {
+                let request = format!(
+                    "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"eth_blockNumber\"}}",
+                    id
+                );
+                let response = exchange(&path, timeout, &mut connection, &request).unwrap();
+                assert_eq!(Some(id), response["id"].as_u64());
+                assert_eq!("0x10", response["result"]);
+            }
+            drop(connection);
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn test_named_pipe_transport() {
+            let path = pipe_path("transport");
+            // `connect` checks that the pipe can be opened, the worker connects again
+            let server = server(&path, 2);
+            let transport = NamedPipe::connect(&path, Duration::from_secs(5)).unwrap();
+            let result = transport.execute("eth_blockNumber", vec![]).wait().unwrap();
+            assert_eq!(Value::String("0x10".into()), result);
+            // the workers close their connections once the transport is dropped
+            drop(transport);
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn test_open_busy_pipe() {
+            let path = pipe_path("busy");
+            let handle = create(&path, 1);
+            let _client = open(&path, Duration::from_secs(1)).unwrap();
+            let _server = accept(handle);
+            let started = Instant::now();
+            let timeout = Duration::from_millis(200);
+            assert!(open(&path, timeout).is_err());
+            assert!(started.elapsed() >= timeout);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+/// named presets for well known pairs of chains, selected with `network` in the config.
+/// a preset fills in the chain ids, the ENS registry, confirmation depths, poll intervals
+/// and the gas of the transactions the config doesn't set itself.
+/// options set in the config always take precedence over the preset.
+/// the bridge contracts are deployed by the authorities, so their addresses are never preset.
+
+/// defaults of a chain
+#[derive(Debug, PartialEq)]
+pub struct ChainPreset {
+    /// chain id the nodes have to report. `None` for private chains
+    pub chain_id: Option<u64>,
+    pub required_confirmations: usize,
+    /// seconds
+    pub poll_interval: u64,
+}
+
+/// defaults of a transaction
+#[derive(Debug, PartialEq)]
+pub struct GasPreset {
+    pub gas: u64,
+    pub gas_price: u64,
+}
+
+/// defaults of a pair of chains
+#[derive(Debug, PartialEq)]
+pub struct NetworkPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub home: ChainPreset,
+    pub foreign: ChainPreset,
+    /// ENS registry on `home`. `None` if ENS isn't deployed there
+    pub ens_registry: Option<&'static str>,
+    pub home_deploy: GasPreset,
+    pub withdraw_relay: GasPreset,
+    pub foreign_deploy: GasPreset,
+    pub deposit_relay: GasPreset,
+    pub withdraw_confirm: GasPreset,
+}
+
+const GWEI: u64 = 1_000_000_000;
+
+/// ENS registry on mainnet, ropsten, rinkeby and goerli
+const ENS_REGISTRY: &str = "00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+pub const PRESETS: &[NetworkPreset] = &[
+    NetworkPreset {
+        name: "kovan-sokol",
+        description: "kovan (home) and the sokol testnet of POA Network (foreign)",
+        home: ChainPreset {
+            chain_id: Some(42),
+            required_confirmations: 8,
+            poll_interval: 4,
+        },
+        foreign: ChainPreset {
+            chain_id: Some(77),
+            required_confirmations: 8,
+            poll_interval: 5,
+        },
+        ens_registry: None,
+        home_deploy: GasPreset {
+            gas: 1_000_000,
+            gas_price: GWEI,
+        },
+        withdraw_relay: GasPreset {
+            gas: 200_000,
+            gas_price: 0,
+        },
+        foreign_deploy: GasPreset {
+            gas: 3_000_000,
+            gas_price: GWEI,
+        },
+        deposit_relay: GasPreset {
+            gas: 150_000,
+            gas_price: GWEI,
+        },
+        withdraw_confirm: GasPreset {
+            gas: 300_000,
+            gas_price: GWEI,
+        },
+    },
+    NetworkPreset {
+        name: "goerli-test",
+        description: "goerli (home) and a private proof of authority test chain (foreign)",
+        home: ChainPreset {
+            chain_id: Some(5),
+            required_confirmations: 6,
+            poll_interval: 15,
+        },
+        foreign: ChainPreset {
+            chain_id: None,
+            required_confirmations: 2,
+            poll_interval: 5,
+        },
+        ens_registry: Some(ENS_REGISTRY),
+        home_deploy: GasPreset {
+            gas: 1_000_000,
+            gas_price: 2 * GWEI,
+        },
+        withdraw_relay: GasPreset {
+            gas: 200_000,
+            gas_price: 0,
+        },
+        foreign_deploy: GasPreset {
+            gas: 3_000_000,
+            gas_price: 0,
+        },
+        deposit_relay: GasPreset {
+            gas: 150_000,
+            gas_price: 0,
+        },
+        withdraw_confirm: GasPreset {
+            gas: 300_000,
+            gas_price: 0,
+        },
+    },
+    NetworkPreset {
+        name: "mainnet-poa",
+        description: "ethereum mainnet (home) and the core network of POA Network (foreign)",
+        home: ChainPreset {
+            chain_id: Some(1),
+            required_confirmations: 12,
+            poll_interval: 15,
+        },
+        foreign: ChainPreset {
+            chain_id: Some(99),
+            required_confirmations: 12,
+            poll_interval: 5,
+        },
+        ens_registry: Some(ENS_REGISTRY),
+        home_deploy: GasPreset {
+            gas: 1_000_000,
+            gas_price: 20 * GWEI,
+        },
+        withdraw_relay: GasPreset {
+            gas: 200_000,
+            gas_price: 0,
+        },
+        foreign_deploy: GasPreset {
+            gas: 3_000_000,
+            gas_price: GWEI,
+        },
+        deposit_relay: GasPreset {
+            gas: 150_000,
+            gas_price: GWEI,
+        },
+        withdraw_confirm: GasPreset {
+            gas: 300_000,
+            gas_price: GWEI,
+        },
+    },
+];
+
+/// returns the preset called `name`
+pub fn preset(name: &str) -> Option<&'static NetworkPreset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+/// names of all presets
+pub fn names() -> Vec<&'static str> {
+    PRESETS.iter().map(|preset| preset.name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{names, preset, PRESETS};
+
+    #[test]
+    fn test_preset() {
+        assert_eq!(Some(1), preset("mainnet-poa").unwrap().home.chain_id);
+        assert_eq!(None, preset("mainnet"));
+        assert_eq!(PRESETS.len(), names().len());
+    }
+
+    #[test]
+    fn test_presets_connect_different_chains() {
+        for preset in PRESETS {
+            assert!(preset.home.chain_id != preset.foreign.chain_id, "{}", preset.name);
+        }
+    }
+}
@@ -0,0 +1,648 @@
+/// export of traces and metrics to an opentelemetry collector over OTLP/HTTP with json encoding.
+/// every transfer is a trace whose id is derived from the transaction of the deposit or withdraw,
+/// so the spans all authorities export for the same transfer end up in the same trace:
+/// `withdraw.sign` from the withdraw being picked up to the signature being submitted and
+/// `deposit.relay` or `withdraw.relay` from the block of the event to the relay (chain time).
+/// the metrics are the rpc metrics of `metrics` and the lag and in flight transactions of `status`.
+/// spans are buffered and exported together with the metrics by a thread of their own.
+/// the collector is reached over http or https through `http_client::request`.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use rand;
+use serde_json;
+use web3::types::H256;
+use config::OtlpConfig;
+use error::{Error, ResultExt};
+use event_queue::TransferEvent;
+use history::{Transfer, TransferDirection};
+use http_client;
+use metrics::{self, Metrics, LATENCY_BUCKETS_MS};
+use status::{self, Status};
+
+/// spans kept while the collector is unreachable. the oldest are dropped beyond it
+const MAX_BUFFERED_SPANS: usize = 10_000;
+/// seconds to wait for the collector to accept a connection and respond
+const EXPORT_TIMEOUT: u64 = 5;
+/// `SPAN_KIND_INTERNAL`
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_CODE_OK: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+/// `AGGREGATION_TEMPORALITY_CUMULATIVE`
+const CUMULATIVE: u8 = 2;
+
+static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// operation on a transfer
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub name: &'static str,
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    /// nanoseconds since the unix epoch
+    pub start: u64,
+    pub end: u64,
+    pub attributes: Vec<(&'static str, String)>,
+    pub error: bool,
+}
+
+/// id of the trace of the transfer of the deposit or withdraw `transaction`
+pub fn trace_id(transaction: &H256) -> [u8; 16] {
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&transaction.0[..16]);
+    id
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH)
+        .expect("system time is after the unix epoch; qed");
+    since_epoch.as_secs() * 1_000_000_000 + u64::from(since_epoch.subsec_nanos())
+}
+
+fn spans() -> &'static Mutex<VecDeque<Span>> {
+    static INIT: Once = ONCE_INIT;
+    static mut SPANS: *const Mutex<VecDeque<Span>> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            SPANS = Box::into_raw(Box::new(Mutex::new(VecDeque::new())));
+        });
+        &*SPANS
+    }
+}
+
+fn record(span: Span) {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut spans = spans().lock().expect("spans are never poisoned; qed");
+    if spans.len() == MAX_BUFFERED_SPANS {
+        spans.pop_front();
+    }
+    spans.push_back(span);
+}
+
+/// span of the relay of `transfer` from the block of its event to the relay.
+/// `None` if either time is unknown
+pub fn transfer_span(transfer: &Transfer) -> Option<Span> {
+    let (start, end) = match (transfer.block_timestamp, transfer.relayed_at) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return None,
+    };
+    let mut attributes = vec![
+        ("bridge.transaction_hash", format!("{:?}", transfer.transaction_hash)),
+        ("bridge.recipient", format!("{:?}", transfer.recipient)),
+        ("bridge.value", transfer.value.to_string()),
+        ("bridge.block_number", transfer.block_number.to_string()),
+    ];
+    match transfer.relay_transaction_hash {
+        Some(hash) => attributes.push(("bridge.relay_transaction_hash", format!("{:?}", hash))),
+        None => attributes.push(("bridge.skipped", "true".into())),
+    }
+    Some(Span {
+        name: match transfer.direction {
+            TransferDirection::Deposit => "deposit.relay",
+            TransferDirection::Withdraw => "withdraw.relay",
+        },
+        trace_id: trace_id(&transfer.transaction_hash),
+        span_id: rand::random(),
+        start: start * 1_000_000_000,
+        end: end.max(start) * 1_000_000_000,
+        attributes,
+        error: false,
+    })
+}
+
+/// records the spans of the relays of `transfers`
+pub fn transfers(transfers: &[Transfer]) {
+    for span in transfers.iter().filter_map(transfer_span) {
+        record(span);
+    }
+}
+
+/// records the spans of the signatures of `events` whose signing started at `started`
+pub fn signatures(events: &[TransferEvent], started: SystemTime) {
+    let end = nanos(SystemTime::now());
+    for event in events {
+        let mut attributes = vec![
+            ("bridge.transaction_hash", format!("{:?}", event.transaction_hash)),
+            ("bridge.recipient", format!("{:?}", event.recipient)),
+            ("bridge.value", event.value.to_string()),
+        ];
+        if let Some(hash) = event.authority_transaction_hash {
+            attributes.push(("bridge.signature_transaction_hash", format!("{:?}", hash)));
+        }
+        record(Span {
+            name: "withdraw.sign",
+            trace_id: trace_id(&event.transaction_hash),
+            span_id: rand::random(),
+            start: nanos(started),
+            end,
+            attributes,
+            error: false,
+        });
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    string_value: String,
+}
+
+fn key_value(key: &str, value: String) -> KeyValue {
+    KeyValue {
+        key: key.into(),
+        value: AnyValue {
+            string_value: value,
+        },
+    }
+}
+
+#[derive(Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+const SCOPE: Scope = Scope {
+    name: "parity-bridge",
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TracesRequest {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Serialize)]
+struct ScopeSpans {
+    scope: Scope,
+    spans: Vec<SpanJson>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpanJson {
+    trace_id: String,
+    span_id: String,
+    name: &'static str,
+    kind: u8,
+    /// 64 bit integers are strings in the json encoding of OTLP
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    status: SpanStatus,
+}
+
+#[derive(Serialize)]
+struct SpanStatus {
+    code: u8,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn traces_request(service_name: &str, spans: &[Span]) -> TracesRequest {
+    TracesRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Resource {
+                attributes: vec![key_value("service.name", service_name.into())],
+            },
+            scope_spans: vec![ScopeSpans {
+                scope: SCOPE,
+                spans: spans
+                    .iter()
+                    .map(|span| SpanJson {
+                        trace_id: to_hex(&span.trace_id),
+                        span_id: to_hex(&span.span_id),
+                        name: span.name,
+                        kind: SPAN_KIND_INTERNAL,
+                        start_time_unix_nano: span.start.to_string(),
+                        end_time_unix_nano: span.end.to_string(),
+                        attributes: span.attributes
+                            .iter()
+                            .map(|&(key, ref value)| key_value(key, value.clone()))
+                            .collect(),
+                        status: SpanStatus {
+                            code: if span.error {
+                                STATUS_CODE_ERROR
+                            } else {
+                                STATUS_CODE_OK
+                            },
+                        },
+                    })
+                    .collect(),
+            }],
+        }],
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetricsRequest {
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceMetrics {
+    resource: Resource,
+    scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Serialize)]
+struct ScopeMetrics {
+    scope: Scope,
+    metrics: Vec<Metric>,
+}
+
+#[derive(Serialize)]
+struct Metric {
+    name: &'static str,
+    unit: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sum: Option<Sum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gauge: Option<Gauge>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    histogram: Option<Histogram>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Sum {
+    aggregation_temporality: u8,
+    is_monotonic: bool,
+    data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Gauge {
+    data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NumberDataPoint {
+    attributes: Vec<KeyValue>,
+    start_time_unix_nano: String,
+    time_unix_nano: String,
+    as_int: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Histogram {
+    aggregation_temporality: u8,
+    data_points: Vec<HistogramDataPoint>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HistogramDataPoint {
+    attributes: Vec<KeyValue>,
+    start_time_unix_nano: String,
+    time_unix_nano: String,
+    count: String,
+    sum: f64,
+    bucket_counts: Vec<String>,
+    explicit_bounds: Vec<f64>,
+}
+
+/// converts cumulative latency buckets (`metrics::MethodMetrics::latency_buckets`)
+/// into the counts per bucket OTLP expects
+fn bucket_counts(cumulative: &[u64]) -> Vec<u64> {
+    cumulative
+        .iter()
+        .scan(0, |previous, &count| {
+            let bucket = count.saturating_sub(*previous);
+            *previous = count;
+            Some(bucket)
+        })
+        .collect()
+}
+
+fn metric(name: &'static str, unit: &'static str) -> Metric {
+    Metric {
+        name,
+        unit,
+        sum: None,
+        gauge: None,
+        histogram: None,
+    }
+}
+
+/// converts the rpc `metrics` and the `status` into a request.
+/// `start` and `now` are nanoseconds since the unix epoch
+fn metrics_request(
+    service_name: &str,
+    metrics: &Metrics,
+    status: &Status,
+    start: u64,
+    now: u64,
+) -> MetricsRequest {
+    let point = |attributes: Vec<KeyValue>, value: u64| NumberDataPoint {
+        attributes,
+        start_time_unix_nano: start.to_string(),
+        time_unix_nano: now.to_string(),
+        as_int: value.to_string(),
+    };
+    let counter = |name, unit, points| Metric {
+        sum: Some(Sum {
+            aggregation_temporality: CUMULATIVE,
+            is_monotonic: true,
+            data_points: points,
+        }),
+        ..metric(name, unit)
+    };
+    let gauge = |name, unit, points| Metric {
+        gauge: Some(Gauge {
+            data_points: points,
+        }),
+        ..metric(name, unit)
+    };
+    let method_attributes = |endpoint: &str, method: &str| {
+        vec![
+            key_value("endpoint", endpoint.into()),
+            key_value("method", method.into()),
+        ]
+    };
+
+    let methods = metrics
+        .iter()
+        .flat_map(|(endpoint, metrics)| {
+            metrics
+                .methods
+                .iter()
+                .map(move |(method, metrics)| (endpoint, method, metrics))
+        })
+        .collect::<Vec<_>>();
+    let method_counter = |name, select: fn(&metrics::MethodMetrics) -> u64| {
+        counter(
+            name,
+            "1",
+            methods
+                .iter()
+                .map(|&(endpoint, method, metrics)| {
+                    point(method_attributes(endpoint, method), select(metrics))
+                })
+                .collect(),
+        )
+    };
+    let endpoint_counter = |name, select: fn(&metrics::EndpointMetrics) -> u64| {
+        counter(
+            name,
+            "1",
+            metrics
+                .iter()
+                .map(|(endpoint, metrics)| {
+                    point(vec![key_value("endpoint", endpoint.clone())], select(metrics))
+                })
+                .collect(),
+        )
+    };
+
+    let mut result = vec![
+        method_counter("bridge.rpc.calls", |metrics| metrics.calls),
+        method_counter("bridge.rpc.errors", |metrics| metrics.errors),
+        method_counter("bridge.rpc.abandoned", |metrics| metrics.abandoned),
+        endpoint_counter("bridge.rpc.retries", |metrics| metrics.retries),
+        endpoint_counter("bridge.rpc.rate_limited", |metrics| metrics.rate_limited),
+        Metric {
+            histogram: Some(Histogram {
+                aggregation_temporality: CUMULATIVE,
+                data_points: methods
+                    .iter()
+                    .map(|&(endpoint, method, metrics)| HistogramDataPoint {
+                        attributes: method_attributes(endpoint, method),
+                        start_time_unix_nano: start.to_string(),
+                        time_unix_nano: now.to_string(),
+                        count: metrics.latency_buckets.last().cloned().unwrap_or(0).to_string(),
+                        sum: metrics.latency_sum_ms as f64,
+                        bucket_counts: bucket_counts(&metrics.latency_buckets)
+                            .into_iter()
+                            .map(|count| count.to_string())
+                            .collect(),
+                        explicit_bounds: LATENCY_BUCKETS_MS.iter().map(|&ms| ms as f64).collect(),
+                    })
+                    .collect(),
+            }),
+            ..metric("bridge.rpc.latency", "ms")
+        },
+        gauge(
+            "bridge.in_flight_transactions",
+            "1",
+            vec![point(vec![], status.in_flight_transactions as u64)],
+        ),
+    ];
+    let lags = [
+        ("deposit_relay", status.deposit_relay_lag()),
+        ("withdraw_confirm", status.withdraw_confirm_lag()),
+        ("withdraw_relay", status.withdraw_relay_lag()),
+    ];
+    let lag_points = lags.iter()
+        .filter_map(|&(component, lag)| {
+            lag.map(|lag| point(vec![key_value("component", component.into())], lag))
+        })
+        .collect::<Vec<_>>();
+    if !lag_points.is_empty() {
+        result.push(gauge("bridge.lag", "{block}", lag_points));
+    }
+
+    MetricsRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Resource {
+                attributes: vec![key_value("service.name", service_name.into())],
+            },
+            scope_metrics: vec![ScopeMetrics {
+                scope: SCOPE,
+                metrics: result,
+            }],
+        }],
+    }
+}
+
+/// url of `path` of the collector at `endpoint`.
+/// `endpoint` is an `http://` or `https://` url or a `host:port` reached over http
+fn url(endpoint: &str, path: &str) -> String {
+    if endpoint.contains("://") {
+        format!("{}{}", endpoint.trim_right_matches('/'), path)
+    } else {
+        format!("http://{}{}", endpoint, path)
+    }
+}
+
+/// posts the json `body` to `path` of the collector at `endpoint`
+fn post(endpoint: &str, path: &str, body: &[u8]) -> Result<(), Error> {
+    let timeout = Duration::from_secs(EXPORT_TIMEOUT);
+    http_client::request("POST", &url(endpoint, path), Some(body), timeout)
+        .chain_err(|| format!("Cannot post to otlp endpoint {}", endpoint))?;
+    Ok(())
+}
+
+/// exports the buffered spans and the current metrics.
+/// spans are put back if the collector doesn't accept them
+fn export(config: &OtlpConfig, start: u64) -> Result<(), Error> {
+    let buffered = spans()
+        .lock()
+        .expect("spans are never poisoned; qed")
+        .drain(..)
+        .collect::<Vec<_>>();
+    if !buffered.is_empty() {
+        let body = serde_json::to_vec(&traces_request(&config.service_name, &buffered))
+            .expect("serialization can't fail; qed");
+        if let Err(err) = post(&config.endpoint, "/v1/traces", &body) {
+            let mut spans = spans().lock().expect("spans are never poisoned; qed");
+            for span in buffered.into_iter().rev() {
+                if spans.len() < MAX_BUFFERED_SPANS {
+                    spans.push_front(span);
+                }
+            }
+            return Err(err);
+        }
+    }
+
+    let request = metrics_request(
+        &config.service_name,
+        &metrics::snapshot(),
+        &status::snapshot(),
+        start,
+        nanos(SystemTime::now()),
+    );
+    let body = serde_json::to_vec(&request).expect("serialization can't fail; qed");
+    post(&config.endpoint, "/v1/metrics", &body)
+}
+
+/// starts recording spans and the thread that exports them and the metrics
+/// every `config.export_interval`
+pub fn start(config: &Option<OtlpConfig>) {
+    let config = match *config {
+        Some(ref config) => config.clone(),
+        None => return,
+    };
+    ENABLED.store(true, Ordering::SeqCst);
+    let start = nanos(SystemTime::now());
+    info!("exporting traces and metrics to {}", config.endpoint);
+    thread::spawn(move || loop {
+        thread::sleep(config.export_interval);
+        if let Err(err) = export(&config, start) {
+            warn!("otlp export failed: {}", err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use history::{Transfer, TransferDirection};
+    use metrics::{EndpointMetrics, Metrics, MethodMetrics};
+    use status::Status;
+    use super::{bucket_counts, metrics_request, trace_id, traces_request, transfer_span, url};
+
+    fn transfer() -> Transfer {
+        Transfer {
+            direction: TransferDirection::Withdraw,
+            block_number: 1,
+            block_timestamp: Some(100),
+            transaction_hash: 2.into(),
+            recipient: 3.into(),
+            value: 4.into(),
+            relay_transaction_hash: Some(5.into()),
+            relayed_at: Some(130),
+            fee: None,
+        }
+    }
+
+    #[test]
+    fn test_transfer_span() {
+        let span = transfer_span(&transfer()).unwrap();
+        assert_eq!("withdraw.relay", span.name);
+        assert_eq!(trace_id(&2.into()), span.trace_id);
+        assert_eq!(100_000_000_000, span.start);
+        assert_eq!(130_000_000_000, span.end);
+
+        let mut untimed = transfer();
+        untimed.relayed_at = None;
+        assert_eq!(None, transfer_span(&untimed));
+    }
+
+    #[test]
+    fn test_traces_request() {
+        let mut span = transfer_span(&transfer()).unwrap();
+        span.span_id = [1; 8];
+        let json = serde_json::to_value(&traces_request("bridge", &[span])).unwrap();
+        let span = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!("0101010101010101", span["spanId"]);
+        assert_eq!(32, span["traceId"].as_str().unwrap().len());
+        assert_eq!("100000000000", span["startTimeUnixNano"]);
+        assert_eq!(
+            "bridge",
+            json["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"]
+        );
+    }
+
+    #[test]
+    fn test_bucket_counts() {
+        assert_eq!(vec![0, 1, 0, 2], bucket_counts(&[0, 1, 1, 3]));
+    }
+
+    #[test]
+    fn test_metrics_request() {
+        let mut endpoint = EndpointMetrics::default();
+        let mut method = MethodMetrics::default();
+        method.calls = 3;
+        endpoint.methods.insert("eth_call".into(), method);
+        let mut metrics = Metrics::new();
+        metrics.insert("home.ipc".into(), endpoint);
+        let status = Status {
+            home_block: Some(10),
+            checked_deposit_relay: Some(7),
+            ..Default::default()
+        };
+
+        let json =
+            serde_json::to_value(&metrics_request("bridge", &metrics, &status, 1, 2)).unwrap();
+        let metrics = json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+            .as_array()
+            .unwrap();
+        assert_eq!("bridge.rpc.calls", metrics[0]["name"]);
+        assert_eq!("3", metrics[0]["sum"]["dataPoints"][0]["asInt"]);
+        let lag = metrics.last().unwrap();
+        assert_eq!("bridge.lag", lag["name"]);
+        assert_eq!("3", lag["gauge"]["dataPoints"][0]["asInt"]);
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!("http://127.0.0.1:4318/v1/traces", url("127.0.0.1:4318", "/v1/traces"));
+        assert_eq!(
+            "https://collector:4318/v1/metrics",
+            url("https://collector:4318/", "/v1/metrics")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+/// pacing of the rpc calls to a node.
+/// hosted rpc providers limit the rate of requests. they answer excess requests
+/// with http status 429 or with json-rpc error `-32005` (`limit exceeded`)
+/// whose data may carry the time to back off in `backoff_seconds`.
+/// retrying limited calls right away degenerates into retry storms.
+/// instead the calls to a node are spaced by an interval that adapts to its rate limit:
+/// the interval doubles whenever the node limits a call and shrinks again while calls succeed.
+/// a limited call is retried once the backoff requested by the node or the interval has passed.
+/// `max_requests_per_second` of a node bounds the interval from below.
+
+use std::{cmp, fmt};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll};
+use jsonrpc_core as rpc;
+use tokio_timer::{Sleep, Timer};
+use web3::{self, RequestId, Transport};
+use metrics::record_rate_limited;
+
+/// json-rpc error code of calls rejected by a rate limit
+const LIMIT_EXCEEDED: i64 = -32005;
+/// interval after the first limited call to a node that isn't paced yet
+const MIN_BACKOFF_INTERVAL_MS: u64 = 50;
+/// upper bound of the interval between two calls
+const MAX_INTERVAL_MS: u64 = 10_000;
+/// upper bound of the backoff requested by a node
+const MAX_BACKOFF_MS: u64 = 60_000;
+/// number of times a limited call is retried before its error is returned
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// returns true if the node rejected a call because of its rate limit
+pub fn is_rate_limited(err: &web3::Error) -> bool {
+    match *err {
+        web3::Error::Rpc(ref rpc_error) => rpc_error.code.code() == LIMIT_EXCEEDED,
+        web3::Error::Transport(ref message) => message.contains("429"),
+        _ => false,
+    }
+}
+
+/// returns the time a rate limited node asks to wait before the next call
+fn requested_backoff(err: &web3::Error) -> Option<Duration> {
+    let seconds = match *err {
+        web3::Error::Rpc(ref rpc_error) => rpc_error
+            .data
+            .as_ref()
+            .and_then(|data| data.get("backoff_seconds"))
+            .and_then(|seconds| seconds.as_f64())?,
+        _ => return None,
+    };
+    if seconds <= 0.0 {
+        return None;
+    }
+    let millis = cmp::min((seconds * 1000.0) as u64, MAX_BACKOFF_MS);
+    Some(Duration::from_millis(millis))
+}
+
+/// schedule of the calls to a node
+#[derive(Debug, PartialEq)]
+struct Pacer {
+    /// lower bound of `interval`
+    min_interval: Duration,
+    /// time between two calls
+    interval: Duration,
+    /// earliest time the next call may be sent
+    next_call: Option<Instant>,
+}
+
+impl Pacer {
+    fn new(max_requests_per_second: Option<u32>) -> Self {
+        let min_interval = match max_requests_per_second {
+            Some(rate) if rate > 0 => Duration::from_secs(1) / rate,
+            _ => Duration::from_secs(0),
+        };
+        Pacer {
+            min_interval,
+            interval: min_interval,
+            next_call: None,
+        }
+    }
+
+    /// reserves the next free slot for a call and returns the time to wait for it
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let slot = match self.next_call {
+            Some(next_call) if next_call > now => next_call,
+            _ => now,
+        };
+        self.next_call = Some(slot + self.interval);
+        slot - now
+    }
+
+    /// widens the interval after the node limited a call.
+    /// no call is sent before `backoff` has passed
+    fn limited(&mut self, now: Instant, backoff: Option<Duration>) {
+        self.interval = cmp::min(
+            cmp::max(
+                self.interval * 2,
+                Duration::from_millis(MIN_BACKOFF_INTERVAL_MS),
+            ),
+            Duration::from_millis(MAX_INTERVAL_MS),
+        );
+        let resume = now + backoff.unwrap_or(self.interval);
+        if self.next_call.map_or(true, |next_call| next_call < resume) {
+            self.next_call = Some(resume);
+        }
+    }
+
+    /// narrows the interval after a call succeeded
+    fn succeeded(&mut self) {
+        self.interval = cmp::max(self.interval - self.interval / 16, self.min_interval);
+    }
+}
+
+/// transport that paces the calls to `endpoint` made through it
+#[derive(Clone)]
+pub struct Paced<T> {
+    transport: T,
+    endpoint: String,
+    timer: Timer,
+    pacer: Arc<Mutex<Pacer>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Paced<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Paced")
+            .field("transport", &self.transport)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl<T> Paced<T> {
+    /// sends at most `max_requests_per_second` calls to `endpoint` if set
+    pub fn new(transport: T, endpoint: String, max_requests_per_second: Option<u32>) -> Self {
+        Paced {
+            transport,
+            endpoint,
+            timer: Timer::default(),
+            pacer: Arc::new(Mutex::new(Pacer::new(max_requests_per_second))),
+        }
+    }
+
+    fn pacer<R, F: FnOnce(&mut Pacer) -> R>(&self, f: F) -> R {
+        f(&mut self.pacer.lock().expect("pacer is never poisoned; qed"))
+    }
+}
+
+impl<T: Transport> Transport for Paced<T> {
+    type Out = PacedCall<T>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let delay = self.pacer(|pacer| pacer.reserve(Instant::now()));
+        let state = if delay == Duration::from_secs(0) {
+            PacedCallState::Call(self.transport.send(id, request.clone()))
+        } else {
+            PacedCallState::Wait(self.timer.sleep(delay))
+        };
+        PacedCall {
+            paced: self.clone(),
+            id,
+            request,
+            retries: 0,
+            state,
+        }
+    }
+}
+
+/// state of a paced call
+enum PacedCallState<F> {
+    /// waiting for the slot of the call
+    Wait(Sleep),
+    /// call is in progress
+    Call(F),
+}
+
+/// rpc call that is sent in its slot and retried while the node limits it
+pub struct PacedCall<T: Transport> {
+    paced: Paced<T>,
+    id: RequestId,
+    request: rpc::Call,
+    retries: u32,
+    state: PacedCallState<T::Out>,
+}
+
+impl<T: Transport> Future for PacedCall<T> {
+    type Item = rpc::Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                PacedCallState::Wait(ref mut sleep) => {
+                    match sleep.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(())) => {}
+                        Err(err) => {
+                            return Err(web3::Error::Transport(format!(
+                                "pacing timer failed: {}",
+                                err
+                            )))
+                        }
+                    }
+                    PacedCallState::Call(
+                        self.paced
+                            .transport
+                            .send(self.id, self.request.clone()),
+                    )
+                }
+                PacedCallState::Call(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(value)) => {
+                        self.paced.pacer(|pacer| pacer.succeeded());
+                        return Ok(Async::Ready(value));
+                    }
+                    Err(err) => {
+                        if !is_rate_limited(&err) {
+                            return Err(err);
+                        }
+                        record_rate_limited(&self.paced.endpoint);
+                        let now = Instant::now();
+                        let backoff = requested_backoff(&err);
+                        self.paced.pacer(|pacer| pacer.limited(now, backoff));
+                        if self.retries == MAX_RATE_LIMIT_RETRIES {
+                            return Err(err);
+                        }
+                        self.retries += 1;
+                        let delay = self.paced.pacer(|pacer| pacer.reserve(now));
+                        warn!(
+                            "{} limited the rate of calls. retrying in {:?}",
+                            self.paced.endpoint, delay
+                        );
+                        PacedCallState::Wait(self.paced.timer.sleep(delay))
+                    }
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use jsonrpc_core as rpc;
+    use web3;
+    use super::{is_rate_limited, requested_backoff, Pacer};
+
+    fn limit_exceeded(data: Option<rpc::Value>) -> web3::Error {
+        web3::Error::Rpc(rpc::Error {
+            code: rpc::ErrorCode::ServerError(-32005),
+            message: "limit exceeded".into(),
+            data,
+        })
+    }
+
+    #[test]
+    fn test_is_rate_limited() {
+        assert!(is_rate_limited(&limit_exceeded(None)));
+        assert!(is_rate_limited(&web3::Error::Transport(
+            "Unexpected response status code: 429 Too Many Requests".into()
+        )));
+        assert!(!is_rate_limited(&web3::Error::Rpc(
+            rpc::Error::internal_error()
+        )));
+    }
+
+    #[test]
+    fn test_requested_backoff() {
+        let data = ::serde_json::from_str(r#"{"backoff_seconds": 1.5}"#).unwrap();
+        assert_eq!(
+            Some(Duration::from_millis(1500)),
+            requested_backoff(&limit_exceeded(Some(data)))
+        );
+        assert_eq!(None, requested_backoff(&limit_exceeded(None)));
+    }
+
+    #[test]
+    fn test_pacer_unpaced() {
+        let mut pacer = Pacer::new(None);
+        let now = Instant::now();
+        assert_eq!(Duration::from_secs(0), pacer.reserve(now));
+        assert_eq!(Duration::from_secs(0), pacer.reserve(now));
+    }
+
+    #[test]
+    fn test_pacer_spaces_calls() {
+        let mut pacer = Pacer::new(Some(4));
+        let now = Instant::now();
+        assert_eq!(Duration::from_secs(0), pacer.reserve(now));
+        assert_eq!(Duration::from_millis(250), pacer.reserve(now));
+        assert_eq!(Duration::from_millis(500), pacer.reserve(now));
+    }
+
+    #[test]
+    fn test_pacer_adapts() {
+        let mut pacer = Pacer::new(None);
+        let now = Instant::now();
+        pacer.limited(now, None);
+        assert_eq!(Duration::from_millis(50), pacer.interval);
+        assert_eq!(Duration::from_millis(50), pacer.reserve(now));
+        pacer.limited(now, Some(Duration::from_secs(2)));
+        assert_eq!(Duration::from_millis(100), pacer.interval);
+        assert_eq!(Duration::from_secs(2), pacer.reserve(now));
+
+        for _ in 0..100 {
+            pacer.succeeded();
+        }
+        assert!(pacer.interval < Duration::from_millis(1));
+
+        let mut pacer = Pacer::new(Some(10));
+        pacer.limited(now, None);
+        for _ in 0..100 {
+            pacer.succeeded();
+        }
+        assert_eq!(Duration::from_millis(100), pacer.interval);
+    }
+}
@@ -0,0 +1,358 @@
+/// relays that can't complete now and are retried apart from their relay stream.
+/// a parked relay no longer holds up the batch of events it belongs to,
+/// so the checkpoint of the relay stream advances past it.
+/// the parked relays are saved in the database with the checkpoints and retried
+/// by `bridge::ParkedRelays` once they can complete, also after a restart.
+/// withdraws are parked while the balance of `HomeBridge` doesn't cover them.
+/// relays whose simulation reverted are parked and retried every
+/// `reverted_relays.retry_interval` until `reverted_relays.max_attempts` is reached.
+/// they are given up then and kept as dead letters in the database
+/// with the revert reason of their last attempt, to be inspected or replayed.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use web3::types::{Address, Bytes, H256, TransactionRequest, U256};
+use signer::Chain;
+
+/// why a relay was parked
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParkReason {
+    /// the balance of `HomeBridge` doesn't cover the value of the withdraw
+    InsufficientBalance,
+    /// the simulation of the transaction reverted
+    Reverted,
+}
+
+/// relay transaction that is retried apart from its relay stream
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ParkedRelay {
+    /// chain the transaction is sent to
+    pub chain: Chain,
+    /// transaction of the transfer on the other chain
+    pub origin: H256,
+    /// value of the transfer
+    pub value: U256,
+    pub reason: ParkReason,
+    pub to: Address,
+    pub data: Bytes,
+    pub gas: U256,
+    /// gas price of the transaction. set by the message of a withdraw
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<U256>,
+    /// attempts of a reverted relay so far
+    #[serde(default)]
+    pub attempts: u32,
+    /// unix timestamp in seconds of the next attempt of a reverted relay
+    #[serde(default)]
+    pub retry_at: u64,
+}
+
+impl ParkedRelay {
+    /// parks the relay of the transfer `origin` of `value` sent as `request`
+    pub fn new(
+        chain: Chain,
+        origin: H256,
+        value: U256,
+        reason: ParkReason,
+        request: &TransactionRequest,
+    ) -> Self {
+        ParkedRelay {
+            chain,
+            origin,
+            value,
+            reason,
+            to: request.to.unwrap_or_default(),
+            data: request.data.clone().unwrap_or_default(),
+            gas: request.gas.unwrap_or_default(),
+            gas_price: request.gas_price,
+            attempts: 0,
+            retry_at: 0,
+        }
+    }
+
+    /// parks the relay of the transfer `origin` of `value` sent as `request`
+    /// whose first attempt reverted at `now`. it is retried after `retry_interval` seconds
+    pub fn reverted(
+        chain: Chain,
+        origin: H256,
+        value: U256,
+        request: &TransactionRequest,
+        now: u64,
+        retry_interval: u64,
+    ) -> Self {
+        ParkedRelay {
+            attempts: 1,
+            retry_at: now + retry_interval,
+            ..ParkedRelay::new(chain, origin, value, ParkReason::Reverted, request)
+        }
+    }
+
+    /// returns the relay after another attempt reverted at `now`,
+    /// or `None` once it reached `max_attempts`
+    pub fn retry(self, now: u64, retry_interval: u64, max_attempts: u32) -> Option<Self> {
+        let attempts = self.attempts + 1;
+        if attempts >= max_attempts {
+            return None;
+        }
+        Some(ParkedRelay {
+            attempts,
+            retry_at: now + retry_interval,
+            ..self
+        })
+    }
+
+    /// returns the transaction that relays the transfer from `from`
+    pub fn request(&self, from: Address) -> TransactionRequest {
+        TransactionRequest {
+            from,
+            to: Some(self.to),
+            gas: Some(self.gas),
+            gas_price: self.gas_price,
+            value: None,
+            data: Some(self.data.clone()),
+            nonce: None,
+            condition: None,
+        }
+    }
+}
+
+/// relay that was given up after `reverted_relays.max_attempts` attempts reverted
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// revert reason of the last attempt
+    pub revert_reason: String,
+    /// unix timestamp in seconds when the relay was given up
+    pub given_up_at: u64,
+    /// serialized after the other fields as it is a table in the database
+    pub relay: ParkedRelay,
+}
+
+/// relays that are retried apart from their relay streams and the relays given up.
+/// clones share the relays
+#[derive(Debug, Default, Clone)]
+pub struct Parked {
+    relays: Arc<Mutex<Vec<ParkedRelay>>>,
+    dead_letters: Arc<Mutex<Vec<DeadLetter>>>,
+    /// set when the relays changed since they were last saved
+    dirty: Arc<AtomicBool>,
+}
+
+impl Parked {
+    fn lock(&self) -> MutexGuard<Vec<ParkedRelay>> {
+        self.relays.lock().expect("parked relays are never poisoned; qed")
+    }
+
+    fn lock_dead_letters(&self) -> MutexGuard<Vec<DeadLetter>> {
+        self.dead_letters
+            .lock()
+            .expect("dead letters are never poisoned; qed")
+    }
+
+    /// continues with the relays and the dead letters loaded from the database
+    pub fn seed(&self, relays: Vec<ParkedRelay>, dead_letters: Vec<DeadLetter>) {
+        *self.lock() = relays;
+        *self.lock_dead_letters() = dead_letters;
+    }
+
+    /// returns the relays to save in the database
+    pub fn relays(&self) -> Vec<ParkedRelay> {
+        self.lock().clone()
+    }
+
+    /// returns the relays to `chain` parked for `reason` in the order they were parked
+    pub fn find(&self, chain: Chain, reason: ParkReason) -> Vec<ParkedRelay> {
+        self.lock()
+            .iter()
+            .filter(|relay| relay.chain == chain && relay.reason == reason)
+            .cloned()
+            .collect()
+    }
+
+    /// returns the reverted relays to `chain` due for another attempt at `now`
+    pub fn due(&self, chain: Chain, now: u64) -> Vec<ParkedRelay> {
+        self.find(chain, ParkReason::Reverted)
+            .into_iter()
+            .filter(|relay| relay.retry_at <= now)
+            .collect()
+    }
+
+    /// parks `relay`. replaces an earlier relay of the same transfer
+    pub fn park(&self, relay: ParkedRelay) {
+        let mut relays = self.lock();
+        relays.retain(|r| r.chain != relay.chain || r.origin != relay.origin);
+        relays.push(relay);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// drops the relay of the transfer `origin` to `chain` once it is done
+    pub fn remove(&self, chain: Chain, origin: H256) {
+        let mut relays = self.lock();
+        let len = relays.len();
+        relays.retain(|r| r.chain != chain || r.origin != origin);
+        if relays.len() != len {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// gives up the relay of `dead_letter` and keeps it as dead letter.
+    /// replaces an earlier dead letter of the same transfer
+    pub fn give_up(&self, dead_letter: DeadLetter) {
+        let (chain, origin) = (dead_letter.relay.chain, dead_letter.relay.origin);
+        self.lock().retain(|r| r.chain != chain || r.origin != origin);
+        let mut dead_letters = self.lock_dead_letters();
+        dead_letters.retain(|d| d.relay.chain != chain || d.relay.origin != origin);
+        dead_letters.push(dead_letter);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// returns the dead letters to save in the database in the order they were given up
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.lock_dead_letters().clone()
+    }
+
+    /// parks the dead letter of the transfer `origin` to `chain` again.
+    /// it is retried on the first tick after `now` with all of its attempts.
+    /// returns the relay or `None` if there is no such dead letter
+    pub fn replay(&self, chain: Chain, origin: H256, now: u64) -> Option<ParkedRelay> {
+        let dead_letter = {
+            let mut dead_letters = self.lock_dead_letters();
+            let index = dead_letters
+                .iter()
+                .position(|d| d.relay.chain == chain && d.relay.origin == origin)?;
+            dead_letters.remove(index)
+        };
+        let relay = ParkedRelay {
+            attempts: 0,
+            retry_at: now,
+            ..dead_letter.relay
+        };
+        self.park(relay.clone());
+        Some(relay)
+    }
+
+    /// returns whether the relays changed since the last call
+    pub fn take_dirty(&self) -> bool {
+        self.dirty.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::{Address, TransactionRequest};
+    use signer::Chain;
+    use super::{DeadLetter, ParkReason, Parked, ParkedRelay};
+
+    fn relay(chain: Chain, origin: u64, value: u64) -> ParkedRelay {
+        let request = TransactionRequest {
+            from: 1.into(),
+            to: Some(2.into()),
+            gas: Some(100_000.into()),
+            gas_price: Some(10.into()),
+            value: None,
+            data: Some(vec![origin as u8].into()),
+            nonce: None,
+            condition: None,
+        };
+        ParkedRelay::new(
+            chain,
+            origin.into(),
+            value.into(),
+            ParkReason::InsufficientBalance,
+            &request,
+        )
+    }
+
+    #[test]
+    fn test_parked() {
+        let parked = Parked::default();
+        assert!(!parked.take_dirty());
+        parked.park(relay(Chain::Home, 1, 10));
+        parked.park(relay(Chain::Home, 2, 20));
+        parked.park(relay(Chain::Foreign, 1, 30));
+        assert!(parked.take_dirty());
+        assert!(!parked.take_dirty());
+
+        parked.park(relay(Chain::Home, 1, 40));
+        let home = parked.find(Chain::Home, ParkReason::InsufficientBalance);
+        assert_eq!(vec![relay(Chain::Home, 2, 20), relay(Chain::Home, 1, 40)], home);
+
+        parked.remove(Chain::Home, 2.into());
+        assert!(parked.take_dirty());
+        parked.remove(Chain::Home, 2.into());
+        assert!(!parked.take_dirty());
+        assert_eq!(2, parked.relays().len());
+    }
+
+    #[test]
+    fn test_request() {
+        let relay = relay(Chain::Home, 3, 10);
+        let request = relay.request(5.into());
+        assert_eq!(Address::from(5), request.from);
+        assert_eq!(Some(2.into()), request.to);
+        assert_eq!(Some(vec![3].into()), request.data);
+        assert_eq!(Some(10.into()), request.gas_price);
+    }
+
+    #[test]
+    fn test_reverted() {
+        let parked = Parked::default();
+        let request = relay(Chain::Foreign, 1, 10).request(1.into());
+        let reverted =
+            ParkedRelay::reverted(Chain::Foreign, 1.into(), 10.into(), &request, 100, 60);
+        assert_eq!(1, reverted.attempts);
+        assert_eq!(160, reverted.retry_at);
+        parked.park(reverted.clone());
+        parked.park(relay(Chain::Foreign, 2, 20));
+        assert!(parked.due(Chain::Foreign, 159).is_empty());
+        assert!(parked.due(Chain::Home, 160).is_empty());
+        assert_eq!(vec![reverted.clone()], parked.due(Chain::Foreign, 160));
+
+        let retried = reverted.retry(160, 60, 3).unwrap();
+        assert_eq!(2, retried.attempts);
+        assert_eq!(220, retried.retry_at);
+        assert_eq!(None, retried.retry(220, 60, 3));
+    }
+
+    #[test]
+    fn test_retry_max_attempts() {
+        let request = relay(Chain::Home, 1, 10).request(1.into());
+        let reverted = ParkedRelay::reverted(Chain::Home, 1.into(), 10.into(), &request, 0, 60);
+        // the first attempt already counts
+        assert_eq!(None, reverted.clone().retry(60, 60, 1));
+        assert_eq!(None, reverted.clone().retry(60, 60, 2));
+
+        let last = reverted.retry(60, 60, 4).unwrap().retry(120, 60, 4).unwrap();
+        assert_eq!(3, last.attempts);
+        assert_eq!(180, last.retry_at);
+        assert_eq!(None, last.clone().retry(180, 60, 4));
+        assert!(last.retry(180, 60, 5).is_some());
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        let parked = Parked::default();
+        let request = relay(Chain::Home, 1, 10).request(1.into());
+        let reverted = ParkedRelay::reverted(Chain::Home, 1.into(), 10.into(), &request, 0, 60);
+        parked.park(reverted.clone());
+        parked.park(relay(Chain::Home, 2, 20));
+        parked.take_dirty();
+
+        parked.give_up(DeadLetter {
+            revert_reason: "already processed".into(),
+            given_up_at: 300,
+            relay: reverted.clone(),
+        });
+        assert!(parked.take_dirty());
+        assert_eq!(vec![relay(Chain::Home, 2, 20)], parked.relays());
+        assert_eq!(1, parked.dead_letters().len());
+        assert_eq!("already processed", parked.dead_letters()[0].revert_reason);
+
+        assert_eq!(None, parked.replay(Chain::Foreign, 1.into(), 400));
+        let replayed = parked.replay(Chain::Home, 1.into(), 400).unwrap();
+        assert_eq!(0, replayed.attempts);
+        assert_eq!(vec![replayed.clone()], parked.due(Chain::Home, 400));
+        assert!(parked.dead_letters().is_empty());
+        assert!(parked.take_dirty());
+    }
+}
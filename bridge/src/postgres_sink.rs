@@ -0,0 +1,288 @@
+/// mirror of the relay history and of the lifecycle of transfers into postgres.
+/// operators can query the activity of the bridge with sql while the database file
+/// and the relay history stay the source of truth of the bridge.
+/// the schema is created and upgraded by the migrations in `MIGRATIONS` on startup.
+/// rows are written by a thread of their own so the relays never wait for postgres.
+/// rows that can't be written are logged and dropped.
+/// compiled in via the cargo feature `postgres-sink`.
+
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use config::PostgresConfig;
+use error::Error;
+use event_queue::TransferEvent;
+use history::Transfer;
+use status;
+
+/// number of rows that may wait for the writer before further rows are dropped
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// schema migrations by version. applied in order, each in a transaction of its own.
+/// never change a released migration. append a new one instead
+pub const MIGRATIONS: &[(i32, &str)] = &[
+    (
+        1,
+        "CREATE TABLE transfers (
+            id BIGSERIAL PRIMARY KEY,
+            direction TEXT NOT NULL,
+            block_number BIGINT NOT NULL,
+            block_timestamp BIGINT,
+            transaction_hash TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            value NUMERIC(78, 0) NOT NULL,
+            relay_transaction_hash TEXT,
+            relayed_at BIGINT,
+            fee NUMERIC(78, 0)
+        );
+        CREATE INDEX transfers_transaction_hash ON transfers (transaction_hash);
+        CREATE INDEX transfers_block_number ON transfers (direction, block_number);",
+    ),
+    (
+        2,
+        "CREATE TABLE transfer_events (
+            id BIGSERIAL PRIMARY KEY,
+            stage TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            transaction_hash TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            value NUMERIC(78, 0) NOT NULL,
+            authority_transaction_hash TEXT,
+            timestamp BIGINT NOT NULL
+        );
+        CREATE INDEX transfer_events_transaction_hash ON transfer_events (transaction_hash);",
+    ),
+];
+
+/// returns the migrations that still have to be applied to a schema at `version`
+fn pending_migrations(version: i32) -> &'static [(i32, &'static str)] {
+    let applied = MIGRATIONS
+        .iter()
+        .take_while(|&&(migration, _)| migration <= version)
+        .count();
+    &MIGRATIONS[applied..]
+}
+
+/// row waiting for the writer
+pub enum Row {
+    Transfer(Transfer),
+    Event(TransferEvent),
+}
+
+fn writer() -> &'static Mutex<Option<SyncSender<Row>>> {
+    static INIT: Once = ONCE_INIT;
+    static mut WRITER: *const Mutex<Option<SyncSender<Row>>> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            WRITER = Box::into_raw(Box::new(Mutex::new(None)));
+        });
+        &*WRITER
+    }
+}
+
+/// connects to the database of `config`, applies the pending migrations
+/// and starts the thread that writes the mirrored rows
+pub fn start(config: &Option<PostgresConfig>) -> Result<(), Error> {
+    let config = match *config {
+        Some(ref config) => config.clone(),
+        None => return Ok(()),
+    };
+    let connection = backend::connect(&config.url)?;
+    backend::migrate(&connection)?;
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+    backend::spawn_writer(config.url, connection, receiver);
+    *writer().lock().expect("writer is never poisoned; qed") = Some(sender);
+    info!("mirroring transfers into postgres");
+    Ok(())
+}
+
+fn enqueue<I: IntoIterator<Item = Row>>(rows: I) {
+    let writer = writer().lock().expect("writer is never poisoned; qed");
+    let sender = match *writer {
+        Some(ref sender) => sender,
+        None => return,
+    };
+    let mut dropped = 0;
+    for row in rows {
+        match sender.try_send(row) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => dropped += 1,
+        }
+    }
+    if dropped > 0 {
+        let message = format!("dropped {} rows the postgres writer couldn't keep up with", dropped);
+        error!("{}", message);
+        status::record_error(message);
+    }
+}
+
+/// mirrors `transfers` if the postgres sink was started
+pub fn transfers(transfers: &[Transfer]) {
+    enqueue(transfers.iter().cloned().map(Row::Transfer));
+}
+
+/// mirrors `events` if the postgres sink was started
+pub fn events(events: &[TransferEvent]) {
+    enqueue(events.iter().cloned().map(Row::Event));
+}
+
+#[cfg(feature = "postgres-sink")]
+mod backend {
+    use std::fmt;
+    use std::sync::mpsc::Receiver;
+    use std::thread;
+    use postgres::{Connection, TlsMode};
+    use serde::Serialize;
+    use serde_json::{self, Value};
+    use error::{Error, ResultExt};
+    use status;
+    use super::{pending_migrations, Row};
+
+    pub fn connect(url: &str) -> Result<Connection, Error> {
+        Connection::connect(url, TlsMode::None).chain_err(|| "Cannot connect to postgres")
+    }
+
+    /// applies the pending migrations and records them in `schema_migrations`
+    pub fn migrate(connection: &Connection) -> Result<(), Error> {
+        connection
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY)",
+            )
+            .chain_err(|| "Cannot create table schema_migrations")?;
+        let version = connection
+            .query("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[])
+            .chain_err(|| "Cannot read the version of the postgres schema")?
+            .get(0)
+            .get::<_, i32>(0);
+        for &(migration, sql) in pending_migrations(version) {
+            info!("applying postgres migration {}", migration);
+            let transaction = connection
+                .transaction()
+                .chain_err(|| "Cannot start a postgres transaction")?;
+            let context = || format!("Cannot apply postgres migration {}", migration);
+            transaction.batch_execute(sql).chain_err(&context)?;
+            transaction
+                .execute(
+                    "INSERT INTO schema_migrations (version) VALUES ($1)",
+                    &[&migration],
+                )
+                .chain_err(&context)?;
+            transaction.commit().chain_err(&context)?;
+        }
+        Ok(())
+    }
+
+    /// `0x` prefixed hex of a hash or an address as written by the relay history
+    fn hex<T: fmt::Debug>(value: &T) -> String {
+        format!("{:?}", value)
+    }
+
+    /// name of a variant as written by the relay history (example: `deposit`)
+    fn name<T: Serialize>(value: &T) -> String {
+        match serde_json::to_value(value).expect("serialization can't fail; qed") {
+            Value::String(name) => name,
+            _ => unreachable!("unit variants serialize to strings; qed"),
+        }
+    }
+
+    fn write(connection: &Connection, row: &Row) -> Result<(), Error> {
+        let result = match *row {
+            Row::Transfer(ref transfer) => connection.execute(
+                "INSERT INTO transfers (direction, block_number, block_timestamp, \
+                 transaction_hash, recipient, value, relay_transaction_hash, relayed_at, fee) \
+                 VALUES ($1, $2, $3, $4, $5, $6::TEXT::NUMERIC, $7, $8, $9::TEXT::NUMERIC)",
+                &[
+                    &name(&transfer.direction),
+                    &(transfer.block_number as i64),
+                    &transfer.block_timestamp.map(|timestamp| timestamp as i64),
+                    &hex(&transfer.transaction_hash),
+                    &hex(&transfer.recipient),
+                    &transfer.value.to_string(),
+                    &transfer.relay_transaction_hash.as_ref().map(hex),
+                    &transfer.relayed_at.map(|timestamp| timestamp as i64),
+                    &transfer.fee.map(|fee| fee.to_string()),
+                ],
+            ),
+            Row::Event(ref event) => connection.execute(
+                "INSERT INTO transfer_events (stage, direction, transaction_hash, recipient, \
+                 value, authority_transaction_hash, timestamp) \
+                 VALUES ($1, $2, $3, $4, $5::TEXT::NUMERIC, $6, $7)",
+                &[
+                    &name(&event.stage),
+                    &name(&event.direction),
+                    &hex(&event.transaction_hash),
+                    &hex(&event.recipient),
+                    &event.value.to_string(),
+                    &event.authority_transaction_hash.as_ref().map(hex),
+                    &(event.timestamp as i64),
+                ],
+            ),
+        };
+        result.chain_err(|| "Cannot insert row")?;
+        Ok(())
+    }
+
+    /// writes the rows of `receiver` until the bridge exits.
+    /// reconnects before the next row if writing one fails
+    pub fn spawn_writer(url: String, connection: Connection, receiver: Receiver<Row>) {
+        thread::spawn(move || {
+            let mut connection = Some(connection);
+            for row in receiver {
+                if connection.is_none() {
+                    connection = connect(&url)
+                        .map_err(|err| warn!("reconnecting to postgres failed: {}", err))
+                        .ok();
+                }
+                let result = match connection {
+                    Some(ref connection) => write(connection, &row),
+                    None => Err("not connected to postgres".into()),
+                };
+                if let Err(err) = result {
+                    let message = format!("failed to mirror a row into postgres: {}", err);
+                    error!("{}", message);
+                    status::record_error(message);
+                    connection = None;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(not(feature = "postgres-sink"))]
+mod backend {
+    use std::sync::mpsc::Receiver;
+    use error::Error;
+    use super::Row;
+
+    pub struct Connection;
+
+    pub fn connect(_: &str) -> Result<Connection, Error> {
+        unreachable!("postgres is only configured if feature `postgres-sink` is enabled; qed")
+    }
+
+    pub fn migrate(_: &Connection) -> Result<(), Error> {
+        unreachable!("postgres is only configured if feature `postgres-sink` is enabled; qed")
+    }
+
+    pub fn spawn_writer(_: String, _: Connection, _: Receiver<Row>) {
+        unreachable!("postgres is only configured if feature `postgres-sink` is enabled; qed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pending_migrations, MIGRATIONS};
+
+    #[test]
+    fn test_pending_migrations() {
+        assert_eq!(MIGRATIONS.len(), pending_migrations(0).len());
+        assert_eq!(2, pending_migrations(1)[0].0);
+        assert!(pending_migrations(MIGRATIONS.len() as i32).is_empty());
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, &(version, _)) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(index as i32 + 1, version);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+/// exposition of the metrics in the prometheus text format served at `GET /metrics` (see `http`).
+/// consists of the rpc metrics of `metrics`, the relays of this authority (`metrics::Relays`)
+/// and the chain status of `status`: the latest blocks, the blocks up to which the components
+/// have processed events and the pending nonces of the accounts of this authority.
+/// counters count since the process started. gauges are left out until they were first observed.
+
+use std::fmt::{Display, Write};
+use metrics::{EndpointMetrics, MethodMetrics, Metrics, RelayMetrics, Relays, LATENCY_BUCKETS_MS};
+use status::Status;
+
+/// content type of the prometheus text format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// escapes a label value. see the prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// text that metric families are appended to
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// starts the family `name` of `kind` (`counter`, `gauge` or `histogram`)
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.text, "# HELP {} {}", name, help)
+            .and_then(|_| writeln!(self.text, "# TYPE {} {}", name, kind))
+            .expect("writing to a string can't fail; qed");
+    }
+
+    fn sample<V: Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|&(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            write!(self.text, "{{{}}}", labels).expect("writing to a string can't fail; qed");
+        }
+        writeln!(self.text, " {}", value).expect("writing to a string can't fail; qed");
+    }
+}
+
+/// renders the rpc `metrics`, the `relays` and the `status` in the prometheus text format.
+/// relays are labelled with their bridge in a process that runs several bridges
+pub fn render(metrics: &Metrics, relays: &Relays, status: &Status) -> String {
+    let mut exposition = Exposition::default();
+
+    let method_counters: [(&str, &str, fn(&MethodMetrics) -> u64); 3] = [
+        ("bridge_rpc_calls_total", "rpc calls to the nodes", |m| m.calls),
+        ("bridge_rpc_errors_total", "rpc calls that completed with an error", |m| m.errors),
+        (
+            "bridge_rpc_abandoned_total",
+            "rpc calls that were dropped before they completed",
+            |m| m.abandoned,
+        ),
+    ];
+    for &(name, help, select) in &method_counters {
+        exposition.family(name, "counter", help);
+        for (endpoint, endpoint_metrics) in metrics {
+            for (method, method_metrics) in &endpoint_metrics.methods {
+                exposition.sample(
+                    name,
+                    &[("endpoint", endpoint.as_str()), ("method", method.as_str())],
+                    select(method_metrics),
+                );
+            }
+        }
+    }
+
+    let name = "bridge_rpc_latency_ms";
+    exposition.family(name, "histogram", "latency of completed rpc calls in milliseconds");
+    for (endpoint, endpoint_metrics) in metrics {
+        for (method, method_metrics) in &endpoint_metrics.methods {
+            let bounds = LATENCY_BUCKETS_MS
+                .iter()
+                .map(|bound| bound.to_string())
+                .chain(Some("+Inf".to_owned()));
+            for (bound, count) in bounds.zip(&method_metrics.latency_buckets) {
+                exposition.sample(
+                    &format!("{}_bucket", name),
+                    &[
+                        ("endpoint", endpoint.as_str()),
+                        ("method", method.as_str()),
+                        ("le", bound.as_str()),
+                    ],
+                    count,
+                );
+            }
+            let labels = [("endpoint", endpoint.as_str()), ("method", method.as_str())];
+            exposition.sample(&format!("{}_sum", name), &labels, method_metrics.latency_sum_ms);
+            exposition.sample(
+                &format!("{}_count", name),
+                &labels,
+                method_metrics.latency_buckets.last().cloned().unwrap_or(0),
+            );
+        }
+    }
+
+    let endpoint_counters: [(&str, &str, fn(&EndpointMetrics) -> u64); 2] = [
+        (
+            "bridge_rpc_retries_total",
+            "relay attempts that were abandoned and rescheduled",
+            |m| m.retries,
+        ),
+        (
+            "bridge_rpc_rate_limited_total",
+            "rpc calls the node rejected because of its rate limit",
+            |m| m.rate_limited,
+        ),
+    ];
+    for &(name, help, select) in &endpoint_counters {
+        exposition.family(name, "counter", help);
+        for (endpoint, endpoint_metrics) in metrics {
+            exposition.sample(name, &[("endpoint", endpoint.as_str())], select(endpoint_metrics));
+        }
+    }
+
+    let relay_counters: [(&str, &str, fn(&RelayMetrics) -> u64); 3] = [
+        ("bridge_deposits_relayed_total", "deposits relayed to foreign", |r| r.deposits),
+        (
+            "bridge_signatures_submitted_total",
+            "signatures of withdraws submitted to foreign",
+            |r| r.signatures,
+        ),
+        ("bridge_withdraws_relayed_total", "withdraws relayed to home", |r| r.withdraws),
+    ];
+    for &(name, help, select) in &relay_counters {
+        exposition.family(name, "counter", help);
+        if relays.is_empty() {
+            exposition.sample(name, &[], 0);
+        }
+        for (bridge, bridge_relays) in relays {
+            match *bridge {
+                Some(ref bridge) => {
+                    exposition.sample(name, &[("bridge", bridge.as_str())], select(bridge_relays))
+                }
+                None => exposition.sample(name, &[], select(bridge_relays)),
+            }
+        }
+    }
+
+    let name = "bridge_latest_block";
+    exposition.family(name, "gauge", "latest block of the chain");
+    for &(chain, block) in &[("home", status.home_block), ("foreign", status.foreign_block)] {
+        if let Some(block) = block {
+            exposition.sample(name, &[("chain", chain)], block);
+        }
+    }
+
+    let name = "bridge_checked_block";
+    exposition.family(name, "gauge", "block up to which the component has processed events");
+    let checked = [
+        ("deposit_relay", status.checked_deposit_relay),
+        ("withdraw_confirm", status.checked_withdraw_confirm),
+        ("withdraw_relay", status.checked_withdraw_relay),
+    ];
+    for &(component, block) in &checked {
+        if let Some(block) = block {
+            exposition.sample(name, &[("component", component)], block);
+        }
+    }
+
+    let name = "bridge_pending_nonce";
+    exposition.family(
+        name,
+        "gauge",
+        "nonce of the next transaction of the account of this authority including pending ones",
+    );
+    let nonces = [
+        ("home", status.home_pending_nonce),
+        ("foreign", status.foreign_pending_nonce),
+    ];
+    for &(chain, nonce) in &nonces {
+        if let Some(nonce) = nonce {
+            exposition.sample(name, &[("chain", chain)], nonce);
+        }
+    }
+
+    let name = "bridge_in_flight_transactions";
+    exposition.family(name, "gauge", "transactions that are being relayed");
+    exposition.sample(name, &[], status.in_flight_transactions);
+
+    exposition.text
+}
+
+#[cfg(test)]
+mod tests {
+    use metrics::{EndpointMetrics, MethodMetrics, Metrics, RelayMetrics, Relays};
+    use status::Status;
+    use super::{escape, render};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(r#"a\\b\"c\nd"#, escape("a\\b\"c\nd"));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut method = MethodMetrics::default();
+        method.calls = 3;
+        method.errors = 1;
+        method.latency_buckets = vec![0, 1, 1, 1, 1, 2, 2, 2, 2, 2];
+        method.latency_sum_ms = 640;
+        let mut endpoint = EndpointMetrics::default();
+        endpoint.methods.insert("eth_call".into(), method);
+        endpoint.retries = 4;
+        let mut metrics = Metrics::new();
+        metrics.insert("home.ipc".into(), endpoint);
+        let mut relays = Relays::new();
+        relays.insert(
+            None,
+            RelayMetrics {
+                deposits: 5,
+                signatures: 6,
+                withdraws: 7,
+            },
+        );
+        let status = Status {
+            home_block: Some(10),
+            checked_deposit_relay: Some(7),
+            foreign_pending_nonce: Some(42.into()),
+            ..Default::default()
+        };
+
+        let text = render(&metrics, &relays, &status);
+        let lines = text.lines().collect::<Vec<_>>();
+        for expected in &[
+            "# TYPE bridge_rpc_calls_total counter",
+            r#"bridge_rpc_calls_total{endpoint="home.ipc",method="eth_call"} 3"#,
+            r#"bridge_rpc_errors_total{endpoint="home.ipc",method="eth_call"} 1"#,
+            r#"bridge_rpc_latency_ms_bucket{endpoint="home.ipc",method="eth_call",le="50"} 1"#,
+            r#"bridge_rpc_latency_ms_bucket{endpoint="home.ipc",method="eth_call",le="+Inf"} 2"#,
+            r#"bridge_rpc_latency_ms_sum{endpoint="home.ipc",method="eth_call"} 640"#,
+            r#"bridge_rpc_latency_ms_count{endpoint="home.ipc",method="eth_call"} 2"#,
+            r#"bridge_rpc_retries_total{endpoint="home.ipc"} 4"#,
+            "bridge_deposits_relayed_total 5",
+            "bridge_signatures_submitted_total 6",
+            "bridge_withdraws_relayed_total 7",
+            r#"bridge_latest_block{chain="home"} 10"#,
+            r#"bridge_checked_block{component="deposit_relay"} 7"#,
+            r#"bridge_pending_nonce{chain="foreign"} 42"#,
+            "bridge_in_flight_transactions 0",
+        ] {
+            assert!(lines.contains(expected), "missing `{}` in\n{}", expected, text);
+        }
+        assert!(!text.contains(r#"chain="foreign"} 10"#));
+        assert!(!text.contains(r#"component="withdraw_relay""#));
+    }
+
+    #[test]
+    fn test_render_relays_by_bridge() {
+        let mut relays = Relays::new();
+        for &(bridge, deposits) in &[("kovan", 2), ("sokol", 3)] {
+            let mut bridge_relays = RelayMetrics::default();
+            bridge_relays.deposits = deposits;
+            relays.insert(Some(bridge.into()), bridge_relays);
+        }
+        let text = render(&Metrics::new(), &relays, &Status::default());
+        let lines = text.lines().collect::<Vec<_>>();
+        for expected in &[
+            r#"bridge_deposits_relayed_total{bridge="kovan"} 2"#,
+            r#"bridge_deposits_relayed_total{bridge="sokol"} 3"#,
+            r#"bridge_withdraws_relayed_total{bridge="kovan"} 0"#,
+        ] {
+            assert!(lines.contains(expected), "missing `{}` in\n{}", expected, text);
+        }
+        assert!(!lines.contains(&"bridge_deposits_relayed_total 0"));
+
+        let text = render(&Metrics::new(), &Relays::new(), &Status::default());
+        assert!(text.lines().any(|line| line == "bridge_deposits_relayed_total 0"));
+    }
+}
@@ -0,0 +1,236 @@
+/// encoding of transactions that are signed locally for `eth_sendRawTransaction`:
+/// legacy transactions with EIP-155 replay protection, EIP-2930 and EIP-1559 transactions.
+/// the transactions are given as `TypedTransactionRequest`. type `0` is a legacy transaction.
+
+use tiny_keccak::keccak256;
+use web3::types::{Bytes, U256};
+use api::{AccessListItem, TypedTransactionRequest};
+use error::Error;
+
+/// appends the rlp encoding of the byte string `bytes` to `out`
+fn append_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        append_length(out, bytes.len(), 0x80);
+        out.extend_from_slice(bytes);
+    }
+}
+
+/// appends the rlp encoding of a list whose encoded items are `items` to `out`
+fn append_list(out: &mut Vec<u8>, items: &[u8]) {
+    append_length(out, items.len(), 0xc0);
+    out.extend_from_slice(items);
+}
+
+fn append_length(out: &mut Vec<u8>, length: usize, offset: u8) {
+    if length < 56 {
+        out.push(offset + length as u8);
+    } else {
+        let mut bytes = Vec::new();
+        let mut rest = length;
+        while rest > 0 {
+            bytes.insert(0, rest as u8);
+            rest >>= 8;
+        }
+        out.push(offset + 55 + bytes.len() as u8);
+        out.extend_from_slice(&bytes);
+    }
+}
+
+/// appends `value` as integer: big endian without leading zeros
+fn append_uint(out: &mut Vec<u8>, value: U256) {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    let start = bytes.iter().position(|&byte| byte != 0).unwrap_or(bytes.len());
+    append_bytes(out, &bytes[start..]);
+}
+
+fn append_access_list(out: &mut Vec<u8>, access_list: &[AccessListItem]) {
+    let mut items = Vec::new();
+    for item in access_list {
+        let mut storage_keys = Vec::new();
+        for key in &item.storage_keys {
+            append_bytes(&mut storage_keys, &key.0);
+        }
+        let mut fields = Vec::new();
+        append_bytes(&mut fields, &item.address.0);
+        append_list(&mut fields, &storage_keys);
+        append_list(&mut items, &fields);
+    }
+    append_list(out, &items);
+}
+
+/// rlp encoded fields of `tx` that are signed, excluding the chain id of legacy transactions
+fn fields(tx: &TypedTransactionRequest, nonce: U256, chain_id: u64) -> Result<Vec<u8>, Error> {
+    let request = &tx.request;
+    let gas = request.gas.ok_or_else(|| Error::from("transaction has no gas"))?;
+    let transaction_type = tx.transaction_type.low_u64();
+    let mut out = Vec::new();
+    if transaction_type != 0 {
+        append_uint(&mut out, chain_id.into());
+    }
+    append_uint(&mut out, nonce);
+    match transaction_type {
+        0 | 1 => {
+            let gas_price = request
+                .gas_price
+                .ok_or_else(|| Error::from("transaction has no gas price"))?;
+            append_uint(&mut out, gas_price);
+        }
+        2 => match (tx.max_priority_fee_per_gas, tx.max_fee_per_gas) {
+            (Some(max_priority_fee_per_gas), Some(max_fee_per_gas)) => {
+                append_uint(&mut out, max_priority_fee_per_gas);
+                append_uint(&mut out, max_fee_per_gas);
+            }
+            _ => bail!("EIP-1559 transaction has no max_fee_per_gas or max_priority_fee_per_gas"),
+        },
+        transaction_type => bail!("transaction type {} is not supported", transaction_type),
+    }
+    append_uint(&mut out, gas);
+    match request.to {
+        Some(to) => append_bytes(&mut out, &to.0),
+        None => append_bytes(&mut out, &[]),
+    }
+    append_uint(&mut out, request.value.unwrap_or_default());
+    append_bytes(&mut out, request.data.as_ref().map_or(&[][..], |data| &data.0[..]));
+    if transaction_type != 0 {
+        append_access_list(&mut out, tx.access_list.as_ref().map_or(&[][..], |list| &list[..]));
+    }
+    Ok(out)
+}
+
+/// returns the encoding of `fields` as transaction of `transaction_type`
+fn envelope(transaction_type: u64, fields: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if transaction_type != 0 {
+        out.push(transaction_type as u8);
+    }
+    append_list(&mut out, fields);
+    out
+}
+
+/// returns the hash of `tx` with `nonce` on the chain `chain_id` that is signed
+pub fn signing_hash(
+    tx: &TypedTransactionRequest,
+    nonce: U256,
+    chain_id: u64,
+) -> Result<[u8; 32], Error> {
+    let transaction_type = tx.transaction_type.low_u64();
+    let mut fields = fields(tx, nonce, chain_id)?;
+    if transaction_type == 0 {
+        // EIP-155
+        append_uint(&mut fields, chain_id.into());
+        append_uint(&mut fields, 0.into());
+        append_uint(&mut fields, 0.into());
+    }
+    Ok(keccak256(&envelope(transaction_type, &fields)))
+}
+
+/// returns `tx` with `nonce` on the chain `chain_id` signed by `signature`
+/// (the recovery id and `r` followed by `s` of the signing hash)
+pub fn encode_signed(
+    tx: &TypedTransactionRequest,
+    nonce: U256,
+    chain_id: u64,
+    signature: (u8, [u8; 64]),
+) -> Result<Bytes, Error> {
+    let (recovery_id, rs) = signature;
+    let transaction_type = tx.transaction_type.low_u64();
+    let mut fields = fields(tx, nonce, chain_id)?;
+    if transaction_type == 0 {
+        // EIP-155
+        append_uint(&mut fields, (chain_id * 2 + 35 + u64::from(recovery_id)).into());
+    } else {
+        append_uint(&mut fields, recovery_id.into());
+    }
+    append_uint(&mut fields, U256::from(&rs[..32]));
+    append_uint(&mut fields, U256::from(&rs[32..]));
+    Ok(envelope(transaction_type, &fields).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hex::{FromHex, ToHex};
+    use web3::types::{Bytes, TransactionRequest};
+    use api::{AccessListItem, TypedTransactionRequest};
+    use super::{append_bytes, append_uint, encode_signed, signing_hash};
+
+    fn rlp_uint(value: u64) -> String {
+        let mut out = Vec::new();
+        append_uint(&mut out, value.into());
+        out.to_hex()
+    }
+
+    #[test]
+    fn test_rlp() {
+        assert_eq!("80", rlp_uint(0));
+        assert_eq!("0f", rlp_uint(15));
+        assert_eq!("820400", rlp_uint(1024));
+        let mut out = Vec::new();
+        append_bytes(&mut out, &[0u8; 56]);
+        assert_eq!("b838", out[..2].to_hex());
+    }
+
+    /// example of EIP-155
+    fn legacy() -> TypedTransactionRequest {
+        TypedTransactionRequest {
+            request: TransactionRequest {
+                from: 0.into(),
+                to: Some("3535353535353535353535353535353535353535".into()),
+                gas: Some(21000.into()),
+                gas_price: Some(20_000_000_000u64.into()),
+                value: Some(1_000_000_000_000_000_000u64.into()),
+                data: Some(Bytes(vec![])),
+                nonce: None,
+                condition: None,
+            },
+            transaction_type: 0.into(),
+            access_list: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }
+    }
+
+    #[test]
+    fn test_legacy_signing_hash() {
+        assert_eq!(
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53",
+            signing_hash(&legacy(), 9.into(), 1).unwrap().to_hex()
+        );
+    }
+
+    #[test]
+    fn test_legacy_encode_signed() {
+        let r = "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276";
+        let s = "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+        let mut rs = [0u8; 64];
+        rs.copy_from_slice(&format!("{}{}", r, s).from_hex().unwrap());
+        assert_eq!(
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+            encode_signed(&legacy(), 9.into(), 1, (0, rs)).unwrap().0.to_hex()
+        );
+    }
+
+    #[test]
+    fn test_typed_envelopes() {
+        let mut tx = legacy();
+        tx.transaction_type = 1.into();
+        tx.access_list = Some(vec![
+            AccessListItem {
+                address: 1.into(),
+                storage_keys: vec![2.into()],
+            },
+        ]);
+        let encoded = encode_signed(&tx, 0.into(), 1, (1, [1u8; 64])).unwrap();
+        assert_eq!(0x01, encoded.0[0]);
+
+        tx.transaction_type = 2.into();
+        assert!(encode_signed(&tx, 0.into(), 1, (1, [1u8; 64])).is_err());
+        tx.max_fee_per_gas = Some(30.into());
+        tx.max_priority_fee_per_gas = Some(2.into());
+        let encoded = encode_signed(&tx, 0.into(), 1, (1, [1u8; 64])).unwrap();
+        assert_eq!(0x02, encoded.0[0]);
+        assert!(signing_hash(&tx, 0.into(), 1).unwrap() != signing_hash(&tx, 0.into(), 2).unwrap());
+    }
+}
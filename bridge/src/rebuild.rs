@@ -0,0 +1,549 @@
+/// reconstruction of the database from the chains for an authority that lost it.
+/// the logs of both contracts since their deployment are scanned and every message
+/// is checked against the contracts:
+/// - a deposit is processed once `ForeignBridge` has its `Deposit`
+///   or rejects a confirmation from `foreign.account` (it already confirmed it)
+/// - a withdraw is processed once its signatures were collected
+///   or `ForeignBridge` stores a signature of it by `foreign.account` (or a fallback account)
+/// - a relay of collected signatures this authority is responsible for is processed
+///   once `HomeBridge` executed the withdraw
+///
+/// every checkpoint is set right before the block of the first unprocessed message
+/// of its direction. messages after it are seen again on start.
+/// signatures are deterministic and the contracts reject a second confirmation
+/// or signature of an authority, so processing them again can't double sign or double pay.
+
+use std::cmp;
+use std::collections::HashSet;
+use std::time::Duration;
+use ethabi::RawLog;
+use rustc_hex::FromHex;
+use tiny_keccak::keccak256;
+use tokio_core::reactor::Core;
+use web3::Transport;
+use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest};
+use api;
+use app::App;
+use bridge::deposit_payload;
+use database::Database;
+use error::Error;
+use message_to_mainnet::MessageToMainnet;
+use transaction::is_rpc_error;
+use util::web3_filter;
+
+/// returns the checkpoint of a direction that was scanned up to `scanned`
+/// whose unprocessed messages are in `unprocessed` (block numbers)
+fn checkpoint(scanned: u64, unprocessed: &[u64]) -> u64 {
+    unprocessed
+        .iter()
+        .min()
+        .map_or(scanned, |block| block.saturating_sub(1))
+}
+
+/// parses a hex encoded address given on the command line
+pub fn parse_address(s: &str) -> Result<Address, Error> {
+    let hex = if s.starts_with("0x") { &s[2..] } else { s };
+    let bytes: Vec<u8> = hex.from_hex()?;
+    if bytes.len() != 20 {
+        bail!("address must be 20 bytes long");
+    }
+    Ok(bytes.as_slice().into())
+}
+
+pub fn block_of(log: &Log) -> u64 {
+    log.block_number
+        .expect("log to be mined and contain `block_number`")
+        .low_u64()
+}
+
+/// messages of one direction found while rebuilding
+#[derive(Debug, PartialEq, Default)]
+pub struct Messages {
+    /// number of messages since the deployment
+    pub total: usize,
+    /// number of messages this authority doesn't have to process again
+    pub processed: usize,
+    /// transaction hashes of the messages this authority has yet to process
+    pub unprocessed: Vec<H256>,
+    /// transaction hashes of the messages that didn't reach the required signatures yet
+    pub awaiting_quorum: Vec<H256>,
+}
+
+/// result of `rebuild_state`
+#[derive(Debug, PartialEq)]
+pub struct RebuiltState {
+    pub database: Database,
+    pub deposits: Messages,
+    pub withdraw_confirms: Messages,
+    pub withdraw_relays: Messages,
+}
+
+impl RebuiltState {
+    /// renders the rebuilt state as a report for the operator
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for &(name, messages, checkpoint) in &[
+            ("deposit relays", &self.deposits, self.database.checked_deposit_relay),
+            (
+                "withdraw confirms",
+                &self.withdraw_confirms,
+                self.database.checked_withdraw_confirm,
+            ),
+            (
+                "withdraw relays",
+                &self.withdraw_relays,
+                self.database.checked_withdraw_relay,
+            ),
+        ] {
+            out.push_str(&format!(
+                "{}: {} messages, {} processed, {} awaiting quorum. checked up to block {}\n",
+                name,
+                messages.total,
+                messages.processed,
+                messages.awaiting_quorum.len(),
+                checkpoint
+            ));
+            for hash in &messages.unprocessed {
+                out.push_str(&format!("  unprocessed: {:?}\n", hash));
+            }
+        }
+        out.push_str(&format!("\n{}", self.database));
+        out
+    }
+}
+
+/// returns the last block of the chain with `required_confirmations`
+pub fn confirmed_head<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    transport: &T,
+    request_timeout: Duration,
+    required_confirmations: usize,
+) -> Result<u64, Error> {
+    let head = event_loop.run(
+        app.timer
+            .timeout(api::block_number(transport), request_timeout),
+    )?;
+    Ok(head.low_u64().saturating_sub(required_confirmations as u64))
+}
+
+/// returns the logs of `filter` in blocks `[from, to]`
+pub fn logs<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    transport: &T,
+    request_timeout: Duration,
+    filter: FilterBuilder,
+    from: u64,
+    to: u64,
+) -> Result<Vec<Log>, Error> {
+    let filter = filter.from_block(from.into()).to_block(to.into()).build();
+    event_loop.run(
+        app.timer
+            .timeout(api::logs(transport, &filter), request_timeout),
+    )
+}
+
+/// returns the accounts of this authority on `foreign`
+fn foreign_accounts<T: Transport>(app: &App<T>) -> Vec<Address> {
+    let mut accounts = vec![app.config.foreign.account];
+    accounts.extend(app.config.foreign.fallback_accounts.iter().cloned());
+    accounts
+}
+
+/// returns the relay of the deposit in `log` to the `ForeignBridge` at `foreign_contract`
+pub fn deposit_request<T: Transport>(
+    app: &App<T>,
+    foreign_contract: Address,
+    log: &Log,
+) -> Result<TransactionRequest, Error> {
+    let hash = log.transaction_hash
+        .expect("log to be mined and contain `transaction_hash`");
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let deposit = app.home_bridge.events().deposit().parse_log(raw_log)?;
+    let payload = deposit_payload(
+        &app.foreign_bridge,
+        deposit.recipient,
+        deposit.value,
+        hash,
+        app.config
+            .token_fee
+            .as_ref()
+            .map(|token_fee| token_fee.deposit_fee),
+    );
+    Ok(TransactionRequest {
+        from: app.config.foreign.account,
+        to: Some(foreign_contract),
+        gas: Some(app.config.txs.deposit_relay.gas.into()),
+        gas_price: Some(app.config.txs.deposit_relay.gas_price.into()),
+        value: None,
+        data: Some(payload),
+        nonce: None,
+        condition: None,
+    })
+}
+
+/// returns whether `foreign.account` already confirmed the deposit relayed by `request`.
+/// `ForeignBridge.deposit` reverts if this authority already confirmed the deposit
+pub fn deposit_confirmed<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    request: &TransactionRequest,
+) -> Result<bool, Error> {
+    match event_loop.run(app.timer.timeout(
+        api::call_transaction(&app.connections.foreign, request),
+        app.config.foreign.request_timeout,
+    )) {
+        Ok(_) => Ok(false),
+        Err(ref err) if is_rpc_error(err) => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+/// reads the signatures of the withdraw `message` from the `ForeignBridge` at `foreign_contract`.
+/// returns the number of signatures read and whether one of them is by an account
+/// of this authority. stops reading at the first signature of this authority
+pub fn withdraw_signatures<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    foreign_contract: Address,
+    message: &[u8],
+) -> Result<(usize, bool), Error> {
+    let foreign = &app.connections.foreign;
+    let foreign_timeout = app.config.foreign.request_timeout;
+    let message_hash = keccak256(message);
+    let accounts = foreign_accounts(app);
+    for index in 0..app.config.authorities.accounts.len() {
+        let payload = app.foreign_bridge
+            .functions()
+            .signature()
+            .input(message_hash, index as u64)
+            .into();
+        // reading past the stored signatures reverts
+        let output = match event_loop.run(app.timer.timeout(
+            api::call(foreign, foreign_contract, payload),
+            foreign_timeout,
+        )) {
+            Ok(output) => output,
+            Err(ref err) if is_rpc_error(err) => return Ok((index, false)),
+            Err(err) => return Err(err),
+        };
+        let signature = match app.foreign_bridge
+            .functions()
+            .signature()
+            .output(output.0.as_slice())
+        {
+            Ok(ref signature) if !signature.is_empty() => signature.clone(),
+            _ => return Ok((index, false)),
+        };
+        let signer = event_loop.run(app.timer.timeout(
+            api::ec_recover(foreign, Bytes(message.to_vec()), Bytes(signature)),
+            foreign_timeout,
+        ))?;
+        if accounts.contains(&signer) {
+            return Ok((index + 1, true));
+        }
+    }
+    Ok((app.config.authorities.accounts.len(), false))
+}
+
+/// returns the withdraw message with `message_hash` stored by the `ForeignBridge`
+/// at `foreign_contract`
+pub fn stored_message<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    foreign_contract: Address,
+    message_hash: [u8; 32],
+) -> Result<Vec<u8>, Error> {
+    let payload = app.foreign_bridge
+        .functions()
+        .message()
+        .input(message_hash)
+        .into();
+    let output = event_loop.run(app.timer.timeout(
+        api::call(&app.connections.foreign, foreign_contract, payload),
+        app.config.foreign.request_timeout,
+    ))?;
+    Ok(app.foreign_bridge
+        .functions()
+        .message()
+        .output(output.0.as_slice())?)
+}
+
+/// reconstructs the database of the deployment of `HomeBridge` at `home_contract`
+/// in block `home_deploy` and `ForeignBridge` at `foreign_contract` in block `foreign_deploy`.
+/// deployments of the token bridge contracts can't be rebuilt
+pub fn rebuild_state<T: Transport>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    home_contract: Address,
+    foreign_contract: Address,
+    home_deploy: u64,
+    foreign_deploy: u64,
+) -> Result<RebuiltState, Error> {
+    if app.config.tokens().is_some() {
+        bail!("the state of token bridges can't be rebuilt from their events");
+    }
+    let home = &app.connections.home;
+    let foreign = &app.connections.foreign;
+    let home_timeout = app.config.home.request_timeout;
+    let foreign_timeout = app.config.foreign.request_timeout;
+    let home_head = confirmed_head(
+        event_loop,
+        app,
+        home,
+        home_timeout,
+        app.config.home.required_confirmations,
+    )?;
+    let foreign_head = confirmed_head(
+        event_loop,
+        app,
+        foreign,
+        foreign_timeout,
+        app.config.foreign.required_confirmations,
+    )?;
+    let home_head = cmp::max(home_head, home_deploy);
+    let foreign_head = cmp::max(foreign_head, foreign_deploy);
+
+    info!("fetching deposits on home");
+    let deposit_logs = logs(
+        event_loop,
+        app,
+        home,
+        home_timeout,
+        web3_filter(
+            app.home_bridge.events().deposit().create_filter(),
+            home_contract,
+        ),
+        home_deploy + 1,
+        home_head,
+    )?;
+    info!("fetching completed deposits on foreign");
+    let completed_deposits = logs(
+        event_loop,
+        app,
+        foreign,
+        foreign_timeout,
+        web3_filter(
+            app.foreign_bridge.events().deposit().create_filter(),
+            foreign_contract,
+        ),
+        foreign_deploy + 1,
+        foreign_head,
+    )?;
+    info!("fetching withdraws on foreign");
+    let withdraw_logs = logs(
+        event_loop,
+        app,
+        foreign,
+        foreign_timeout,
+        web3_filter(
+            app.foreign_bridge.events().withdraw().create_filter(),
+            foreign_contract,
+        ),
+        foreign_deploy + 1,
+        foreign_head,
+    )?;
+    info!("fetching collected signatures on foreign");
+    let collected_logs = logs(
+        event_loop,
+        app,
+        foreign,
+        foreign_timeout,
+        web3_filter(
+            app.foreign_bridge
+                .events()
+                .collected_signatures()
+                .create_filter(),
+            foreign_contract,
+        ),
+        foreign_deploy + 1,
+        foreign_head,
+    )?;
+
+    let completed = completed_deposits
+        .into_iter()
+        .map(|log| {
+            let raw_log = RawLog {
+                topics: log.topics.into_iter().map(|t| t.0.into()).collect(),
+                data: log.data.0,
+            };
+            let deposit = app.foreign_bridge.events().deposit().parse_log(raw_log)?;
+            Ok(H256(deposit.transaction_hash.0))
+        })
+        .collect::<Result<HashSet<_>, Error>>()?;
+
+    info!("checking {} deposits", deposit_logs.len());
+    let mut deposits = Messages::default();
+    let mut unprocessed_blocks = Vec::new();
+    for log in &deposit_logs {
+        let hash = log.transaction_hash
+            .expect("log to be mined and contain `transaction_hash`");
+        deposits.total += 1;
+        if completed.contains(&hash) {
+            deposits.processed += 1;
+            continue;
+        }
+        deposits.awaiting_quorum.push(hash);
+        let request = deposit_request(app, foreign_contract, log)?;
+        if deposit_confirmed(event_loop, app, &request)? {
+            deposits.processed += 1;
+        } else {
+            deposits.unprocessed.push(hash);
+            unprocessed_blocks.push(block_of(log));
+        }
+    }
+    let checked_deposit_relay = cmp::max(checkpoint(home_head, &unprocessed_blocks), home_deploy);
+
+    let collected = collected_logs
+        .iter()
+        .map(|log| {
+            let raw_log = RawLog {
+                topics: log.topics.iter().map(|t| t.0.into()).collect(),
+                data: log.data.0.clone(),
+            };
+            Ok((
+                app.foreign_bridge
+                    .events()
+                    .collected_signatures()
+                    .parse_log(raw_log)?,
+                log,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let collected_hashes = collected
+        .iter()
+        .map(|&(ref event, _)| H256(event.message_hash.0))
+        .collect::<HashSet<_>>();
+
+    info!("checking {} withdraws", withdraw_logs.len());
+    let mut withdraw_confirms = Messages::default();
+    let mut unprocessed_blocks = Vec::new();
+    for log in withdraw_logs {
+        let block = block_of(&log);
+        let hash = log.transaction_hash
+            .expect("log to be mined and contain `transaction_hash`");
+        let chain_ids = app.config.message_chain_ids();
+        let message = MessageToMainnet::from_log(log, home_contract, chain_ids)?.to_bytes();
+        let message_hash = keccak256(&message);
+        withdraw_confirms.total += 1;
+        if collected_hashes.contains(&H256(message_hash)) {
+            withdraw_confirms.processed += 1;
+            continue;
+        }
+        withdraw_confirms.awaiting_quorum.push(hash);
+        let (_, signed) = withdraw_signatures(event_loop, app, foreign_contract, &message)?;
+        if signed {
+            withdraw_confirms.processed += 1;
+        } else {
+            withdraw_confirms.unprocessed.push(hash);
+            unprocessed_blocks.push(block);
+        }
+    }
+    let checked_withdraw_confirm = cmp::max(
+        checkpoint(foreign_head, &unprocessed_blocks),
+        foreign_deploy,
+    );
+
+    info!("checking {} collected signatures", collected.len());
+    let mut withdraw_relays = Messages::default();
+    let mut unprocessed_blocks = Vec::new();
+    for (event, log) in collected {
+        if event.authority_responsible_for_relay != app.config.foreign.account.0.into() {
+            continue;
+        }
+        withdraw_relays.total += 1;
+        let message = stored_message(
+            event_loop,
+            app,
+            foreign_contract,
+            event.message_hash.0,
+        )?;
+        let message = MessageToMainnet::from_bytes(&message);
+        let payload = app.home_bridge
+            .functions()
+            .withdraws()
+            .input(message.sidenet_transaction_hash)
+            .into();
+        let output = event_loop.run(app.timer.timeout(
+            api::call(home, home_contract, payload),
+            home_timeout,
+        ))?;
+        if app.home_bridge
+            .functions()
+            .withdraws()
+            .output(output.0.as_slice())?
+        {
+            withdraw_relays.processed += 1;
+        } else {
+            withdraw_relays
+                .unprocessed
+                .push(log.transaction_hash
+                    .expect("log to be mined and contain `transaction_hash`"));
+            unprocessed_blocks.push(block_of(log));
+        }
+    }
+    let checked_withdraw_relay = cmp::max(
+        checkpoint(foreign_head, &unprocessed_blocks),
+        foreign_deploy,
+    );
+
+    Ok(RebuiltState {
+        database: Database {
+            home_contract_address: home_contract,
+            foreign_contract_address: foreign_contract,
+            home_deploy,
+            foreign_deploy,
+            checked_deposit_relay,
+            checked_withdraw_relay,
+            checked_withdraw_confirm,
+            // detection of unaccounted transfers keeps no state on chain.
+            // transfers since the deployment are reported again
+            checked_unaccounted_transfers: home_deploy,
+            // signatures and executions of calls aren't rebuilt. calls since the deployment
+            // are signed and relayed again and the contracts reject those already done
+            checked_call_confirm: foreign_deploy,
+            checked_call_relay: foreign_deploy,
+            // hashes are recorded again once the streams have yielded
+            checked_deposit_relay_hash: None,
+            checked_withdraw_relay_hash: None,
+            checked_withdraw_confirm_hash: None,
+            // the relayed value isn't recorded on chain. the windows of `value_limits` start over
+            deposit_value_window: None,
+            withdraw_value_window: None,
+            // the chains tell which events were relayed. nothing is in flight
+            in_flight: Vec::new(),
+            // withdraws that wait for the balance of `HomeBridge` are found again
+            parked: Vec::new(),
+            // relays given up aren't recorded on chain. they are found and retried again
+            dead_letters: Vec::new(),
+        },
+        deposits,
+        withdraw_confirms,
+        withdraw_relays,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checkpoint, parse_address};
+
+    #[test]
+    fn test_checkpoint() {
+        assert_eq!(100, checkpoint(100, &[]));
+        assert_eq!(41, checkpoint(100, &[60, 42, 90]));
+        assert_eq!(0, checkpoint(100, &[0]));
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            "49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+            parse_address("0x49edf201c1e139282643d5e7c6fb0c7219ad1db7").unwrap()
+        );
+        assert!(parse_address("0x49edf201").is_err());
+    }
+}
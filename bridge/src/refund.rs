@@ -0,0 +1,266 @@
+/// refunds of ether that reached `HomeBridge` but will never be relayed to `foreign`.
+/// examples: deposits whose relay permanently failed on `foreign`
+/// and unaccounted transfers (see `bridge::unaccounted_transfers`).
+/// a refund is a withdraw message that is keyed by the hash of the transaction
+/// that brought the ether to `HomeBridge` instead of a transaction on `foreign`.
+/// once `requiredSignatures` authorities signed it anyone can execute it via `HomeBridge.withdraw`.
+/// `HomeBridge` records executed messages by that hash so every transaction
+/// can only be refunded once.
+
+use futures::{Async, Future, Poll};
+use rustc_hex::{FromHex, ToHex};
+use tokio_timer::Timeout;
+use web3::Transport;
+use web3::types::{Address, Bytes, H256, H520, TransactionRequest, U256};
+use api::{self, ApiCall};
+use app::App;
+use audit::{self, SignatureRecord};
+use config::parse_uint;
+use contracts::home::HomeBridge;
+use error::Error;
+use message_to_mainnet::{ChainIds, MessageToMainnet};
+use signature::Signature;
+use signer::{remote_signer, sign_message, signing_backend, Chain, MessageSignature};
+use transaction::{relay_transaction, RelayTransaction};
+
+/// returns the refund of `value` wei to `recipient` for the ether that reached
+/// the `HomeBridge` at `home_contract` in transaction `transaction_hash`.
+/// anyone but `recipient` must execute the refund with `gas_price`.
+/// a version 2 message for `chain_ids` if they are `Some` (see `Config::message_chain_ids`)
+pub fn refund_message(
+    recipient: Address,
+    value: U256,
+    transaction_hash: H256,
+    gas_price: U256,
+    home_contract: Address,
+    chain_ids: Option<ChainIds>,
+) -> MessageToMainnet {
+    MessageToMainnet {
+        recipient,
+        value,
+        sidenet_transaction_hash: transaction_hash,
+        mainnet_gas_price: gas_price,
+        mainnet_bridge_address: home_contract,
+        chain_ids,
+    }
+}
+
+/// decodes `s` from hex with optional `0x` prefix
+fn from_hex(s: &str) -> Result<Vec<u8>, Error> {
+    let hex = if s.starts_with("0x") { &s[2..] } else { s };
+    Ok(hex.from_hex()?)
+}
+
+/// returns the `0x`-prefixed hex encoding of `bytes`
+pub fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", bytes.to_hex())
+}
+
+/// parses a hex encoded transaction hash
+pub fn parse_transaction_hash(s: &str) -> Result<H256, Error> {
+    let bytes = from_hex(s)?;
+    if bytes.len() != 32 {
+        bail!("transaction hash must be 32 bytes long");
+    }
+    Ok(bytes.as_slice().into())
+}
+
+/// parses the refund of `value` wei to `recipient` for transaction `transaction_hash`
+/// as given on the command line. see `refund_message`
+pub fn parse_refund(
+    recipient: &str,
+    value: &str,
+    transaction_hash: &str,
+    gas_price: &str,
+    home_contract: Address,
+    chain_ids: Option<ChainIds>,
+) -> Result<MessageToMainnet, Error> {
+    let recipient = from_hex(recipient)?;
+    if recipient.len() != 20 {
+        bail!("recipient must be a 20 byte address");
+    }
+    Ok(refund_message(
+        recipient.as_slice().into(),
+        parse_uint(value)?,
+        parse_transaction_hash(transaction_hash)?,
+        parse_uint(gas_price)?,
+        home_contract,
+        chain_ids,
+    ))
+}
+
+/// parses a hex encoded refund message of either version
+pub fn parse_message(s: &str) -> Result<MessageToMainnet, Error> {
+    MessageToMainnet::try_from_bytes(&from_hex(s)?)
+}
+
+/// parses a hex encoded signature as returned by `eth_sign`
+pub fn parse_signature(s: &str) -> Result<Signature, Error> {
+    Signature::from_bytes(&from_hex(s)?)
+}
+
+/// creates a future that signs `message` with the authority key of `foreign.account`
+/// which also signs withdraw messages
+pub fn sign_refund<T: Transport>(
+    app: &App<T>,
+    message: &MessageToMainnet,
+) -> MessageSignature<T> {
+    sign_message(app, Bytes(message.to_bytes()))
+}
+
+/// appends the `signature` of the refund `message` to the signature audit log if one is configured
+pub fn audit_refund_signature<T: Transport>(
+    app: &App<T>,
+    message: &MessageToMainnet,
+    signature: H520,
+) -> Result<(), Error> {
+    match app.config.signature_audit_log {
+        Some(ref path) => audit::append(
+            path,
+            &SignatureRecord::new(
+                message,
+                app.config.foreign.account,
+                signing_backend(&app.config),
+                signature,
+                None,
+            ),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// creates a future that executes the refund `message` signed by `signatures`
+/// by calling `HomeBridge.withdraw` at `home_contract` from `home.account`.
+/// resolves to `None` if the simulation reverted
+/// (example: the refund was already executed or lacks signatures).
+pub fn execute_refund<T: Transport + Clone>(
+    app: &App<T>,
+    home_contract: Address,
+    message: &MessageToMainnet,
+    signatures: &[Signature],
+) -> RelayTransaction<T> {
+    let payload: Bytes = app.home_bridge
+        .functions()
+        .withdraw()
+        .input(
+            signatures.iter().map(|x| x.v),
+            signatures.iter().map(|x| x.r),
+            signatures.iter().map(|x| x.s),
+            message.to_bytes(),
+        )
+        .into();
+    let request = TransactionRequest {
+        from: app.config.home.account,
+        to: Some(home_contract),
+        gas: Some(app.config.txs.withdraw_relay.gas.into()),
+        gas_price: Some(message.mainnet_gas_price),
+        value: None,
+        data: Some(payload),
+        nonce: None,
+        condition: None,
+    };
+    relay_transaction(
+        app.connections.home.clone(),
+        app.timer.clone(),
+        &app.config.home,
+        request,
+        app.config
+            .home
+            .access_list_mode(&app.config.txs.withdraw_relay),
+    ).via_signer(remote_signer(&app.config), Chain::Home)
+        .via_keystore(app.keystore.clone(), Chain::Home)
+        .with_nonces(app.nonces.get(Chain::Home))
+        .with_fixed_gas_price()
+}
+
+/// creates a future that resolves to whether the `HomeBridge` at `home_contract`
+/// executed a refund or withdraw keyed by `transaction_hash`
+pub fn refund_executed<T: Transport>(
+    app: &App<T>,
+    home_contract: Address,
+    transaction_hash: H256,
+) -> RefundExecuted<T> {
+    let home_bridge = HomeBridge::default();
+    let payload = home_bridge.functions().withdraws().input(transaction_hash).into();
+    RefundExecuted {
+        future: app.timer.timeout(
+            api::call(&app.connections.home, home_contract, payload),
+            app.config.home.request_timeout,
+        ),
+        home_bridge,
+    }
+}
+
+/// future that resolves to whether a refund was executed
+pub struct RefundExecuted<T: Transport> {
+    future: Timeout<ApiCall<Bytes, T::Out>>,
+    home_bridge: HomeBridge,
+}
+
+impl<T: Transport> Future for RefundExecuted<T> {
+    type Item = bool;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let output = try_ready!(self.future.poll());
+        let executed = self.home_bridge
+            .functions()
+            .withdraws()
+            .output(output.0.as_slice())?;
+        Ok(Async::Ready(executed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_message, parse_refund, parse_signature, refund_message, to_hex};
+
+    #[test]
+    fn test_parse_message_roundtrips() {
+        let message = refund_message(1.into(), 100.into(), 2.into(), 3.into(), 4.into());
+        let hex = to_hex(&message.to_bytes());
+        assert_eq!(message, parse_message(&hex).unwrap());
+        assert_eq!(message, parse_message(&hex[2..]).unwrap());
+    }
+
+    #[test]
+    fn test_parse_refund() {
+        let message = parse_refund(
+            "0x0000000000000000000000000000000000000001",
+            "1_000",
+            "0x0000000000000000000000000000000000000000000000000000000000000002",
+            "0x3",
+            4.into(),
+        ).unwrap();
+        assert_eq!(
+            refund_message(1.into(), 1000.into(), 2.into(), 3.into(), 4.into()),
+            message
+        );
+        assert!(parse_refund("0x01", "1", "0x02", "0", 4.into()).is_err());
+        assert!(
+            parse_refund(
+                "0x0000000000000000000000000000000000000001",
+                "-1",
+                "0x0000000000000000000000000000000000000000000000000000000000000002",
+                "0",
+                4.into()
+            ).is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_message_rejects_wrong_length() {
+        assert!(parse_message("0x00").is_err());
+        assert!(parse_message("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        let hex = format!("0x{}{}1c", "11".repeat(32), "22".repeat(32));
+        let signature = parse_signature(&hex).unwrap();
+        assert_eq!(0x1c, signature.v);
+        assert_eq!([0x11u8; 32], signature.r.0);
+        assert_eq!([0x22u8; 32], signature.s.0);
+        assert!(parse_signature("0x1c").is_err());
+    }
+}
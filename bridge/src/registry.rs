@@ -0,0 +1,100 @@
+/// resolution of contract addresses via an on-chain registry.
+/// the registry is expected to implement `getAddress(bytes32,string)`
+/// of parity's `SimpleRegistry` where the first argument is the keccak hash
+/// of the name and the second is the key `"A"` under which addresses are stored.
+
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use tiny_keccak::keccak256;
+use tokio_timer::{Timeout, Timer};
+use web3::Transport;
+use web3::types::{Address, Bytes};
+use api::{self, ApiCall};
+use contracts::registry::Registry;
+use error::Error;
+
+/// key of the address record of a registry entry
+const ADDRESS_KEY: &str = "A";
+
+/// payload of the call to `Registry.getAddress` that resolves `name`
+fn get_address_payload(registry: &Registry, name: &str) -> Bytes {
+    registry
+        .functions()
+        .get_address()
+        .input(keccak256(name.as_bytes()), ADDRESS_KEY.to_owned())
+        .into()
+}
+
+/// creates a future that resolves `name` via the registry contract at `registry_address`
+pub fn resolve_address<T: Transport>(
+    transport: T,
+    timer: &Timer,
+    request_timeout: Duration,
+    registry_address: Address,
+    name: String,
+) -> ResolveAddress<T> {
+    let registry = Registry::default();
+    let payload = get_address_payload(&registry, &name);
+    ResolveAddress {
+        future: timer.timeout(
+            api::call(transport, registry_address, payload),
+            request_timeout,
+        ),
+        registry,
+        registry_address,
+        name,
+    }
+}
+
+/// future that resolves to the address registered under a name
+pub struct ResolveAddress<T: Transport> {
+    future: Timeout<ApiCall<Bytes, T::Out>>,
+    registry: Registry,
+    registry_address: Address,
+    name: String,
+}
+
+impl<T: Transport> Future for ResolveAddress<T> {
+    type Item = Address;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let output = try_ready!(self.future.poll());
+        let address: Address = self.registry
+            .functions()
+            .get_address()
+            .output(output.0.as_slice())?
+            .0
+            .into();
+        if address == Address::default() {
+            bail!(
+                "name `{}` is not registered in registry {}",
+                self.name,
+                self.registry_address
+            );
+        }
+        Ok(Async::Ready(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hex::FromHex;
+    use web3::types::Bytes;
+    use contracts::registry::Registry;
+    use super::get_address_payload;
+
+    #[test]
+    fn test_get_address_payload() {
+        let payload = get_address_payload(&Registry::default(), "HomeBridge");
+        let expected: Bytes = "6795dbcd\
+            f9625891f6a0094907a2115f6a410817067177f398ff24853a2c6dc23febcf92\
+            0000000000000000000000000000000000000000000000000000000000000040\
+            0000000000000000000000000000000000000000000000000000000000000001\
+            4100000000000000000000000000000000000000000000000000000000000000"
+            .from_hex()
+            .unwrap()
+            .into();
+        assert_eq!(expected, payload);
+    }
+}
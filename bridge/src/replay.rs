@@ -0,0 +1,396 @@
+/// replay of a range of blocks after a missed event or a corrupted database.
+/// the logs of the bridge contract on one chain in the range are scanned again
+/// and every message is checked against the contracts like `rebuild` does.
+/// only the messages this authority never processed are relayed:
+/// - `home`: deposits `ForeignBridge` accepts a confirmation of `foreign.account` for
+/// - `foreign`: withdraws that neither collected their signatures nor have a signature
+///   of this authority are signed, and collected signatures this authority is responsible
+///   for are relayed unless `HomeBridge` executed them already
+///
+/// the database is left untouched. the bridge can keep running during a replay
+/// since the contracts reject a second confirmation, signature or withdraw.
+
+use std::sync::Arc;
+use tokio_core::reactor::Core;
+use web3::Transport;
+use web3::types::{Address, Bytes, FilterBuilder, H256, Log, TransactionRequest};
+use ethabi::RawLog;
+use api;
+use app::App;
+use error::Error;
+use message_to_mainnet::MessageToMainnet;
+use rebuild::{block_of, confirmed_head, deposit_confirmed, deposit_request, logs,
+              stored_message, withdraw_signatures};
+use refund::{execute_refund, refund_executed};
+use signature::Signature;
+use signer::{self, remote_signer, Chain};
+use transaction::relay_transaction;
+use util::web3_filter;
+
+/// parses the chain given on the command line
+pub fn parse_chain(s: &str) -> Result<Chain, Error> {
+    match s {
+        "home" => Ok(Chain::Home),
+        "foreign" => Ok(Chain::Foreign),
+        other => bail!("chain must be `home` or `foreign` but is `{}`", other),
+    }
+}
+
+/// messages of one kind found in the replayed range
+#[derive(Debug, PartialEq, Default)]
+pub struct Replayed {
+    /// number of messages in the range
+    pub total: usize,
+    /// number of messages this authority processed already
+    pub processed: usize,
+    /// transaction hashes of the messages and of their relays
+    pub relayed: Vec<(H256, H256)>,
+    /// transaction hashes of the messages whose relay reverted in its simulation
+    pub reverted: Vec<H256>,
+}
+
+/// result of `replay`
+#[derive(Debug, PartialEq)]
+pub struct ReplayReport {
+    pub from_block: u64,
+    pub to_block: u64,
+    /// name of each kind of message and what was replayed of it
+    pub replayed: Vec<(&'static str, Replayed)>,
+}
+
+impl ReplayReport {
+    /// renders the report for the operator
+    pub fn render(&self) -> String {
+        let mut out = format!("replayed blocks {} to {}\n", self.from_block, self.to_block);
+        for &(name, ref replayed) in &self.replayed {
+            out.push_str(&format!(
+                "{}: {} messages, {} processed already, {} relayed, {} reverted\n",
+                name,
+                replayed.total,
+                replayed.processed,
+                replayed.relayed.len(),
+                replayed.reverted.len()
+            ));
+            for &(hash, relay) in &replayed.relayed {
+                out.push_str(&format!("  relayed: {:?} in {:?}\n", hash, relay));
+            }
+            for hash in &replayed.reverted {
+                out.push_str(&format!("  reverted: {:?}\n", hash));
+            }
+        }
+        out
+    }
+}
+
+fn transaction_hash(log: &Log) -> H256 {
+    log.transaction_hash
+        .expect("log to be mined and contain `transaction_hash`")
+}
+
+/// relays the deposits on home in `logs` that `foreign.account` never confirmed
+fn replay_deposits<T: Transport + Clone>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    foreign_contract: Address,
+    logs: &[Log],
+) -> Result<Replayed, Error> {
+    let mut replayed = Replayed::default();
+    for log in logs {
+        let hash = transaction_hash(log);
+        replayed.total += 1;
+        let request = deposit_request(app, foreign_contract, log)?;
+        if deposit_confirmed(event_loop, app, &request)? {
+            replayed.processed += 1;
+            continue;
+        }
+        info!("relaying deposit {:?} of block {}", hash, block_of(log));
+        let relay = relay_transaction(
+            app.connections.foreign.clone(),
+            app.timer.clone(),
+            &app.config.foreign,
+            request,
+            app.config
+                .foreign
+                .access_list_mode(&app.config.txs.deposit_relay),
+        ).via_signer(remote_signer(&app.config), Chain::Foreign)
+            .via_keystore(app.keystore.clone(), Chain::Foreign)
+            .with_nonces(app.nonces.get(Chain::Foreign));
+        match event_loop.run(relay)? {
+            Some(relay) => replayed.relayed.push((hash, relay)),
+            None => replayed.reverted.push(hash),
+        }
+    }
+    Ok(replayed)
+}
+
+/// signs the withdraws on foreign in `logs` that neither collected their signatures
+/// nor have a signature of this authority
+fn replay_withdraw_confirms<T: Transport + Clone>(
+    event_loop: &mut Core,
+    app: &Arc<App<T>>,
+    home_contract: Address,
+    foreign_contract: Address,
+    logs: Vec<Log>,
+) -> Result<Replayed, Error> {
+    let mut replayed = Replayed::default();
+    for log in logs {
+        let hash = transaction_hash(&log);
+        replayed.total += 1;
+        let chain_ids = app.config.message_chain_ids();
+        let message = MessageToMainnet::from_log(log, home_contract, chain_ids)?.to_bytes();
+        let (count, signed) = withdraw_signatures(event_loop, app, foreign_contract, &message)?;
+        if signed || count >= app.config.authorities.required_signatures as usize {
+            replayed.processed += 1;
+            continue;
+        }
+        info!("signing withdraw {:?}", hash);
+        let (account, signature) =
+            event_loop.run(signer::sign_with_failover(app.clone(), Bytes(message.clone())))?;
+        let payload = app.foreign_bridge
+            .functions()
+            .submit_signature()
+            .input(signature.0.to_vec(), message)
+            .into();
+        let request = TransactionRequest {
+            // `submitSignature` requires the sender to be the signer
+            from: account,
+            to: Some(foreign_contract),
+            gas: Some(app.config.txs.withdraw_confirm.gas.into()),
+            gas_price: Some(app.config.txs.withdraw_confirm.gas_price.into()),
+            value: None,
+            data: Some(payload),
+            nonce: None,
+            condition: None,
+        };
+        let future = signer::send_transaction(
+            app,
+            Chain::Foreign,
+            request,
+            app.config.txs.withdraw_confirm.dynamic_fees,
+        )?;
+        replayed.relayed.push((hash, event_loop.run(future)?));
+    }
+    Ok(replayed)
+}
+
+/// relays the collected signatures on foreign in `logs` this authority is responsible for
+/// unless `HomeBridge` executed their withdraws already
+fn replay_withdraw_relays<T: Transport + Clone>(
+    event_loop: &mut Core,
+    app: &App<T>,
+    home_contract: Address,
+    foreign_contract: Address,
+    logs: Vec<Log>,
+) -> Result<Replayed, Error> {
+    let mut replayed = Replayed::default();
+    for log in logs {
+        let hash = transaction_hash(&log);
+        let raw_log = RawLog {
+            topics: log.topics.into_iter().map(|t| t.0.into()).collect(),
+            data: log.data.0,
+        };
+        let event = app.foreign_bridge
+            .events()
+            .collected_signatures()
+            .parse_log(raw_log)?;
+        if event.authority_responsible_for_relay != app.config.foreign.account.0.into() {
+            continue;
+        }
+        replayed.total += 1;
+        let message = stored_message(event_loop, app, foreign_contract, event.message_hash.0)?;
+        let message = MessageToMainnet::try_from_bytes(&message)?;
+        let executed = event_loop.run(refund_executed(
+            app,
+            home_contract,
+            message.sidenet_transaction_hash,
+        ))?;
+        if executed {
+            replayed.processed += 1;
+            continue;
+        }
+        let signatures = (0..app.config.authorities.required_signatures)
+            .map(|index| {
+                let payload = app.foreign_bridge
+                    .functions()
+                    .signature()
+                    .input(event.message_hash, index)
+                    .into();
+                let output = event_loop.run(app.timer.timeout(
+                    api::call(&app.connections.foreign, foreign_contract, payload),
+                    app.config.foreign.request_timeout,
+                ))?;
+                let signature = app.foreign_bridge
+                    .functions()
+                    .signature()
+                    .output(output.0.as_slice())?;
+                Signature::try_from_bytes(&signature)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        info!("relaying withdraw {:?}", hash);
+        // a withdraw is executed like a refund: `HomeBridge.withdraw` with the collected signatures
+        match event_loop.run(execute_refund(app, home_contract, &message, &signatures))? {
+            Some(relay) => replayed.relayed.push((hash, relay)),
+            None => replayed.reverted.push(hash),
+        }
+    }
+    Ok(replayed)
+}
+
+/// replays the messages of the contract on `chain` in blocks `[from_block, to_block]`.
+/// `to_block` must have `required_confirmations`. token bridges can't be replayed
+pub fn replay<T: Transport + Clone>(
+    event_loop: &mut Core,
+    app: &Arc<App<T>>,
+    home_contract: Address,
+    foreign_contract: Address,
+    chain: Chain,
+    from_block: u64,
+    to_block: u64,
+) -> Result<ReplayReport, Error> {
+    if app.config.tokens().is_some() {
+        bail!("token bridges can't be replayed");
+    }
+    if from_block > to_block {
+        bail!("--from-block {} is after --to-block {}", from_block, to_block);
+    }
+    let (transport, node) = match chain {
+        Chain::Home => (&app.connections.home, &app.config.home),
+        Chain::Foreign => (&app.connections.foreign, &app.config.foreign),
+    };
+    let head = confirmed_head(
+        event_loop,
+        app,
+        transport,
+        node.request_timeout,
+        node.required_confirmations,
+    )?;
+    if to_block > head {
+        bail!(
+            "--to-block {} doesn't have {} confirmations yet. the latest block that does is {}",
+            to_block,
+            node.required_confirmations,
+            head
+        );
+    }
+    let range_logs = |event_loop: &mut Core, filter: FilterBuilder| {
+        logs(
+            event_loop,
+            app,
+            transport,
+            node.request_timeout,
+            filter,
+            from_block,
+            to_block,
+        )
+    };
+
+    let mut replayed = Vec::new();
+    match chain {
+        Chain::Home => {
+            info!("fetching deposits on home");
+            let deposit_logs = range_logs(
+                event_loop,
+                web3_filter(
+                    app.home_bridge.events().deposit().create_filter(),
+                    home_contract,
+                ),
+            )?;
+            info!("checking {} deposits", deposit_logs.len());
+            replayed.push((
+                "deposits",
+                replay_deposits(event_loop, app, foreign_contract, &deposit_logs)?,
+            ));
+        }
+        Chain::Foreign => {
+            info!("fetching withdraws on foreign");
+            let withdraw_logs = range_logs(
+                event_loop,
+                web3_filter(
+                    app.foreign_bridge.events().withdraw().create_filter(),
+                    foreign_contract,
+                ),
+            )?;
+            info!("checking {} withdraws", withdraw_logs.len());
+            replayed.push((
+                "withdraw confirms",
+                replay_withdraw_confirms(
+                    event_loop,
+                    app,
+                    home_contract,
+                    foreign_contract,
+                    withdraw_logs,
+                )?,
+            ));
+            if app.config.role.executes_withdraws() {
+                info!("fetching collected signatures on foreign");
+                let collected_logs = range_logs(
+                    event_loop,
+                    web3_filter(
+                        app.foreign_bridge
+                            .events()
+                            .collected_signatures()
+                            .create_filter(),
+                        foreign_contract,
+                    ),
+                )?;
+                info!("checking {} collected signatures", collected_logs.len());
+                replayed.push((
+                    "withdraw relays",
+                    replay_withdraw_relays(
+                        event_loop,
+                        app,
+                        home_contract,
+                        foreign_contract,
+                        collected_logs,
+                    )?,
+                ));
+            }
+        }
+    }
+
+    Ok(ReplayReport {
+        from_block,
+        to_block,
+        replayed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use signer::Chain;
+    use super::{parse_chain, ReplayReport, Replayed};
+
+    #[test]
+    fn test_parse_chain() {
+        assert_eq!(Chain::Home, parse_chain("home").unwrap());
+        assert_eq!(Chain::Foreign, parse_chain("foreign").unwrap());
+        assert!(parse_chain("mainnet").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let report = ReplayReport {
+            from_block: 10,
+            to_block: 20,
+            replayed: vec![
+                (
+                    "deposits",
+                    Replayed {
+                        total: 3,
+                        processed: 1,
+                        relayed: vec![(1.into(), 2.into())],
+                        reverted: vec![3.into()],
+                    },
+                ),
+            ],
+        };
+        assert_eq!(
+            "replayed blocks 10 to 20\n\
+             deposits: 3 messages, 1 processed already, 1 relayed, 1 reverted\n  \
+             relayed: 0x0000000000000000000000000000000000000000000000000000000000000001 in \
+             0x0000000000000000000000000000000000000000000000000000000000000002\n  \
+             reverted: 0x0000000000000000000000000000000000000000000000000000000000000003\n",
+            report.render()
+        );
+    }
+}
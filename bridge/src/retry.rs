@@ -0,0 +1,311 @@
+/// retries of rpc calls that failed for reasons that are likely gone a moment later:
+/// the node restarts, the connection to it was reset, it is still catching up
+/// (`-32000` `header not found`, `missing trie node`) or it is overloaded.
+/// such calls are retried after an exponential backoff with jitter (see `retry` of a node).
+/// reverts, invalid requests and other errors of the node are returned right away.
+/// calls that send transactions are never retried by the transport because the node may
+/// have accepted the transaction before the connection broke. relay transactions
+/// simulate and send again instead (see `transaction::RelayTransaction`).
+/// the log streams ride out timeouts the same way (see `api::LogStream`).
+/// a call is given up once `max_retries` retries failed in a row.
+
+use std::{cmp, fmt};
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use jsonrpc_core as rpc;
+use rand::{self, Rng};
+use tokio_timer::{Sleep, Timer};
+use web3::{self, RequestId, Transport};
+use config::RetryConfig;
+use error::{Error, ErrorKind};
+use metrics::record_retry;
+use pacing::is_rate_limited;
+
+/// json-rpc error code nodes use for most of their errors
+const SERVER_ERROR: i64 = -32000;
+/// messages of `-32000` errors of nodes that are starting, syncing or overloaded
+const TRANSIENT_SERVER_ERRORS: [&str; 6] = [
+    "header not found",
+    "missing trie node",
+    "timeout",
+    "timed out",
+    "try again",
+    "busy",
+];
+/// methods whose calls must not be sent twice
+const SENDING_METHODS: [&str; 2] = ["eth_sendTransaction", "eth_sendRawTransaction"];
+
+/// returns true if the call that failed with `err` is likely to succeed when it is retried.
+/// the transport failed or the node returned a `-32000` error it returns while catching up
+pub fn is_transient(err: &web3::Error) -> bool {
+    match *err {
+        web3::Error::Transport(_) => !is_rate_limited(err),
+        web3::Error::Rpc(ref rpc_error) if rpc_error.code.code() == SERVER_ERROR => {
+            let message = rpc_error.message.to_lowercase();
+            TRANSIENT_SERVER_ERRORS
+                .iter()
+                .any(|transient| message.contains(transient))
+        }
+        _ => false,
+    }
+}
+
+/// returns true if `err` is a timeout, a transient error or a rate limit
+/// that outlasted the pacing of the node
+pub fn is_retryable(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::Timeout(_) | ErrorKind::Io(_) => true,
+        ErrorKind::Web3(ref err) => is_transient(err) || is_rate_limited(err),
+        _ => false,
+    }
+}
+
+/// delays between the retries of a failing call.
+/// the delay doubles with every retry up to `max_backoff`.
+/// with `jitter` a random part of up to half the delay is skipped
+/// so that calls that failed together are not retried in lockstep
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    config: RetryConfig,
+    /// retries since the last success
+    retries: u32,
+}
+
+impl Backoff {
+    pub fn new(config: RetryConfig) -> Self {
+        Backoff { config, retries: 0 }
+    }
+
+    /// returns the delay before the next retry or `None` once `max_retries` are used up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.retries >= self.config.max_retries {
+            return None;
+        }
+        let delay = exponential_delay(&self.config, self.retries);
+        self.retries += 1;
+        if !self.config.jitter {
+            return Some(delay);
+        }
+        let millis = delay.as_secs() * 1000 + u64::from(delay.subsec_nanos() / 1_000_000);
+        let skipped = rand::thread_rng().gen_range(0, millis / 2 + 1);
+        Some(Duration::from_millis(millis - skipped))
+    }
+
+    /// starts over after a success
+    pub fn reset(&mut self) {
+        self.retries = 0;
+    }
+}
+
+/// returns the delay before retry number `retries` + 1 without jitter
+fn exponential_delay(config: &RetryConfig, retries: u32) -> Duration {
+    1u32.checked_shl(retries)
+        .and_then(|factor| config.initial_backoff.checked_mul(factor))
+        .map_or(config.max_backoff, |delay| cmp::min(delay, config.max_backoff))
+}
+
+/// transport that retries the calls to `endpoint` that fail for transient reasons
+#[derive(Clone)]
+pub struct Retrying<T> {
+    transport: T,
+    endpoint: String,
+    timer: Timer,
+    config: RetryConfig,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Retrying<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Retrying")
+            .field("transport", &self.transport)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl<T> Retrying<T> {
+    pub fn new(transport: T, endpoint: String, config: RetryConfig) -> Self {
+        Retrying {
+            transport,
+            endpoint,
+            timer: Timer::default(),
+            config,
+        }
+    }
+}
+
+impl<T: Transport> Transport for Retrying<T> {
+    type Out = RetryingCall<T>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let retried = match request {
+            rpc::Call::MethodCall(ref call) => !SENDING_METHODS.contains(&call.method.as_str()),
+            _ => false,
+        };
+        RetryingCall {
+            state: RetryingCallState::Call(self.transport.send(id, request.clone())),
+            backoff: if retried {
+                Some(Backoff::new(self.config.clone()))
+            } else {
+                None
+            },
+            retrying: self.clone(),
+            id,
+            request,
+        }
+    }
+}
+
+/// state of a retrying call
+enum RetryingCallState<F> {
+    /// waiting for the backoff to pass
+    Wait(Sleep),
+    /// call is in progress
+    Call(F),
+}
+
+/// rpc call that is retried while it fails for transient reasons
+pub struct RetryingCall<T: Transport> {
+    retrying: Retrying<T>,
+    id: RequestId,
+    request: rpc::Call,
+    /// `None` if the call is not retried
+    backoff: Option<Backoff>,
+    state: RetryingCallState<T::Out>,
+}
+
+impl<T: Transport> Future for RetryingCall<T> {
+    type Item = rpc::Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                RetryingCallState::Wait(ref mut sleep) => {
+                    match sleep.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(())) => {}
+                        Err(err) => {
+                            return Err(web3::Error::Transport(format!(
+                                "retry timer failed: {}",
+                                err
+                            )))
+                        }
+                    }
+                    RetryingCallState::Call(
+                        self.retrying
+                            .transport
+                            .send(self.id, self.request.clone()),
+                    )
+                }
+                RetryingCallState::Call(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(value)) => return Ok(Async::Ready(value)),
+                    Err(err) => {
+                        if !is_transient(&err) {
+                            return Err(err);
+                        }
+                        let delay = match self.backoff.as_mut().and_then(Backoff::next_delay) {
+                            Some(delay) => delay,
+                            None => return Err(err),
+                        };
+                        record_retry(&self.retrying.endpoint);
+                        warn!(
+                            "call to {} failed: {:?}. retrying in {:?}",
+                            self.retrying.endpoint, err, delay
+                        );
+                        RetryingCallState::Wait(self.retrying.timer.sleep(delay))
+                    }
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use jsonrpc_core as rpc;
+    use web3;
+    use config::RetryConfig;
+    use error::ErrorKind;
+    use super::{is_retryable, is_transient, Backoff};
+
+    fn server_error(code: i64, message: &str) -> web3::Error {
+        web3::Error::Rpc(rpc::Error {
+            code: rpc::ErrorCode::ServerError(code),
+            message: message.into(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&web3::Error::Transport(
+            "Connection reset by peer".into()
+        )));
+        assert!(is_transient(&server_error(-32000, "header not found")));
+        assert!(is_transient(&server_error(-32000, "Request timed out")));
+        assert!(!is_transient(&server_error(-32000, "nonce too low")));
+        assert!(!is_transient(&server_error(-32005, "limit exceeded")));
+        assert!(!is_transient(&web3::Error::Transport(
+            "Unexpected response status code: 429 Too Many Requests".into()
+        )));
+        assert!(!is_transient(&web3::Error::Rpc(rpc::Error {
+            code: rpc::ErrorCode::InvalidParams,
+            message: "invalid address".into(),
+            data: None,
+        })));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(&ErrorKind::Timeout("eth_getLogs").into()));
+        assert!(is_retryable(
+            &ErrorKind::Web3(server_error(-32005, "limit exceeded")).into()
+        ));
+        assert!(!is_retryable(
+            &ErrorKind::Web3(server_error(3, "execution reverted")).into()
+        ));
+        assert!(!is_retryable(&"Cannot parse database".into()));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(RetryConfig {
+            max_retries: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            jitter: false,
+        });
+        assert_eq!(Some(Duration::from_millis(100)), backoff.next_delay());
+        assert_eq!(Some(Duration::from_millis(200)), backoff.next_delay());
+        assert_eq!(Some(Duration::from_millis(400)), backoff.next_delay());
+        assert_eq!(Some(Duration::from_millis(500)), backoff.next_delay());
+        assert_eq!(None, backoff.next_delay());
+        backoff.reset();
+        assert_eq!(Some(Duration::from_millis(100)), backoff.next_delay());
+    }
+
+    #[test]
+    fn test_backoff_with_jitter() {
+        let mut backoff = Backoff::new(RetryConfig {
+            max_retries: 40,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+        });
+        let mut ceiling = Duration::from_millis(100);
+        for _ in 0..40 {
+            let delay = backoff.next_delay().unwrap();
+            assert!(delay <= ceiling);
+            assert!(delay >= ceiling / 2);
+            ceiling = ::std::cmp::min(ceiling * 2, Duration::from_secs(30));
+        }
+        assert_eq!(None, backoff.next_delay());
+    }
+}
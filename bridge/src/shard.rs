@@ -0,0 +1,662 @@
+/// partitions of the events of an authority leased by the processes relaying them (`[shard]`).
+/// the processes share the table `shard_leases` in postgres with a lease per partition:
+/// the partitions of a bridge by transaction hash, or one per bridge with `partition_by = "token"`
+/// so the bridges of a bridges file are spread among the processes.
+/// every process renews its leases three times per `lease_duration` and takes free
+/// and expired leases until it holds its share of the leases of their scope.
+/// leases above its share are released so that processes that join get theirs.
+/// a lease records up to which block every log stream handled the events of its partition.
+/// a process that takes a lease over rewinds its log streams to these blocks and handles
+/// the events of the partition from there, without handling the events of the partitions
+/// it held all along twice. events are only handled while the leases are valid,
+/// so a process that can't renew them stops before another process takes them over.
+/// compiled in via the cargo feature `shard-leases`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard, Once, ONCE_INIT};
+use std::thread;
+use std::time::Duration;
+use rand;
+use serde_json;
+use web3::types::{H256, Log};
+use config::{Config, PartitionBy, ShardConfig};
+use database::Database;
+use error::Error;
+use status;
+use supervisor;
+
+/// log streams whose events are partitioned among the processes
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardedStream {
+    DepositRelay,
+    WithdrawRelay,
+    WithdrawConfirm,
+    CallConfirm,
+    CallRelay,
+}
+
+/// blocks up to which every log stream handled the events of a partition
+pub type Handled = BTreeMap<ShardedStream, u64>;
+
+/// returns the partition of the event of the transaction `hash` among `partitions`
+pub fn partition(hash: &H256, partitions: u64) -> u64 {
+    let low = hash.0[24..]
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte));
+    low % partitions
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// number of partitions of the events. all events are handled if `None`
+    partitions: Option<u64>,
+    /// held partitions with the blocks up to which their events were handled
+    held: BTreeMap<u64, Handled>,
+    /// blocks up to which the log streams of this process are checked
+    checked: Handled,
+    /// blocks the log streams rewind to to handle the events of taken over partitions
+    rewinds: Handled,
+    /// unix timestamp in seconds until which the held leases are valid
+    valid_until: u64,
+}
+
+/// partitions of the events held by this process. clones share the partitions
+#[derive(Debug, Default, Clone)]
+pub struct Leases {
+    state: Arc<Mutex<State>>,
+}
+
+impl Leases {
+    /// partitions of the events under `shard`. none are held until their leases are taken.
+    /// all events are handled if `shard` is `None`
+    pub fn new(shard: &Option<ShardConfig>) -> Self {
+        let partitions = shard.as_ref().map(|shard| match shard.partition_by {
+            PartitionBy::Hash => shard.partitions,
+            PartitionBy::Token => 1,
+        });
+        Leases {
+            state: Arc::new(Mutex::new(State {
+                partitions,
+                ..State::default()
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<State> {
+        self.state.lock().expect("leases are never poisoned; qed")
+    }
+
+    /// continues from the checkpoints of the log streams in `database`
+    pub fn seed(&self, database: &Database) {
+        self.lock().checked = vec![
+            (ShardedStream::DepositRelay, database.checked_deposit_relay),
+            (ShardedStream::WithdrawRelay, database.checked_withdraw_relay),
+            (ShardedStream::WithdrawConfirm, database.checked_withdraw_confirm),
+            (ShardedStream::CallConfirm, database.checked_call_confirm),
+            (ShardedStream::CallRelay, database.checked_call_relay),
+        ].into_iter()
+            .collect();
+    }
+
+    /// whether this process handles the event `log` of `stream`
+    pub fn handles(&self, stream: ShardedStream, log: &Log) -> bool {
+        let state = self.lock();
+        let partitions = match state.partitions {
+            Some(partitions) => partitions,
+            None => return true,
+        };
+        // the log streams only yield mined logs
+        let (hash, block) = match (log.transaction_hash, log.block_number) {
+            (Some(hash), Some(block)) => (hash, block.low_u64()),
+            _ => return true,
+        };
+        if status::now() >= state.valid_until {
+            return false;
+        }
+        match state.held.get(&partition(&hash, partitions)) {
+            Some(handled) => block > handled.get(&stream).cloned().unwrap_or(0),
+            None => false,
+        }
+    }
+
+    /// records that `stream` is checked up to `block`.
+    /// the events of the held partitions are handled up to there
+    pub fn checked(&self, stream: ShardedStream, block: u64) {
+        let mut state = self.lock();
+        state.checked.insert(stream, block);
+        for handled in state.held.values_mut() {
+            let entry = handled.entry(stream).or_insert(0);
+            *entry = (*entry).max(block);
+        }
+    }
+
+    /// returns the block `stream` has to rewind to to handle the events of taken over partitions
+    pub fn take_rewind(&self, stream: ShardedStream) -> Option<u64> {
+        self.lock().rewinds.remove(&stream)
+    }
+
+    /// holds the partition `index` whose events were handled up to `handled`.
+    /// streams are rewound to the blocks before their checkpoints.
+    /// a partition nobody handled yet continues from the checkpoints of this process
+    fn take(&self, index: u64, handled: Option<Handled>) {
+        let mut state = self.lock();
+        let handled = state
+            .checked
+            .iter()
+            .map(|(&stream, &checked)| {
+                let block = handled
+                    .as_ref()
+                    .and_then(|handled| handled.get(&stream).cloned())
+                    .unwrap_or(checked);
+                (stream, block)
+            })
+            .collect::<Handled>();
+        for (&stream, &block) in &handled {
+            if block < state.checked[&stream] {
+                let rewind = state.rewinds.entry(stream).or_insert(block);
+                *rewind = (*rewind).min(block);
+            }
+        }
+        state.held.insert(index, handled);
+    }
+
+    /// stops handling the events of the partition `index`
+    fn release(&self, index: u64) {
+        self.lock().held.remove(&index);
+    }
+
+    /// returns the held partitions with the blocks up to which their events were handled
+    fn held(&self) -> BTreeMap<u64, Handled> {
+        self.lock().held.clone()
+    }
+
+    /// records that the held leases are valid until `valid_until`
+    fn renewed(&self, valid_until: u64) {
+        self.lock().valid_until = valid_until;
+    }
+}
+
+/// lease of a partition as read from the lease table
+#[derive(Debug, PartialEq, Clone)]
+struct Lease {
+    name: String,
+    owner: Option<String>,
+    /// unix timestamp in seconds of the database
+    expires_at: u64,
+}
+
+impl Lease {
+    fn is_live(&self, now: u64) -> bool {
+        self.owner.is_some() && self.expires_at > now
+    }
+}
+
+/// returns the leases of `own` that `owner` takes and the leases it releases
+/// to hold its share of the leases of `scope` at `now`
+fn plan(scope: &[Lease], own: &[String], owner: &str, now: u64) -> (Vec<String>, Vec<String>) {
+    let mut owners = scope
+        .iter()
+        .filter(|lease| lease.is_live(now))
+        .filter_map(|lease| lease.owner.clone())
+        .collect::<BTreeSet<_>>();
+    owners.insert(owner.to_owned());
+    let share = (scope.len() + owners.len() - 1) / owners.len();
+    let held = scope
+        .iter()
+        .filter(|lease| {
+            lease.is_live(now) && lease.owner.as_ref().map(String::as_str) == Some(owner)
+        })
+        .map(|lease| lease.name.clone())
+        .collect::<Vec<_>>();
+    if held.len() > share {
+        return (Vec::new(), held[share..].to_vec());
+    }
+    let free = scope
+        .iter()
+        .filter(|lease| !lease.is_live(now) && own.contains(&lease.name))
+        .map(|lease| lease.name.clone())
+        .take(share - held.len())
+        .collect();
+    (free, Vec::new())
+}
+
+/// returns the scope of the leases of the bridge of `config` and their names by partition
+fn scope(config: &Config, shard: &ShardConfig, database: &Database) -> (String, Vec<String>) {
+    match shard.partition_by {
+        PartitionBy::Hash => {
+            let scope = format!("hash/{:?}", database.foreign_contract_address);
+            let names = (0..shard.partitions)
+                .map(|index| format!("{}/{}", scope, index))
+                .collect();
+            (scope, names)
+        }
+        PartitionBy::Token => {
+            let token = match config.foreign.token_address {
+                Some(token) => format!("{:?}", token),
+                // an ether bridge bridges the ether locked in its contract
+                None => format!("ether/{:?}", database.foreign_contract_address),
+            };
+            ("token".into(), vec![format!("token/{}", token)])
+        }
+    }
+}
+
+/// name of this process in the lease table if `shard.owner` isn't set. random per start
+fn default_owner() -> &'static str {
+    static INIT: Once = ONCE_INIT;
+    static mut OWNER: *const String = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            let owner = format!("{}-{:08x}", process::id(), rand::random::<u32>());
+            OWNER = Box::into_raw(Box::new(owner));
+        });
+        &*OWNER
+    }
+}
+
+/// renews and takes the leases of a bridge
+struct Keeper {
+    url: String,
+    connection: Option<backend::Connection>,
+    scope: String,
+    names: Vec<String>,
+    owner: String,
+    lease_duration: u64,
+    leases: Leases,
+}
+
+impl Keeper {
+    fn index(&self, name: &str) -> u64 {
+        self.names
+            .iter()
+            .position(|own| own == name)
+            .expect("only own leases are taken and released; qed") as u64
+    }
+
+    /// renews the held leases, then takes and releases leases to hold the share of this process
+    fn tick(&mut self) -> Result<(), Error> {
+        if self.connection.is_none() {
+            self.connection = Some(backend::connect(&self.url)?);
+        }
+        let connection = self.connection
+            .as_ref()
+            .expect("connection was just set; qed");
+        // the leases are valid for this process from before their expiry was written
+        let valid_until = status::now() + self.lease_duration;
+        let now = backend::now(connection)?;
+        let expires_at = now + self.lease_duration;
+        for (index, handled) in self.leases.held() {
+            let name = &self.names[index as usize];
+            if !backend::renew(connection, name, &self.owner, expires_at, &handled)? {
+                warn!("lost the lease {} to another process", name);
+                self.leases.release(index);
+            }
+        }
+        let scope = backend::leases(connection, &self.scope)?;
+        let (take, release) = plan(&scope, &self.names, &self.owner, now);
+        let held = self.leases.held();
+        for name in release {
+            let index = self.index(&name);
+            backend::release(connection, &name, &self.owner, &held[&index])?;
+            self.leases.release(index);
+            info!("released the lease {} to another process", name);
+        }
+        for name in take {
+            if let Some(handled) = backend::take(connection, &name, &self.owner, now, expires_at)? {
+                let handled = handled.and_then(|handled| serde_json::from_str(&handled).ok());
+                info!("took the lease {}. its events were handled up to {:?}", name, handled);
+                self.leases.take(self.index(&name), handled);
+            }
+        }
+        self.leases.renewed(valid_until);
+        Ok(())
+    }
+
+    /// ticks three times per `lease_duration` until the process exits.
+    /// reconnects before the next tick if one fails
+    fn run(mut self) {
+        let interval = Duration::from_secs(self.lease_duration) / 3;
+        loop {
+            thread::sleep(interval);
+            if let Err(err) = self.tick() {
+                let message = format!("failed to renew the leases of {}: {}", self.scope, err);
+                warn!("{}", message);
+                status::record_error(message);
+                self.connection = None;
+            }
+        }
+    }
+}
+
+/// takes the share of the leases of the bridge of `config` and `database` for `leases`
+/// and starts the thread that keeps them. fails if the lease table can't be reached
+pub fn start(config: &Config, database: &Database, leases: Leases) -> Result<(), Error> {
+    let shard = match config.shard {
+        Some(ref shard) => shard,
+        None => return Ok(()),
+    };
+    let (scope, names) = scope(config, shard, database);
+    let owner = shard
+        .owner
+        .clone()
+        .unwrap_or_else(|| default_owner().to_owned());
+    let connection = backend::connect(&shard.url)?;
+    backend::register(&connection, &scope, &names, &owner)?;
+    leases.seed(database);
+    let mut keeper = Keeper {
+        url: shard.url.clone(),
+        connection: Some(connection),
+        scope,
+        names,
+        owner,
+        lease_duration: shard.lease_duration.as_secs(),
+        leases,
+    };
+    keeper.tick()?;
+    info!(
+        "leasing the partitions of {} as {}. holding {}",
+        keeper.scope,
+        keeper.owner,
+        keeper.leases.held().len()
+    );
+    thread::Builder::new()
+        .name(supervisor::prefixed("shard-leases"))
+        .spawn(move || keeper.run())?;
+    Ok(())
+}
+
+#[cfg(feature = "shard-leases")]
+mod backend {
+    use postgres::TlsMode;
+    use serde_json;
+    use error::{Error, ResultExt};
+    use super::{Handled, Lease};
+
+    pub use postgres::Connection;
+
+    pub fn connect(url: &str) -> Result<Connection, Error> {
+        Connection::connect(url, TlsMode::None).chain_err(|| "Cannot connect to postgres")
+    }
+
+    /// creates the lease table and the leases of `names` in `scope` if they don't exist.
+    /// frees the leases of `names` that `owner` held before a restart
+    pub fn register(
+        connection: &Connection,
+        scope: &str,
+        names: &[String],
+        owner: &str,
+    ) -> Result<(), Error> {
+        connection
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS shard_leases (
+                    name TEXT PRIMARY KEY,
+                    scope TEXT NOT NULL,
+                    owner TEXT,
+                    expires_at BIGINT NOT NULL DEFAULT 0,
+                    handled TEXT
+                )",
+            )
+            .chain_err(|| "Cannot create table shard_leases")?;
+        for name in names {
+            connection
+                .execute(
+                    "INSERT INTO shard_leases (name, scope) VALUES ($1, $2) \
+                     ON CONFLICT (name) DO NOTHING",
+                    &[name, &scope],
+                )
+                .chain_err(|| format!("Cannot register lease {}", name))?;
+            connection
+                .execute(
+                    "UPDATE shard_leases SET owner = NULL, expires_at = 0 \
+                     WHERE name = $1 AND owner = $2",
+                    &[name, &owner],
+                )
+                .chain_err(|| format!("Cannot free lease {}", name))?;
+        }
+        Ok(())
+    }
+
+    /// returns the time of the database. the expiry of the leases follows it
+    pub fn now(connection: &Connection) -> Result<u64, Error> {
+        let now = connection
+            .query("SELECT EXTRACT(EPOCH FROM now())::BIGINT", &[])
+            .chain_err(|| "Cannot read the time of postgres")?
+            .get(0)
+            .get::<_, i64>(0);
+        Ok(now as u64)
+    }
+
+    fn to_json(handled: &Handled) -> String {
+        serde_json::to_string(handled).expect("serialization can't fail; qed")
+    }
+
+    /// extends the lease `name` of `owner` to `expires_at`. `false` if `owner` lost it
+    pub fn renew(
+        connection: &Connection,
+        name: &str,
+        owner: &str,
+        expires_at: u64,
+        handled: &Handled,
+    ) -> Result<bool, Error> {
+        let updated = connection
+            .execute(
+                "UPDATE shard_leases SET expires_at = $3, handled = $4 \
+                 WHERE name = $1 AND owner = $2",
+                &[&name, &owner, &(expires_at as i64), &to_json(handled)],
+            )
+            .chain_err(|| format!("Cannot renew lease {}", name))?;
+        Ok(updated > 0)
+    }
+
+    /// returns the leases of `scope`
+    pub fn leases(connection: &Connection, scope: &str) -> Result<Vec<Lease>, Error> {
+        let rows = connection
+            .query(
+                "SELECT name, owner, expires_at FROM shard_leases WHERE scope = $1 ORDER BY name",
+                &[&scope],
+            )
+            .chain_err(|| format!("Cannot read the leases of {}", scope))?;
+        Ok(rows.iter()
+            .map(|row| Lease {
+                name: row.get(0),
+                owner: row.get(1),
+                expires_at: row.get::<_, i64>(2) as u64,
+            })
+            .collect())
+    }
+
+    /// takes the lease `name` for `owner` until `expires_at` if it is free or expired at `now`.
+    /// returns the blocks up to which its events were handled if it was taken
+    pub fn take(
+        connection: &Connection,
+        name: &str,
+        owner: &str,
+        now: u64,
+        expires_at: u64,
+    ) -> Result<Option<Option<String>>, Error> {
+        let rows = connection
+            .query(
+                "UPDATE shard_leases SET owner = $2, expires_at = $4 \
+                 WHERE name = $1 AND (owner IS NULL OR expires_at <= $3) RETURNING handled",
+                &[&name, &owner, &(now as i64), &(expires_at as i64)],
+            )
+            .chain_err(|| format!("Cannot take lease {}", name))?;
+        Ok(rows.iter().next().map(|row| row.get(0)))
+    }
+
+    /// frees the lease `name` of `owner` for another process
+    pub fn release(
+        connection: &Connection,
+        name: &str,
+        owner: &str,
+        handled: &Handled,
+    ) -> Result<(), Error> {
+        connection
+            .execute(
+                "UPDATE shard_leases SET owner = NULL, expires_at = 0, handled = $3 \
+                 WHERE name = $1 AND owner = $2",
+                &[&name, &owner, &to_json(handled)],
+            )
+            .chain_err(|| format!("Cannot release lease {}", name))?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "shard-leases"))]
+mod backend {
+    use error::Error;
+    use super::{Handled, Lease};
+
+    pub struct Connection;
+
+    pub fn connect(_: &str) -> Result<Connection, Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+
+    pub fn register(_: &Connection, _: &str, _: &[String], _: &str) -> Result<(), Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+
+    pub fn now(_: &Connection) -> Result<u64, Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+
+    pub fn renew(_: &Connection, _: &str, _: &str, _: u64, _: &Handled) -> Result<bool, Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+
+    pub fn leases(_: &Connection, _: &str) -> Result<Vec<Lease>, Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+
+    pub fn take(
+        _: &Connection,
+        _: &str,
+        _: &str,
+        _: u64,
+        _: u64,
+    ) -> Result<Option<Option<String>>, Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+
+    pub fn release(_: &Connection, _: &str, _: &str, _: &Handled) -> Result<(), Error> {
+        unreachable!("shards are only configured if feature `shard-leases` is enabled; qed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::{H256, Log};
+    use config::{PartitionBy, ShardConfig};
+    use database::Database;
+    use super::{partition, plan, Lease, Leases, ShardedStream};
+
+    fn log(hash: u64, block: u64) -> Log {
+        Log {
+            transaction_hash: Some(hash.into()),
+            block_number: Some(block.into()),
+            ..Default::default()
+        }
+    }
+
+    fn lease(name: &str, owner: Option<&str>, expires_at: u64) -> Lease {
+        Lease {
+            name: name.into(),
+            owner: owner.map(Into::into),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_partition() {
+        for hash in (0u64..12).map(H256::from) {
+            assert!(partition(&hash, 3) < 3);
+        }
+        assert_eq!(2, partition(&H256::from(5), 3));
+        assert_eq!(0, partition(&H256::from(5), 1));
+    }
+
+    #[test]
+    fn test_plan() {
+        let own = vec!["a".to_owned(), "b".to_owned(), "c".to_owned(), "d".to_owned()];
+        // alone it takes all
+        let scope = vec![
+            lease("a", None, 0),
+            lease("b", None, 0),
+            lease("c", Some("x"), 90),
+            lease("d", None, 0),
+        ];
+        let all = (own.clone(), Vec::new());
+        assert_eq!(all, plan(&scope, &own, "x", 100));
+        // a live process gets half
+        let scope = vec![
+            lease("a", Some("y"), 110),
+            lease("b", None, 0),
+            lease("c", Some("x"), 110),
+            lease("d", None, 0),
+        ];
+        assert_eq!((vec!["b".to_owned()], Vec::new()), plan(&scope, &own, "x", 100));
+        // a joining process takes a free lease first
+        let mut scope = vec![
+            lease("a", Some("x"), 110),
+            lease("b", Some("x"), 110),
+            lease("c", Some("x"), 110),
+            lease("d", None, 0),
+        ];
+        assert_eq!((vec!["d".to_owned()], Vec::new()), plan(&scope, &own, "y", 100));
+        // then leases above the share are released to it
+        scope[3] = lease("d", Some("y"), 110);
+        assert_eq!((Vec::new(), vec!["c".to_owned()]), plan(&scope, &own, "x", 100));
+        assert_eq!((Vec::new(), Vec::new()), plan(&scope, &own, "y", 100));
+    }
+
+    #[test]
+    fn test_leases() {
+        let shard = ShardConfig {
+            url: String::new(),
+            owner: None,
+            partition_by: PartitionBy::Hash,
+            partitions: 2,
+            lease_duration: Default::default(),
+        };
+        let leases = Leases::new(&Some(shard));
+        leases.seed(&Database {
+            checked_deposit_relay: 100,
+            ..Default::default()
+        });
+        leases.renewed(u64::max_value());
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(0, 101)));
+
+        // a new partition continues from the checkpoints
+        leases.take(0, None);
+        assert!(leases.handles(ShardedStream::DepositRelay, &log(0, 101)));
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(1, 101)));
+        assert_eq!(None, leases.take_rewind(ShardedStream::DepositRelay));
+
+        // a taken over partition rewinds the stream to where its previous owner stopped
+        let handled = vec![(ShardedStream::DepositRelay, 80)].into_iter().collect();
+        leases.take(1, Some(handled));
+        assert_eq!(Some(80), leases.take_rewind(ShardedStream::DepositRelay));
+        assert_eq!(None, leases.take_rewind(ShardedStream::DepositRelay));
+        assert!(leases.handles(ShardedStream::DepositRelay, &log(1, 81)));
+        // the events of the partition held all along aren't handled again
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(0, 81)));
+
+        leases.checked(ShardedStream::DepositRelay, 90);
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(1, 90)));
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(0, 100)));
+
+        leases.release(1);
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(1, 101)));
+        leases.renewed(0);
+        assert!(!leases.handles(ShardedStream::DepositRelay, &log(0, 101)));
+    }
+
+    #[test]
+    fn test_leases_unsharded() {
+        let leases = Leases::new(&None);
+        assert!(leases.handles(ShardedStream::WithdrawConfirm, &log(1, 1)));
+    }
+}
@@ -1,14 +1,76 @@
 /// ECDSA signatures:
 /// conversion from/to byte vectors.
 /// from/to v, r, s components.
+/// recovery of the signer of a withdraw message like `ecrecover` in `HomeBridge.withdraw`.
 
 use ethereum_types::H256;
 use ethabi;
+use secp256k1::{ContextFlag, Message, PublicKey, RecoverableSignature, RecoveryId, Secp256k1};
+use tiny_keccak::keccak256;
+use web3::types::Address;
 
-use error::Error;
+use error::{Error, ResultExt};
 
 pub const SIGNATURE_LENGTH: usize = 65;
 
+/// hash of `message` that authorities sign and `ecrecover` recovers the signer from.
+/// `message` is prefixed by `\x19Ethereum Signed Message:\n` and its length like `eth_sign`
+/// does and `MessageSigning.hashMessage` of the contracts expects
+pub fn hash_message(message: &[u8]) -> [u8; 32] {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    keccak256(&prefixed)
+}
+
+/// returns the account of the secp256k1 key `public`
+pub fn public_key_address(context: &Secp256k1, public: &PublicKey) -> Address {
+    // uncompressed keys are prefixed with 0x04
+    let hash = keccak256(&public.serialize_vec(context, false)[1..]);
+    Address::from(&hash[12..])
+}
+
+/// checks `signatures` of the withdraw `message` like `HomeBridge.withdraw` does:
+/// the first `required_signatures` must recover to distinct accounts among `authorities`.
+/// fails with the first signature that `HomeBridge.withdraw` would reject
+pub fn verify_signatures(
+    message: &[u8],
+    signatures: &[Signature],
+    authorities: &[Address],
+    required_signatures: u32,
+) -> Result<(), Error> {
+    if signatures.len() < required_signatures as usize {
+        bail!(
+            "{} signatures instead of the {} required",
+            signatures.len(),
+            required_signatures
+        );
+    }
+    let mut signers = Vec::new();
+    for (index, signature) in signatures
+        .iter()
+        .take(required_signatures as usize)
+        .enumerate()
+    {
+        let signer = signature
+            .recover(message)
+            .chain_err(|| format!("Cannot recover the signer of signature {}", index))?;
+        if !authorities.contains(&signer) {
+            bail!("signature {} is by {:?} which is no authority", index, signer);
+        }
+        if signers.contains(&signer) {
+            bail!("signature {} is another signature by {:?}", index, signer);
+        }
+        signers.push(signer);
+    }
+    Ok(())
+}
+
+/// order of the secp256k1 curve. `r` and `s` of valid signatures are below it
+const SECP256K1_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
 /// an ECDSA signature consisting of `v`, `r` and `s`
 #[derive(PartialEq, Debug)]
 pub struct Signature {
@@ -30,6 +92,22 @@ impl Signature {
         })
     }
 
+    /// parses a signature that `ecrecover` in `HomeBridge.withdraw` can recover.
+    /// fails if `bytes` isn't `SIGNATURE_LENGTH` long, `v` isn't 27 or 28
+    /// or `r` or `s` is out of the range of the curve
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let signature = Self::from_bytes(bytes)?;
+        if signature.v != 27 && signature.v != 28 {
+            bail!("signature has v {} instead of 27 or 28", signature.v);
+        }
+        for &(name, value) in &[("r", signature.r), ("s", signature.s)] {
+            if value.is_zero() || value.0 >= SECP256K1_ORDER {
+                bail!("signature has {} {:?} out of range", name, value);
+            }
+        }
+        Ok(signature)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut result = vec![0u8; SIGNATURE_LENGTH];
         result[0..32].copy_from_slice(&self.r.0[..]);
@@ -41,13 +119,102 @@ impl Signature {
     pub fn to_payload(&self) -> Vec<u8> {
         ethabi::encode(&[ethabi::Token::Bytes(self.to_bytes())])
     }
+
+    /// recovers the account that signed `message` like `ecrecover` in `HomeBridge.withdraw`
+    pub fn recover(&self, message: &[u8]) -> Result<Address, Error> {
+        let context = Secp256k1::with_caps(ContextFlag::VerifyOnly);
+        let recovery_id = RecoveryId::from_i32(i32::from(self.v) - 27)
+            .map_err(|_| format!("signature has v {} instead of 27 or 28", self.v))?;
+        let mut rs = [0u8; 64];
+        rs[..32].copy_from_slice(&self.r.0);
+        rs[32..].copy_from_slice(&self.s.0);
+        let signature = RecoverableSignature::from_compact(&context, &rs, recovery_id)
+            .map_err(|err| format!("signature is malformed: {:?}", err))?;
+        let hash = Message::from_slice(&hash_message(message))
+            .expect("hashes are 32 bytes long; qed");
+        let public = context
+            .recover(&hash, &signature)
+            .map_err(|err| format!("signature recovers to no key: {:?}", err))?;
+        Ok(public_key_address(&context, &public))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use quickcheck::TestResult;
+    use rustc_hex::FromHex;
+    use keystore::LocalKey;
     use super::*;
 
+    fn key(secret: &str) -> LocalKey {
+        LocalKey::from_secret(&secret.from_hex().unwrap()).unwrap()
+    }
+
+    fn sign(key: &LocalKey, message: &[u8]) -> Signature {
+        Signature::from_bytes(&key.sign_message(message).unwrap().0).unwrap()
+    }
+
+    #[test]
+    fn test_signature_recover() {
+        let key = key("7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+        let message = b"withdraw message";
+        let signature = sign(&key, message);
+        assert_eq!(key.address(), signature.recover(message).unwrap());
+        assert!(signature.recover(b"another message").unwrap() != key.address());
+
+        let bad_v = Signature { v: 29, ..signature };
+        assert!(bad_v.recover(message).is_err());
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let first = key("7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d");
+        let second = key("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318");
+        let outsider = key("0000000000000000000000000000000000000000000000000000000000000001");
+        let authorities = vec![first.address(), second.address()];
+        let message = b"withdraw message";
+
+        let signatures = vec![sign(&first, message), sign(&second, message)];
+        assert!(verify_signatures(message, &signatures, &authorities, 2).is_ok());
+        assert!(verify_signatures(message, &signatures[..1], &authorities, 1).is_ok());
+        // too few signatures
+        assert!(verify_signatures(message, &signatures[..1], &authorities, 2).is_err());
+        // signatures of another message
+        assert!(verify_signatures(b"another message", &signatures, &authorities, 2).is_err());
+        // signature of an account that is no authority
+        let signatures = vec![sign(&first, message), sign(&outsider, message)];
+        assert!(verify_signatures(message, &signatures, &authorities, 2).is_err());
+        // the same authority twice
+        let signatures = vec![sign(&second, message), sign(&second, message)];
+        assert!(verify_signatures(message, &signatures, &authorities, 2).is_err());
+    }
+
+    #[test]
+    fn test_signature_try_from_bytes() {
+        let signature = Signature {
+            v: 27,
+            r: "0x75ebc3036b5a5a758be9a8c0e6f6ed8d46c640dda39845de99d9570ba76798e2".into(),
+            s: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7eac4a655451e159313c3641e".into(),
+        };
+        let bytes = signature.to_bytes();
+        assert_eq!(signature, Signature::try_from_bytes(&bytes).unwrap());
+        assert!(Signature::try_from_bytes(&bytes[1..]).is_err());
+
+        let mut bad_v = bytes.clone();
+        bad_v[64] = 1;
+        assert!(Signature::try_from_bytes(&bad_v).is_err());
+
+        let mut zero_r = bytes.clone();
+        for byte in &mut zero_r[0..32] {
+            *byte = 0;
+        }
+        assert!(Signature::try_from_bytes(&zero_r).is_err());
+
+        let mut s_beyond_order = bytes;
+        s_beyond_order[32..64].copy_from_slice(&SECP256K1_ORDER);
+        assert!(Signature::try_from_bytes(&s_beyond_order).is_err());
+    }
+
     quickcheck! {
         fn quickcheck_signature_roundtrips(v: u8, r_raw: Vec<u8>, s_raw: Vec<u8>) -> TestResult {
             if r_raw.len() != 32 || s_raw.len() != 32 {
@@ -0,0 +1,1166 @@
+/// signing by a separate signer daemon.
+/// `parity-bridge signer` runs next to the nodes that hold the unlocked authority accounts.
+/// a bridge configured with `signer` (the relayer) tracks both chains but holds no keys:
+/// it asks the signer for every signature and every transaction over a local unix socket.
+/// the protocol is narrow. the signer only
+/// - signs withdraw messages (and refunds) bound to the `HomeBridge` of the deployment
+/// - sends transactions from the authority accounts without value to the bridge contracts
+///   that call `ForeignBridge.deposit`, `ForeignBridge.depositWithFee`, `ForeignBridge.submitSignature`,
+///   `ForeignBridge.claimRelayFees` or `HomeBridge.withdraw`.
+///   the node of the signer assigns the nonces
+/// requests are authenticated with a token shared via `signer.token_file`.
+/// the socket is only accessible by the user running the signer.
+/// a compromised relayer can't obtain the keys, can't sign for other contracts
+/// and can't send ether from the authority accounts.
+///
+/// every request is a line of json on a new connection, answered by a line of json.
+///
+/// with `[keystore]` configured the key of the keystore signs instead of the nodes
+/// (see `LocalSigner`). accounts the keystore doesn't hold are still signed for by the nodes.
+
+use std::fs;
+use std::io::Read;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use futures::future::{self, FutureResult};
+use futures::sync::oneshot;
+use serde_json;
+use tokio_core::reactor::Core;
+use tokio_timer::{Timeout, Timer};
+use web3::Transport;
+use web3::types::{Address, Bytes, H256, H520, TransactionRequest, U256};
+use ethereum_types;
+use api::{self, ApiCall, TypedTransactionRequest};
+use app::App;
+use config::{Config, KeystoreConfig, SignerConfig};
+use contracts::foreign::ForeignBridge;
+use contracts::home::HomeBridge;
+use dry_run::{self, DryRun};
+use error::{Error, ResultExt};
+use keystore::LocalKey;
+use message_to_mainnet::{ChainIds, MessageToMainnet};
+use raw_transaction;
+use status;
+use transaction::DynamicFees;
+
+/// chain a transaction is sent to
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chain {
+    Home,
+    Foreign,
+}
+
+/// transaction the relayer asks the signer to send
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SignerTransaction {
+    pub chain: Chain,
+    pub from: Address,
+    pub to: Address,
+    pub gas: U256,
+    pub gas_price: U256,
+    pub data: Bytes,
+    /// EIP-1559 fees. the transaction is sent as legacy transaction without them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl SignerTransaction {
+    /// returns the transaction for `request` to `chain`.
+    /// fails if `request` lacks a field or transfers value
+    pub fn from_request(chain: Chain, request: &TransactionRequest) -> Result<Self, Error> {
+        if request.value.map_or(false, |value| !value.is_zero()) {
+            bail!("the signer never sends transactions with value");
+        }
+        match (
+            request.to,
+            request.gas,
+            request.gas_price,
+            request.data.as_ref(),
+        ) {
+            (Some(to), Some(gas), Some(gas_price), Some(data)) => Ok(SignerTransaction {
+                chain,
+                from: request.from,
+                to,
+                gas,
+                gas_price,
+                data: data.clone(),
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+            }),
+            _ => bail!("transactions for the signer need `to`, `gas`, `gas_price` and `data`"),
+        }
+    }
+
+    /// sends the transaction as EIP-1559 transaction with `dynamic_fees` if they are set
+    pub fn with_dynamic_fees(mut self, dynamic_fees: Option<DynamicFees>) -> Self {
+        if let Some(fees) = dynamic_fees {
+            self.max_fee_per_gas = Some(fees.max_fee_per_gas.unwrap_or(self.gas_price));
+            self.max_priority_fee_per_gas = Some(fees.max_priority_fee_per_gas);
+        }
+        self
+    }
+
+    fn dynamic_fees(&self) -> Option<DynamicFees> {
+        self.max_priority_fee_per_gas
+            .map(|max_priority_fee_per_gas| DynamicFees {
+                max_fee_per_gas: self.max_fee_per_gas,
+                max_priority_fee_per_gas,
+            })
+    }
+
+    fn to_request(&self) -> TransactionRequest {
+        TransactionRequest {
+            from: self.from,
+            to: Some(self.to),
+            gas: Some(self.gas),
+            gas_price: Some(self.gas_price),
+            value: None,
+            data: Some(self.data.clone()),
+            nonce: None,
+            condition: None,
+        }
+    }
+}
+
+/// request to the signer
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SignerRequest {
+    /// sign a withdraw message with the authority account on `foreign`
+    SignMessage { message: Bytes },
+    /// send a transaction
+    SendTransaction { transaction: SignerTransaction },
+}
+
+/// response of the signer
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerResponse {
+    Signature(H520),
+    TransactionHash(H256),
+    /// the request was refused or failed
+    Error(String),
+}
+
+/// request as sent over the socket
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    token: String,
+    request: SignerRequest,
+}
+
+/// returns the function selector of `payload`
+fn selector(payload: &[u8]) -> &[u8] {
+    &payload[..4]
+}
+
+/// what the signer signs and sends
+#[derive(Debug, PartialEq, Clone)]
+pub struct Policy {
+    pub home_contract: Address,
+    pub foreign_contract: Address,
+    pub home_account: Address,
+    pub foreign_account: Address,
+    /// chain ids every message must name (see `Config::message_chain_ids`).
+    /// messages with chain ids are refused if `None`
+    pub chain_ids: Option<ChainIds>,
+}
+
+impl Policy {
+    /// returns the reason why `request` must be refused or `None` if it may be served
+    pub fn check(&self, request: &SignerRequest) -> Option<String> {
+        match *request {
+            SignerRequest::SignMessage { ref message } => {
+                let message = match MessageToMainnet::try_from_bytes(&message.0) {
+                    Ok(message) => message,
+                    Err(err) => return Some(format!("message is malformed: {}", err)),
+                };
+                if message.mainnet_bridge_address != self.home_contract {
+                    return Some(format!(
+                        "message is meant for HomeBridge at {:?} instead of {:?}",
+                        message.mainnet_bridge_address, self.home_contract
+                    ));
+                }
+                if message.chain_ids != self.chain_ids {
+                    return Some(format!(
+                        "message is meant for chains {:?} instead of {:?}",
+                        message.chain_ids, self.chain_ids
+                    ));
+                }
+                None
+            }
+            SignerRequest::SendTransaction { ref transaction } => {
+                let (account, contract, payloads) = match transaction.chain {
+                    Chain::Home => (
+                        self.home_account,
+                        self.home_contract,
+                        vec![
+                            HomeBridge::default().functions().withdraw().input(
+                                Vec::<u8>::new(),
+                                Vec::<ethereum_types::H256>::new(),
+                                Vec::<ethereum_types::H256>::new(),
+                                Vec::<u8>::new(),
+                            ),
+                        ],
+                    ),
+                    Chain::Foreign => {
+                        let foreign = ForeignBridge::default();
+                        (
+                            self.foreign_account,
+                            self.foreign_contract,
+                            vec![
+                                foreign.functions().deposit().input(
+                                    ethereum_types::Address::zero(),
+                                    ethereum_types::U256::zero(),
+                                    [0u8; 32],
+                                ),
+                                foreign
+                                    .functions()
+                                    .submit_signature()
+                                    .input(Vec::<u8>::new(), Vec::<u8>::new()),
+                                foreign.functions().deposit_with_fee().input(
+                                    ethereum_types::Address::zero(),
+                                    ethereum_types::U256::zero(),
+                                    [0u8; 32],
+                                    ethereum_types::U256::zero(),
+                                ),
+                                foreign.functions().claim_relay_fees().input(),
+                            ],
+                        )
+                    }
+                };
+                if transaction.from != account {
+                    return Some(format!(
+                        "transactions must be sent from {:?} but this one is from {:?}",
+                        account, transaction.from
+                    ));
+                }
+                if transaction.to != contract {
+                    return Some(format!(
+                        "transactions must be sent to {:?} but this one is to {:?}",
+                        contract, transaction.to
+                    ));
+                }
+                let data = &transaction.data.0;
+                if data.len() < 4
+                    || !payloads
+                        .iter()
+                        .any(|payload| selector(payload) == selector(data))
+                {
+                    return Some("transaction calls a function the signer doesn't serve".into());
+                }
+                None
+            }
+        }
+    }
+}
+
+/// reads the token clients authenticate with from `path`
+pub fn read_token(path: &Path) -> Result<String, Error> {
+    let mut token = String::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut token))
+        .chain_err(|| format!("Cannot read token from {:?}", path))?;
+    let token = token.trim().to_owned();
+    if token.is_empty() {
+        bail!("token in {:?} is empty", path);
+    }
+    Ok(token)
+}
+
+/// compares `a` and `b` in time independent of their content
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// returns the response to the request in `line`
+fn respond<F>(line: &str, token: &str, policy: &Policy, serve: &mut F) -> SignerResponse
+where
+    F: FnMut(&SignerRequest) -> Result<SignerResponse, Error>,
+{
+    let envelope: Envelope = match serde_json::from_str(line) {
+        Ok(envelope) => envelope,
+        Err(err) => return SignerResponse::Error(format!("malformed request: {}", err)),
+    };
+    if !constant_time_eq(envelope.token.as_bytes(), token.as_bytes()) {
+        warn!("rejected signer request with invalid token");
+        return SignerResponse::Error("invalid token".into());
+    }
+    if let Some(reason) = policy.check(&envelope.request) {
+        warn!("refused signer request: {}. request: {:?}", reason, envelope.request);
+        return SignerResponse::Error(reason);
+    }
+    match serve(&envelope.request) {
+        Ok(response) => {
+            info!("served signer request {:?}: {:?}", envelope.request, response);
+            response
+        }
+        Err(err) => {
+            error!("signer request {:?} failed: {}", envelope.request, err);
+            SignerResponse::Error(err.to_string())
+        }
+    }
+}
+
+/// serves requests on `config.socket` one at a time until the process exits.
+/// requests that are authenticated and allowed by `policy` are passed to `serve`
+#[cfg(unix)]
+pub fn listen<F>(config: &SignerConfig, policy: &Policy, mut serve: F) -> Result<(), Error>
+where
+    F: FnMut(&SignerRequest) -> Result<SignerResponse, Error>,
+{
+    // fail early if the token can't be read
+    read_token(&config.token_file)?;
+    // the socket of a previous run prevents binding
+    let _ = fs::remove_file(&config.socket);
+    let listener = UnixListener::bind(&config.socket)
+        .chain_err(|| format!("Cannot bind signer socket {:?}", config.socket))?;
+    fs::set_permissions(&config.socket, fs::Permissions::from_mode(0o600))?;
+    info!("signer listening on {:?}", config.socket);
+
+    for stream in listener.incoming() {
+        let result = stream.map_err(Error::from).and_then(|mut stream| {
+            // the token is read on every request so it can be rotated without a restart
+            let token = read_token(&config.token_file)?;
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line)?;
+            let response = respond(&line, &token, policy, &mut serve);
+            let response =
+                serde_json::to_string(&response).expect("serialization can't fail; qed");
+            writeln!(stream, "{}", response)?;
+            Ok(())
+        });
+        if let Err(err) = result {
+            warn!("signer connection failed: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// the signer daemon requires unix sockets
+#[cfg(not(unix))]
+pub fn listen<F>(_config: &SignerConfig, _policy: &Policy, _serve: F) -> Result<(), Error>
+where
+    F: FnMut(&SignerRequest) -> Result<SignerResponse, Error>,
+{
+    bail!("the signer daemon requires unix sockets which this platform lacks")
+}
+
+/// serves `request` with the keystore of `app` if it holds the account
+/// and otherwise with the unlocked accounts of its nodes.
+/// blocks until the node responds
+pub fn serve_with_nodes<T: Transport>(
+    app: &App<T>,
+    event_loop: &mut Core,
+    request: &SignerRequest,
+) -> Result<SignerResponse, Error> {
+    match *request {
+        SignerRequest::SignMessage { ref message } => {
+            let signature = match app.keystore {
+                Some(ref keystore) if keystore.holds(app.config.foreign.account) => {
+                    keystore.sign_message(&message.0)?
+                }
+                _ => event_loop.run(app.timer.timeout(
+                    api::sign(
+                        &app.connections.foreign,
+                        app.config.foreign.account,
+                        message.clone(),
+                    ),
+                    app.config.foreign.request_timeout,
+                ))?,
+            };
+            Ok(SignerResponse::Signature(signature))
+        }
+        SignerRequest::SendTransaction { ref transaction } => {
+            let (connection, node) = match transaction.chain {
+                Chain::Home => (&app.connections.home, &app.config.home),
+                Chain::Foreign => (&app.connections.foreign, &app.config.foreign),
+            };
+            let request = transaction.to_request();
+            let hash = match app.keystore {
+                Some(ref keystore) if keystore.holds(request.from) => {
+                    event_loop.run(keystore.send_transaction(
+                        connection,
+                        &app.timer,
+                        node.request_timeout,
+                        transaction.chain,
+                        node.chain_id,
+                        with_fees(request, transaction.dynamic_fees()),
+                    )?)?
+                }
+                _ => event_loop.run(app.timer.timeout(
+                    send_request(connection, request, transaction.dynamic_fees()),
+                    node.request_timeout,
+                ))?,
+            };
+            Ok(SignerResponse::TransactionHash(hash))
+        }
+    }
+}
+
+/// sends `request` via the node of `connection`.
+/// as EIP-1559 transaction if `dynamic_fees` are set
+fn send_request<T: Transport>(
+    connection: T,
+    request: TransactionRequest,
+    dynamic_fees: Option<DynamicFees>,
+) -> ApiCall<H256, T::Out> {
+    match dynamic_fees {
+        Some(fees) => api::send_typed_transaction(connection, &fees.typed_request(&request, None)),
+        None => api::send_transaction(connection, request),
+    }
+}
+
+/// client of a signer daemon
+#[derive(Debug, PartialEq, Clone)]
+pub struct RemoteSigner {
+    socket: PathBuf,
+    token_file: PathBuf,
+}
+
+impl RemoteSigner {
+    pub fn new(config: &SignerConfig) -> Self {
+        RemoteSigner {
+            socket: config.socket.clone(),
+            token_file: config.token_file.clone(),
+        }
+    }
+
+    /// sends `request` to the signer on a thread of its own
+    /// so the event loop isn't blocked
+    pub fn call(&self, request: SignerRequest) -> SignerCall {
+        let (sender, receiver) = oneshot::channel();
+        let signer = self.clone();
+        thread::spawn(move || {
+            // the call was abandoned if the receiver is gone
+            let _ = sender.send(signer.call_blocking(&request));
+        });
+        SignerCall { receiver }
+    }
+
+    #[cfg(unix)]
+    fn call_blocking(&self, request: &SignerRequest) -> Result<SignerResponse, Error> {
+        // the token is read on every call so it can be rotated without a restart
+        let envelope = Envelope {
+            token: read_token(&self.token_file)?,
+            request: request.clone(),
+        };
+        let mut stream = UnixStream::connect(&self.socket)
+            .chain_err(|| format!("Cannot connect to signer at {:?}", self.socket))?;
+        let request = serde_json::to_string(&envelope).expect("serialization can't fail; qed");
+        writeln!(stream, "{}", request)?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        serde_json::from_str(&line).chain_err(|| "Cannot parse response of signer")
+    }
+
+    #[cfg(not(unix))]
+    fn call_blocking(&self, _request: &SignerRequest) -> Result<SignerResponse, Error> {
+        bail!("the signer daemon requires unix sockets which this platform lacks")
+    }
+
+    /// checks that the token can be read and the signer accepts connections
+    #[cfg(unix)]
+    pub fn check_available(&self) -> Result<(), Error> {
+        read_token(&self.token_file)?;
+        UnixStream::connect(&self.socket)
+            .chain_err(|| format!("Cannot connect to signer at {:?}", self.socket))?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn check_available(&self) -> Result<(), Error> {
+        bail!("the signer daemon requires unix sockets which this platform lacks")
+    }
+}
+
+/// returns the client of the signer daemon if `config` has one
+pub fn remote_signer(config: &Config) -> Option<RemoteSigner> {
+    config.signer.as_ref().map(RemoteSigner::new)
+}
+
+/// future that resolves to the response of the signer to a request
+pub struct SignerCall {
+    receiver: oneshot::Receiver<Result<SignerResponse, Error>>,
+}
+
+impl Future for SignerCall {
+    type Item = SignerResponse;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.receiver.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(Ok(SignerResponse::Error(reason)))) => {
+                bail!("signer refused request: {}", reason)
+            }
+            Ok(Async::Ready(result)) => result.map(Async::Ready),
+            Err(_) => bail!("signer call was dropped"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct LocalNonces {
+    home: Option<U256>,
+    foreign: Option<U256>,
+}
+
+impl LocalNonces {
+    fn get_mut(&mut self, chain: Chain) -> &mut Option<U256> {
+        match chain {
+            Chain::Home => &mut self.home,
+            Chain::Foreign => &mut self.foreign,
+        }
+    }
+}
+
+/// signing with the key of `[keystore]` instead of the unlocked accounts of the nodes.
+/// transactions are signed here and sent via `eth_sendRawTransaction`.
+/// their nonce is the pending nonce of the node unless transactions this process sent
+/// before are ahead of it
+#[derive(Clone)]
+pub struct LocalSigner {
+    key: Arc<LocalKey>,
+    /// nonces of the next transactions to the chains. `None` until the first is sent
+    nonces: Arc<Mutex<LocalNonces>>,
+}
+
+impl LocalSigner {
+    pub fn new(key: LocalKey) -> Self {
+        LocalSigner {
+            key: Arc::new(key),
+            nonces: Default::default(),
+        }
+    }
+
+    /// decrypts the keystore of `config`
+    pub fn load(config: &KeystoreConfig) -> Result<Self, Error> {
+        LocalKey::load(&config.path, &config.password_file).map(LocalSigner::new)
+    }
+
+    pub fn address(&self) -> Address {
+        self.key.address()
+    }
+
+    /// returns whether the keystore holds the key of `account`
+    pub fn holds(&self, account: Address) -> bool {
+        self.key.address() == account
+    }
+
+    pub fn sign_message(&self, message: &[u8]) -> Result<H520, Error> {
+        self.key.sign_message(message)
+    }
+
+    fn nonces(&self) -> MutexGuard<LocalNonces> {
+        self.nonces.lock().expect("no code panics while holding the lock; qed")
+    }
+
+    /// returns the nonce of the next transaction to `chain`
+    /// given the `pending` nonce of the node
+    fn assign_nonce(&self, chain: Chain, pending: U256) -> U256 {
+        let mut nonces = self.nonces();
+        let next = nonces.get_mut(chain);
+        let nonce = match *next {
+            Some(next) if next > pending => next,
+            _ => pending,
+        };
+        *next = Some(nonce + 1.into());
+        nonce
+    }
+
+    /// starts over from the pending nonce of the node
+    /// after a transaction to `chain` may not have been sent
+    fn reset_nonce(&self, chain: Chain) {
+        *self.nonces().get_mut(chain) = None;
+    }
+
+    /// creates a future that signs `transaction` for `chain` with the id `chain_id`
+    /// and sends it via `transport`. a nonce set in `transaction` is kept
+    pub fn send_transaction<T: Transport>(
+        &self,
+        transport: T,
+        timer: &Timer,
+        request_timeout: Duration,
+        chain: Chain,
+        chain_id: Option<u64>,
+        transaction: TypedTransactionRequest,
+    ) -> Result<LocalTransaction<T>, Error> {
+        let chain_id = chain_id.ok_or_else(|| {
+            format!("signing with the keystore requires the chain id of {:?}", chain)
+        })?;
+        let state = match transaction.request.nonce {
+            Some(nonce) => LocalTransactionState::Sign(nonce),
+            None => LocalTransactionState::FetchNonce(timer.timeout(
+                api::pending_nonce(&transport, self.address()),
+                request_timeout,
+            )),
+        };
+        Ok(LocalTransaction {
+            signer: self.clone(),
+            transport,
+            timer: timer.clone(),
+            request_timeout,
+            chain,
+            chain_id,
+            transaction,
+            state,
+        })
+    }
+}
+
+enum LocalTransactionState<T: Transport> {
+    /// fetching the pending nonce of the account
+    FetchNonce(Timeout<ApiCall<U256, T::Out>>),
+    /// signing the transaction with the nonce
+    Sign(U256),
+    /// signed transaction is being sent
+    Send(Timeout<ApiCall<H256, T::Out>>),
+}
+
+/// future that signs a transaction with the key of the keystore and sends it.
+/// resolves to the hash of the transaction
+pub struct LocalTransaction<T: Transport> {
+    signer: LocalSigner,
+    transport: T,
+    timer: Timer,
+    request_timeout: Duration,
+    chain: Chain,
+    chain_id: u64,
+    transaction: TypedTransactionRequest,
+    state: LocalTransactionState<T>,
+}
+
+impl<T: Transport> Future for LocalTransaction<T> {
+    type Item = H256;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next_state = match self.state {
+                LocalTransactionState::FetchNonce(ref mut future) => {
+                    let pending = try_ready!(future.poll());
+                    LocalTransactionState::Sign(self.signer.assign_nonce(self.chain, pending))
+                }
+                LocalTransactionState::Sign(nonce) => {
+                    let hash =
+                        raw_transaction::signing_hash(&self.transaction, nonce, self.chain_id)?;
+                    let raw = raw_transaction::encode_signed(
+                        &self.transaction,
+                        nonce,
+                        self.chain_id,
+                        self.signer.key.sign_hash(&hash)?,
+                    )?;
+                    LocalTransactionState::Send(self.timer.timeout(
+                        api::send_raw_transaction(&self.transport, raw),
+                        self.request_timeout,
+                    ))
+                }
+                LocalTransactionState::Send(ref mut future) => {
+                    return match future.poll() {
+                        Err(err) => {
+                            self.signer.reset_nonce(self.chain);
+                            Err(err)
+                        }
+                        result => result,
+                    };
+                }
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+/// returns `request` as EIP-1559 transaction if `dynamic_fees` are set
+/// and as legacy transaction otherwise
+fn with_fees(
+    request: TransactionRequest,
+    dynamic_fees: Option<DynamicFees>,
+) -> TypedTransactionRequest {
+    match dynamic_fees {
+        Some(fees) => fees.typed_request(&request, None),
+        None => TypedTransactionRequest::legacy(request),
+    }
+}
+
+/// describes what signs messages for the signature audit log
+pub fn signing_backend(config: &Config) -> String {
+    match (&config.signer, &config.keystore) {
+        (&Some(ref signer), _) => format!("signer at {}", signer.socket.display()),
+        (&None, &Some(ref keystore)) => format!("keystore {}", keystore.path.display()),
+        (&None, &None) => format!("eth_sign via {}", config.foreign.endpoint),
+    }
+}
+
+/// future that resolves to the signature of a message
+pub enum MessageSignature<T: Transport> {
+    /// signed by the node of `foreign`
+    Node(Timeout<ApiCall<H520, T::Out>>),
+    /// signed by a signer daemon
+    Remote(Timeout<SignerCall>),
+    /// signed with the key of the keystore
+    Local(FutureResult<H520, Error>),
+}
+
+impl<T: Transport> Future for MessageSignature<T> {
+    type Item = H520;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            MessageSignature::Node(ref mut future) => Ok(Async::Ready(try_ready!(future.poll()))),
+            MessageSignature::Remote(ref mut future) => match try_ready!(future.poll()) {
+                SignerResponse::Signature(signature) => Ok(Async::Ready(signature)),
+                response => bail!("unexpected response of signer: {:?}", response),
+            },
+            MessageSignature::Local(ref mut future) => future.poll(),
+        }
+    }
+}
+
+/// creates a future that signs `message` with the authority account on `foreign`.
+/// the signer daemon or the keystore signs if one is configured
+pub fn sign_message<T: Transport>(app: &App<T>, message: Bytes) -> MessageSignature<T> {
+    sign_message_as(app, app.config.foreign.account, message)
+}
+
+/// creates a future that signs `message` with `account` on `foreign`.
+/// the signer daemon signs with its own account if one is configured.
+/// the keystore signs if it holds `account`
+fn sign_message_as<T: Transport>(
+    app: &App<T>,
+    account: Address,
+    message: Bytes,
+) -> MessageSignature<T> {
+    if let Some(signer) = remote_signer(&app.config) {
+        return MessageSignature::Remote(app.timer.timeout(
+            signer.call(SignerRequest::SignMessage { message }),
+            app.config.foreign.request_timeout,
+        ));
+    }
+    match app.keystore {
+        Some(ref keystore) if keystore.holds(account) => {
+            MessageSignature::Local(future::result(keystore.sign_message(&message.0)))
+        }
+        _ => MessageSignature::Node(app.timer.timeout(
+            api::sign(&app.connections.foreign, account, message),
+            app.config.foreign.request_timeout,
+        )),
+    }
+}
+
+/// creates a future that signs `message` with the first account of this authority on `foreign`
+/// whose signer is available: `foreign.account` followed by `foreign.fallback_accounts`.
+/// resolves to the account and the signature
+pub fn sign_with_failover<T: Transport>(app: Arc<App<T>>, message: Bytes) -> FailoverSignature<T> {
+    FailoverSignature {
+        future: sign_message(&app, message.clone()),
+        app,
+        message,
+        index: 0,
+    }
+}
+
+/// future that signs a message and fails over to the next account of the authority
+/// when signing fails
+pub struct FailoverSignature<T: Transport> {
+    app: Arc<App<T>>,
+    message: Bytes,
+    /// index of the account in `foreign.account` followed by `foreign.fallback_accounts`
+    index: usize,
+    future: MessageSignature<T>,
+}
+
+impl<T: Transport> FailoverSignature<T> {
+    fn account(&self, index: usize) -> Option<Address> {
+        let foreign = &self.app.config.foreign;
+        match index {
+            0 => Some(foreign.account),
+            index => foreign.fallback_accounts.get(index - 1).cloned(),
+        }
+    }
+}
+
+impl<T: Transport> Future for FailoverSignature<T> {
+    type Item = (Address, H520);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let err = match self.future.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(signature)) => {
+                    let account = self.account(self.index)
+                        .expect("only accounts that exist are signed with; qed");
+                    return Ok(Async::Ready((account, signature)));
+                }
+                Err(err) => err,
+            };
+            let failed = self.account(self.index)
+                .expect("only accounts that exist are signed with; qed");
+            let next = match self.account(self.index + 1) {
+                Some(next) => next,
+                None => return Err(err),
+            };
+            let message = format!(
+                "signing with {} failed: {}. failing over to {}",
+                failed, err, next
+            );
+            error!("{}", message);
+            status::record_error(message);
+            self.index += 1;
+            self.future = sign_message_as(&self.app, next, self.message.clone());
+        }
+    }
+}
+
+/// creates a future that sends `request` to `chain` via the signer daemon.
+/// as EIP-1559 transaction if `dynamic_fees` are set
+pub fn send_via_signer(
+    signer: &RemoteSigner,
+    timer: &Timer,
+    request_timeout: Duration,
+    chain: Chain,
+    request: &TransactionRequest,
+    dynamic_fees: Option<DynamicFees>,
+) -> Result<SentTransaction, Error> {
+    let transaction =
+        SignerTransaction::from_request(chain, request)?.with_dynamic_fees(dynamic_fees);
+    Ok(SentTransaction {
+        future: timer.timeout(
+            signer.call(SignerRequest::SendTransaction { transaction }),
+            request_timeout,
+        ),
+    })
+}
+
+/// future that resolves to the hash of a transaction sent by the signer daemon
+pub struct SentTransaction {
+    future: Timeout<SignerCall>,
+}
+
+impl Future for SentTransaction {
+    type Item = H256;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match try_ready!(self.future.poll()) {
+            SignerResponse::TransactionHash(hash) => Ok(Async::Ready(hash)),
+            response => bail!("unexpected response of signer: {:?}", response),
+        }
+    }
+}
+
+/// future that resolves to the hash of a sent transaction.
+/// resolves to the zero hash in a dry run because nothing is sent
+pub enum SendTransaction<T: Transport> {
+    /// sent by the node
+    Node(Timeout<ApiCall<H256, T::Out>>),
+    /// sent by a signer daemon
+    Remote(SentTransaction),
+    /// signed with the key of the keystore
+    Local(LocalTransaction<T>),
+    /// simulated instead of sent in a dry run
+    DryRun(DryRun<T>),
+}
+
+impl<T: Transport> Future for SendTransaction<T> {
+    type Item = H256;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            SendTransaction::Node(ref mut future) => Ok(Async::Ready(try_ready!(future.poll()))),
+            SendTransaction::Remote(ref mut future) => future.poll(),
+            SendTransaction::Local(ref mut future) => future.poll(),
+            SendTransaction::DryRun(ref mut future) => {
+                try_ready!(future.poll());
+                Ok(Async::Ready(H256::zero()))
+            }
+        }
+    }
+}
+
+/// creates a future that sends `request` to `chain`.
+/// as EIP-1559 transaction if `dynamic_fees` are set.
+/// the signer daemon sends it if one is configured.
+/// the keystore signs it if it holds the sender.
+/// in a dry run it is only simulated
+pub fn send_transaction<T: Transport + Clone>(
+    app: &App<T>,
+    chain: Chain,
+    request: TransactionRequest,
+    dynamic_fees: Option<DynamicFees>,
+) -> Result<SendTransaction<T>, Error> {
+    let (connection, node) = match chain {
+        Chain::Home => (&app.connections.home, &app.config.home),
+        Chain::Foreign => (&app.connections.foreign, &app.config.foreign),
+    };
+    if dry_run::is_enabled() {
+        return Ok(SendTransaction::DryRun(dry_run::simulate(
+            connection.clone(),
+            &app.timer,
+            node.request_timeout,
+            request,
+        )));
+    }
+    if let Some(signer) = remote_signer(&app.config) {
+        return Ok(SendTransaction::Remote(send_via_signer(
+            &signer,
+            &app.timer,
+            node.request_timeout,
+            chain,
+            &request,
+            dynamic_fees,
+        )?));
+    }
+    match app.keystore {
+        Some(ref keystore) if keystore.holds(request.from) => {
+            Ok(SendTransaction::Local(keystore.send_transaction(
+                connection.clone(),
+                &app.timer,
+                node.request_timeout,
+                chain,
+                node.chain_id,
+                with_fees(request, dynamic_fees),
+            )?))
+        }
+        _ => Ok(SendTransaction::Node(app.timer.timeout(
+            send_request(connection, request, dynamic_fees),
+            node.request_timeout,
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::{Bytes, TransactionRequest, U256};
+    use contracts::foreign::ForeignBridge;
+    use error::Error;
+    use refund::refund_message;
+    use transaction::DynamicFees;
+    use keystore::LocalKey;
+    use message_to_mainnet::ChainIds;
+    use super::{constant_time_eq, respond, Chain, LocalSigner, Policy, SignerRequest,
+                SignerResponse, SignerTransaction};
+
+    fn policy() -> Policy {
+        Policy {
+            home_contract: 1.into(),
+            foreign_contract: 2.into(),
+            home_account: 3.into(),
+            foreign_account: 4.into(),
+            chain_ids: None,
+        }
+    }
+
+    fn deposit(from: u64, to: u64) -> SignerRequest {
+        let data = ForeignBridge::default().functions().deposit().input(
+            [5u8; 20],
+            100u64,
+            [6u8; 32],
+        );
+        SignerRequest::SendTransaction {
+            transaction: SignerTransaction {
+                chain: Chain::Foreign,
+                from: from.into(),
+                to: to.into(),
+                gas: 0.into(),
+                gas_price: 0.into(),
+                data: data.into(),
+                max_fee_per_gas: None,
+                max_priority_fee_per_gas: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_policy_check_message() {
+        let policy = policy();
+        let message = |home_contract: u64, chain_ids: Option<ChainIds>| {
+            SignerRequest::SignMessage {
+                message: Bytes(
+                    refund_message(
+                        5.into(),
+                        100.into(),
+                        6.into(),
+                        0.into(),
+                        home_contract.into(),
+                        chain_ids,
+                    ).to_bytes(),
+                ),
+            }
+        };
+        let chain_ids = ChainIds {
+            home: 1,
+            foreign: 42,
+        };
+        assert_eq!(None, policy.check(&message(1, None)));
+        assert!(policy.check(&message(7, None)).is_some());
+        // this policy signs no messages with chain ids
+        assert!(policy.check(&message(1, Some(chain_ids))).is_some());
+        assert!(
+            policy
+                .check(&SignerRequest::SignMessage {
+                    message: Bytes(vec![0; 20]),
+                })
+                .is_some()
+        );
+
+        let policy = Policy {
+            chain_ids: Some(chain_ids),
+            ..policy
+        };
+        assert_eq!(None, policy.check(&message(1, Some(chain_ids))));
+        // meant for other chains
+        let other_foreign = ChainIds {
+            foreign: 77,
+            ..chain_ids
+        };
+        assert!(policy.check(&message(1, Some(other_foreign))).is_some());
+        // legacy messages are accepted by any deployment and would bypass the chain ids
+        assert!(policy.check(&message(1, None)).is_some());
+    }
+
+    #[test]
+    fn test_policy_check_transaction() {
+        let policy = policy();
+        assert_eq!(None, policy.check(&deposit(4, 2)));
+        // from another account
+        assert!(policy.check(&deposit(3, 2)).is_some());
+        // to another contract
+        assert!(policy.check(&deposit(4, 1)).is_some());
+
+        let mut transfer = deposit(4, 2);
+        if let SignerRequest::SendTransaction { ref mut transaction } = transfer {
+            transaction.data = Bytes(vec![0xa9, 0x05, 0x9c, 0xbb]);
+        }
+        assert!(policy.check(&transfer).is_some());
+
+        let mut home = deposit(3, 1);
+        if let SignerRequest::SendTransaction { ref mut transaction } = home {
+            transaction.chain = Chain::Home;
+        }
+        // `deposit` is not served on home
+        assert!(policy.check(&home).is_some());
+    }
+
+    #[test]
+    fn test_from_request_rejects_value() {
+        let request = TransactionRequest {
+            from: 4.into(),
+            to: Some(2.into()),
+            gas: Some(0.into()),
+            gas_price: Some(0.into()),
+            value: Some(1.into()),
+            data: Some(Bytes(vec![])),
+            nonce: None,
+            condition: None,
+        };
+        assert!(SignerTransaction::from_request(Chain::Foreign, &request).is_err());
+    }
+
+    #[test]
+    fn test_with_dynamic_fees() {
+        let request = TransactionRequest {
+            from: 4.into(),
+            to: Some(2.into()),
+            gas: Some(0.into()),
+            gas_price: Some(9.into()),
+            value: None,
+            data: Some(Bytes(vec![])),
+            nonce: None,
+            condition: None,
+        };
+        let transaction = SignerTransaction::from_request(Chain::Foreign, &request).unwrap();
+        assert_eq!(None, transaction.dynamic_fees());
+        let serialized = ::serde_json::to_string(&transaction).unwrap();
+        assert!(!serialized.contains("max_fee_per_gas"));
+
+        let transaction = transaction.with_dynamic_fees(Some(DynamicFees {
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: 2.into(),
+        }));
+        assert_eq!(
+            Some(DynamicFees {
+                max_fee_per_gas: Some(9.into()),
+                max_priority_fee_per_gas: 2.into(),
+            }),
+            transaction.dynamic_fees()
+        );
+        let serialized = ::serde_json::to_string(&transaction).unwrap();
+        assert_eq!(transaction, ::serde_json::from_str(&serialized).unwrap());
+    }
+
+    #[test]
+    fn test_respond() {
+        let policy = policy();
+        let mut served = 0;
+        {
+            let mut serve = |_: &SignerRequest| -> Result<SignerResponse, Error> {
+                served += 1;
+                Ok(SignerResponse::TransactionHash(7.into()))
+            };
+            let line = |token: &str, request: &SignerRequest| {
+                format!(
+                    r#"{{"token":"{}","request":{}}}"#,
+                    token,
+                    ::serde_json::to_string(request).unwrap()
+                )
+            };
+
+            assert_eq!(
+                SignerResponse::TransactionHash(7.into()),
+                respond(&line("secret", &deposit(4, 2)), "secret", &policy, &mut serve)
+            );
+            assert_eq!(
+                SignerResponse::Error("invalid token".into()),
+                respond(&line("guess", &deposit(4, 2)), "secret", &policy, &mut serve)
+            );
+            match respond(&line("secret", &deposit(3, 2)), "secret", &policy, &mut serve) {
+                SignerResponse::Error(_) => {}
+                other => panic!("expected refusal but got {:?}", other),
+            }
+            match respond("{", "secret", &policy, &mut serve) {
+                SignerResponse::Error(_) => {}
+                other => panic!("expected error but got {:?}", other),
+            }
+        }
+        assert_eq!(1, served);
+    }
+
+    #[test]
+    fn test_local_nonces() {
+        let mut secret = [0u8; 32];
+        secret[31] = 1;
+        let signer = LocalSigner::new(LocalKey::from_secret(&secret).unwrap());
+        assert!(signer.holds("7e5f4552091a69125d5dfcb7b8c2659029395bdf".into()));
+        assert_eq!(U256::from(5), signer.assign_nonce(Chain::Home, 5.into()));
+        // the node doesn't know the transaction with nonce 5 yet
+        assert_eq!(U256::from(6), signer.assign_nonce(Chain::Home, 5.into()));
+        assert_eq!(U256::from(9), signer.assign_nonce(Chain::Home, 9.into()));
+        assert_eq!(U256::from(0), signer.assign_nonce(Chain::Foreign, 0.into()));
+        signer.reset_nonce(Chain::Home);
+        assert_eq!(U256::from(7), signer.assign_nonce(Chain::Home, 7.into()));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secre"));
+    }
+}
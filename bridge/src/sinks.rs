@@ -0,0 +1,36 @@
+/// fan-out of the relayed transfers and the lifecycle events of transfers to everything
+/// that records them: the relay history, sqlite, the event queue and the subscribers
+/// of the http api, postgres, the status and otlp.
+/// the relay streams record their completed relays and signatures here once.
+
+use web3::Transport;
+use app::App;
+use error::Error;
+use event_queue::{self, TransferEvent};
+use history::{self, Transfer};
+use otlp;
+use postgres_sink;
+use status;
+
+/// records the relayed `transfers` and the lifecycle `events` in every configured sink.
+/// fails if the relay history or sqlite can't be written
+pub fn record<T: Transport>(
+    app: &App<T>,
+    transfers: &[Transfer],
+    events: &[TransferEvent],
+) -> Result<(), Error> {
+    if let Some(ref path) = app.config.relay_history {
+        history::record(path, transfers)?;
+    }
+    if let Some(ref sqlite) = app.sqlite {
+        sqlite.record_transfers(transfers)?;
+        sqlite.record_events(events)?;
+    }
+    event_queue::publish(events);
+    event_queue::notify(&app.events, events);
+    postgres_sink::transfers(transfers);
+    status::record_relays(transfers);
+    postgres_sink::events(events);
+    otlp::transfers(transfers);
+    Ok(())
+}
@@ -0,0 +1,390 @@
+/// database of the bridge in sqlite.
+/// with `[sqlite]` configured the checkpoints of the log streams are saved in sqlite
+/// instead of the database file, next to a record of every processed transfer:
+/// the relays of deposits and withdraws with the transactions on both chains
+/// and the lifecycle events of the transfers.
+/// on the first start the checkpoints are imported from the database file,
+/// which is left untouched and no longer written.
+/// the schema is created and upgraded by the migrations in `MIGRATIONS` on startup.
+/// compiled in via the cargo feature `sqlite`.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use database::Database;
+use error::{Error, ResultExt};
+use event_queue::TransferEvent;
+use history::Transfer;
+use status;
+
+/// schema migrations by version. applied in order, each in a transaction of its own.
+/// never change a released migration. append a new one instead
+pub const MIGRATIONS: &[(i64, &str)] = &[
+    (
+        1,
+        "CREATE TABLE checkpoints (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            database TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        CREATE TABLE transfers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            direction TEXT NOT NULL,
+            status TEXT NOT NULL,
+            block_number INTEGER NOT NULL,
+            block_timestamp INTEGER,
+            transaction_hash TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            value TEXT NOT NULL,
+            relay_transaction_hash TEXT,
+            relayed_at INTEGER,
+            fee TEXT
+        );
+        CREATE INDEX transfers_transaction_hash ON transfers (transaction_hash);
+        CREATE INDEX transfers_block_number ON transfers (direction, block_number);
+        CREATE TABLE transfer_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            stage TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            transaction_hash TEXT NOT NULL,
+            recipient TEXT NOT NULL,
+            value TEXT NOT NULL,
+            authority_transaction_hash TEXT,
+            timestamp INTEGER NOT NULL
+        );
+        CREATE INDEX transfer_events_transaction_hash ON transfer_events (transaction_hash);",
+    ),
+];
+
+/// returns the migrations that still have to be applied to a schema at `version`
+fn pending_migrations(version: i64) -> &'static [(i64, &'static str)] {
+    let applied = MIGRATIONS
+        .iter()
+        .take_while(|&&(migration, _)| migration <= version)
+        .count();
+    &MIGRATIONS[applied..]
+}
+
+/// handle of the sqlite database. clones share the connection
+#[derive(Clone)]
+pub struct SqliteDatabase {
+    connection: Arc<Mutex<backend::Connection>>,
+}
+
+impl SqliteDatabase {
+    /// opens or creates the database at `path` and applies the pending migrations
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut connection = backend::open(path.as_ref())
+            .chain_err(|| format!("Cannot open sqlite database {:?}", path.as_ref()))?;
+        backend::migrate(&mut connection)?;
+        Ok(SqliteDatabase {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<backend::Connection> {
+        self.connection
+            .lock()
+            .expect("no code panics while holding the lock; qed")
+    }
+
+    /// returns the saved checkpoints.
+    /// imports them from the database file at `toml_path` if none are saved yet
+    pub fn load_or_import<P: AsRef<Path>>(&self, toml_path: P) -> Result<Database, Error> {
+        if let Some(database) = backend::load(&self.connection())? {
+            return Ok(database);
+        }
+        let database = Database::load(toml_path.as_ref())?;
+        self.save(&database)?;
+        info!("imported the checkpoints of {:?} into sqlite", toml_path.as_ref());
+        Ok(database)
+    }
+
+    /// replaces the saved checkpoints with `database`
+    pub fn save(&self, database: &Database) -> Result<(), Error> {
+        backend::save(&self.connection(), database, status::now())
+            .chain_err(|| "Cannot save checkpoints to sqlite")
+    }
+
+    /// records the relays of `transfers`
+    pub fn record_transfers(&self, transfers: &[Transfer]) -> Result<(), Error> {
+        backend::insert_transfers(&mut self.connection(), transfers)
+            .chain_err(|| "Cannot record transfers in sqlite")
+    }
+
+    /// records the lifecycle `events` of transfers
+    pub fn record_events(&self, events: &[TransferEvent]) -> Result<(), Error> {
+        backend::insert_events(&mut self.connection(), events)
+            .chain_err(|| "Cannot record transfer events in sqlite")
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod backend {
+    use std::fmt;
+    use std::path::Path;
+    use rusqlite;
+    use serde::Serialize;
+    use serde_json::{self, Value};
+    use database::Database;
+    use error::{Error, ResultExt};
+    use event_queue::TransferEvent;
+    use history::Transfer;
+    use super::pending_migrations;
+
+    pub use rusqlite::Connection;
+
+    pub fn open(path: &Path) -> Result<Connection, Error> {
+        Connection::open(path).chain_err(|| "Cannot open file")
+    }
+
+    /// applies the pending migrations and records them in `schema_migrations`
+    pub fn migrate(connection: &mut Connection) -> Result<(), Error> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY)",
+            )
+            .chain_err(|| "Cannot create table schema_migrations")?;
+        let version = connection
+            .query_row(
+                "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+                &[],
+                |row| row.get::<_, i64>(0),
+            )
+            .chain_err(|| "Cannot read the version of the sqlite schema")?;
+        for &(migration, sql) in pending_migrations(version) {
+            info!("applying sqlite migration {}", migration);
+            let context = || format!("Cannot apply sqlite migration {}", migration);
+            let transaction = connection.transaction().chain_err(&context)?;
+            transaction.execute_batch(sql).chain_err(&context)?;
+            transaction
+                .execute(
+                    "INSERT INTO schema_migrations (version) VALUES (?)",
+                    &[&migration],
+                )
+                .chain_err(&context)?;
+            transaction.commit().chain_err(&context)?;
+        }
+        Ok(())
+    }
+
+    pub fn load(connection: &Connection) -> Result<Option<Database>, Error> {
+        let result = connection.query_row(
+            "SELECT database FROM checkpoints WHERE id = 1",
+            &[],
+            |row| row.get::<_, String>(0),
+        );
+        match result {
+            Ok(database) => database.parse().map(Some),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(err) => Err(err).chain_err(|| "Cannot load checkpoints from sqlite"),
+        }
+    }
+
+    pub fn save(connection: &Connection, database: &Database, now: u64) -> Result<(), Error> {
+        connection
+            .execute(
+                "INSERT OR REPLACE INTO checkpoints (id, database, updated_at) VALUES (1, ?, ?)",
+                &[&database.to_string(), &(now as i64)],
+            )
+            .chain_err(|| "Cannot replace row")?;
+        Ok(())
+    }
+
+    /// `0x` prefixed hex of a hash or an address as written by the relay history
+    fn hex<T: fmt::Debug>(value: &T) -> String {
+        format!("{:?}", value)
+    }
+
+    /// name of a variant as written by the relay history (example: `deposit`)
+    fn name<T: Serialize>(value: &T) -> String {
+        match serde_json::to_value(value).expect("serialization can't fail; qed") {
+            Value::String(name) => name,
+            _ => unreachable!("unit variants serialize to strings; qed"),
+        }
+    }
+
+    pub fn insert_transfers(
+        connection: &mut Connection,
+        transfers: &[Transfer],
+    ) -> Result<(), Error> {
+        let transaction = connection
+            .transaction()
+            .chain_err(|| "Cannot start a transaction")?;
+        for transfer in transfers {
+            transaction
+                .execute(
+                    "INSERT INTO transfers (direction, status, block_number, block_timestamp, \
+                     transaction_hash, recipient, value, relay_transaction_hash, relayed_at, fee) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    &[
+                        &name(&transfer.direction),
+                        &name(&TransferEvent::relayed(transfer).stage),
+                        &(transfer.block_number as i64),
+                        &transfer.block_timestamp.map(|timestamp| timestamp as i64),
+                        &hex(&transfer.transaction_hash),
+                        &hex(&transfer.recipient),
+                        &transfer.value.to_string(),
+                        &transfer.relay_transaction_hash.as_ref().map(hex),
+                        &transfer.relayed_at.map(|timestamp| timestamp as i64),
+                        &transfer.fee.map(|fee| fee.to_string()),
+                    ],
+                )
+                .chain_err(|| "Cannot insert row")?;
+        }
+        transaction.commit().chain_err(|| "Cannot commit transaction")?;
+        Ok(())
+    }
+
+    pub fn insert_events(
+        connection: &mut Connection,
+        events: &[TransferEvent],
+    ) -> Result<(), Error> {
+        let transaction = connection
+            .transaction()
+            .chain_err(|| "Cannot start a transaction")?;
+        for event in events {
+            transaction
+                .execute(
+                    "INSERT INTO transfer_events (stage, direction, transaction_hash, recipient, \
+                     value, authority_transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                    &[
+                        &name(&event.stage),
+                        &name(&event.direction),
+                        &hex(&event.transaction_hash),
+                        &hex(&event.recipient),
+                        &event.value.to_string(),
+                        &event.authority_transaction_hash.as_ref().map(hex),
+                        &(event.timestamp as i64),
+                    ],
+                )
+                .chain_err(|| "Cannot insert row")?;
+        }
+        transaction.commit().chain_err(|| "Cannot commit transaction")?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+mod backend {
+    use std::path::Path;
+    use database::Database;
+    use error::Error;
+    use event_queue::TransferEvent;
+    use history::Transfer;
+
+    pub struct Connection;
+
+    pub fn open(_: &Path) -> Result<Connection, Error> {
+        unreachable!("sqlite is only configured if feature `sqlite` is enabled; qed")
+    }
+
+    pub fn migrate(_: &mut Connection) -> Result<(), Error> {
+        unreachable!("sqlite is only configured if feature `sqlite` is enabled; qed")
+    }
+
+    pub fn load(_: &Connection) -> Result<Option<Database>, Error> {
+        unreachable!("sqlite is only configured if feature `sqlite` is enabled; qed")
+    }
+
+    pub fn save(_: &Connection, _: &Database, _: u64) -> Result<(), Error> {
+        unreachable!("sqlite is only configured if feature `sqlite` is enabled; qed")
+    }
+
+    pub fn insert_transfers(_: &mut Connection, _: &[Transfer]) -> Result<(), Error> {
+        unreachable!("sqlite is only configured if feature `sqlite` is enabled; qed")
+    }
+
+    pub fn insert_events(_: &mut Connection, _: &[TransferEvent]) -> Result<(), Error> {
+        unreachable!("sqlite is only configured if feature `sqlite` is enabled; qed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pending_migrations, MIGRATIONS};
+
+    #[test]
+    fn test_pending_migrations() {
+        assert_eq!(MIGRATIONS.len(), pending_migrations(0).len());
+        assert!(pending_migrations(MIGRATIONS.len() as i64).is_empty());
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, &(version, _)) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(index as i64 + 1, version);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod sqlite_tests {
+    extern crate tempdir;
+    use self::tempdir::TempDir;
+    use database::Database;
+    use history::{Transfer, TransferDirection};
+    use super::{backend, SqliteDatabase};
+
+    #[test]
+    fn test_import_and_save_checkpoints() {
+        let tempdir = TempDir::new("test_sqlite_checkpoints").unwrap();
+        let toml_path = tempdir.path().join("db.toml");
+        let database = Database {
+            home_contract_address: 1.into(),
+            foreign_contract_address: 2.into(),
+            checked_deposit_relay: 10,
+            ..Default::default()
+        };
+        database.save(::std::fs::File::create(&toml_path).unwrap()).unwrap();
+
+        let sqlite = SqliteDatabase::open(tempdir.path().join("bridge.sqlite")).unwrap();
+        assert_eq!(database, sqlite.load_or_import(&toml_path).unwrap());
+
+        let advanced = Database {
+            checked_deposit_relay: 20,
+            ..database.clone()
+        };
+        sqlite.save(&advanced).unwrap();
+        // the database file is only read on the first start
+        assert_eq!(advanced, sqlite.load_or_import(&toml_path).unwrap());
+        assert_eq!(database, Database::load(&toml_path).unwrap());
+
+        // migrations are applied once
+        let reopened = SqliteDatabase::open(tempdir.path().join("bridge.sqlite")).unwrap();
+        assert_eq!(advanced, reopened.load_or_import("/nonexistent").unwrap());
+    }
+
+    #[test]
+    fn test_record_transfers() {
+        let tempdir = TempDir::new("test_sqlite_transfers").unwrap();
+        let sqlite = SqliteDatabase::open(tempdir.path().join("bridge.sqlite")).unwrap();
+        let transfer = Transfer {
+            direction: TransferDirection::Deposit,
+            block_number: 5,
+            block_timestamp: Some(1000),
+            transaction_hash: 0x10.into(),
+            recipient: 0x20.into(),
+            value: 100.into(),
+            relay_transaction_hash: Some(0x30.into()),
+            relayed_at: Some(1010),
+            fee: Some(0.into()),
+        };
+        let skipped = Transfer {
+            relay_transaction_hash: None,
+            ..transfer.clone()
+        };
+        sqlite.record_transfers(&[transfer, skipped]).unwrap();
+
+        let connection = sqlite.connection();
+        let mut statement = connection
+            .prepare("SELECT status FROM transfers ORDER BY id")
+            .unwrap();
+        let statuses = statement
+            .query_map(&[], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(vec!["relayed".to_owned(), "skipped".to_owned()], statuses);
+        assert!(backend::load(&connection).unwrap().is_none());
+    }
+}
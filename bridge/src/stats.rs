@@ -0,0 +1,257 @@
+/// rolling aggregates of the relay history: daily volume, transfer counts,
+/// average latency between the source block and the relay and relay fees per direction.
+/// transfers count towards the day (UTC) of the block of their event.
+/// transfers recorded before block timestamps were tracked are not counted.
+
+use std::path::Path;
+use web3::types::U256;
+use error::Error;
+use history::{self, Transfer, TransferDirection};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+/// number of days aggregated if not given
+pub const DEFAULT_DAYS: u64 = 7;
+/// upper bound on the number of days
+pub const MAX_DAYS: u64 = 366;
+
+/// aggregates of the transfers of one direction
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct DirectionStatistics {
+    pub count: u64,
+    /// sum of the values. `None` on overflow
+    pub volume: Option<U256>,
+    /// sum of the relay fees paid by the recipients. `None` on overflow
+    pub fees: Option<U256>,
+    /// average seconds between the block of the event and the relay.
+    /// `None` if no relay recorded its time
+    pub average_latency: Option<u64>,
+    #[serde(skip)]
+    total_latency: u64,
+    #[serde(skip)]
+    timed_relays: u64,
+}
+
+/// `None` if either is `None` or on overflow
+fn add(total: Option<U256>, value: Option<U256>) -> Option<U256> {
+    let (sum, overflow) = total?.overflowing_add(value?);
+    if overflow {
+        None
+    } else {
+        Some(sum)
+    }
+}
+
+impl DirectionStatistics {
+    fn new() -> Self {
+        DirectionStatistics {
+            volume: Some(U256::zero()),
+            fees: Some(U256::zero()),
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, transfer: &Transfer) {
+        self.count += 1;
+        self.volume = add(self.volume, Some(transfer.value));
+        self.fees = add(self.fees, transfer.fee);
+        if let (Some(block), Some(relayed_at), Some(_)) = (
+            transfer.block_timestamp,
+            transfer.relayed_at,
+            transfer.relay_transaction_hash,
+        ) {
+            self.total_latency += relayed_at.saturating_sub(block);
+            self.timed_relays += 1;
+            self.average_latency = Some(self.total_latency / self.timed_relays);
+        }
+    }
+}
+
+/// aggregates of the transfers of one day
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct DailyStatistics {
+    /// `YYYY-MM-DD` in UTC
+    pub date: String,
+    pub deposits: DirectionStatistics,
+    pub withdraws: DirectionStatistics,
+}
+
+/// aggregates of the transfers of the last days
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Statistics {
+    /// oldest first. the last day is the current one
+    pub days: Vec<DailyStatistics>,
+    /// totals over all days
+    pub deposits: DirectionStatistics,
+    pub withdraws: DirectionStatistics,
+}
+
+/// returns the date (`YYYY-MM-DD`) of the `day`th day after the unix epoch
+fn date(day: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = day + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+/// returns the start of the first of the `days` days up to the day of `now`
+/// in seconds since the unix epoch
+fn first_day(now: u64, days: u64) -> u64 {
+    (now / SECONDS_PER_DAY + 1).saturating_sub(days) * SECONDS_PER_DAY
+}
+
+/// aggregates `transfers` over the `days` days up to the day of `now` (seconds since the unix epoch)
+pub fn compute(transfers: &[Transfer], now: u64, days: u64) -> Statistics {
+    let first = first_day(now, days) / SECONDS_PER_DAY;
+    let today = now / SECONDS_PER_DAY;
+    let mut statistics = Statistics {
+        days: (first..today + 1)
+            .map(|day| DailyStatistics {
+                date: date(day),
+                deposits: DirectionStatistics::new(),
+                withdraws: DirectionStatistics::new(),
+            })
+            .collect(),
+        deposits: DirectionStatistics::new(),
+        withdraws: DirectionStatistics::new(),
+    };
+
+    for transfer in transfers {
+        let day = match transfer.block_timestamp {
+            Some(timestamp) => timestamp / SECONDS_PER_DAY,
+            None => continue,
+        };
+        if day < first || day > today {
+            continue;
+        }
+        let daily = &mut statistics.days[(day - first) as usize];
+        let (daily, total) = match transfer.direction {
+            TransferDirection::Deposit => (&mut daily.deposits, &mut statistics.deposits),
+            TransferDirection::Withdraw => (&mut daily.withdraws, &mut statistics.withdraws),
+        };
+        daily.add(transfer);
+        total.add(transfer);
+    }
+
+    statistics
+}
+
+/// aggregates the history file at `path` over the `days` days up to the day of `now`
+pub fn load<P: AsRef<Path>>(path: P, now: u64, days: u64) -> Result<Statistics, Error> {
+    let transfers = history::since(path, first_day(now, days))?;
+    Ok(compute(&transfers, now, days))
+}
+
+fn show<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".into(), |value| value.to_string())
+}
+
+/// renders `statistics` as a table
+pub fn render(statistics: &Statistics) -> String {
+    let mut out = format!(
+        "{:<12}{:<10}{:>7}{:>28}{:>24}{:>13}\n",
+        "DATE", "DIRECTION", "COUNT", "VOLUME (WEI)", "FEES (WEI)", "LATENCY (S)"
+    );
+    let rows = statistics
+        .days
+        .iter()
+        .map(|day| (day.date.as_str(), &day.deposits, &day.withdraws))
+        .chain(Some((
+            "total",
+            &statistics.deposits,
+            &statistics.withdraws,
+        )));
+    for (date, deposits, withdraws) in rows {
+        for &(direction, stats) in &[("deposit", deposits), ("withdraw", withdraws)] {
+            out.push_str(&format!(
+                "{:<12}{:<10}{:>7}{:>28}{:>24}{:>13}\n",
+                date,
+                direction,
+                stats.count,
+                show(stats.volume),
+                show(stats.fees),
+                show(stats.average_latency)
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use history::{Transfer, TransferDirection};
+    use super::{compute, date, render};
+
+    // 2018-01-06 00:00:00 UTC
+    const SATURDAY: u64 = 1_515_196_800;
+
+    fn transfer(direction: TransferDirection, timestamp: u64, value: u64) -> Transfer {
+        Transfer {
+            direction,
+            block_number: 1,
+            block_timestamp: Some(timestamp),
+            transaction_hash: 2.into(),
+            recipient: 3.into(),
+            value: value.into(),
+            relay_transaction_hash: Some(4.into()),
+            relayed_at: Some(timestamp + 30),
+            fee: Some((value / 10).into()),
+        }
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!("1970-01-01", date(0));
+        assert_eq!("2018-01-06", date(SATURDAY / 86_400));
+        assert_eq!("2000-02-29", date(11_016));
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut untimed = transfer(TransferDirection::Deposit, SATURDAY, 500);
+        untimed.block_timestamp = None;
+        let mut reverted = transfer(TransferDirection::Withdraw, SATURDAY + 100, 200);
+        reverted.relay_transaction_hash = None;
+        let transfers = vec![
+            // too old
+            transfer(TransferDirection::Deposit, SATURDAY - 86_400, 1000),
+            transfer(TransferDirection::Deposit, SATURDAY, 100),
+            untimed,
+            transfer(TransferDirection::Withdraw, SATURDAY + 10, 50),
+            reverted,
+            transfer(TransferDirection::Deposit, SATURDAY + 86_400, 300),
+        ];
+
+        let statistics = compute(&transfers, SATURDAY + 86_400 + 60, 2);
+        assert_eq!(2, statistics.days.len());
+        assert_eq!("2018-01-06", statistics.days[0].date);
+        assert_eq!(1, statistics.days[0].deposits.count);
+        assert_eq!(2, statistics.days[0].withdraws.count);
+        assert_eq!(Some(250.into()), statistics.days[0].withdraws.volume);
+        assert_eq!(Some(25.into()), statistics.days[0].withdraws.fees);
+        assert_eq!(Some(30), statistics.days[0].withdraws.average_latency);
+        assert_eq!("2018-01-07", statistics.days[1].date);
+        assert_eq!(0, statistics.days[1].withdraws.count);
+        assert_eq!(None, statistics.days[1].withdraws.average_latency);
+
+        assert_eq!(2, statistics.deposits.count);
+        assert_eq!(Some(400.into()), statistics.deposits.volume);
+        assert_eq!(Some(40.into()), statistics.deposits.fees);
+        assert_eq!(Some(30), statistics.deposits.average_latency);
+
+        let rendered = render(&statistics);
+        assert_eq!(7, rendered.lines().count());
+        assert!(rendered.lines().last().unwrap().starts_with("total       withdraw        2"));
+    }
+}
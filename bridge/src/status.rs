@@ -0,0 +1,194 @@
+/// status of the bridge as shown by the dashboard of the http api and by `parity-bridge top`.
+/// the components of the bridge record what they observe here as they run.
+/// the http api reads it from its own thread.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+use web3::types::U256;
+use history::Transfer;
+use supervisor::prefixed;
+
+/// number of errors kept in `Status::recent_errors`
+const MAX_RECENT_ERRORS: usize = 20;
+/// number of transfers kept in `Status::recent_relays`
+const MAX_RECENT_RELAYS: usize = 20;
+
+/// error the bridge recovered from
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ErrorEntry {
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    pub message: String,
+}
+
+/// withdraws whose signatures were collected but that `HomeBridge` didn't execute yet.
+/// only known if the solvency check is enabled
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PendingWithdraws {
+    pub count: usize,
+    /// `None` on overflow
+    pub total_value: Option<U256>,
+}
+
+/// status of the bridge. fields are `None` until they were first observed
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct Status {
+    /// seconds since the unix epoch of the last update of the chain status
+    pub updated_at: Option<u64>,
+    /// latest block of `home`
+    pub home_block: Option<u64>,
+    /// latest block of `foreign`
+    pub foreign_block: Option<u64>,
+    /// blocks up to which the components of the bridge have processed events
+    pub checked_deposit_relay: Option<u64>,
+    pub checked_withdraw_confirm: Option<u64>,
+    pub checked_withdraw_relay: Option<u64>,
+    pub checked_unaccounted_transfers: Option<u64>,
+    pub checked_call_confirm: Option<u64>,
+    pub checked_call_relay: Option<u64>,
+    pub home_contract_balance: Option<U256>,
+    /// balance of `home.account` that pays for relays of withdraws
+    pub home_account_balance: Option<U256>,
+    /// balance of `foreign.account` that pays for relays of deposits and signatures
+    pub foreign_account_balance: Option<U256>,
+    pub pending_withdraws: Option<PendingWithdraws>,
+    /// nonce of the next transaction of `home.account` including pending ones
+    pub home_pending_nonce: Option<U256>,
+    /// nonce of the next transaction of `foreign.account` including pending ones
+    pub foreign_pending_nonce: Option<U256>,
+    /// relay fees in tokens `foreign.account` earned and hasn't claimed yet
+    pub unclaimed_relay_fees: Option<U256>,
+    /// transactions that are being relayed
+    pub in_flight_transactions: usize,
+    /// number of events each component is processing
+    pub queues: BTreeMap<String, usize>,
+    /// most recent first
+    pub recent_errors: VecDeque<ErrorEntry>,
+    /// transfers relayed since the start of the process, most recent first.
+    /// transfers whose simulation reverted are left out
+    pub recent_relays: VecDeque<Transfer>,
+}
+
+/// number of blocks a component lags behind the latest block
+fn lag(latest: Option<u64>, checked: Option<u64>) -> Option<u64> {
+    match (latest, checked) {
+        (Some(latest), Some(checked)) => Some(latest.saturating_sub(checked)),
+        _ => None,
+    }
+}
+
+impl Status {
+    /// blocks the deposit relay lags behind `home`
+    pub fn deposit_relay_lag(&self) -> Option<u64> {
+        lag(self.home_block, self.checked_deposit_relay)
+    }
+
+    /// blocks the withdraw confirmation lags behind `foreign`
+    pub fn withdraw_confirm_lag(&self) -> Option<u64> {
+        lag(self.foreign_block, self.checked_withdraw_confirm)
+    }
+
+    /// blocks the withdraw relay lags behind `foreign`
+    pub fn withdraw_relay_lag(&self) -> Option<u64> {
+        lag(self.foreign_block, self.checked_withdraw_relay)
+    }
+}
+
+fn registry() -> &'static Mutex<Status> {
+    static INIT: Once = ONCE_INIT;
+    static mut REGISTRY: *const Mutex<Status> = 0 as *const _;
+    unsafe {
+        INIT.call_once(|| {
+            REGISTRY = Box::into_raw(Box::new(Mutex::new(Status::default())));
+        });
+        &*REGISTRY
+    }
+}
+
+/// updates the status with `f`
+pub fn record<F: FnOnce(&mut Status)>(f: F) {
+    f(&mut registry().lock().expect("status is never poisoned; qed"));
+}
+
+/// returns the current status
+pub fn snapshot() -> Status {
+    registry()
+        .lock()
+        .expect("status is never poisoned; qed")
+        .clone()
+}
+
+/// sets the number of events `component` is processing.
+/// prefixed with the name of the bridge in a process that runs several bridges
+pub fn set_queue(component: &str, depth: usize) {
+    let component = prefixed(component);
+    record(|status| {
+        status.queues.insert(component, depth);
+    });
+}
+
+/// records an error the bridge recovered from
+pub fn record_error(message: String) {
+    let timestamp = now();
+    record(|status| {
+        status
+            .recent_errors
+            .push_front(ErrorEntry { timestamp, message });
+        status.recent_errors.truncate(MAX_RECENT_ERRORS);
+    });
+}
+
+/// records the transfers of `transfers` that were relayed
+pub fn record_relays(transfers: &[Transfer]) {
+    record(|status| {
+        for transfer in transfers {
+            if transfer.relay_transaction_hash.is_some() {
+                status.recent_relays.push_front(transfer.clone());
+            }
+        }
+        status.recent_relays.truncate(MAX_RECENT_RELAYS);
+    });
+}
+
+/// counts a transaction as in flight while it lives
+pub struct InFlight(());
+
+impl InFlight {
+    pub fn start() -> Self {
+        record(|status| status.in_flight_transactions += 1);
+        InFlight(())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        record(|status| status.in_flight_transactions -= 1);
+    }
+}
+
+/// returns the current time in seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is after the unix epoch; qed")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Status;
+
+    #[test]
+    fn test_lag() {
+        let mut status = Status::default();
+        assert_eq!(None, status.deposit_relay_lag());
+        status.home_block = Some(110);
+        status.checked_deposit_relay = Some(98);
+        assert_eq!(Some(12), status.deposit_relay_lag());
+        status.foreign_block = Some(10);
+        status.checked_withdraw_relay = Some(12);
+        assert_eq!(Some(0), status.withdraw_relay_lag());
+        assert_eq!(None, status.withdraw_confirm_lag());
+    }
+}
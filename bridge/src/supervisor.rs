@@ -0,0 +1,111 @@
+/// several bridges in one process (`parity-bridge --bridges`).
+/// every bridge of the bridges file (see `config::BridgesConfig`) has its own config,
+/// contracts, authority and database and its streams run concurrently on one event loop.
+/// while a bridge is polled its name is the current bridge of the thread:
+/// log lines are prefixed with it (see `logging`), the rpc metrics of its endpoints
+/// and the queues of its components are recorded under `<name>/<endpoint>`
+/// and `<name>/<component>` (see `prefixed`).
+/// the process runs until every bridge shut down and stops with the error
+/// of the first bridge that fails, so that whatever supervises the process restarts it.
+
+use std::cell::RefCell;
+use std::mem;
+use futures::{Future, Poll};
+use error::Error;
+
+thread_local! {
+    /// name of the bridge that is polled on this thread
+    static CURRENT: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// returns the name of the bridge that is polled on this thread.
+/// `None` if the process runs a single bridge
+pub fn current() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// prefixes `name` with the name of the current bridge
+pub fn prefixed(name: &str) -> String {
+    match current() {
+        Some(bridge) => format!("{}/{}", bridge, name),
+        None => name.to_owned(),
+    }
+}
+
+/// makes `name` the current bridge until the returned guard is dropped
+pub fn enter(name: &str) -> Entered {
+    let previous = CURRENT.with(|current| {
+        mem::replace(&mut *current.borrow_mut(), Some(name.to_owned()))
+    });
+    Entered { previous }
+}
+
+/// restores the previous current bridge when dropped
+pub struct Entered {
+    previous: Option<String>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// creates a future that polls `future`, the run of the bridge `name`, as the current bridge
+pub fn supervise<F>(name: String, future: F) -> Supervised<F> {
+    Supervised { name, future }
+}
+
+/// future that runs a bridge of the process and logs why it failed
+pub struct Supervised<F> {
+    name: String,
+    future: F,
+}
+
+impl<F: Future<Error = Error>> Future for Supervised<F> {
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _entered = enter(&self.name);
+        self.future.poll().map_err(|err| {
+            error!("bridge {} failed: {}", self.name, err);
+            err
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use futures::future;
+    use error::Error;
+    use super::{current, enter, prefixed, supervise};
+
+    #[test]
+    fn test_enter() {
+        assert_eq!(None, current());
+        assert_eq!("deposit_relay", prefixed("deposit_relay"));
+        {
+            let _outer = enter("eth-poa");
+            assert_eq!(Some("eth-poa".into()), current());
+            {
+                let _inner = enter("eth-xdai");
+                assert_eq!("eth-xdai/deposit_relay", prefixed("deposit_relay"));
+            }
+            assert_eq!(Some("eth-poa".into()), current());
+        }
+        assert_eq!(None, current());
+    }
+
+    #[test]
+    fn test_supervise() {
+        let run = supervise(
+            "eth-poa".into(),
+            future::lazy(|| future::ok::<_, Error>(current())),
+        );
+        assert_eq!(Some("eth-poa".into()), run.wait().unwrap());
+        assert_eq!(None, current());
+    }
+}
@@ -0,0 +1,249 @@
+/// connections to nodes configured with `https://` or `wss://` urls.
+/// the certificate of the node is checked against the system roots and the certificates
+/// of `tls.ca_bundle`. the bridge authenticates with the certificate of `tls.client_identity`
+/// towards proxies that require mutual tls. `tls.server_name` replaces the host of the url
+/// in SNI and in the check of the certificate of the node (see `TlsConfig`).
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use native_tls::{self, Certificate, Identity, TlsStream};
+use config::TlsConfig;
+use error::{Error, ResultExt};
+
+/// `url` of a node split into its parts
+#[derive(Debug, PartialEq, Clone)]
+pub struct NodeUrl {
+    /// whether the url has the tls variant of its scheme (`https` or `wss`)
+    pub tls: bool,
+    /// host without port. the name the certificate of the node is checked against
+    pub host: String,
+    /// host and port
+    pub address: String,
+    /// path and query
+    pub path: String,
+}
+
+/// splits `url` of scheme `scheme` or its tls variant (`scheme` followed by `s`).
+/// the port defaults to `80`, or `443` with tls
+pub fn parse_url(url: &str, scheme: &str) -> Result<NodeUrl, Error> {
+    let url = url.trim();
+    let (tls, rest) = match url.find("://") {
+        Some(index) if &url[..index] == scheme => (false, &url[index + 3..]),
+        Some(index) if url[..index].len() == scheme.len() + 1
+            && url[..index].starts_with(scheme) && url[..index].ends_with('s') =>
+        {
+            (true, &url[index + 3..])
+        }
+        Some(index) => bail!(
+            "url {} has scheme {}. only {} and {}s are supported",
+            url,
+            &url[..index],
+            scheme,
+            scheme
+        ),
+        None => bail!("url {} has no scheme", url),
+    };
+    let (address, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if address.is_empty() {
+        bail!("url {} has no host", url);
+    }
+    let (host, address) = match address.rfind(':') {
+        Some(index) => (&address[..index], address.to_owned()),
+        None => (address, format!("{}:{}", address, if tls { 443 } else { 80 })),
+    };
+    Ok(NodeUrl {
+        tls,
+        host: host.to_owned(),
+        address,
+        path: path.to_owned(),
+    })
+}
+
+/// returns the certificates of the pem file `path`
+fn read_certificates(path: &::std::path::Path) -> Result<Vec<Certificate>, Error> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = fs::read_to_string(path)
+        .chain_err(|| format!("Cannot read CA bundle {}", path.display()))?;
+    let certificates = pem.split(END)
+        .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|block| {
+            let certificate = format!("{}{}\n", block.trim(), END);
+            Certificate::from_pem(certificate.as_bytes()).chain_err(|| {
+                format!("CA bundle {} contains an invalid certificate", path.display())
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    if certificates.is_empty() {
+        bail!("CA bundle {} contains no certificate", path.display());
+    }
+    Ok(certificates)
+}
+
+/// wraps tcp connections to a node in tls as configured by `TlsConfig`
+#[derive(Clone)]
+pub struct Connector {
+    connector: native_tls::TlsConnector,
+    server_name: Option<String>,
+}
+
+impl Connector {
+    pub fn new(config: &TlsConfig) -> Result<Self, Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(ref ca_bundle) = config.ca_bundle {
+            for certificate in read_certificates(ca_bundle)? {
+                builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(ref path) = config.client_identity {
+            let der = fs::read(path)
+                .chain_err(|| format!("Cannot read client identity {}", path.display()))?;
+            let password = match config.client_identity_password_file {
+                Some(ref password_file) => fs::read_to_string(password_file)
+                    .chain_err(|| {
+                        format!("Cannot read password file {}", password_file.display())
+                    })?
+                    .trim_right_matches(|c| c == '\r' || c == '\n')
+                    .to_owned(),
+                None => String::new(),
+            };
+            let identity = Identity::from_pkcs12(&der, &password)
+                .chain_err(|| format!("Cannot decrypt client identity {}", path.display()))?;
+            builder.identity(identity);
+        }
+        let connector = builder
+            .build()
+            .chain_err(|| "Cannot set up tls towards the node")?;
+        Ok(Connector {
+            connector,
+            server_name: config.server_name.clone(),
+        })
+    }
+
+    /// completes the tls handshake over `stream` to the node at `url`
+    fn connect(&self, url: &NodeUrl, stream: TcpStream) -> Result<TlsStream<TcpStream>, Error> {
+        let server_name = self.server_name.as_ref().unwrap_or(&url.host);
+        self.connector
+            .connect(server_name, stream)
+            .map_err(|err| format!("tls handshake with {} failed: {}", url.address, err).into())
+    }
+}
+
+/// connection to a node owned by a single thread
+pub enum NodeStream {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl NodeStream {
+    /// opens a connection to the node at `url`. wrapped in tls by `tls`
+    /// if the url has the tls variant of its scheme.
+    /// reads and writes time out after `timeout`
+    pub fn connect(
+        url: &NodeUrl,
+        tls: Option<&Connector>,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let socket = url.address
+            .to_socket_addrs()
+            .chain_err(|| format!("Cannot resolve node {}", url.address))?
+            .next()
+            .ok_or_else(|| Error::from(format!("Cannot resolve node {}", url.address)))?;
+        let stream = TcpStream::connect_timeout(&socket, timeout)
+            .chain_err(|| format!("Cannot connect to node {}", url.address))?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.set_nodelay(true)?;
+        match (url.tls, tls) {
+            (false, _) => Ok(NodeStream::Plain(stream)),
+            (true, Some(tls)) => tls.connect(url, stream).map(NodeStream::Tls),
+            (true, None) => bail!("node {} requires tls", url.address),
+        }
+    }
+
+    fn tcp(&self) -> &TcpStream {
+        match *self {
+            NodeStream::Plain(ref stream) => stream,
+            NodeStream::Tls(ref stream) => stream.get_ref(),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    /// closes the connection. a thread that reads it notices
+    pub fn shutdown(&self) {
+        let _ = self.tcp().shutdown(Shutdown::Both);
+    }
+}
+
+impl Read for NodeStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            NodeStream::Plain(ref mut stream) => stream.read(buf),
+            NodeStream::Tls(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for NodeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            NodeStream::Plain(ref mut stream) => stream.write(buf),
+            NodeStream::Tls(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            NodeStream::Plain(ref mut stream) => stream.flush(),
+            NodeStream::Tls(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_url, NodeUrl};
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            NodeUrl {
+                tls: false,
+                host: "node".into(),
+                address: "node:8546".into(),
+                path: "/".into(),
+            },
+            parse_url("ws://node:8546", "ws").unwrap()
+        );
+        assert_eq!(
+            NodeUrl {
+                tls: true,
+                host: "node".into(),
+                address: "node:443".into(),
+                path: "/v3/key".into(),
+            },
+            parse_url("wss://node/v3/key", "ws").unwrap()
+        );
+        assert_eq!(
+            NodeUrl {
+                tls: true,
+                host: "rpc.example.com".into(),
+                address: "rpc.example.com:8443".into(),
+                path: "/".into(),
+            },
+            parse_url("https://rpc.example.com:8443", "http").unwrap()
+        );
+        assert_eq!("node:80", parse_url("http://node", "http").unwrap().address);
+        assert!(parse_url("https://node", "ws").is_err());
+        assert!(parse_url("wsx://node", "ws").is_err());
+        assert!(parse_url("node:8546", "ws").is_err());
+        assert!(parse_url("ws:///", "ws").is_err());
+    }
+}
@@ -0,0 +1,207 @@
+/// `parity-bridge top`: live terminal view of a running bridge for incidents over ssh.
+/// renders the status the bridge serves at `GET /status` of its http api.
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use serde_json::{self, Value};
+use web3::types::U256;
+use error::{Error, ResultExt};
+use http_client;
+use status;
+
+/// fetches `GET /status` from the http api at `address`
+pub fn fetch_status(address: SocketAddr) -> Result<Value, Error> {
+    let timeout = Duration::from_secs(5);
+    let url = format!("http://{}/status", address);
+    let body = http_client::request("GET", &url, None, timeout)
+        .chain_err(|| format!("Cannot fetch status from http api at {}", address))?;
+    serde_json::from_slice(&body).chain_err(|| "Cannot parse status")
+}
+
+/// formats a json value. `-` for `null`
+fn show(value: &Value) -> String {
+    match *value {
+        Value::Null => "-".into(),
+        Value::String(ref s) => s.clone(),
+        ref other => other.to_string(),
+    }
+}
+
+/// formats a hex encoded amount of wei in decimal
+fn wei(value: &Value) -> String {
+    let parsed = value.as_str().and_then(|hex| {
+        let hex = if hex.starts_with("0x") { &hex[2..] } else { hex };
+        U256::from_str(hex).ok()
+    });
+    match parsed {
+        Some(wei) => format!("{} wei", wei),
+        None => show(value),
+    }
+}
+
+/// formats a timestamp in seconds since the unix epoch relative to `now`
+fn ago(timestamp: &Value, now: u64) -> String {
+    match timestamp.as_u64() {
+        Some(timestamp) => format!("{}s ago", now.saturating_sub(timestamp)),
+        None => "never".into(),
+    }
+}
+
+/// renders `report` as returned by `GET /status` at `now`
+pub fn render(report: &Value, now: u64) -> String {
+    let status = &report["status"];
+    let mut out = String::new();
+
+    out.push_str(&format!(
+        "chain status updated {}\n\n",
+        ago(&status["updated_at"], now)
+    ));
+
+    out.push_str(&format!(
+        "{:<18}{:<9}{:>12}{:>12}{:>8}\n",
+        "STREAM", "CHAIN", "LATEST", "CHECKED", "LAG"
+    ));
+    for &(name, chain, latest, checked, lag) in &[
+        (
+            "deposit relay",
+            "home",
+            "home_block",
+            "checked_deposit_relay",
+            "deposit_relay_lag",
+        ),
+        (
+            "withdraw confirm",
+            "foreign",
+            "foreign_block",
+            "checked_withdraw_confirm",
+            "withdraw_confirm_lag",
+        ),
+        (
+            "withdraw relay",
+            "foreign",
+            "foreign_block",
+            "checked_withdraw_relay",
+            "withdraw_relay_lag",
+        ),
+    ] {
+        out.push_str(&format!(
+            "{:<18}{:<9}{:>12}{:>12}{:>8}\n",
+            name,
+            chain,
+            show(&status[latest]),
+            show(&status[checked]),
+            show(&report[lag])
+        ));
+    }
+
+    out.push_str(&format!(
+        "\nin-flight transactions: {}\n",
+        show(&status["in_flight_transactions"])
+    ));
+    out.push_str("queue depths:");
+    match status["queues"].as_object() {
+        Some(queues) if !queues.is_empty() => {
+            for (component, depth) in queues {
+                out.push_str(&format!(" {}={}", component, show(depth)));
+            }
+        }
+        _ => out.push_str(" -"),
+    }
+    out.push('\n');
+    let pending = &status["pending_withdraws"];
+    if pending.is_null() {
+        out.push_str("pending withdraws: - (requires `solvency_check`)\n");
+    } else {
+        out.push_str(&format!(
+            "pending withdraws: {} totalling {}\n",
+            show(&pending["count"]),
+            wei(&pending["total_value"])
+        ));
+    }
+
+    out.push_str(&format!(
+        "\nHomeBridge balance:      {}\nhome.account balance:    {}\nforeign.account balance: {}\n",
+        wei(&status["home_contract_balance"]),
+        wei(&status["home_account_balance"]),
+        wei(&status["foreign_account_balance"])
+    ));
+
+    out.push_str("\nRECENT ERRORS\n");
+    match status["recent_errors"].as_array() {
+        Some(errors) if !errors.is_empty() => {
+            for error in errors {
+                out.push_str(&format!(
+                    "{:>10}  {}\n",
+                    ago(&error["timestamp"], now),
+                    show(&error["message"])
+                ));
+            }
+        }
+        _ => out.push_str("none\n"),
+    }
+
+    out
+}
+
+/// renders the current status of the bridge whose http api listens on `address`.
+/// renders the error if the status can't be fetched
+pub fn frame(address: SocketAddr) -> String {
+    let now = status::now();
+    let body = match fetch_status(address) {
+        Ok(report) => render(&report, now),
+        Err(err) => format!("cannot fetch status: {}\n", err),
+    };
+    format!("parity-bridge top - {} (ctrl-c to quit)\n\n{}", address, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::render;
+
+    #[test]
+    fn test_render() {
+        let report = r#"{
+            "status": {
+                "updated_at": 990,
+                "home_block": 110,
+                "foreign_block": 20,
+                "checked_deposit_relay": 98,
+                "checked_withdraw_confirm": 20,
+                "checked_withdraw_relay": null,
+                "home_contract_balance": "0x3e8",
+                "home_account_balance": null,
+                "foreign_account_balance": null,
+                "pending_withdraws": null,
+                "in_flight_transactions": 2,
+                "queues": { "deposit_relay": 2, "withdraw_relay": 0 },
+                "recent_errors": [{ "timestamp": 995, "message": "deadline exceeded" }]
+            },
+            "deposit_relay_lag": 12,
+            "withdraw_confirm_lag": 0,
+            "withdraw_relay_lag": null,
+            "recent_transfers": null
+        }"#;
+        let rendered = render(&serde_json::from_str(report).unwrap(), 1000);
+        let expected = "chain status updated 10s ago
+
+STREAM            CHAIN          LATEST     CHECKED     LAG
+deposit relay     home              110          98      12
+withdraw confirm  foreign            20          20       0
+withdraw relay    foreign            20           -       -
+
+in-flight transactions: 2
+queue depths: deposit_relay=2 withdraw_relay=0
+pending withdraws: - (requires `solvency_check`)
+
+HomeBridge balance:      1000 wei
+home.account balance:    -
+foreign.account balance: -
+
+RECENT ERRORS
+    5s ago  deadline exceeded
+";
+        assert_eq!(expected, rendered);
+    }
+}
@@ -0,0 +1,1373 @@
+/// submission of relay transactions.
+/// every relay transaction is first simulated via `eth_call`
+/// against the pending block and only broadcast if the simulation succeeds.
+/// this saves the gas of transactions that are destined to fail.
+/// depending on the chain relay transactions are sent as legacy, EIP-2930 or EIP-1559
+/// transactions and can carry an EIP-2930 access list.
+/// relay transactions with configured EIP-1559 fees are always sent as EIP-1559 transactions.
+/// with a signer daemon transactions are sent via the signer without access list.
+/// with a keystore that holds the sender transactions are signed locally.
+/// relays start after a random delay so that authorities reacting to the same event
+/// don't all broadcast at the same time.
+/// a relay attempt that doesn't complete within the relay deadline of its chain
+/// or runs into a request timeout is abandoned and rescheduled.
+/// an attempt that fails for another transient reason (see `retry`) is rescheduled
+/// after an exponential backoff until `retry.max_retries` attempts in a row failed.
+/// the relay fails once `max_relay_attempts` attempts of its chain were abandoned.
+/// an abandoned attempt that may have sent the transaction is never sent again as
+/// a new transaction: with a nonce manager the next attempt keeps the nonce of the abandoned one
+/// and otherwise waits until the transactions of the sender pending by then are mined.
+/// on chains with `stuck_transactions` relay transactions get their nonces
+/// from a `NonceManager` that tracks them until they are mined.
+/// relays of deposits and withdraws are journaled once sent (see `journal`). after a restart
+/// the relay of a journaled transaction looks up its receipt before anything is sent again.
+/// in a dry run (see `dry_run`) relay transactions are only simulated and never sent.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use futures::{Async, Future, Poll};
+use tokio_timer::{Sleep, Timeout, Timer};
+use web3::{self, Transport};
+use web3::types::{Address, Bytes, H256, TransactionReceipt, TransactionRequest, U256};
+use serde_json::Value;
+use rustc_hex::FromHex;
+use rand::{self, Rng};
+use ethabi;
+use api::{self, AccessListItem, AccessListWithGasUsed, ApiCall, TypedTransactionRequest};
+use config::Node;
+use dry_run::{self, DryRun};
+use journal::{Journal, JournalEntry};
+use metrics::record_retry;
+use retry::{is_retryable, Backoff};
+use signer::{send_via_signer, Chain, LocalSigner, LocalTransaction, RemoteSigner, SentTransaction};
+use status::{record_error, InFlight};
+use error::{Error, ErrorKind};
+
+/// function selector of solidity's `Error(string)`
+/// which prefixes the revert reason in the output of a reverted call
+const REVERT_REASON_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// decodes a revert reason from the hex string `data` (optionally prefixed by other text
+/// as in parity's `Reverted 0x...`) that accompanies a `eth_call` error.
+fn decode_revert_reason(data: &str) -> Option<String> {
+    let hex = data.rsplit("0x").next()?;
+    let bytes: Vec<u8> = hex.from_hex().ok()?;
+    if bytes.len() < 4 || bytes[0..4] != REVERT_REASON_SELECTOR {
+        return None;
+    }
+    let mut tokens = ethabi::decode(&[ethabi::ParamType::String], &bytes[4..]).ok()?;
+    tokens.pop()?.to_string()
+}
+
+/// returns whether `err` is an error response of the node
+/// as opposed to a timeout or a transport error.
+pub fn is_rpc_error(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::Web3(web3::Error::Rpc(_)) => true,
+        _ => false,
+    }
+}
+
+/// returns true if `err` is a request timeout
+pub fn is_timeout(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::Timeout(_) => true,
+        _ => false,
+    }
+}
+
+/// returns `Some(reason)` if `err` signals that a simulated transaction reverted.
+/// `reason` is the decoded revert reason or the message of the rpc error if there is none.
+/// returns `None` if `err` is not a revert (timeout, transport error, ...).
+pub fn revert_reason(err: &Error) -> Option<String> {
+    let rpc_error = match *err.kind() {
+        ErrorKind::Web3(web3::Error::Rpc(ref rpc_error)) => rpc_error,
+        _ => return None,
+    };
+    let reason = rpc_error
+        .data
+        .as_ref()
+        .and_then(|data| data.as_str())
+        .and_then(decode_revert_reason);
+    Some(reason.unwrap_or_else(|| rpc_error.message.clone()))
+}
+
+/// transaction envelope used for relay transactions on a chain.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TransactionType {
+    /// legacy transaction with `gasPrice`.
+    /// sent as EIP-2930 transaction if it has an access list.
+    Legacy,
+    /// EIP-2930 transaction with `gasPrice` and access list
+    AccessList,
+    /// EIP-1559 transaction with `maxFeePerGas` and `maxPriorityFeePerGas`
+    DynamicFee,
+}
+
+/// EIP-1559 fees of a relay transaction configured in `[transactions]`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DynamicFees {
+    /// upper bound of the fee per gas. the gas price of the transaction if `None`
+    pub max_fee_per_gas: Option<U256>,
+    /// fee per gas paid to the block producer on top of the base fee
+    pub max_priority_fee_per_gas: U256,
+}
+
+impl DynamicFees {
+    /// returns `request` as EIP-1559 transaction with these fees
+    pub fn typed_request(
+        &self,
+        request: &TransactionRequest,
+        access_list: Option<Vec<AccessListItem>>,
+    ) -> TypedTransactionRequest {
+        TypedTransactionRequest {
+            request: TransactionRequest {
+                gas_price: None,
+                ..request.clone()
+            },
+            transaction_type: 2.into(),
+            access_list,
+            max_fee_per_gas: self.max_fee_per_gas.or(request.gas_price),
+            max_priority_fee_per_gas: Some(self.max_priority_fee_per_gas),
+        }
+    }
+}
+
+/// returns the typed transaction for `request`
+/// or `None` if `request` should be sent as legacy transaction.
+/// requests with `dynamic_fees` are sent as EIP-1559 transactions with these fees.
+/// otherwise EIP-1559 transactions pay exactly the configured gas price which is used
+/// as both fee cap and priority fee.
+/// `HomeBridge.withdraw` requires the effective gas price of relays to match the message.
+fn typed_request(
+    request: &TransactionRequest,
+    transaction_type: TransactionType,
+    access_list: Option<Vec<AccessListItem>>,
+    dynamic_fees: Option<&DynamicFees>,
+) -> Option<TypedTransactionRequest> {
+    if let Some(fees) = dynamic_fees {
+        return Some(fees.typed_request(request, access_list));
+    }
+    match (transaction_type, access_list) {
+        (TransactionType::Legacy, None) => None,
+        (TransactionType::Legacy, Some(access_list))
+        | (TransactionType::AccessList, Some(access_list)) => Some(TypedTransactionRequest {
+            request: request.clone(),
+            transaction_type: 1.into(),
+            access_list: Some(access_list),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }),
+        (TransactionType::AccessList, None) => Some(TypedTransactionRequest {
+            request: request.clone(),
+            transaction_type: 1.into(),
+            access_list: Some(vec![]),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }),
+        (TransactionType::DynamicFee, access_list) => Some(TypedTransactionRequest {
+            request: TransactionRequest {
+                gas_price: None,
+                ..request.clone()
+            },
+            transaction_type: 2.into(),
+            access_list,
+            max_fee_per_gas: request.gas_price,
+            max_priority_fee_per_gas: request.gas_price,
+        }),
+    }
+}
+
+/// whether and how a relay transaction gets an EIP-2930 access list.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AccessListMode {
+    /// don't attach an access list
+    Disabled,
+    /// use the configured access list
+    Static(Vec<AccessListItem>),
+    /// generate the access list via `eth_createAccessList`.
+    /// the transaction is sent without access list if that fails.
+    Generate,
+}
+
+/// relay transaction sent by this process whose nonce isn't confirmed yet
+#[derive(Debug, PartialEq, Clone)]
+pub struct PendingTransaction {
+    pub hash: H256,
+    /// the transaction as sent. `transaction.request.nonce` is set
+    pub transaction: TypedTransactionRequest,
+    /// whether the gas price must not change.
+    /// `HomeBridge.withdraw` requires the gas price of relays to match the message
+    pub fixed_gas_price: bool,
+    /// when the transaction or its latest replacement was sent
+    pub sent_at: Instant,
+    /// number of times the transaction was replaced or rebroadcast
+    pub replacements: u32,
+}
+
+impl PendingTransaction {
+    pub fn nonce(&self) -> U256 {
+        self.transaction
+            .request
+            .nonce
+            .expect("tracked transactions have a nonce; qed")
+    }
+}
+
+#[derive(Default)]
+struct NonceState {
+    /// nonce of the next transaction. `None` until the first is sent
+    /// and after a transaction may not have been sent
+    next: Option<U256>,
+    /// sent transactions by nonce
+    pending: BTreeMap<U256, PendingTransaction>,
+}
+
+/// local view of the nonces of the relay transactions `account` sends to a chain.
+/// relay transactions get their nonces from here instead of the node
+/// and are tracked until the nonce of the account in the latest block passes them
+/// so that stuck ones can be replaced. clones share the view
+#[derive(Clone)]
+pub struct NonceManager {
+    account: Address,
+    state: Arc<Mutex<NonceState>>,
+}
+
+impl NonceManager {
+    pub fn new(account: Address) -> Self {
+        NonceManager {
+            account,
+            state: Default::default(),
+        }
+    }
+
+    pub fn account(&self) -> Address {
+        self.account
+    }
+
+    fn state(&self) -> MutexGuard<NonceState> {
+        self.state
+            .lock()
+            .expect("no code panics while holding the lock; qed")
+    }
+
+    /// returns the nonce of the next transaction given the `pending` nonce of the node
+    pub fn assign(&self, pending: U256) -> U256 {
+        let mut state = self.state();
+        let nonce = match state.next {
+            Some(next) if next > pending => next,
+            _ => pending,
+        };
+        state.next = Some(nonce + 1.into());
+        nonce
+    }
+
+    /// starts over from the pending nonce of the node
+    /// after a transaction may not have been sent
+    pub fn reset(&self) {
+        self.state().next = None;
+    }
+
+    /// tracks `transaction` which was sent at `now` as `hash` until its nonce is confirmed
+    pub fn track(
+        &self,
+        hash: H256,
+        transaction: TypedTransactionRequest,
+        fixed_gas_price: bool,
+        now: Instant,
+    ) {
+        let pending = PendingTransaction {
+            hash,
+            transaction,
+            fixed_gas_price,
+            sent_at: now,
+            replacements: 0,
+        };
+        self.state().pending.insert(pending.nonce(), pending);
+    }
+
+    /// records that the tracked transaction with the nonce of `transaction`
+    /// was replaced by `transaction` which was sent at `now` as `hash`
+    pub fn replace(&self, hash: H256, transaction: TypedTransactionRequest, now: Instant) {
+        let nonce = transaction
+            .request
+            .nonce
+            .expect("replacements have a nonce; qed");
+        let mut state = self.state();
+        let (fixed_gas_price, replacements) = state
+            .pending
+            .get(&nonce)
+            .map_or((false, 0), |pending| {
+                (pending.fixed_gas_price, pending.replacements + 1)
+            });
+        state.pending.insert(
+            nonce,
+            PendingTransaction {
+                hash,
+                transaction,
+                fixed_gas_price,
+                sent_at: now,
+                replacements,
+            },
+        );
+    }
+
+    /// waits another timeout before the transaction with `nonce` is considered stuck again
+    /// after it couldn't be replaced at `now`
+    pub fn postpone(&self, nonce: U256, now: Instant) {
+        if let Some(pending) = self.state().pending.get_mut(&nonce) {
+            pending.sent_at = now;
+        }
+    }
+
+    /// forgets the transactions with nonces below `confirmed`,
+    /// the nonce of the account in the latest block. returns how many were forgotten
+    pub fn confirm(&self, confirmed: U256) -> usize {
+        let mut state = self.state();
+        let unconfirmed = state.pending.split_off(&confirmed);
+        let count = state.pending.len();
+        state.pending = unconfirmed;
+        count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().pending.is_empty()
+    }
+
+    /// returns the transactions that were sent at least `timeout` before `now`,
+    /// lowest nonce first
+    pub fn stuck(&self, timeout: Duration, now: Instant) -> Vec<PendingTransaction> {
+        self.state()
+            .pending
+            .values()
+            .filter(|pending| now.duration_since(pending.sent_at) >= timeout)
+            .cloned()
+            .collect()
+    }
+
+    /// returns the nonce the chain waits for if none of the tracked transactions holds it.
+    /// `confirmed` and `pending` are the nonces of the account in the latest block and
+    /// including the transaction pool of the node which skips transactions after a gap
+    pub fn gap(&self, confirmed: U256, pending: U256) -> Option<U256> {
+        match self.state().pending.keys().next() {
+            Some(&lowest) if lowest > confirmed && pending == confirmed => Some(confirmed),
+            _ => None,
+        }
+    }
+}
+
+/// nonce managers of the chains. `None` for chains without `stuck_transactions`
+#[derive(Clone, Default)]
+pub struct NonceManagers {
+    pub home: Option<NonceManager>,
+    pub foreign: Option<NonceManager>,
+}
+
+impl NonceManagers {
+    pub fn get(&self, chain: Chain) -> Option<NonceManager> {
+        match chain {
+            Chain::Home => self.home.clone(),
+            Chain::Foreign => self.foreign.clone(),
+        }
+    }
+}
+
+/// returns `fee` raised by `percent` (rounded up) and capped at `max`.
+/// returns `None` if that doesn't raise it
+fn bump_fee(fee: U256, percent: u64, max: Option<U256>) -> Option<U256> {
+    let increase = cmp::max((fee * percent.into() + 99.into()) / 100.into(), 1.into());
+    let (bumped, overflow) = fee.overflowing_add(increase);
+    let bumped = match max {
+        Some(max) if overflow || bumped > max => max,
+        _ if overflow => return None,
+        _ => bumped,
+    };
+    if bumped > fee {
+        Some(bumped)
+    } else {
+        None
+    }
+}
+
+/// returns `transaction` with its gas price or its EIP-1559 fees raised by `percent`
+/// and capped at `max_gas_price`. returns `None` if they can't be raised.
+/// nodes only accept a replacement that raises the fees by at least 10 percent
+pub fn bump_fees(
+    transaction: &TypedTransactionRequest,
+    percent: u64,
+    max_gas_price: Option<U256>,
+) -> Option<TypedTransactionRequest> {
+    let mut bumped = transaction.clone();
+    match (
+        transaction.request.gas_price,
+        transaction.max_fee_per_gas,
+        transaction.max_priority_fee_per_gas,
+    ) {
+        (Some(gas_price), _, _) => {
+            bumped.request.gas_price = Some(bump_fee(gas_price, percent, max_gas_price)?);
+        }
+        (None, Some(max_fee), Some(priority_fee)) => {
+            let max_fee = bump_fee(max_fee, percent, max_gas_price)?;
+            bumped.max_fee_per_gas = Some(max_fee);
+            bumped.max_priority_fee_per_gas = Some(bump_fee(priority_fee, percent, Some(max_fee))?);
+        }
+        _ => return None,
+    }
+    Some(bumped)
+}
+
+/// returns a transfer of 0 wei from the sender of `transaction` to itself
+/// with nonce `nonce` and the fees of `transaction`.
+/// it fills a nonce gap that keeps `transaction` from being mined
+pub fn gap_filler(transaction: &TypedTransactionRequest, nonce: U256) -> TypedTransactionRequest {
+    TypedTransactionRequest {
+        request: TransactionRequest {
+            from: transaction.request.from,
+            to: Some(transaction.request.from),
+            gas: Some(21_000.into()),
+            gas_price: transaction.request.gas_price,
+            value: Some(0.into()),
+            data: None,
+            nonce: Some(nonce),
+            condition: None,
+        },
+        access_list: if transaction.transaction_type.is_zero() {
+            None
+        } else {
+            Some(vec![])
+        },
+        ..transaction.clone()
+    }
+}
+
+/// state of a relay transaction
+enum RelayTransactionState<T: Transport> {
+    /// waiting for the random submission delay to pass
+    Delay(Sleep),
+    /// looking up the receipt of the transaction journaled before a restart
+    Verify {
+        hash: H256,
+        future: Timeout<ApiCall<Option<TransactionReceipt>, T::Out>>,
+    },
+    /// simulating the transaction via `eth_call` against the pending block
+    Simulate(Timeout<ApiCall<Bytes, T::Out>>),
+    /// generating the access list via `eth_createAccessList`
+    CreateAccessList(Timeout<ApiCall<AccessListWithGasUsed, T::Out>>),
+    /// fetching the pending nonce of the sender to assign the nonce of the transaction
+    FetchNonce {
+        future: Timeout<ApiCall<U256, T::Out>>,
+        access_list: Option<Vec<AccessListItem>>,
+    },
+    /// typed transaction is being sent
+    SendTyped(Timeout<ApiCall<H256, T::Out>>),
+    /// legacy transaction is being sent
+    Send(Timeout<ApiCall<H256, T::Out>>),
+    /// legacy transaction is being sent by the signer daemon
+    SendRemote(SentTransaction),
+    /// transaction signed with the key of the keystore is being sent
+    SendLocal(LocalTransaction<T>),
+    /// transaction is simulated instead of sent in a dry run
+    DryRun(DryRun<T>),
+    /// fetching the pending nonce of the sender after an attempt that may have sent
+    /// the transaction was abandoned (`pending` is `None`) and then its nonce in the latest
+    /// block until that reaches `pending`
+    Settle {
+        pending: Option<U256>,
+        future: Timeout<ApiCall<U256, T::Out>>,
+    },
+    /// waiting before the nonce of the sender in the latest block is polled again
+    SettleDelay { pending: U256, future: Sleep },
+}
+
+impl<T: Transport> RelayTransactionState<T> {
+    /// whether the transaction may have been sent in this state
+    fn may_have_sent(&self) -> bool {
+        match *self {
+            RelayTransactionState::SendTyped(_)
+            | RelayTransactionState::Send(_)
+            | RelayTransactionState::SendRemote(_)
+            | RelayTransactionState::SendLocal(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn send<T: Transport>(
+    transport: &T,
+    timer: &Timer,
+    request_timeout: Duration,
+    request: &TransactionRequest,
+) -> RelayTransactionState<T> {
+    RelayTransactionState::Send(timer.timeout(
+        api::send_transaction(transport, request.clone()),
+        request_timeout,
+    ))
+}
+
+/// sends `transaction` as typed transaction unless it is a legacy transaction
+fn send_typed<T: Transport>(
+    transport: &T,
+    timer: &Timer,
+    request_timeout: Duration,
+    transaction: &TypedTransactionRequest,
+) -> RelayTransactionState<T> {
+    if transaction.transaction_type.is_zero() {
+        return send(transport, timer, request_timeout, &transaction.request);
+    }
+    RelayTransactionState::SendTyped(timer.timeout(
+        api::send_typed_transaction(transport, transaction),
+        request_timeout,
+    ))
+}
+
+fn simulate<T: Transport>(
+    transport: &T,
+    timer: &Timer,
+    request_timeout: Duration,
+    request: &TransactionRequest,
+) -> RelayTransactionState<T> {
+    if dry_run::is_enabled() {
+        return RelayTransactionState::DryRun(dry_run::simulate(
+            transport.clone(),
+            timer,
+            request_timeout,
+            request.clone(),
+        ));
+    }
+    RelayTransactionState::Simulate(timer.timeout(
+        api::call_transaction(transport, request),
+        request_timeout,
+    ))
+}
+
+/// returns a random delay in `[0, max]` with millisecond precision
+fn submission_delay(max: Duration) -> Duration {
+    let max_millis = max.as_secs() * 1000 + u64::from(max.subsec_nanos() / 1_000_000);
+    if max_millis == 0 {
+        return Duration::from_secs(0);
+    }
+    Duration::from_millis(rand::thread_rng().gen_range(0, max_millis + 1))
+}
+
+/// creates a new `RelayTransaction` which relays `request` to the chain configured by `node`.
+/// simulating `request` starts after a random delay of up to `node.max_submission_delay`.
+pub fn relay_transaction<T: Transport>(
+    transport: T,
+    timer: Timer,
+    node: &Node,
+    request: TransactionRequest,
+    access_list: AccessListMode,
+) -> RelayTransaction<T> {
+    let delay = submission_delay(node.max_submission_delay);
+    let state = if delay == Duration::from_secs(0) {
+        simulate(&transport, &timer, node.request_timeout, &request)
+    } else {
+        RelayTransactionState::Delay(timer.sleep(delay))
+    };
+    RelayTransaction {
+        endpoint: node.endpoint.to_string(),
+        deadline: timer.sleep(delay + node.relay_deadline),
+        attempt: 1,
+        transport,
+        timer,
+        request_timeout: node.request_timeout,
+        relay_deadline: node.relay_deadline,
+        max_attempts: node.max_relay_attempts,
+        poll_interval: node.poll_interval,
+        request,
+        transaction_type: node.transaction_type.unwrap_or(TransactionType::Legacy),
+        chain_id: node.chain_id,
+        access_list,
+        dynamic_fees: None,
+        signer: None,
+        keystore: None,
+        nonces: None,
+        fixed_gas_price: false,
+        journal: None,
+        sent: None,
+        pinned: false,
+        unsettled: false,
+        revert_reason: None,
+        state,
+        backoff: Backoff::new(node.retry.clone()),
+        _in_flight: InFlight::start(),
+    }
+}
+
+/// future that simulates a transaction and sends it
+/// if the simulation didn't revert.
+/// resolves to the hash of the sent transaction or to `None` if the
+/// simulation reverted and the transaction was not sent.
+/// an attempt that exceeds `relay_deadline` or whose requests time out
+/// is abandoned and a new attempt is started.
+/// the new attempt simulates the transaction again which reverts
+/// if the abandoned attempt already got the transaction mined.
+/// if the abandoned attempt may have sent the transaction the new attempt resends it
+/// with the same nonce or, if the node assigned the nonce, first waits
+/// until the transactions of the sender pending by then are mined.
+/// an attempt that fails for a transient reason is rescheduled after a backoff.
+/// fails once `max_attempts` attempts were abandoned.
+pub struct RelayTransaction<T: Transport> {
+    transport: T,
+    /// endpoint of `transport` for metrics
+    endpoint: String,
+    timer: Timer,
+    request_timeout: Duration,
+    relay_deadline: Duration,
+    max_attempts: u32,
+    /// interval of the polls of the nonce of the sender while an abandoned attempt settles
+    poll_interval: Duration,
+    request: TransactionRequest,
+    transaction_type: TransactionType,
+    chain_id: Option<u64>,
+    access_list: AccessListMode,
+    /// EIP-1559 fees that override `transaction_type`
+    dynamic_fees: Option<DynamicFees>,
+    /// signer daemon that sends the transaction to the chain instead of `transport`
+    signer: Option<(RemoteSigner, Chain)>,
+    /// keystore that signs the transaction instead of the node if it holds the sender
+    keystore: Option<(LocalSigner, Chain)>,
+    /// assigns the nonce of the transaction and tracks it once it is sent
+    nonces: Option<NonceManager>,
+    /// whether the transaction is rebroadcast unchanged instead of replaced when stuck
+    fixed_gas_price: bool,
+    /// journals the transaction to the chain as relay of the events up to the block once sent
+    journal: Option<(Journal, Chain, u64)>,
+    /// the transaction of the current attempt as it is sent
+    sent: Option<TypedTransactionRequest>,
+    /// whether the nonce of the transaction is kept because an abandoned attempt
+    /// may have sent the transaction with it
+    pinned: bool,
+    /// whether an abandoned attempt may have sent the transaction with a nonce the node assigned.
+    /// the next attempt waits until it is mined if so
+    unsettled: bool,
+    /// revert reason of the simulation if it reverted
+    revert_reason: Option<String>,
+    state: RelayTransactionState<T>,
+    /// fires when the current attempt exceeds `relay_deadline`
+    deadline: Sleep,
+    /// number of the current attempt
+    attempt: u32,
+    /// delays before the attempts after transient failures
+    backoff: Backoff,
+    /// counts the transaction as in flight until it is relayed or dropped
+    _in_flight: InFlight,
+}
+
+impl<T: Transport + Clone> RelayTransaction<T> {
+    /// revert reason of the simulation once the transaction resolved to `None`
+    pub fn revert_reason(&self) -> Option<&str> {
+        self.revert_reason.as_ref().map(String::as_str)
+    }
+
+    /// sends the transaction to `chain` via `signer` if there is one
+    pub fn via_signer(mut self, signer: Option<RemoteSigner>, chain: Chain) -> Self {
+        self.signer = signer.map(|signer| (signer, chain));
+        self
+    }
+
+    /// signs the transaction to `chain` with `keystore` if there is one and it holds the sender
+    pub fn via_keystore(mut self, keystore: Option<LocalSigner>, chain: Chain) -> Self {
+        self.keystore = keystore.map(|keystore| (keystore, chain));
+        self
+    }
+
+    /// sends the transaction as EIP-1559 transaction with `dynamic_fees` if they are set
+    pub fn with_dynamic_fees(mut self, dynamic_fees: Option<DynamicFees>) -> Self {
+        self.dynamic_fees = dynamic_fees;
+        self
+    }
+
+    /// takes the nonce of the transaction from `nonces` if it manages the sender
+    /// and tracks the transaction there once it is sent.
+    /// not used with a signer daemon whose node assigns the nonces
+    pub fn with_nonces(mut self, nonces: Option<NonceManager>) -> Self {
+        let from = self.request.from;
+        self.nonces = nonces.and_then(|nonces| {
+            if nonces.account() == from {
+                Some(nonces)
+            } else {
+                None
+            }
+        });
+        self
+    }
+
+    /// rebroadcasts the transaction unchanged if it gets stuck
+    /// instead of replacing it with a higher gas price
+    pub fn with_fixed_gas_price(mut self) -> Self {
+        self.fixed_gas_price = true;
+        self
+    }
+
+    /// journals the transaction to `chain` as relay of the events up to `block` once it is sent.
+    /// if `journal` holds a transaction with the same recipient and payload
+    /// its receipt is looked up first and the relay is done if it was mined
+    pub fn with_journal(mut self, journal: Journal, chain: Chain, block: u64) -> Self {
+        if let (Some(to), &Some(ref data)) = (self.request.to, &self.request.data) {
+            if let Some(hash) = journal.find(chain, to, data) {
+                info!("verifying transaction {:?} journaled before the restart", hash);
+                self.state = RelayTransactionState::Verify {
+                    hash,
+                    future: self.timer.timeout(
+                        api::transaction_receipt(&self.transport, hash),
+                        self.request_timeout,
+                    ),
+                };
+            }
+        }
+        self.journal = Some((journal, chain, block));
+        self
+    }
+
+    /// sends the transaction with `access_list`. signed with the keystore if it holds the sender
+    /// and otherwise by the node. with a nonce manager the nonce is assigned first
+    fn send_typed(
+        &mut self,
+        access_list: Option<Vec<AccessListItem>>,
+    ) -> Result<RelayTransactionState<T>, Error> {
+        if self.nonces.is_some() && self.request.nonce.is_none() {
+            return Ok(RelayTransactionState::FetchNonce {
+                future: self.timer.timeout(
+                    api::pending_nonce(&self.transport, self.request.from),
+                    self.request_timeout,
+                ),
+                access_list,
+            });
+        }
+        let transaction = typed_request(
+            &self.request,
+            self.transaction_type,
+            access_list,
+            self.dynamic_fees.as_ref(),
+        ).unwrap_or_else(|| TypedTransactionRequest::legacy(self.request.clone()));
+        self.sent = Some(transaction.clone());
+        if let Some((ref keystore, chain)) = self.keystore {
+            if keystore.holds(self.request.from) {
+                return Ok(RelayTransactionState::SendLocal(keystore.send_transaction(
+                    self.transport.clone(),
+                    &self.timer,
+                    self.request_timeout,
+                    chain,
+                    self.chain_id,
+                    transaction,
+                )?));
+            }
+        }
+        Ok(send_typed(
+            &self.transport,
+            &self.timer,
+            self.request_timeout,
+            &transaction,
+        ))
+    }
+
+    /// tracks the sent transaction as `hash` if there is a nonce manager
+    fn track(&self, hash: H256) {
+        if let (&Some(ref nonces), &Some(ref sent)) = (&self.nonces, &self.sent) {
+            if sent.request.nonce.is_some() {
+                nonces.track(hash, sent.clone(), self.fixed_gas_price, Instant::now());
+            }
+        }
+    }
+
+    /// journals the sent transaction as `hash` if there is a journal
+    fn journal_sent(&self, hash: H256) {
+        if let Some((ref journal, chain, block)) = self.journal {
+            if let (Some(to), &Some(ref data)) = (self.request.to, &self.request.data) {
+                journal.record(JournalEntry {
+                    chain,
+                    block,
+                    transaction_hash: hash,
+                    to,
+                    data: data.clone(),
+                });
+            }
+        }
+    }
+
+    /// gives up the assigned nonce after the transaction may not have been sent.
+    /// the next attempt gets a nonce again
+    fn release_nonce(&mut self) {
+        if self.request.nonce.take().is_some() {
+            if let Some(ref nonces) = self.nonces {
+                nonces.reset();
+            }
+        }
+    }
+
+    /// starts an attempt. waits until an abandoned attempt settled first
+    fn start(&self) -> RelayTransactionState<T> {
+        if self.unsettled {
+            return RelayTransactionState::Settle {
+                pending: None,
+                future: self.timer.timeout(
+                    api::pending_nonce(&self.transport, self.request.from),
+                    self.request_timeout,
+                ),
+            };
+        }
+        simulate(
+            &self.transport,
+            &self.timer,
+            self.request_timeout,
+            &self.request,
+        )
+    }
+
+    /// abandons the current attempt and starts a new one after `delay`
+    fn reschedule(&mut self, delay: Duration) {
+        if self.state.may_have_sent() && !self.unsettled {
+            if self.nonces.is_some() && self.request.nonce.is_some() {
+                self.pinned = true;
+            } else {
+                self.unsettled = true;
+            }
+        }
+        if !self.pinned {
+            self.release_nonce();
+        }
+        record_retry(&self.endpoint);
+        self.state = if delay == Duration::from_secs(0) {
+            self.start()
+        } else {
+            RelayTransactionState::Delay(self.timer.sleep(delay))
+        };
+        self.deadline = self.timer.sleep(delay + self.relay_deadline);
+        self.attempt += 1;
+    }
+
+    fn poll_attempt(&mut self) -> Poll<Option<H256>, Error> {
+        loop {
+            let next_state = match self.state {
+                RelayTransactionState::Delay(ref mut future) => {
+                    try_ready!(future.poll());
+                    self.start()
+                }
+                RelayTransactionState::Settle {
+                    pending,
+                    ref mut future,
+                } => {
+                    let nonce = try_ready!(future.poll());
+                    match pending {
+                        None => RelayTransactionState::Settle {
+                            pending: Some(nonce),
+                            future: self.timer.timeout(
+                                api::confirmed_nonce(&self.transport, self.request.from),
+                                self.request_timeout,
+                            ),
+                        },
+                        Some(pending) if nonce >= pending => {
+                            info!(
+                                "transactions from {} pending when an attempt to relay transaction to {:?} was abandoned are mined. simulating the relay again",
+                                self.request.from, self.request.to
+                            );
+                            self.unsettled = false;
+                            self.start()
+                        }
+                        Some(pending) => RelayTransactionState::SettleDelay {
+                            pending,
+                            future: self.timer.sleep(self.poll_interval),
+                        },
+                    }
+                }
+                RelayTransactionState::SettleDelay {
+                    pending,
+                    ref mut future,
+                } => {
+                    try_ready!(future.poll());
+                    RelayTransactionState::Settle {
+                        pending: Some(pending),
+                        future: self.timer.timeout(
+                            api::confirmed_nonce(&self.transport, self.request.from),
+                            self.request_timeout,
+                        ),
+                    }
+                }
+                RelayTransactionState::Verify {
+                    hash,
+                    ref mut future,
+                } => match try_ready!(future.poll()) {
+                    Some(receipt) => {
+                        info!(
+                            "transaction {:?} journaled before the restart was mined in block {}",
+                            hash, receipt.block_number
+                        );
+                        return Ok(Async::Ready(Some(hash)));
+                    }
+                    None => {
+                        info!(
+                            "transaction {:?} journaled before the restart is not mined. simulating the relay again",
+                            hash
+                        );
+                        simulate(
+                            &self.transport,
+                            &self.timer,
+                            self.request_timeout,
+                            &self.request,
+                        )
+                    }
+                },
+                RelayTransactionState::Simulate(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(_)) => match (&self.signer, &self.access_list) {
+                        (&Some((ref signer, chain)), _) => {
+                            RelayTransactionState::SendRemote(send_via_signer(
+                                signer,
+                                &self.timer,
+                                self.request_timeout,
+                                chain,
+                                &self.request,
+                                self.dynamic_fees,
+                            )?)
+                        }
+                        (&None, &AccessListMode::Disabled) => self.send_typed(None)?,
+                        (&None, &AccessListMode::Static(ref access_list)) => {
+                            self.send_typed(Some(access_list.clone()))?
+                        }
+                        (&None, &AccessListMode::Generate) => {
+                            RelayTransactionState::CreateAccessList(self.timer.timeout(
+                                api::create_access_list(&self.transport, &self.request),
+                                self.request_timeout,
+                            ))
+                        }
+                    },
+                    Err(err) => match revert_reason(&err) {
+                        Some(reason) => {
+                            warn!(
+                                "simulation of transaction from {} to {:?} reverted: {}. transaction won't be sent. data: {:?}",
+                                self.request.from,
+                                self.request.to,
+                                reason,
+                                self.request.data
+                            );
+                            record_error(format!(
+                                "simulation of transaction from {} to {:?} reverted: {}",
+                                self.request.from, self.request.to, reason
+                            ));
+                            if self.pinned {
+                                self.pinned = false;
+                                self.release_nonce();
+                            }
+                            self.revert_reason = Some(reason);
+                            return Ok(Async::Ready(None));
+                        }
+                        None => return Err(err),
+                    },
+                },
+                RelayTransactionState::FetchNonce {
+                    ref mut future,
+                    ref mut access_list,
+                } => {
+                    let pending = try_ready!(future.poll());
+                    let access_list = access_list.take();
+                    let nonce = self.nonces
+                        .as_ref()
+                        .expect("nonces are fetched only with a nonce manager; qed")
+                        .assign(pending);
+                    self.request.nonce = Some(nonce);
+                    self.send_typed(access_list)?
+                }
+                RelayTransactionState::CreateAccessList(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(result)) => self.send_typed(Some(result.access_list))?,
+                    Err(err) => {
+                        warn!(
+                            "failed to create access list: {}. sending transaction without it",
+                            err
+                        );
+                        self.send_typed(None)?
+                    }
+                },
+                RelayTransactionState::SendTyped(ref mut future) => match future.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(hash)) => {
+                        self.track(hash);
+                        self.journal_sent(hash);
+                        return Ok(Async::Ready(Some(hash)));
+                    }
+                    // the node rejected the transaction so it was not sent
+                    Err(ref err) if is_rpc_error(err) => {
+                        warn!(
+                            "node rejected typed transaction: {}. falling back to legacy transaction",
+                            err
+                        );
+                        self.sent = Some(TypedTransactionRequest::legacy(self.request.clone()));
+                        send(
+                            &self.transport,
+                            &self.timer,
+                            self.request_timeout,
+                            &self.request,
+                        )
+                    }
+                    Err(err) => return Err(err),
+                },
+                RelayTransactionState::Send(ref mut future) => {
+                    let hash = try_ready!(future.poll());
+                    self.track(hash);
+                    self.journal_sent(hash);
+                    return Ok(Async::Ready(Some(hash)));
+                }
+                RelayTransactionState::SendRemote(ref mut future) => {
+                    let hash = try_ready!(future.poll());
+                    self.journal_sent(hash);
+                    return Ok(Async::Ready(Some(hash)));
+                }
+                RelayTransactionState::SendLocal(ref mut future) => {
+                    let hash = try_ready!(future.poll());
+                    self.track(hash);
+                    self.journal_sent(hash);
+                    return Ok(Async::Ready(Some(hash)));
+                }
+                RelayTransactionState::DryRun(ref mut future) => {
+                    try_ready!(future.poll());
+                    return Ok(Async::Ready(None));
+                }
+            };
+
+            self.state = next_state;
+        }
+    }
+}
+
+impl<T: Transport + Clone> Future for RelayTransaction<T> {
+    type Item = Option<H256>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let (reason, delay) = match self.poll_attempt() {
+                Ok(Async::NotReady) => match self.deadline.poll()? {
+                    Async::NotReady => return Ok(Async::NotReady),
+                    Async::Ready(()) => (
+                        format!("deadline of {:?} exceeded", self.relay_deadline),
+                        Duration::from_secs(0),
+                    ),
+                },
+                Err(ref err) if is_timeout(err) => (err.to_string(), Duration::from_secs(0)),
+                // the transaction of an abandoned attempt holds the nonce
+                Err(ref err) if self.pinned && is_rpc_error(err) => {
+                    self.pinned = false;
+                    self.unsettled = true;
+                    (err.to_string(), Duration::from_secs(0))
+                }
+                Err(err) => {
+                    let delay = if is_retryable(&err) {
+                        self.backoff.next_delay()
+                    } else {
+                        None
+                    };
+                    match delay {
+                        Some(delay) => (err.to_string(), delay),
+                        None => {
+                            self.release_nonce();
+                            return Err(err);
+                        }
+                    }
+                }
+                result => return result,
+            };
+
+            if self.attempt >= self.max_attempts {
+                self.pinned = false;
+                self.release_nonce();
+                bail!(
+                    "relay of transaction from {} to {:?} failed after {} attempts: {}",
+                    self.request.from,
+                    self.request.to,
+                    self.attempt,
+                    reason
+                );
+            }
+            warn!(
+                "attempt {} to relay transaction from {} to {:?} abandoned: {}. rescheduling in {:?}",
+                self.attempt, self.request.from, self.request.to, reason, delay
+            );
+            record_error(format!(
+                "attempt {} to relay transaction from {} to {:?} abandoned: {}",
+                self.attempt, self.request.from, self.request.to, reason
+            ));
+            self.reschedule(delay);
+        }
+    }
+}
+
+/// future that sends the replacement of a stuck transaction.
+/// resolves to the hash of the replacement
+pub enum Replacement<T: Transport> {
+    /// signed and sent by the node
+    Node(Timeout<ApiCall<H256, T::Out>>),
+    /// signed with the key of the keystore
+    Local(LocalTransaction<T>),
+}
+
+impl<T: Transport> Future for Replacement<T> {
+    type Item = H256;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            Replacement::Node(ref mut future) => future.poll(),
+            Replacement::Local(ref mut future) => future.poll(),
+        }
+    }
+}
+
+/// creates a future that sends `transaction` with the nonce it carries.
+/// signed with `keystore` if it holds the sender and otherwise by the node
+pub fn send_replacement<T: Transport>(
+    transport: T,
+    timer: &Timer,
+    request_timeout: Duration,
+    keystore: Option<(&LocalSigner, Chain)>,
+    chain_id: Option<u64>,
+    transaction: TypedTransactionRequest,
+) -> Result<Replacement<T>, Error> {
+    if let Some((keystore, chain)) = keystore {
+        if keystore.holds(transaction.request.from) {
+            return keystore
+                .send_transaction(transport, timer, request_timeout, chain, chain_id, transaction)
+                .map(Replacement::Local);
+        }
+    }
+    let future = if transaction.transaction_type.is_zero() {
+        api::send_transaction(transport, transaction.request)
+    } else {
+        api::send_typed_transaction(transport, &transaction)
+    };
+    Ok(Replacement::Node(timer.timeout(future, request_timeout)))
+}
+
+/// creates a future that detects the transaction type to use on a chain.
+/// nodes of chains with EIP-1559 enabled support `eth_feeHistory`.
+pub fn detect_transaction_type<T: Transport>(
+    transport: T,
+    timer: &Timer,
+    request_timeout: Duration,
+) -> DetectTransactionType<T> {
+    DetectTransactionType {
+        future: timer.timeout(api::fee_history(transport, 1), request_timeout),
+    }
+}
+
+/// future that resolves to the transaction type to use on a chain
+pub struct DetectTransactionType<T: Transport> {
+    future: Timeout<ApiCall<Value, T::Out>>,
+}
+
+impl<T: Transport> Future for DetectTransactionType<T> {
+    type Item = TransactionType;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.future.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(_)) => Ok(Async::Ready(TransactionType::DynamicFee)),
+            Err(ref err) if is_rpc_error(err) => Ok(Async::Ready(TransactionType::Legacy)),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hex::ToHex;
+    use ethabi;
+    use web3::types::{TransactionRequest, U256};
+    use api::AccessListItem;
+    use std::time::{Duration, Instant};
+    use api::TypedTransactionRequest;
+    use super::{bump_fees, decode_revert_reason, submission_delay, typed_request, DynamicFees,
+                NonceManager, TransactionType};
+
+    fn encoded_reason(reason: &str) -> String {
+        let mut bytes = vec![0x08, 0xc3, 0x79, 0xa0];
+        bytes.extend(ethabi::encode(&[ethabi::Token::String(reason.into())]));
+        bytes.to_hex()
+    }
+
+    #[test]
+    fn test_decode_revert_reason() {
+        let data = format!("0x{}", encoded_reason("insufficient balance"));
+        assert_eq!(
+            Some("insufficient balance".to_string()),
+            decode_revert_reason(&data)
+        );
+    }
+
+    #[test]
+    fn test_decode_revert_reason_parity_format() {
+        let data = format!("Reverted 0x{}", encoded_reason("nope"));
+        assert_eq!(Some("nope".to_string()), decode_revert_reason(&data));
+    }
+
+    #[test]
+    fn test_decode_revert_reason_without_reason() {
+        assert_eq!(None, decode_revert_reason("Reverted 0x"));
+        assert_eq!(None, decode_revert_reason("Reverted"));
+        assert_eq!(None, decode_revert_reason("0x12345678"));
+    }
+
+    fn request() -> TransactionRequest {
+        TransactionRequest {
+            from: 1.into(),
+            to: Some(2.into()),
+            gas: Some(3.into()),
+            gas_price: Some(4.into()),
+            value: None,
+            data: None,
+            nonce: None,
+            condition: None,
+        }
+    }
+
+    #[test]
+    fn test_typed_request_legacy() {
+        assert_eq!(
+            None,
+            typed_request(&request(), TransactionType::Legacy, None, None)
+        );
+
+        let access_list = vec![
+            AccessListItem {
+                address: 5.into(),
+                storage_keys: vec![6.into()],
+            },
+        ];
+        let typed = typed_request(
+            &request(),
+            TransactionType::Legacy,
+            Some(access_list.clone()),
+            None,
+        ).unwrap();
+        assert_eq!(request(), typed.request);
+        assert_eq!(1, typed.transaction_type.low_u64());
+        assert_eq!(Some(access_list), typed.access_list);
+    }
+
+    #[test]
+    fn test_typed_request_access_list() {
+        let typed = typed_request(&request(), TransactionType::AccessList, None, None).unwrap();
+        assert_eq!(request(), typed.request);
+        assert_eq!(1, typed.transaction_type.low_u64());
+        assert_eq!(Some(vec![]), typed.access_list);
+        assert_eq!(None, typed.max_fee_per_gas);
+    }
+
+    #[test]
+    fn test_typed_request_dynamic_fee() {
+        let typed = typed_request(&request(), TransactionType::DynamicFee, None, None).unwrap();
+        assert_eq!(None, typed.request.gas_price);
+        assert_eq!(request().gas, typed.request.gas);
+        assert_eq!(2, typed.transaction_type.low_u64());
+        assert_eq!(None, typed.access_list);
+        assert_eq!(Some(4.into()), typed.max_fee_per_gas);
+        assert_eq!(Some(4.into()), typed.max_priority_fee_per_gas);
+    }
+
+    #[test]
+    fn test_typed_request_configured_dynamic_fees() {
+        let fees = DynamicFees {
+            max_fee_per_gas: Some(7.into()),
+            max_priority_fee_per_gas: 2.into(),
+        };
+        for &transaction_type in &[TransactionType::Legacy, TransactionType::DynamicFee] {
+            let typed = typed_request(&request(), transaction_type, None, Some(&fees)).unwrap();
+            assert_eq!(None, typed.request.gas_price);
+            assert_eq!(2, typed.transaction_type.low_u64());
+            assert_eq!(Some(7.into()), typed.max_fee_per_gas);
+            assert_eq!(Some(2.into()), typed.max_priority_fee_per_gas);
+        }
+
+        let fees = DynamicFees {
+            max_fee_per_gas: None,
+            ..fees
+        };
+        let typed = typed_request(&request(), TransactionType::Legacy, None, Some(&fees)).unwrap();
+        assert_eq!(Some(4.into()), typed.max_fee_per_gas);
+    }
+
+    fn with_nonce(nonce: u64) -> TypedTransactionRequest {
+        TypedTransactionRequest::legacy(TransactionRequest {
+            nonce: Some(nonce.into()),
+            ..request()
+        })
+    }
+
+    #[test]
+    fn test_nonce_manager() {
+        let nonces = NonceManager::new(1.into());
+        assert_eq!(U256::from(5), nonces.assign(5.into()));
+        // the node doesn't know the transaction with nonce 5 yet
+        assert_eq!(U256::from(6), nonces.assign(5.into()));
+        nonces.reset();
+        assert_eq!(U256::from(5), nonces.assign(5.into()));
+
+        let sent_at = Instant::now();
+        nonces.track(0x15.into(), with_nonce(5), false, sent_at);
+        nonces.track(0x16.into(), with_nonce(6), true, sent_at);
+        nonces.track(0x17.into(), with_nonce(7), false, sent_at + Duration::from_secs(60));
+
+        let now = sent_at + Duration::from_secs(100);
+        let stuck = nonces.stuck(Duration::from_secs(100), now);
+        assert_eq!(2, stuck.len());
+        assert_eq!(U256::from(5), stuck[0].nonce());
+        assert!(stuck[1].fixed_gas_price);
+
+        nonces.replace(0x25.into(), with_nonce(5), now);
+        let stuck = nonces.stuck(Duration::from_secs(100), now);
+        assert_eq!(1, stuck.len());
+        assert_eq!(U256::from(6), stuck[0].nonce());
+
+        assert_eq!(None, nonces.gap(5.into(), 8.into()));
+        assert_eq!(1, nonces.confirm(6.into()));
+        assert!(!nonces.is_empty());
+        nonces.postpone(6.into(), now);
+        assert!(nonces.stuck(Duration::from_secs(100), now).is_empty());
+        assert_eq!(2, nonces.confirm(8.into()));
+        assert!(nonces.is_empty());
+    }
+
+    #[test]
+    fn test_nonce_manager_gap() {
+        let nonces = NonceManager::new(1.into());
+        nonces.track(0x17.into(), with_nonce(7), false, Instant::now());
+        // the transaction with nonce 6 wasn't sent or was lost by the node
+        assert_eq!(Some(U256::from(6)), nonces.gap(6.into(), 6.into()));
+        // the node holds a transaction with nonce 6
+        assert_eq!(None, nonces.gap(6.into(), 8.into()));
+        assert_eq!(None, nonces.gap(7.into(), 8.into()));
+    }
+
+    #[test]
+    fn test_bump_fees() {
+        let bumped = bump_fees(&with_nonce(1), 12, None).unwrap();
+        // 4 raised by 12 percent, rounded up
+        assert_eq!(Some(5.into()), bumped.request.gas_price);
+        assert_eq!(Some(1.into()), bumped.request.nonce);
+
+        let bumped = bump_fees(&with_nonce(1), 12, Some(4.into()));
+        assert_eq!(None, bumped);
+
+        let fees = DynamicFees {
+            max_fee_per_gas: Some(100.into()),
+            max_priority_fee_per_gas: 10.into(),
+        };
+        let typed = fees.typed_request(&request(), None);
+        let bumped = bump_fees(&typed, 10, Some(105.into())).unwrap();
+        assert_eq!(Some(105.into()), bumped.max_fee_per_gas);
+        assert_eq!(Some(11.into()), bumped.max_priority_fee_per_gas);
+        assert_eq!(None, bumped.request.gas_price);
+    }
+
+    #[test]
+    fn test_submission_delay() {
+        assert_eq!(Duration::from_secs(0), submission_delay(Duration::from_secs(0)));
+        assert_eq!(Duration::from_secs(0), submission_delay(Duration::from_millis(0)));
+        for _ in 0..100 {
+            assert!(submission_delay(Duration::from_millis(1500)) <= Duration::from_millis(1500));
+        }
+    }
+}
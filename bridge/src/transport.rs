@@ -0,0 +1,133 @@
+/// the transports that reach the node of a chain: its ipc socket (`ipc`),
+/// its http json-rpc endpoint (`http`) or its websocket endpoint (`ws`).
+/// `ipc` is a unix socket, or a named pipe on windows (see `named_pipe`).
+/// `https://` urls and `http` with `compression` or `compress_requests` use `HttpClient`
+/// instead of the http transport of `web3`, which supports none of them.
+/// `NodeTransport` hides which one a chain is configured with from the rest of the bridge.
+
+use std::path::Path;
+use futures::{Future, Poll};
+use jsonrpc_core as rpc;
+use tokio_core::reactor::Handle;
+use web3::{self, RequestId, Transport};
+use web3::transports::http::Http;
+#[cfg(unix)]
+use web3::transports::ipc::Ipc;
+use config::{Node, NodeEndpoint};
+use error::{Error, ErrorKind};
+use http_client::HttpClient;
+#[cfg(not(unix))]
+use named_pipe::NamedPipe;
+use tls::Connector;
+use websocket::WebSocket;
+
+/// upper bound of the number of concurrent requests to an http endpoint
+const MAX_PARALLEL_HTTP_REQUESTS: usize = 64;
+
+/// transport of `ipc`
+#[cfg(unix)]
+pub type IpcTransport = Ipc;
+/// transport of `ipc`
+#[cfg(not(unix))]
+pub type IpcTransport = NamedPipe;
+
+/// connects to the unix socket at `path`. requests run on the event loop of `handle`
+#[cfg(unix)]
+fn connect_ipc(path: &Path, _node: &Node, handle: &Handle) -> Result<IpcTransport, Error> {
+    Ok(Ipc::with_event_loop(path, handle).map_err(ErrorKind::Web3)?)
+}
+
+/// connects to the named pipe at `path`
+#[cfg(not(unix))]
+fn connect_ipc(path: &Path, node: &Node, _handle: &Handle) -> Result<IpcTransport, Error> {
+    NamedPipe::connect(path, node.request_timeout)
+}
+
+/// transport to the node of a chain
+#[derive(Debug, Clone)]
+pub enum NodeTransport {
+    Ipc(IpcTransport),
+    Http(Http),
+    HttpClient(HttpClient),
+    Ws(WebSocket),
+}
+
+impl NodeTransport {
+    /// connects to the endpoint of `node`. requests of the http transport of `web3`
+    /// run on the event loop of `handle`
+    pub fn connect(node: &Node, handle: &Handle) -> Result<Self, Error> {
+        let tls = if node.endpoint.is_tls() {
+            Some(Connector::new(&node.tls)?)
+        } else {
+            None
+        };
+        let transport = match node.endpoint {
+            NodeEndpoint::Ipc(ref path) => connect_ipc(path, node, handle).map(NodeTransport::Ipc)?,
+            NodeEndpoint::Http(ref url)
+                if tls.is_some() || node.compression || node.compress_requests =>
+            {
+                HttpClient::new(
+                    url,
+                    tls,
+                    node.compression,
+                    node.compress_requests,
+                    node.request_timeout,
+                ).map(NodeTransport::HttpClient)?
+            }
+            NodeEndpoint::Http(ref url) => {
+                Http::with_event_loop(url, handle, MAX_PARALLEL_HTTP_REQUESTS)
+                    .map(NodeTransport::Http)
+                    .map_err(ErrorKind::Web3)?
+            }
+            NodeEndpoint::Ws(ref url) => WebSocket::connect(url, tls).map(NodeTransport::Ws)?,
+        };
+        Ok(transport)
+    }
+}
+
+/// call through a `NodeTransport`
+pub enum NodeCall {
+    Ipc(<IpcTransport as Transport>::Out),
+    Http(<Http as Transport>::Out),
+    HttpClient(<HttpClient as Transport>::Out),
+    Ws(<WebSocket as Transport>::Out),
+}
+
+impl Future for NodeCall {
+    type Item = rpc::Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            NodeCall::Ipc(ref mut future) => future.poll(),
+            NodeCall::Http(ref mut future) => future.poll(),
+            NodeCall::HttpClient(ref mut future) => future.poll(),
+            NodeCall::Ws(ref mut future) => future.poll(),
+        }
+    }
+}
+
+impl Transport for NodeTransport {
+    type Out = NodeCall;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        match *self {
+            NodeTransport::Ipc(ref transport) => transport.prepare(method, params),
+            NodeTransport::Http(ref transport) => transport.prepare(method, params),
+            NodeTransport::HttpClient(ref transport) => transport.prepare(method, params),
+            NodeTransport::Ws(ref transport) => transport.prepare(method, params),
+        }
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        match *self {
+            NodeTransport::Ipc(ref transport) => NodeCall::Ipc(transport.send(id, request)),
+            NodeTransport::Http(ref transport) => NodeCall::Http(transport.send(id, request)),
+            NodeTransport::HttpClient(ref transport) => {
+                NodeCall::HttpClient(transport.send(id, request))
+            }
+            NodeTransport::Ws(ref transport) => NodeCall::Ws(transport.send(id, request)),
+        }
+    }
+}
+
@@ -1,5 +1,7 @@
 use web3::types::{Address, FilterBuilder, H256};
 use ethabi;
+use rustc_hex::ToHex;
+use tiny_keccak::keccak256;
 
 fn web3_topic(topic: ethabi::Topic<ethabi::Hash>) -> Option<Vec<H256>> {
     let t: Vec<ethabi::Hash> = topic.into();
@@ -20,3 +22,45 @@ pub fn web3_filter(filter: ethabi::TopicFilter, address: Address) -> FilterBuild
         .address(vec![address])
         .topics(t0, t1, t2, t3)
 }
+
+/// returns the EIP-55 mixed-case checksum encoding of `address` without `0x` prefix.
+/// a letter is uppercase if the corresponding nibble of the keccak hash
+/// of the lowercase hex encoding is 8 or greater.
+pub fn to_checksum(address: &Address) -> String {
+    let hex: String = address.0.to_hex();
+    let hash = keccak256(hex.as_bytes());
+    hex.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = if i % 2 == 0 {
+                hash[i / 2] >> 4
+            } else {
+                hash[i / 2] & 0x0f
+            };
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_checksum;
+
+    #[test]
+    fn test_to_checksum() {
+        // test vectors from EIP-55
+        for expected in &[
+            "5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "fB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "dbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "D1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let address = expected.to_lowercase().parse().unwrap();
+            assert_eq!(*expected, to_checksum(&address));
+        }
+    }
+}
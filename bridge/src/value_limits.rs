@@ -0,0 +1,419 @@
+/// off-chain limits on the value this authority relays per direction (see `value_limits`).
+/// transfers of more than `max_single_value` are skipped with a warning.
+/// the value relayed in a direction is accounted in a window of 24 hours
+/// that starts with the first transfer relayed after the previous window is over.
+/// a transfer that would take the total of the window above `max_daily_value`
+/// holds back the events from its block on until the window is over.
+/// both directions send their transactions to foreign so the windows follow the time of
+/// foreign (see `clock`). they are saved in the database
+/// so that they survive restarts.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use futures::{Async, Poll, Stream};
+use tokio_timer::{Interval, Timer};
+use web3::types::{Log, U256};
+use api::LogStreamItem;
+use clock;
+use config::ValueLimit;
+use control::Direction;
+use error::{Error, Result};
+use shard::{Leases, ShardedStream};
+
+/// length of the window in which `max_daily_value` is accounted
+pub const WINDOW: u64 = 24 * 60 * 60;
+
+/// value relayed within a window
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct ValueWindow {
+    /// seconds since the unix epoch at which the window started
+    pub started_at: u64,
+    pub total: U256,
+}
+
+impl ValueWindow {
+    /// seconds until the window is over at `now`
+    pub fn remaining(&self, now: u64) -> u64 {
+        (self.started_at + WINDOW).saturating_sub(now)
+    }
+}
+
+/// what happens to a transfer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Admission {
+    Relay,
+    /// the value of the transfer exceeds a limit on its own
+    Skip,
+    /// the window is exhausted. it is over in that many seconds
+    Defer(u64),
+}
+
+/// returns the admission of a transfer of `value` at `now` under `limit`
+/// and the window after relaying it
+pub fn admit(
+    limit: &ValueLimit,
+    window: Option<ValueWindow>,
+    value: U256,
+    now: u64,
+) -> (Admission, Option<ValueWindow>) {
+    let exceeds = |max: Option<U256>| max.map_or(false, |max| value > max);
+    if exceeds(limit.max_single_value) || exceeds(limit.max_daily_value) {
+        return (Admission::Skip, window);
+    }
+    let max_daily_value = match limit.max_daily_value {
+        Some(max_daily_value) => max_daily_value,
+        None => return (Admission::Relay, None),
+    };
+    let window = match window {
+        Some(window) if window.remaining(now) > 0 => window,
+        _ => ValueWindow {
+            started_at: now,
+            total: U256::zero(),
+        },
+    };
+    match window.total.checked_add(value) {
+        Some(total) if total <= max_daily_value => (
+            Admission::Relay,
+            Some(ValueWindow { total, ..window }),
+        ),
+        _ => (Admission::Defer(window.remaining(now)), Some(window)),
+    }
+}
+
+/// events of a `LogStreamItem` split by `ValueLimiter::limit`
+#[derive(Debug, PartialEq)]
+pub struct Limit {
+    /// events that can be relayed. `None` if the first block of the item is held back
+    pub released: Option<LogStreamItem>,
+    /// events held back until the window is over and the seconds until then
+    pub held: Option<(LogStreamItem, u64)>,
+}
+
+/// limits of a direction and its current window. clones share the window
+#[derive(Debug, Default, Clone)]
+pub struct ValueLimiter {
+    limit: ValueLimit,
+    window: Arc<Mutex<Option<ValueWindow>>>,
+}
+
+impl ValueLimiter {
+    pub fn new(limit: ValueLimit) -> Self {
+        ValueLimiter {
+            limit,
+            window: Default::default(),
+        }
+    }
+
+    /// whether the limiter lets every transfer through
+    pub fn is_unlimited(&self) -> bool {
+        self.limit.max_single_value.is_none() && self.limit.max_daily_value.is_none()
+    }
+
+    /// continues the window loaded from the database
+    pub fn seed(&self, window: Option<ValueWindow>) {
+        *self.window.lock().expect("value window is never poisoned; qed") = window;
+    }
+
+    /// returns the current window to save in the database
+    pub fn window(&self) -> Option<ValueWindow> {
+        *self.window.lock().expect("value window is never poisoned; qed")
+    }
+
+    /// accounts the events in `item` whose value is returned by `value` at `now`.
+    /// events this process doesn't `handle` are released without being accounted.
+    /// events of a block are either all released or all held back
+    pub fn limit(
+        &self,
+        item: LogStreamItem,
+        now: u64,
+        value: fn(&Log) -> Result<U256>,
+        handles: &Fn(&Log) -> bool,
+    ) -> Result<Limit> {
+        let mut window = self.window();
+        let LogStreamItem { from, to, mut logs } = item;
+        let mut released = Vec::with_capacity(logs.len());
+        let mut start = 0;
+        while start < logs.len() {
+            let block = block_number(&logs[start])?;
+            let end = start + logs[start..]
+                .iter()
+                .take_while(|log| log.block_number == logs[start].block_number)
+                .count();
+            let mut block_window = window;
+            let mut admitted = Vec::new();
+            let mut deferred = None;
+            for log in &logs[start..end] {
+                if !handles(log) {
+                    admitted.push(log.clone());
+                    continue;
+                }
+                let transferred = value(log)?;
+                match admit(&self.limit, block_window, transferred, now) {
+                    (Admission::Relay, next) => {
+                        block_window = next;
+                        admitted.push(log.clone());
+                    }
+                    (Admission::Skip, _) => warn!(
+                        "skipping transfer of {} in transaction {:?}. its value exceeds the limits",
+                        transferred, log.transaction_hash
+                    ),
+                    (Admission::Defer(remaining), _) => {
+                        deferred = Some(remaining);
+                        break;
+                    }
+                }
+            }
+            if let Some(remaining) = deferred {
+                self.seed(window);
+                let held = LogStreamItem {
+                    from: block,
+                    to,
+                    logs: logs.split_off(start),
+                };
+                let released = if block > from {
+                    Some(LogStreamItem {
+                        from,
+                        to: block - 1,
+                        logs: released,
+                    })
+                } else {
+                    None
+                };
+                return Ok(Limit {
+                    released,
+                    held: Some((held, remaining)),
+                });
+            }
+            window = block_window;
+            released.extend(admitted);
+            start = end;
+        }
+        self.seed(window);
+        Ok(Limit {
+            released: Some(LogStreamItem {
+                from,
+                to,
+                logs: released,
+            }),
+            held: None,
+        })
+    }
+}
+
+fn block_number(log: &Log) -> Result<u64> {
+    let block = log.block_number
+        .ok_or_else(|| "`log` must be mined and contain `block_number`")?;
+    Ok(block.low_u64())
+}
+
+/// limiters of both directions
+#[derive(Debug, Default, Clone)]
+pub struct ValueLimiters {
+    pub deposits: ValueLimiter,
+    pub withdraws: ValueLimiter,
+}
+
+/// creates a new `Limited` which accounts the events of `stream` in `limiter`
+/// and checks whether the window is over every `poll_interval`.
+/// `value` returns the value transferred by an event
+pub fn limited<S: Stream<Item = LogStreamItem>>(
+    stream: S,
+    direction: Direction,
+    limiter: ValueLimiter,
+    value: fn(&Log) -> Result<U256>,
+    leases: Leases,
+    sharded: ShardedStream,
+    timer: &Timer,
+    poll_interval: Duration,
+) -> Limited<S> {
+    Limited {
+        stream,
+        direction,
+        limiter,
+        value,
+        leases,
+        sharded,
+        interval: timer.interval(poll_interval),
+        item: None,
+        exhausted: false,
+    }
+}
+
+/// stream that drops the events of `stream` which exceed the limits on their own
+/// and holds back events while the window of the limiter is exhausted.
+/// `stream` is not polled until the held back events are released.
+pub struct Limited<S: Stream> {
+    stream: S,
+    direction: Direction,
+    limiter: ValueLimiter,
+    value: fn(&Log) -> Result<U256>,
+    /// events of partitions this process doesn't hold are not accounted
+    leases: Leases,
+    sharded: ShardedStream,
+    interval: Interval,
+    item: Option<LogStreamItem>,
+    /// whether the window was exhausted when last checked
+    exhausted: bool,
+}
+
+impl<S: Stream<Item = LogStreamItem>> Stream for Limited<S>
+where
+    Error: From<S::Error>,
+{
+    type Item = LogStreamItem;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.item.is_none() {
+                let item = try_stream!(self.stream.poll());
+                if self.limiter.is_unlimited() {
+                    return Ok(Async::Ready(Some(item)));
+                }
+                self.item = Some(item);
+            }
+
+            let item = self.item.take().expect("item was just set; qed");
+            let (leases, sharded) = (&self.leases, self.sharded);
+            let handles = |log: &Log| leases.handles(sharded, log);
+            let limit = self.limiter
+                .limit(item, clock::timestamp("foreign"), self.value, &handles)?;
+            match limit.held {
+                Some((held, remaining)) => {
+                    if !self.exhausted {
+                        warn!(
+                            "{:?} reached max_daily_value. deferring relays for {}s",
+                            self.direction, remaining
+                        );
+                    }
+                    self.exhausted = true;
+                    self.item = Some(held);
+                }
+                None => {
+                    if self.exhausted {
+                        info!("{:?} value window is over", self.direction);
+                    }
+                    self.exhausted = false;
+                }
+            }
+
+            if let Some(released) = limit.released {
+                return Ok(Async::Ready(Some(released)));
+            }
+
+            let _ = try_stream!(self.interval.poll());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use web3::types::{Log, U256};
+    use api::LogStreamItem;
+    use config::ValueLimit;
+    use error::Result;
+    use super::{admit, Admission, Limit, ValueLimiter, ValueWindow, WINDOW};
+
+    fn limit() -> ValueLimit {
+        ValueLimit {
+            max_single_value: Some(10.into()),
+            max_daily_value: Some(25.into()),
+        }
+    }
+
+    fn window(started_at: u64, total: u64) -> Option<ValueWindow> {
+        Some(ValueWindow {
+            started_at,
+            total: total.into(),
+        })
+    }
+
+    #[test]
+    fn test_admit() {
+        let limit = limit();
+        assert_eq!(
+            (Admission::Relay, window(100, 10)),
+            admit(&limit, None, 10.into(), 100)
+        );
+        assert_eq!(
+            (Admission::Skip, window(100, 10)),
+            admit(&limit, window(100, 10), 11.into(), 200)
+        );
+        assert_eq!(
+            (Admission::Relay, window(100, 25)),
+            admit(&limit, window(100, 20), 5.into(), 200)
+        );
+        assert_eq!(
+            (Admission::Defer(WINDOW - 100), window(100, 20)),
+            admit(&limit, window(100, 20), 6.into(), 200)
+        );
+        // the window is over
+        assert_eq!(
+            (Admission::Relay, window(100 + WINDOW, 6)),
+            admit(&limit, window(100, 20), 6.into(), 100 + WINDOW)
+        );
+    }
+
+    #[test]
+    fn test_admit_without_daily_limit() {
+        let limit = ValueLimit {
+            max_single_value: Some(10.into()),
+            max_daily_value: None,
+        };
+        assert_eq!(
+            (Admission::Relay, None),
+            admit(&limit, None, 10.into(), 100)
+        );
+        assert_eq!((Admission::Skip, None), admit(&limit, None, 11.into(), 100));
+    }
+
+    fn log(block: u64, value: u64) -> Log {
+        let mut data = [0u8; 32];
+        U256::from(value).to_big_endian(&mut data);
+        Log {
+            block_number: Some(block.into()),
+            data: data.to_vec().into(),
+            ..Default::default()
+        }
+    }
+
+    fn value(log: &Log) -> Result<U256> {
+        Ok(U256::from(&log.data.0[..]))
+    }
+
+    #[test]
+    fn test_limit() {
+        let limiter = ValueLimiter::new(limit());
+        let item = LogStreamItem {
+            from: 10,
+            to: 20,
+            logs: vec![log(11, 10), log(12, 20), log(13, 5), log(15, 10), log(15, 1)],
+        };
+        let limit = limiter.limit(item, 100, value, &|_| true).unwrap();
+        assert_eq!(
+            Limit {
+                released: Some(LogStreamItem {
+                    from: 10,
+                    to: 14,
+                    logs: vec![log(11, 10), log(13, 5)],
+                }),
+                held: Some((
+                    LogStreamItem {
+                        from: 15,
+                        to: 20,
+                        logs: vec![log(15, 10), log(15, 1)],
+                    },
+                    WINDOW,
+                )),
+            },
+            limit
+        );
+        // the first event of the block was not accounted
+        assert_eq!(window(100, 15), limiter.window());
+
+        let held = limit.held.unwrap().0;
+        let limit = limiter.limit(held, 100 + WINDOW, value, &|_| true).unwrap();
+        assert_eq!(None, limit.held);
+        assert_eq!(window(100 + WINDOW, 11), limiter.window());
+    }
+}
@@ -0,0 +1,392 @@
+/// websocket connections to nodes configured with `ws`.
+/// `WebSocket` is the json-rpc transport over such a connection. a thread of its own reads
+/// the responses and reconnects after the connection dropped. requests that were sent
+/// or made while it was down are sent again once it's restored, so calls only fail if
+/// the outage outlasts their `request_timeout`.
+/// the subscription to new blocks (`subscribe_new_heads`) opens a connection of its own
+/// and is opened again after it dropped (see `heads`).
+/// `wss://` urls are reached over tls (see `tls`). the websocket protocol is spoken by
+/// `tungstenite`, which doesn't support `permessage-deflate`, so messages aren't compressed.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use futures::{Async, Future, Poll};
+use futures::sync::oneshot;
+use jsonrpc_core as rpc;
+use serde_json::{self, Value};
+use tungstenite::{self, Message};
+use url::Url;
+use web3::{self, helpers, RequestId, Transport};
+use error::{Error, ResultExt};
+use tls::{self, Connector, NodeStream};
+
+/// seconds to wait for the node to accept a connection and complete the handshake
+const CONNECT_TIMEOUT: u64 = 5;
+/// seconds before a dropped connection is opened again
+const RECONNECT_DELAY: u64 = 1;
+/// interval in which the thread that reads a connection lets threads that write it
+/// take their turn (see `Connection`)
+const POLL_INTERVAL_MS: u64 = 50;
+
+/// websocket connection to a node that one thread reads while others write.
+/// a connection can't be split into halves. the reading thread holds it for at most
+/// `POLL_INTERVAL_MS` while no message arrives before writers get their turn
+#[derive(Clone)]
+pub struct Connection(Arc<Mutex<tungstenite::WebSocket<NodeStream>>>);
+
+/// opens a websocket connection to `url`. `wss://` urls are wrapped in tls by `tls`
+pub fn connect(url: &str, tls: Option<&Connector>) -> Result<Connection, Error> {
+    let node_url = tls::parse_url(url, "ws")?;
+    let request = Url::parse(url.trim()).chain_err(|| format!("Invalid websocket url {}", url))?;
+    let timeout = Duration::from_secs(CONNECT_TIMEOUT);
+    let stream = NodeStream::connect(&node_url, tls, timeout)?;
+    let (socket, _) = tungstenite::client(request, stream)
+        .map_err(|err| format!("websocket handshake with {} failed: {}", url, err))?;
+    // responses may take arbitrarily long. calls time out on their own
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL_MS)))?;
+    Ok(Connection(Arc::new(Mutex::new(socket))))
+}
+
+/// whether `err` means that no message arrived within `POLL_INTERVAL_MS`.
+/// `tungstenite` keeps what it read of a message until the next read
+fn is_poll_timeout(err: &tungstenite::Error) -> bool {
+    match *err {
+        tungstenite::Error::Io(ref err) => {
+            err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+        }
+        _ => false,
+    }
+}
+
+impl Connection {
+    fn socket(&self) -> Result<MutexGuard<tungstenite::WebSocket<NodeStream>>, Error> {
+        self.0
+            .lock()
+            .map_err(|_| "websocket connection poisoned".into())
+    }
+
+    /// reads the next text message of the node. pings are answered while reading
+    pub fn read_text(&self) -> Result<String, Error> {
+        loop {
+            let result = self.socket()?.read_message();
+            match result {
+                Ok(Message::Text(text)) => return Ok(text),
+                Ok(Message::Binary(bytes)) => {
+                    return String::from_utf8(bytes)
+                        .map_err(|_| "node sent a binary message that isn't utf-8".into())
+                }
+                Ok(Message::Close(_)) => bail!("node closed the connection"),
+                // pings are answered by `tungstenite`
+                Ok(_) => {}
+                Err(ref err) if is_poll_timeout(err) => thread::yield_now(),
+                Err(err) => bail!("cannot read message of node: {}", err),
+            }
+        }
+    }
+
+    /// sends `text` to the node
+    pub fn write_text(&self, text: &str) -> Result<(), Error> {
+        self.socket()?
+            .write_message(Message::Text(text.to_owned()))
+            .map_err(|err| format!("cannot send message to node: {}", err).into())
+    }
+
+    /// closes the connection. the reading thread notices
+    pub fn shutdown(&self) {
+        if let Ok(socket) = self.socket() {
+            socket.get_ref().shutdown();
+        }
+    }
+}
+
+/// request that waits for its response
+struct PendingCall {
+    /// the serialized request, sent again after a reconnect
+    request: String,
+    response: oneshot::Sender<Result<Value, web3::Error>>,
+}
+
+#[derive(Default)]
+struct ConnectionState {
+    /// the connection, written by the callers. `None` while it's down
+    writer: Option<Connection>,
+    /// requests that wait for their responses by id
+    pending: HashMap<RequestId, PendingCall>,
+}
+
+/// json-rpc transport over a websocket connection that reconnects after it dropped
+#[derive(Clone)]
+pub struct WebSocket {
+    url: Arc<String>,
+    tls: Option<Connector>,
+    id: Arc<AtomicUsize>,
+    state: Arc<Mutex<ConnectionState>>,
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebSocket").field("url", &self.url).finish()
+    }
+}
+
+impl WebSocket {
+    /// connects to the node at `url` (see `connect`). fails if the first connection
+    /// can't be opened. later connections are opened again until they succeed
+    pub fn connect(url: &str, tls: Option<Connector>) -> Result<Self, Error> {
+        let connection = connect(url, tls.as_ref())?;
+        let transport = WebSocket {
+            url: Arc::new(url.to_owned()),
+            tls,
+            id: Arc::new(AtomicUsize::new(1)),
+            state: Arc::new(Mutex::new(ConnectionState::default())),
+        };
+        transport.state().writer = Some(connection.clone());
+        let reading = transport.clone();
+        thread::spawn(move || reading.read_responses(connection));
+        Ok(transport)
+    }
+
+    fn state(&self) -> MutexGuard<ConnectionState> {
+        self.state
+            .lock()
+            .expect("no code panics while holding the lock; qed")
+    }
+
+    /// dispatches the responses of the node and reconnects after the connection dropped
+    fn read_responses(&self, mut connection: Connection) {
+        loop {
+            let err = match self.dispatch(&connection) {
+                Ok(()) => unreachable!("dispatch only returns on errors; qed"),
+                Err(err) => err,
+            };
+            warn!("connection to {} dropped: {}. reconnecting", self.url, err);
+            self.state().writer = None;
+            connection = loop {
+                thread::sleep(Duration::from_secs(RECONNECT_DELAY));
+                match self.reconnect() {
+                    Ok(connection) => break connection,
+                    Err(err) => warn!("cannot reconnect to {}: {}", self.url, err),
+                }
+            };
+            info!("reconnected to {}", self.url);
+        }
+    }
+
+    /// opens a new connection and sends the pending requests again
+    fn reconnect(&self) -> Result<Connection, Error> {
+        let connection = connect(&self.url, self.tls.as_ref())?;
+        let mut state = self.state();
+        // calls whose futures were dropped (timed out) aren't sent again
+        state.pending.retain(|_, call| !call.response.is_canceled());
+        let mut ids = state.pending.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        for id in ids {
+            connection.write_text(&state.pending[&id].request)?;
+        }
+        state.writer = Some(connection.clone());
+        Ok(connection)
+    }
+
+    fn dispatch(&self, connection: &Connection) -> Result<(), Error> {
+        loop {
+            let message = connection.read_text()?;
+            let response: Value = match serde_json::from_str(&message) {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!("ignoring message of {} that isn't json: {}", self.url, err);
+                    continue;
+                }
+            };
+            // notifications of subscriptions have no id
+            let id = match response.get("id").and_then(Value::as_u64) {
+                Some(id) => id as RequestId,
+                None => continue,
+            };
+            let call = match self.state().pending.remove(&id) {
+                Some(call) => call,
+                None => continue,
+            };
+            let _ = call.response.send(call_result(response));
+        }
+    }
+}
+
+/// returns the result of the json-rpc `response`
+pub fn call_result(response: Value) -> Result<Value, web3::Error> {
+    match serde_json::from_value::<rpc::Output>(response) {
+        Ok(rpc::Output::Success(success)) => Ok(success.result),
+        Ok(rpc::Output::Failure(failure)) => Err(web3::Error::Rpc(failure.error)),
+        Err(err) => Err(web3::Error::Transport(format!(
+            "node responded with malformed json-rpc: {}",
+            err
+        ))),
+    }
+}
+
+impl Transport for WebSocket {
+    type Out = WebSocketCall;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.id.fetch_add(1, Ordering::SeqCst);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let request = match serde_json::to_string(&request) {
+            Ok(request) => request,
+            Err(err) => {
+                return WebSocketCall::Failed(Some(web3::Error::Transport(format!(
+                    "cannot serialize request: {}",
+                    err
+                ))))
+            }
+        };
+        let (sender, receiver) = oneshot::channel();
+        let mut state = self.state();
+        let written = match state.writer {
+            // while the connection is down the request is sent after the reconnect
+            None => Ok(()),
+            Some(ref writer) => writer.write_text(&request).map_err(|err| {
+                // the reading thread notices and reconnects
+                writer.shutdown();
+                err
+            }),
+        };
+        if let Err(err) = written {
+            warn!("cannot send request to {}: {}. sending it after the reconnect", self.url, err);
+            state.writer = None;
+        }
+        state.pending.insert(
+            id,
+            PendingCall {
+                request,
+                response: sender,
+            },
+        );
+        WebSocketCall::Pending(receiver)
+    }
+}
+
+/// response of a call through a `WebSocket`
+pub enum WebSocketCall {
+    Pending(oneshot::Receiver<Result<Value, web3::Error>>),
+    Failed(Option<web3::Error>),
+}
+
+impl Future for WebSocketCall {
+    type Item = Value;
+    type Error = web3::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            WebSocketCall::Pending(ref mut receiver) => match receiver.poll() {
+                Ok(Async::Ready(result)) => result.map(Async::Ready),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(oneshot::Canceled) => Err(web3::Error::Transport(
+                    "websocket transport dropped the request".into(),
+                )),
+            },
+            WebSocketCall::Failed(ref mut err) => {
+                Err(err.take().expect("a failed call isn't polled again; qed"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+    use futures::Future;
+    use serde_json::{self, Value};
+    use tungstenite::{self, Message};
+    use web3::{self, Transport};
+    use super::{call_result, WebSocket};
+
+    /// accepts `connections` websocket connections on `127.0.0.1` one after another.
+    /// answers each request with a notification followed by its response with
+    /// the result `0x10`. closes a connection after `requests_per_connection` requests.
+    /// returns the url of the server
+    fn serve(connections: usize, requests_per_connection: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut socket = tungstenite::accept(stream)
+                    .map_err(|err| err.to_string())
+                    .unwrap();
+                socket.write_message(Message::Ping(vec![7])).unwrap();
+                let mut answered = 0;
+                while answered < requests_per_connection {
+                    let request = match socket.read_message().unwrap() {
+                        Message::Text(text) => text,
+                        _ => continue,
+                    };
+                    let request: Value = serde_json::from_str(&request).unwrap();
+                    let notification = "{\"jsonrpc\":\"2.0\",\"method\":\"eth_subscription\"}";
+                    socket
+                        .write_message(Message::Text(notification.into()))
+                        .unwrap();
+                    let response = format!(
+                        "{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":\"0x10\"}}",
+                        request["id"]
+                    );
+                    socket.write_message(Message::Text(response)).unwrap();
+                    answered += 1;
+                }
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_websocket_transport() {
+        let url = serve(1, 2);
+        let transport = WebSocket::connect(&url, None).unwrap();
+        for _ in 0..2 {
+            let result = transport.execute("eth_blockNumber", vec![]).wait().unwrap();
+            assert_eq!(Value::from("0x10"), result);
+        }
+    }
+
+    #[test]
+    fn test_websocket_transport_reconnects() {
+        // the server drops the first connection after a request
+        let url = serve(2, 1);
+        let transport = WebSocket::connect(&url, None).unwrap();
+        let result = transport.execute("eth_blockNumber", vec![]).wait().unwrap();
+        assert_eq!(Value::from("0x10"), result);
+        let result = transport.execute("eth_blockNumber", vec![]).wait().unwrap();
+        assert_eq!(Value::from("0x10"), result);
+    }
+
+    #[test]
+    fn test_connect_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        assert!(WebSocket::connect(&url, None).is_err());
+        assert!(WebSocket::connect("http://node", None).is_err());
+    }
+
+    #[test]
+    fn test_call_result() {
+        let success = serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#).unwrap();
+        assert_eq!(Ok(serde_json::Value::from("0x1")), call_result(success).map_err(|_| ()));
+        let failure = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32000,"message":"nonce too low"}}"#,
+        ).unwrap();
+        match call_result(failure) {
+            Err(web3::Error::Rpc(err)) => assert_eq!("nonce too low", err.message),
+            _ => panic!("expected an rpc error"),
+        }
+    }
+}
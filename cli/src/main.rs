@@ -7,37 +7,114 @@ extern crate log;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate tokio_core;
 
-use std::env;
+use std::{env, process, thread};
+use std::io::{self, Write};
 use std::sync::Arc;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use docopt::Docopt;
 use futures::{future, Stream};
 use tokio_core::reactor::Core;
 
 use bridge::app::App;
-use bridge::bridge::create_bridge;
-use bridge::config::Config;
-use bridge::error::Error;
+use bridge::bridge::{create_bridge, create_bridge_backed_by, DryRunBackend};
+use bridge::config::{self, BridgesConfig, Config};
+use bridge::control;
+use bridge::error::{Error, ErrorKind, MAX_LAG_EXIT_CODE};
 use bridge::database::Database;
+use bridge::doctor;
+use bridge::dry_run;
+use bridge::event_queue;
+use bridge::gas_price;
+use bridge::http;
+use bridge::logging;
+use bridge::metrics;
+use bridge::otlp;
+use bridge::postgres_sink;
+use bridge::rebuild;
+use bridge::refund;
+use bridge::replay;
+use bridge::shard;
+use bridge::signer;
+use bridge::stats;
+use bridge::status;
+use bridge::supervisor;
+use bridge::top;
 
 #[derive(Debug, Deserialize)]
 pub struct Args {
     arg_config: PathBuf,
     arg_database: PathBuf,
+    cmd_config: bool,
+    cmd_generate: bool,
+    cmd_refund: bool,
+    cmd_sign: bool,
+    cmd_execute: bool,
+    cmd_status: bool,
+    cmd_database: bool,
+    cmd_export: bool,
+    cmd_import: bool,
+    cmd_top: bool,
+    cmd_signer: bool,
+    cmd_stats: bool,
+    cmd_rebuild_state: bool,
+    cmd_doctor: bool,
+    cmd_replay: bool,
+    flag_home_deployment: Option<PathBuf>,
+    flag_foreign_deployment: Option<PathBuf>,
+    flag_recipient: String,
+    flag_value: String,
+    flag_transaction: String,
+    flag_gas_price: String,
+    flag_message: String,
+    flag_signature: Vec<String>,
+    flag_snapshot: PathBuf,
+    flag_days: u64,
+    flag_json: bool,
+    flag_dry_run: bool,
+    flag_log_format: Option<String>,
+    flag_bridges: Option<PathBuf>,
+    flag_home_contract: Option<String>,
+    flag_foreign_contract: Option<String>,
+    flag_home_deploy: u64,
+    flag_foreign_deploy: u64,
+    flag_chain: String,
+    flag_from_block: u64,
+    flag_to_block: u64,
 }
 
 fn main() {
-    let _ = env_logger::init();
+    init_logger();
     let result = execute(env::args());
 
     match result {
         Ok(s) => println!("{}", s),
-        Err(err) => print_err(err),
+        Err(err) => {
+            let max_lag_exceeded = match *err.kind() {
+                ErrorKind::MaxLagExceeded(..) => true,
+                _ => false,
+            };
+            print_err(err);
+            if max_lag_exceeded {
+                process::exit(MAX_LAG_EXIT_CODE);
+            }
+        }
     }
 }
 
+/// logs in the format set via `logging::set_format`, filtered by `RUST_LOG`
+fn init_logger() {
+    let mut builder = env_logger::LogBuilder::new();
+    builder.format(logging::format);
+    if let Ok(filters) = env::var("RUST_LOG") {
+        builder.parse(&filters);
+    }
+    let _ = builder.init();
+}
+
 fn print_err(err: Error) {
     let message = err.iter()
         .map(|e| e.to_string())
@@ -59,11 +136,45 @@ Parity-bridge
     Commit: {}
 
 Usage:
-    parity-bridge --config <config> --database <database>
+    parity-bridge --config <config> --database <database> [--dry-run] [--log-format <format>]
+    parity-bridge --bridges <bridges> [--dry-run] [--log-format <format>]
+    parity-bridge config generate [--home-deployment <home-dir>] [--foreign-deployment <foreign-dir>]
+    parity-bridge refund sign --config <config> --database <database> --recipient <address> --value <wei> --transaction <hash> [--gas-price <wei>]
+    parity-bridge refund execute --config <config> --database <database> --message <message> (--signature <signature>)...
+    parity-bridge refund status --config <config> --database <database> --transaction <hash>
+    parity-bridge database export --database <database>
+    parity-bridge database import --database <database> --snapshot <snapshot>
+    parity-bridge top --config <config>
+    parity-bridge stats --config <config> [--days <days>] [--json]
+    parity-bridge signer --config <config> --database <database>
+    parity-bridge rebuild-state --config <config> --database <database> [--home-contract <address>] [--foreign-contract <address>] [--home-deploy <block>] [--foreign-deploy <block>]
+    parity-bridge doctor --config <config> --database <database>
+    parity-bridge replay --config <config> --database <database> --chain <chain> --from-block <block> --to-block <block>
     parity-bridge -h | --help
 
 Options:
-    -h, --help           Display help message and exit.
+    -h, --help                              Display help message and exit.
+    --home-deployment <home-dir>            Use the contract of a `HomeBridge` deployment info dir.
+    --foreign-deployment <foreign-dir>      Use the contract of a `ForeignBridge` deployment info dir.
+    --recipient <address>                   Address the refund is paid to.
+    --value <wei>                           Value of the refund in wei.
+    --transaction <hash>                    Transaction on home that brought the refunded ether to `HomeBridge`.
+    --gas-price <wei>                       Gas price anyone but the recipient must execute the refund with [default: 0].
+    --message <message>                     Refund message printed by `refund sign`.
+    --signature <signature>                 Signature of the refund message printed by `refund sign`.
+    --snapshot <snapshot>                   Database exported by `database export`.
+    --days <days>                           Number of days up to today to aggregate [default: 7].
+    --json                                  Print the statistics as json.
+    --bridges <bridges>                     File that lists the configs and databases of several bridges run by one process.
+    --log-format <format>                   Format of the log: `text` or `json`. Overrides `log_format` of the config.
+    --dry-run                               Simulate the transactions of the bridge instead of sending them and don't save the checkpoints.
+    --home-contract <address>               Address of `HomeBridge` unless resolved via `home.registry`.
+    --foreign-contract <address>            Address of `ForeignBridge` unless resolved via `foreign.registry`.
+    --home-deploy <block>                   Block `HomeBridge` was deployed in. scanning starts after it [default: 0].
+    --foreign-deploy <block>                Block `ForeignBridge` was deployed in. scanning starts after it [default: 0].
+    --chain <chain>                         Chain whose logs are replayed: `home` or `foreign`.
+    --from-block <block>                    First block of the replayed range.
+    --to-block <block>                      Last block of the replayed range.
 "#,
         env!("CARGO_PKG_VERSION"),
         env!("GIT_HASH")
@@ -74,23 +185,433 @@ Options:
         .and_then(|d| d.argv(command).deserialize())
         .map_err(|e| e.to_string())?;
 
+    if let Some(ref format) = args.flag_log_format {
+        logging::set_format(logging::parse_format(format)?);
+    }
+
+    if let Some(ref path) = args.flag_bridges {
+        return run_bridges(path, &args);
+    }
+
+    if args.cmd_config && args.cmd_generate {
+        return Ok(generate_config(&args));
+    }
+
+    if args.cmd_database {
+        return if args.cmd_export {
+            Ok(Database::load(&args.arg_database)?.to_string())
+        } else {
+            import_snapshot(&args)
+        };
+    }
+
     info!(target: "bridge", "Loading config");
     let config = Config::load(args.arg_config)?;
+    if args.flag_log_format.is_none() {
+        logging::set_format(config.log_format);
+    }
+
+    if args.cmd_top {
+        return run_top(&config);
+    }
+
+    if args.cmd_stats {
+        return print_stats(&config, &args);
+    }
+
+    if args.cmd_doctor {
+        let mut event_loop = Core::new().unwrap();
+        let checks = doctor::diagnose(&mut event_loop, config, &args.arg_database);
+        return Ok(doctor::render(&checks));
+    }
+
+    info!(target: "bridge", "Installing signal handlers");
+    control::install_signal_handlers();
 
     info!(target: "bridge", "Starting event loop");
     let mut event_loop = Core::new().unwrap();
 
-    info!(target: "bridge", "Establishing ipc connection");
-    let app = App::new_ipc(config, &args.arg_database, &event_loop.handle())?;
+    info!(target: "bridge", "Connecting to nodes");
+    let mut app = App::new(config, &args.arg_database, &event_loop.handle())?;
+
+    info!(target: "bridge", "Resolving ENS names");
+    app.resolve_ens_names(&mut event_loop)?;
+
+    info!(target: "bridge", "Detecting transaction types");
+    app.detect_transaction_types(&mut event_loop)?;
+
+    info!(target: "bridge", "Preparing keystore");
+    app.prepare_keystore(&mut event_loop)?;
+
+    if args.cmd_rebuild_state {
+        // never overwrite a database that may be ahead of what the chains reveal
+        if args.arg_database.exists() {
+            return Err(format!(
+                "{:?} already exists. `rebuild-state` only creates a missing database",
+                args.arg_database
+            ).into());
+        }
+        let mut database = Database::default();
+        if let Some(ref address) = args.flag_home_contract {
+            database.home_contract_address = rebuild::parse_address(address)?;
+        }
+        if let Some(ref address) = args.flag_foreign_contract {
+            database.foreign_contract_address = rebuild::parse_address(address)?;
+        }
+        app.resolve_contract_addresses(&mut event_loop, &mut database)?;
+        if database.home_contract_address == Default::default()
+            || database.foreign_contract_address == Default::default()
+        {
+            return Err("`rebuild-state` requires the contract addresses. \
+                        pass --home-contract and --foreign-contract or configure registries"
+                .into());
+        }
+        info!(target: "bridge", "Rebuilding state from the chains");
+        let app_ref = app.as_ref();
+        let state = rebuild::rebuild_state(
+            &mut event_loop,
+            &app_ref,
+            database.home_contract_address,
+            database.foreign_contract_address,
+            args.flag_home_deploy,
+            args.flag_foreign_deploy,
+        )?;
+        state.database.store(&args.arg_database)?;
+        return Ok(state.render());
+    }
+
+    let mut database = app.load_database()?;
+
+    info!(target: "bridge", "Resolving contract addresses");
+    app.resolve_contract_addresses(&mut event_loop, &mut database)?;
+
+    info!(target: "bridge", "Verifying deployment");
+    app.verify_deployment(&mut event_loop, &database)?;
+
+    if args.flag_dry_run {
+        info!(target: "bridge", "Starting dry run. no transactions are sent");
+        dry_run::enable(&mut app);
+    }
+
+    if args.cmd_signer {
+        let signer_config = match app.config.signer {
+            Some(ref signer_config) => signer_config.clone(),
+            None => return Err("`signer` requires the `signer` table to be configured".into()),
+        };
+        let policy = signer::Policy {
+            home_contract: database.home_contract_address,
+            foreign_contract: database.foreign_contract_address,
+            home_account: app.config.home.account,
+            foreign_account: app.config.foreign.account,
+            chain_ids: app.config.message_chain_ids(),
+        };
+        info!(target: "bridge", "Starting signer");
+        signer::listen(&signer_config, &policy, |request| {
+            signer::serve_with_nodes(&app, &mut event_loop, request)
+        })?;
+        return Ok("Done".into());
+    }
+
+    if args.cmd_refund {
+        if app.config.tokens().is_some() {
+            return Err("`refund` requires `HomeBridge`. token bridges hold no ether to refund".into());
+        }
+        let app_ref = app.as_ref();
+        let home_contract = database.home_contract_address;
+        if args.cmd_sign {
+            let message = refund::parse_refund(
+                &args.flag_recipient,
+                &args.flag_value,
+                &args.flag_transaction,
+                &args.flag_gas_price,
+                home_contract,
+                app.config.message_chain_ids(),
+            )?;
+            info!(target: "bridge", "Signing refund");
+            let signature = event_loop.run(refund::sign_refund(&app_ref, &message))?;
+            refund::audit_refund_signature(&app_ref, &message, signature)?;
+            return Ok(format!(
+                "message: {}\nsignature: {}",
+                refund::to_hex(&message.to_bytes()),
+                refund::to_hex(&signature.0)
+            ));
+        }
+        if args.cmd_execute {
+            if !app_ref.config.role.executes_withdraws() {
+                return Err("`role = \"signer\"` never sends transactions to home. \
+                            execute the refund from another account"
+                    .into());
+            }
+            let message = refund::parse_message(&args.flag_message)?;
+            let signatures = args.flag_signature
+                .iter()
+                .map(|signature| refund::parse_signature(signature))
+                .collect::<Result<Vec<_>, _>>()?;
+            info!(target: "bridge", "Executing refund");
+            let future = refund::execute_refund(&app_ref, home_contract, &message, &signatures);
+            return match event_loop.run(future)? {
+                Some(hash) => Ok(format!("refund sent in transaction {:?}", hash)),
+                None => Ok("refund was not sent because its simulation reverted".into()),
+            };
+        }
+        let transaction_hash = refund::parse_transaction_hash(&args.flag_transaction)?;
+        let executed = event_loop.run(refund::refund_executed(
+            &app_ref,
+            home_contract,
+            transaction_hash,
+        ))?;
+        return Ok(if executed {
+            "executed".into()
+        } else {
+            "not executed".into()
+        });
+    }
+
+    if args.cmd_replay {
+        let chain = replay::parse_chain(&args.flag_chain)?;
+        let app_ref = Arc::new(app.as_ref());
+        info!(
+            target: "bridge",
+            "Replaying blocks {} to {}",
+            args.flag_from_block,
+            args.flag_to_block
+        );
+        let report = replay::replay(
+            &mut event_loop,
+            &app_ref,
+            database.home_contract_address,
+            database.foreign_contract_address,
+            chain,
+            args.flag_from_block,
+            args.flag_to_block,
+        )?;
+        return Ok(report.render());
+    }
+
+    if app.config.http_api.is_some() {
+        info!(target: "bridge", "Starting http api");
+        http::serve_api(&app.config, app.parked.clone())?;
+    }
+
+    if app.config.postgres.is_some() {
+        info!(target: "bridge", "Starting postgres sink");
+        postgres_sink::start(&app.config.postgres)?;
+    }
+
+    if app.config.event_queue.is_some() {
+        info!(target: "bridge", "Starting event queue");
+        event_queue::start(&app.config.event_queue)?;
+    }
+
+    if app.config.shard.is_some() {
+        info!(target: "bridge", "Taking shard leases");
+        shard::start(&app.config, &database, app.leases.clone())?;
+    }
+
+    otlp::start(&app.config.otlp);
+    gas_price::start("home", &app.config.home, app.gas_price_oracles.home.clone());
+    gas_price::start("foreign", &app.config.foreign, app.gas_price_oracles.foreign.clone());
+
     let app_ref = Arc::new(app.as_ref());
 
-    let database = Database::load(&args.arg_database)?;
+    info!(target: "bridge", "Installing shutdown handlers");
+    control::install_shutdown_handlers();
 
     info!(target: "bridge", "Starting listening to events");
-    let bridge = create_bridge(app_ref, &database)
-        .and_then(|_| future::ok(true))
-        .collect();
-    event_loop.run(bridge)?;
+    if args.flag_dry_run {
+        let bridge = create_bridge_backed_by(app_ref, &database, DryRunBackend)
+            .and_then(|_| future::ok(true))
+            .collect();
+        event_loop.run(bridge)?;
+    } else {
+        let bridge = create_bridge(app_ref, &database)
+            .and_then(|_| future::ok(true))
+            .collect();
+        event_loop.run(bridge)?;
+    }
+
+    Ok(shutdown_summary())
+}
+
+/// runs every bridge of the bridges file at `path` on one event loop
+fn run_bridges(path: &Path, args: &Args) -> Result<String, Error> {
+    info!(target: "bridge", "Loading bridges");
+    let bridges = BridgesConfig::load(path)?;
+    if args.flag_log_format.is_none() {
+        logging::set_format(bridges.log_format);
+    }
+
+    info!(target: "bridge", "Installing signal handlers");
+    control::install_signal_handlers();
+
+    info!(target: "bridge", "Starting event loop");
+    let mut event_loop = Core::new().unwrap();
+
+    let mut apps = Vec::new();
+    for definition in bridges.bridges {
+        let _entered = supervisor::enter(&definition.name);
+
+        info!(target: "bridge", "Connecting to nodes");
+        let mut app = App::new(definition.config, &definition.database, &event_loop.handle())?;
+
+        info!(target: "bridge", "Resolving ENS names");
+        app.resolve_ens_names(&mut event_loop)?;
+
+        info!(target: "bridge", "Detecting transaction types");
+        app.detect_transaction_types(&mut event_loop)?;
+
+        info!(target: "bridge", "Preparing keystore");
+        app.prepare_keystore(&mut event_loop)?;
+
+        let mut database = app.load_database()?;
+
+        info!(target: "bridge", "Resolving contract addresses");
+        app.resolve_contract_addresses(&mut event_loop, &mut database)?;
+
+        info!(target: "bridge", "Verifying deployment");
+        app.verify_deployment(&mut event_loop, &database)?;
+
+        if args.flag_dry_run {
+            info!(target: "bridge", "Starting dry run. no transactions are sent");
+            dry_run::enable(&mut app);
+        }
+
+        // every bridge publishes its events to the queue it configures
+        if app.config.event_queue.is_some() {
+            info!(target: "bridge", "Starting event queue");
+            event_queue::start(&app.config.event_queue)?;
+        }
+
+        // every bridge leases the partitions of its own events
+        if app.config.shard.is_some() {
+            info!(target: "bridge", "Taking shard leases");
+            shard::start(&app.config, &database, app.leases.clone())?;
+        }
+
+        // only one bridge of the process configures the http api, postgres or otlp
+        if app.config.http_api.is_some() {
+            info!(target: "bridge", "Starting http api");
+            http::serve_api(&app.config, app.parked.clone())?;
+        }
+
+        if app.config.postgres.is_some() {
+            info!(target: "bridge", "Starting postgres sink");
+            postgres_sink::start(&app.config.postgres)?;
+        }
+
+        otlp::start(&app.config.otlp);
+        gas_price::start("home", &app.config.home, app.gas_price_oracles.home.clone());
+        gas_price::start("foreign", &app.config.foreign, app.gas_price_oracles.foreign.clone());
+
+        apps.push((definition.name, app, database));
+    }
+
+    info!(target: "bridge", "Installing shutdown handlers");
+    control::install_shutdown_handlers();
+
+    info!(target: "bridge", "Starting listening to events of {} bridges", apps.len());
+    if args.flag_dry_run {
+        let bridges = apps.iter()
+            .map(|&(ref name, ref app, ref database)| {
+                let app_ref = Arc::new(app.as_ref());
+                let bridge = create_bridge_backed_by(app_ref, database, DryRunBackend)
+                    .and_then(|_| future::ok(true))
+                    .collect();
+                supervisor::supervise(name.clone(), bridge)
+            })
+            .collect::<Vec<_>>();
+        event_loop.run(future::join_all(bridges))?;
+    } else {
+        let bridges = apps.iter()
+            .map(|&(ref name, ref app, ref database)| {
+                let app_ref = Arc::new(app.as_ref());
+                let bridge = create_bridge(app_ref, database)
+                    .and_then(|_| future::ok(true))
+                    .collect();
+                supervisor::supervise(name.clone(), bridge)
+            })
+            .collect::<Vec<_>>();
+        event_loop.run(future::join_all(bridges))?;
+    }
+
+    Ok(shutdown_summary())
+}
+
+/// summarizes the work of the bridge that was shut down by `SIGINT` or `SIGTERM`
+fn shutdown_summary() -> String {
+    let relays = metrics::total_relays();
+    let status = status::snapshot();
+    let checked = |block: Option<u64>| block.map_or("none this run".into(), |n| n.to_string());
+    format!(
+        "Shut down cleanly\n\
+         relayed deposits: {}\n\
+         submitted signatures: {}\n\
+         relayed withdraws: {}\n\
+         checked deposit relay: {}\n\
+         checked withdraw confirm: {}\n\
+         checked withdraw relay: {}",
+        relays.deposits,
+        relays.signatures,
+        relays.withdraws,
+        checked(status.checked_deposit_relay),
+        checked(status.checked_withdraw_confirm),
+        checked(status.checked_withdraw_relay)
+    )
+}
+
+/// redraws the status of the bridge served by its http api every second until interrupted
+fn run_top(config: &Config) -> Result<String, Error> {
+    let address = match config.http_api {
+        Some(ref http_api) => http_api.address,
+        None => return Err("`top` requires `http_api` to be configured".into()),
+    };
+    loop {
+        // clear the screen and move the cursor to the top left
+        print!("\x1b[2J\x1b[H{}", top::frame(address));
+        io::stdout().flush()?;
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// aggregates the relay history over the days in `args`
+fn print_stats(config: &Config, args: &Args) -> Result<String, Error> {
+    let path = match config.relay_history {
+        Some(ref path) => path,
+        None => return Err("`stats` requires `relay_history` to be configured".into()),
+    };
+    if args.flag_days == 0 || args.flag_days > stats::MAX_DAYS {
+        return Err(format!("--days must be between 1 and {}", stats::MAX_DAYS).into());
+    }
+    let statistics = stats::load(path, status::now(), args.flag_days)?;
+    if args.flag_json {
+        Ok(serde_json::to_string_pretty(&statistics).expect("serialization can't fail; qed"))
+    } else {
+        Ok(stats::render(&statistics))
+    }
+}
+
+/// imports the checkpoints of the snapshot in `args` into the database in `args`.
+/// creates the database if there is none
+fn import_snapshot(args: &Args) -> Result<String, Error> {
+    let snapshot = Database::load(&args.flag_snapshot)?;
+    let database = match Database::load(&args.arg_database) {
+        Ok(database) => database.import_snapshot(&snapshot)?,
+        Err(Error(ErrorKind::MissingFile(_), _)) => snapshot,
+        Err(err) => return Err(err),
+    };
+    database.store(&args.arg_database)?;
+    Ok(database.to_string())
+}
 
-    Ok("Done".into())
+/// returns a config template that uses the contracts of the deployments in `args`
+fn generate_config(args: &Args) -> String {
+    let home_bin = args.flag_home_deployment
+        .as_ref()
+        .map(|dir| dir.join("deployed_bytecode"))
+        .unwrap_or_else(|| "contracts/compiled/HomeBridge.bin".into());
+    let foreign_bin = args.flag_foreign_deployment
+        .as_ref()
+        .map(|dir| dir.join("deployed_bytecode"))
+        .unwrap_or_else(|| "contracts/compiled/ForeignBridge.bin".into());
+    config::template(&home_bin, &foreign_bin)
 }
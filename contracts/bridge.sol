@@ -122,6 +122,18 @@ library Message {
     // offset 52: 32 bytes :: uint256 (big endian) - value
     // offset 84: 32 bytes :: bytes32 - transaction hash
     // offset 116: 32 bytes :: uint256 (big endian) - home gas price
+    // offset 148: 20 bytes :: address - address of the `HomeBridge` the message is meant for
+    // messages of the baseline layout (116 bytes) end before the address of the `HomeBridge`.
+    // they were signed before messages were bound to a `HomeBridge` and are refused, see `withdraw`
+    // messages of the token bridges continue with
+    // offset 168: 20 bytes :: address - token on home that `HomeTokenBridge` transfers to the recipient
+    //
+    // layout of a version 2 message :: bytes:
+    // offset  0: 32 bytes :: uint256 (big endian) - message length
+    // offset 32: 1 byte :: uint8 - version (2)
+    // offset 33: 32 bytes :: uint256 (big endian) - chain id of home
+    // offset 65: 32 bytes :: uint256 (big endian) - chain id of foreign
+    // offset 97: the fields of the legacy layout above (136 bytes, 156 for the token bridges)
 
     // mload always reads 32 bytes.
     // if mload reads an address it only interprets the last 20 bytes as the address.
@@ -169,6 +181,66 @@ library Message {
         }
         return gasPrice;
     }
+
+    function getHomeBridge(bytes message) internal pure returns (address) {
+        address homeBridge;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            homeBridge := mload(add(message, 136))
+        }
+        return homeBridge;
+    }
+
+    function getToken(bytes message) internal pure returns (address) {
+        address token;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            token := mload(add(message, 156))
+        }
+        return token;
+    }
+
+    function getVersion(bytes message) internal pure returns (uint8) {
+        return uint8(message[0]);
+    }
+
+    function getHomeChainId(bytes message) internal pure returns (uint256) {
+        uint256 chainId;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            chainId := mload(add(message, 33))
+        }
+        return chainId;
+    }
+
+    function getForeignChainId(bytes message) internal pure returns (uint256) {
+        uint256 chainId;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            chainId := mload(add(message, 65))
+        }
+        return chainId;
+    }
+
+    /// returns the fields of `message` in the legacy layout.
+    /// messages of the legacy layout (136 or 156 bytes) are returned as they are.
+    /// version 2 messages (201 or 221 bytes) must be meant for the chains
+    /// `homeChainId` and `foreignChainId`. a chain id of `0` rejects all version 2 messages.
+    /// the getters above read the fields from the returned bytes
+    function checkedFields(bytes message, uint256 homeChainId, uint256 foreignChainId) internal pure returns (bytes) {
+        if (message.length != 201 && message.length != 221) {
+            return message;
+        }
+        require(getVersion(message) == 2);
+        require(homeChainId != 0 && getHomeChainId(message) == homeChainId);
+        require(foreignChainId != 0 && getForeignChainId(message) == foreignChainId);
+
+        bytes memory fields = new bytes(message.length - 65);
+        for (uint256 i = 0; i < fields.length; i++) {
+            fields[i] = message[65 + i];
+        }
+        return fields;
+    }
 }
 
 
@@ -189,6 +261,115 @@ library MessageTest {
     function getHomeGasPrice(bytes message) public pure returns (uint256) {
         return Message.getHomeGasPrice(message);
     }
+
+    function getHomeBridge(bytes message) public pure returns (address) {
+        return Message.getHomeBridge(message);
+    }
+
+    function getToken(bytes message) public pure returns (address) {
+        return Message.getToken(message);
+    }
+
+    function getVersion(bytes message) public pure returns (uint8) {
+        return Message.getVersion(message);
+    }
+
+    function getHomeChainId(bytes message) public pure returns (uint256) {
+        return Message.getHomeChainId(message);
+    }
+
+    function getForeignChainId(bytes message) public pure returns (uint256) {
+        return Message.getForeignChainId(message);
+    }
+
+    function checkedFields(bytes message, uint256 homeChainId, uint256 foreignChainId) public pure returns (bytes) {
+        return Message.checkedFields(message, homeChainId, foreignChainId);
+    }
+}
+
+
+library CallMessage {
+    // layout of the message of a call :: bytes:
+    // offset  0: 32 bytes :: uint256 (big endian) - message length (not part of message. any `bytes` begins with the length in memory)
+    // offset 32: 1 byte :: uint8 - tag (0x80)
+    // offset 33: 20 bytes :: address - recipient address
+    // offset 53: 20 bytes :: address - sender of the call request on foreign
+    // offset 73: 32 bytes :: bytes32 - transaction hash
+    // offset 105: 32 bytes :: uint256 (big endian) - gas limit of the call
+    // offset 137: 32 bytes :: uint256 (big endian) - home gas price
+    // offset 169: 20 bytes :: address - address of the `HomeBridge` the message is meant for
+    // offset 189: the rest :: bytes - data of the call. begins with the 4 byte function selector
+
+    // addresses are read like in the message library above
+
+    /// whether `message` is tagged as the message of a call and carries a function selector.
+    /// version 2 withdraw messages begin with their version (2) instead of the tag
+    /// and legacy withdraw messages (136 or 156 bytes) are too short
+    function isCall(bytes message) internal pure returns (bool) {
+        return message.length >= 161 && message[0] == 0x80;
+    }
+
+    function getRecipient(bytes message) internal pure returns (address) {
+        address recipient;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            recipient := mload(add(message, 21))
+        }
+        return recipient;
+    }
+
+    function getSender(bytes message) internal pure returns (address) {
+        address sender;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            sender := mload(add(message, 41))
+        }
+        return sender;
+    }
+
+    function getTransactionHash(bytes message) internal pure returns (bytes32) {
+        bytes32 hash;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            hash := mload(add(message, 73))
+        }
+        return hash;
+    }
+
+    function getGasLimit(bytes message) internal pure returns (uint256) {
+        uint256 gasLimit;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            gasLimit := mload(add(message, 105))
+        }
+        return gasLimit;
+    }
+
+    function getHomeGasPrice(bytes message) internal pure returns (uint256) {
+        uint256 gasPrice;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            gasPrice := mload(add(message, 137))
+        }
+        return gasPrice;
+    }
+
+    function getHomeBridge(bytes message) internal pure returns (address) {
+        address homeBridge;
+        // solium-disable-next-line security/no-inline-assembly
+        assembly {
+            homeBridge := mload(add(message, 157))
+        }
+        return homeBridge;
+    }
+
+    function getData(bytes message) internal pure returns (bytes) {
+        bytes memory data = new bytes(message.length - 157);
+        for (uint256 i = 0; i < data.length; i++) {
+            data[i] = message[157 + i];
+        }
+        return data;
+    }
 }
 
 
@@ -221,7 +402,19 @@ contract HomeBridge {
     address[] public authorities;
 
     /// Used foreign transaction hashes.
-    mapping (bytes32 => bool) withdraws;
+    /// public so that relayers and refund tooling can check whether a message was executed.
+    mapping (bytes32 => bool) public withdraws;
+
+    /// chain ids version 2 messages must name. `0` if the deployment only accepts legacy messages
+    uint256 public homeChainId;
+    uint256 public foreignChainId;
+
+    /// Used foreign transaction hashes of calls.
+    mapping (bytes32 => bool) public calls;
+
+    /// sender on foreign of the call `executeCall` is executing. `0x0` outside of calls.
+    /// recipients check it to authenticate calls from foreign
+    address public messageSender;
 
     /// Event created on money deposit.
     event Deposit (address recipient, uint256 value);
@@ -229,13 +422,18 @@ contract HomeBridge {
     /// Event created on money withdraw.
     event Withdraw (address recipient, uint256 value, bytes32 transactionHash);
 
+    /// Event created on call execution. `success` is false if the call reverted
+    event CallExecuted(address recipient, bytes32 transactionHash, bool success);
+
     /// Constructor.
     function HomeBridge(
         uint256 requiredSignaturesParam,
         address[] authoritiesParam,
         uint256 estimatedGasCostOfWithdrawParam,
         uint256 maxTotalHomeContractBalanceParam,
-        uint256 maxSingleDepositValueParam
+        uint256 maxSingleDepositValueParam,
+        uint256 homeChainIdParam,
+        uint256 foreignChainIdParam
     ) public
     {
         require(requiredSignaturesParam != 0);
@@ -245,6 +443,8 @@ contract HomeBridge {
         estimatedGasCostOfWithdraw = estimatedGasCostOfWithdrawParam;
         maxTotalHomeContractBalance = maxTotalHomeContractBalanceParam;
         maxSingleDepositValue = maxSingleDepositValueParam;
+        homeChainId = homeChainIdParam;
+        foreignChainId = foreignChainIdParam;
     }
 
     /// Should be used to deposit money.
@@ -268,15 +468,24 @@ contract HomeBridge {
     /// transfering any ether `value` out of this contract to `recipient`.
     /// bridge users must trust a majority of `requiredSignatures` of the `authorities`.
     function withdraw(uint8[] vs, bytes32[] rs, bytes32[] ss, bytes message) public {
-        require(message.length == 116);
+        // version 2 messages additionally bind the signatures to the chains of this deployment.
+        // messages of the baseline layout (116 bytes) are refused: they name no `HomeBridge`,
+        // so their signatures would be valid for every deployment of the same authorities
+        bytes memory fields = Message.checkedFields(message, homeChainId, foreignChainId);
+        require(fields.length == 136);
+
+        // signatures for a message are only valid for the `HomeBridge` the message names.
+        // this prevents replaying signatures collected for a retired deployment
+        // against a redeployed `HomeBridge`.
+        require(Message.getHomeBridge(fields) == address(this));
 
         // check that at least `requiredSignatures` `authorities` have signed `message`
         require(Helpers.hasEnoughValidSignatures(message, vs, rs, ss, authorities, requiredSignatures));
 
-        address recipient = Message.getRecipient(message);
-        uint256 value = Message.getValue(message);
-        bytes32 hash = Message.getTransactionHash(message);
-        uint256 homeGasPrice = Message.getHomeGasPrice(message);
+        address recipient = Message.getRecipient(fields);
+        uint256 value = Message.getValue(fields);
+        bytes32 hash = Message.getTransactionHash(fields);
+        uint256 homeGasPrice = Message.getHomeGasPrice(fields);
 
         // if the recipient calls `withdraw` they can choose the gas price freely.
         // if anyone else calls `withdraw` they have to use the gas price
@@ -307,6 +516,52 @@ contract HomeBridge {
 
         Withdraw(recipient, valueRemainingAfterSubtractingCost, hash);
     }
+
+    /// final step of a call requested by `ForeignBridge.requestCall`.
+    /// checks that `requiredSignatures` `authorities` have signed off on the `message`.
+    /// then calls `recipient` with `data` and `gasLimit` (all extracted from `message`)
+    /// while `messageSender` is the sender of the request on foreign.
+    /// see call message library above for a breakdown of the `message` contents.
+    /// a call that reverts is not executed again.
+    ///
+    /// the relay cost `(gasLimit + estimatedGasCostOfWithdraw) * homeGasPrice`
+    /// is refunded to the caller out of the balance of this contract.
+    /// the sender of the request burned as many tokens on foreign.
+    function executeCall(uint8[] vs, bytes32[] rs, bytes32[] ss, bytes message) public {
+        // withdraw messages lack the tag of calls
+        require(CallMessage.isCall(message));
+        require(CallMessage.getHomeBridge(message) == address(this));
+
+        // check that at least `requiredSignatures` `authorities` have signed `message`
+        require(Helpers.hasEnoughValidSignatures(message, vs, rs, ss, authorities, requiredSignatures));
+
+        // the gas price is paid by the sender of the request. see `withdraw`
+        uint256 homeGasPrice = CallMessage.getHomeGasPrice(message);
+        require(tx.gasprice == homeGasPrice);
+
+        // calls can't be nested: `messageSender` must be the sender of the running call
+        require(messageSender == 0x0);
+
+        bytes32 hash = CallMessage.getTransactionHash(message);
+        require(!calls[hash]);
+        calls[hash] = true;
+
+        address recipient = CallMessage.getRecipient(message);
+        uint256 gasLimit = CallMessage.getGasLimit(message);
+        bytes memory data = CallMessage.getData(message);
+
+        messageSender = CallMessage.getSender(message);
+        // a call gets at most 63/64 of the remaining gas.
+        // the caller must not be able to cut the gas limit the sender paid for
+        require(msg.gas - msg.gas / 64 > gasLimit);
+        bool success = recipient.call.gas(gasLimit)(data);
+        messageSender = 0x0;
+
+        // refund relay cost to relaying authority
+        msg.sender.transfer((gasLimit + estimatedGasCostOfWithdraw) * homeGasPrice);
+
+        CallExecuted(recipient, hash, success);
+    }
 }
 
 
@@ -408,6 +663,10 @@ contract ForeignBridge {
 
     uint256 public estimatedGasCostOfWithdraw;
 
+    /// chain ids version 2 messages must name. `0` if the deployment only accepts legacy messages
+    uint256 public homeChainId;
+    uint256 public foreignChainId;
+
     /// Contract authorities.
     address[] public authorities;
 
@@ -417,6 +676,9 @@ contract ForeignBridge {
     /// Pending signatures and authorities who confirmed them
     mapping (bytes32 => SignaturesCollection) signatures;
 
+    /// tokens that authorities earned as relay fees of deposits and haven't claimed yet
+    mapping (address => uint256) public relayFees;
+
     /// triggered when an authority confirms a deposit
     event DepositConfirmation(address recipient, uint256 value, bytes32 transactionHash);
 
@@ -431,10 +693,26 @@ contract ForeignBridge {
     /// Collected signatures which should be relayed to home chain.
     event CollectedSignatures(address authorityResponsibleForRelay, bytes32 messageHash);
 
+    /// triggered when an authority claims the relay fees it earned
+    event RelayFeesClaimed(address authority, uint256 tokens);
+
+    /// Pending signatures of calls and authorities who confirmed them
+    mapping (bytes32 => SignaturesCollection) callSignatures;
+
+    /// Event created on call request. picked up by the bridge authorities
+    event UserRequestForCall(address sender, address recipient, bytes data, uint256 gasLimit, uint256 homeGasPrice);
+
+    event CallSignatureSubmitted(bytes32 messageHash);
+
+    /// Collected signatures of a call which should be relayed to home chain.
+    event CollectedCallSignatures(address authorityResponsibleForRelay, bytes32 messageHash);
+
     function ForeignBridge(
         uint256 _requiredSignatures,
         address[] _authorities,
-        uint256 _estimatedGasCostOfWithdraw
+        uint256 _estimatedGasCostOfWithdraw,
+        uint256 _homeChainId,
+        uint256 _foreignChainId
     ) public
     {
         require(_requiredSignatures != 0);
@@ -442,6 +720,8 @@ contract ForeignBridge {
         requiredSignatures = _requiredSignatures;
         authorities = _authorities;
         estimatedGasCostOfWithdraw = _estimatedGasCostOfWithdraw;
+        homeChainId = _homeChainId;
+        foreignChainId = _foreignChainId;
     }
 
     /// require that sender is an authority
@@ -458,7 +738,54 @@ contract ForeignBridge {
     function deposit(address recipient, uint256 value, bytes32 transactionHash) public onlyAuthority() {
         // Protection from misbehaving authority
         var hash = keccak256(recipient, value, transactionHash);
+        confirmDeposit(recipient, value, transactionHash, 0, hash);
+    }
+
+    /// Used to deposit money to the contract while charging a relay fee in tokens.
+    ///
+    /// like `deposit` but `recipient` only gets `value - fee`.
+    /// `fee` is split among the authorities that confirmed the deposit
+    /// and can be claimed by them via `claimRelayFees`.
+    /// confirmations only count towards the same deposit if they name the same `fee`
+    /// so all authorities have to charge the same fee.
+    function depositWithFee(address recipient, uint256 value, bytes32 transactionHash, uint256 fee) public onlyAuthority() {
+        require(fee < value);
+        // Protection from misbehaving authority
+        var hash = keccak256(recipient, value, transactionHash, fee);
+        confirmDeposit(recipient, value, transactionHash, fee, hash);
+    }
 
+    /// Used to confirm several deposits in one transaction.
+    ///
+    /// confirms every deposit like `deposit` if `fee` is 0 and like `depositWithFee` otherwise.
+    /// deposits that `msg.sender` already confirmed are skipped
+    /// so that a batch doesn't fail because one of its deposits was relayed on its own.
+    /// fails if it confirms none of the deposits.
+    function depositBatch(address[] recipients, uint256[] values, bytes32[] transactionHashes, uint256 fee) public onlyAuthority() {
+        require(recipients.length == values.length);
+        require(recipients.length == transactionHashes.length);
+
+        uint256 confirmed = 0;
+        for (uint256 i = 0; i < recipients.length; i++) {
+            bytes32 hash;
+            if (fee == 0) {
+                hash = keccak256(recipients[i], values[i], transactionHashes[i]);
+            } else {
+                require(fee < values[i]);
+                hash = keccak256(recipients[i], values[i], transactionHashes[i], fee);
+            }
+            if (Helpers.addressArrayContains(deposits[hash], msg.sender)) {
+                continue;
+            }
+            confirmDeposit(recipients[i], values[i], transactionHashes[i], fee, hash);
+            confirmed++;
+        }
+        require(confirmed > 0);
+    }
+
+    /// records the confirmation of the deposit `hash` by `msg.sender`
+    /// and mints the tokens once `requiredSignatures` authorities confirmed it
+    function confirmDeposit(address recipient, uint256 value, bytes32 transactionHash, uint256 fee, bytes32 hash) internal {
         // don't allow authority to confirm deposit twice
         require(!Helpers.addressArrayContains(deposits[hash], msg.sender));
 
@@ -470,14 +797,37 @@ contract ForeignBridge {
             return;
         }
 
-        balances[recipient] += value;
-        // mints tokens
-        totalSupply += value;
+        uint256 valueRemainingAfterSubtractingFee = value - fee;
+        balances[recipient] += valueRemainingAfterSubtractingFee;
+        // mints tokens. the fee is minted once it is claimed
+        totalSupply += valueRemainingAfterSubtractingFee;
         // ERC20 specifies: a token contract which creates new tokens
         // SHOULD trigger a Transfer event with the _from address
         // set to 0x0 when tokens are created.
-        Transfer(0x0, recipient, value);
-        Deposit(recipient, value, transactionHash);
+        Transfer(0x0, recipient, valueRemainingAfterSubtractingFee);
+
+        // every confirming authority paid gas for the deposit.
+        // the authority completing the deposit gets the remainder of the division
+        uint256 share = fee / requiredSignatures;
+        for (uint256 i = 0; i < requiredSignatures; i++) {
+            relayFees[deposits[hash][i]] += share;
+        }
+        relayFees[msg.sender] += fee - share * requiredSignatures;
+
+        Deposit(recipient, valueRemainingAfterSubtractingFee, transactionHash);
+    }
+
+    /// mints the relay fees `msg.sender` earned into its balance.
+    /// authorities can then transfer the tokens to `home` via `transferHomeViaRelay`
+    /// to reimburse the gas they spend on relaying.
+    function claimRelayFees() public {
+        uint256 tokens = relayFees[msg.sender];
+        require(tokens > 0);
+        relayFees[msg.sender] = 0;
+        balances[msg.sender] += tokens;
+        totalSupply += tokens;
+        Transfer(0x0, msg.sender, tokens);
+        RelayFeesClaimed(msg.sender, tokens);
     }
 
     /// Transfer `value` from `msg.sender`s local balance (on `foreign` chain) to `recipient` on `home` chain.
@@ -515,11 +865,16 @@ contract ForeignBridge {
     /// withdrawal recipient (bytes20)
     /// withdrawal value (uint256)
     /// foreign transaction hash (bytes32) // to avoid transaction duplication
+    /// home gas price (uint256)
+    /// home bridge address (bytes20) // to avoid replays against other deployments
+    ///
+    /// version 2 messages prefix these fields with the version byte and the chain ids
+    /// of home and foreign (see message library above)
     function submitSignature(bytes signature, bytes message) public onlyAuthority() {
         // ensure that `signature` is really `message` signed by `msg.sender`
         require(msg.sender == MessageSigning.recoverAddressFromSignedMessage(signature, message));
 
-        require(message.length == 116);
+        require(Message.checkedFields(message, homeChainId, foreignChainId).length == 136);
         var hash = keccak256(message);
 
         // each authority can only provide one signature per message
@@ -545,4 +900,352 @@ contract ForeignBridge {
     function message(bytes32 hash) public view returns (bytes) {
         return signatures[hash].message;
     }
+
+    /// Request a call of `recipient` with `data` by `HomeBridge` on `home` chain.
+    ///
+    /// burns the relay cost `(gasLimit + estimatedGasCostOfWithdraw) * homeGasPrice`
+    /// from `msg.sender`s local balance.
+    /// emits a `UserRequestForCall` event which will be picked up by the bridge authorities.
+    /// bridge authorities will then sign off (by calling `submitCallSignature`) on a message
+    /// containing the call and the `hash` of the transaction on `foreign` containing the event.
+    /// once `requiredSignatures` are collected a `CollectedCallSignatures` event will be emitted.
+    /// an authority will pick up `CollectedCallSignatures` and call `HomeBridge.executeCall`.
+    /// `data` must begin with the 4 byte function selector.
+    function requestCall(address recipient, bytes data, uint256 gasLimit, uint256 homeGasPrice) public {
+        require(data.length >= 4);
+        // bounds keep the relay cost from overflowing
+        require(gasLimit < 2**64);
+        require(homeGasPrice < 2**128);
+
+        uint256 relayCost = (gasLimit + estimatedGasCostOfWithdraw) * homeGasPrice;
+        require(balances[msg.sender] >= relayCost);
+
+        balances[msg.sender] -= relayCost;
+        // burns tokens
+        totalSupply -= relayCost;
+        Transfer(msg.sender, 0x0, relayCost);
+        UserRequestForCall(msg.sender, recipient, data, gasLimit, homeGasPrice);
+    }
+
+    /// `submitSignature` for the messages of calls.
+    /// see call message library for their layout
+    function submitCallSignature(bytes signature, bytes message) public onlyAuthority() {
+        // ensure that `signature` is really `message` signed by `msg.sender`
+        require(msg.sender == MessageSigning.recoverAddressFromSignedMessage(signature, message));
+
+        require(CallMessage.isCall(message));
+        var hash = keccak256(message);
+
+        // each authority can only provide one signature per message
+        require(!Helpers.addressArrayContains(callSignatures[hash].signed, msg.sender));
+        callSignatures[hash].message = message;
+        callSignatures[hash].signed.push(msg.sender);
+        callSignatures[hash].signatures.push(signature);
+
+        if (callSignatures[hash].signed.length == requiredSignatures) {
+            CollectedCallSignatures(msg.sender, hash);
+        } else {
+            CallSignatureSubmitted(hash);
+        }
+    }
+
+    /// Get signature of a call
+    function callSignature(bytes32 hash, uint256 index) public view returns (bytes) {
+        return callSignatures[hash].signatures[index];
+    }
+
+    /// Get message of a call
+    function callMessage(bytes32 hash) public view returns (bytes) {
+        return callSignatures[hash].message;
+    }
+}
+
+
+/// the part of ERC20 the token bridges rely on.
+/// ERC20 spec: https://github.com/ethereum/EIPs/blob/master/EIPS/eip-20.md
+contract ERC20 {
+    function transfer(address to, uint256 tokens) public returns (bool);
+    function transferFrom(address from, address to, uint256 tokens) public returns (bool);
+}
+
+
+/// home side of a bridge between an existing ERC20 `token` on home
+/// and an existing ERC20 token on foreign (held by `ForeignTokenBridge`).
+/// holds the tokens transferred to foreign until they are withdrawn again.
+/// `withdraw` has the interface of `HomeBridge.withdraw`.
+contract HomeTokenBridge {
+    /// Number of authorities signatures required to withdraw tokens.
+    ///
+    /// Must be lesser than number of authorities.
+    uint256 public requiredSignatures;
+
+    /// Contract authorities.
+    address[] public authorities;
+
+    /// token held by this bridge
+    ERC20 public token;
+
+    /// chain ids version 2 messages must name. `0` if the deployment only accepts legacy messages
+    uint256 public homeChainId;
+    uint256 public foreignChainId;
+
+    /// Used foreign transaction hashes.
+    mapping (bytes32 => bool) public withdraws;
+
+    /// triggered when `value` tokens were transferred to this bridge for `recipient` on foreign
+    event TransferToForeign(address token, address recipient, uint256 value);
+
+    /// triggered when tokens were withdrawn
+    event Withdraw(address recipient, uint256 value, bytes32 transactionHash);
+
+    function HomeTokenBridge(
+        uint256 requiredSignaturesParam,
+        address[] authoritiesParam,
+        address tokenParam,
+        uint256 homeChainIdParam,
+        uint256 foreignChainIdParam
+    ) public
+    {
+        require(requiredSignaturesParam != 0);
+        require(requiredSignaturesParam <= authoritiesParam.length);
+        require(tokenParam != address(0));
+        requiredSignatures = requiredSignaturesParam;
+        authorities = authoritiesParam;
+        token = ERC20(tokenParam);
+        homeChainId = homeChainIdParam;
+        foreignChainId = foreignChainIdParam;
+    }
+
+    /// transfers `value` tokens of `msg.sender` to `recipient` on foreign.
+    /// `msg.sender` has to `approve` this bridge to transfer `value` tokens first.
+    function transferToForeign(address recipient, uint256 value) public {
+        // don't allow 0 value transfers to foreign
+        require(value > 0);
+        require(token.transferFrom(msg.sender, this, value));
+        TransferToForeign(token, recipient, value);
+    }
+
+    /// final step of a withdraw.
+    /// like `HomeBridge.withdraw` but transfers `value` tokens to `recipient`
+    /// and requires the message to name `token`.
+    /// the relaying authority isn't refunded the relay cost.
+    function withdraw(uint8[] vs, bytes32[] rs, bytes32[] ss, bytes message) public {
+        bytes memory fields = Message.checkedFields(message, homeChainId, foreignChainId);
+        require(fields.length == 156);
+        require(Message.getHomeBridge(fields) == address(this));
+        require(Message.getToken(fields) == address(token));
+
+        // check that at least `requiredSignatures` `authorities` have signed `message`
+        require(Helpers.hasEnoughValidSignatures(message, vs, rs, ss, authorities, requiredSignatures));
+
+        address recipient = Message.getRecipient(fields);
+        uint256 value = Message.getValue(fields);
+        bytes32 hash = Message.getTransactionHash(fields);
+        uint256 homeGasPrice = Message.getHomeGasPrice(fields);
+
+        // see `HomeBridge.withdraw`
+        require((recipient == msg.sender) || (tx.gasprice == homeGasPrice));
+
+        // Duplicated withdraw or reentry.
+        require(!withdraws[hash]);
+        withdraws[hash] = true;
+
+        require(token.transfer(recipient, value));
+
+        Withdraw(recipient, value, hash);
+    }
+}
+
+
+/// foreign side of a bridge between ERC20 tokens (see `HomeTokenBridge`).
+/// transfers `token` to the recipients of transfers to foreign once the authorities
+/// confirmed them and holds the tokens transferred to home.
+/// the authorities fund it with the tokens it transfers.
+/// collects the signatures of withdraws with the interface of `ForeignBridge`
+/// (`submitSignature`, `signature`, `message` and `CollectedSignatures`).
+contract ForeignTokenBridge {
+    struct SignaturesCollection {
+        /// Signed message.
+        bytes message;
+        /// Authorities who signed the message.
+        address[] signed;
+        /// Signatures
+        bytes[] signatures;
+    }
+
+    /// Number of authorities signatures required to withdraw tokens.
+    ///
+    /// Must be less than number of authorities.
+    uint256 public requiredSignatures;
+
+    /// Contract authorities.
+    address[] public authorities;
+
+    /// token held by this bridge
+    ERC20 public token;
+
+    /// chain ids version 2 messages must name. `0` if the deployment only accepts legacy messages
+    uint256 public homeChainId;
+    uint256 public foreignChainId;
+
+    /// Pending deposits and authorities who confirmed them
+    mapping (bytes32 => address[]) deposits;
+
+    /// Pending signatures and authorities who confirmed them
+    mapping (bytes32 => SignaturesCollection) signatures;
+
+    /// relay fees each authority earned by confirming deposits via `depositWithFee`
+    /// and hasn't claimed yet. the tokens stay in this bridge until they are claimed
+    mapping (address => uint256) public relayFees;
+
+    /// triggered when an authority confirms a deposit
+    event DepositConfirmation(address recipient, uint256 value, bytes32 transactionHash);
+
+    /// triggered when enough authorities have confirmed a deposit
+    event Deposit(address recipient, uint256 value, bytes32 transactionHash);
+
+    /// triggered when `value` tokens were transferred to this bridge for `recipient` on home
+    event TransferToHome(address token, address recipient, uint256 value, uint256 homeGasPrice);
+
+    /// triggered when an authority claims its relay fees
+    event RelayFeesClaimed(address authority, uint256 tokens);
+
+    event WithdrawSignatureSubmitted(bytes32 messageHash);
+
+    /// Collected signatures which should be relayed to home chain.
+    event CollectedSignatures(address authorityResponsibleForRelay, bytes32 messageHash);
+
+    function ForeignTokenBridge(
+        uint256 _requiredSignatures,
+        address[] _authorities,
+        address _token,
+        uint256 _homeChainId,
+        uint256 _foreignChainId
+    ) public
+    {
+        require(_requiredSignatures != 0);
+        require(_requiredSignatures <= _authorities.length);
+        require(_token != address(0));
+        requiredSignatures = _requiredSignatures;
+        authorities = _authorities;
+        token = ERC20(_token);
+        homeChainId = _homeChainId;
+        foreignChainId = _foreignChainId;
+    }
+
+    /// require that sender is an authority
+    modifier onlyAuthority() {
+        require(Helpers.addressArrayContains(authorities, msg.sender));
+        _;
+    }
+
+    /// confirms the transfer of `value` tokens to `recipient`
+    /// in the home transaction `transactionHash`.
+    /// transfers the tokens once `requiredSignatures` authorities confirmed it.
+    /// `tokenParam` must be `token` so authorities can't confirm for another token bridge
+    function deposit(address tokenParam, address recipient, uint256 value, bytes32 transactionHash) public onlyAuthority() {
+        require(tokenParam == address(token));
+        // Protection from misbehaving authority
+        var hash = keccak256(tokenParam, recipient, value, transactionHash);
+        confirmDeposit(recipient, value, transactionHash, 0, hash);
+    }
+
+    /// like `deposit` but `recipient` only gets `value - fee` tokens.
+    /// like `ForeignBridge.depositWithFee` `fee` is split among the authorities
+    /// that confirmed the deposit and confirmations only count towards the same deposit
+    /// if they name the same `fee`
+    function depositWithFee(address tokenParam, address recipient, uint256 value, bytes32 transactionHash, uint256 fee) public onlyAuthority() {
+        require(tokenParam == address(token));
+        require(fee < value);
+        // Protection from misbehaving authority
+        var hash = keccak256(tokenParam, recipient, value, transactionHash, fee);
+        confirmDeposit(recipient, value, transactionHash, fee, hash);
+    }
+
+    /// records the confirmation of the deposit `hash` by `msg.sender`
+    /// and transfers the tokens once `requiredSignatures` authorities confirmed it
+    function confirmDeposit(address recipient, uint256 value, bytes32 transactionHash, uint256 fee, bytes32 hash) internal {
+        // don't allow authority to confirm deposit twice
+        require(!Helpers.addressArrayContains(deposits[hash], msg.sender));
+
+        deposits[hash].push(msg.sender);
+
+        if (deposits[hash].length != requiredSignatures) {
+            DepositConfirmation(recipient, value, transactionHash);
+            return;
+        }
+
+        uint256 valueRemainingAfterSubtractingFee = value - fee;
+        require(token.transfer(recipient, valueRemainingAfterSubtractingFee));
+
+        // the authority completing the deposit gets the remainder of the division
+        uint256 share = fee / requiredSignatures;
+        for (uint256 i = 0; i < requiredSignatures; i++) {
+            relayFees[deposits[hash][i]] += share;
+        }
+        relayFees[msg.sender] += fee - share * requiredSignatures;
+
+        Deposit(recipient, valueRemainingAfterSubtractingFee, transactionHash);
+    }
+
+    /// transfers the relay fees `msg.sender` earned to `msg.sender`
+    function claimRelayFees() public {
+        uint256 tokens = relayFees[msg.sender];
+        require(tokens > 0);
+        relayFees[msg.sender] = 0;
+        require(token.transfer(msg.sender, tokens));
+        RelayFeesClaimed(msg.sender, tokens);
+    }
+
+    /// transfers the relay fees `msg.sender` earned to `recipient` on home
+    /// like `transferToHome` without moving the tokens, which this bridge already holds
+    function transferRelayFeesHome(address recipient, uint256 homeGasPrice) public {
+        uint256 tokens = relayFees[msg.sender];
+        require(tokens > 0);
+        relayFees[msg.sender] = 0;
+        RelayFeesClaimed(msg.sender, tokens);
+        TransferToHome(token, recipient, tokens, homeGasPrice);
+    }
+
+    /// transfers `value` tokens of `msg.sender` to `recipient` on home.
+    /// `msg.sender` has to `approve` this bridge to transfer `value` tokens first.
+    /// like `ForeignBridge.transferHomeViaRelay` the authorities then sign off on the withdraw.
+    function transferToHome(address recipient, uint256 value, uint256 homeGasPrice) public {
+        // don't allow 0 value transfers to home
+        require(value > 0);
+        require(token.transferFrom(msg.sender, this, value));
+        TransferToHome(token, recipient, value, homeGasPrice);
+    }
+
+    /// like `ForeignBridge.submitSignature` for messages of the token bridges
+    /// which name the token on home after the fields of `ForeignBridge` messages
+    function submitSignature(bytes signature, bytes message) public onlyAuthority() {
+        // ensure that `signature` is really `message` signed by `msg.sender`
+        require(msg.sender == MessageSigning.recoverAddressFromSignedMessage(signature, message));
+
+        require(Message.checkedFields(message, homeChainId, foreignChainId).length == 156);
+        var hash = keccak256(message);
+
+        // each authority can only provide one signature per message
+        require(!Helpers.addressArrayContains(signatures[hash].signed, msg.sender));
+        signatures[hash].message = message;
+        signatures[hash].signed.push(msg.sender);
+        signatures[hash].signatures.push(signature);
+
+        if (signatures[hash].signed.length == requiredSignatures) {
+            CollectedSignatures(msg.sender, hash);
+        } else {
+            WithdrawSignatureSubmitted(hash);
+        }
+    }
+
+    /// Get signature
+    function signature(bytes32 hash, uint256 index) public view returns (bytes) {
+        return signatures[hash].signatures[index];
+    }
+
+    /// Get message
+    function message(bytes32 hash) public view returns (bytes) {
+        return signatures[hash].message;
+    }
 }
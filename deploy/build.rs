@@ -1,6 +1,29 @@
+extern crate crypto;
+
+use std::fs;
+use std::io::Read;
 use std::process::Command;
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+
+/// contract source that `parity-bridge-deploy` deploys the compilation artifacts of
+const SOURCE: &str = "../contracts/bridge.sol";
+/// checked in compilation artifacts of `SOURCE` (see `bridge/build.rs`)
+const VENDORED_DIR: &str = "../contracts/compiled";
+
+fn read(path: &str) -> Option<String> {
+    let mut content = String::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .ok()
+        .map(|_| content.trim().to_owned())
+}
 
 fn main() {
+    println!("cargo:rerun-if-changed={}", SOURCE);
+    println!("cargo:rerun-if-changed={}/source_hash", VENDORED_DIR);
+    println!("cargo:rerun-if-changed={}/solc_version", VENDORED_DIR);
+
     // make last git commit hash (`git rev-parse HEAD`)
     // available via `env!("GIT_HASH")` in sources
     let output = Command::new("git")
@@ -9,4 +32,26 @@ fn main() {
         .unwrap();
     let git_hash = String::from_utf8(output.stdout).unwrap();
     println!("cargo:rustc-env=GIT_HASH={}", git_hash);
+
+    // deployments use the checked in artifacts. warn if they don't match the source
+    let source = fs::read(SOURCE).unwrap_or_else(|err| panic!("cannot read `{}`: {}", SOURCE, err));
+    let mut hasher = Sha256::new();
+    hasher.input(&source);
+    match read(&format!("{}/source_hash", VENDORED_DIR)) {
+        Some(ref hash) if *hash == hasher.result_str() => {}
+        Some(_) => println!(
+            "cargo:warning=`{}` changed since the artifacts in `{}` were compiled. run `tools/compile_contracts.sh` before deploying",
+            SOURCE, VENDORED_DIR
+        ),
+        None => println!(
+            "cargo:warning=`{}` has no artifacts to deploy. run `tools/compile_contracts.sh`",
+            VENDORED_DIR
+        ),
+    }
+
+    // make solc version of the checked in artifacts
+    // available via `env!("CONTRACTS_SOLC_VERSION")` in sources
+    let solc_version =
+        read(&format!("{}/solc_version", VENDORED_DIR)).unwrap_or_else(|| "unknown".into());
+    println!("cargo:rustc-env=CONTRACTS_SOLC_VERSION={}", solc_version);
 }
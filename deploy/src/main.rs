@@ -20,11 +20,14 @@ use bridge::bridge::{DeployForeign, DeployHome};
 use bridge::config::Config;
 use bridge::error::Error;
 use bridge::database::Database;
+use bridge::manifest::{self, Manifest};
 
 #[derive(Debug, Deserialize)]
 pub struct Args {
     arg_config: PathBuf,
     arg_database: PathBuf,
+    cmd_verify: bool,
+    flag_manifest: PathBuf,
 }
 
 fn main() {
@@ -56,16 +59,20 @@ Parity-bridge
     Copyright 2017 Parity Technologies (UK) Limited
     Version: {}
     Commit: {}
+    Contracts: {}
 
 Usage:
-    parity-bridge-deploy --config <config> --database <database>
+    parity-bridge-deploy --config <config> --database <database> [--manifest <manifest>]
+    parity-bridge-deploy verify --config <config> --database <database> [--manifest <manifest>]
     parity-bridge-deploy -h | --help
 
 Options:
-    -h, --help           Display help message and exit.
+    -h, --help             Display help message and exit.
+    --manifest <manifest>  Deployment manifest that is written by a deployment and verified by `verify` [default: deployment.toml].
 "#,
         env!("CARGO_PKG_VERSION"),
-        env!("GIT_HASH")
+        env!("GIT_HASH"),
+        env!("CONTRACTS_SOLC_VERSION")
     );
 
     info!(target: "parity-bridge-deploy", "Parsing cli arguments");
@@ -79,10 +86,30 @@ Options:
     info!(target: "parity-bridge-deploy", "Starting event loop");
     let mut event_loop = Core::new().unwrap();
 
-    info!(target: "parity-bridge-deploy", "Establishing ipc connection");
-    let app = App::new_ipc(config, &args.arg_database, &event_loop.handle())?;
+    info!(target: "parity-bridge-deploy", "Connecting to nodes");
+    let mut app = App::new(config, &args.arg_database, &event_loop.handle())?;
+
+    info!(target: "parity-bridge-deploy", "Resolving ENS names");
+    app.resolve_ens_names(&mut event_loop)?;
     let app_ref = Arc::new(app.as_ref());
 
+    if args.cmd_verify {
+        info!(target: "parity-bridge-deploy", "Loading manifest");
+        let manifest = Manifest::load(&args.flag_manifest)?;
+        if args.arg_database.exists() {
+            manifest.check_database(&Database::load(&args.arg_database)?)?;
+        }
+        info!(target: "parity-bridge-deploy", "Verifying deployment");
+        manifest::verify(&mut event_loop, &app_ref, &manifest)?;
+        return Ok(format!(
+            "verified {} at {:?} on home and {} at {:?} on foreign",
+            manifest.home.contract_name,
+            manifest.home.contract_address,
+            manifest.foreign.contract_name,
+            manifest.foreign.contract_address
+        ));
+    }
+
     info!(target: "parity-bridge-deploy", "Deploying HomeBridge contract");
     let home_deployed = event_loop.run(DeployHome::new(app_ref.clone()))?;
     home_deployed.dump_info(format!(
@@ -101,5 +128,14 @@ Options:
     info!(target: "parity-bridge-deploy", "\n\n{}\n", database);
     database.save(fs::File::create(&app_ref.database_path)?)?;
 
+    let manifest = Manifest::from_deployments(
+        &mut event_loop,
+        &app_ref,
+        &home_deployed,
+        &foreign_deployed,
+    )?;
+    info!(target: "parity-bridge-deploy", "\n\n{}\n", manifest);
+    manifest.save(fs::File::create(&args.flag_manifest)?)?;
+
     Ok("Done".into())
 }
@@ -114,16 +114,22 @@ macro_rules! test_app_stream {
 			use self::futures::{Future, Stream};
 			use self::ethereum_types::U256;
 			use self::bridge::app::{App, Connections};
-			use self::bridge::contracts::{foreign, home};
+			use self::bridge::contracts::{foreign, home, ContractVersion};
 			use self::bridge::config::{
                 Config,
                 Authorities,
                 Node,
+                NodeEndpoint,
                 ContractConfig,
                 Transactions,
-                TransactionConfig
+                TransactionConfig,
+                Role,
+                LogFormat,
+                MessageVersion,
+                TlsConfig
             };
 			use self::bridge::database::Database;
+			use self::bridge::api::ConfirmationStrategy;
 
 			let home = $crate::MockedTransport {
 				requests: Default::default(),
@@ -151,23 +157,67 @@ macro_rules! test_app_stream {
 				txs: $txs,
 				home: Node {
 					account: $home_acc.parse().unwrap(),
-					ipc: "".into(),
+					endpoint: NodeEndpoint::Ipc("".into()),
 					contract: ContractConfig {
 						bin: Default::default(),
 					},
 					poll_interval: Duration::from_secs(0),
+					max_poll_interval: Duration::from_secs(0),
+					subscribe_new_heads: false,
 					request_timeout: Duration::from_secs(5),
+					tls: TlsConfig::default(),
+					compression: false,
+					compress_requests: false,
+					relay_deadline: Duration::from_secs(60),
+					max_relay_attempts: 20,
+					max_submission_delay: Duration::from_millis(0),
 					required_confirmations: $home_conf,
+					confirmation_strategy: ConfirmationStrategy::Depth,
+					reorg_checkpoints: 0,
+					chain_id: None,
+					access_lists: false,
+					transaction_type: None,
+					registry: None,
+					maintenance_windows: vec![],
+					max_requests_per_second: None,
+					fallback_accounts: vec![],
+					token_address: None,
+					gas_price_oracle: None,
+					stuck_transactions: None,
+					balance_watchdog: None,
+					retry: Default::default(),
 				},
 				foreign: Node {
 					account: $foreign_acc.parse().unwrap(),
-					ipc: "".into(),
+					endpoint: NodeEndpoint::Ipc("".into()),
 					contract: ContractConfig {
 						bin: Default::default(),
 					},
 					poll_interval: Duration::from_secs(0),
+					max_poll_interval: Duration::from_secs(0),
+					subscribe_new_heads: false,
 					request_timeout: Duration::from_secs(5),
+					tls: TlsConfig::default(),
+					compression: false,
+					compress_requests: false,
+					relay_deadline: Duration::from_secs(60),
+					max_relay_attempts: 20,
+					max_submission_delay: Duration::from_millis(0),
 					required_confirmations: $foreign_conf,
+					confirmation_strategy: ConfirmationStrategy::Depth,
+					reorg_checkpoints: 0,
+					chain_id: None,
+					access_lists: false,
+					transaction_type: None,
+					registry: None,
+					maintenance_windows: vec![],
+					max_requests_per_second: None,
+					fallback_accounts: vec![],
+					token_address: None,
+					gas_price_oracle: None,
+					stuck_transactions: None,
+					balance_watchdog: None,
+					retry: Default::default(),
 				},
 				authorities: Authorities {
 					accounts: $authorities_accs.iter().map(|a: &&str| a.parse().unwrap()).collect(),
@@ -176,6 +226,34 @@ macro_rules! test_app_stream {
 				estimated_gas_cost_of_withdraw: U256::from_dec_str("100000").unwrap(),
 				max_total_home_contract_balance: U256::from_dec_str("10000000000000000000").unwrap(),
 				max_single_deposit_value: U256::from_dec_str("1000000000000000000").unwrap(),
+				contract_version: ContractVersion::V1,
+				message_version: MessageVersion::V1,
+				deployment_manifest: None,
+				ens_registry: Default::default(),
+				ens_names: vec![],
+				pause_files: Default::default(),
+				value_limits: Default::default(),
+				unaccounted_transfers: None,
+				message_calls: None,
+				solvency_check: false,
+				metrics: None,
+				signature_audit_log: None,
+				verify_signatures: false,
+				relay_history: None,
+				event_queue: None,
+				postgres: None,
+				sqlite: None,
+				otlp: None,
+				http_api: None,
+				role: Role::Full,
+				log_format: LogFormat::Text,
+				signer: None,
+				keystore: None,
+				shard: None,
+				clock: Default::default(),
+				max_lag: None,
+				token_fee: None,
+				reverted_relays: Default::default(),
 			};
 
 			let app = App {
@@ -188,6 +266,17 @@ macro_rules! test_app_stream {
 				home_bridge: home::HomeBridge::default(),
 				foreign_bridge: foreign::ForeignBridge::default(),
 				timer: Default::default(),
+				heads: Default::default(),
+				checkpoints: Default::default(),
+				gas_price_oracles: Default::default(),
+				keystore: None,
+				sqlite: None,
+				nonces: Default::default(),
+				value_limits: Default::default(),
+				journal: Default::default(),
+				parked: Default::default(),
+				leases: Default::default(),
+				events: None,
 			};
 
 			let app = Arc::new(app);
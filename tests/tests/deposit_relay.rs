@@ -111,6 +111,15 @@ test_app_stream! {
             res => json!([]);
     ],
     foreign_transport => [
+        "eth_call" =>
+            req => json!([{
+                "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x0",
+                "gasPrice": "0x0",
+                "to": "0x0000000000000000000000000000000000000000"
+            }, "pending"]),
+            res => json!("0x");
         "eth_sendTransaction" =>
             req => json!([{
                 "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
@@ -145,6 +154,7 @@ test_app_stream! {
         deposit_relay: TransactionConfig {
             gas: 0xfd,
             gas_price: 0xa0,
+            ..Default::default()
         },
         ..Default::default()
     },
@@ -169,6 +179,15 @@ test_app_stream! {
             }]);
     ],
     foreign_transport => [
+        "eth_call" =>
+            req => json!([{
+                "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0xfd",
+                "gasPrice": "0xa0",
+                "to": "0x0000000000000000000000000000000000000000"
+            }, "pending"]),
+            res => json!("0x");
         "eth_sendTransaction" =>
             req => json!([{
                 "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
@@ -224,6 +243,15 @@ test_app_stream! {
             }]);
     ],
     foreign_transport => [
+        "eth_call" =>
+            req => json!([{
+                "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x0",
+                "gasPrice": "0x0",
+                "to": "0x0000000000000000000000000000000000000dd1"
+            }, "pending"]),
+            res => json!("0x");
         "eth_sendTransaction" =>
             req => json!([{
                 "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
@@ -279,6 +307,15 @@ test_app_stream! {
             }]);
     ],
     foreign_transport => [
+        "eth_call" =>
+            req => json!([{
+                "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+                "from": "0x00000000000000000000000000000000000000ee",
+                "gas": "0x0",
+                "gasPrice": "0x0",
+                "to":"0x0000000000000000000000000000000000000dd1"
+            }, "pending"]),
+            res => json!("0x");
         "eth_sendTransaction" =>
             req => json!([{
                 "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
@@ -339,6 +376,24 @@ test_app_stream! {
             ]);
     ],
     foreign_transport => [
+        "eth_call" =>
+            req => json!([{
+                "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x0",
+                "gasPrice": "0x0",
+                "to": "0x0000000000000000000000000000000000000000"
+            }, "pending"]),
+            res => json!("0x");
+        "eth_call" =>
+            req => json!([{
+                "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a942436f",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x0",
+                "gasPrice": "0x0",
+                "to": "0x0000000000000000000000000000000000000000"
+            }, "pending"]),
+            res => json!("0x");
         "eth_sendTransaction" =>
             req => json!([{
                 "data": "0x26b3293f000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
@@ -359,3 +414,77 @@ test_app_stream! {
             res => json!("0x1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b");
     ]
 }
+
+test_app_stream! {
+    name => deposit_relay_batch,
+    database => Database::default(),
+    home =>
+        account => "0000000000000000000000000000000000000001",
+        confirmations => 12;
+    foreign =>
+        account => "0000000000000000000000000000000000000001",
+        confirmations => 12;
+    authorities =>
+        accounts => [
+            "0000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000002",
+        ],
+        signatures => 1;
+    txs => Transactions {
+        deposit_relay: TransactionConfig {
+            gas: 0xfd,
+            gas_price: 0xa0,
+            ..Default::default()
+        },
+        max_batch_size: Some(10),
+        ..Default::default()
+    },
+    init => |app, db| create_deposit_relay(app, db).take(1),
+    expected => vec![0x1005],
+    home_transport => [
+        "eth_blockNumber" =>
+            req => json!([]),
+            res => json!("0x1011");
+        "eth_getLogs" =>
+            req => json!([{
+                "address": ["0x0000000000000000000000000000000000000000"],
+                "fromBlock": "0x1",
+                "limit": null,
+                "toBlock": "0x1005",
+                "topics": [[DEPOSIT_TOPIC], null, null, null]
+            }]),
+            res => json!([{
+                "address": "0x0000000000000000000000000000000000000000",
+                "topics": [DEPOSIT_TOPIC],
+                "data": "0x000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000f0",
+                "type": "",
+                "transactionHash": "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364"
+            }, {
+                "address": "0x0000000000000000000000000000000000000000",
+                "topics": [DEPOSIT_TOPIC],
+                "data": "0x00000000000000000000000000000000000000000000000000000000000000aa0000000000000000000000000000000000000000000000000000000000000100",
+                "type": "",
+                "transactionHash": "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408"
+            }]);
+    ],
+    foreign_transport => [
+        "eth_call" =>
+            req => json!([{
+                "data": "0xf09fafc0000000000000000000000000000000000000000000000000000000000000008000000000000000000000000000000000000000000000000000000000000000e0000000000000000000000000000000000000000000000000000000000000014000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000aa000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000f000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000002884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364e55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x1fa",
+                "gasPrice": "0xa0",
+                "to": "0x0000000000000000000000000000000000000000"
+            }, "pending"]),
+            res => json!("0x");
+        "eth_sendTransaction" =>
+            req => json!([{
+                "data": "0xf09fafc0000000000000000000000000000000000000000000000000000000000000008000000000000000000000000000000000000000000000000000000000000000e0000000000000000000000000000000000000000000000000000000000000014000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002000000000000000000000000aff3454fce5edbc8cca8697c15331677e6ebcccc00000000000000000000000000000000000000000000000000000000000000aa000000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000f000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000000000000000000000000000000000002884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364e55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408",
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x1fa",
+                "gasPrice": "0xa0",
+                "to": "0x0000000000000000000000000000000000000000"
+            }]),
+            res => json!("0x1db8f385535c0d178b8f40016048f3a3cffee8f94e68978ea4b277f57b638f0b");
+    ]
+}
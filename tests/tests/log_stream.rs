@@ -9,7 +9,8 @@ extern crate web3;
 
 use std::time::Duration;
 use web3::types::{FilterBuilder, H160, H256, Log};
-use bridge::api::{log_stream, LogStreamInit, LogStreamItem};
+use bridge::api::{log_stream, ConfirmationStrategy, LogStreamInit, LogStreamItem};
+use bridge::checkpoints::Checkpoints;
 
 test_transport_stream! {
     name => log_stream_basic,
@@ -20,6 +21,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 10,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(2)
@@ -71,6 +73,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 10,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(2)
@@ -125,6 +128,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 10,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(1)
@@ -163,6 +167,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 0,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(3)
@@ -230,6 +235,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 0,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(2)
@@ -278,6 +284,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 0,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(2)
@@ -326,6 +333,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 10,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(1)
@@ -369,6 +377,7 @@ test_transport_stream! {
             poll_interval: Duration::from_secs(0),
             request_timeout: Duration::from_secs(5),
             confirmations: 10,
+            strategy: ConfirmationStrategy::Depth,
         };
 
         log_stream(transport, Default::default(), init).take(3)
@@ -459,3 +468,379 @@ test_transport_stream! {
             }
         ]);
 }
+
+test_transport_stream! {
+    name => log_stream_pinned_hash_refetches_replaced_block,
+    init => |transport| {
+        let init = LogStreamInit {
+            after: 10,
+            filter: FilterBuilder::default(),
+            poll_interval: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(5),
+            confirmations: 10,
+            strategy: ConfirmationStrategy::PinnedHash,
+        };
+
+        log_stream(transport, Default::default(), init).take(1)
+    },
+    expected => vec![LogStreamItem {
+        from: 0xb,
+        to: 0x1006,
+        logs: vec![],
+    }],
+    "eth_blockNumber" =>
+        req => json!([]),
+        res => json!("0x1010");
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1006",
+            "topics": null
+        }]),
+        res => json!([]);
+    // block 0x1006 was replaced while the logs were fetched
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "timestamp": "0x5a50e781"
+        });
+    "eth_blockNumber" =>
+        req => json!([]),
+        res => json!("0x1010");
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "timestamp": "0x5a50e781"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1006",
+            "topics": null
+        }]),
+        res => json!([]);
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "timestamp": "0x5a50e781"
+        });
+}
+
+test_transport_stream! {
+    name => log_stream_pinned_hash_refetches_logs_of_replaced_ancestor,
+    init => |transport| {
+        let init = LogStreamInit {
+            after: 10,
+            filter: FilterBuilder::default(),
+            poll_interval: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(5),
+            confirmations: 10,
+            strategy: ConfirmationStrategy::PinnedHash,
+        };
+
+        log_stream(transport, Default::default(), init).take(1)
+    },
+    expected => vec![LogStreamItem {
+        from: 0xb,
+        to: 0x1006,
+        logs: vec![Log {
+            address: "0000000000000000000000000000000000000001".into(),
+            topics: vec![],
+            data: vec![0x10].into(),
+            block_hash: Some(
+                "3333333333333333333333333333333333333333333333333333333333333333".into(),
+            ),
+            block_number: Some(0x1005.into()),
+            log_type: "".into(),
+            ..Default::default()
+        }],
+    }],
+    "eth_blockNumber" =>
+        req => json!([]),
+        res => json!("0x1010");
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "parentHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "timestamp": "0x5a50e780"
+        });
+    // the node served the log of a block replaced below the pinned block
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1006",
+            "topics": null
+        }]),
+        res => json!([{
+            "address": "0x0000000000000000000000000000000000000001",
+            "topics": [],
+            "data": "0x10",
+            "blockHash": "0x4444444444444444444444444444444444444444444444444444444444444444",
+            "blockNumber": "0x1005",
+            "type": ""
+        }]);
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1005", false]),
+        res => json!({
+            "hash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "parentHash": "0x5555555555555555555555555555555555555555555555555555555555555555",
+            "timestamp": "0x5a50e770"
+        });
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "parentHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_blockNumber" =>
+        req => json!([]),
+        res => json!("0x1010");
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "parentHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1006",
+            "topics": null
+        }]),
+        res => json!([{
+            "address": "0x0000000000000000000000000000000000000001",
+            "topics": [],
+            "data": "0x10",
+            "blockHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "blockNumber": "0x1005",
+            "type": ""
+        }]);
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1005", false]),
+        res => json!({
+            "hash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "parentHash": "0x5555555555555555555555555555555555555555555555555555555555555555",
+            "timestamp": "0x5a50e770"
+        });
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "parentHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "timestamp": "0x5a50e780"
+        });
+}
+
+test_transport_stream! {
+    name => log_stream_finalized,
+    init => |transport| {
+        let init = LogStreamInit {
+            after: 10,
+            filter: FilterBuilder::default(),
+            poll_interval: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(5),
+            confirmations: 10,
+            strategy: ConfirmationStrategy::Finalized,
+        };
+
+        log_stream(transport, Default::default(), init).take(2)
+    },
+    // `confirmations` don't apply to the finalized block
+    expected => vec![LogStreamItem {
+        from: 0xb,
+        to: 0x1006,
+        logs: vec![],
+    }, LogStreamItem {
+        from: 0x1007,
+        to: 0x1020,
+        logs: vec![],
+    }],
+    "eth_getBlockByNumber" =>
+        req => json!(["finalized", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "number": "0x1006",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1006",
+            "topics": null
+        }]),
+        res => json!([]);
+    // nothing was finalized since
+    "eth_getBlockByNumber" =>
+        req => json!(["finalized", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "number": "0x1006",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_getBlockByNumber" =>
+        req => json!(["finalized", false]),
+        res => json!({
+            "hash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "number": "0x1020",
+            "timestamp": "0x5a50e900"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0x1007",
+            "limit": null,
+            "toBlock": "0x1020",
+            "topics": null
+        }]),
+        res => json!([]);
+}
+
+test_transport_stream! {
+    name => log_stream_safe,
+    init => |transport| {
+        let init = LogStreamInit {
+            after: 10,
+            filter: FilterBuilder::default(),
+            poll_interval: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(5),
+            confirmations: 0,
+            strategy: ConfirmationStrategy::Safe,
+        };
+
+        log_stream(transport, Default::default(), init).take(1)
+    },
+    expected => vec![LogStreamItem {
+        from: 0xb,
+        to: 0x1008,
+        logs: vec![],
+    }],
+    "eth_getBlockByNumber" =>
+        req => json!(["safe", false]),
+        res => json!({
+            "hash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "number": "0x1008",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1008",
+            "topics": null
+        }]),
+        res => json!([]);
+}
+
+test_transport_stream! {
+    name => log_stream_without_delay,
+    init => |transport| {
+        let init = LogStreamInit {
+            after: 10,
+            filter: FilterBuilder::default(),
+            poll_interval: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(5),
+            confirmations: 10,
+            strategy: ConfirmationStrategy::Delay(Duration::from_secs(0)),
+        };
+
+        log_stream(transport, Default::default(), init).take(1)
+    },
+    // every block is confirmed once it is seen
+    expected => vec![LogStreamItem {
+        from: 0xb,
+        to: 0x1010,
+        logs: vec![],
+    }],
+    "eth_blockNumber" =>
+        req => json!([]),
+        res => json!("0x1010");
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0xb",
+            "limit": null,
+            "toBlock": "0x1010",
+            "topics": null
+        }]),
+        res => json!([]);
+}
+
+test_transport_stream! {
+    name => log_stream_rewinds_to_canonical_checkpoint,
+    init => |transport| {
+        let init = LogStreamInit {
+            after: 10,
+            filter: FilterBuilder::default(),
+            poll_interval: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(5),
+            confirmations: 10,
+            strategy: ConfirmationStrategy::Depth,
+        };
+        let checkpoints = Checkpoints::new(4);
+        checkpoints.seed(5, Some(H256::from(0x11)));
+        checkpoints.push(10, H256::from(0x22));
+
+        log_stream(transport, Default::default(), init)
+            .rewinding(&checkpoints)
+            .take(1)
+    },
+    expected => vec![LogStreamItem {
+        from: 6,
+        to: 0x1006,
+        logs: vec![],
+    }],
+    "eth_blockNumber" =>
+        req => json!([]),
+        res => json!("0x1010");
+    // block 10 of the latest checkpoint was replaced
+    "eth_getBlockByNumber" =>
+        req => json!(["0xa", false]),
+        res => json!({
+            "hash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+            "timestamp": "0x5a50e780"
+        });
+    "eth_getBlockByNumber" =>
+        req => json!(["0x5", false]),
+        res => json!({
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000011",
+            "timestamp": "0x5a50e770"
+        });
+    "eth_getBlockByNumber" =>
+        req => json!(["0x1006", false]),
+        res => json!({
+            "hash": "0x4444444444444444444444444444444444444444444444444444444444444444",
+            "timestamp": "0x5a50e790"
+        });
+    "eth_getLogs" =>
+        req => json!([{
+            "address": null,
+            "fromBlock": "0x6",
+            "limit": null,
+            "toBlock": "0x1006",
+            "topics": null
+        }]),
+        res => json!([]);
+}
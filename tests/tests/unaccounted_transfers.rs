@@ -0,0 +1,73 @@
+/// test interactions of unaccounted_transfers state machine with RPC
+extern crate bridge;
+extern crate ethereum_types;
+extern crate futures;
+#[macro_use]
+extern crate serde_json;
+#[macro_use]
+extern crate tests;
+
+use bridge::bridge::create_unaccounted_transfers;
+
+test_app_stream! {
+    name => unaccounted_transfers_basic,
+    database => Database {
+        home_contract_address: "00000000000000000000000000000000000000dd".into(),
+        home_deploy: 0x10,
+        ..Database::default()
+    },
+    home =>
+        account => "0000000000000000000000000000000000000001",
+        confirmations => 12;
+    foreign =>
+        account => "0000000000000000000000000000000000000001",
+        confirmations => 12;
+    authorities =>
+        accounts => [
+            "0000000000000000000000000000000000000001",
+        ],
+        signatures => 1;
+    txs => Transactions::default(),
+    init => |app, db| create_unaccounted_transfers(app, db).take(2),
+    expected => vec![0x1005, 0x1006],
+    home_transport => [
+        "eth_blockNumber" =>
+            req => json!([]),
+            res => json!("0x1011");
+        "trace_filter" =>
+            req => json!([{
+                "fromBlock": "0x11",
+                "toBlock": "0x1005",
+                "toAddress": ["0x00000000000000000000000000000000000000dd"]
+            }]),
+            res => json!([{
+                "action": {
+                    "address": "0x00000000000000000000000000000000000000aa",
+                    "refundAddress": "0x00000000000000000000000000000000000000dd",
+                    "balance": "0x64"
+                },
+                "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+                "blockNumber": 0x1000,
+                "result": null,
+                "subtraces": 0,
+                "traceAddress": [0],
+                "transactionHash": "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+                "transactionPosition": 0,
+                "type": "suicide"
+            }]);
+        "eth_blockNumber" =>
+            req => json!([]),
+            res => json!("0x1011");
+        "eth_blockNumber" =>
+            req => json!([]),
+            res => json!("0x1012");
+        "trace_filter" =>
+            req => json!([{
+                "fromBlock": "0x1006",
+                "toBlock": "0x1006",
+                "toAddress": ["0x00000000000000000000000000000000000000dd"]
+            }]),
+            res => json!([]);
+    ],
+    foreign_transport => []
+}
@@ -195,6 +195,7 @@ test_app_stream! {
         withdraw_confirm: TransactionConfig {
             gas: 0xfe,
             gas_price: 0xa1,
+            ..Default::default()
         },
         ..Default::default()
     },
@@ -235,6 +236,8 @@ test_app_stream! {
                     value: 10000.into(),
                     sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                     mainnet_gas_price: 1000.into(),
+                    mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                    chain_ids: None,
                 }
                 .to_bytes()
                 .to_hex())
@@ -253,6 +256,8 @@ test_app_stream! {
                             value: 10000.into(),
                             sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                             mainnet_gas_price: 1000.into(),
+                            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                            chain_ids: None,
                         }.to_bytes()
                     )
                     .to_hex()),
@@ -300,6 +305,7 @@ test_app_stream! {
         withdraw_confirm: TransactionConfig {
             gas: 0xff,
             gas_price: 0xaa,
+            ..Default::default()
         },
         ..Default::default()
     },
@@ -347,6 +353,8 @@ test_app_stream! {
                     value: 10000.into(),
                     sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                     mainnet_gas_price: 1000.into(),
+                    mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                    chain_ids: None,
                 }
                 .to_bytes()
                 .to_hex())
@@ -360,6 +368,8 @@ test_app_stream! {
                     value: 42.into(),
                     sidenet_transaction_hash: "0xfffedad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                     mainnet_gas_price: 100.into(),
+                    mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                    chain_ids: None,
                 }
                 .to_bytes()
                 .to_hex())
@@ -378,6 +388,8 @@ test_app_stream! {
                             value: 10000.into(),
                             sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                             mainnet_gas_price: 1000.into(),
+                            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                            chain_ids: None,
                         }.to_bytes()
                     )
                     .to_hex()),
@@ -400,6 +412,8 @@ test_app_stream! {
                             value: 42.into(),
                             sidenet_transaction_hash: "0xfffedad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                             mainnet_gas_price: 100.into(),
+                            mainnet_bridge_address: "0x49edf201c1e139282643d5e7c6fb0c7219ad1db7".into(),
+                            chain_ids: None,
                         }.to_bytes()
                     )
                     .to_hex()),
@@ -141,6 +141,31 @@ test_app_stream! {
     init => |app, db| create_withdraw_relay(app, db).take(1),
     expected => vec![0x1005],
     home_transport => [
+        // simulation of `HomeBridge.withdraw`
+        "eth_call" =>
+            req => json!([{
+                "data": format!("0x{}", contracts::home::HomeBridge::default()
+                    .functions()
+                    .withdraw()
+                    .input(
+                        vec![U256::from(1), U256::from(4)],
+                        vec![H256::from(2), H256::from(5)],
+                        vec![H256::from(3), H256::from(6)],
+                        MessageToMainnet {
+                            recipient: [1u8; 20].into(),
+                            value: 10000.into(),
+                            sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
+                            mainnet_gas_price: 1000.into(),
+                            mainnet_bridge_address: "0x00000000000000000000000000000000000000dd".into(),
+                            chain_ids: None,
+                        }.to_bytes()
+                    ).to_hex()),
+                "from": "0x0000000000000000000000000000000000000001",
+                "gas": "0x0",
+                "gasPrice": "0x3e8",
+                "to": "0x00000000000000000000000000000000000000dd"
+            }, "pending"]),
+            res => json!("0x");
         // `HomeBridge.withdraw`
         "eth_sendTransaction" =>
             req => json!([{
@@ -156,6 +181,8 @@ test_app_stream! {
                             value: 10000.into(),
                             sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                             mainnet_gas_price: 1000.into(),
+                            mainnet_bridge_address: "0x00000000000000000000000000000000000000dd".into(),
+                            chain_ids: None,
                         }.to_bytes()
                     ).to_hex()),
                 "from": "0x0000000000000000000000000000000000000001",
@@ -195,6 +222,8 @@ test_app_stream! {
                 value: 10000.into(),
                 sidenet_transaction_hash: "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364".into(),
                 mainnet_gas_price: 1000.into(),
+                mainnet_bridge_address: "0x00000000000000000000000000000000000000dd".into(),
+                chain_ids: None,
             }.to_payload().to_hex()));
         // calls to `signature`
         "eth_call" =>
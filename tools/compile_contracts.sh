@@ -1,9 +1,17 @@
 #!/usr/bin/env bash
+# compiles contracts/bridge.sol into the artifacts checked in at contracts/compiled
+# which the build of the bridge uses instead of invoking solc.
+# run from the root of the repository after every change of contracts/bridge.sol
+
+set -e
 
 solc \
   --abi \
   --bin \
   --optimize \
-  --output-dir compiled_contracts \
+  --output-dir contracts/compiled \
   --overwrite \
   contracts/bridge.sol
+
+sha256sum contracts/bridge.sol | cut -d ' ' -f 1 > contracts/compiled/source_hash
+solc --version | tail -n 1 > contracts/compiled/solc_version
@@ -2,13 +2,16 @@ var ForeignBridge = artifacts.require("ForeignBridge");
 var helpers = require("./helpers/helpers");
 
 contract('ForeignBridge', function(accounts) {
+  // `ForeignBridge` doesn't interpret the `HomeBridge` a message is meant for
+  var homeBridgeAddress = "0x00000000000000000000000000000000000000aa";
+
   it("should deploy contract", function() {
     var meta;
     var requiredSignatures = 1;
     var estimatedGasCostOfWithdraw = 0;
     var authorities = [accounts[0], accounts[1]];
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
 
       return web3.eth.getTransactionReceipt(instance.transactionHash);
@@ -27,7 +30,7 @@ contract('ForeignBridge', function(accounts) {
 
   it("should fail to deploy contract with not enough required signatures", function() {
     var authorities = [accounts[0], accounts[1]];
-    return ForeignBridge.new(0, authorities, 0)
+    return ForeignBridge.new(0, authorities, 0, 0, 0)
       .then(function() {
         assert(false, "Contract should fail to deploy");
       }, helpers.ignoreExpectedError)
@@ -35,7 +38,7 @@ contract('ForeignBridge', function(accounts) {
 
   it("should fail to deploy contract with to many signatures", function() {
     var authorities = [accounts[0], accounts[1]];
-    return ForeignBridge.new(3, authorities, 0)
+    return ForeignBridge.new(3, authorities, 0, 0, 0)
       .then(function() {
         assert(false, "Contract should fail to deploy");
       }, helpers.ignoreExpectedError)
@@ -50,7 +53,7 @@ contract('ForeignBridge', function(accounts) {
     var value = web3.toWei(1, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, value, hash, { from: authorities[0] });
     }).then(function(result) {
@@ -81,7 +84,7 @@ contract('ForeignBridge', function(accounts) {
     var value = web3.toWei(1, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
 
       return meta.deposit(userAccount, value, hash, { from: authorities[0] });
@@ -130,7 +133,7 @@ contract('ForeignBridge', function(accounts) {
     var value = web3.toWei(1, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, value, hash, { from: authorities[0] });
     }).then(function(_) {
@@ -150,7 +153,7 @@ contract('ForeignBridge', function(accounts) {
     var value = web3.toWei(1, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, value, hash, { from: userAccount })
         .then(function() {
@@ -169,7 +172,7 @@ contract('ForeignBridge', function(accounts) {
     var value = web3.toWei(1, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, value, hash, { from: authorities[0] });
     }).then(function(result) {
@@ -207,6 +210,140 @@ contract('ForeignBridge', function(accounts) {
     })
   })
 
+  it("should split the fee of a deposit among the confirming authorities", function() {
+    var meta;
+    var requiredSignatures = 2;
+    var estimatedGasCostOfWithdraw = 0;
+    var authorities = [accounts[0], accounts[1], accounts[2]];
+    var userAccount = accounts[3];
+    var value = web3.toWei(1, "ether");
+    var fee = 1001;
+    var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
+
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
+      meta = instance;
+      return meta.depositWithFee(userAccount, value, hash, fee, { from: authorities[0] });
+    }).then(function(result) {
+      assert.equal(1, result.logs.length);
+      assert.equal("DepositConfirmation", result.logs[0].event);
+
+      // confirmations with another fee count separately
+      return meta.depositWithFee(userAccount, value, hash, fee + 1, { from: authorities[1] });
+    }).then(function(result) {
+      assert.equal("DepositConfirmation", result.logs[0].event);
+
+      return meta.depositWithFee(userAccount, value, hash, fee, { from: authorities[2] });
+    }).then(function(result) {
+      assert.equal(2, result.logs.length)
+
+      assert.equal("Transfer", result.logs[0].event);
+      assert.equal(userAccount, result.logs[0].args.to);
+      assert.equal(web3.toBigNumber(value).minus(fee).toString(), result.logs[0].args.tokens.toString());
+
+      assert.equal("Deposit", result.logs[1].event);
+      assert.equal(web3.toBigNumber(value).minus(fee).toString(), result.logs[1].args.value.toString());
+
+      return Promise.all(authorities.map((authority) => meta.relayFees.call(authority)));
+    }).then(function(result) {
+      // the authority completing the deposit gets the remainder
+      assert.deepEqual(["500", "0", "501"], result.map((fees) => fees.toString()));
+
+      return meta.totalSupply.call();
+    }).then(function(result) {
+      assert.equal(web3.toBigNumber(value).minus(fee).toString(), result.toString(), "fees are minted once claimed");
+    })
+  })
+
+  it("should fail to deposit with a fee that isn't less than the value", function() {
+    var requiredSignatures = 1;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
+
+    return ForeignBridge.new(requiredSignatures, authorities, 0, 0, 0).then(function(instance) {
+      return instance.depositWithFee(userAccount, 100, hash, 100, { from: authorities[0] })
+        .then(function() {
+          assert(false, "should fail");
+        }, helpers.ignoreExpectedError)
+    })
+  })
+
+  it("should allow authorities to claim their relay fees", function() {
+    var meta;
+    var requiredSignatures = 1;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var value = web3.toWei(1, "ether");
+    var fee = 1000;
+    var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
+
+    return ForeignBridge.new(requiredSignatures, authorities, 0, 0, 0).then(function(instance) {
+      meta = instance;
+      return meta.depositWithFee(userAccount, value, hash, fee, { from: authorities[0] });
+    }).then(function(result) {
+      return meta.claimRelayFees({ from: authorities[0] });
+    }).then(function(result) {
+      assert.equal(2, result.logs.length)
+
+      assert.equal("Transfer", result.logs[0].event);
+      assert.equal("0x0000000000000000000000000000000000000000", result.logs[0].args.from);
+      assert.equal(authorities[0], result.logs[0].args.to);
+      assert.equal(fee, result.logs[0].args.tokens);
+
+      assert.equal("RelayFeesClaimed", result.logs[1].event);
+      assert.equal(authorities[0], result.logs[1].args.authority);
+      assert.equal(fee, result.logs[1].args.tokens);
+
+      return meta.balances.call(authorities[0]);
+    }).then(function(result) {
+      assert.equal(fee, result, "claimed fees should be in the balance of the authority");
+
+      return meta.totalSupply.call();
+    }).then(function(result) {
+      assert.equal(value, result, "total supply should match the deposited value");
+
+      return meta.claimRelayFees({ from: authorities[0] })
+        .then(function() {
+          assert(false, "claiming without unclaimed fees should fail");
+        }, helpers.ignoreExpectedError)
+    })
+  })
+
+  it("should confirm a batch of deposits and skip deposits confirmed before", function() {
+    var meta;
+    var requiredSignatures = 1;
+    var authorities = [accounts[0], accounts[1]];
+    var recipients = [accounts[2], accounts[3]];
+    var values = [web3.toWei(1, "ether"), web3.toWei(2, "ether")];
+    var hashes = [
+      "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408",
+      "0x884edad9ce6fa2440d8a54cc123490eb96d2768479d49ff9c7366125a9424364",
+    ];
+
+    return ForeignBridge.new(requiredSignatures, authorities, 0, 0, 0).then(function(instance) {
+      meta = instance;
+      return meta.deposit(recipients[0], values[0], hashes[0], { from: authorities[0] });
+    }).then(function(result) {
+      return meta.depositBatch(recipients, values, hashes, 0, { from: authorities[0] });
+    }).then(function(result) {
+      assert.equal(2, result.logs.length, "only the second deposit should be confirmed")
+
+      assert.equal("Deposit", result.logs[1].event);
+      assert.equal(recipients[1], result.logs[1].args.recipient);
+      assert.equal(values[1], result.logs[1].args.value);
+      assert.equal(hashes[1], result.logs[1].args.transactionHash);
+
+      return Promise.all(recipients.map((recipient) => meta.balances.call(recipient)));
+    }).then(function(result) {
+      assert.deepEqual(values, result.map((balance) => balance.toString()));
+
+      return meta.depositBatch(recipients, values, hashes, 0, { from: authorities[0] })
+        .then(function() {
+          assert(false, "a batch of confirmed deposits should fail");
+        }, helpers.ignoreExpectedError)
+    })
+  })
+
   it("should not allow user to transfer value they don't have to home", function() {
     var meta;
     var requiredSignatures = 1;
@@ -218,7 +355,7 @@ contract('ForeignBridge', function(accounts) {
     var userValue = web3.toWei(3, "ether");
     var transferedValue = web3.toWei(4, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, userValue, hash, { from: authorities[0] });
     }).then(function(result) {
@@ -240,7 +377,7 @@ contract('ForeignBridge', function(accounts) {
     var userValue = web3.toWei(3, "ether");
     var transferedValue = web3.toWei(0, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, userValue, hash, { from: authorities[0] });
     }).then(function(result) {
@@ -262,7 +399,7 @@ contract('ForeignBridge', function(accounts) {
     var userValue = web3.toWei(3, "ether");
     var transferedValue = web3.toWei(4, "ether");
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, userValue, hash, { from: authorities[0] });
     }).then(function(result) {
@@ -284,7 +421,7 @@ contract('ForeignBridge', function(accounts) {
     var userValue = web3.toWei(3, "ether");
     var transferedValue = estimatedGasCostOfWithdraw.times(homeGasPrice);
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return meta.deposit(userAccount, userValue, hash, { from: authorities[0] });
     }).then(function(result) {
@@ -306,7 +443,7 @@ contract('ForeignBridge', function(accounts) {
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
     var transferedValue = estimatedGasCostOfWithdraw.times(homeGasPrice).plus(1);
     var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       // top up balance so we can transfer
       return meta.deposit(userAccount, value, hash, { from: authorities[0] });
@@ -344,8 +481,8 @@ contract('ForeignBridge', function(accounts) {
     var recipientAccount = accounts[2];
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return helpers.sign(authorities[0], message);
     }).then(function(result) {
@@ -373,10 +510,10 @@ contract('ForeignBridge', function(accounts) {
     var recipientAccount = accounts[2];
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
     var signature;
 
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
 
       return helpers.sign(authorities[0], message);
@@ -408,9 +545,9 @@ contract('ForeignBridge', function(accounts) {
     var recipientAccount = accounts[2];
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
-    var message2 = helpers.createMessage(recipientAccount, web3.toBigNumber(2000), transactionHash, homeGasPrice);
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
+    var message2 = helpers.createMessage(recipientAccount, web3.toBigNumber(2000), transactionHash, homeGasPrice, homeBridgeAddress);
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return Promise.all([
         helpers.sign(authorities[0], message),
@@ -464,6 +601,59 @@ contract('ForeignBridge', function(accounts) {
     })
   })
 
+  it("should collect signatures for version 2 message for the chains of the deployment", function() {
+    var meta;
+    var signature;
+    var requiredSignatures = 1;
+    var estimatedGasCostOfWithdraw = 0;
+    var authorities = [accounts[0], accounts[1]];
+    var recipientAccount = accounts[2];
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+    var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
+    var message = helpers.createMessageV2(
+      helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress),
+      web3.toBigNumber(1),
+      web3.toBigNumber(42)
+    );
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 1, 42).then(function(instance) {
+      meta = instance;
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      signature = result;
+      return meta.submitSignature(result, message, { from: authorities[0] });
+    }).then(function(result) {
+      assert.equal(1, result.logs.length, "Exactly one event should be created");
+      assert.equal("CollectedSignatures", result.logs[0].event, "Event name should be CollectedSignatures");
+      return meta.message(result.logs[0].args.messageHash);
+    }).then(function(result) {
+      assert.equal(message, result);
+    })
+  })
+
+  it("should not be possible to submit version 2 message for other chains", function() {
+    var meta;
+    var requiredSignatures = 1;
+    var estimatedGasCostOfWithdraw = 0;
+    var authorities = [accounts[0], accounts[1]];
+    var recipientAccount = accounts[2];
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+    var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
+    var message = helpers.createMessageV2(
+      helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress),
+      web3.toBigNumber(3),
+      web3.toBigNumber(42)
+    );
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 1, 42).then(function(instance) {
+      meta = instance;
+      return helpers.sign(authorities[0], message);
+    }).then(function(signature) {
+      return meta.submitSignature(signature, message, { from: authorities[0] })
+        .then(function() {
+          assert(false, "submitSignature should fail for message meant for other chains");
+        }, helpers.ignoreExpectedError)
+    })
+  })
+
   it("should not be possible to submit message that is too short", function() {
     var meta;
     var requiredSignatures = 1;
@@ -472,9 +662,9 @@ contract('ForeignBridge', function(accounts) {
     var recipientAccount = accounts[2];
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
     var truncatedMessage = message.substr(0, 84);
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return helpers.sign(authorities[0], truncatedMessage);
     }).then(function(signature) {
@@ -494,9 +684,9 @@ contract('ForeignBridge', function(accounts) {
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
     var homeGasPrice2 = web3.toBigNumber(web3.toWei(2, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
-    var message2 = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice2);
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
+    var message2 = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice2, homeBridgeAddress);
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return helpers.sign(authorities[0], message);
     }).then(function(result) {
@@ -516,9 +706,9 @@ contract('ForeignBridge', function(accounts) {
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
     var homeGasPrice2 = web3.toBigNumber(web3.toWei(2, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
-    var message2 = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice2);
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
+    var message2 = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice2, homeBridgeAddress);
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return helpers.sign(authorities[0], message);
     }).then(function(result) {
@@ -537,9 +727,9 @@ contract('ForeignBridge', function(accounts) {
     var recipientAccount = accounts[2];
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
-    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, web3.toBigNumber(1000), transactionHash, homeGasPrice, homeBridgeAddress);
     var signature;
-    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw).then(function(instance) {
+    return ForeignBridge.new(requiredSignatures, authorities, estimatedGasCostOfWithdraw, 0, 0).then(function(instance) {
       meta = instance;
       return helpers.sign(authorities[0], message);
     }).then(function(result) {
@@ -4,6 +4,8 @@ var Helpers = artifacts.require("HelpersTest");
 var helpers = require("./helpers/helpers");
 
 contract("Helpers", function(accounts) {
+  var homeBridgeAddress = "0x00000000000000000000000000000000000000aa";
+
   it("`addressArrayContains` should function correctly", function() {
     var addresses = accounts.slice(0, 3);
     var otherAddress = accounts[3];
@@ -89,7 +91,7 @@ contract("Helpers", function(accounts) {
     var recipientAccount = accounts[2];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridgeAddress);
 
     return Helpers.new().then(function(instance) {
       library = instance;
@@ -120,7 +122,7 @@ contract("Helpers", function(accounts) {
     var recipientAccount = accounts[3];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridgeAddress);
 
     return Helpers.new().then(function(instance) {
       library = instance;
@@ -165,8 +167,8 @@ contract("Helpers", function(accounts) {
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
     var homeGasPrice2 = web3.toBigNumber(100);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
-    var message2 = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice2);
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridgeAddress);
+    var message2 = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice2, homeBridgeAddress);
 
     return Helpers.new().then(function(instance) {
       library = instance;
@@ -197,7 +199,7 @@ contract("Helpers", function(accounts) {
     var recipientAccount = accounts[2];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridgeAddress);
 
     return Helpers.new().then(function(instance) {
       library = instance;
@@ -228,7 +230,7 @@ contract("Helpers", function(accounts) {
     var recipientAccount = accounts[2];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridgeAddress);
 
     return Helpers.new().then(function(instance) {
       library = instance;
@@ -259,7 +261,7 @@ contract("Helpers", function(accounts) {
     var recipientAccount = accounts[2];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridgeAddress);
 
     return Helpers.new().then(function(instance) {
       library = instance;
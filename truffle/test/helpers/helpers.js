@@ -74,9 +74,9 @@ module.exports.getBalances = getBalances;
 
 
 // returns hex string of the bytes of the message
-// composed from `recipient`, `value` and `transactionHash`
+// composed from `recipient`, `value`, `transactionHash`, `homeGasPrice` and `homeBridge`
 // that is relayed from `foreign` to `home` on withdraw
-function createMessage(recipient, value, transactionHash, homeGasPrice) {
+function createMessage(recipient, value, transactionHash, homeGasPrice, homeBridge) {
   web3._extend.utils.isBigNumber(value);
   recipient = strip0x(recipient);
   assert.equal(recipient.length, 20 * 2);
@@ -91,13 +91,45 @@ function createMessage(recipient, value, transactionHash, homeGasPrice) {
   homeGasPrice = strip0x(bigNumberToPaddedBytes32(homeGasPrice));
   assert.equal(homeGasPrice.length, 64);
 
-  var message = "0x" + recipient + value + transactionHash + homeGasPrice;
-  var expectedMessageLength = (20 + 32 + 32 + 32) * 2 + 2;
+  homeBridge = strip0x(homeBridge);
+  assert.equal(homeBridge.length, 20 * 2);
+
+  var message = "0x" + recipient + value + transactionHash + homeGasPrice + homeBridge;
+  var expectedMessageLength = (20 + 32 + 32 + 32 + 20) * 2 + 2;
   assert.equal(message.length, expectedMessageLength);
   return message;
 }
 module.exports.createMessage = createMessage;
 
+// returns hex string of the bytes of the message relayed from `foreign` to `home`
+// by the token bridges: the message of `createMessage` followed by `token` on home
+function createTokenMessage(recipient, value, transactionHash, homeGasPrice, homeBridge, token) {
+  token = strip0x(token);
+  assert.equal(token.length, 20 * 2);
+
+  var message = createMessage(recipient, value, transactionHash, homeGasPrice, homeBridge) + token;
+  var expectedMessageLength = (20 + 32 + 32 + 32 + 20 + 20) * 2 + 2;
+  assert.equal(message.length, expectedMessageLength);
+  return message;
+}
+module.exports.createTokenMessage = createTokenMessage;
+
+// returns hex string of the version 2 message wrapping the fields of `message`
+// (as returned by `createMessage` or `createTokenMessage`) for the chains
+// `homeChainId` and `foreignChainId`
+function createMessageV2(message, homeChainId, foreignChainId) {
+  web3._extend.utils.isBigNumber(homeChainId);
+  homeChainId = strip0x(bigNumberToPaddedBytes32(homeChainId));
+  assert.equal(homeChainId.length, 64);
+
+  web3._extend.utils.isBigNumber(foreignChainId);
+  foreignChainId = strip0x(bigNumberToPaddedBytes32(foreignChainId));
+  assert.equal(foreignChainId.length, 64);
+
+  return "0x02" + homeChainId + foreignChainId + strip0x(message);
+}
+module.exports.createMessageV2 = createMessageV2;
+
 // returns array of integers progressing from `start` up to, but not including, `end`
 function range(start, end) {
   var result = [];
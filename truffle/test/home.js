@@ -11,12 +11,20 @@ function newHomeBridge(options) {
   if (options.maxSingleDepositValue === undefined) {
     options.maxSingleDepositValue = 0;
   }
+  if (options.homeChainId === undefined) {
+    options.homeChainId = 0;
+  }
+  if (options.foreignChainId === undefined) {
+    options.foreignChainId = 0;
+  }
   return HomeBridge.new(
     options.requiredSignatures,
     options.authorities,
     options.estimatedGasCostOfWithdraw,
     options.maxTotalHomeContractBalance,
-    options.maxSingleDepositValue
+    options.maxSingleDepositValue,
+    options.homeChainId,
+    options.foreignChainId
   )
 }
 
@@ -167,13 +175,14 @@ contract('HomeBridge', function(accounts) {
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(0);
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
-    var message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
       authorities: authorities,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address);
 
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
@@ -210,6 +219,9 @@ contract('HomeBridge', function(accounts) {
       assert.equal(recipientAccount, result.logs[0].args.recipient, "Event recipient should match recipient in message");
       assert(value.equals(result.logs[0].args.value), "Event value should match value in message");
       assert.equal(transactionHash, result.logs[0].args.transactionHash);
+      return homeBridge.withdraws.call(transactionHash);
+    }).then(function(result) {
+      assert(result, "transactionHash should be marked as withdrawn");
     })
   })
 
@@ -229,7 +241,7 @@ contract('HomeBridge', function(accounts) {
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
     var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
-    var message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -237,6 +249,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: estimatedGasCostOfWithdraw
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address);
 
       return helpers.getBalances(accounts);
     }).then(function(result) {
@@ -303,7 +316,7 @@ contract('HomeBridge', function(accounts) {
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var requestedGasPrice = web3.toBigNumber(100);
     var usedGasPrice = web3.toBigNumber(1000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", requestedGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -311,6 +324,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", requestedGasPrice, homeBridge.address);
 
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
@@ -344,7 +358,7 @@ contract('HomeBridge', function(accounts) {
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var requestedGasPrice = web3.toBigNumber(100);
     var usedGasPrice = web3.toBigNumber(1000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", requestedGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -352,6 +366,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", requestedGasPrice, homeBridge.address);
 
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
@@ -385,7 +400,7 @@ contract('HomeBridge', function(accounts) {
     var chargerAccount = accounts[4];
     var value = estimatedGasCostOfWithdraw;
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -393,6 +408,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: estimatedGasCostOfWithdraw,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
 
       return helpers.getBalances(accounts);
     }).then(function(result) {
@@ -430,8 +446,8 @@ contract('HomeBridge', function(accounts) {
     var homeGasPrice = web3.toBigNumber(10000);
     var transactionHash1 = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
     var transactionHash2 = "0x038c79eb958a13aa71996bac27c628f33f227288bd27d5e157b97e55e08fd2b3";
-    var message1 = helpers.createMessage(recipientAccount, value, transactionHash1, homeGasPrice);
-    var message2 = helpers.createMessage(recipientAccount, value, transactionHash2, homeGasPrice);
+    var message1;
+    var message2;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -439,6 +455,8 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message1 = helpers.createMessage(recipientAccount, value, transactionHash1, homeGasPrice, homeBridge.address);
+      message2 = helpers.createMessage(recipientAccount, value, transactionHash2, homeGasPrice, homeBridge.address);
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
         value: value.times(2),
@@ -488,8 +506,8 @@ contract('HomeBridge', function(accounts) {
     var recipientAccount = accounts[3];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message1 = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
-    var message2 = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message1;
+    var message2;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -497,6 +515,8 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message1 = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
+      message2 = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
         value: value.times(2),
@@ -542,7 +562,7 @@ contract('HomeBridge', function(accounts) {
     var recipientAccount = accounts[3];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -550,6 +570,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
       return helpers.sign(authorities[0], message);
     }).then(function(result) {
       signature = result;
@@ -566,6 +587,181 @@ contract('HomeBridge', function(accounts) {
     })
   })
 
+  it("withdraw should fail if message is meant for another HomeBridge", function() {
+    var homeBridge;
+    var signature;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var homeGasPrice = web3.toBigNumber(10000);
+    // signed for a retired deployment
+    var otherHomeBridgeAddress = "0x00000000000000000000000000000000000000aa";
+    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, otherHomeBridgeAddress);
+
+    return newHomeBridge({
+      requiredSignatures: 1,
+      authorities: authorities,
+      estimatedGasCostOfWithdraw: 0,
+    }).then(function(instance) {
+      homeBridge = instance;
+
+      // "charge" HomeBridge so we can withdraw later
+      return homeBridge.sendTransaction({
+        value: value,
+        from: userAccount
+      })
+    }).then(function(result) {
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      signature = result;
+      var vrs = helpers.signatureToVRS(signature);
+
+      return homeBridge.withdraw(
+        [vrs.v],
+        [vrs.r],
+        [vrs.s],
+        message,
+        {from: userAccount, gasPrice: homeGasPrice}
+      ).then(function() {
+        assert(false, "withdraw should fail");
+      }, helpers.ignoreExpectedError)
+    })
+  })
+
+  it("should allow withdraw with version 2 message for the chains of the deployment", function() {
+    var homeBridge;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var homeGasPrice = web3.toBigNumber(0);
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+    var message;
+
+    return newHomeBridge({
+      requiredSignatures: 1,
+      authorities: authorities,
+      homeChainId: 1,
+      foreignChainId: 42,
+    }).then(function(instance) {
+      homeBridge = instance;
+      message = helpers.createMessageV2(
+        helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address),
+        web3.toBigNumber(1),
+        web3.toBigNumber(42)
+      );
+
+      // "charge" HomeBridge so we can withdraw later
+      return homeBridge.sendTransaction({
+        value: value,
+        from: userAccount
+      })
+    }).then(function(result) {
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      var vrs = helpers.signatureToVRS(result);
+      return homeBridge.withdraw(
+        [vrs.v],
+        [vrs.r],
+        [vrs.s],
+        message,
+        {from: userAccount, gasPrice: homeGasPrice}
+      );
+    }).then(function(result) {
+      assert.equal(1, result.logs.length, "Exactly one event should be created");
+      assert.equal("Withdraw", result.logs[0].event, "Event name should be Withdraw");
+      assert.equal(recipientAccount, result.logs[0].args.recipient, "Event recipient should match recipient in message");
+      assert(value.equals(result.logs[0].args.value), "Event value should match value in message");
+      assert.equal(transactionHash, result.logs[0].args.transactionHash);
+    })
+  })
+
+  it("withdraw should fail if version 2 message is meant for other chains", function() {
+    var homeBridge;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var homeGasPrice = web3.toBigNumber(0);
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+    var message;
+
+    return newHomeBridge({
+      requiredSignatures: 1,
+      authorities: authorities,
+      homeChainId: 1,
+      foreignChainId: 42,
+    }).then(function(instance) {
+      homeBridge = instance;
+      // signed for a deployment between home and another foreign chain
+      message = helpers.createMessageV2(
+        helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address),
+        web3.toBigNumber(1),
+        web3.toBigNumber(77)
+      );
+
+      return homeBridge.sendTransaction({
+        value: value,
+        from: userAccount
+      })
+    }).then(function(result) {
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      var vrs = helpers.signatureToVRS(result);
+      return homeBridge.withdraw(
+        [vrs.v],
+        [vrs.r],
+        [vrs.s],
+        message,
+        {from: userAccount, gasPrice: homeGasPrice}
+      ).then(function() {
+        assert(false, "withdraw should fail");
+      }, helpers.ignoreExpectedError)
+    })
+  })
+
+  it("withdraw should fail with version 2 message if the deployment has no chain ids", function() {
+    var homeBridge;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var homeGasPrice = web3.toBigNumber(0);
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+    var message;
+
+    return newHomeBridge({
+      requiredSignatures: 1,
+      authorities: authorities,
+    }).then(function(instance) {
+      homeBridge = instance;
+      message = helpers.createMessageV2(
+        helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address),
+        web3.toBigNumber(0),
+        web3.toBigNumber(0)
+      );
+
+      return homeBridge.sendTransaction({
+        value: value,
+        from: userAccount
+      })
+    }).then(function(result) {
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      var vrs = helpers.signatureToVRS(result);
+      return homeBridge.withdraw(
+        [vrs.v],
+        [vrs.r],
+        [vrs.s],
+        message,
+        {from: userAccount, gasPrice: homeGasPrice}
+      ).then(function() {
+        assert(false, "withdraw should fail");
+      }, helpers.ignoreExpectedError)
+    })
+  })
+
   it("should not allow withdraw with message.length too short", function() {
     var homeBridge;
     var signature;
@@ -574,9 +770,7 @@ contract('HomeBridge', function(accounts) {
     var recipientAccount = accounts[3];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
-    // make message too short
-    message = message.substr(0, 115);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 1,
@@ -584,6 +778,9 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
+      // make message too short
+      message = message.substr(0, 115);
 
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
@@ -617,7 +814,7 @@ contract('HomeBridge', function(accounts) {
     var recipientAccount = accounts[3];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 2,
@@ -625,6 +822,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
 
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
@@ -658,7 +856,7 @@ contract('HomeBridge', function(accounts) {
     var recipientAccount = accounts[3];
     var value = web3.toBigNumber(web3.toWei(1, "ether"));
     var homeGasPrice = web3.toBigNumber(10000);
-    var message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice);
+    var message;
 
     return newHomeBridge({
       requiredSignatures: 2,
@@ -666,6 +864,7 @@ contract('HomeBridge', function(accounts) {
       estimatedGasCostOfWithdraw: 0,
     }).then(function(instance) {
       homeBridge = instance;
+      message = helpers.createMessage(recipientAccount, value, "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80", homeGasPrice, homeBridge.address);
 
       // "charge" HomeBridge so we can withdraw later
       return homeBridge.sendTransaction({
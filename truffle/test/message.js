@@ -6,7 +6,8 @@ contract("Message", function(accounts) {
   var value = web3.toBigNumber(web3.toWei(1, "ether"));
   var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
   var homeGasPrice = web3.toBigNumber(web3.toWei(3, "gwei"));
-  var message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice);
+  var homeBridgeAddress = accounts[1];
+  var message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridgeAddress);
 
   it("should extract value", function() {
     return Message.new().then(function(instance) {
@@ -39,4 +40,48 @@ contract("Message", function(accounts) {
       assert(result.equals(homeGasPrice));
     })
   })
+
+  it("should extract homeBridge", function() {
+    return Message.new().then(function(instance) {
+      return instance.getHomeBridge.call(message)
+    }).then(function(result) {
+      assert.equal(result, homeBridgeAddress);
+    })
+  })
+
+  it("should extract version and chain ids of version 2 message", function() {
+    var messageV2 = helpers.createMessageV2(message, web3.toBigNumber(1), web3.toBigNumber(42));
+    var instance;
+    return Message.new().then(function(result) {
+      instance = result;
+      return Promise.all([
+        instance.getVersion.call(messageV2),
+        instance.getHomeChainId.call(messageV2),
+        instance.getForeignChainId.call(messageV2),
+        instance.checkedFields.call(messageV2, 1, 42),
+      ])
+    }).then(function(result) {
+      assert(result[0].equals(2));
+      assert(result[1].equals(1));
+      assert(result[2].equals(42));
+      assert.equal(result[3], message);
+    })
+  })
+
+  it("should return legacy message unchanged from checkedFields", function() {
+    return Message.new().then(function(instance) {
+      return instance.checkedFields.call(message, 1, 42)
+    }).then(function(result) {
+      assert.equal(result, message);
+    })
+  })
+
+  it("should reject version 2 message for other chains in checkedFields", function() {
+    var messageV2 = helpers.createMessageV2(message, web3.toBigNumber(1), web3.toBigNumber(42));
+    return Message.new().then(function(instance) {
+      return instance.checkedFields.call(messageV2, 1, 43)
+    }).then(function() {
+      assert(false, "checkedFields should fail for message meant for other chains");
+    }, helpers.ignoreExpectedError)
+  })
 })
@@ -0,0 +1,195 @@
+var HomeTokenBridge = artifacts.require("HomeTokenBridge");
+var ForeignTokenBridge = artifacts.require("ForeignTokenBridge");
+// any ERC20 token will do
+var ForeignBridge = artifacts.require("ForeignBridge");
+var helpers = require("./helpers/helpers");
+
+// returns a token with `value` tokens minted to `owner`
+function newToken(authority, owner, value) {
+  var token;
+  var hash = "0xe55bb43c36cdf79e23b4adc149cdded921f0d482e613c50c6540977c213bc408";
+  return ForeignBridge.new(1, [authority], 0, 0, 0).then(function(instance) {
+    token = instance;
+
+    return token.deposit(owner, value, hash, {from: authority});
+  }).then(function() {
+    return token;
+  })
+}
+
+contract('HomeTokenBridge', function(accounts) {
+  it("should fail to deploy contract without token", function() {
+    var authorities = [accounts[0], accounts[1]];
+    return HomeTokenBridge.new(1, authorities, "0x0000000000000000000000000000000000000000", 0, 0)
+      .then(function() {
+        assert(false, "Contract should fail to deploy");
+      }, helpers.ignoreExpectedError)
+  })
+
+  it("should hold transferred tokens and create event", function() {
+    var token;
+    var homeBridge;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+
+    return newToken(authorities[0], userAccount, value).then(function(instance) {
+      token = instance;
+
+      return HomeTokenBridge.new(1, authorities, token.address, 0, 0);
+    }).then(function(instance) {
+      homeBridge = instance;
+
+      return token.approve(homeBridge.address, value, {from: userAccount});
+    }).then(function() {
+      return homeBridge.transferToForeign(recipientAccount, value, {from: userAccount});
+    }).then(function(result) {
+      assert.equal(1, result.logs.length, "Exactly one event should be created");
+      assert.equal("TransferToForeign", result.logs[0].event, "Event name should be TransferToForeign");
+      assert.equal(token.address, result.logs[0].args.token, "Event token should be the token of the bridge");
+      assert.equal(recipientAccount, result.logs[0].args.recipient, "Event recipient should be the recipient");
+      assert(value.equals(result.logs[0].args.value), "Event value should match transferred value");
+
+      return token.balanceOf(homeBridge.address);
+    }).then(function(result) {
+      assert(value.equals(result), "HomeTokenBridge should hold the transferred tokens");
+    })
+  })
+
+  it("should withdraw tokens once and only with token message", function() {
+    var token;
+    var homeBridge;
+    var signature;
+    var message;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var homeGasPrice = web3.toBigNumber(0);
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+
+    return newToken(authorities[0], userAccount, value).then(function(instance) {
+      token = instance;
+
+      return HomeTokenBridge.new(1, authorities, token.address, 0, 0);
+    }).then(function(instance) {
+      homeBridge = instance;
+
+      // fund HomeTokenBridge so we can withdraw later
+      return token.transfer(homeBridge.address, value, {from: userAccount});
+    }).then(function() {
+      // an ether message lacks the token
+      message = helpers.createMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address);
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      var vrs = helpers.signatureToVRS(result);
+      return homeBridge.withdraw([vrs.v], [vrs.r], [vrs.s], message, {from: userAccount, gasPrice: homeGasPrice});
+    }).then(function() {
+      assert(false, "withdraw should fail for a message without token");
+    }, helpers.ignoreExpectedError).then(function() {
+      message = helpers.createTokenMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge.address, token.address);
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      signature = result;
+      var vrs = helpers.signatureToVRS(signature);
+      return homeBridge.withdraw([vrs.v], [vrs.r], [vrs.s], message, {from: userAccount, gasPrice: homeGasPrice});
+    }).then(function(result) {
+      assert.equal(1, result.logs.length, "Exactly one event should be created");
+      assert.equal("Withdraw", result.logs[0].event, "Event name should be Withdraw");
+      assert.equal(recipientAccount, result.logs[0].args.recipient, "Event recipient should match recipient in message");
+      assert(value.equals(result.logs[0].args.value), "Event value should match value in message");
+
+      return token.balanceOf(recipientAccount);
+    }).then(function(result) {
+      assert(value.equals(result), "recipient should receive the withdrawn tokens");
+
+      var vrs = helpers.signatureToVRS(signature);
+      return homeBridge.withdraw([vrs.v], [vrs.r], [vrs.s], message, {from: userAccount, gasPrice: homeGasPrice});
+    }).then(function() {
+      assert(false, "withdraw should fail if the transaction hash was withdrawn already");
+    }, helpers.ignoreExpectedError)
+  })
+})
+
+contract('ForeignTokenBridge', function(accounts) {
+  it("should transfer tokens once enough authorities confirmed the deposit", function() {
+    var token;
+    var foreignBridge;
+    var authorities = [accounts[0], accounts[1]];
+    var recipientAccount = accounts[2];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+
+    return ForeignBridge.new(1, [authorities[0]], 0, 0, 0).then(function(instance) {
+      token = instance;
+
+      return ForeignTokenBridge.new(2, authorities, token.address, 0, 0);
+    }).then(function(instance) {
+      foreignBridge = instance;
+
+      // fund ForeignTokenBridge with the tokens it transfers
+      return token.deposit(foreignBridge.address, value, transactionHash, {from: authorities[0]});
+    }).then(function() {
+      return foreignBridge.deposit(accounts[5], recipientAccount, value, transactionHash, {from: authorities[0]});
+    }).then(function() {
+      assert(false, "deposit should fail for another token");
+    }, helpers.ignoreExpectedError).then(function() {
+      return foreignBridge.deposit(token.address, recipientAccount, value, transactionHash, {from: authorities[0]});
+    }).then(function(result) {
+      assert.equal("DepositConfirmation", result.logs[0].event, "Event name should be DepositConfirmation");
+
+      return foreignBridge.deposit(token.address, recipientAccount, value, transactionHash, {from: authorities[1]});
+    }).then(function(result) {
+      assert.equal("Deposit", result.logs[0].event, "Event name should be Deposit");
+
+      return token.balanceOf(recipientAccount);
+    }).then(function(result) {
+      assert(value.equals(result), "recipient should receive the deposited tokens");
+    })
+  })
+
+  it("should collect signatures of token messages", function() {
+    var token;
+    var foreignBridge;
+    var authorities = [accounts[0], accounts[1]];
+    var userAccount = accounts[2];
+    var recipientAccount = accounts[3];
+    var homeBridge = accounts[4];
+    var homeToken = accounts[5];
+    var value = web3.toBigNumber(web3.toWei(1, "ether"));
+    var homeGasPrice = web3.toBigNumber(10000);
+    var transactionHash = "0x1045bfe274b88120a6b1e5d01b5ec00ab5d01098346e90e7c7a3c9b8f0181c80";
+    var message = helpers.createTokenMessage(recipientAccount, value, transactionHash, homeGasPrice, homeBridge, homeToken);
+    var signature;
+
+    return newToken(authorities[0], userAccount, value).then(function(instance) {
+      token = instance;
+
+      return ForeignTokenBridge.new(1, authorities, token.address, 0, 0);
+    }).then(function(instance) {
+      foreignBridge = instance;
+
+      return token.approve(foreignBridge.address, value, {from: userAccount});
+    }).then(function() {
+      return foreignBridge.transferToHome(recipientAccount, value, homeGasPrice, {from: userAccount});
+    }).then(function(result) {
+      assert.equal("TransferToHome", result.logs[0].event, "Event name should be TransferToHome");
+      assert.equal(token.address, result.logs[0].args.token, "Event token should be the token of the bridge");
+      assert(homeGasPrice.equals(result.logs[0].args.homeGasPrice), "Event homeGasPrice should match");
+
+      return helpers.sign(authorities[0], message);
+    }).then(function(result) {
+      signature = result;
+      return foreignBridge.submitSignature(signature, message, {from: authorities[0]});
+    }).then(function(result) {
+      assert.equal("CollectedSignatures", result.logs[0].event, "Event name should be CollectedSignatures");
+      var hash = result.logs[0].args.messageHash;
+
+      return Promise.all([foreignBridge.message(hash), foreignBridge.signature(hash, 0)]);
+    }).then(function(result) {
+      assert.equal(message, result[0], "message should be stored");
+      assert.equal(signature, result[1], "signature should be stored");
+    })
+  })
+})